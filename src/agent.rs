@@ -0,0 +1,144 @@
+use crate::config::Config;
+use crate::storage::Storage;
+use crate::timeutil;
+use crate::{cancel, categories, index, reap, redact};
+use anyhow::Result;
+use rand::Rng;
+use std::time::Duration;
+
+/// Shortest polling interval the agent accepts, to stay well inside GitHub's rate limits
+pub const MIN_INTERVAL_SECS: u64 = 30;
+
+/// Longest delay between polls after repeated failures
+const MAX_BACKOFF_SECS: u64 = 60 * 60;
+
+/// How often the agent deletes keys past their deletion deadline when asked to
+const REAP_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Applies up to ±20% jitter to an interval, so several agents do not poll in lockstep.
+/// `fraction` is a random number in [-1, 1].
+pub fn jittered(interval_secs: u64, fraction: f64) -> u64 {
+    let spread = interval_secs as f64 * 0.2 * fraction.clamp(-1.0, 1.0);
+    (interval_secs as f64 + spread).round().max(1.0) as u64
+}
+
+/// Returns the delay before the next poll after a number of consecutive failures
+pub fn backoff(interval_secs: u64, failures: u32) -> u64 {
+    interval_secs
+        .saturating_mul(1u64 << failures.min(16))
+        .min(MAX_BACKOFF_SECS.max(interval_secs))
+}
+
+/// Brings the local key index up to date and rebuilds the category index from it
+async fn refresh(storage: &Storage, lmk: &str, master_key: &str) -> Result<usize> {
+    let profile = storage.profile();
+    let repo = storage.repo_full_name();
+    let previous = index::load(profile, &repo, lmk)?;
+    let key_index = index::refresh(storage, master_key, previous.as_ref()).await?;
+    index::save(profile, lmk, &key_index)?;
+
+    let category_index = key_index
+        .keys
+        .values()
+        .filter_map(|e| e.category.clone())
+        .collect();
+    categories::store_index(profile, &repo, lmk, &category_index)?;
+    Ok(key_index.keys.len())
+}
+
+/// Keeps the local indexes warm until cancellation is requested: polls the branch head every
+/// interval (with jitter) and only refreshes when the head moved or the cached category
+/// index expired. With `reap`, keys past their deletion deadline are also deleted, at most
+/// once an hour. Failures back off exponentially.
+pub async fn run(
+    storage: &Storage,
+    password: &str,
+    master_key: &str,
+    interval_secs: u64,
+    reap: bool,
+) -> Result<()> {
+    let profile = storage.profile();
+    let repo = storage.repo_full_name();
+    let lmk = Config::get_or_create_lmk_with_profile(profile, password)?;
+    let branch = storage.vault_branch().await?;
+
+    let mut last_head: Option<String> = None;
+    let mut last_reap: Option<u64> = None;
+    let mut failures = 0u32;
+
+    loop {
+        let now_unix = timeutil::now_unix();
+        let now = timeutil::format_rfc3339(now_unix);
+        let result = async {
+            if reap && last_reap.is_none_or(|t| now_unix >= t + REAP_INTERVAL_SECS) {
+                let due = reap::find(storage, master_key, now_unix).await?;
+                reap::run(storage, master_key, &due).await?;
+                for key in &due {
+                    println!(
+                        "[{}] Deleted '{}' (past its deletion deadline)",
+                        now, key.path
+                    );
+                }
+                last_reap = Some(now_unix);
+            }
+            let head = storage.branch_head(&branch).await?;
+            let expired = categories::load_index(profile, &repo, &lmk)?.is_none();
+            if head != last_head || expired {
+                let count = refresh(storage, &lmk, master_key).await?;
+                println!("[{}] Index refreshed ({} keys)", now, count);
+            }
+            Ok::<_, anyhow::Error>(head)
+        }
+        .await;
+
+        let delay = match result {
+            Ok(head) => {
+                last_head = head;
+                failures = 0;
+                interval_secs
+            }
+            Err(e) if cancel::is_cancelled(&e) => return Ok(()),
+            Err(e) => {
+                failures += 1;
+                let delay = backoff(interval_secs, failures);
+                eprintln!(
+                    "[{}] Refresh failed ({}); retrying in {}",
+                    now,
+                    redact::redact(&e.to_string()),
+                    timeutil::format_duration_secs(delay)
+                );
+                delay
+            }
+        };
+
+        let fraction = rand::rng().random_range(-1.0..=1.0);
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(jittered(delay, fraction))) => {}
+            _ = cancel::requested() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jittered() {
+        assert_eq!(jittered(300, 0.0), 300);
+        assert_eq!(jittered(300, 1.0), 360);
+        assert_eq!(jittered(300, -1.0), 240);
+        assert_eq!(jittered(300, 5.0), 360);
+        assert_eq!(jittered(1, -1.0), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(60, 0), 60);
+        assert_eq!(backoff(60, 1), 120);
+        assert_eq!(backoff(60, 3), 480);
+        assert_eq!(backoff(60, 100), MAX_BACKOFF_SECS);
+        // An interval longer than the cap is never shortened
+        assert_eq!(backoff(2 * MAX_BACKOFF_SECS, 2), 2 * MAX_BACKOFF_SECS);
+    }
+}
@@ -0,0 +1,461 @@
+use crate::derived;
+use crate::storage::Storage;
+use crate::templatefn;
+use crate::timeutil;
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Shortest value a manifest may ask to generate
+pub const MIN_GENERATED_LENGTH: usize = 6;
+
+/// Longest value a manifest may ask to generate
+pub const MAX_GENERATED_LENGTH: usize = 1024;
+
+/// Characters a generated value is drawn from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Charset {
+    /// Letters and digits
+    #[default]
+    Alphanumeric,
+    /// Lowercase hexadecimal digits
+    Hex,
+    /// Letters, digits and punctuation
+    Symbols,
+}
+
+impl Charset {
+    fn chars(self) -> &'static [u8] {
+        match self {
+            Charset::Alphanumeric => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"
+            }
+            Charset::Hex => b"0123456789abcdef",
+            Charset::Symbols => {
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!#$%&()*+,-./:;<=>?@[]^_{|}~"
+            }
+        }
+    }
+}
+
+/// Length of generated values when none is given
+pub const DEFAULT_GENERATED_LENGTH: usize = 32;
+
+fn default_length() -> usize {
+    DEFAULT_GENERATED_LENGTH
+}
+
+/// How the value of a key is generated when the key does not exist yet
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Generate {
+    #[serde(default = "default_length")]
+    pub length: usize,
+    #[serde(default)]
+    pub charset: Charset,
+}
+
+impl Generate {
+    /// Generates a random value following the policy
+    pub fn value(&self) -> Vec<u8> {
+        let chars = self.charset.chars();
+        let mut rng = rand::rng();
+        (0..self.length)
+            .map(|_| chars[rng.random_range(0..chars.len())])
+            .collect()
+    }
+}
+
+/// One declared key. Exactly one of `value`, `env` and `generate` says where its value comes
+/// from.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeySpec {
+    /// The value itself (for values that are not secret, such as hosts or URLs)
+    pub value: Option<String>,
+    /// Environment variable holding the value when the manifest is applied
+    pub env: Option<String>,
+    /// Generate a value when the key is created; an existing value is kept
+    pub generate: Option<Generate>,
+    /// When the value expires: a duration from when it is written ('90d') or a date
+    /// ('2025-06-30')
+    pub expires: Option<String>,
+}
+
+/// Keys declared under a category
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategorySpec {
+    /// Delete keys under the category that the manifest does not declare
+    #[serde(default)]
+    pub prune: bool,
+    /// Key name -> declaration
+    #[serde(default)]
+    pub keys: BTreeMap<String, KeySpec>,
+}
+
+/// The desired state of a vault, e.g.
+///
+/// ```yaml
+/// categories:
+///   prod/db:
+///     prune: true
+///     keys:
+///       password:
+///         generate: { length: 40, charset: symbols }
+///         expires: 90d
+///       host:
+///         value: db.internal
+/// keys:
+///   ci/deploy-token:
+///     env: DEPLOY_TOKEN
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Key path ('category/name') -> declaration
+    #[serde(default)]
+    pub keys: BTreeMap<String, KeySpec>,
+    /// Category -> the keys declared under it
+    #[serde(default)]
+    pub categories: BTreeMap<String, CategorySpec>,
+}
+
+impl Manifest {
+    /// Reads and validates a manifest file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        Self::parse(&text).with_context(|| format!("'{}' is not a valid manifest", path.display()))
+    }
+
+    /// Parses and validates a manifest
+    pub fn parse(text: &str) -> Result<Self> {
+        let manifest: Manifest = serde_yaml::from_str(text)?;
+        if manifest.keys.is_empty() && manifest.categories.is_empty() {
+            return Err(anyhow::anyhow!("It declares no keys and no categories"));
+        }
+        for category in manifest.categories.keys() {
+            if Storage::validate_category(Some(category))?.is_none() {
+                return Err(anyhow::anyhow!("Category names cannot be empty"));
+            }
+        }
+        for (path, spec) in manifest.entries()? {
+            spec.validate(&path)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Returns every declared key by full path
+    pub fn entries(&self) -> Result<BTreeMap<String, &KeySpec>> {
+        let mut entries = BTreeMap::new();
+        let declared = self.keys.iter().map(|(path, spec)| {
+            let (category, name) = templatefn::split_key_path(path);
+            (derived::key_id(name, category), spec)
+        });
+        let in_categories = self.categories.iter().flat_map(|(category, c)| {
+            c.keys
+                .iter()
+                .map(move |(name, spec)| (derived::key_id(name, Some(category)), spec))
+        });
+        for (path, spec) in declared.chain(in_categories) {
+            let path = path?;
+            if entries.insert(path.clone(), spec).is_some() {
+                return Err(anyhow::anyhow!("Key '{}' is declared twice", path));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Returns the categories whose undeclared keys are deleted
+    pub fn pruned(&self) -> Vec<&str> {
+        self.categories
+            .iter()
+            .filter(|(_, c)| c.prune)
+            .map(|(category, _)| category.trim_matches('/'))
+            .collect()
+    }
+}
+
+impl KeySpec {
+    fn validate(&self, path: &str) -> Result<()> {
+        let sources = [
+            self.value.is_some(),
+            self.env.is_some(),
+            self.generate.is_some(),
+        ];
+        if sources.iter().filter(|s| **s).count() != 1 {
+            return Err(anyhow::anyhow!(
+                "Key '{}' needs exactly one of 'value', 'env' and 'generate'",
+                path
+            ));
+        }
+        if let Some(generate) = &self.generate {
+            if !(MIN_GENERATED_LENGTH..=MAX_GENERATED_LENGTH).contains(&generate.length) {
+                return Err(anyhow::anyhow!(
+                    "Key '{}' asks for a {}-character value; generated values are {}-{} characters",
+                    path,
+                    generate.length,
+                    MIN_GENERATED_LENGTH,
+                    MAX_GENERATED_LENGTH
+                ));
+            }
+        }
+        if let Some(expires) = &self.expires {
+            timeutil::parse_expiry(expires, 0)
+                .with_context(|| format!("Key '{}' has an invalid expiry", path))?;
+        }
+        Ok(())
+    }
+}
+
+/// What applying the manifest does to a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The key does not exist yet
+    Create,
+    /// The key exists with a different value or expiry
+    Update,
+    /// The key is under a pruned category but not declared
+    Delete,
+    /// The key already matches its declaration
+    Unchanged,
+}
+
+/// One key of the plan
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    /// Key path ('category/name')
+    pub path: String,
+    pub change: Change,
+    /// The value to write (None to keep the current one)
+    pub value: Option<Vec<u8>>,
+    /// The expiry to record (None to leave it as it is)
+    pub expires: Option<u64>,
+    /// The key's recorded expiry has passed and the plan does not renew it
+    pub expired: bool,
+}
+
+impl Step {
+    /// Returns true if the vault differs from the manifest for this key
+    pub fn is_drift(&self) -> bool {
+        self.change != Change::Unchanged || self.expired
+    }
+}
+
+/// Plans bringing the vault in line with a manifest. `current` holds the decrypted values
+/// of the stored keys and `expiries` their recorded expiry, by key path; `env` looks up
+/// environment variables. Keys whose value is generated keep their current value. Unless
+/// `require_env` is set, a key whose environment variable is missing is only checked for
+/// existence instead of failing the plan.
+pub fn plan(
+    manifest: &Manifest,
+    current: &BTreeMap<String, Vec<u8>>,
+    expiries: &BTreeMap<String, u64>,
+    env: impl Fn(&str) -> Option<String>,
+    require_env: bool,
+    now: u64,
+) -> Result<Vec<Step>> {
+    let entries = manifest.entries()?;
+    let mut steps = Vec::new();
+    for (path, spec) in &entries {
+        let existing = current.get(path);
+        let desired = match (&spec.value, &spec.env, &spec.generate) {
+            (Some(value), _, _) => Some(value.as_bytes().to_vec()),
+            (_, Some(name), _) => match env(name) {
+                Some(value) => Some(value.into_bytes()),
+                None if require_env => {
+                    return Err(anyhow::anyhow!(
+                        "Environment variable '{}' (the value of '{}') is not set",
+                        name,
+                        path
+                    ))
+                }
+                None => None,
+            },
+            (_, _, Some(generate)) if existing.is_none() => Some(generate.value()),
+            _ => None,
+        };
+        let value = desired.filter(|v| Some(v) != existing);
+
+        let expires = match &spec.expires {
+            // A duration runs from when the value is written
+            Some(e) if timeutil::parse_duration_secs(e).is_ok() => {
+                if value.is_some() || !expiries.contains_key(path) {
+                    Some(timeutil::parse_expiry(e, now)?)
+                } else {
+                    None
+                }
+            }
+            Some(e) => {
+                let date = timeutil::parse_expiry(e, now)?;
+                (expiries.get(path) != Some(&date)).then_some(date)
+            }
+            None => None,
+        };
+
+        let change = match existing {
+            None => Change::Create,
+            Some(_) if value.is_some() || expires.is_some() => Change::Update,
+            Some(_) => Change::Unchanged,
+        };
+        let expired =
+            expiries.get(path).is_some_and(|e| *e <= now) && expires.is_none_or(|e| e <= now);
+        steps.push(Step {
+            path: path.clone(),
+            change,
+            value,
+            expires,
+            expired,
+        });
+    }
+
+    let pruned = manifest.pruned();
+    for path in current.keys() {
+        let in_pruned = pruned.iter().any(|c| path.starts_with(&format!("{}/", c)));
+        if in_pruned && !entries.contains_key(path) {
+            steps.push(Step {
+                path: path.clone(),
+                change: Change::Delete,
+                value: None,
+                expires: None,
+                expired: false,
+            });
+        }
+    }
+    steps.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+categories:
+  prod/db:
+    prune: true
+    keys:
+      password:
+        generate: { length: 40, charset: hex }
+        expires: 90d
+      host:
+        value: db.internal
+      user:
+        value: app
+        expires: 2030-01-01
+keys:
+  ci/deploy-token:
+    env: DEPLOY_TOKEN
+"#;
+
+    #[test]
+    fn test_parse() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let entries = manifest.entries().unwrap();
+        assert_eq!(
+            entries.keys().collect::<Vec<_>>(),
+            vec![
+                "ci/deploy-token",
+                "prod/db/host",
+                "prod/db/password",
+                "prod/db/user"
+            ]
+        );
+        assert_eq!(manifest.pruned(), vec!["prod/db"]);
+        let generated = entries["prod/db/password"]
+            .generate
+            .as_ref()
+            .unwrap()
+            .value();
+        assert_eq!(generated.len(), 40);
+        assert!(generated.iter().all(|c| c.is_ascii_hexdigit()));
+
+        let invalid = [
+            "",
+            "keys:\n  a:\n    value: x\n    env: X\n",
+            "keys:\n  a: {}\n",
+            "keys:\n  a:\n    generate: { length: 2 }\n",
+            "keys:\n  a:\n    value: x\n    expires: soon\n",
+            "keys:\n  a:\n    value: x\n    colour: red\n",
+            "keys:\n  db/a:\n    value: x\ncategories:\n  db:\n    keys:\n      a:\n        value: y\n",
+        ];
+        for text in invalid {
+            assert!(Manifest::parse(text).is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let now = 1_704_103_200;
+        let current: BTreeMap<String, Vec<u8>> = [
+            ("prod/db/password", "kept"),
+            ("prod/db/host", "db.old"),
+            ("prod/db/user", "app"),
+            ("prod/db/legacy", "x"),
+            ("prod/cache/url", "y"),
+        ]
+        .iter()
+        .map(|(path, value)| (path.to_string(), value.as_bytes().to_vec()))
+        .collect();
+        let expiries: BTreeMap<String, u64> = [("prod/db/password".to_string(), now + 10)]
+            .into_iter()
+            .collect();
+        let env = |name: &str| (name == "DEPLOY_TOKEN").then(|| "tok".to_string());
+
+        let steps = plan(&manifest, &current, &expiries, env, true, now).unwrap();
+        let summary: Vec<(&str, Change, Option<&[u8]>, Option<u64>)> = steps
+            .iter()
+            .map(|s| (s.path.as_str(), s.change, s.value.as_deref(), s.expires))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("ci/deploy-token", Change::Create, Some(&b"tok"[..]), None),
+                (
+                    "prod/db/host",
+                    Change::Update,
+                    Some(&b"db.internal"[..]),
+                    None
+                ),
+                ("prod/db/legacy", Change::Delete, None, None),
+                ("prod/db/password", Change::Unchanged, None, None),
+                ("prod/db/user", Change::Update, None, Some(1_893_456_000)),
+            ]
+        );
+
+        // A new generated key gets a value and an expiry counted from now
+        let steps = plan(
+            &manifest,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            env,
+            true,
+            now,
+        )
+        .unwrap();
+        let password = steps.iter().find(|s| s.path == "prod/db/password").unwrap();
+        assert_eq!(password.change, Change::Create);
+        assert_eq!(password.value.as_ref().map(Vec::len), Some(40));
+        assert_eq!(password.expires, Some(now + 90 * 86400));
+
+        let err = plan(&manifest, &current, &expiries, |_| None, true, now).unwrap_err();
+        assert!(err.to_string().contains("DEPLOY_TOKEN"));
+
+        // Checking for drift works without the environment, and notices values past their
+        // expiry that nothing renews
+        let steps = plan(&manifest, &current, &expiries, |_| None, false, now + 20).unwrap();
+        let token = steps.iter().find(|s| s.path == "ci/deploy-token").unwrap();
+        assert_eq!((token.change, token.value.as_ref()), (Change::Create, None));
+        let password = steps.iter().find(|s| s.path == "prod/db/password").unwrap();
+        assert_eq!(password.change, Change::Unchanged);
+        assert!(password.expired && password.is_drift());
+        assert!(steps
+            .iter()
+            .all(|s| s.path == "prod/db/password" || !s.expired));
+    }
+}
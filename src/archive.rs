@@ -0,0 +1,105 @@
+use crate::audit::{self, AuditEvent};
+use crate::storage::Storage;
+use crate::{meta, vault};
+use anyhow::Result;
+
+/// Category that archived keys are moved under, keeping their original category below it
+/// ('api/legacy/token' becomes 'archive/api/legacy/token')
+pub const CATEGORY: &str = "archive";
+
+/// Returns true if a key in this category is archived
+pub fn is_archived(category: Option<&str>) -> bool {
+    category.is_some_and(|c| {
+        let c = c.trim_matches('/');
+        c == CATEGORY
+            || c.strip_prefix(CATEGORY)
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Returns the category a key is moved to when it is archived
+pub fn archived_category(category: Option<&str>) -> String {
+    match category
+        .map(|c| c.trim_matches('/'))
+        .filter(|c| !c.is_empty())
+    {
+        Some(category) => format!("{}/{}", CATEGORY, category),
+        None => CATEGORY.to_string(),
+    }
+}
+
+/// Returns the category an archived key came from (None for no category), or None if the
+/// category is not an archive category
+pub fn original_category(category: Option<&str>) -> Option<Option<String>> {
+    if !is_archived(category) {
+        return None;
+    }
+    let category = category?.trim_matches('/');
+    Some(
+        category
+            .strip_prefix(CATEGORY)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .map(|c| c.to_string()),
+    )
+}
+
+/// Moves a stored key with its metadata and attachments from one category to another in a
+/// single commit, recording the move in the audit log. Whatever moves to another top-level
+/// category is re-encrypted with that category's data key. Fails if the key does not exist or the target is
+/// already taken.
+pub async fn move_key(
+    storage: &Storage,
+    master_key: &str,
+    key: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    message: &str,
+    event: &AuditEvent,
+) -> Result<()> {
+    let display = |category: Option<&str>| match category {
+        Some(cat) => format!("{}/{}", cat, key),
+        None => key.to_string(),
+    };
+    let (data, _) = storage
+        .get_blob(key, from)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Key '{}' not found.", display(from)))?;
+    if storage.get_blob(key, to).await?.is_some() {
+        return Err(anyhow::anyhow!(
+            "Key '{}' already exists. Move or delete it first.",
+            display(to)
+        ));
+    }
+
+    let data = vault::reencrypt_for(master_key, to, &data)?;
+    let mut changes = storage.key_changes(key, to, Some(&data)).await?;
+    changes.extend(storage.key_changes(key, from, None).await?);
+    changes.extend(meta::move_changes(storage, master_key, (key, from), (key, to), false).await?);
+    changes.push(audit::append_change(storage, event).await?);
+    storage.commit_changes(&changes, message).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_categories() {
+        assert_eq!(archived_category(Some("api/legacy")), "archive/api/legacy");
+        assert_eq!(archived_category(None), "archive");
+
+        assert!(is_archived(Some("archive")));
+        assert!(is_archived(Some("archive/api")));
+        assert!(!is_archived(Some("archives/api")));
+        assert!(!is_archived(Some("api/archive")));
+        assert!(!is_archived(None));
+
+        assert_eq!(
+            original_category(Some("archive/api/legacy")),
+            Some(Some("api/legacy".to_string()))
+        );
+        assert_eq!(original_category(Some("archive")), Some(None));
+        assert_eq!(original_category(Some("api/legacy")), None);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::storage::{Storage, TreeChange};
+use crate::timeutil;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Repository path of the append-only audit log (one JSON event per line)
+pub const LOG_PATH: &str = ".axkeystore/audit.log";
+
+/// A vault-wide operation recorded in the audit log. Events only hold key paths and
+/// counts, never values.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AuditEvent {
+    /// When the operation happened (RFC 3339, UTC)
+    pub time: String,
+    /// Short operation name (e.g. 'category-merge')
+    pub action: String,
+    /// Human-readable description of what changed
+    pub details: String,
+}
+
+impl AuditEvent {
+    /// Creates an event stamped with the current time
+    pub fn new(action: &str, details: &str) -> Self {
+        Self {
+            time: timeutil::format_rfc3339(timeutil::now_unix()),
+            action: action.to_string(),
+            details: details.to_string(),
+        }
+    }
+
+    /// Returns true if the event is of this action or one of its kinds, so 'category' matches
+    /// 'category-merge' and 'category-delete'
+    pub fn has_action(&self, action: &str) -> bool {
+        self.action
+            .strip_prefix(action)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+    }
+}
+
+/// Parses an audit log, oldest event first. Lines that are not events are skipped.
+pub fn parse(log: &[u8]) -> Vec<AuditEvent> {
+    String::from_utf8_lossy(log)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Reads the audit log of the vault (empty if nothing was recorded yet)
+pub async fn load(storage: &Storage) -> Result<Vec<AuditEvent>> {
+    let log = storage
+        .get_file(LOG_PATH)
+        .await
+        .context("Failed to read the audit log")?;
+    Ok(log.map(|(data, _)| parse(&data)).unwrap_or_default())
+}
+
+/// Appends an event to an existing log, returning the new log content
+pub fn append(log: &[u8], event: &AuditEvent) -> Result<Vec<u8>> {
+    let mut content = log.to_vec();
+    if !content.is_empty() && !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    content.extend(serde_json::to_vec(event)?);
+    content.push(b'\n');
+    Ok(content)
+}
+
+/// Builds the change that appends an event to the audit log, so it can be committed together
+/// with the operation it records
+pub async fn append_change(storage: &Storage, event: &AuditEvent) -> Result<TreeChange> {
+    append_all_change(storage, std::slice::from_ref(event)).await
+}
+
+/// Builds the change that appends several events to the audit log in order
+pub async fn append_all_change(storage: &Storage, events: &[AuditEvent]) -> Result<TreeChange> {
+    let mut log = storage
+        .get_file(LOG_PATH)
+        .await
+        .context("Failed to read the audit log")?
+        .map(|(data, _)| data)
+        .unwrap_or_default();
+    for event in events {
+        log = append(&log, event)?;
+    }
+
+    Ok(TreeChange::Upsert {
+        path: LOG_PATH.to_string(),
+        content: log,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append() {
+        let first = AuditEvent {
+            time: "2024-01-01T10:00:00Z".to_string(),
+            action: "category-merge".to_string(),
+            details: "api/production -> api/prod".to_string(),
+        };
+        let log = append(b"", &first).unwrap();
+        let log = append(&log[..log.len() - 1], &AuditEvent::new("test", "second")).unwrap();
+
+        let lines: Vec<&str> = std::str::from_utf8(&log).unwrap().lines().collect();
+        assert_eq!(lines.len(), 2);
+        let parsed: AuditEvent = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed, first);
+        assert!(log.ends_with(b"\n"));
+    }
+
+    #[test]
+    fn test_parse_and_has_action() {
+        let log = append(b"", &AuditEvent::new("category-merge", "a -> b")).unwrap();
+        let mut log = append(&log, &AuditEvent::new("delete", "api/key")).unwrap();
+        log.extend(b"not an event\n");
+
+        let events = parse(&log);
+        assert_eq!(events.len(), 2);
+        assert!(events[0].has_action("category"));
+        assert!(events[0].has_action("category-merge"));
+        assert!(!events[0].has_action("category-delete"));
+        assert!(!events[0].has_action("cat"));
+        assert!(events[1].has_action("delete"));
+        assert!(!events[1].has_action("category"));
+    }
+}
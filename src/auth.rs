@@ -1,3 +1,5 @@
+use crate::logging::SendLogged;
+use crate::redact;
 use anyhow::{Context, Result};
 use reqwest::Client;
 use serde::Deserialize;
@@ -69,7 +71,10 @@ fn parse_device_code_response(text: &str) -> Result<DeviceCodeResponse> {
                 ));
             }
 
-            return Err(anyhow::anyhow!("Failed to parse response: {}", text));
+            Err(anyhow::anyhow!(
+                "Failed to parse response: {}",
+                redact::redact(text)
+            ))
         }
     }
 }
@@ -87,11 +92,11 @@ pub async fn authenticate() -> Result<String> {
         .post("https://github.com/login/device/code")
         .header("Accept", "application/json")
         .query(&[("client_id", client_id.as_str())]) // Omitted scope for GitHub App
-        .send()
+        .send_logged()
         .await?;
 
     let text = res.text().await?;
-    // println!("Device code response: {}", text); // Debug
+    tracing::trace!("Device code response: {}", redact::redact(&text));
 
     // Try to parse response
     let device_res = parse_device_code_response(&text)?;
@@ -137,55 +142,98 @@ async fn poll_for_token(
                 ("device_code", device_res.device_code.as_str()),
                 ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
             ])
-            .send()
+            .send_logged()
             .await?;
 
         let text = res.text().await?;
-        // println!("Poll response: {}", text); // Debug
+        tracing::trace!("Poll response: {}", redact::redact(&text));
 
         let poll_res: PollResponse = serde_json::from_str(&text)?;
 
         match poll_res {
             PollResponse::Success(token_data) => {
+                redact::register(&token_data.access_token);
                 println!("Successfully authenticated!");
                 return Ok(token_data.access_token);
             }
-            PollResponse::Error(err) => {
-                match err.error.as_str() {
-                    "authorization_pending" => {
-                        // Continue polling
-                    }
-                    "slow_down" => {
-                        interval = Duration::from_secs(err.interval + 5);
-                        println!("Slowing down polling...");
-                    }
-                    "expired_token" => {
-                        return Err(anyhow::anyhow!("Device code expired. Please try again."));
-                    }
-                    "access_denied" => {
-                        return Err(anyhow::anyhow!("Access denied by user."));
-                    }
-                    _ => {
-                        return Err(anyhow::anyhow!(
-                            "Authentication error: {}",
-                            err.error_description
-                        ));
-                    }
+            PollResponse::Error(err) => match err.error.as_str() {
+                "authorization_pending" => {
+                    tracing::debug!(
+                        "Authorization pending, polling again in {} s",
+                        interval.as_secs()
+                    );
                 }
-            }
+                "slow_down" => {
+                    interval = Duration::from_secs(err.interval + 5);
+                    println!("Slowing down polling...");
+                }
+                "expired_token" => {
+                    return Err(anyhow::anyhow!("Device code expired. Please try again."));
+                }
+                "access_denied" => {
+                    return Err(anyhow::anyhow!("Access denied by user."));
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Authentication error: {}",
+                        err.error_description
+                    ));
+                }
+            },
         }
     }
 }
 
 use crate::crypto::{CryptoHandler, EncryptedBlob};
 
+/// Returns the key that encrypts a profile's token: the LMK, mixed with this machine's
+/// fingerprint when the profile binds its token to the device
+fn token_key(profile: Option<&str>, lmk: &str) -> Result<String> {
+    if crate::config::Config::load_with_profile(profile)?.bind_token_to_device {
+        crate::device::bind_key(lmk)
+    } else {
+        Ok(lmk.to_string())
+    }
+}
+
 /// Encrypts and saves the GitHub access token for a specific profile
 pub fn save_token_with_profile(profile: Option<&str>, token: &str, password: &str) -> Result<()> {
     let lmk = crate::config::Config::get_or_create_lmk_with_profile(profile, password)?;
     let config_dir = crate::config::Config::get_config_dir(profile)?;
     let token_path = config_dir.join("github_token.json");
 
-    save_token_to_path(token, &token_path, &lmk)
+    save_token_to_path(token, &token_path, &token_key(profile, &lmk)?)?;
+    // The new token may belong to another account
+    crate::config::Config::forget_login_with_profile(profile)
+}
+
+/// Binds a profile's token to this machine, or releases it, re-encrypting a saved token
+pub fn set_device_binding_with_profile(
+    profile: Option<&str>,
+    password: &str,
+    bind: bool,
+) -> Result<()> {
+    let mut config = crate::config::Config::load_with_profile(profile)?;
+    if config.bind_token_to_device == bind {
+        return Ok(());
+    }
+    let token = if is_logged_in_with_profile(profile) {
+        Some(get_saved_token_with_profile(profile, password)?)
+    } else {
+        None
+    };
+
+    config.bind_token_to_device = bind;
+    config.save_with_profile(profile)?;
+    if let Some(token) = token {
+        if let Err(e) = save_token_with_profile(profile, &token, password) {
+            // Keep the setting matching the token on disk
+            config.bind_token_to_device = !bind;
+            config.save_with_profile(profile)?;
+            return Err(e);
+        }
+    }
+    Ok(())
 }
 
 /// Internal helper to save token to a specific path with encryption
@@ -214,6 +262,7 @@ fn save_token_to_path(token: &str, path: &std::path::Path, key: &str) -> Result<
 /// Retrieves and decrypts the saved GitHub access token for a specific profile
 pub fn get_saved_token_with_profile(profile: Option<&str>, password: &str) -> Result<String> {
     let lmk = crate::config::Config::get_or_create_lmk_with_profile(profile, password)?;
+    let bound = crate::config::Config::load_with_profile(profile)?.bind_token_to_device;
     let config_dir = crate::config::Config::get_config_dir(profile)?;
     let token_path = config_dir.join("github_token.json");
 
@@ -228,8 +277,16 @@ pub fn get_saved_token_with_profile(profile: Option<&str>, password: &str) -> Re
     let encrypted: EncryptedBlob =
         serde_json::from_str(&content).context("Failed to parse encrypted token")?;
 
-    let decrypted = CryptoHandler::decrypt(&encrypted, &lmk)
-        .map_err(|_| anyhow::anyhow!("Incorrect master password or corrupted local master key."))?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, &token_key(profile, &lmk)?).map_err(|_| {
+        if bound {
+            anyhow::anyhow!(
+                "The GitHub token of profile '{}' is bound to another machine. Run 'axkeystore login' on this one.",
+                profile.unwrap_or("default")
+            )
+        } else {
+            anyhow::anyhow!("Incorrect master password or corrupted local master key.")
+        }
+    })?;
 
     Ok(String::from_utf8(decrypted).context("Token is not valid UTF-8")?)
 }
@@ -367,4 +424,36 @@ mod tests {
 
         std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
     }
+
+    #[test]
+    fn test_device_bound_token() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+        std::env::set_var("AXKEYSTORE_TEST_DEVICE_ID", "machine-a");
+
+        let pass = "test-pass";
+        save_token_with_profile(None, "token", pass).unwrap();
+        set_device_binding_with_profile(None, pass, true).unwrap();
+        assert!(
+            crate::config::Config::load_with_profile(None)
+                .unwrap()
+                .bind_token_to_device
+        );
+        assert_eq!(get_saved_token_with_profile(None, pass).unwrap(), "token");
+
+        // A copy of the configuration does not unlock on another machine
+        std::env::set_var("AXKEYSTORE_TEST_DEVICE_ID", "machine-b");
+        let err = get_saved_token_with_profile(None, pass).unwrap_err();
+        assert!(err.to_string().contains("bound to another machine"));
+        assert!(set_device_binding_with_profile(None, pass, false).is_err());
+
+        std::env::set_var("AXKEYSTORE_TEST_DEVICE_ID", "machine-a");
+        set_device_binding_with_profile(None, pass, false).unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_DEVICE_ID", "machine-b");
+        assert_eq!(get_saved_token_with_profile(None, pass).unwrap(), "token");
+
+        std::env::remove_var("AXKEYSTORE_TEST_DEVICE_ID");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
 }
@@ -0,0 +1,535 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::Storage;
+use crate::timeutil;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Version of the backup archive format
+const FORMAT_VERSION: u32 = 2;
+
+/// Version of the archives written before backups were sealed with the master password
+const PLAIN_FORMAT_VERSION: u32 = 1;
+
+/// Directories and files of '.axkeystore' that are not copied into a backup: the master key
+/// has its own field, and shared blobs are restored into the keys that point to them
+const SKIPPED_FILES: [&str; 2] = [crate::storage::MASTER_KEY_PATH, crate::storage::DEDUP_DIR];
+
+/// A snapshot of every key blob in the vault, exactly as stored in the repository. Values stay
+/// encrypted with the master key, so the archive is as safe as the repository itself.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Archive {
+    /// Archive format version
+    pub format: u32,
+    /// The repository the backup was taken from ('owner/repo')
+    pub repo: String,
+    /// When the backup was taken (RFC 3339)
+    pub created: String,
+    /// Base64 encoded encrypted blobs, by key path
+    pub keys: BTreeMap<String, String>,
+    /// Base64 encoded master key blob (encrypted with the master password), so the backup
+    /// can be restored without the repository. Missing from backups of older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub master_key: Option<String>,
+    /// Base64 encoded vault files (metadata, attachments, trash, settings), by repository
+    /// path. Missing from backups of older versions.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+}
+
+/// A backup file: the archive, encrypted as a whole with the master password so not even
+/// the key paths can be read without it
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    format: u32,
+    archive: EncryptedBlob,
+}
+
+/// How a key differs between a backup and the live vault
+#[derive(Debug, PartialEq)]
+pub enum Drift {
+    /// The key exists in the vault but not in the backup
+    NotBackedUp,
+    /// The key is in the backup but was deleted from the vault
+    Deleted,
+    /// The value changed since the backup
+    Changed,
+    /// The value is the same but was re-encrypted (e.g. by 'rekey' or an identical update)
+    Reencrypted,
+    /// The backed-up blob cannot be decrypted with the current master key, so its value
+    /// cannot be compared
+    Unreadable,
+}
+
+/// Returns the hex encoded SHA-256 digest of data
+fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Decrypts a stored blob and returns the hash of its value
+fn value_hash(blob: &[u8], master_key: &str) -> Option<String> {
+    let encrypted: EncryptedBlob = serde_json::from_slice(blob).ok()?;
+    CryptoHandler::decrypt(&encrypted, master_key)
+        .ok()
+        .map(|value| content_hash(&value))
+}
+
+/// Downloads every key blob into a new archive
+pub async fn create(storage: &Storage) -> Result<Archive> {
+    let keys = storage
+        .list_all_keys()
+        .await?
+        .into_iter()
+        .map(|entry| (entry.display_path(), BASE64.encode(&entry.data)))
+        .collect();
+    let master_key = storage
+        .get_master_key_blob()
+        .await?
+        .map(|blob| BASE64.encode(blob));
+    let mut files = BTreeMap::new();
+    for path in storage.list_files_recursive(".axkeystore").await? {
+        crate::cancel::check()?;
+        if is_skipped(&path) {
+            continue;
+        }
+        if let Some(data) = storage.read_file(&path).await? {
+            files.insert(path, BASE64.encode(data));
+        }
+    }
+    Ok(Archive {
+        format: FORMAT_VERSION,
+        repo: storage.repo_full_name(),
+        created: timeutil::format_rfc3339(timeutil::now_unix()),
+        keys,
+        master_key,
+        files,
+    })
+}
+
+/// Returns true for vault files that are not copied into a backup
+fn is_skipped(path: &str) -> bool {
+    SKIPPED_FILES.iter().any(|skipped| {
+        path.strip_prefix(skipped)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Writes an archive, encrypted with the master password, to a file readable only by the
+/// current user
+pub fn write(path: &Path, archive: &Archive, password: &str) -> Result<()> {
+    let sealed = Sealed {
+        format: FORMAT_VERSION,
+        archive: CryptoHandler::encrypt(&serde_json::to_vec(archive)?, password)?,
+    };
+    crate::config::write_private_file(path, &serde_json::to_vec_pretty(&sealed)?)
+}
+
+/// Reads an archive from a file, decrypting it with the master password it was written
+/// with. Unencrypted archives of older versions are read as they are.
+pub fn read(path: &Path, password: &str) -> Result<Archive> {
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let not_a_backup = || format!("'{}' is not an AxKeyStore backup", path.display());
+    let (archive, expected) = match serde_json::from_slice::<Sealed>(&data) {
+        Ok(sealed) => {
+            if sealed.format != FORMAT_VERSION {
+                return Err(anyhow::anyhow!(
+                    "Unsupported backup format {} (expected {})",
+                    sealed.format,
+                    FORMAT_VERSION
+                ));
+            }
+            let plain = CryptoHandler::decrypt(&sealed.archive, password).map_err(|_| {
+                anyhow::anyhow!(
+                    "The master password does not decrypt '{}' (was it changed since the backup?)",
+                    path.display()
+                )
+            })?;
+            let archive: Archive = serde_json::from_slice(&plain).with_context(not_a_backup)?;
+            (archive, FORMAT_VERSION)
+        }
+        Err(_) => (
+            serde_json::from_slice::<Archive>(&data).with_context(not_a_backup)?,
+            PLAIN_FORMAT_VERSION,
+        ),
+    };
+    if archive.format != expected {
+        return Err(anyhow::anyhow!(
+            "Unsupported backup format {} (expected {})",
+            archive.format,
+            expected
+        ));
+    }
+    Ok(archive)
+}
+
+/// Returns the backup's master key blob, as stored in the repository
+pub fn master_key_blob(archive: &Archive) -> Result<Option<Vec<u8>>> {
+    archive
+        .master_key
+        .as_ref()
+        .map(|encoded| {
+            BASE64
+                .decode(encoded)
+                .context("The backup's master key is corrupted")
+        })
+        .transpose()
+}
+
+/// Decrypts the backup's master key with the master password, or returns None for backups
+/// of older versions, which lack it
+pub fn master_key(archive: &Archive, password: &str) -> Result<Option<String>> {
+    let blob = match master_key_blob(archive)? {
+        Some(blob) => blob,
+        None => return Ok(None),
+    };
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&blob).context("The backup's master key is corrupted")?;
+    let master_key = CryptoHandler::decrypt(&encrypted, password).map_err(|_| {
+        anyhow::anyhow!("The master password does not decrypt the backup's master key")
+    })?;
+    Ok(Some(
+        String::from_utf8(master_key).context("Master key is not valid UTF-8")?,
+    ))
+}
+
+/// Decodes the key blobs of a backup, by key path
+pub fn key_blobs(archive: &Archive) -> Result<Vec<(String, Vec<u8>)>> {
+    archive
+        .keys
+        .iter()
+        .map(|(path, encoded)| {
+            let data = BASE64
+                .decode(encoded)
+                .with_context(|| format!("Backup entry '{}' is corrupted", path))?;
+            Ok((path.clone(), data))
+        })
+        .collect()
+}
+
+/// Decodes the vault files of a backup, by repository path
+pub fn vault_files(archive: &Archive) -> Result<Vec<(String, Vec<u8>)>> {
+    archive
+        .files
+        .iter()
+        .map(|(path, encoded)| {
+            let data = BASE64
+                .decode(encoded)
+                .with_context(|| format!("Backup file '{}' is corrupted", path))?;
+            Ok((path.clone(), data))
+        })
+        .collect()
+}
+
+/// Compares a backup with the live key blobs (by key path). Blobs are compared by content
+/// hash first; only blobs that differ are decrypted, to tell real changes from re-encryption.
+/// Keys that match are not listed.
+pub fn diff(
+    archive: &Archive,
+    live: &BTreeMap<String, Vec<u8>>,
+    master_key: &str,
+) -> Result<Vec<(String, Drift)>> {
+    let mut drift = Vec::new();
+    for (path, encoded) in &archive.keys {
+        let backed_up = BASE64
+            .decode(encoded)
+            .with_context(|| format!("Backup entry '{}' is corrupted", path))?;
+        let current = match live.get(path) {
+            Some(current) => current,
+            None => {
+                drift.push((path.clone(), Drift::Deleted));
+                continue;
+            }
+        };
+        if content_hash(&backed_up) == content_hash(current) {
+            continue;
+        }
+        let kind = match (
+            value_hash(&backed_up, master_key),
+            value_hash(current, master_key),
+        ) {
+            (None, _) => Drift::Unreadable,
+            (Some(old), Some(new)) if old == new => Drift::Reencrypted,
+            _ => Drift::Changed,
+        };
+        drift.push((path.clone(), kind));
+    }
+    for path in live.keys() {
+        if !archive.keys.contains_key(path) {
+            drift.push((path.clone(), Drift::NotBackedUp));
+        }
+    }
+    drift.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(drift)
+}
+
+/// Where a recovery drill got the master key from
+#[derive(Debug, PartialEq)]
+pub enum MasterKeySource {
+    /// The backup's own master key, decrypted with the master password
+    Backup,
+    /// The backup has no master key; the live vault's was used instead, so a recovery
+    /// without the repository would fail
+    LiveVault,
+    /// Neither the backup nor the live vault provided a master key
+    Missing,
+}
+
+/// What happened when a key was read back from a restored backup
+#[derive(Debug, PartialEq)]
+pub enum Recovery {
+    /// The value decrypted
+    Ok,
+    /// The entry is not a well-formed encrypted blob
+    Corrupted(String),
+    /// The blob does not decrypt with the master key
+    Unreadable,
+}
+
+/// The outcome of a recovery drill
+#[derive(Debug)]
+pub struct Drill {
+    /// Number of keys restored from the backup
+    pub restored: usize,
+    pub master_key: MasterKeySource,
+    /// The keys read back, by path, and what happened
+    pub sampled: Vec<(String, Recovery)>,
+}
+
+impl Drill {
+    /// Returns true if the backup alone would bring every sampled key back
+    pub fn passed(&self) -> bool {
+        self.master_key == MasterKeySource::Backup
+            && self.sampled.iter().all(|(_, r)| *r == Recovery::Ok)
+    }
+}
+
+/// Rehearses a recovery without touching any repository: restores the backup into memory,
+/// decrypts its master key with the master password (or falls back to the live vault's
+/// master key for backups without one), then reads back up to `sample` randomly chosen keys
+/// the way 'get' would. Keys outside `category` (when given) are not sampled.
+pub fn drill(
+    archive: &Archive,
+    password: &str,
+    live_master_key: Option<&str>,
+    category: Option<&str>,
+    sample: usize,
+) -> Result<Drill> {
+    let mut restored = BTreeMap::new();
+    for (path, encoded) in &archive.keys {
+        restored.insert(path.as_str(), BASE64.decode(encoded));
+    }
+
+    let (master_key, source) = match master_key(archive, password)? {
+        Some(master_key) => (Some(master_key), MasterKeySource::Backup),
+        None => match live_master_key {
+            Some(master_key) => (Some(master_key.to_string()), MasterKeySource::LiveVault),
+            None => (None, MasterKeySource::Missing),
+        },
+    };
+
+    let candidates = restored.iter().filter(|(path, _)| {
+        category.is_none_or(|c| {
+            let c = c.trim_matches('/');
+            path.strip_prefix(c)
+                .is_some_and(|rest| rest.starts_with('/'))
+        })
+    });
+    let mut chosen = candidates.choose_multiple(&mut rand::rng(), sample);
+    chosen.sort_by_key(|(path, _)| **path);
+
+    let sampled = chosen
+        .into_iter()
+        .map(|(path, decoded)| {
+            let outcome = match decoded {
+                Err(e) => Recovery::Corrupted(format!("not valid base64: {}", e)),
+                Ok(data) => match serde_json::from_slice::<EncryptedBlob>(data)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|blob| CryptoHandler::check_structure(&blob).map(|_| blob))
+                {
+                    Err(e) => Recovery::Corrupted(e.to_string()),
+                    Ok(blob) => match &master_key {
+                        Some(key) if CryptoHandler::decrypt(&blob, key).is_ok() => Recovery::Ok,
+                        _ => Recovery::Unreadable,
+                    },
+                },
+            };
+            (path.to_string(), outcome)
+        })
+        .collect();
+
+    Ok(Drill {
+        restored: restored.len(),
+        master_key: source,
+        sampled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(value: &[u8], master_key: &str) -> Vec<u8> {
+        serde_json::to_vec(&CryptoHandler::encrypt(value, master_key).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let key = "master-key";
+        let same = blob(b"unchanged", key);
+        let old_api = blob(b"old", key);
+        let old_db = blob(b"db-pass", key);
+        let archive = Archive {
+            format: FORMAT_VERSION,
+            repo: "octocat/vault".to_string(),
+            created: "2024-01-01T00:00:00Z".to_string(),
+            keys: [
+                ("same", &same),
+                ("cloud/api", &old_api),
+                ("cloud/db", &old_db),
+                ("gone", &same),
+                ("legacy", &blob(b"x", "previous-master-key")),
+            ]
+            .into_iter()
+            .map(|(path, data)| (path.to_string(), BASE64.encode(data)))
+            .collect(),
+            master_key: None,
+            files: BTreeMap::new(),
+        };
+
+        let live: BTreeMap<String, Vec<u8>> = [
+            ("same", same.clone()),
+            ("cloud/api", blob(b"new", key)),
+            ("cloud/db", blob(b"db-pass", key)),
+            ("legacy", blob(b"x", key)),
+            ("fresh", blob(b"fresh", key)),
+        ]
+        .into_iter()
+        .map(|(path, data)| (path.to_string(), data))
+        .collect();
+
+        assert_eq!(
+            diff(&archive, &live, key).unwrap(),
+            vec![
+                ("cloud/api".to_string(), Drift::Changed),
+                ("cloud/db".to_string(), Drift::Reencrypted),
+                ("fresh".to_string(), Drift::NotBackedUp),
+                ("gone".to_string(), Drift::Deleted),
+                ("legacy".to_string(), Drift::Unreadable),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drill() {
+        let (password, key) = ("master-password", "master-key");
+        let master_key_blob =
+            serde_json::to_vec(&CryptoHandler::encrypt(key.as_bytes(), password).unwrap()).unwrap();
+        let mut archive = Archive {
+            format: FORMAT_VERSION,
+            repo: "octocat/vault".to_string(),
+            created: "2024-01-01T00:00:00Z".to_string(),
+            keys: [
+                ("cloud/api", blob(b"api", key)),
+                ("cloud/db", blob(b"db", key)),
+                ("legacy", blob(b"x", "previous-master-key")),
+                ("broken", b"not json".to_vec()),
+            ]
+            .into_iter()
+            .map(|(path, data)| (path.to_string(), BASE64.encode(data)))
+            .collect(),
+            master_key: Some(BASE64.encode(&master_key_blob)),
+            files: BTreeMap::new(),
+        };
+
+        let report = drill(&archive, password, None, None, 10).unwrap();
+        assert_eq!(report.restored, 4);
+        assert_eq!(report.master_key, MasterKeySource::Backup);
+        assert_eq!(report.sampled.len(), 4);
+        assert!(matches!(report.sampled[0], (ref p, Recovery::Corrupted(_)) if p == "broken"));
+        assert_eq!(report.sampled[1], ("cloud/api".to_string(), Recovery::Ok));
+        assert_eq!(
+            report.sampled[3],
+            ("legacy".to_string(), Recovery::Unreadable)
+        );
+        assert!(!report.passed());
+
+        // Sampling stays within the category and the requested size
+        let report = drill(&archive, password, None, Some("cloud"), 1).unwrap();
+        assert_eq!(report.sampled.len(), 1);
+        assert!(report.sampled[0].0.starts_with("cloud/"));
+        assert!(report.passed());
+
+        assert!(drill(&archive, "wrong-password", None, None, 10).is_err());
+
+        // Older backups need the live vault's master key
+        archive.master_key = None;
+        let report = drill(&archive, password, Some(key), Some("cloud"), 10).unwrap();
+        assert_eq!(report.master_key, MasterKeySource::LiveVault);
+        assert!(report.sampled.iter().all(|(_, r)| *r == Recovery::Ok));
+        assert!(!report.passed());
+        let report = drill(&archive, password, None, Some("cloud"), 10).unwrap();
+        assert_eq!(report.master_key, MasterKeySource::Missing);
+        assert_eq!(report.sampled[0].1, Recovery::Unreadable);
+    }
+
+    #[test]
+    fn test_sealed_archive() {
+        let (password, key) = ("master-password", "master-key");
+        let master_key_blob =
+            serde_json::to_vec(&CryptoHandler::encrypt(key.as_bytes(), password).unwrap()).unwrap();
+        let archive = Archive {
+            format: FORMAT_VERSION,
+            repo: "octocat/vault".to_string(),
+            created: "2024-01-01T00:00:00Z".to_string(),
+            keys: [("cloud/api".to_string(), BASE64.encode(blob(b"api", key)))].into(),
+            master_key: Some(BASE64.encode(&master_key_blob)),
+            files: [(
+                ".axkeystore/meta/cloud/api.json".to_string(),
+                BASE64.encode(b"{}"),
+            )]
+            .into(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("vault.axk");
+        write(&file, &archive, password).unwrap();
+
+        // Nothing, not even the key paths, is readable without the password
+        let raw = std::fs::read_to_string(&file).unwrap();
+        assert!(!raw.contains("cloud/api"));
+        assert_eq!(read(&file, password).unwrap(), archive);
+        assert!(read(&file, "wrong-password").is_err());
+        assert_eq!(
+            master_key(&archive, password).unwrap().as_deref(),
+            Some(key)
+        );
+        assert_eq!(
+            vault_files(&archive).unwrap(),
+            vec![(
+                ".axkeystore/meta/cloud/api.json".to_string(),
+                b"{}".to_vec()
+            )]
+        );
+
+        // Backups of older versions were written unencrypted
+        let plain = dir.path().join("vault.json");
+        let legacy = Archive {
+            format: PLAIN_FORMAT_VERSION,
+            master_key: None,
+            files: BTreeMap::new(),
+            ..archive
+        };
+        std::fs::write(&plain, serde_json::to_vec(&legacy).unwrap()).unwrap();
+        assert_eq!(read(&plain, password).unwrap(), legacy);
+        assert_eq!(master_key(&legacy, password).unwrap(), None);
+
+        assert!(is_skipped(".axkeystore/master_key.json"));
+        assert!(is_skipped(".axkeystore/dedup/abc.json"));
+        assert!(!is_skipped(".axkeystore/dedup-settings.json"));
+        assert!(!is_skipped(".axkeystore/meta/cloud/api.json"));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::config::{CachePolicy, Config};
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::timeutil::now_unix;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Kinds of material that may be cached locally.
+/// Decrypted secret values are intentionally not representable here: they are never cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CacheKind {
+    /// Keys derived from passwords or the master key
+    DerivedKeys,
+    /// Non-secret metadata such as key listings and the account login
+    Metadata,
+}
+
+impl CacheKind {
+    /// All cache kinds, in display order
+    pub const ALL: [CacheKind; 2] = [CacheKind::DerivedKeys, CacheKind::Metadata];
+
+    /// Directory name used for this kind inside the profile cache directory
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            CacheKind::DerivedKeys => "derived_keys",
+            CacheKind::Metadata => "metadata",
+        }
+    }
+
+    /// Returns the configured lifetime in seconds for this kind
+    pub fn ttl(&self, policy: &CachePolicy) -> u64 {
+        match self {
+            CacheKind::DerivedKeys => policy.derived_keys_ttl,
+            CacheKind::Metadata => policy.metadata_ttl,
+        }
+    }
+
+    /// Updates the configured lifetime in seconds for this kind
+    pub fn set_ttl(&self, policy: &mut CachePolicy, ttl: u64) {
+        match self {
+            CacheKind::DerivedKeys => policy.derived_keys_ttl = ttl,
+            CacheKind::Metadata => policy.metadata_ttl = ttl,
+        }
+    }
+}
+
+/// A cached item on disk, encrypted with the profile's Local Master Key
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// Unix timestamp at which the entry was written
+    created_at: u64,
+    /// The encrypted cached payload
+    blob: EncryptedBlob,
+}
+
+/// Summary of the cache state for one kind
+pub struct CacheStatus {
+    pub kind: CacheKind,
+    /// Configured lifetime in seconds (0 means disabled)
+    pub ttl: u64,
+    /// Number of entries currently on disk
+    pub entries: usize,
+    /// Age in seconds of the oldest entry, if any
+    pub oldest_age: Option<u64>,
+}
+
+/// Returns the cache directory for a specific kind within a profile
+fn cache_dir(profile: Option<&str>, kind: CacheKind) -> Result<PathBuf> {
+    Ok(Config::get_config_dir(profile)?
+        .join("cache")
+        .join(kind.dir_name()))
+}
+
+/// Maps an arbitrary cache entry name to a safe file name
+fn entry_path(profile: Option<&str>, kind: CacheKind, name: &str) -> Result<PathBuf> {
+    let safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Ok(cache_dir(profile, kind)?.join(format!("{}.json", safe)))
+}
+
+/// Stores an entry in the cache if the profile's policy allows caching this kind
+pub fn store(
+    profile: Option<&str>,
+    kind: CacheKind,
+    name: &str,
+    data: &[u8],
+    lmk: &str,
+) -> Result<()> {
+    let policy = Config::load_with_profile(profile)?.cache_policy;
+    if kind.ttl(&policy) == 0 {
+        return Ok(());
+    }
+
+    let path = entry_path(profile, kind, name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let entry = CacheEntry {
+        created_at: now_unix(),
+        blob: CryptoHandler::encrypt(data, lmk)?,
+    };
+    crate::config::write_private_file(&path, &serde_json::to_vec(&entry)?)
+}
+
+/// Loads an entry from the cache, discarding it if it has expired or caching is disabled
+pub fn load(
+    profile: Option<&str>,
+    kind: CacheKind,
+    name: &str,
+    lmk: &str,
+) -> Result<Option<Vec<u8>>> {
+    let path = entry_path(profile, kind, name)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let policy = Config::load_with_profile(profile)?.cache_policy;
+    let ttl = kind.ttl(&policy);
+
+    let content = std::fs::read(&path)?;
+    let entry: CacheEntry = match serde_json::from_slice(&content) {
+        Ok(e) => e,
+        Err(_) => {
+            std::fs::remove_file(&path)?;
+            return Ok(None);
+        }
+    };
+
+    if ttl == 0 || now_unix().saturating_sub(entry.created_at) > ttl {
+        std::fs::remove_file(&path)?;
+        return Ok(None);
+    }
+
+    let data = CryptoHandler::decrypt(&entry.blob, lmk).context("Failed to decrypt cache entry")?;
+    Ok(Some(data))
+}
+
+/// Reports the policy and on-disk state of every cache kind for a profile
+pub fn status(profile: Option<&str>) -> Result<Vec<CacheStatus>> {
+    let policy = Config::load_with_profile(profile)?.cache_policy;
+    let now = now_unix();
+    let mut result = Vec::new();
+
+    for kind in CacheKind::ALL {
+        let dir = cache_dir(profile, kind)?;
+        let mut entries = 0;
+        let mut oldest: Option<u64> = None;
+
+        if dir.exists() {
+            for item in std::fs::read_dir(&dir)? {
+                let path = item?.path();
+                if !path.is_file() {
+                    continue;
+                }
+                entries += 1;
+                let created = std::fs::read(&path)
+                    .ok()
+                    .and_then(|c| serde_json::from_slice::<CacheEntry>(&c).ok())
+                    .map(|e| e.created_at);
+                if let Some(created) = created {
+                    let age = now.saturating_sub(created);
+                    oldest = Some(oldest.map_or(age, |o| o.max(age)));
+                }
+            }
+        }
+
+        result.push(CacheStatus {
+            kind,
+            ttl: kind.ttl(&policy),
+            entries,
+            oldest_age: oldest,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Removes all cached entries of a kind (or of every kind) and returns how many were removed
+pub fn clear(profile: Option<&str>, kind: Option<CacheKind>) -> Result<usize> {
+    let kinds: Vec<CacheKind> = match kind {
+        Some(k) => vec![k],
+        None => CacheKind::ALL.to_vec(),
+    };
+
+    let mut removed = 0;
+    for kind in kinds {
+        let dir = cache_dir(profile, kind)?;
+        if !dir.exists() {
+            continue;
+        }
+        for item in std::fs::read_dir(&dir)? {
+            let path = item?.path();
+            if path.is_file() {
+                std::fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Updates the cache lifetime for a kind and purges existing entries when caching is disabled
+pub fn set_ttl(profile: Option<&str>, kind: CacheKind, ttl: u64) -> Result<()> {
+    let mut config = Config::load_with_profile(profile)?;
+    kind.set_ttl(&mut config.cache_policy, ttl);
+    config.save_with_profile(profile)?;
+
+    if ttl == 0 {
+        clear(profile, Some(kind))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_store_and_load() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        // Metadata caching is enabled by default
+        store(None, CacheKind::Metadata, "login", b"octocat", "lmk").unwrap();
+        assert_eq!(
+            load(None, CacheKind::Metadata, "login", "lmk").unwrap(),
+            Some(b"octocat".to_vec())
+        );
+
+        // Derived keys are disabled by default, so nothing is written
+        store(None, CacheKind::DerivedKeys, "k", b"secret", "lmk").unwrap();
+        assert!(load(None, CacheKind::DerivedKeys, "k", "lmk")
+            .unwrap()
+            .is_none());
+
+        let statuses = status(None).unwrap();
+        let metadata = statuses
+            .iter()
+            .find(|s| s.kind == CacheKind::Metadata)
+            .unwrap();
+        assert_eq!(metadata.entries, 1);
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[test]
+    fn test_cache_expiry_and_clear() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        set_ttl(None, CacheKind::DerivedKeys, 60).unwrap();
+        store(None, CacheKind::DerivedKeys, "key", b"derived", "lmk").unwrap();
+
+        // Rewrite the entry as if it had been created long ago
+        let path = entry_path(None, CacheKind::DerivedKeys, "key").unwrap();
+        let mut entry: CacheEntry = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        entry.created_at -= 120;
+        std::fs::write(&path, serde_json::to_vec(&entry).unwrap()).unwrap();
+
+        assert!(load(None, CacheKind::DerivedKeys, "key", "lmk")
+            .unwrap()
+            .is_none());
+        assert!(!path.exists());
+
+        store(None, CacheKind::Metadata, "a", b"1", "lmk").unwrap();
+        store(None, CacheKind::Metadata, "b", b"2", "lmk").unwrap();
+        assert_eq!(clear(None, None).unwrap(), 2);
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+}
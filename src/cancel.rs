@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Set when the user asks a long operation to stop (Ctrl-C). Bulk operations check it
+/// between items, so they stop where their progress is saved rather than in the middle of
+/// a commit or a local file write.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The error a bulk operation returns when it stops because cancellation was requested
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Asks running bulk operations to stop at their next checkpoint
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Returns true once cancellation has been requested
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Fails with [`Cancelled`] if cancellation has been requested. Call it at points where
+/// stopping leaves a consistent state.
+pub fn check() -> anyhow::Result<()> {
+    if is_requested() {
+        return Err(Cancelled.into());
+    }
+    Ok(())
+}
+
+/// Returns true if an error was caused by a cancellation
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<Cancelled>())
+}
+
+/// Completes once cancellation has been requested, for operations that wait between steps
+pub async fn requested() {
+    while !is_requested() {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_is_cancelled() {
+        let err = Err::<(), _>(anyhow::Error::new(Cancelled))
+            .context("Failed to list keys")
+            .unwrap_err();
+        assert!(is_cancelled(&err));
+        assert!(!is_cancelled(&anyhow::anyhow!("Network error")));
+    }
+}
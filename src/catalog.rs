@@ -0,0 +1,236 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::derived;
+use crate::meta::{self, KeyMeta};
+use crate::progress::Progress;
+use crate::storage::Storage;
+use anyhow::Result;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Output formats of the metadata catalog
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CatalogFormat {
+    Json,
+    Csv,
+}
+
+/// Formats accepted by 'export': the catalog formats, or Docker secrets (which holds values)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    /// A directory with one file per secret, for Docker Swarm and Compose secrets
+    DockerSecrets,
+    /// A password store for pass and gopass, encrypted to the --gpg-id keys
+    Pass,
+}
+
+impl ExportFormat {
+    /// Returns the catalog format, or None for formats that export values
+    pub fn catalog_format(self) -> Option<CatalogFormat> {
+        match self {
+            ExportFormat::Json => Some(CatalogFormat::Json),
+            ExportFormat::Csv => Some(CatalogFormat::Csv),
+            ExportFormat::DockerSecrets | ExportFormat::Pass => None,
+        }
+    }
+}
+
+/// Non-secret description of one key. Never holds a value, ciphertext, or value length.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CatalogEntry {
+    /// Full key path ('category/name')
+    pub path: String,
+    pub category: Option<String>,
+    pub name: String,
+    /// 'text', 'binary', 'totp', 'derived', or 'unreadable' if it does not decrypt
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// When the key was last changed (RFC 3339)
+    pub last_changed: Option<String>,
+    /// Committer email of the last change, if GitHub reports one
+    pub changed_by: Option<String>,
+    /// Owner recorded in the key's metadata
+    pub owner: Option<String>,
+    /// Tags recorded in the key's metadata
+    pub tags: Vec<String>,
+}
+
+/// Classifies a decrypted value without exposing it
+pub fn classify(value: &[u8]) -> &'static str {
+    match std::str::from_utf8(value) {
+        Ok(text) if text.trim_start().starts_with("otpauth://totp/") => "totp",
+        Ok(_) => "text",
+        Err(_) => "binary",
+    }
+}
+
+/// Builds the catalog of every key (optionally restricted to a category), sorted by path.
+/// Values are decrypted only to classify them.
+pub async fn build(
+    storage: &Storage,
+    master_key: &str,
+    category: Option<&str>,
+) -> Result<Vec<CatalogEntry>> {
+    let mut catalog = Vec::new();
+    let metadata: HashMap<String, KeyMeta> = meta::load_all(storage, master_key)
+        .await?
+        .into_iter()
+        .collect();
+    let owner_and_tags = |path: &str| {
+        metadata
+            .get(path)
+            .map_or((None, Vec::new()), |m| (m.owner.clone(), m.tags.clone()))
+    };
+
+    let entries: Vec<_> = storage
+        .list_all_keys()
+        .await?
+        .into_iter()
+        .filter(|e| category.is_none_or(|c| e.in_category(c)))
+        .collect();
+    let progress = Progress::new(entries.len(), "Cataloguing");
+    // Histories are fetched a few at a time; the catalog is sorted afterwards
+    let fetched: Vec<CatalogEntry> = stream::iter(entries)
+        .map(|entry| {
+            let progress = &progress;
+            let owner_and_tags = &owner_and_tags;
+            async move {
+                progress.item(&entry.display_path());
+                let kind = serde_json::from_slice::<EncryptedBlob>(&entry.data)
+                    .ok()
+                    .and_then(|b| CryptoHandler::decrypt(&b, master_key).ok())
+                    .map_or("unreadable", |v| classify(&v));
+                let version = storage
+                    .get_latest_key_version(&entry.name, entry.category.as_deref())
+                    .await?;
+
+                let path = entry.display_path();
+                let (owner, tags) = owner_and_tags(&path);
+                progress.succeed();
+                Ok::<_, anyhow::Error>(CatalogEntry {
+                    path,
+                    category: entry.category.clone(),
+                    name: entry.name.clone(),
+                    kind: kind.to_string(),
+                    last_changed: version.as_ref().map(|v| v.date.clone()),
+                    changed_by: version.and_then(|v| v.committer_email),
+                    owner,
+                    tags,
+                })
+            }
+        })
+        .buffer_unordered(storage.parallelism())
+        .try_collect()
+        .await?;
+    catalog.extend(fetched);
+    progress.finish();
+
+    // Derived keys have no file of their own; their history lives in the registry
+    let registry = derived::load(storage, master_key).await?;
+    for id in registry.keys.keys() {
+        if category.is_some_and(|c| !id.starts_with(&format!("{}/", c.trim_matches('/')))) {
+            continue;
+        }
+        let (cat, name) = match id.rsplit_once('/') {
+            Some((cat, name)) => (Some(cat.to_string()), name.to_string()),
+            None => (None, id.clone()),
+        };
+        let (owner, tags) = owner_and_tags(id);
+        catalog.push(CatalogEntry {
+            path: id.clone(),
+            category: cat,
+            name,
+            kind: "derived".to_string(),
+            last_changed: None,
+            changed_by: None,
+            owner,
+            tags,
+        });
+    }
+
+    catalog.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(catalog)
+}
+
+/// Quotes a CSV field when it contains a separator, quote, or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Renders the catalog in the requested format
+pub fn render(catalog: &[CatalogEntry], format: CatalogFormat) -> Result<String> {
+    match format {
+        CatalogFormat::Json => Ok(serde_json::to_string_pretty(catalog)? + "\n"),
+        CatalogFormat::Csv => {
+            let mut out =
+                String::from("path,category,name,type,last_changed,changed_by,owner,tags\n");
+            for e in catalog {
+                let tags = e.tags.join(";");
+                let fields = [
+                    e.path.as_str(),
+                    e.category.as_deref().unwrap_or_default(),
+                    e.name.as_str(),
+                    e.kind.as_str(),
+                    e.last_changed.as_deref().unwrap_or_default(),
+                    e.changed_by.as_deref().unwrap_or_default(),
+                    e.owner.as_deref().unwrap_or_default(),
+                    tags.as_str(),
+                ];
+                let fields: Vec<String> = fields.into_iter().map(csv_field).collect();
+                out.push_str(&fields.join(","));
+                out.push('\n');
+            }
+            Ok(out)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> CatalogEntry {
+        CatalogEntry {
+            path: "cloud/aws/api".to_string(),
+            category: Some("cloud/aws".to_string()),
+            name: "api".to_string(),
+            kind: "text".to_string(),
+            last_changed: Some("2024-01-01T10:00:00Z".to_string()),
+            changed_by: Some("jane, \"ops\"@example.com".to_string()),
+            owner: Some("payments".to_string()),
+            tags: vec!["prod".to_string(), "stripe".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(b"hunter2"), "text");
+        assert_eq!(classify(&[0xff, 0xfe, 0x00]), "binary");
+        assert_eq!(
+            classify(b"otpauth://totp/ACME:jane?secret=GEZDGNBV"),
+            "totp"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let csv = render(&[entry()], CatalogFormat::Csv).unwrap();
+        assert_eq!(
+            csv,
+            "path,category,name,type,last_changed,changed_by,owner,tags\ncloud/aws/api,cloud/aws,api,text,2024-01-01T10:00:00Z,\"jane, \"\"ops\"\"@example.com\",payments,prod;stripe\n"
+        );
+
+        let json = render(&[entry()], CatalogFormat::Json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0]["type"], "text");
+        assert_eq!(parsed[0]["path"], "cloud/aws/api");
+        assert_eq!(parsed[0]["tags"][1], "stripe");
+    }
+}
@@ -0,0 +1,207 @@
+use crate::cache::{self, CacheKind};
+use crate::storage::KeyEntry;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Returns the cache entry name of the category index of a repository
+fn index_name(repo: &str) -> String {
+    format!("categories_{}", repo)
+}
+
+/// Collects the distinct categories of a set of keys
+pub fn from_entries(entries: &[KeyEntry]) -> BTreeSet<String> {
+    entries.iter().filter_map(|e| e.category.clone()).collect()
+}
+
+/// Counts the keys of every category from the display paths of the keys: the keys directly
+/// in it and those in its whole tree. Parent categories holding only subcategories are listed
+/// too.
+pub fn counts<'a>(paths: impl IntoIterator<Item = &'a str>) -> BTreeMap<String, (usize, usize)> {
+    let mut counts: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for path in paths {
+        let category = match path.trim_matches('/').rsplit_once('/') {
+            Some((category, _)) => category,
+            None => continue,
+        };
+        counts.entry(category.to_string()).or_default().0 += 1;
+        let mut parent = Some(category);
+        while let Some(current) = parent {
+            counts.entry(current.to_string()).or_default().1 += 1;
+            parent = current.rsplit_once('/').map(|(p, _)| p);
+        }
+    }
+    counts
+}
+
+/// Loads the cached category index of a repository, or None if it is missing or expired
+pub fn load_index(
+    profile: Option<&str>,
+    repo: &str,
+    lmk: &str,
+) -> Result<Option<BTreeSet<String>>> {
+    match cache::load(profile, CacheKind::Metadata, &index_name(repo), lmk)? {
+        Some(data) => Ok(Some(
+            serde_json::from_slice(&data).context("Cached category index is corrupted")?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Replaces the cached category index of a repository
+pub fn store_index(
+    profile: Option<&str>,
+    repo: &str,
+    lmk: &str,
+    categories: &BTreeSet<String>,
+) -> Result<()> {
+    cache::store(
+        profile,
+        CacheKind::Metadata,
+        &index_name(repo),
+        &serde_json::to_vec(categories)?,
+        lmk,
+    )
+}
+
+/// Adds a category to the cached index, if there is one
+pub fn record(profile: Option<&str>, repo: &str, lmk: &str, category: &str) -> Result<()> {
+    if let Some(mut categories) = load_index(profile, repo, lmk)? {
+        if categories.insert(category.trim_matches('/').to_string()) {
+            store_index(profile, repo, lmk, &categories)?;
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of the category picker
+#[derive(Debug, PartialEq)]
+pub enum Choice {
+    /// Store the key without a category
+    Root,
+    /// An existing category
+    Existing(String),
+    /// A category that does not exist yet
+    New(String),
+}
+
+/// Interprets the picker input: blank for no category, the number of a listed category, or a
+/// category path typed out in full
+pub fn resolve_choice(input: &str, categories: &[String]) -> Choice {
+    let input = input.trim().trim_matches('/');
+    if input.is_empty() {
+        return Choice::Root;
+    }
+    if let Ok(n) = input.parse::<usize>() {
+        if (1..=categories.len()).contains(&n) {
+            return Choice::Existing(categories[n - 1].clone());
+        }
+    }
+    match categories.iter().find(|c| c.as_str() == input) {
+        Some(c) => Choice::Existing(c.clone()),
+        None => Choice::New(input.to_string()),
+    }
+}
+
+/// Returns the categories that start with the typed prefix
+pub fn filter<'a>(prefix: &str, categories: &'a [String]) -> Vec<&'a String> {
+    let prefix = prefix.trim().trim_matches('/');
+    categories
+        .iter()
+        .filter(|c| c.starts_with(prefix))
+        .collect()
+}
+
+/// Returns existing categories a new category is likely a typo or variant of: same parent
+/// and a last segment that is a prefix of the other's or within two edits of it
+pub fn similar<'a>(category: &str, categories: &'a [String]) -> Vec<&'a String> {
+    let split = |c: &str| -> (String, String) {
+        match c.rsplit_once('/') {
+            Some((parent, last)) => (parent.to_string(), last.to_string()),
+            None => (String::new(), c.to_string()),
+        }
+    };
+    let (parent, last) = split(category.trim_matches('/'));
+
+    categories
+        .iter()
+        .filter(|c| {
+            let (other_parent, other_last) = split(c);
+            other_parent == parent
+                && other_last != last
+                && (other_last.starts_with(&last)
+                    || last.starts_with(&other_last)
+                    || edit_distance(&last, &other_last) <= 2)
+        })
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn categories() -> Vec<String> {
+        vec![
+            "api/prod".to_string(),
+            "api/staging".to_string(),
+            "cloud/aws".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_counts() {
+        let counts = counts(["api/stage/db", "api/stage/token", "api/prod/db", "root-key"]);
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("api".to_string(), (0, 3)),
+                ("api/prod".to_string(), (1, 1)),
+                ("api/stage".to_string(), (2, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_choice() {
+        let cats = categories();
+        assert_eq!(resolve_choice("", &cats), Choice::Root);
+        assert_eq!(
+            resolve_choice("2", &cats),
+            Choice::Existing("api/staging".into())
+        );
+        assert_eq!(
+            resolve_choice("/cloud/aws/", &cats),
+            Choice::Existing("cloud/aws".into())
+        );
+        assert_eq!(resolve_choice("9", &cats), Choice::New("9".into()));
+        assert_eq!(
+            resolve_choice("db/main", &cats),
+            Choice::New("db/main".into())
+        );
+        assert_eq!(filter("api", &cats).len(), 2);
+    }
+
+    #[test]
+    fn test_similar() {
+        let cats = categories();
+        assert_eq!(similar("api/production", &cats), vec!["api/prod"]);
+        assert_eq!(similar("api/stagign", &cats), vec!["api/staging"]);
+        assert_eq!(similar("cloud/aws", &cats), Vec::<&String>::new());
+        assert!(similar("db/prod", &cats).is_empty());
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
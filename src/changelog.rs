@@ -0,0 +1,321 @@
+use crate::storage::KeyVersion;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Output formats of the changelog
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChangelogFormat {
+    Markdown,
+    Text,
+    Json,
+}
+
+/// Kinds of secret changes, in the order they are listed
+const SECTIONS: [&str; 11] = [
+    "Added",
+    "Updated",
+    "Rotated",
+    "Reserved",
+    "Deleted",
+    "Moved",
+    "Archived",
+    "Unarchived",
+    "Imported",
+    "Metadata changed",
+    "Re-encrypted",
+];
+
+/// Commit message prefixes written by axkeystore and the kind of change each records
+const PREFIXES: [(&str, &str); 16] = [
+    ("Create key: ", "Added"),
+    ("Add derived key: ", "Added"),
+    ("Update key: ", "Updated"),
+    ("Rotate derived key: ", "Rotated"),
+    ("Reserve key: ", "Reserved"),
+    ("Delete key: ", "Deleted"),
+    ("Delete derived key: ", "Deleted"),
+    ("Merge category: ", "Moved"),
+    ("Archive key: ", "Archived"),
+    ("Unarchive key: ", "Unarchived"),
+    ("Import keys into ", "Imported"),
+    ("Update metadata of key: ", "Metadata changed"),
+    ("Update metadata of keys: ", "Metadata changed"),
+    ("Delete metadata of key: ", "Metadata changed"),
+    ("Attach file to key: ", "Metadata changed"),
+    ("Remove attachment from key: ", "Metadata changed"),
+];
+
+/// Kinds of operations a commit can record, for filtering a key's history
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Action {
+    /// Stored, updated or restored values (including derived keys being added)
+    Store,
+    /// Rotated derived keys
+    Rotate,
+    /// Deleted keys, whether one by one, by pattern, by category, purged or reaped
+    Delete,
+    /// Renamed or merged categories, and archived or unarchived keys
+    Move,
+    /// Keys imported from the environment or promoted from another vault
+    Import,
+    /// Metadata, attachment and version label changes
+    Metadata,
+    /// Reserved key names
+    Reserve,
+    /// Values re-encrypted under a new master key
+    Reencrypt,
+}
+
+/// Commit subject prefixes and the action each records. Checked in order, so the metadata
+/// prefixes come before the generic 'Delete ' one.
+const ACTION_PREFIXES: [(&str, Action); 25] = [
+    ("Update metadata of key: ", Action::Metadata),
+    ("Update metadata of keys: ", Action::Metadata),
+    ("Delete metadata of key: ", Action::Metadata),
+    ("Attach file to key: ", Action::Metadata),
+    ("Remove attachment from key: ", Action::Metadata),
+    ("Label version of key: ", Action::Metadata),
+    ("Remove version label of key: ", Action::Metadata),
+    ("Create key: ", Action::Store),
+    ("Update key: ", Action::Store),
+    ("Add derived key: ", Action::Store),
+    ("Restore ", Action::Store),
+    ("Apply manifest: ", Action::Store),
+    ("Rotate derived key: ", Action::Rotate),
+    ("Purge key: ", Action::Delete),
+    ("Delete ", Action::Delete),
+    ("Merge category: ", Action::Move),
+    ("Rename category: ", Action::Move),
+    ("Archive key: ", Action::Move),
+    ("Unarchive key: ", Action::Move),
+    ("Import keys into ", Action::Import),
+    ("Import keys from ", Action::Import),
+    ("Promote ", Action::Import),
+    ("Reserve key: ", Action::Reserve),
+    ("Re-encrypt ", Action::Reencrypt),
+    (
+        "Remove shared blobs of the old master key",
+        Action::Reencrypt,
+    ),
+];
+
+/// Returns the action a commit message records, or None for commits not written by
+/// axkeystore
+pub fn action_of(message: &str) -> Option<Action> {
+    let subject = message.lines().next()?.trim();
+    ACTION_PREFIXES
+        .iter()
+        .find(|(prefix, _)| subject.starts_with(prefix))
+        .map(|(_, action)| *action)
+}
+
+/// One kind of change and the paths it touched, sorted and without duplicates
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Section {
+    pub action: &'static str,
+    pub paths: Vec<String>,
+}
+
+/// Extracts the kind of change and the key path (or category) from a commit message.
+/// Commits not written by axkeystore (and recipient changes) are not secret changes and
+/// yield None.
+pub fn parse_message(message: &str) -> Option<(&'static str, String)> {
+    let subject = message.lines().next()?.trim();
+    if let Some(path) = subject.strip_prefix("Re-encrypt ") {
+        // Rekey commits name repository files ('keys/cloud/api.json'), and sharing a
+        // category names the category ('category: team')
+        let path = path
+            .strip_prefix("keys/")
+            .and_then(|p| p.strip_suffix(".json"))
+            .or_else(|| path.strip_prefix("category: "))
+            .unwrap_or(path);
+        return Some(("Re-encrypted", path.to_string()));
+    }
+    let (action, rest) = PREFIXES
+        .iter()
+        .find_map(|(prefix, action)| subject.strip_prefix(prefix).map(|rest| (*action, rest)))?;
+    let path = match action {
+        // 'Rotate derived key: api (counter 3)'
+        "Rotated" => rest.split(" (counter ").next().unwrap_or(rest),
+        _ => rest,
+    };
+    match action {
+        // 'Merge category: api/production into api/prod'
+        "Moved" => {
+            let (from, into) = path.split_once(" into ")?;
+            Some((action, format!("{} -> {}", from, into)))
+        }
+        _ => Some((action, path.to_string())),
+    }
+}
+
+/// Groups the secret changes of a list of commits by kind. Sections without changes are
+/// left out.
+pub fn build(commits: &[KeyVersion]) -> Vec<Section> {
+    let changes: Vec<(&str, String)> = commits
+        .iter()
+        .filter_map(|c| parse_message(&c.message))
+        .collect();
+    SECTIONS
+        .iter()
+        .filter_map(|action| {
+            let paths: BTreeSet<&String> = changes
+                .iter()
+                .filter(|(a, _)| a == action)
+                .map(|(_, path)| path)
+                .collect();
+            (!paths.is_empty()).then(|| Section {
+                action,
+                paths: paths.into_iter().cloned().collect(),
+            })
+        })
+        .collect()
+}
+
+/// Renders the changelog in the requested format. `since` is shown in the heading.
+pub fn render(sections: &[Section], since: &str, format: ChangelogFormat) -> Result<String> {
+    let mut out = String::new();
+    match format {
+        ChangelogFormat::Json => return Ok(serde_json::to_string_pretty(sections)? + "\n"),
+        ChangelogFormat::Markdown => {
+            out.push_str(&format!("## Secret changes since {}\n", since));
+            if sections.is_empty() {
+                out.push_str("\nNo secret changes.\n");
+            }
+            for section in sections {
+                out.push_str(&format!("\n### {}\n\n", section.action));
+                for path in &section.paths {
+                    out.push_str(&format!("- `{}`\n", path));
+                }
+            }
+        }
+        ChangelogFormat::Text => {
+            out.push_str(&format!("Secret changes since {}\n", since));
+            if sections.is_empty() {
+                out.push_str("  (none)\n");
+            }
+            for section in sections {
+                out.push_str(&format!("{}:\n", section.action));
+                for path in &section.paths {
+                    out.push_str(&format!("  {}\n", path));
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(message: &str) -> KeyVersion {
+        KeyVersion {
+            sha: "abc".to_string(),
+            date: "2024-01-01T10:00:00Z".to_string(),
+            message: message.to_string(),
+            verified: false,
+            verification_reason: None,
+            committer_email: None,
+            author: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_message() {
+        assert_eq!(
+            parse_message("Update key: cloud/api"),
+            Some(("Updated", "cloud/api".to_string()))
+        );
+        assert_eq!(
+            parse_message("Rotate derived key: db/pass (counter 3)"),
+            Some(("Rotated", "db/pass".to_string()))
+        );
+        assert_eq!(
+            parse_message("Merge category: api/production into api/prod"),
+            Some(("Moved", "api/production -> api/prod".to_string()))
+        );
+        assert_eq!(
+            parse_message("Re-encrypt keys/cloud/api.json"),
+            Some(("Re-encrypted", "cloud/api".to_string()))
+        );
+        assert_eq!(
+            parse_message("Re-encrypt category: team"),
+            Some(("Re-encrypted", "team".to_string()))
+        );
+        assert_eq!(
+            parse_message("Archive key: legacy/ftp"),
+            Some(("Archived", "legacy/ftp".to_string()))
+        );
+        assert_eq!(parse_message("Add recipient: jane"), None);
+        assert_eq!(parse_message("Initial commit"), None);
+    }
+
+    #[test]
+    fn test_action_of() {
+        assert_eq!(action_of("Update key: cloud/api"), Some(Action::Store));
+        assert_eq!(action_of("Create key: cloud/api"), Some(Action::Store));
+        assert_eq!(action_of("Delete key: old"), Some(Action::Delete));
+        assert_eq!(action_of("Unarchive key: legacy/ftp"), Some(Action::Move));
+        assert_eq!(action_of("Delete category: legacy"), Some(Action::Delete));
+        assert_eq!(
+            action_of("Delete 3 key(s) past their deletion deadline"),
+            Some(Action::Delete)
+        );
+        assert_eq!(
+            action_of("Delete metadata of key: old"),
+            Some(Action::Metadata)
+        );
+        assert_eq!(
+            action_of("Rotate derived key: db/pass (counter 3)"),
+            Some(Action::Rotate)
+        );
+        assert_eq!(
+            action_of("Re-encrypt keys/cloud/api.json\n\nrekey"),
+            Some(Action::Reencrypt)
+        );
+        assert_eq!(action_of("Fix README"), None);
+    }
+
+    #[test]
+    fn test_build_and_render() {
+        let commits = vec![
+            commit("Update key: cloud/api"),
+            commit("Delete key: old"),
+            commit("Update key: cloud/api"),
+            commit("Add derived key: db/pass"),
+            commit("Update key: billing/stripe"),
+            commit("Fix README"),
+        ];
+        let sections = build(&commits);
+        assert_eq!(
+            sections,
+            vec![
+                Section {
+                    action: "Added",
+                    paths: vec!["db/pass".to_string()],
+                },
+                Section {
+                    action: "Updated",
+                    paths: vec!["billing/stripe".to_string(), "cloud/api".to_string()],
+                },
+                Section {
+                    action: "Deleted",
+                    paths: vec!["old".to_string()],
+                },
+            ]
+        );
+
+        let markdown = render(&sections, "v1.2.0", ChangelogFormat::Markdown).unwrap();
+        assert!(markdown.starts_with("## Secret changes since v1.2.0\n"));
+        assert!(markdown.contains("\n### Updated\n\n- `billing/stripe`\n- `cloud/api`\n"));
+
+        let text = render(&[], "2024-01-01", ChangelogFormat::Text).unwrap();
+        assert_eq!(text, "Secret changes since 2024-01-01\n  (none)\n");
+
+        let json = render(&sections, "v1", ChangelogFormat::Json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[2]["action"], "Deleted");
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Clipboard tools to try, in order, for the current platform
+fn candidates() -> Vec<(&'static str, Vec<&'static str>)> {
+    if cfg!(target_os = "macos") {
+        vec![("pbcopy", vec![])]
+    } else if cfg!(target_os = "windows") {
+        vec![("clip", vec![])]
+    } else {
+        vec![
+            ("wl-copy", vec![]),
+            ("xclip", vec!["-selection", "clipboard"]),
+            ("xsel", vec!["--clipboard", "--input"]),
+        ]
+    }
+}
+
+/// Copies text to the system clipboard using the platform's clipboard tool
+pub fn copy(text: &str) -> Result<()> {
+    for (program, args) in candidates() {
+        let child = Command::new(program)
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut child = match child {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "No clipboard tool found. Install pbcopy, clip, wl-copy, xclip or xsel."
+    ))
+}
@@ -0,0 +1,95 @@
+use crate::config::GlobalConfig;
+use crate::index;
+use crate::settings;
+use anyhow::Result;
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use std::collections::BTreeSet;
+
+/// Environment variable the shell sets when it asks the binary for completions
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Returns the script that registers dynamic completion for a shell. The script calls back
+/// into axkeystore on every TAB, so completions follow the current set of commands and keys.
+pub fn registration(shell: clap_complete::Shell) -> Result<String> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(&shell.to_string())
+        .ok_or_else(|| anyhow::anyhow!("Completion is not supported for '{}'", shell))?;
+    let bin = std::env::args()
+        .next()
+        .unwrap_or_else(|| "axkeystore".to_string());
+    let mut script = Vec::new();
+    completer.write_registration(COMPLETE_VAR, "axkeystore", &bin, &bin, &mut script)?;
+    Ok(String::from_utf8(script)?)
+}
+
+/// Returns the distinct key names of a list of key paths, sorted
+fn key_names(paths: &[String]) -> Vec<String> {
+    let names: BTreeSet<&str> = paths
+        .iter()
+        .map(|p| p.rsplit_once('/').map_or(p.as_str(), |(_, name)| name))
+        .collect();
+    names.into_iter().map(|n| n.to_string()).collect()
+}
+
+/// Returns every category of a list of key paths, including parent categories, sorted
+fn categories(paths: &[String]) -> Vec<String> {
+    let mut found = BTreeSet::new();
+    for path in paths {
+        let mut category = match path.rsplit_once('/') {
+            Some((cat, _)) => cat,
+            None => continue,
+        };
+        loop {
+            found.insert(category.to_string());
+            match category.rsplit_once('/') {
+                Some((parent, _)) => category = parent,
+                None => break,
+            }
+        }
+    }
+    found.into_iter().collect()
+}
+
+/// Key paths of the active profile, or the one named by AXKEYSTORE_PROFILE. The '--profile'
+/// flag of the line being completed is not visible here.
+fn active_paths() -> Vec<String> {
+    let profile = match settings::env_profile().ok().flatten() {
+        Some(name) => (name != "default").then_some(name),
+        None => GlobalConfig::get_active_profile().ok().flatten(),
+    };
+    index::load_names(profile.as_deref())
+}
+
+/// Completion candidates for key name arguments
+pub fn key_candidates() -> Vec<CompletionCandidate> {
+    key_names(&active_paths())
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Completion candidates for category arguments
+pub fn category_candidates() -> Vec<CompletionCandidate> {
+    categories(&active_paths())
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_and_categories() {
+        let paths: Vec<String> = ["cloud/aws/api", "cloud/db", "api", "payments/api"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(key_names(&paths), vec!["api", "db"]);
+        assert_eq!(categories(&paths), vec!["cloud", "cloud/aws", "payments"]);
+        assert!(categories(&[]).is_empty());
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Manifest read by 'compose up' when no other is given
+pub const DEFAULT_MANIFEST: &str = ".axkeystore.toml";
+
+/// Variable telling the Compose file where the secret files are
+/// (e.g. 'file: ${AXKEYSTORE_SECRETS_DIR}/db_password')
+pub const SECRETS_DIR_VAR: &str = "AXKEYSTORE_SECRETS_DIR";
+
+/// The secrets a Compose project needs, e.g.
+///
+/// ```toml
+/// [secrets]
+/// db_password = "prod/db/password"
+///
+/// [env]
+/// API_KEY = "prod/api/key"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Docker secret name -> key path; each becomes a file in the secrets directory
+    #[serde(default)]
+    pub secrets: BTreeMap<String, String>,
+    /// Environment variable -> key path; set for the command only
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Reads and validates a manifest file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        Self::parse(&text).with_context(|| format!("'{}' is not a valid manifest", path.display()))
+    }
+
+    /// Parses and validates a manifest
+    pub fn parse(text: &str) -> Result<Self> {
+        let manifest: Manifest = toml::from_str(text)?;
+        if manifest.secrets.is_empty() && manifest.env.is_empty() {
+            return Err(anyhow::anyhow!(
+                "It declares no [secrets] and no [env] entries"
+            ));
+        }
+        for (name, key) in &manifest.secrets {
+            validate_secret_name(name)?;
+            validate_key(name, key)?;
+        }
+        for (name, key) in &manifest.env {
+            validate_env_name(name)?;
+            validate_key(name, key)?;
+        }
+        Ok(manifest)
+    }
+
+    /// Returns every key path the manifest refers to, without duplicates
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self
+            .secrets
+            .values()
+            .chain(self.env.values())
+            .map(|k| k.as_str())
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+}
+
+/// Docker secret names become file names, so only letters, digits, '_', '-' and '.' are
+/// allowed, starting with a letter or digit
+fn validate_secret_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid secret name '{}': use letters, digits, '_', '-' and '.', starting with a letter or digit",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn validate_env_name(name: &str) -> Result<()> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid || name == SECRETS_DIR_VAR {
+        return Err(anyhow::anyhow!(
+            "Invalid environment variable name '{}'",
+            name
+        ));
+    }
+    Ok(())
+}
+
+fn validate_key(name: &str, key: &str) -> Result<()> {
+    if key.trim_matches('/').is_empty() {
+        return Err(anyhow::anyhow!("'{}' has no key path", name));
+    }
+    Ok(())
+}
+
+/// Returns a fresh directory for the secret files under the system's temporary directory.
+/// It is not created yet; writing the secrets creates it readable only by the current user.
+pub fn secrets_dir() -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "axkeystore-compose-{}-{:08x}",
+        std::process::id(),
+        rand::random::<u32>()
+    ))
+}
+
+/// Runs a command with the secrets directory and the manifest's variables in its
+/// environment. Stdin, stdout and stderr are inherited. Returns the command's exit code.
+pub fn run(command: &[String], dir: &Path, env: &BTreeMap<String, String>) -> Result<i32> {
+    let (program, args) = command.split_first().context("No command given")?;
+    let status = Command::new(program)
+        .args(args)
+        .env(SECRETS_DIR_VAR, dir)
+        .envs(env)
+        .status()
+        .with_context(|| format!("Failed to run '{}'", program))?;
+    // A command killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::parse(
+            r#"
+            [secrets]
+            db_password = "prod/db/password"
+            "tls.key" = "prod/tls/key"
+
+            [env]
+            API_KEY = "prod/api/key"
+            DB_PASSWORD = "prod/db/password"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(manifest.secrets["db_password"], "prod/db/password");
+        assert_eq!(manifest.env["API_KEY"], "prod/api/key");
+        assert_eq!(
+            manifest.keys(),
+            vec!["prod/api/key", "prod/db/password", "prod/tls/key"]
+        );
+
+        // Nothing declared, unknown tables, and names that are not valid
+        assert!(Manifest::parse("").is_err());
+        assert!(Manifest::parse("[volumes]\na = \"b\"").is_err());
+        assert!(Manifest::parse("[secrets]\n\"../db\" = \"prod/db\"").is_err());
+        assert!(Manifest::parse("[secrets]\n\".axkeystore-secrets\" = \"prod/db\"").is_err());
+        assert!(Manifest::parse("[env]\n\"1KEY\" = \"prod/db\"").is_err());
+        assert!(Manifest::parse("[env]\nAXKEYSTORE_SECRETS_DIR = \"prod/db\"").is_err());
+        assert!(Manifest::parse("[env]\nKEY = \"/\"").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let dir = Path::new("/tmp/secrets");
+        let mut env = BTreeMap::new();
+        env.insert("API_KEY".to_string(), "s3cret".to_string());
+        let command = |script: &str| vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+
+        assert_eq!(
+            run(
+                &command(
+                    r#"test "$API_KEY" = s3cret && test "$AXKEYSTORE_SECRETS_DIR" = /tmp/secrets"#
+                ),
+                dir,
+                &env
+            )
+            .unwrap(),
+            0
+        );
+        assert_eq!(run(&command("exit 4"), dir, &env).unwrap(), 4);
+        assert!(run(&[], dir, &env).is_err());
+    }
+}
@@ -1,7 +1,46 @@
-use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::apply::Charset;
+use crate::catalog::ExportFormat;
+use crate::crypto::{CryptoHandler, EncryptedBlob, Padding};
+use crate::pipe::ValueFormat;
+use crate::wasmplugin::CategoryPlugins;
 use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Vault selected with '--vault' for the whole process (None for the profile's repository)
+static SELECTED_VAULT: OnceLock<String> = OnceLock::new();
+
+/// Makes every command of this process use a named vault of the profile instead of its
+/// repository. Only the first selection counts.
+pub fn select_vault(name: &str) {
+    let _ = SELECTED_VAULT.set(name.to_string());
+}
+
+/// Returns the vault selected with '--vault', if any
+pub fn selected_vault() -> Option<&'static str> {
+    SELECTED_VAULT.get().map(|name| name.as_str())
+}
+
+/// Repository that replaces one profile's repository for the whole process (from
+/// AXKEYSTORE_REPO), with the profile it applies to
+static REPO_OVERRIDE: OnceLock<(Option<String>, String)> = OnceLock::new();
+
+/// Makes every command of this process use another repository for one profile. Other
+/// profiles (e.g. the source of 'promote') keep theirs. Only the first override counts.
+pub fn override_repo(profile: Option<&str>, repo: &str) {
+    let _ = REPO_OVERRIDE.set((profile.map(|p| p.to_string()), repo.to_string()));
+}
+
+/// Returns the repository that replaces a profile's repository, if any
+fn repo_override(profile: Option<&str>) -> Option<&'static str> {
+    REPO_OVERRIDE
+        .get()
+        .filter(|(overridden, _)| overridden.as_deref() == profile)
+        .map(|(_, repo)| repo.as_str())
+}
 
 /// Local configuration for AxKeyStore (profile-specific)
 #[derive(Serialize, Deserialize, Default)]
@@ -10,6 +49,144 @@ pub struct Config {
     pub encrypted_repo_name: Option<EncryptedBlob>,
     /// Encrypted Local Master Key (36 chars) used for local secrets
     pub encrypted_lmk: Option<EncryptedBlob>,
+    /// Additional vault repositories of the profile, by name, each encrypted like the
+    /// repository name (selected with '--vault')
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vaults: BTreeMap<String, EncryptedBlob>,
+    /// Encrypted GitHub login of the saved token, so commands need not look it up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_login: Option<EncryptedBlob>,
+    /// Controls what may be cached locally for this profile and for how long
+    #[serde(default)]
+    pub cache_policy: CachePolicy,
+    /// Commit signing and signature verification settings
+    #[serde(default)]
+    pub signing: SigningConfig,
+    /// Keep a plain-text list of key paths next to the key index so the shell can complete
+    /// key names without the master password
+    #[serde(default)]
+    pub complete_key_names: bool,
+    /// WASM generator and policy plugins, by the category they apply to (with subcategories)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub category_plugins: BTreeMap<String, CategoryPlugins>,
+    /// Plugins ('axkeystore-<name>' executables) that may store keys; others can only read
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub writable_plugins: BTreeSet<String>,
+    /// Directory of the repository that holds the vault (None for the repository root)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_prefix: Option<String>,
+    /// Branch of the repository that holds the vault (None for the default branch)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Mix this machine's fingerprint into the key that encrypts the GitHub token, so a
+    /// copy of the configuration directory cannot unlock it elsewhere
+    #[serde(default)]
+    pub bind_token_to_device: bool,
+    /// Pad values before encryption so their stored size does not reveal their length
+    /// (None stores them unpadded)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub padding: Option<Padding>,
+    /// Append every command's GitHub requests to a log file in the profile's directory
+    #[serde(default)]
+    pub log_file: bool,
+    /// How many GitHub requests bulk reads make at once (None for the default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallelism: Option<usize>,
+    /// Values used when a command's option is left out (set with 'config set')
+    #[serde(default, skip_serializing_if = "Defaults::is_empty")]
+    pub defaults: Defaults,
+}
+
+/// Per-profile defaults for options of 'store', 'get' and 'export'
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Defaults {
+    /// Category used when --category is left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Encoding 'get' prints values in (None prints them as they are)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<ValueFormat>,
+    /// Format of 'export' (None for json)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_format: Option<ExportFormat>,
+    /// Length of values made by the built-in generator (None for a random length)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generate_length: Option<usize>,
+    /// Characters the built-in generator draws from (None for letters and digits)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generate_charset: Option<Charset>,
+}
+
+impl Defaults {
+    /// Returns true if no default is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Lifetimes (in seconds) for each kind of locally cached material.
+/// A lifetime of 0 disables caching for that kind. Decrypted secret values are never cached.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CachePolicy {
+    /// Lifetime of cached derived keys
+    pub derived_keys_ttl: u64,
+    /// Lifetime of cached non-secret metadata (e.g. key listings, account login)
+    pub metadata_ttl: u64,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            derived_keys_ttl: 0,
+            metadata_ttl: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Signature formats supported for commits created by AxKeyStore
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureFormat {
+    /// OpenPGP signatures created with `gpg`
+    Gpg,
+    /// SSH signatures created with `ssh-keygen -Y sign`
+    Ssh,
+}
+
+impl SignatureFormat {
+    /// Human readable name of the format
+    pub fn label(&self) -> &'static str {
+        match self {
+            SignatureFormat::Gpg => "GPG",
+            SignatureFormat::Ssh => "SSH",
+        }
+    }
+}
+
+/// Commit signing and signature verification settings for a profile
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SigningConfig {
+    /// Signature format used for new commits (None disables signing)
+    pub format: Option<SignatureFormat>,
+    /// GPG key ID, or path to the SSH private key, used for signing
+    pub key: Option<String>,
+    /// Author name recorded on signed commits
+    pub name: Option<String>,
+    /// Author email recorded on signed commits (must belong to the signing key)
+    pub email: Option<String>,
+    /// Verify the latest commit signature of a key on every `get`
+    #[serde(default)]
+    pub verify_on_get: bool,
+    /// Committer emails whose verified signatures are trusted (empty trusts any verified signature)
+    #[serde(default)]
+    pub trusted_signers: Vec<String>,
+}
+
+impl SigningConfig {
+    /// Returns true if new commits should be signed
+    pub fn is_enabled(&self) -> bool {
+        self.format.is_some() && self.key.is_some()
+    }
 }
 
 /// Global settings across all profiles
@@ -58,6 +235,7 @@ impl Config {
             None => base_dir,
         };
         std::fs::create_dir_all(&dir)?;
+        crate::layout::migrate(&dir)?;
         Ok(dir)
     }
 
@@ -76,6 +254,12 @@ impl Config {
                 "Profile name 'default' is reserved for the root profile."
             ));
         }
+        if crate::layout::DATA_DIRS.contains(&name) {
+            return Err(anyhow::anyhow!(
+                "Profile name '{}' is reserved for profile data.",
+                name
+            ));
+        }
         if !name
             .chars()
             .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
@@ -99,6 +283,20 @@ impl Config {
         Ok(config)
     }
 
+    /// Loads the configuration for a specific profile, failing if it cannot be parsed instead
+    /// of falling back to defaults. None if the profile has no configuration yet.
+    pub fn load_checked_with_profile(profile: Option<&str>) -> Result<Option<Self>> {
+        let path = Self::get_config_path(profile)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let config = serde_json::from_str(&content)
+            .with_context(|| format!("'{}' is corrupted", path.display()))?;
+        Ok(Some(config))
+    }
+
     /// Saves the current configuration to a specific profile
     pub fn save_with_profile(&self, profile: Option<&str>) -> Result<()> {
         let path = Self::get_config_path(profile)?;
@@ -125,8 +323,22 @@ impl Config {
         Ok(lmk)
     }
 
-    /// Decrypts and retrieves the repository name for a specific profile
+    /// Decrypts and retrieves the repository name for a specific profile, or the repository
+    /// of the vault selected with '--vault', or the one that overrides the profile's
     pub fn get_repo_name_with_profile(profile: Option<&str>, password: &str) -> Result<String> {
+        if let Some(vault) = selected_vault() {
+            return Self::get_vault_repo_with_profile(profile, vault, password)?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Vault '{}' is not configured for profile '{}'. Add it with 'axkeystore vault add {} OWNER/REPO'.",
+                    vault,
+                    profile.unwrap_or("default"),
+                    vault
+                )
+            });
+        }
+        if let Some(repo) = repo_override(profile) {
+            return Ok(repo.to_string());
+        }
         let config = Self::load_with_profile(profile)?;
         match config.encrypted_repo_name {
             Some(blob) => {
@@ -159,6 +371,195 @@ impl Config {
         config.save_with_profile(profile)?;
         Ok(())
     }
+
+    /// Forgets everything encrypted with the profile's master password (the LMK and what it
+    /// encrypts), keeping the other settings. Used when the password is lost.
+    pub fn forget_secrets_with_profile(profile: Option<&str>) -> Result<()> {
+        let mut config = Self::load_with_profile(profile)?;
+        config.encrypted_lmk = None;
+        config.encrypted_repo_name = None;
+        config.encrypted_login = None;
+        config.vaults.clear();
+        config.save_with_profile(profile)
+    }
+
+    /// Validates the name of a vault
+    pub fn validate_vault_name(name: &str) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid vault name '{}'. Only alphabets, numbers, '_' and '-' are allowed.",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decrypts the repository of a named vault of a profile, if it has one by that name
+    pub fn get_vault_repo_with_profile(
+        profile: Option<&str>,
+        name: &str,
+        password: &str,
+    ) -> Result<Option<String>> {
+        Ok(Self::get_vault_repos_with_profile(profile, password)?.remove(name))
+    }
+
+    /// Decrypts the repositories of every named vault of a profile
+    pub fn get_vault_repos_with_profile(
+        profile: Option<&str>,
+        password: &str,
+    ) -> Result<BTreeMap<String, String>> {
+        let config = Self::load_with_profile(profile)?;
+        if config.vaults.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+        let lmk = Self::get_or_create_lmk_with_profile(profile, password)?;
+        config
+            .vaults
+            .iter()
+            .map(|(name, blob)| {
+                let decrypted = CryptoHandler::decrypt(blob, &lmk)
+                    .map_err(|_| anyhow::anyhow!("Corrupted repository of vault '{}'.", name))?;
+                let repo = String::from_utf8(decrypted).context("Repo name is not valid UTF-8")?;
+                Ok((name.clone(), repo))
+            })
+            .collect()
+    }
+
+    /// Encrypts and saves the repository of a named vault of a profile
+    pub fn set_vault_repo_with_profile(
+        profile: Option<&str>,
+        name: &str,
+        repo: &str,
+        password: &str,
+    ) -> Result<()> {
+        Self::validate_vault_name(name)?;
+        let lmk = Self::get_or_create_lmk_with_profile(profile, password)?;
+        let encrypted = CryptoHandler::encrypt(repo.as_bytes(), &lmk)?;
+
+        let mut config = Self::load_with_profile(profile)?;
+        config.vaults.insert(name.to_string(), encrypted);
+        config.save_with_profile(profile)?;
+        Ok(())
+    }
+
+    /// Removes a named vault from a profile (its repository is left untouched). Returns false
+    /// if the profile has no vault by that name.
+    pub fn remove_vault_with_profile(profile: Option<&str>, name: &str) -> Result<bool> {
+        let mut config = Self::load_with_profile(profile)?;
+        if config.vaults.remove(name).is_none() {
+            return Ok(false);
+        }
+        config.save_with_profile(profile)?;
+        Ok(true)
+    }
+
+    /// Decrypts the GitHub login cached for a specific profile, if any
+    pub fn get_login_with_profile(profile: Option<&str>, password: &str) -> Result<Option<String>> {
+        let config = Self::load_with_profile(profile)?;
+        let blob = match config.encrypted_login {
+            Some(blob) => blob,
+            None => return Ok(None),
+        };
+        let lmk = Self::get_or_create_lmk_with_profile(profile, password)?;
+        let decrypted = CryptoHandler::decrypt(&blob, &lmk)
+            .map_err(|_| anyhow::anyhow!("Corrupted GitHub login configuration."))?;
+        Ok(Some(
+            String::from_utf8(decrypted).context("Login is not valid UTF-8")?,
+        ))
+    }
+
+    /// Encrypts and caches the GitHub login of a profile's saved token
+    pub fn set_login_with_profile(
+        profile: Option<&str>,
+        login: &str,
+        password: &str,
+    ) -> Result<()> {
+        let lmk = Self::get_or_create_lmk_with_profile(profile, password)?;
+        let encrypted = CryptoHandler::encrypt(login.as_bytes(), &lmk)?;
+
+        let mut config = Self::load_with_profile(profile)?;
+        config.encrypted_login = Some(encrypted);
+        config.save_with_profile(profile)?;
+        Ok(())
+    }
+
+    /// Forgets the cached GitHub login of a profile, so the next command looks it up again
+    pub fn forget_login_with_profile(profile: Option<&str>) -> Result<()> {
+        let mut config = Self::load_with_profile(profile)?;
+        if config.encrypted_login.take().is_some() {
+            config.save_with_profile(profile)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a file and restricts it to be readable only by the current user on Unix
+pub fn write_private_file(path: &std::path::Path, content: &[u8]) -> Result<()> {
+    // Written next to the target and renamed over it, so an interrupted write never leaves
+    // a truncated file behind
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid file path '{}'", path.display()))?;
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    std::fs::write(&tmp, content)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Entries of a profile's directory that belong to how this machine used the profile rather
+/// than to its setup (caches, its log, an interrupted rekey), so cloning and exporting leave
+/// them out
+const UNCLONED: &[&str] = &["cache", "axkeystore.log", "rekey_journal.json"];
+
+/// Version of the profile bundle format
+const PROFILE_BUNDLE_FORMAT: u32 = 1;
+
+/// A profile packed into one file, to set it up on another machine. Its files keep their own
+/// encryption with the master password, and the bundle is encrypted with a password of its
+/// own.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ProfileBundle {
+    /// Bundle format version
+    pub format: u32,
+    /// Name of the exported profile ('default' for the root profile)
+    pub profile: String,
+    /// Base64 encoded files, by path relative to the profile's directory
+    pub files: BTreeMap<String, String>,
+}
+
+/// Reads every file below a directory into `files`, by path relative to the profile's
+/// directory ('index/index.json')
+fn read_files(
+    dir: &std::path::Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, Vec<u8>>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            read_files(&entry.path(), &format!("{}/", path), files)?;
+        } else {
+            files.insert(path, std::fs::read(entry.path())?);
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if a profile's directory holds settings or a login
+fn is_set_up(dir: &std::path::Path) -> bool {
+    dir.join("config.json").exists() || dir.join("github_token.json").exists()
 }
 
 impl GlobalConfig {
@@ -238,6 +639,161 @@ impl GlobalConfig {
         }
         Ok(())
     }
+
+    /// Reads the files that make up a profile's setup (None for the default profile), by
+    /// path relative to its directory
+    fn profile_files(profile: Option<&str>) -> Result<BTreeMap<String, Vec<u8>>> {
+        let dir = Config::get_config_dir(profile)?;
+        let mut files = BTreeMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if UNCLONED.contains(&name.as_str()) {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                // Other directories next to the default profile's files are named profiles
+                if crate::layout::DATA_DIRS.contains(&name.as_str()) {
+                    read_files(&entry.path(), &format!("{}/", name), &mut files)?;
+                }
+            } else if profile.is_some() || name != "global.json" {
+                files.insert(name, std::fs::read(entry.path())?);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Writes the files of a profile's setup into a profile (None for the default profile)
+    /// that is not set up yet. Each file is readable only by the current user.
+    fn write_profile_files(profile: Option<&str>, files: &BTreeMap<String, Vec<u8>>) -> Result<()> {
+        for path in files.keys() {
+            let valid = path
+                .split('/')
+                .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+            if !valid {
+                return Err(anyhow::anyhow!("Invalid profile file path '{}'", path));
+            }
+        }
+        let dir = Config::get_config_dir(profile)?;
+        if is_set_up(&dir) {
+            return Err(anyhow::anyhow!(
+                "Profile '{}' already exists",
+                profile.unwrap_or("default")
+            ));
+        }
+        for (path, content) in files {
+            let file = dir.join(path);
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_private_file(&file, content)?;
+        }
+        Ok(())
+    }
+
+    /// Copies a profile (None for the default one) to a new profile: its settings, its
+    /// encrypted token, repository and vaults, its identity and its key index. Everything
+    /// stays encrypted with the same master password. The new profile must not exist yet.
+    pub fn clone_profile(from: Option<&str>, to: &str) -> Result<()> {
+        Config::validate_profile_name(to)?;
+        Self::write_profile_files(Some(to), &Self::profile_files(from)?)
+    }
+
+    /// Writes a profile (None for the default one) to a bundle encrypted with its password.
+    /// A token bound to this machine cannot be used elsewhere, so it is left out. Returns
+    /// true if the token was included.
+    pub fn export_profile(
+        profile: Option<&str>,
+        path: &std::path::Path,
+        password: &str,
+    ) -> Result<bool> {
+        if !is_set_up(&Config::get_config_dir(profile)?) {
+            return Err(anyhow::anyhow!(
+                "Profile '{}' is not set up",
+                profile.unwrap_or("default")
+            ));
+        }
+        let mut files = Self::profile_files(profile)?;
+        let with_token = !Config::load_with_profile(profile)?.bind_token_to_device;
+        if !with_token {
+            files.remove("github_token.json");
+        }
+        let bundle = ProfileBundle {
+            format: PROFILE_BUNDLE_FORMAT,
+            profile: profile.unwrap_or("default").to_string(),
+            files: files
+                .into_iter()
+                .map(|(path, content)| (path, BASE64.encode(content)))
+                .collect(),
+        };
+        let encrypted = CryptoHandler::encrypt(&serde_json::to_vec(&bundle)?, password)?;
+        write_private_file(path, &serde_json::to_vec_pretty(&encrypted)?)?;
+        Ok(with_token && bundle.files.contains_key("github_token.json"))
+    }
+
+    /// Sets up a profile from a bundle written by export_profile, under its exported name
+    /// unless another is given ('default' for the root profile). The profile must not be set
+    /// up yet. Returns the profile's name and whether the bundle held a login.
+    pub fn import_profile(
+        path: &std::path::Path,
+        password: &str,
+        name: Option<&str>,
+    ) -> Result<(String, bool)> {
+        let data =
+            std::fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let encrypted: EncryptedBlob = serde_json::from_slice(&data)
+            .with_context(|| format!("'{}' is not a profile bundle", path.display()))?;
+        let decrypted = CryptoHandler::decrypt(&encrypted, password)
+            .map_err(|_| anyhow::anyhow!("Incorrect bundle password or corrupted bundle."))?;
+        let bundle: ProfileBundle =
+            serde_json::from_slice(&decrypted).context("Profile bundle is corrupted")?;
+        if bundle.format != PROFILE_BUNDLE_FORMAT {
+            return Err(anyhow::anyhow!(
+                "Unsupported bundle format {} (expected {})",
+                bundle.format,
+                PROFILE_BUNDLE_FORMAT
+            ));
+        }
+
+        let name = name.unwrap_or(&bundle.profile).to_string();
+        let profile = (!name.eq_ignore_ascii_case("default")).then_some(name.as_str());
+        if let Some(profile) = profile {
+            Config::validate_profile_name(profile)?;
+        }
+        let files = bundle
+            .files
+            .iter()
+            .map(|(path, encoded)| {
+                let content = BASE64
+                    .decode(encoded)
+                    .with_context(|| format!("Profile bundle entry '{}' is corrupted", path))?;
+                Ok((path.clone(), content))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        Self::write_profile_files(profile, &files)?;
+        Ok((name, files.contains_key("github_token.json")))
+    }
+
+    /// Renames a profile, keeping it active if it was
+    pub fn rename_profile(from: &str, to: &str) -> Result<()> {
+        Config::validate_profile_name(from)?;
+        Config::validate_profile_name(to)?;
+        let base_dir = Config::get_base_dir()?;
+        let (source, target) = (base_dir.join(from), base_dir.join(to));
+        if !source.is_dir() {
+            return Err(anyhow::anyhow!("Profile '{}' does not exist", from));
+        }
+        if target.exists() {
+            return Err(anyhow::anyhow!("Profile '{}' already exists", to));
+        }
+        std::fs::rename(&source, &target)
+            .with_context(|| format!("Failed to rename profile '{}'", from))?;
+
+        if Self::get_active_profile()?.as_deref() == Some(from) {
+            Self::set_active_profile(Some(to.to_string()))?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -298,6 +854,82 @@ mod tests {
         std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
     }
 
+    #[test]
+    fn test_config_login() {
+        let _lock = TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+        let password = "test-password";
+
+        assert!(Config::get_login_with_profile(None, password)
+            .unwrap()
+            .is_none());
+        Config::set_login_with_profile(None, "octocat", password).unwrap();
+        assert_eq!(
+            Config::get_login_with_profile(None, password)
+                .unwrap()
+                .as_deref(),
+            Some("octocat")
+        );
+        assert!(Config::get_login_with_profile(None, "wrong-password").is_err());
+
+        Config::forget_login_with_profile(None).unwrap();
+        assert!(Config::load_with_profile(None)
+            .unwrap()
+            .encrypted_login
+            .is_none());
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[test]
+    fn test_repo_override() {
+        override_repo(Some("env-repo"), "acme/vault");
+        assert_eq!(repo_override(Some("env-repo")), Some("acme/vault"));
+        assert_eq!(repo_override(Some("other")), None);
+        assert_eq!(repo_override(None), None);
+    }
+
+    #[test]
+    fn test_config_vaults() {
+        let _lock = TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+        let password = "test-password";
+
+        assert!(Config::get_vault_repos_with_profile(None, password)
+            .unwrap()
+            .is_empty());
+        Config::set_vault_repo_with_profile(None, "infra", "octocat/infra-vault", password)
+            .unwrap();
+        Config::set_vault_repo_with_profile(None, "personal", "octocat/mine", password).unwrap();
+        assert!(
+            Config::set_vault_repo_with_profile(None, "bad/name", "octocat/x", password).is_err()
+        );
+        assert_eq!(
+            Config::get_vault_repo_with_profile(None, "infra", password)
+                .unwrap()
+                .as_deref(),
+            Some("octocat/infra-vault")
+        );
+        assert_eq!(
+            Config::get_vault_repos_with_profile(None, password)
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["infra", "personal"]
+        );
+        assert!(Config::get_vault_repo_with_profile(None, "infra", "wrong-password").is_err());
+
+        assert!(Config::remove_vault_with_profile(None, "infra").unwrap());
+        assert!(!Config::remove_vault_with_profile(None, "infra").unwrap());
+        assert!(Config::get_vault_repo_with_profile(None, "infra", password)
+            .unwrap()
+            .is_none());
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
     #[test]
     fn test_config_wrong_password() {
         let _lock = TEST_MUTEX.lock().unwrap();
@@ -319,6 +951,8 @@ mod tests {
         assert!(Config::validate_profile_name("invalid@profile").is_err());
         assert!(Config::validate_profile_name("default").is_err());
         assert!(Config::validate_profile_name("DEFAULT").is_err());
+        assert!(Config::validate_profile_name("cache").is_err());
+        assert!(Config::validate_profile_name("index").is_err());
     }
 
     #[test]
@@ -392,6 +1026,91 @@ mod tests {
         std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
     }
 
+    #[test]
+    fn test_clone_and_rename_profiles() {
+        let _lock = TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+        let password = "test-password";
+
+        Config::set_repo_name_with_profile(None, "root-repo", password).unwrap();
+        Config::set_repo_name_with_profile(Some("work"), "work-repo", password).unwrap();
+        let cache = Config::get_config_dir(None).unwrap().join("cache");
+        std::fs::create_dir_all(&cache).unwrap();
+        std::fs::write(cache.join("entry.json"), "{}").unwrap();
+        let index = Config::get_config_dir(None).unwrap().join("index");
+        std::fs::create_dir_all(&index).unwrap();
+        std::fs::write(index.join("index.json"), "{}").unwrap();
+
+        // The default profile's copy leaves out the other profiles and the caches
+        GlobalConfig::clone_profile(None, "copy").unwrap();
+        assert_eq!(
+            Config::get_repo_name_with_profile(Some("copy"), password).unwrap(),
+            "root-repo"
+        );
+        let copy = temp_dir.path().join("copy");
+        assert!(copy.join("index/index.json").exists());
+        assert!(!copy.join("cache").exists());
+        assert!(!copy.join("work").exists());
+        assert!(GlobalConfig::clone_profile(Some("work"), "copy").is_err());
+
+        GlobalConfig::set_active_profile(Some("work".to_string())).unwrap();
+        GlobalConfig::rename_profile("work", "job").unwrap();
+        assert_eq!(
+            Config::get_repo_name_with_profile(Some("job"), password).unwrap(),
+            "work-repo"
+        );
+        assert_eq!(
+            GlobalConfig::get_active_profile().unwrap().as_deref(),
+            Some("job")
+        );
+        assert!(GlobalConfig::rename_profile("work", "other").is_err());
+        assert!(GlobalConfig::rename_profile("job", "copy").is_err());
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[test]
+    fn test_export_and_import_profile() {
+        let _lock = TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+        let password = "test-password";
+        let bundle = temp_dir.path().join("work.axp");
+
+        Config::set_repo_name_with_profile(Some("work"), "work-repo", password).unwrap();
+        let dir = Config::get_config_dir(Some("work")).unwrap();
+        std::fs::write(dir.join("github_token.json"), "{}").unwrap();
+        assert!(GlobalConfig::export_profile(Some("work"), &bundle, "bundle password").unwrap());
+        assert!(GlobalConfig::export_profile(Some("empty"), &bundle, "x").is_err());
+
+        assert!(GlobalConfig::import_profile(&bundle, "wrong password", None).is_err());
+        // The exported profile exists here already
+        assert!(GlobalConfig::import_profile(&bundle, "bundle password", None).is_err());
+        let (name, with_token) =
+            GlobalConfig::import_profile(&bundle, "bundle password", Some("laptop")).unwrap();
+        assert_eq!((name.as_str(), with_token), ("laptop", true));
+        assert_eq!(
+            Config::get_repo_name_with_profile(Some("laptop"), password).unwrap(),
+            "work-repo"
+        );
+
+        // A token bound to this machine stays behind
+        let mut config = Config::load_with_profile(Some("work")).unwrap();
+        config.bind_token_to_device = true;
+        config.save_with_profile(Some("work")).unwrap();
+        assert!(!GlobalConfig::export_profile(Some("work"), &bundle, "bundle password").unwrap());
+        let (name, with_token) =
+            GlobalConfig::import_profile(&bundle, "bundle password", Some("default")).unwrap();
+        assert_eq!((name.as_str(), with_token), ("default", false));
+        assert!(!Config::get_config_dir(None)
+            .unwrap()
+            .join("github_token.json")
+            .exists());
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
     #[test]
     fn test_local_master_key() {
         let _lock = TEST_MUTEX.lock().unwrap();
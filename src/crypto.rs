@@ -1,3 +1,4 @@
+use crate::redact;
 use anyhow::{Context, Result};
 use argon2::{
     password_hash::{rand_core::OsRng, rand_core::RngCore, PasswordHasher, SaltString},
@@ -8,7 +9,125 @@ use chacha20poly1305::{
     aead::{Aead, KeyInit, Payload},
     XChaCha20Poly1305, XNonce,
 };
+use hkdf::Hkdf;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Plaintexts smaller than this are never compressed
+const MIN_COMPRESS_SIZE: usize = 1024;
+
+/// Upper bound on the size of a decompressed plaintext, to refuse decompression bombs
+const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Compression applied to a plaintext before it was encrypted
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+}
+
+impl Compression {
+    /// Associated data that binds the compression marker to the ciphertext
+    fn aad(&self) -> &'static [u8] {
+        match self {
+            Compression::Zstd => b"zstd",
+        }
+    }
+}
+
+/// Size of the buckets plaintexts are padded to with the 'bucket' scheme
+const BUCKET_SIZE: usize = 256;
+
+/// Marks the end of a plaintext when it is padded (ISO/IEC 7816-4); only zeros follow it
+const PADDING_MARKER: u8 = 0x80;
+
+/// Padding applied to a plaintext before it is encrypted, so the ciphertext does not reveal
+/// its exact length
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Padding {
+    /// Padmé: rounds the length up so only its magnitude shows, adding at most 12% overhead
+    Padme,
+    /// Rounds the length up to a multiple of 256 bytes, hiding the length of short secrets
+    /// entirely
+    Bucket,
+}
+
+impl Padding {
+    /// Returns the padded length of a plaintext of `len` bytes (including the end marker)
+    fn padded_len(&self, len: usize) -> usize {
+        let len = len + 1;
+        match self {
+            Padding::Bucket => len.div_ceil(BUCKET_SIZE) * BUCKET_SIZE,
+            Padding::Padme => {
+                if len < 2 {
+                    return len;
+                }
+                let e = usize::BITS - 1 - len.leading_zeros();
+                let s = u32::BITS - e.leading_zeros();
+                let mask = (1usize << (e - s)) - 1;
+                (len + mask) & !mask
+            }
+        }
+    }
+
+    /// Appends the end marker and the zeros that bring a plaintext to its padded length
+    fn pad(&self, data: &[u8]) -> Vec<u8> {
+        let mut padded = Vec::with_capacity(self.padded_len(data.len()));
+        padded.extend_from_slice(data);
+        padded.push(PADDING_MARKER);
+        padded.resize(self.padded_len(data.len()), 0);
+        padded
+    }
+
+    /// Strips the zeros and end marker added by pad
+    fn unpad(mut data: Vec<u8>) -> Result<Vec<u8>> {
+        let end = data
+            .iter()
+            .rposition(|&b| b != 0)
+            .filter(|&i| data[i] == PADDING_MARKER)
+            .ok_or_else(|| anyhow::anyhow!("Invalid padding in decrypted data"))?;
+        data.truncate(end);
+        Ok(data)
+    }
+
+    /// Returns the name of the scheme, as given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Padding::Padme => "padme",
+            Padding::Bucket => "bucket",
+        }
+    }
+}
+
+/// Padding the profile applies to everything encrypted by this process
+static PADDING: OnceLock<Padding> = OnceLock::new();
+
+/// Pads every plaintext encrypted from now on with this scheme (set once, from the profile's
+/// configuration)
+pub fn set_padding(padding: Padding) {
+    let _ = PADDING.set(padding);
+}
+
+/// Associated data that binds the compression and padding markers to the ciphertext.
+/// Blobs without padding use the compression marker alone, as they always have.
+fn aad(compression: Option<Compression>, padding: Option<Padding>) -> Vec<u8> {
+    let mut aad = compression.map_or(&[][..], |c| c.aad()).to_vec();
+    if let Some(padding) = padding {
+        if !aad.is_empty() {
+            aad.push(b'+');
+        }
+        aad.extend_from_slice(padding.name().as_bytes());
+    }
+    aad
+}
+
+/// Prefix of a vault key that holds category data keys only, instead of the master key
+const SCOPED_KEY_PREFIX: &str = "scoped:";
 
 /// Represents an encrypted data packet including KDF parameters and payload
 #[derive(Serialize, Deserialize)]
@@ -19,6 +138,27 @@ pub struct EncryptedBlob {
     pub nonce: String,
     /// Base64 encoded ciphertext
     pub ciphertext: String,
+    /// Compression applied before encryption, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Padding applied after compression, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub padding: Option<Padding>,
+    /// Top-level category whose data key encrypted the data (None for the key it was
+    /// decrypted with directly, e.g. the master key)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+/// Data encrypted to a single X25519 public key (age-style key wrapping)
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WrappedKey {
+    /// Base64 encoded ephemeral X25519 public key used for the key agreement
+    pub ephemeral_public: String,
+    /// Base64 encoded nonce
+    pub nonce: String,
+    /// Base64 encoded ciphertext
+    pub ciphertext: String,
 }
 
 /// Secure cryptographic operations for data encryption and decryption
@@ -38,6 +178,13 @@ impl CryptoHandler {
             .collect()
     }
 
+    /// Returns the Argon2id parameters used for key derivation (memory in KiB, iterations,
+    /// parallelism)
+    pub fn kdf_params() -> (u32, u32, u32) {
+        let params = argon2::Params::default();
+        (params.m_cost(), params.t_cost(), params.p_cost())
+    }
+
     /// Derives a 32-byte encryption key from a password and salt using Argon2id
     fn derive_key(password: &str, salt: &str) -> Result<[u8; 32]> {
         let salt =
@@ -61,8 +208,45 @@ impl CryptoHandler {
         Ok(key)
     }
 
-    /// Encrypts data using a password and authenticated encryption (XChaCha20-Poly1305)
+    /// Encrypts data using a password and authenticated encryption (XChaCha20-Poly1305),
+    /// padded with the profile's padding scheme if it has one
     pub fn encrypt(data: &[u8], password: &str) -> Result<EncryptedBlob> {
+        Self::encrypt_with(data, password, None, PADDING.get().copied())
+    }
+
+    /// Encrypts data like encrypt, compressing it with zstd first when that makes it smaller.
+    /// Used for key values, which can be large JSON or config files.
+    pub fn encrypt_compressed(data: &[u8], password: &str) -> Result<EncryptedBlob> {
+        if data.len() >= MIN_COMPRESS_SIZE {
+            let compressed = zstd::bulk::compress(data, 0).context("Compression failed")?;
+            if compressed.len() < data.len() {
+                return Self::encrypt_with(
+                    &compressed,
+                    password,
+                    Some(Compression::Zstd),
+                    PADDING.get().copied(),
+                );
+            }
+        }
+        Self::encrypt(data, password)
+    }
+
+    /// Encrypts an (optionally already compressed) payload, padding it first if requested
+    fn encrypt_with(
+        data: &[u8],
+        password: &str,
+        compression: Option<Compression>,
+        padding: Option<Padding>,
+    ) -> Result<EncryptedBlob> {
+        let padded;
+        let data = match padding {
+            Some(scheme) => {
+                padded = scheme.pad(data);
+                &padded[..]
+            }
+            None => data,
+        };
+
         let salt = SaltString::generate(&mut OsRng);
         let key = Self::derive_key(password, salt.as_str())?;
 
@@ -76,7 +260,7 @@ impl CryptoHandler {
                 nonce,
                 Payload {
                     msg: data,
-                    aad: &[],
+                    aad: &aad(compression, padding),
                 },
             )
             .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
@@ -85,12 +269,80 @@ impl CryptoHandler {
             salt: salt.as_str().to_string(),
             nonce: BASE64.encode(nonce_bytes),
             ciphertext: BASE64.encode(ciphertext),
+            compression,
+            padding,
+            category: None,
         })
     }
 
-    /// Decrypts data using a password and verifies data integrity
+    /// Derives the data key of a top-level category from the master key
+    pub fn derive_category_key(master_key: &str, category: &str) -> Result<String> {
+        let hk = Hkdf::<Sha256>::new(Some(b"axkeystore-category-v1"), master_key.as_bytes());
+        let mut key = [0u8; 32];
+        hk.expand(category.as_bytes(), &mut key)
+            .map_err(|_| anyhow::anyhow!("Category key derivation failed"))?;
+        Ok(BASE64.encode(key))
+    }
+
+    /// Encodes category data keys (by top-level category) as a vault key that opens the
+    /// values of those categories only
+    pub fn scoped_key(keys: &BTreeMap<String, String>) -> Result<String> {
+        Ok(format!(
+            "{}{}",
+            SCOPED_KEY_PREFIX,
+            BASE64.encode(serde_json::to_vec(keys)?)
+        ))
+    }
+
+    /// Returns the category data keys a scoped vault key holds, or None for any other key
+    /// (e.g. the master key)
+    pub fn scoped_categories(vault_key: &str) -> Option<BTreeMap<String, String>> {
+        let encoded = vault_key.strip_prefix(SCOPED_KEY_PREFIX)?;
+        serde_json::from_slice(&BASE64.decode(encoded).ok()?).ok()
+    }
+
+    /// Returns the data key of a top-level category: derived from the master key, or taken
+    /// from a scoped vault key that holds it
+    pub fn category_key(vault_key: &str, category: &str) -> Result<String> {
+        match Self::scoped_categories(vault_key) {
+            Some(keys) => keys
+                .get(category)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("You have no access to category '{}'.", category)),
+            None => Self::derive_category_key(vault_key, category),
+        }
+    }
+
+    /// Encrypts data like encrypt_compressed, with the data key of a top-level category
+    /// instead of the vault key itself. decrypt picks the category key again from the
+    /// vault key.
+    pub fn encrypt_for_category(
+        data: &[u8],
+        vault_key: &str,
+        category: &str,
+    ) -> Result<EncryptedBlob> {
+        let key = Self::category_key(vault_key, category)?;
+        let mut blob = Self::encrypt_compressed(data, &key)?;
+        blob.category = Some(category.to_string());
+        Ok(blob)
+    }
+
+    /// Decrypts data using a password and verifies data integrity, removing padding and
+    /// decompressing it if it was padded or compressed before encryption. Data encrypted
+    /// for a category is decrypted with that category's data key.
     pub fn decrypt(blob: &EncryptedBlob, password: &str) -> Result<Vec<u8>> {
-        let key = Self::derive_key(password, &blob.salt)?;
+        // Neither the password nor what it decrypts may show up in an error or a log
+        redact::register(password);
+        let password = match &blob.category {
+            Some(category) => Cow::Owned(Self::category_key(password, category)?),
+            None if Self::scoped_categories(password).is_some() => {
+                return Err(anyhow::anyhow!(
+                    "This data is encrypted with the master key, which you have no access to."
+                ))
+            }
+            None => Cow::Borrowed(password),
+        };
+        let key = Self::derive_key(&password, &blob.salt)?;
 
         let cipher = XChaCha20Poly1305::new(&key.into());
 
@@ -109,13 +361,156 @@ impl CryptoHandler {
                 nonce,
                 Payload {
                     msg: &ciphertext,
-                    aad: &[],
+                    aad: &aad(blob.compression, blob.padding),
                 },
             )
             .map_err(|_| anyhow::anyhow!("Decryption failed - wrong password?"))?;
-
+        let plaintext = match blob.padding {
+            Some(_) => Padding::unpad(plaintext)?,
+            None => plaintext,
+        };
+
+        let plaintext = match blob.compression {
+            None => plaintext,
+            Some(Compression::Zstd) => zstd::bulk::decompress(&plaintext, MAX_DECOMPRESSED_SIZE)
+                .context("Failed to decompress decrypted data")?,
+        };
+        redact::register_bytes(&plaintext);
         Ok(plaintext)
     }
+
+    /// Checks that a blob is well-formed (valid salt, 24-byte nonce, and base64 ciphertext long
+    /// enough to hold an authentication tag) without attempting to decrypt it
+    pub fn check_structure(blob: &EncryptedBlob) -> Result<()> {
+        SaltString::from_b64(&blob.salt).map_err(|e| anyhow::anyhow!("Invalid salt: {}", e))?;
+
+        let nonce_bytes = BASE64.decode(&blob.nonce).context("Invalid nonce base64")?;
+        if nonce_bytes.len() != 24 {
+            return Err(anyhow::anyhow!(
+                "Invalid nonce length ({} bytes, expected 24)",
+                nonce_bytes.len()
+            ));
+        }
+
+        let ciphertext = BASE64
+            .decode(&blob.ciphertext)
+            .context("Invalid ciphertext base64")?;
+        if ciphertext.len() < 16 {
+            return Err(anyhow::anyhow!("Ciphertext is too short"));
+        }
+
+        Ok(())
+    }
+
+    /// Generates a new random X25519 identity secret
+    pub fn generate_identity() -> [u8; 32] {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        secret
+    }
+
+    /// Returns the base64 encoded X25519 public key belonging to an identity secret
+    pub fn identity_public_key(secret: &[u8; 32]) -> String {
+        let public = PublicKey::from(&StaticSecret::from(*secret));
+        BASE64.encode(public.as_bytes())
+    }
+
+    /// Parses a base64 encoded X25519 public key
+    fn parse_public_key(encoded: &str) -> Result<PublicKey> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .context("Invalid public key base64")?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid public key length"))?;
+        Ok(PublicKey::from(bytes))
+    }
+
+    /// Derives a wrapping key from an X25519 shared secret, bound to both public keys
+    fn derive_wrapping_key(
+        shared: &[u8],
+        ephemeral: &PublicKey,
+        recipient: &PublicKey,
+    ) -> Result<[u8; 32]> {
+        let mut salt = Vec::with_capacity(64);
+        salt.extend_from_slice(ephemeral.as_bytes());
+        salt.extend_from_slice(recipient.as_bytes());
+
+        let hk = Hkdf::<Sha256>::new(Some(&salt), shared);
+        let mut key = [0u8; 32];
+        hk.expand(b"axkeystore-recipient-v1", &mut key)
+            .map_err(|_| anyhow::anyhow!("Wrapping key derivation failed"))?;
+        Ok(key)
+    }
+
+    /// Encrypts data so that only the holder of the recipient's identity can decrypt it
+    pub fn wrap_for_recipient(data: &[u8], recipient_public_key: &str) -> Result<WrappedKey> {
+        let recipient = Self::parse_public_key(recipient_public_key)?;
+        let ephemeral_secret = StaticSecret::from(Self::generate_identity());
+        let ephemeral_public = PublicKey::from(&ephemeral_secret);
+
+        let shared = ephemeral_secret.diffie_hellman(&recipient);
+        if !shared.was_contributory() {
+            return Err(anyhow::anyhow!("Invalid recipient public key"));
+        }
+        let key = Self::derive_wrapping_key(shared.as_bytes(), &ephemeral_public, &recipient)?;
+
+        let cipher = XChaCha20Poly1305::new(&key.into());
+        let mut nonce_bytes = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce_bytes);
+        let nonce = XNonce::from_slice(&nonce_bytes);
+
+        let ciphertext = cipher
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: data,
+                    aad: &[],
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+        Ok(WrappedKey {
+            ephemeral_public: BASE64.encode(ephemeral_public.as_bytes()),
+            nonce: BASE64.encode(nonce_bytes),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    /// Decrypts data that was wrapped for the public key of the given identity
+    pub fn unwrap_with_identity(wrapped: &WrappedKey, secret: &[u8; 32]) -> Result<Vec<u8>> {
+        let identity = StaticSecret::from(*secret);
+        let recipient = PublicKey::from(&identity);
+        let ephemeral = Self::parse_public_key(&wrapped.ephemeral_public)?;
+
+        let shared = identity.diffie_hellman(&ephemeral);
+        let key = Self::derive_wrapping_key(shared.as_bytes(), &ephemeral, &recipient)?;
+
+        let cipher = XChaCha20Poly1305::new(&key.into());
+        let nonce_bytes = BASE64
+            .decode(&wrapped.nonce)
+            .context("Invalid nonce base64")?;
+        if nonce_bytes.len() != 24 {
+            return Err(anyhow::anyhow!("Invalid nonce length"));
+        }
+        let nonce = XNonce::from_slice(&nonce_bytes);
+
+        let ciphertext = BASE64
+            .decode(&wrapped.ciphertext)
+            .context("Invalid ciphertext base64")?;
+
+        cipher
+            .decrypt(
+                nonce,
+                Payload {
+                    msg: &ciphertext,
+                    aad: &[],
+                },
+            )
+            .map_err(|_| {
+                anyhow::anyhow!("Unwrapping failed - key was not shared with this identity")
+            })
+    }
 }
 
 #[cfg(test)]
@@ -209,4 +604,170 @@ mod tests {
         let result = CryptoHandler::decrypt(&encrypted, password);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_check_structure() {
+        let blob = CryptoHandler::encrypt(b"value", "password").unwrap();
+        assert!(CryptoHandler::check_structure(&blob).is_ok());
+
+        let bad_nonce = EncryptedBlob {
+            nonce: BASE64.encode([0u8; 12]),
+            ..CryptoHandler::encrypt(b"value", "password").unwrap()
+        };
+        assert!(CryptoHandler::check_structure(&bad_nonce).is_err());
+
+        let bad_ciphertext = EncryptedBlob {
+            ciphertext: "not base64!".to_string(),
+            ..CryptoHandler::encrypt(b"value", "password").unwrap()
+        };
+        assert!(CryptoHandler::check_structure(&bad_ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_compressed() {
+        let password = "password";
+        let config = "{\"name\": \"service\", \"replicas\": 3}\n".repeat(200);
+
+        let encrypted = CryptoHandler::encrypt_compressed(config.as_bytes(), password).unwrap();
+        assert_eq!(encrypted.compression, Some(Compression::Zstd));
+        assert!(BASE64.decode(&encrypted.ciphertext).unwrap().len() < config.len());
+        assert_eq!(
+            CryptoHandler::decrypt(&encrypted, password).unwrap(),
+            config.as_bytes()
+        );
+
+        // Stripping the marker must not yield the compressed bytes as if they were the value
+        let stripped = EncryptedBlob {
+            compression: None,
+            ..CryptoHandler::encrypt_compressed(config.as_bytes(), password).unwrap()
+        };
+        assert!(CryptoHandler::decrypt(&stripped, password).is_err());
+
+        // Small or incompressible values are stored as before, without the field
+        let small = CryptoHandler::encrypt_compressed(b"short", password).unwrap();
+        assert!(small.compression.is_none());
+        assert!(!serde_json::to_string(&small)
+            .unwrap()
+            .contains("compression"));
+    }
+
+    #[test]
+    fn test_padding() {
+        assert_eq!(Padding::Bucket.padded_len(0), 256);
+        assert_eq!(Padding::Bucket.padded_len(16), 256);
+        assert_eq!(Padding::Bucket.padded_len(64), 256);
+        assert_eq!(Padding::Bucket.padded_len(255), 256);
+        assert_eq!(Padding::Bucket.padded_len(256), 512);
+        assert_eq!(Padding::Padme.padded_len(8), 10);
+        assert_eq!(Padding::Padme.padded_len(1000), 1024);
+        assert!(Padding::Padme.padded_len(100_000) <= 100_001 * 112 / 100);
+
+        let password = "password";
+        for value in [
+            &b""[..],
+            b"sk_live_0123456789",
+            b"ends with zeros\0\0",
+            b"\x80",
+        ] {
+            let blob =
+                CryptoHandler::encrypt_with(value, password, None, Some(Padding::Bucket)).unwrap();
+            assert_eq!(blob.padding, Some(Padding::Bucket));
+            assert_eq!(BASE64.decode(&blob.ciphertext).unwrap().len(), 256 + 16);
+            assert_eq!(CryptoHandler::decrypt(&blob, password).unwrap(), value);
+        }
+
+        // Stripping the marker must not yield the padded bytes as if they were the value
+        let stripped = EncryptedBlob {
+            padding: None,
+            ..CryptoHandler::encrypt_with(b"value", password, None, Some(Padding::Padme)).unwrap()
+        };
+        assert!(CryptoHandler::decrypt(&stripped, password).is_err());
+
+        // Padding is applied after compression and removed before decompression
+        let config = "{\"name\": \"service\"}\n".repeat(200);
+        let compressed = zstd::bulk::compress(config.as_bytes(), 0).unwrap();
+        let blob = CryptoHandler::encrypt_with(
+            &compressed,
+            password,
+            Some(Compression::Zstd),
+            Some(Padding::Padme),
+        )
+        .unwrap();
+        assert_eq!(
+            CryptoHandler::decrypt(&blob, password).unwrap(),
+            config.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_category_keys() {
+        let master_key = CryptoHandler::generate_master_key();
+        let team = CryptoHandler::derive_category_key(&master_key, "team").unwrap();
+        assert_eq!(
+            team,
+            CryptoHandler::derive_category_key(&master_key, "team").unwrap()
+        );
+        assert_ne!(
+            team,
+            CryptoHandler::derive_category_key(&master_key, "personal").unwrap()
+        );
+
+        let shared =
+            CryptoHandler::encrypt_for_category(b"team secret", &master_key, "team").unwrap();
+        assert_eq!(shared.category.as_deref(), Some("team"));
+        let private =
+            CryptoHandler::encrypt_for_category(b"my secret", &master_key, "personal").unwrap();
+        let legacy = CryptoHandler::encrypt(b"old secret", &master_key).unwrap();
+
+        // The master key opens everything
+        assert_eq!(
+            CryptoHandler::decrypt(&shared, &master_key).unwrap(),
+            b"team secret"
+        );
+        assert_eq!(
+            CryptoHandler::decrypt(&legacy, &master_key).unwrap(),
+            b"old secret"
+        );
+
+        // A key scoped to 'team' opens only the values encrypted for 'team'
+        let scoped =
+            CryptoHandler::scoped_key(&BTreeMap::from([("team".to_string(), team)])).unwrap();
+        assert!(CryptoHandler::scoped_categories(&scoped).is_some());
+        assert!(CryptoHandler::scoped_categories(&master_key).is_none());
+        assert_eq!(
+            CryptoHandler::decrypt(&shared, &scoped).unwrap(),
+            b"team secret"
+        );
+        assert!(CryptoHandler::decrypt(&private, &scoped).is_err());
+        assert!(CryptoHandler::decrypt(&legacy, &scoped).is_err());
+
+        // Relabelling a value with another category does not make it readable
+        let relabelled = EncryptedBlob {
+            category: Some("team".to_string()),
+            ..CryptoHandler::encrypt_for_category(b"my secret", &master_key, "personal").unwrap()
+        };
+        assert!(CryptoHandler::decrypt(&relabelled, &master_key).is_err());
+    }
+
+    #[test]
+    fn test_wrap_unwrap_for_recipient() {
+        let identity = CryptoHandler::generate_identity();
+        let public = CryptoHandler::identity_public_key(&identity);
+
+        let wrapped = CryptoHandler::wrap_for_recipient(b"remote-master-key", &public).unwrap();
+        let unwrapped = CryptoHandler::unwrap_with_identity(&wrapped, &identity).unwrap();
+        assert_eq!(unwrapped, b"remote-master-key");
+
+        // A different identity cannot unwrap it
+        let other = CryptoHandler::generate_identity();
+        assert!(CryptoHandler::unwrap_with_identity(&wrapped, &other).is_err());
+    }
+
+    #[test]
+    fn test_wrap_invalid_public_key() {
+        assert!(CryptoHandler::wrap_for_recipient(b"data", "not-base64!").is_err());
+        assert!(CryptoHandler::wrap_for_recipient(b"data", &BASE64.encode([1u8; 16])).is_err());
+        // The all-zero point is rejected as non-contributory
+        assert!(CryptoHandler::wrap_for_recipient(b"data", &BASE64.encode([0u8; 32])).is_err());
+    }
 }
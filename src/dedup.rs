@@ -0,0 +1,182 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::index;
+use crate::storage::{KeyEntry, Storage, TreeChange, DEDUP_DIR};
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Keys that hold the same value
+#[derive(Debug, PartialEq)]
+pub struct Group {
+    /// Id of the value (see value_id)
+    pub id: String,
+    /// Display paths of the keys, sorted
+    pub paths: Vec<String>,
+    /// The encrypted blob stored once for all of them
+    blob: Vec<u8>,
+}
+
+/// What deduplication would do
+#[derive(Debug, PartialEq)]
+pub struct Plan {
+    /// Values shared by two or more keys
+    pub groups: Vec<Group>,
+    /// Ids of every value in the vault; shared blobs with other ids are no longer used
+    ids: BTreeSet<String>,
+}
+
+/// Returns the id of a value: a hash keyed with the master key, so equal values can be found
+/// without revealing anything about them to readers of the repository
+pub fn value_id(master_key: &str, value: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(master_key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(b"axkeystore-dedup-v1\0");
+    mac.update(value);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Groups the stored keys by value
+pub fn plan(master_key: &str, entries: &[KeyEntry]) -> Result<Plan> {
+    let mut by_id: BTreeMap<String, Vec<&KeyEntry>> = BTreeMap::new();
+    for entry in entries {
+        let path = entry.display_path();
+        let encrypted: EncryptedBlob = serde_json::from_slice(&entry.data)
+            .with_context(|| format!("Failed to parse key '{}'", path))?;
+        let value = CryptoHandler::decrypt(&encrypted, master_key)
+            .with_context(|| format!("Key '{}' cannot be decrypted", path))?;
+        by_id
+            .entry(value_id(master_key, &value))
+            .or_default()
+            .push(entry);
+    }
+
+    let ids = by_id.keys().cloned().collect();
+    let groups = by_id
+        .into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .map(|(id, entries)| {
+            let mut paths: Vec<String> = entries.iter().map(|e| e.display_path()).collect();
+            paths.sort();
+            Group {
+                id,
+                paths,
+                blob: entries[0].data.clone(),
+            }
+        })
+        .collect();
+    Ok(Plan { groups, ids })
+}
+
+/// Stores each shared value once and points its keys to it, removing shared blobs that no key
+/// uses anymore, in a single commit. Returns the number of removed shared blobs.
+pub async fn apply(storage: &Storage, plan: &Plan) -> Result<usize> {
+    let mut changes: Vec<TreeChange> = Vec::new();
+    for group in &plan.groups {
+        changes.extend(
+            storage
+                .path_changes(&Storage::dedup_path(&group.id), Some(&group.blob))
+                .await?,
+        );
+        let pointer = Storage::dedup_pointer(&group.id)?;
+        for path in &group.paths {
+            let (category, name) = crate::templatefn::split_key_path(path);
+            changes.extend(storage.key_changes(name, category, Some(&pointer)).await?);
+        }
+    }
+
+    let mut removed = 0;
+    for path in storage.list_files_recursive(DEDUP_DIR).await? {
+        let id = path
+            .strip_prefix(DEDUP_DIR)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .and_then(|rest| rest.strip_suffix(".json"))
+            .unwrap_or_default();
+        if !plan.ids.contains(id) {
+            changes.extend(storage.path_changes(&path, None).await?);
+            removed += 1;
+        }
+    }
+
+    if !changes.is_empty() {
+        storage
+            .apply_changes(
+                &changes,
+                &format!("Deduplicate {} shared value(s)", plan.groups.len()),
+            )
+            .await?;
+    }
+    Ok(removed)
+}
+
+/// Lists the other keys pointing to the same shared blob as a key, using only the repository
+/// tree. Returns None if the key does not point to a shared blob.
+pub async fn sharing(
+    storage: &Storage,
+    key: &str,
+    category: Option<&str>,
+) -> Result<Option<Vec<String>>> {
+    let path = Storage::build_key_path(key, category)?;
+    let is_pointer = storage
+        .get_file(&path)
+        .await?
+        .is_some_and(|(data, _)| Storage::parse_pointer(&data).is_some());
+    if !is_pointer {
+        return Ok(None);
+    }
+
+    // Pointers to the same blob have identical content, hence the same Git blob SHA
+    let tree = storage.list_tree().await?;
+    let sha = match tree.get(&path) {
+        Some(sha) => sha,
+        None => return Ok(Some(Vec::new())),
+    };
+    Ok(Some(
+        tree.iter()
+            .filter(|(other, other_sha)| *other_sha == sha && **other != path)
+            .filter_map(|(other, _)| index::key_display_path(other))
+            .map(|p| p.to_string())
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(category: Option<&str>, name: &str, value: &[u8], master_key: &str) -> KeyEntry {
+        KeyEntry {
+            name: name.to_string(),
+            category: category.map(|c| c.to_string()),
+            data: serde_json::to_vec(&CryptoHandler::encrypt(value, master_key).unwrap()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_value_id() {
+        assert_eq!(value_id("mk", b"value"), value_id("mk", b"value"));
+        assert_ne!(value_id("mk", b"value"), value_id("mk", b"other"));
+        assert_ne!(value_id("mk", b"value"), value_id("other-mk", b"value"));
+        assert_eq!(value_id("mk", b"value").len(), 64);
+    }
+
+    #[test]
+    fn test_plan() {
+        let mk = "master-key";
+        let entries = vec![
+            entry(Some("prod"), "stripe", b"sk_shared", mk),
+            entry(None, "unique", b"alone", mk),
+            entry(Some("dev"), "stripe", b"sk_shared", mk),
+        ];
+        let plan = plan(mk, &entries).unwrap();
+        assert_eq!(plan.groups.len(), 1);
+        assert_eq!(plan.groups[0].id, value_id(mk, b"sk_shared"));
+        assert_eq!(plan.groups[0].paths, vec!["dev/stripe", "prod/stripe"]);
+        assert_eq!(plan.ids.len(), 2);
+        assert!(plan.ids.contains(&value_id(mk, b"alone")));
+    }
+}
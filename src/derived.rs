@@ -0,0 +1,168 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::{Storage, TreeChange};
+use anyhow::{Context, Result};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+/// Repository path of the encrypted registry of derived keys
+pub const REGISTRY_PATH: &str = ".axkeystore/derived.json";
+
+/// Default length of a derived value
+pub const DEFAULT_LENGTH: usize = 32;
+
+/// Characters used for derived values (matches generated values)
+const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// A key whose value is derived from the master key instead of being stored
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DerivedKey {
+    /// Rotation counter; bumping it yields a new, unrelated value
+    pub counter: u32,
+    /// Length of the derived value
+    pub length: usize,
+}
+
+/// All derived keys of a vault, indexed by 'category/name' (or just 'name')
+#[derive(Serialize, Deserialize, Default)]
+pub struct DerivedRegistry {
+    pub keys: BTreeMap<String, DerivedKey>,
+}
+
+/// Returns the canonical registry id ('category/name') of a key, validating both parts
+pub fn key_id(key: &str, category: Option<&str>) -> Result<String> {
+    let path = Storage::build_key_path(key, category)?;
+    Ok(path
+        .trim_start_matches("keys/")
+        .trim_end_matches(".json")
+        .to_string())
+}
+
+/// Validates the requested length of a derived value
+pub fn validate_length(length: usize) -> Result<()> {
+    if !(6..=64).contains(&length) {
+        return Err(anyhow::anyhow!(
+            "Derived value length must be between 6 and 64 characters"
+        ));
+    }
+    Ok(())
+}
+
+/// Deterministically derives a value from the master key, the key id and its counter using
+/// HKDF-SHA256, mapping the output onto alphanumeric characters without modulo bias
+pub fn derive_value(master_key: &str, id: &str, counter: u32, length: usize) -> Result<String> {
+    validate_length(length)?;
+    let hk = Hkdf::<Sha256>::new(Some(b"axkeystore-derived-v1"), master_key.as_bytes());
+
+    let mut value = String::with_capacity(length);
+    let mut block = 0u32;
+    while value.len() < length {
+        let info = format!("{}#{}#{}", id, counter, block);
+        let mut okm = [0u8; 64];
+        hk.expand(info.as_bytes(), &mut okm)
+            .map_err(|_| anyhow::anyhow!("Key derivation failed"))?;
+
+        // Reject bytes >= 248 (= 62 * 4) so every character is equally likely
+        for b in okm {
+            if value.len() == length {
+                break;
+            }
+            if (b as usize) < CHARSET.len() * 4 {
+                value.push(CHARSET[b as usize % CHARSET.len()] as char);
+            }
+        }
+        block += 1;
+    }
+    Ok(value)
+}
+
+/// Loads the derived key registry (empty if none exists yet, or for a vault key scoped to
+/// some categories, which derived keys are not shared with)
+pub async fn load(storage: &Storage, master_key: &str) -> Result<DerivedRegistry> {
+    if CryptoHandler::scoped_categories(master_key).is_some() {
+        return Ok(DerivedRegistry::default());
+    }
+    let data = match storage.get_file(REGISTRY_PATH).await? {
+        Some((data, _)) => data,
+        None => return Ok(DerivedRegistry::default()),
+    };
+
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&data).context("Failed to parse derived key registry")?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, master_key)
+        .context("Failed to decrypt derived key registry")?;
+    serde_json::from_slice(&decrypted).context("Derived key registry is corrupted")
+}
+
+/// Encrypts and saves the derived key registry
+pub async fn save(
+    storage: &Storage,
+    master_key: &str,
+    registry: &DerivedRegistry,
+    message: &str,
+) -> Result<()> {
+    storage
+        .put_file(REGISTRY_PATH, &encrypt(master_key, registry)?, message)
+        .await
+}
+
+/// Builds the changes that save the derived key registry, so it can be committed together
+/// with other changes
+pub async fn save_changes(
+    storage: &Storage,
+    master_key: &str,
+    registry: &DerivedRegistry,
+) -> Result<Vec<TreeChange>> {
+    storage
+        .path_changes(REGISTRY_PATH, Some(&encrypt(master_key, registry)?))
+        .await
+}
+
+/// Encrypts the registry with the master key, refusing keys scoped to some categories
+fn encrypt(master_key: &str, registry: &DerivedRegistry) -> Result<Vec<u8>> {
+    if CryptoHandler::scoped_categories(master_key).is_some() {
+        return Err(anyhow::anyhow!(
+            "Derived keys need the master key, which you have no access to."
+        ));
+    }
+    let encrypted = CryptoHandler::encrypt_compressed(&serde_json::to_vec(registry)?, master_key)?;
+    Ok(serde_json::to_vec(&encrypted)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_value_is_deterministic() {
+        let a = derive_value("master", "db/password", 1, 32).unwrap();
+        let b = derive_value("master", "db/password", 1, 32).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 32);
+        assert!(a.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn test_derive_value_depends_on_inputs() {
+        let base = derive_value("master", "db/password", 1, 32).unwrap();
+        assert_ne!(base, derive_value("master", "db/password", 2, 32).unwrap());
+        assert_ne!(base, derive_value("master", "db/other", 1, 32).unwrap());
+        assert_ne!(base, derive_value("other", "db/password", 1, 32).unwrap());
+
+        // Longer values extend shorter ones from the same stream
+        let long = derive_value("master", "db/password", 1, 64).unwrap();
+        assert_eq!(long.len(), 64);
+        assert!(long.starts_with(&base));
+
+        assert!(derive_value("master", "db/password", 1, 5).is_err());
+        assert!(derive_value("master", "db/password", 1, 65).is_err());
+    }
+
+    #[test]
+    fn test_key_id() {
+        assert_eq!(key_id("api", None).unwrap(), "api");
+        assert_eq!(key_id("api", Some("/cloud/aws/")).unwrap(), "cloud/aws/api");
+        assert!(key_id("bad/key", None).is_err());
+    }
+}
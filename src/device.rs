@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+/// Overrides the machine identifier, so tests can simulate other machines
+const TEST_ID_VAR: &str = "AXKEYSTORE_TEST_DEVICE_ID";
+
+/// Returns this machine's fingerprint: a SHA-256 hash of the identifier the OS assigns to the
+/// machine at installation, its OS and its architecture. The identifier itself is never
+/// stored or shown.
+pub fn fingerprint() -> Result<String> {
+    let id = match std::env::var(TEST_ID_VAR) {
+        Ok(id) => id,
+        Err(_) => machine_id()?,
+    };
+    let mut hasher = Sha256::new();
+    for part in [
+        "axkeystore-device-v1",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        id.trim(),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Mixes the device fingerprint into a key, so whatever it encrypts can only be decrypted on
+/// this machine
+pub fn bind_key(key: &str) -> Result<String> {
+    Ok(format!("{}:device:{}", key, fingerprint()?))
+}
+
+/// Reads the machine identifier: the machine-id on Linux and other Unix systems, the platform
+/// UUID on macOS, and the MachineGuid on Windows
+fn machine_id() -> Result<String> {
+    let id = if cfg!(target_os = "macos") {
+        let output = command_output("ioreg", &["-rd1", "-c", "IOPlatformExpertDevice"])?;
+        output
+            .lines()
+            .find(|line| line.contains("\"IOPlatformUUID\""))
+            .and_then(|line| line.rsplit('"').nth(1))
+            .map(|uuid| uuid.to_string())
+    } else if cfg!(windows) {
+        let output = command_output(
+            "reg",
+            &[
+                "query",
+                r"HKLM\SOFTWARE\Microsoft\Cryptography",
+                "/v",
+                "MachineGuid",
+            ],
+        )?;
+        output
+            .lines()
+            .find(|line| line.contains("MachineGuid"))
+            .and_then(|line| line.split_whitespace().last())
+            .map(|guid| guid.to_string())
+    } else {
+        ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())
+    };
+    id.map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .context("Could not determine this machine's identifier")
+}
+
+/// Runs a command and returns its standard output
+fn command_output(program: &str, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run '{}'", program))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("'{}' failed: {}", program, output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        std::env::set_var(TEST_ID_VAR, "machine-a");
+        let a = fingerprint().unwrap();
+        assert_eq!(a.len(), 64);
+        assert!(!a.contains("machine-a"));
+        assert_eq!(fingerprint().unwrap(), a);
+        assert_ne!(bind_key("lmk").unwrap(), "lmk");
+
+        std::env::set_var(TEST_ID_VAR, "machine-b");
+        assert_ne!(fingerprint().unwrap(), a);
+        std::env::remove_var(TEST_ID_VAR);
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// File in an export directory that lists the secret files written by AxKeyStore, so the
+/// auto-clean only removes what the export created
+const MANIFEST_NAME: &str = ".axkeystore-secrets";
+
+/// Returns the Docker secret name of a key: its path relative to the exported category, with
+/// '/' replaced by '_' (e.g. 'aws/api' in category 'prod' becomes 'aws_api')
+pub fn secret_name(path: &str, category: Option<&str>) -> String {
+    let relative = match category.map(|c| c.trim_matches('/')) {
+        Some(cat) if !cat.is_empty() => path
+            .strip_prefix(cat)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .unwrap_or(path),
+        _ => path,
+    };
+    relative.replace('/', "_")
+}
+
+/// Writes one file per secret (readable only by the current user) into a directory, which
+/// is created if needed. Existing files with the same names are replaced.
+pub fn write_dir(dir: &Path, secrets: &BTreeMap<String, Vec<u8>>) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+
+    for (name, value) in secrets {
+        let path = dir.join(name);
+        crate::config::write_private_file(&path, value)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    }
+
+    let mut manifest = String::new();
+    for name in secrets.keys() {
+        manifest.push_str(name);
+        manifest.push('\n');
+    }
+    crate::config::write_private_file(&dir.join(MANIFEST_NAME), manifest.as_bytes())
+}
+
+/// Removes the secret files listed in a directory's manifest, then the manifest and the
+/// directory itself if nothing else is left in it. Returns the number of files removed.
+pub fn clean(dir: &Path) -> Result<usize> {
+    let manifest_path = dir.join(MANIFEST_NAME);
+    let manifest = match std::fs::read_to_string(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).context("Failed to read the secrets manifest"),
+    };
+
+    let mut removed = 0;
+    // Names never contain separators, so the manifest cannot point outside the directory
+    for name in manifest
+        .lines()
+        .filter(|n| !n.is_empty() && !n.contains(['/', '\\']))
+    {
+        if std::fs::remove_file(dir.join(name)).is_ok() {
+            removed += 1;
+        }
+    }
+    std::fs::remove_file(&manifest_path)?;
+    let _ = std::fs::remove_dir(dir);
+    Ok(removed)
+}
+
+/// Starts a detached process that cleans the directory after a delay, so the files do not
+/// outlive the deployment that needed them
+pub fn schedule_clean(dir: &Path, after_secs: u64) -> Result<()> {
+    let dir = std::fs::canonicalize(dir)?;
+    std::process::Command::new(std::env::current_exe()?)
+        .arg("clean-secrets")
+        .arg(&dir)
+        .arg("--after")
+        .arg(after_secs.to_string())
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .context("Failed to start the auto-clean process")?;
+    Ok(())
+}
+
+/// Returns the `secrets:` section of a Compose file that mounts the exported files
+pub fn compose_snippet(dir: &Path, names: &[&String]) -> String {
+    let mut out = String::from("secrets:\n");
+    for name in names {
+        out.push_str(&format!(
+            "  {}:\n    file: {}\n",
+            name,
+            dir.join(name.as_str()).display()
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_name() {
+        assert_eq!(secret_name("prod/aws/api", Some("prod")), "aws_api");
+        assert_eq!(secret_name("prod/db", Some("/prod/")), "db");
+        assert_eq!(secret_name("prod/aws/api", None), "prod_aws_api");
+        assert_eq!(secret_name("token", None), "token");
+    }
+
+    #[test]
+    fn test_write_and_clean() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("secrets");
+        let mut secrets = BTreeMap::new();
+        secrets.insert("db_password".to_string(), b"hunter2".to_vec());
+        secrets.insert("api_key".to_string(), b"abc".to_vec());
+        write_dir(&dir, &secrets).unwrap();
+
+        assert_eq!(std::fs::read(dir.join("db_password")).unwrap(), b"hunter2");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join("api_key"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let names: Vec<&String> = secrets.keys().collect();
+        assert_eq!(
+            compose_snippet(Path::new("./secrets"), &names),
+            "secrets:\n  api_key:\n    file: ./secrets/api_key\n  db_password:\n    file: ./secrets/db_password\n"
+        );
+
+        // Files the export did not write are left alone, and so is their directory
+        std::fs::write(dir.join("other"), b"keep").unwrap();
+        assert_eq!(clean(&dir).unwrap(), 2);
+        assert!(!dir.join("db_password").exists());
+        assert!(dir.join("other").exists());
+        assert_eq!(clean(&dir).unwrap(), 0);
+    }
+}
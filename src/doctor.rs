@@ -0,0 +1,357 @@
+use crate::auth;
+use crate::config::Config;
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::recipients;
+use crate::storage::Storage;
+use crate::timeutil;
+
+/// The checks, in the order they run. Each one needs the ones before it to pass.
+const CHECKS: [&str; 10] = [
+    "Configuration",
+    "Local master key",
+    "Repository setting",
+    "Token",
+    "GitHub account",
+    "App installation",
+    "Repository",
+    "Master key",
+    "Rate limit",
+    "Clock",
+];
+
+/// Clock skew (in seconds) that gets a warning, and the one that fails the check
+const SKEW_WARN_SECS: u64 = 60;
+const SKEW_FAIL_SECS: u64 = 300;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+    /// Not performed, because an earlier check failed or it does not apply
+    Skipped,
+}
+
+impl Status {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Status::Ok => " OK ",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skipped => "SKIP",
+        }
+    }
+}
+
+/// The result of one check
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    /// What was found
+    pub detail: String,
+    /// How to fix a failed check or act on a warning
+    pub fix: Option<String>,
+}
+
+/// The checks performed so far
+#[derive(Default)]
+struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    /// Records the outcome of the next check. Returns None after a failure, so the caller
+    /// can stop with '?'.
+    fn record(&mut self, status: Status, detail: String, fix: Option<&str>) -> Option<()> {
+        self.checks.push(Check {
+            name: CHECKS[self.checks.len()],
+            status,
+            detail,
+            fix: fix.map(|f| f.to_string()),
+        });
+        (status != Status::Fail).then_some(())
+    }
+
+    fn ok(&mut self, detail: String) -> Option<()> {
+        self.record(Status::Ok, detail, None)
+    }
+
+    fn warn(&mut self, detail: String, fix: &str) -> Option<()> {
+        self.record(Status::Warn, detail, Some(fix))
+    }
+
+    fn fail(&mut self, detail: String, fix: &str) -> Option<()> {
+        self.record(Status::Fail, detail, Some(fix))
+    }
+
+    fn skip(&mut self, detail: String) -> Option<()> {
+        self.record(Status::Skipped, detail, None)
+    }
+
+    /// Marks the checks that were not reached as skipped
+    fn finish(mut self) -> Vec<Check> {
+        let done = self.checks.len();
+        for name in &CHECKS[done..] {
+            self.checks.push(Check {
+                name,
+                status: Status::Skipped,
+                detail: "Skipped because of the failure above".to_string(),
+                fix: None,
+            });
+        }
+        self.checks
+    }
+}
+
+/// Describes how far the local clock is from GitHub's, and how bad that is
+fn clock_status(local: u64, server: u64) -> (Status, String) {
+    let skew = local.abs_diff(server);
+    let direction = if local >= server {
+        "ahead of"
+    } else {
+        "behind"
+    };
+    let detail = if skew == 0 {
+        "The local clock matches GitHub's".to_string()
+    } else {
+        format!(
+            "The local clock is {} {} GitHub's",
+            timeutil::format_duration_secs(skew),
+            direction
+        )
+    };
+    let status = match skew {
+        s if s >= SKEW_FAIL_SECS => Status::Fail,
+        s if s >= SKEW_WARN_SECS => Status::Warn,
+        _ => Status::Ok,
+    };
+    (status, detail)
+}
+
+/// Checks a profile end-to-end without changing anything, stopping at the first failure
+pub async fn run(profile: Option<&str>, password: &str) -> Vec<Check> {
+    let mut report = Report::default();
+    let _ = diagnose(profile, password, &mut report).await;
+    report.finish()
+}
+
+async fn diagnose(profile: Option<&str>, password: &str, report: &mut Report) -> Option<()> {
+    let profile_name = profile.unwrap_or("default");
+
+    let config = match Config::load_checked_with_profile(profile) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            return report.fail(
+                format!("Profile '{}' is not set up", profile_name),
+                "Run 'axkeystore login', then 'axkeystore init'.",
+            )
+        }
+        Err(e) => {
+            return report.fail(
+                format!("{:#}", e),
+                "Restore config.json from a backup, or delete the profile and set it up again.",
+            )
+        }
+    };
+    report.ok("config.json is readable".to_string())?;
+
+    if config.encrypted_lmk.is_none() {
+        return report.fail(
+            "No local master key is set up".to_string(),
+            "Run 'axkeystore login', then 'axkeystore init'.",
+        );
+    }
+    if let Err(e) = Config::get_or_create_lmk_with_profile(profile, password) {
+        return report.fail(
+            e.to_string(),
+            "Check the master password: the one used with 'axkeystore init', or last set with 'axkeystore reset-password'.",
+        );
+    }
+    report.ok("Unlocked with the master password".to_string())?;
+
+    let repo = match Config::get_repo_name_with_profile(profile, password) {
+        Ok(repo) => repo,
+        Err(e) => return report.fail(e.to_string(), "Run 'axkeystore init'."),
+    };
+    report.ok(format!("Vault repository is '{}'", repo))?;
+
+    let token = match std::env::var("AXKEYSTORE_TEST_TOKEN") {
+        Ok(token) => token,
+        Err(_) if !auth::is_logged_in_with_profile(profile) => {
+            return report.fail(
+                format!("Profile '{}' is not logged in", profile_name),
+                "Run 'axkeystore login'.",
+            )
+        }
+        Err(_) => match auth::get_saved_token_with_profile(profile, password) {
+            Ok(token) => token,
+            Err(e) => return report.fail(e.to_string(), "Run 'axkeystore login'."),
+        },
+    };
+    report.ok("The saved GitHub token decrypts".to_string())?;
+
+    let storage = match Storage::with_token(profile, &repo, token).await {
+        Ok(storage) => storage,
+        Err(e) => {
+            return report.fail(
+                format!("GitHub rejected the token: {:#}", e),
+                "Run 'axkeystore login' again; the token may have expired or been revoked.",
+            )
+        }
+    };
+    report.ok(format!("Signed in to GitHub as '{}'", storage.login()))?;
+
+    let app_name = std::env::var("GITHUB_APP_NAME").unwrap_or_else(|_| "axkeystore".to_string());
+    let install_fix = format!(
+        "Install the app at https://github.com/apps/{}/installations/new and grant it access to the vault repository.",
+        app_name
+    );
+    match storage.installation_count().await {
+        Ok(Some(0)) => report.fail(
+            "The GitHub App is not installed on any account".to_string(),
+            &install_fix,
+        )?,
+        Ok(Some(count)) => report.ok(format!(
+            "The GitHub App is installed on {} account(s)",
+            count
+        ))?,
+        Ok(None) => report
+            .skip("The token is not a GitHub App token, so no installation is needed".into())?,
+        Err(e) => report.warn(
+            format!("Could not list the app's installations: {:#}", e),
+            &install_fix,
+        )?,
+    }
+
+    match storage.is_private().await {
+        Ok(true) => report.ok(format!("'{}' is reachable", storage.repo_full_name()))?,
+        Ok(false) => report.warn(
+            format!(
+                "'{}' is reachable, but it is PUBLIC",
+                storage.repo_full_name()
+            ),
+            "Make the repository private in its GitHub settings, and run 'axkeystore posture'.",
+        )?,
+        Err(e) => report.fail(
+            format!("Cannot reach '{}': {:#}", storage.repo_full_name(), e),
+            &format!(
+                "Check that the repository exists and that the app can access it. {}",
+                install_fix
+            ),
+        )?,
+    }
+
+    let blob = match storage.get_master_key_blob().await {
+        Ok(blob) => blob,
+        Err(e) => {
+            return report.fail(
+                format!("Cannot read the master key: {:#}", e),
+                "Check the token's access to the repository's contents.",
+            )
+        }
+    };
+    match blob {
+        None => report.warn(
+            "The vault has no master key yet".to_string(),
+            "It is created with the first 'axkeystore store'. If keys were stored before, restore '.axkeystore/master_key.json' from the repository history.",
+        )?,
+        Some(data) => {
+            let encrypted = match serde_json::from_slice::<EncryptedBlob>(&data)
+                .map_err(anyhow::Error::from)
+                .and_then(|blob| CryptoHandler::check_structure(&blob).map(|_| blob))
+            {
+                Ok(blob) => blob,
+                Err(e) => {
+                    return report.fail(
+                        format!("The master key is corrupted: {:#}", e),
+                        "Restore '.axkeystore/master_key.json' from the repository history or a backup.",
+                    )
+                }
+            };
+            if CryptoHandler::decrypt(&encrypted, password).is_ok() {
+                report.ok("The master key decrypts with the master password".to_string())?;
+            } else {
+                match recipients::unwrap_master_key(&storage, password).await {
+                    Ok(Some(_)) => {
+                        report.ok("The master key is shared with this profile's identity".into())?
+                    }
+                    _ => report.fail(
+                        "The master password does not decrypt the vault's master key".to_string(),
+                        "Use the password the vault was created with, or ask its owner to add you with 'axkeystore recipient add'.",
+                    )?,
+                }
+            }
+        }
+    }
+
+    let rate_limit = match storage.rate_limit().await {
+        Ok(rate_limit) => rate_limit,
+        Err(e) => {
+            report.warn(
+                format!("Could not read the rate limit: {:#}", e),
+                "Try again later.",
+            )?;
+            return report.skip("GitHub's time is unknown".to_string());
+        }
+    };
+    let now = timeutil::now_unix();
+    let usage = rate_limit.describe(now);
+    if rate_limit.remaining == 0 {
+        report.fail(
+            usage,
+            "Wait for the limit to reset before running more commands.",
+        )?;
+    } else if rate_limit.is_low() {
+        report.warn(
+            usage,
+            "Avoid bulk commands until the limit resets; enable metadata caching with 'axkeystore cache set metadata <ttl>'.",
+        )?;
+    } else {
+        report.ok(usage)?;
+    }
+
+    match rate_limit.server_time {
+        Some(server) => match clock_status(now, server) {
+            (Status::Ok, detail) => report.ok(detail),
+            (status, detail) => report.record(
+                status,
+                detail,
+                Some("Synchronize the system clock (e.g. enable NTP); expiry dates, deletion deadlines and TOTP codes depend on it."),
+            ),
+        },
+        None => report.skip("GitHub did not report its time".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_skips_after_failure() {
+        let mut report = Report::default();
+        assert!(report.ok("readable".to_string()).is_some());
+        assert!(report.warn("odd".to_string(), "fix it").is_some());
+        assert!(report.fail("broken".to_string(), "fix it").is_none());
+        let checks = report.finish();
+
+        assert_eq!(checks.len(), CHECKS.len());
+        assert_eq!(checks[0].name, "Configuration");
+        assert_eq!(checks[2].status, Status::Fail);
+        assert_eq!(checks[2].fix.as_deref(), Some("fix it"));
+        assert!(checks[3..].iter().all(|c| c.status == Status::Skipped));
+        assert_eq!(checks.last().unwrap().name, "Clock");
+    }
+
+    #[test]
+    fn test_clock_status() {
+        assert_eq!(clock_status(1000, 1000).0, Status::Ok);
+        assert_eq!(clock_status(1030, 1000).0, Status::Ok);
+        assert_eq!(clock_status(1000, 1120).0, Status::Warn);
+        assert!(clock_status(1000, 1120).1.contains("behind"));
+        assert_eq!(clock_status(1400, 1000).0, Status::Fail);
+        assert!(clock_status(1400, 1000).1.contains("ahead of"));
+    }
+}
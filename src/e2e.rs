@@ -0,0 +1,175 @@
+use crate::config::Config;
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::Storage;
+use crate::{rekey, vault};
+use anyhow::{Context, Result};
+
+/// Every repository used by 'e2e' starts with this, so it can never delete a real vault
+pub const REPO_PREFIX: &str = "axkeystore-e2e-";
+
+/// Category and name of the key the test stores
+const CATEGORY: &str = "e2e";
+const KEY: &str = "token";
+
+/// Returns a fresh name for a throwaway repository
+pub fn random_repo_name() -> String {
+    format!("{}{:08x}", REPO_PREFIX, rand::random::<u32>())
+}
+
+/// Checks that a repository name is one 'e2e' may create and delete
+pub fn validate_repo_name(name: &str) -> Result<()> {
+    let valid = name.strip_prefix(REPO_PREFIX).is_some_and(|rest| {
+        !rest.is_empty()
+            && rest
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    });
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid repository '{}': the end-to-end test only uses repositories of your own named '{}<suffix>'",
+            name,
+            REPO_PREFIX
+        ));
+    }
+    Ok(())
+}
+
+fn check(ok: bool, what: &str) -> Result<()> {
+    if !ok {
+        return Err(anyhow::anyhow!("{}", what));
+    }
+    Ok(())
+}
+
+/// Encrypts a value and stores it as the test key
+async fn store(storage: &Storage, master_key: &str, value: &str) -> Result<()> {
+    let encrypted = vault::encrypt_value(master_key, Some(CATEGORY), value.as_bytes())?;
+    storage
+        .save_blob(KEY, &serde_json::to_vec(&encrypted)?, Some(CATEGORY))
+        .await
+}
+
+/// Decrypts a stored blob with a master key
+fn open(data: &[u8], master_key: &str) -> Result<String> {
+    let encrypted: EncryptedBlob = serde_json::from_slice(data)?;
+    Ok(String::from_utf8(CryptoHandler::decrypt(
+        &encrypted, master_key,
+    )?)?)
+}
+
+/// Reads the test key's current value
+async fn get(storage: &Storage, master_key: &str) -> Result<Option<String>> {
+    match storage.get_blob(KEY, Some(CATEGORY)).await? {
+        Some((data, _)) => Ok(Some(open(&data, master_key)?)),
+        None => Ok(None),
+    }
+}
+
+/// Runs a store, get, update, history, rekey and delete cycle against an empty repository,
+/// printing each step. Stops at the first step whose result is not what was written.
+pub async fn run(storage: &Storage, password: &str) -> Result<()> {
+    // The rekey step would replace the journal of an interrupted rekey of another vault
+    let lmk = Config::get_or_create_lmk_with_profile(storage.profile(), password)?;
+    if rekey::load_journal(storage.profile(), &lmk)?.is_some() {
+        return Err(anyhow::anyhow!(
+            "An interrupted rekey is waiting to be resumed. Run 'axkeystore rekey' first."
+        ));
+    }
+
+    let master_key = CryptoHandler::generate_master_key();
+    let encrypted = CryptoHandler::encrypt(master_key.as_bytes(), password)?;
+    storage
+        .save_master_key_blob(&serde_json::to_vec(&encrypted)?)
+        .await?;
+    println!("  ok  initialize master key");
+
+    let first = format!("e2e-{:016x}", rand::random::<u64>());
+    let second = format!("e2e-{:016x}", rand::random::<u64>());
+    store(storage, &master_key, &first).await.context("store")?;
+    check(
+        get(storage, &master_key).await.context("get")?.as_deref() == Some(first.as_str()),
+        "get: the value read is not the value stored",
+    )?;
+    println!("  ok  store and get");
+
+    store(storage, &master_key, &second)
+        .await
+        .context("update")?;
+    check(
+        get(storage, &master_key).await.context("get")?.as_deref() == Some(second.as_str()),
+        "update: the value read is not the new value",
+    )?;
+    println!("  ok  update");
+
+    let versions = storage
+        .get_key_history(KEY, Some(CATEGORY), 1, 10)
+        .await
+        .context("history")?;
+    check(
+        versions.len() == 2,
+        &format!("history: expected 2 versions, found {}", versions.len()),
+    )?;
+    let old = storage
+        .get_blob_at_version(KEY, Some(CATEGORY), &versions[1].sha)
+        .await
+        .context("history")?
+        .context("history: the first version cannot be read")?;
+    check(
+        open(&old, &master_key)? == first,
+        "history: the first version does not hold the first value",
+    )?;
+    println!("  ok  history");
+
+    rekey::run(storage, password, &master_key)
+        .await
+        .context("rekey")?;
+    let blob = storage
+        .get_master_key_blob()
+        .await?
+        .context("rekey: the master key disappeared")?;
+    let new_master_key = open(&blob, password).context("rekey")?;
+    check(
+        new_master_key != master_key,
+        "rekey: the master key did not change",
+    )?;
+    check(
+        get(storage, &new_master_key)
+            .await
+            .context("get")?
+            .as_deref()
+            == Some(second.as_str()),
+        "rekey: the value does not decrypt with the new master key",
+    )?;
+    println!("  ok  rekey");
+
+    check(
+        storage
+            .delete_blob(KEY, Some(CATEGORY))
+            .await
+            .context("delete")?,
+        "delete: the key was not found",
+    )?;
+    check(
+        get(storage, &new_master_key).await?.is_none(),
+        "delete: the key can still be read",
+    )?;
+    println!("  ok  delete");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_names() {
+        let name = random_repo_name();
+        assert!(name.starts_with(REPO_PREFIX));
+        assert!(validate_repo_name(&name).is_ok());
+        assert!(validate_repo_name("axkeystore-e2e-ci_1").is_ok());
+
+        assert!(validate_repo_name("axkeystore-e2e-").is_err());
+        assert!(validate_repo_name("axkeystore-storage").is_err());
+        assert!(validate_repo_name("octocat/axkeystore-e2e-1").is_err());
+    }
+}
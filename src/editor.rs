@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use rand::Rng;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A decrypted value on disk that is overwritten and removed when dropped, even on errors
+struct SecretFile {
+    path: PathBuf,
+}
+
+impl SecretFile {
+    /// Creates the file readable only by the current user and writes the value to it
+    fn create(dir: &Path, content: &[u8]) -> Result<Self> {
+        let suffix: u64 = rand::rng().random();
+        let path = dir.join(format!("axkeystore-edit-{:016x}", suffix));
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&path)
+            .with_context(|| format!("Failed to create '{}'", path.display()))?;
+
+        let secret = Self { path };
+        file.write_all(content)?;
+        file.sync_all()?;
+        Ok(secret)
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        // Best effort: overwrite the contents before unlinking so they do not linger on disk
+        if let Ok(len) = std::fs::metadata(&self.path).map(|m| m.len()) {
+            if let Ok(mut file) = std::fs::OpenOptions::new().write(true).open(&self.path) {
+                let _ = file.write_all(&vec![0u8; len as usize]);
+                let _ = file.sync_all();
+            }
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Returns where decrypted values are written for editing: a RAM-backed tmpfs where the
+/// platform has one, the system temp directory otherwise
+fn secure_temp_dir() -> PathBuf {
+    let shm = Path::new("/dev/shm");
+    if cfg!(target_os = "linux") && shm.is_dir() {
+        shm.to_path_buf()
+    } else {
+        std::env::temp_dir()
+    }
+}
+
+/// Returns the user's editor command ($VISUAL, then $EDITOR, then a platform default)
+fn editor_command() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| {
+            if cfg!(target_os = "windows") {
+                "notepad".to_string()
+            } else {
+                "vi".to_string()
+            }
+        })
+}
+
+/// Opens a value in the user's editor. Returns the edited value, or None if it was not changed.
+pub fn edit(value: &[u8]) -> Result<Option<Vec<u8>>> {
+    edit_with(&editor_command(), &secure_temp_dir(), value)
+}
+
+/// Opens a value in the given editor command (which may include arguments, e.g. 'code --wait')
+fn edit_with(editor: &str, dir: &Path, value: &[u8]) -> Result<Option<Vec<u8>>> {
+    let file = SecretFile::create(dir, value)?;
+
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("No editor configured")?;
+    let status = Command::new(program)
+        .args(parts)
+        .arg(&file.path)
+        .status()
+        .with_context(|| format!("Failed to launch editor '{}'", program))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Editor '{}' exited with {}; nothing was saved",
+            program,
+            status
+        ));
+    }
+
+    let edited = std::fs::read(&file.path).context("Failed to read the edited value")?;
+    Ok(if edited == value { None } else { Some(edited) })
+}
+
+/// Lines that mark the two sides of a conflict in [`conflict_text`]
+const OURS_MARKER: &str = "<<<<<<< yours";
+const SEPARATOR: &str = "=======";
+const THEIRS_MARKER: &str = ">>>>>>> theirs";
+
+/// Puts two conflicting text values into one, between conflict markers, for the user to
+/// resolve in the editor
+pub fn conflict_text(ours: &str, theirs: &str) -> Vec<u8> {
+    let mut text = String::new();
+    for (marker, side) in [(OURS_MARKER, ours), (SEPARATOR, theirs)] {
+        text.push_str(marker);
+        text.push('\n');
+        text.push_str(side);
+        if !side.is_empty() && !side.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    text.push_str(THEIRS_MARKER);
+    text.push('\n');
+    text.into_bytes()
+}
+
+/// Returns true if a value still holds a conflict marker
+pub fn has_conflict_markers(value: &[u8]) -> bool {
+    String::from_utf8_lossy(value)
+        .lines()
+        .any(|line| line == OURS_MARKER || line == SEPARATOR || line == THEIRS_MARKER)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_with() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // An editor that changes nothing
+        assert!(edit_with("true", dir.path(), b"value").unwrap().is_none());
+
+        // An editor that rewrites the file
+        let script = dir.path().join("fake-editor.sh");
+        std::fs::write(&script, "#!/bin/sh\nprintf 'new value' > \"$1\"\n").unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        let edited = edit_with(script.to_str().unwrap(), dir.path(), b"value").unwrap();
+        assert_eq!(edited.as_deref(), Some(&b"new value"[..]));
+
+        // A failing editor aborts the edit
+        assert!(edit_with("false", dir.path(), b"value").is_err());
+
+        // No decrypted copy is left behind
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                e.file_name()
+                    .to_string_lossy()
+                    .starts_with("axkeystore-edit-")
+            })
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_conflict_text() {
+        let text = conflict_text("mine", "theirs\n");
+        assert_eq!(
+            String::from_utf8(text.clone()).unwrap(),
+            "<<<<<<< yours\nmine\n=======\ntheirs\n>>>>>>> theirs\n"
+        );
+        assert!(has_conflict_markers(&text));
+        assert!(!has_conflict_markers(b"mine\ntheirs\n"));
+        // Markers only count on a line of their own
+        assert!(!has_conflict_markers(b"a ======= b"));
+    }
+
+    #[test]
+    fn test_secret_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let file = SecretFile::create(dir.path(), b"secret").unwrap();
+        let mode = std::fs::metadata(&file.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let path = file.path.clone();
+        drop(file);
+        assert!(!path.exists());
+    }
+}
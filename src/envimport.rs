@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+/// Parses the contents of /proc/<pid>/environ (NUL-separated NAME=value pairs)
+pub fn parse_environ(data: &[u8]) -> BTreeMap<String, Vec<u8>> {
+    data.split(|b| *b == 0)
+        .filter_map(|pair| {
+            let eq = pair.iter().position(|b| *b == b'=')?;
+            let name = std::str::from_utf8(&pair[..eq]).ok()?;
+            (!name.is_empty()).then(|| (name.to_string(), pair[eq + 1..].to_vec()))
+        })
+        .collect()
+}
+
+/// Reads the environment of a running process (Linux only). Reading another user's process
+/// requires the same permissions as ptrace.
+pub fn read_process_env(pid: u32) -> Result<BTreeMap<String, Vec<u8>>> {
+    if !cfg!(target_os = "linux") {
+        return Err(anyhow::anyhow!(
+            "Importing from a process is only supported on Linux"
+        ));
+    }
+    let path = format!("/proc/{}/environ", pid);
+    let data = std::fs::read(&path).with_context(|| {
+        format!(
+            "Failed to read '{}'. Does the process exist, and are you allowed to inspect it?",
+            path
+        )
+    })?;
+    Ok(parse_environ(&data))
+}
+
+/// Returns the environment of the current process
+pub fn current_env() -> BTreeMap<String, Vec<u8>> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| {
+            let name = name.into_string().ok()?;
+            Some((name, value.into_encoded_bytes()))
+        })
+        .collect()
+}
+
+/// Selects the variables to import and maps them to key names. Variables matching the prefix
+/// are imported with the prefix stripped; variables named explicitly keep their name.
+pub fn select(
+    env: &BTreeMap<String, Vec<u8>>,
+    prefix: Option<&str>,
+    names: &[String],
+) -> BTreeMap<String, Vec<u8>> {
+    let mut selected = BTreeMap::new();
+    if let Some(prefix) = prefix {
+        for (name, value) in env {
+            if let Some(stripped) = name.strip_prefix(prefix) {
+                if !stripped.is_empty() {
+                    selected.insert(stripped.to_string(), value.clone());
+                }
+            }
+        }
+    }
+    for name in names {
+        if let Some(value) = env.get(name) {
+            selected.insert(name.clone(), value.clone());
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_environ() {
+        let env = parse_environ(b"PATH=/usr/bin\0APP_DB=postgres://x?a=b\0EMPTY=\0=bogus\0NOEQ\0");
+        assert_eq!(env.len(), 3);
+        assert_eq!(env["APP_DB"], b"postgres://x?a=b");
+        assert_eq!(env["EMPTY"], b"");
+    }
+
+    #[test]
+    fn test_select() {
+        let env = parse_environ(b"PATH=/usr/bin\0APP_DB=db\0APP_TOKEN=t\0APP_=x\0HOME=/root\0");
+        let selected = select(
+            &env,
+            Some("APP_"),
+            &["HOME".to_string(), "MISSING".to_string()],
+        );
+        let names: Vec<&str> = selected.keys().map(|k| k.as_str()).collect();
+        assert_eq!(names, vec!["DB", "HOME", "TOKEN"]);
+        assert_eq!(selected["DB"], b"db");
+    }
+}
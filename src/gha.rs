@@ -0,0 +1,168 @@
+use crate::storage::Storage;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::collections::BTreeMap;
+
+/// Validates a target repository given as 'owner/name'
+pub fn validate_repo(repo: &str) -> Result<()> {
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    };
+    match repo.split_once('/') {
+        Some((owner, name)) if valid_part(owner) && valid_part(name) => Ok(()),
+        _ => Err(anyhow::anyhow!(
+            "Invalid repository '{}'. Use the form 'owner/name'.",
+            repo
+        )),
+    }
+}
+
+/// Returns the Actions secret name of a key from its name relative to the synced category:
+/// upper-cased, with '/' and '-' replaced by '_' (e.g. 'aws/api-token' becomes
+/// 'AWS_API_TOKEN'). Fails for names GitHub does not accept.
+pub fn secret_name(relative: &str) -> Result<String> {
+    let name: String = relative
+        .chars()
+        .map(|c| match c {
+            '/' | '-' => '_',
+            c => c.to_ascii_uppercase(),
+        })
+        .collect();
+    if name.is_empty()
+        || name.starts_with(|c: char| c.is_ascii_digit())
+        || name.starts_with("GITHUB_")
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(anyhow::anyhow!(
+            "'{}' cannot be used as a GitHub Actions secret name ('{}')",
+            relative,
+            name
+        ));
+    }
+    Ok(name)
+}
+
+/// Encrypts a value for a repository's Actions public key (base64 encoded) as a libsodium
+/// sealed box, which only GitHub can open. Returns the base64 encoded ciphertext.
+pub fn seal(public_key: &str, value: &[u8]) -> Result<String> {
+    let bytes: [u8; 32] = BASE64
+        .decode(public_key)
+        .context("Invalid Actions public key encoding")?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Actions public key must be 32 bytes"))?;
+    let sealed = crypto_box::PublicKey::from(bytes)
+        .seal(&mut crypto_box::aead::OsRng, value)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the secret"))?;
+    Ok(BASE64.encode(sealed))
+}
+
+/// Creates or updates Actions secrets of a repository ('owner/name'). Values are sealed
+/// locally; GitHub never receives them in the clear. Returns the number of secrets written.
+pub async fn sync(
+    storage: &Storage,
+    repo: &str,
+    secrets: &BTreeMap<String, Vec<u8>>,
+) -> Result<usize> {
+    let public_key = storage.actions_public_key(repo).await?;
+    let mut written = 0;
+    for (name, value) in secrets {
+        let encrypted = seal(&public_key.key, value)?;
+        storage
+            .put_actions_secret(repo, name, &encrypted, &public_key.key_id)
+            .await?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    #[test]
+    fn test_secret_name() {
+        assert_eq!(secret_name("npm-token").unwrap(), "NPM_TOKEN");
+        assert_eq!(secret_name("aws/api-key").unwrap(), "AWS_API_KEY");
+        assert!(secret_name("github_token").is_err());
+        assert!(secret_name("2fa").is_err());
+
+        assert!(validate_repo("org/app").is_ok());
+        assert!(validate_repo("org").is_err());
+        assert!(validate_repo("org/app/extra").is_err());
+    }
+
+    #[test]
+    fn test_seal() {
+        let secret = crypto_box::SecretKey::generate(&mut crypto_box::aead::OsRng);
+        let public_key = BASE64.encode(secret.public_key().as_bytes());
+
+        let sealed = BASE64
+            .decode(seal(&public_key, b"s3cr3t").unwrap())
+            .unwrap();
+        assert_eq!(secret.unseal(&sealed).unwrap(), b"s3cr3t");
+        assert!(seal("c2hvcnQ=", b"s3cr3t").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        let secret = crypto_box::SecretKey::generate(&mut crypto_box::aead::OsRng);
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/org/app/actions/secrets/public-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "key_id": "key-1",
+                "key": BASE64.encode(secret.public_key().as_bytes())
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/org/app/actions/secrets/NPM_TOKEN"))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        let secrets = BTreeMap::from([("NPM_TOKEN".to_string(), b"npm_abc".to_vec())]);
+        assert_eq!(sync(&storage, "org/app", &secrets).await.unwrap(), 1);
+
+        // The value is sent sealed for the repository's key, never in the clear
+        let requests: Vec<Request> = mock_server.received_requests().await.unwrap();
+        let put = requests
+            .iter()
+            .find(|r| r.method.as_str() == "PUT")
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&put.body).unwrap();
+        assert_eq!(body["key_id"], "key-1");
+        let sealed = BASE64
+            .decode(body["encrypted_value"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(secret.unseal(&sealed).unwrap(), b"npm_abc");
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+}
@@ -0,0 +1,361 @@
+use crate::config::Config;
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::meta::{self, KeyMeta};
+use crate::storage::Storage;
+use crate::{derived, timeutil};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// What the index knows about one key. Never holds a value.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct IndexEntry {
+    pub category: Option<String>,
+    pub name: String,
+    /// Blob SHA of the key file when last seen (None for derived keys, and for keys written
+    /// locally since the last refresh)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+    #[serde(default)]
+    pub derived: bool,
+    /// Blob SHA of the metadata document the fields below were read from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta_sha: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl IndexEntry {
+    /// Returns the display path ('category/name' or just 'name')
+    pub fn display_path(&self) -> String {
+        match &self.category {
+            Some(cat) => format!("{}/{}", cat, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Returns true if the entry is in the given category or one of its subcategories
+    pub fn in_category(&self, category: &str) -> bool {
+        let category = category.trim_matches('/');
+        match &self.category {
+            Some(cat) => {
+                cat == category
+                    || (cat.starts_with(category) && cat[category.len()..].starts_with('/'))
+            }
+            None => category.is_empty(),
+        }
+    }
+
+    /// Copies the searchable fields of a key's metadata
+    fn set_meta(&mut self, key_meta: &KeyMeta) {
+        self.tags = key_meta.tags.clone();
+        self.description = key_meta.description.clone();
+        self.owner = key_meta.owner.clone();
+    }
+}
+
+/// Local index of the keys in a vault, so list, search and completion do not need to walk
+/// the repository
+#[derive(Serialize, Deserialize, Default)]
+pub struct KeyIndex {
+    /// The repository the index describes ('owner/repo')
+    pub repo: String,
+    /// When the index was last refreshed from the repository (Unix seconds)
+    pub refreshed: u64,
+    /// Keys by display path
+    pub keys: BTreeMap<String, IndexEntry>,
+}
+
+/// Returns the directory of a profile that holds its key index
+fn index_dir(profile: Option<&str>) -> Result<PathBuf> {
+    let dir = Config::get_config_dir(profile)?.join("index");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Returns the path of the encrypted key index for a profile
+fn index_path(profile: Option<&str>) -> Result<PathBuf> {
+    Ok(index_dir(profile)?.join("index.json"))
+}
+
+/// Returns the path of the plain-text list of key paths used for shell completion
+fn names_path(profile: Option<&str>) -> Result<PathBuf> {
+    Ok(index_dir(profile)?.join("completion_names"))
+}
+
+/// Splits a display path into category and key name
+fn split_path(path: &str) -> (Option<String>, String) {
+    match path.rsplit_once('/') {
+        Some((cat, name)) => (Some(cat.to_string()), name.to_string()),
+        None => (None, path.to_string()),
+    }
+}
+
+/// Returns the display path of a key file ('keys/<category>/<key>.json'). Chunk files do not
+/// end in '.json' and are skipped.
+pub fn key_display_path(repo_path: &str) -> Option<&str> {
+    repo_path.strip_prefix("keys/")?.strip_suffix(".json")
+}
+
+/// Loads the index of a repository. The index is disposable: one that is missing, belongs
+/// to another repository, or cannot be decrypted (e.g. after a password reset) yields None.
+pub fn load(profile: Option<&str>, repo: &str, lmk: &str) -> Result<Option<KeyIndex>> {
+    let path = index_path(profile)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let index = serde_json::from_slice::<EncryptedBlob>(&std::fs::read(&path)?)
+        .ok()
+        .and_then(|blob| CryptoHandler::decrypt(&blob, lmk).ok())
+        .and_then(|data| serde_json::from_slice::<KeyIndex>(&data).ok());
+    Ok(index.filter(|i| i.repo == repo))
+}
+
+/// Encrypts the index with the Local Master Key and writes it to disk. When key name
+/// completion is enabled for the profile, the list of key paths is rewritten as well.
+pub fn save(profile: Option<&str>, lmk: &str, index: &KeyIndex) -> Result<()> {
+    let encrypted = CryptoHandler::encrypt(&serde_json::to_vec(index)?, lmk)?;
+    crate::config::write_private_file(&index_path(profile)?, &serde_json::to_vec(&encrypted)?)?;
+    if Config::load_with_profile(profile)?.complete_key_names {
+        save_names(profile, index)?;
+    }
+    Ok(())
+}
+
+/// Writes the key paths of the index (one per line, no metadata) for shell completion
+pub fn save_names(profile: Option<&str>, index: &KeyIndex) -> Result<()> {
+    let mut names = String::new();
+    for path in index.keys.keys() {
+        names.push_str(path);
+        names.push('\n');
+    }
+    crate::config::write_private_file(&names_path(profile)?, names.as_bytes())
+}
+
+/// Removes the plain-text list of key paths, if there is one
+pub fn remove_names(profile: Option<&str>) -> Result<()> {
+    let path = names_path(profile)?;
+    if path.exists() {
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Reads the key paths written for shell completion. Completion must never fail loudly, so
+/// a missing or unreadable list yields no paths.
+pub fn load_names(profile: Option<&str>) -> Vec<String> {
+    names_path(profile)
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .map(|names| {
+            names
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Brings the index up to date with a single tree listing. Metadata documents are only
+/// downloaded and decrypted when their SHA changed since the previous index.
+pub async fn refresh(
+    storage: &Storage,
+    master_key: &str,
+    previous: Option<&KeyIndex>,
+) -> Result<KeyIndex> {
+    let tree = storage.list_tree().await?;
+
+    let mut found: BTreeMap<String, (Option<String>, bool)> = tree
+        .iter()
+        .filter_map(|(path, sha)| {
+            key_display_path(path).map(|p| (p.to_string(), (Some(sha.clone()), false)))
+        })
+        .collect();
+    for id in derived::load(storage, master_key).await?.keys.into_keys() {
+        found.entry(id).or_insert((None, true));
+    }
+
+    let mut keys = BTreeMap::new();
+    for (path, (sha, is_derived)) in found {
+        let (category, name) = split_path(&path);
+        let meta_sha = meta::meta_path(&name, category.as_deref())
+            .ok()
+            .and_then(|p| tree.get(&p).cloned());
+        let mut entry = IndexEntry {
+            category,
+            name,
+            sha,
+            derived: is_derived,
+            meta_sha,
+            ..Default::default()
+        };
+
+        match previous.and_then(|p| p.keys.get(&path)) {
+            Some(old) if old.meta_sha.is_some() && old.meta_sha == entry.meta_sha => {
+                entry.tags = old.tags.clone();
+                entry.description = old.description.clone();
+                entry.owner = old.owner.clone();
+            }
+            _ if entry.meta_sha.is_some() => {
+                let key_meta =
+                    meta::load(storage, master_key, &entry.name, entry.category.as_deref())
+                        .await
+                        .with_context(|| format!("Failed to read the metadata of '{}'", path))?;
+                entry.set_meta(&key_meta);
+            }
+            _ => {}
+        }
+        keys.insert(path, entry);
+    }
+
+    Ok(KeyIndex {
+        repo: storage.repo_full_name(),
+        refreshed: timeutil::now_unix(),
+        keys,
+    })
+}
+
+/// Records keys that were just written. Their SHAs stay unknown until the next refresh;
+/// metadata, when given, replaces the indexed fields.
+pub fn record_write(
+    index: &mut KeyIndex,
+    category: Option<&str>,
+    names: &[&str],
+    is_derived: bool,
+    key_meta: Option<&KeyMeta>,
+) {
+    let category = category
+        .map(|c| c.trim_matches('/').to_string())
+        .filter(|c| !c.is_empty());
+    for name in names {
+        let entry = IndexEntry {
+            category: category.clone(),
+            name: name.to_string(),
+            derived: is_derived,
+            ..Default::default()
+        };
+        let entry = index.keys.entry(entry.display_path()).or_insert(entry);
+        entry.sha = None;
+        entry.derived = is_derived;
+        if let Some(key_meta) = key_meta {
+            entry.meta_sha = None;
+            entry.set_meta(key_meta);
+        }
+    }
+}
+
+/// Removes a deleted key from the index
+pub fn record_removal(index: &mut KeyIndex, category: Option<&str>, name: &str) {
+    let path = match category.map(|c| c.trim_matches('/')) {
+        Some(cat) if !cat.is_empty() => format!("{}/{}", cat, name),
+        _ => name.to_string(),
+    };
+    index.keys.remove(&path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(
+            key_display_path("keys/cloud/aws/api.json"),
+            Some("cloud/aws/api")
+        );
+        assert_eq!(key_display_path("keys/api.json"), Some("api"));
+        assert_eq!(key_display_path("keys/big.json.part000"), None);
+        assert_eq!(key_display_path(".axkeystore/meta/api.json"), None);
+        assert_eq!(
+            split_path("cloud/aws/api"),
+            (Some("cloud/aws".to_string()), "api".to_string())
+        );
+        assert_eq!(split_path("api"), (None, "api".to_string()));
+    }
+
+    #[test]
+    fn test_record_write_and_removal() {
+        let mut index = KeyIndex {
+            repo: "octocat/vault".to_string(),
+            ..Default::default()
+        };
+        index.keys.insert(
+            "cloud/api".to_string(),
+            IndexEntry {
+                category: Some("cloud".to_string()),
+                name: "api".to_string(),
+                sha: Some("abc".to_string()),
+                meta_sha: Some("def".to_string()),
+                tags: vec!["prod".to_string()],
+                ..Default::default()
+            },
+        );
+
+        // A value update keeps the indexed metadata but forgets the stale SHA
+        record_write(&mut index, Some("/cloud/"), &["api"], false, None);
+        let entry = &index.keys["cloud/api"];
+        assert_eq!(entry.sha, None);
+        assert_eq!(entry.meta_sha.as_deref(), Some("def"));
+        assert_eq!(entry.tags, vec!["prod"]);
+
+        let key_meta = KeyMeta {
+            description: Some("Stripe".to_string()),
+            ..Default::default()
+        };
+        record_write(&mut index, None, &["a", "b"], false, Some(&key_meta));
+        assert_eq!(index.keys.len(), 3);
+        assert_eq!(index.keys["a"].description.as_deref(), Some("Stripe"));
+
+        record_removal(&mut index, Some("cloud"), "api");
+        record_removal(&mut index, None, "missing");
+        let paths: Vec<&String> = index.keys.keys().collect();
+        assert_eq!(paths, vec!["a", "b"]);
+        assert!(index.keys["b"].in_category(""));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        assert!(load(None, "octocat/vault", "lmk").unwrap().is_none());
+
+        let mut index = KeyIndex {
+            repo: "octocat/vault".to_string(),
+            ..Default::default()
+        };
+        record_write(&mut index, Some("cloud"), &["stripe-live"], false, None);
+        save(None, "lmk", &index).unwrap();
+
+        // Key names are not readable on disk
+        let raw = std::fs::read_to_string(index_path(None).unwrap()).unwrap();
+        assert!(!raw.contains("stripe-live"));
+
+        let loaded = load(None, "octocat/vault", "lmk").unwrap().unwrap();
+        assert!(loaded.keys.contains_key("cloud/stripe-live"));
+        assert!(load(None, "octocat/other", "lmk").unwrap().is_none());
+        assert!(load(None, "octocat/vault", "wrong").unwrap().is_none());
+
+        // Key paths are only written in plain text once completion is enabled
+        assert!(load_names(None).is_empty());
+        let config = Config {
+            complete_key_names: true,
+            ..Default::default()
+        };
+        config.save_with_profile(None).unwrap();
+        save(None, "lmk", &index).unwrap();
+        assert_eq!(load_names(None), vec!["cloud/stripe-live"]);
+        remove_names(None).unwrap();
+        assert!(load_names(None).is_empty());
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+}
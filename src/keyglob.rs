@@ -0,0 +1,99 @@
+/// Returns true if a key path contains wildcards ('*' or '?')
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?'])
+}
+
+/// Checks whether a key path ('category/name') matches a pattern. '*' matches any characters
+/// within a segment, '?' exactly one, and a '**' segment any number of segments (even none).
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+    let path: Vec<&str> = path.trim_matches('/').split('/').collect();
+    segments_match(&pattern, &path)
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| segments_match(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((first, path_rest)) => {
+                let segment: Vec<char> = segment.chars().collect();
+                let first: Vec<char> = first.chars().collect();
+                segment_matches(&segment, &first) && segments_match(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn segment_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| segment_matches(rest, &text[skip..])),
+        Some(('?', rest)) => !text.is_empty() && segment_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && segment_matches(rest, &text[1..]),
+    }
+}
+
+/// Returns the category every match of a pattern lies in: the segments before the first one
+/// with a wildcard, leaving out the key name (None if the pattern can match anywhere)
+pub fn base_category(pattern: &str) -> Option<String> {
+    let segments: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+    let fixed: Vec<&str> = segments[..segments.len() - 1]
+        .iter()
+        .take_while(|s| !is_pattern(s))
+        .copied()
+        .collect();
+    if fixed.is_empty() {
+        None
+    } else {
+        Some(fixed.join("/"))
+    }
+}
+
+/// Returns the key paths that match a pattern, sorted and without duplicates
+pub fn expand<'a>(pattern: &str, paths: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut matched: Vec<&str> = paths.into_iter().filter(|p| matches(pattern, p)).collect();
+    matched.sort();
+    matched.dedup();
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("api/prod/tmp-*", "api/prod/tmp-1"));
+        assert!(matches("api/prod/tmp-*", "api/prod/tmp-"));
+        assert!(!matches("api/prod/tmp-*", "api/prod/token"));
+        assert!(!matches("api/prod/tmp-*", "api/prod/sub/tmp-1"));
+        assert!(matches("db/**", "db/password"));
+        assert!(matches("db/**", "db/replica/eu/password"));
+        assert!(!matches("db/**", "dbx/password"));
+        assert!(matches("**/password", "password"));
+        assert!(matches("*/db-?", "prod/db-1"));
+        assert!(!matches("*/db-?", "prod/db-12"));
+        assert!(matches("api/*/key", "api/prod/key"));
+    }
+
+    #[test]
+    fn test_base_category() {
+        assert_eq!(
+            base_category("api/prod/tmp-*"),
+            Some("api/prod".to_string())
+        );
+        assert_eq!(base_category("db/**"), Some("db".to_string()));
+        assert_eq!(base_category("api/*/key"), Some("api".to_string()));
+        assert_eq!(base_category("**/password"), None);
+        assert_eq!(base_category("tmp-*"), None);
+    }
+
+    #[test]
+    fn test_expand() {
+        let paths = ["db/b", "db/a", "web/a", "db/a"];
+        assert_eq!(expand("db/*", paths), vec!["db/a", "db/b"]);
+        assert!(expand("cache/*", paths).is_empty());
+    }
+}
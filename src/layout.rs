@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// File recording which layout a profile's configuration directory uses
+const LAYOUT_FILE: &str = "layout.json";
+
+/// Layout written by this version of AxKeyStore. Directories without a layout file use
+/// version 1, where every file sat directly in the profile's directory.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Subdirectories of a profile's configuration directory. Those of the default profile sit
+/// next to the directories of the named profiles, so no profile may take these names.
+pub const DATA_DIRS: &[&str] = &["cache", "index", "wasm-plugins"];
+
+/// A file or directory that moved between two layouts (paths relative to the profile's
+/// directory)
+struct Move {
+    from: &'static str,
+    to: &'static str,
+}
+
+/// The moves from each layout to the next: `MIGRATIONS[0]` goes from version 1 to 2. Entries
+/// are never edited once released, since profiles may still be in any older layout.
+const MIGRATIONS: &[&[Move]] = &[
+    // 2: the local key index and the key names for shell completion get their own directory
+    &[
+        Move {
+            from: "index.json",
+            to: "index/index.json",
+        },
+        Move {
+            from: "completion_names",
+            to: "index/completion_names",
+        },
+    ],
+];
+
+#[derive(Serialize, Deserialize)]
+struct LayoutFile {
+    version: u32,
+}
+
+/// Returns the layout version of a profile's configuration directory
+pub fn version(dir: &Path) -> Result<u32> {
+    let path = dir.join(LAYOUT_FILE);
+    if !path.exists() {
+        return Ok(1);
+    }
+    let layout: LayoutFile = serde_json::from_slice(&std::fs::read(&path)?)
+        .with_context(|| format!("'{}' is corrupted", path.display()))?;
+    Ok(layout.version)
+}
+
+/// Brings a profile's configuration directory to the current layout, one version at a time.
+/// A step that fails is rolled back, so its files stay where the previous layout expects
+/// them and the next run tries again. Nothing is ever overwritten: a file present in both
+/// the old and the new place stops the migration.
+pub fn migrate(dir: &Path) -> Result<()> {
+    let current = version(dir)?;
+    if current > CURRENT_VERSION {
+        return Err(anyhow::anyhow!(
+            "'{}' was written by a newer version of axkeystore (layout {}). Please upgrade.",
+            dir.display(),
+            current
+        ));
+    }
+
+    for (step, moves) in MIGRATIONS
+        .iter()
+        .enumerate()
+        .skip(current.saturating_sub(1) as usize)
+    {
+        let target = step as u32 + 2;
+        let mut done: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let result = apply_moves(dir, moves, &mut done).and_then(|_| {
+            let layout = serde_json::to_vec(&LayoutFile { version: target })?;
+            crate::config::write_private_file(&dir.join(LAYOUT_FILE), &layout)
+        });
+        if let Err(e) = result {
+            rollback(&done);
+            return Err(e.context(format!(
+                "Failed to migrate '{}' to layout {}; it was left unchanged",
+                dir.display(),
+                target
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Performs the moves of one step, recording each completed one for a rollback
+fn apply_moves(dir: &Path, moves: &[Move], done: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    for m in moves {
+        let (from, to) = (dir.join(m.from), dir.join(m.to));
+        if !from.exists() {
+            continue;
+        }
+        if to.exists() {
+            return Err(anyhow::anyhow!(
+                "Both '{}' and '{}' exist. Remove the one that is out of date and try again.",
+                from.display(),
+                to.display()
+            ));
+        }
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match std::fs::rename(&from, &to) {
+            Ok(()) => done.push((from, to)),
+            // Another process migrated it in the meantime
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to move '{}' to '{}'", from.display(), to.display())
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves files back to where they were, newest move first, and removes directories the
+/// moves created if they are empty again
+fn rollback(done: &[(PathBuf, PathBuf)]) {
+    for (from, to) in done.iter().rev() {
+        let _ = std::fs::rename(to, from);
+        if let Some(parent) = to.parent() {
+            let _ = std::fs::remove_dir(parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        std::fs::write(dir.join("index.json"), "index").unwrap();
+        std::fs::write(dir.join("github_token.json"), "token").unwrap();
+        assert_eq!(version(dir).unwrap(), 1);
+
+        migrate(dir).unwrap();
+        assert_eq!(version(dir).unwrap(), CURRENT_VERSION);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("index/index.json")).unwrap(),
+            "index"
+        );
+        assert!(!dir.join("index.json").exists());
+        assert!(!dir.join("index/completion_names").exists());
+        // Files the layout does not move stay in place
+        assert!(dir.join("github_token.json").exists());
+
+        // Migrating again changes nothing
+        migrate(dir).unwrap();
+        assert!(dir.join("index/index.json").exists());
+    }
+
+    #[test]
+    fn test_migrate_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        std::fs::write(dir.join("index.json"), "index").unwrap();
+        std::fs::write(dir.join("completion_names"), "old").unwrap();
+        std::fs::create_dir(dir.join("index")).unwrap();
+        std::fs::write(dir.join("index/completion_names"), "new").unwrap();
+
+        assert!(migrate(dir).is_err());
+        assert_eq!(version(dir).unwrap(), 1);
+        // The move that succeeded before the conflict was undone
+        assert_eq!(
+            std::fs::read_to_string(dir.join("index.json")).unwrap(),
+            "index"
+        );
+        assert!(!dir.join("index/index.json").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("completion_names")).unwrap(),
+            "old"
+        );
+
+        std::fs::remove_file(dir.join("index/completion_names")).unwrap();
+        migrate(dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("index/completion_names")).unwrap(),
+            "old"
+        );
+    }
+
+    #[test]
+    fn test_newer_layout_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(LAYOUT_FILE),
+            format!(r#"{{"version":{}}}"#, CURRENT_VERSION + 1),
+        )
+        .unwrap();
+        assert!(migrate(dir.path()).is_err());
+    }
+}
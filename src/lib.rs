@@ -0,0 +1,68 @@
+//! AxKeyStore as a library: the storage, encryption and configuration layers behind the
+//! `axkeystore` CLI, plus a high-level [`Vault`] for programs that fetch secrets themselves.
+
+pub mod agent;
+pub mod apply;
+pub mod archive;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod cache;
+pub mod cancel;
+pub mod catalog;
+pub mod categories;
+pub mod changelog;
+pub mod compose;
+pub mod config;
+pub mod crypto;
+pub mod dedup;
+pub mod derived;
+pub mod device;
+pub mod dockersecrets;
+pub mod doctor;
+#[cfg(feature = "e2e")]
+pub mod e2e;
+pub mod envimport;
+pub mod gha;
+pub mod index;
+pub mod keyglob;
+pub mod layout;
+pub mod lifecycle;
+pub mod lint;
+pub mod logging;
+pub mod meta;
+pub mod mirror;
+pub mod notify;
+pub mod parity;
+pub mod passstore;
+pub mod pipe;
+pub mod plugin;
+pub mod pmimport;
+pub mod posture;
+pub mod progress;
+pub mod promote;
+pub mod purge;
+pub mod reap;
+pub mod recipients;
+pub mod recovery;
+pub mod redact;
+pub mod rekey;
+pub mod retention;
+pub mod search;
+pub mod serve;
+pub mod serviceaccount;
+pub mod settings;
+pub mod shellenv;
+pub mod signing;
+pub mod storage;
+pub mod templatefn;
+pub mod timeutil;
+pub mod totp;
+pub mod trash;
+pub mod vault;
+pub mod wasmplugin;
+
+pub use config::Config;
+pub use crypto::CryptoHandler;
+pub use storage::Storage;
+pub use vault::Vault;
@@ -0,0 +1,99 @@
+use crate::storage::KeyVersion;
+use crate::timeutil;
+
+/// Compact lifecycle summary of a key computed from its commit history
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    /// Number of commits that touched the key
+    pub versions: usize,
+    /// Date of the oldest change
+    pub first_change: String,
+    /// Date of the newest change
+    pub last_change: String,
+    /// Committers and how many changes each made, most active first
+    pub changed_by: Vec<(String, usize)>,
+    /// Mean time between consecutive changes, if there were at least two
+    pub average_interval_secs: Option<u64>,
+}
+
+/// Summarizes a key's history (newest first, as returned by GitHub)
+pub fn summarize(history: &[KeyVersion]) -> Option<Summary> {
+    let last = history.first()?;
+    let first = history.last()?;
+
+    let mut changed_by: Vec<(String, usize)> = Vec::new();
+    for version in history {
+        let who = version
+            .committer_email
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        match changed_by.iter_mut().find(|(email, _)| *email == who) {
+            Some((_, count)) => *count += 1,
+            None => changed_by.push((who, 1)),
+        }
+    }
+    // Stable sort keeps the most recent committer first among equals
+    changed_by.sort_by_key(|c| std::cmp::Reverse(c.1));
+
+    let average_interval_secs = match (
+        timeutil::parse_timestamp(&first.date),
+        timeutil::parse_timestamp(&last.date),
+    ) {
+        (Ok(start), Ok(end)) if history.len() > 1 => {
+            Some(end.saturating_sub(start) / (history.len() as u64 - 1))
+        }
+        _ => None,
+    };
+
+    Some(Summary {
+        versions: history.len(),
+        first_change: first.date.clone(),
+        last_change: last.date.clone(),
+        changed_by,
+        average_interval_secs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(date: &str, email: Option<&str>) -> KeyVersion {
+        KeyVersion {
+            sha: "abc".to_string(),
+            date: date.to_string(),
+            message: "Update key: api".to_string(),
+            verified: false,
+            verification_reason: None,
+            committer_email: email.map(|e| e.to_string()),
+            author: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert!(summarize(&[]).is_none());
+
+        let history = vec![
+            version("2024-01-21T00:00:00Z", Some("bob@example.com")),
+            version("2024-01-11T00:00:00Z", Some("jane@example.com")),
+            version("2024-01-01T00:00:00Z", Some("jane@example.com")),
+        ];
+        let summary = summarize(&history).unwrap();
+        assert_eq!(summary.versions, 3);
+        assert_eq!(summary.first_change, "2024-01-01T00:00:00Z");
+        assert_eq!(summary.last_change, "2024-01-21T00:00:00Z");
+        assert_eq!(
+            summary.changed_by,
+            vec![
+                ("jane@example.com".to_string(), 2),
+                ("bob@example.com".to_string(), 1)
+            ]
+        );
+        assert_eq!(summary.average_interval_secs, Some(10 * 24 * 60 * 60));
+
+        let single = summarize(&[version("2024-01-01T00:00:00Z", None)]).unwrap();
+        assert_eq!(single.changed_by, vec![("unknown".to_string(), 1)]);
+        assert!(single.average_interval_secs.is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::notify::pattern_matches;
+
+/// Key path patterns treated as production: values promoted into them must pass the linter
+pub const PROTECTED_PATTERNS: &[&str] = &["prod/**", "production/**"];
+
+/// Shortest value accepted in a protected category
+const MIN_LENGTH: usize = 16;
+
+/// Least estimated strength (in bits) accepted in a protected category
+const MIN_BITS: f64 = 80.0;
+
+/// Words that mark a value as a placeholder or a development credential
+const PLACEHOLDER_WORDS: &[&str] = &[
+    "changeme",
+    "password",
+    "example",
+    "dummy",
+    "placeholder",
+    "secret",
+    "localhost",
+    "default",
+];
+
+/// Values that are development credentials when they make up the whole value
+const DEV_VALUES: &[&str] = &["dev", "test", "local", "admin", "root", "qwerty"];
+
+/// Returns true if a key path ('category/name') is in a protected category
+pub fn is_protected(path: &str) -> bool {
+    PROTECTED_PATTERNS
+        .iter()
+        .any(|pattern| pattern_matches(pattern, path))
+}
+
+/// Estimates the strength of a value in bits from its length and the character classes it
+/// uses. This is an upper bound, so values that fail it are certainly weak.
+fn estimated_bits(value: &str) -> f64 {
+    let mut pool = 0;
+    if value.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if value.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if value.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if value.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        pool += 33;
+    }
+    value.chars().count() as f64 * f64::from(pool.max(1)).log2()
+}
+
+/// Checks a value against the validation policy of protected categories and returns the
+/// problems found (empty if it passes). Binary values such as certificates are not checked.
+pub fn check(value: &[u8]) -> Vec<String> {
+    let text = match std::str::from_utf8(value) {
+        Ok(text) => text.trim(),
+        Err(_) => return Vec::new(),
+    };
+
+    let mut problems = Vec::new();
+    let length = text.chars().count();
+    if length < MIN_LENGTH {
+        problems.push(format!(
+            "it is {} characters long; at least {} are required",
+            length, MIN_LENGTH
+        ));
+    }
+    let lower = text.to_lowercase();
+    if let Some(word) = PLACEHOLDER_WORDS.iter().find(|w| lower.contains(*w)) {
+        problems.push(format!("it contains the placeholder word '{}'", word));
+    }
+    let stem = lower.trim_end_matches(|c: char| c.is_ascii_digit() || !c.is_alphanumeric());
+    if DEV_VALUES.contains(&stem) {
+        problems.push("it looks like a development credential".to_string());
+    }
+    let distinct: std::collections::BTreeSet<char> = text.chars().collect();
+    if length > 0 && distinct.len() * 4 < length.min(32) {
+        problems.push("it repeats too few distinct characters".to_string());
+    }
+    let bits = estimated_bits(text);
+    if bits < MIN_BITS {
+        problems.push(format!(
+            "its estimated strength is {:.0} bits; at least {:.0} are required",
+            bits, MIN_BITS
+        ));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_protected() {
+        assert!(is_protected("prod/db-password"));
+        assert!(is_protected("prod/payments/stripe"));
+        assert!(!is_protected("dev/prod"));
+        assert!(!is_protected("production-notes"));
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(check(b"q8Vt2mXk4Rw9Lp3zNc7Hj5Ys").is_empty());
+        assert!(check(&[0xff, 0x00, 0x13]).is_empty());
+
+        let weak = check(b"dev123");
+        assert!(weak.iter().any(|p| p.contains("6 characters")));
+        assert!(weak.iter().any(|p| p.contains("development credential")));
+        assert!(check(b"ChangeMe-ChangeMe-2024")
+            .iter()
+            .any(|p| p.contains("'changeme'")));
+        assert!(check(b"aaaaaaaaaaaaaaaaaaaaaaaa")
+            .iter()
+            .any(|p| p.contains("distinct characters")));
+        assert!(check(b"abcdefghijklmnopq")
+            .iter()
+            .any(|p| p.contains("estimated strength")));
+    }
+}
@@ -1,24 +1,51 @@
-mod auth;
-mod config;
-mod crypto;
-mod storage;
+mod clipboard;
+mod completion;
+mod editor;
 mod tui;
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+#[cfg(feature = "e2e")]
+use axkeystore::e2e;
+use axkeystore::vault::{
+    encrypt_value, init_master_key, load_master_key, read_value, reencrypt_for,
+};
+use axkeystore::{
+    agent, apply, archive, audit, auth, backup, cache, cancel, catalog, categories, changelog,
+    compose, config, crypto, dedup, derived, device, dockersecrets, doctor, envimport, gha, index,
+    keyglob, lifecycle, lint, logging, meta, mirror, notify, parity, passstore, pipe, plugin,
+    pmimport, posture, progress, promote, purge, reap, recipients, recovery, redact, rekey,
+    retention, search, serve, serviceaccount, settings, shellenv, signing, storage, templatefn,
+    timeutil, totp, trash, wasmplugin,
+};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::engine::ArgValueCandidates;
 use rand::Rng;
 use std::collections::BTreeMap;
-use std::io::Write;
+use std::io::{IsTerminal, Read, Write};
 
 /// Command line arguments for AxKeyStore
 #[derive(Parser)]
 #[command(name = "axkeystore")]
 #[command(about = "A secure, GitHub-backed keystore CLI", long_about = None)]
 struct Cli {
-    /// Use a specific profile
+    /// Use a specific profile (also set AXKEYSTORE_PROFILE)
     #[arg(short, long, global = true)]
     profile: Option<String>,
 
+    /// Use a named vault of the profile (added with 'vault add') instead of its repository
+    #[arg(long, global = true)]
+    vault: Option<String>,
+
+    /// Do not print the banner or warnings and notices on stderr (set AXKEYSTORE_NO_BANNER=1,
+    /// or pipe stdout, to only hide the banner)
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Log GitHub requests and their timings to stderr (-vv for more detail). Tokens and
+    /// secrets are never logged.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
     /// Command to execute
     #[command(subcommand)]
     command: Option<Commands>,
@@ -34,762 +61,9212 @@ enum Commands {
         /// The name of the key
         #[arg(short, long)]
         key: String,
-        /// The value to store (if not provided, a random alphanumeric value will be generated)
-        #[arg(short, long)]
+        /// The value to store (visible in shell history; if omitted, you are prompted for it)
+        #[arg(long)]
         value: Option<String>,
+        /// Prompt for the value without echoing it (the default when --value is omitted)
+        #[arg(long, conflicts_with_all = ["value", "generate", "if_absent"])]
+        prompt: bool,
+        /// Generate a random value instead of prompting for one (alphanumeric, unless the
+        /// profile's generate-length or generate-charset setting says otherwise)
+        #[arg(short, long, conflicts_with = "value")]
+        generate: bool,
         /// Optional category path (e.g., 'api/production/internal')
-        #[arg(short, long)]
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
         category: Option<String>,
+        /// Only create the key if it does not exist yet; never prompts and never overwrites
+        #[arg(long)]
+        if_absent: bool,
+        /// Derive the value from the master key instead of storing it (rotate with 'rotate')
+        #[arg(long, conflicts_with_all = ["value", "if_absent", "prompt", "generate"])]
+        derived: bool,
+        /// Length of a derived value (6-64)
+        #[arg(long, requires = "derived", default_value_t = derived::DEFAULT_LENGTH)]
+        length: usize,
+        /// Store a TOTP seed (an otpauth://totp/ URI or a base32 secret) for use with 'totp'
+        #[arg(long, conflicts_with_all = ["value", "derived", "prompt", "generate"])]
+        totp: Option<String>,
+        /// Store the contents of a file (binary data is supported)
+        #[arg(short, long, conflicts_with_all = ["value", "derived", "totp", "prompt", "generate"])]
+        file: Option<std::path::PathBuf>,
+        /// Read the value from standard input, e.g. 'openssl rand -hex 32 | axkeystore store
+        /// --key session-secret --stdin' (one trailing newline is dropped)
+        #[arg(long, conflicts_with_all = ["value", "derived", "totp", "file", "prompt", "generate"])]
+        stdin: bool,
+        /// Keep the trailing newline of a value read with --stdin
+        #[arg(long, requires = "stdin")]
+        keep_newline: bool,
+        /// When the value expires: a duration from now ('90d') or a date ('2025-06-30')
+        #[arg(long)]
+        expires: Option<String>,
+        /// Delete the key with 'reap' after this long ('30d') or on this date ('2025-06-30')
+        #[arg(long)]
+        delete_after: Option<String>,
+        /// Tag the key (repeatable; replaces existing tags, '' clears them)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Describe what the key is for ('' clears it)
+        #[arg(long)]
+        description: Option<String>,
+        /// Person or team responsible for the key ('' clears it)
+        #[arg(long)]
+        owner: Option<String>,
+        /// Related URL, e.g. the provider's console ('' clears it)
+        #[arg(long)]
+        url: Option<String>,
     },
     /// Retrieve a stored value
     Get {
-        /// The name of the key to retrieve
-        #[arg(index = 1)]
+        /// The name of the key to retrieve, or a pattern matching several keys
+        /// (e.g. 'api/prod/tmp-*' or 'db/**')
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
         key: String,
         /// Optional category path (e.g., 'api/production/internal')
-        #[arg(short, long)]
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
         category: Option<String>,
-        /// Optional version (SHA) to retrieve
-        #[arg(short, long)]
+        /// Optional version (SHA, or a label set with 'tag set') to retrieve
+        #[arg(long)]
         version: Option<String>,
+        /// Warn if the latest commit touching the key is unsigned or signed by an unknown key
+        #[arg(long)]
+        verify: bool,
+        /// Create the key if it does not exist yet (requires --generate)
+        #[arg(long, requires = "generate", conflicts_with = "version")]
+        create_if_missing: bool,
+        /// Generate a random value when creating a missing key
+        #[arg(long, requires = "create_if_missing")]
+        generate: bool,
+        /// Write the value to a file instead of printing it (required for binary values)
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
+        /// Compare the value with another key ('category/name') without printing either
+        #[arg(long, value_name = "OTHER_KEY", conflicts_with_all = ["version", "out", "create_if_missing"])]
+        verify_against: Option<String>,
+        /// Look up the --verify-against key in the vault of this profile
+        #[arg(long, requires = "verify_against")]
+        against_profile: Option<String>,
+        /// Read an archived key without asking for confirmation
+        #[arg(long)]
+        archived: bool,
+        /// Print only the value: no banner and no trailing newline (the default when
+        /// stdout is not a terminal)
+        #[arg(long)]
+        raw: bool,
+        /// Print the value encoded, which is safe for binary values
+        #[arg(long, value_enum, conflicts_with = "verify_against")]
+        format: Option<pipe::ValueFormat>,
     },
     /// View the version history of a key
     History {
         /// The name of the key
-        #[arg(index = 1)]
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
         key: String,
         /// Optional category path
-        #[arg(short, long)]
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
         category: Option<String>,
+        /// Pick a version from the list and print, copy, or restore it
+        #[arg(short, long)]
+        interactive: bool,
+        /// Show the stored (encrypted) size of each version and how it changed
+        #[arg(long, conflicts_with = "interactive")]
+        stat: bool,
+        /// Only versions committed on or after this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only versions committed before this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        until: Option<String>,
+        /// Only versions made by this kind of operation
+        #[arg(long, value_enum)]
+        action: Option<changelog::Action>,
+        /// Show at most this many versions, without asking to show more
+        #[arg(short = 'n', long, conflicts_with = "interactive")]
+        limit: Option<usize>,
+        /// Print the versions as JSON, without asking to show more
+        #[arg(long, conflicts_with_all = ["interactive", "stat"])]
+        json: bool,
+    },
+    /// View the audit log of vault-wide operations, newest first
+    Log {
+        /// Only events of this action or its kinds (e.g. 'delete', or 'category' for
+        /// 'category-merge' and 'category-delete'); repeat for several
+        #[arg(long)]
+        action: Vec<String>,
+        /// Only events on or after this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only events before this date (YYYY-MM-DD or RFC 3339)
+        #[arg(long)]
+        until: Option<String>,
+        /// Print the events as JSON
+        #[arg(long)]
+        json: bool,
     },
     /// List all stored keys with their decrypted values, grouped by category
-    List,
+    List {
+        /// Only list key names, from the local index (nothing is decrypted or downloaded)
+        #[arg(long)]
+        names: bool,
+        /// Also list archived keys
+        #[arg(long)]
+        archived: bool,
+    },
+    /// Print the current TOTP code for a key stored with 'store --totp'
+    Totp {
+        /// The name of the key holding the TOTP seed
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// Rotate a derived key so it yields a new value
+    Rotate {
+        /// The name of the derived key
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// Check that every stored key is well-formed and decrypts with the master key
+    Verify {
+        /// Only check keys in this category (and its subcategories)
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+        /// Rehearse a disaster recovery instead: restore this backup file into memory and
+        /// read a sample of its keys back, without touching the repository
+        #[arg(long, value_name = "FILE")]
+        against_backup: Option<std::path::PathBuf>,
+        /// Number of keys to read back from the backup
+        #[arg(long, default_value_t = 20, requires = "against_backup")]
+        sample: usize,
+    },
     /// Initialize the AxKeyStore repository on GitHub
     Init {
-        /// Name of the repository to use
+        /// Name of the repository to use, or 'owner/name' for one owned by an organization
+        /// (e.g. 'myorg/axkeystore-storage'); it is created as a private repository if missing
         #[arg(short, long, default_value = "axkeystore-storage")]
         repo: String,
+        /// Keep the vault in a directory of the repository (e.g. 'secrets/vault'), so an
+        /// existing repository can host it alongside other content
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Keep the vault on this branch instead of the default branch (e.g. 'vault', which
+        /// can then be protected); it is created from the default branch if missing
+        #[arg(long)]
+        branch: Option<String>,
     },
     /// Delete a stored key
     Delete {
-        /// The name of the key to delete
-        #[arg(index = 1)]
+        /// The name of the key to delete, or a pattern matching several keys
+        /// (e.g. 'api/prod/tmp-*' or 'db/**')
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
         key: String,
         /// Optional category path (e.g., 'api/production/internal')
-        #[arg(short, long)]
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
         category: Option<String>,
     },
+    /// Move a key that must be kept but not used into the 'archive/' category, where
+    /// 'list' hides it and 'get' asks before reading it
+    Archive {
+        /// The name of the key to archive
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal'); with --restore, the
+        /// original category of the archived key
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+        /// Move an archived key back to its original category
+        #[arg(long)]
+        restore: bool,
+    },
+    /// Manage the additional vault repositories of the profile
+    Vault {
+        #[command(subcommand)]
+        command: VaultCommands,
+    },
     /// Manage profiles
     Profile {
         #[command(subcommand)]
         command: ProfileCommands,
     },
+    /// Show or change the defaults of the profile, e.g. the category used when --category is
+    /// left out
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
     /// Reset your master password
     ResetPassword,
+    /// Re-encrypt every key under a new master key (resumes an interrupted run)
+    Rekey,
+    /// Split the master key into shares for trusted people, and regain access with them
+    /// after forgetting the master password
+    Recovery {
+        #[command(subcommand)]
+        command: RecoveryCommands,
+    },
+    /// Regain access with the recovery code shown at 'init' after forgetting the master
+    /// password: log in to GitHub again and set a new master password
+    Recover {
+        /// The recovery code (prompted for without echo if omitted)
+        #[arg(long)]
+        code: Option<String>,
+        /// Repository of the vault ('owner/name', or 'name' for one of your own)
+        #[arg(long)]
+        repo: String,
+    },
+    /// Show or set how values are padded before encryption, so their stored size does not
+    /// reveal their length
+    Padding {
+        /// 'padme' (rounds sizes up by at most 12%) or 'bucket' (multiples of 256 bytes)
+        #[arg(index = 1, value_enum)]
+        scheme: Option<crypto::Padding>,
+        /// Stop padding values
+        #[arg(long, conflicts_with = "scheme")]
+        off: bool,
+    },
+    /// Show, turn on or turn off the profile's log file, which records every command's
+    /// GitHub requests for debugging intermittent failures
+    LogFile {
+        /// Start logging to the file
+        #[arg(long)]
+        on: bool,
+        /// Stop logging to the file
+        #[arg(long, conflicts_with = "on")]
+        off: bool,
+    },
+    /// Show or set how many GitHub requests bulk reads (list, export, verify) make at once
+    Parallelism {
+        /// Requests at once (1-32); lower it if GitHub reports secondary rate limits
+        #[arg(index = 1, value_parser = clap::value_parser!(u32).range(1..=storage::MAX_PARALLELISM as i64))]
+        limit: Option<u32>,
+        /// Go back to the default
+        #[arg(long, conflicts_with = "limit")]
+        reset: bool,
+    },
+    /// Show the active profile, GitHub account, vault repository and API host (no key is read)
+    Whoami,
+    /// Check the profile's setup end-to-end (configuration, token, GitHub access, repository,
+    /// master key, rate limit and clock) and suggest fixes
+    #[command(alias = "status")]
+    Doctor,
+    /// Run a store, get, history, delete and rekey cycle against a throwaway repository,
+    /// which is created for the test and deleted afterwards (needs the 'delete_repo' scope)
+    #[cfg(feature = "e2e")]
+    E2e {
+        /// Name of the throwaway repository; must start with 'axkeystore-e2e-' (default: a
+        /// random one)
+        #[arg(long)]
+        repo: Option<String>,
+        /// Leave the repository in place after the test, for inspection
+        #[arg(long)]
+        keep: bool,
+    },
+    /// Bind the profile's GitHub token to this machine, so a copied configuration directory
+    /// cannot be unlocked elsewhere, even with the master password
+    Device {
+        #[command(subcommand)]
+        command: DeviceCommands,
+    },
+    /// Inspect and control what is cached locally for the profile
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Share the vault with teammates using their public keys
+    Recipient {
+        #[command(subcommand)]
+        command: RecipientCommands,
+    },
+    /// Configure commit signing and signature verification
+    Signing {
+        #[command(subcommand)]
+        command: SigningCommands,
+    },
+    /// Route notifications about key changes to webhooks by category
+    Notify {
+        #[command(subcommand)]
+        command: NotifyCommands,
+    },
+    /// Take encrypted backups of the keys and check them against the live vault
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommands,
+    },
+    /// Rebuild a vault from a backup (keys, metadata and master key), e.g. after losing
+    /// the repository
+    Restore {
+        /// Backup file written by 'backup create'
+        #[arg(index = 1)]
+        file: std::path::PathBuf,
+        /// Restore into this repository ('owner/name', or 'name' for one of your own)
+        /// instead of the profile's, creating it if needed
+        #[arg(long)]
+        repo: Option<String>,
+    },
+    /// Copy the vault's repository (still encrypted) to a second repository, possibly on
+    /// GitLab, and keep it in sync on demand or after every change. Without options, pushes
+    /// to the mirror set up before.
+    Mirror {
+        /// Repository to mirror to ('owner/name', or 'name' for one of your own on GitHub)
+        #[arg(long, value_name = "REPO")]
+        to: Option<String>,
+        /// Hosting service of the mirror (default: github)
+        #[arg(long, value_enum, requires = "to")]
+        provider: Option<mirror::Provider>,
+        /// Host of a self-managed GitLab or GitHub Enterprise instance
+        #[arg(long, requires = "to")]
+        host: Option<String>,
+        /// Push to the mirror after every change to keys, not only when running 'mirror'
+        #[arg(long, requires = "to")]
+        auto: bool,
+        /// Stop mirroring (the mirror repository is left as it is)
+        #[arg(long, conflicts_with = "to")]
+        off: bool,
+    },
+    /// Move the vault to a new repository, with its whole history, and use it from then on
+    /// (e.g. after renaming the repository or moving it to an organization)
+    Migrate {
+        /// New repository ('owner/name', or 'name' for one of your own); created if missing,
+        /// and it must be empty
+        #[arg(long, value_name = "REPO")]
+        to_repo: String,
+        /// Archive the old repository (read-only) once the vault was moved
+        #[arg(long)]
+        archive_old: bool,
+    },
+    /// Store values shared by several keys once, and find the keys that share a value
+    Dedup {
+        #[command(subcommand)]
+        command: DedupCommands,
+    },
+    /// Give headless consumers (e.g. CI) expiring, read-only access to some categories
+    ServiceAccount {
+        #[command(subcommand)]
+        command: ServiceAccountCommands,
+    },
+    /// Show a key's type and a summary of its change history (never its value)
+    Info {
+        /// The key name
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// Manage the local key index used by 'list --names', 'search' and shell completion
+    Index {
+        #[command(subcommand)]
+        command: IndexCommands,
+    },
+    /// Find keys by name, category, tag or description (values are never searched)
+    Search {
+        /// Text to look for (case-insensitive)
+        #[arg(index = 1)]
+        query: String,
+        /// Treat the query as a regular expression
+        #[arg(long)]
+        regex: bool,
+        /// Only search this category and its subcategories
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+        /// Refresh the local key index from the repository first
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Show a key's description, tags, owner and other metadata (never its value)
+    Show {
+        /// The key name
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// Copy a key's value to another key path, or every key of a category to another category
+    /// (with --from/--to); values promoted into production categories ('prod/**') must pass
+    /// the validation policy
+    #[command(alias = "copy")]
+    Promote {
+        /// Key path to copy from ('category/name')
+        #[arg(
+            index = 1,
+            required_unless_present = "from_category",
+            conflicts_with = "from_category",
+            add = ArgValueCandidates::new(completion::key_candidates)
+        )]
+        from: Option<String>,
+        /// Key path to copy to ('category/name')
+        #[arg(index = 2, required_unless_present = "from_category")]
+        to: Option<String>,
+        /// Category to copy every key from
+        #[arg(long = "from", add = ArgValueCandidates::new(completion::category_candidates))]
+        from_category: Option<String>,
+        /// Category to copy the keys into (defaults to the same category with --to-profile)
+        #[arg(long = "to", requires = "from_category")]
+        to_category: Option<String>,
+        /// Only copy these keys (paths relative to --from), comma-separated
+        #[arg(long, requires = "from_category", value_delimiter = ',')]
+        keys: Vec<String>,
+        /// Copy from the vault of this profile instead of the current one
+        #[arg(long)]
+        from_profile: Option<String>,
+        /// Copy into the vault of another profile, re-encrypting with its master key
+        #[arg(long)]
+        to_profile: Option<String>,
+        /// Show what would change without copying anything
+        #[arg(long, requires = "from_category")]
+        dry_run: bool,
+    },
+    /// Bring the vault in line with a YAML manifest declaring keys, categories, generation
+    /// policies and expiry: show the plan of keys to create, update and delete, then apply it
+    /// in a single commit
+    Apply {
+        /// The manifest (e.g. 'vault.yaml')
+        #[arg(index = 1)]
+        manifest: std::path::PathBuf,
+        /// Show the plan without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Apply the plan without asking for confirmation
+        #[arg(long, conflicts_with = "dry_run")]
+        auto_approve: bool,
+    },
+    /// Show how the vault differs from a manifest (missing, changed, extra and expired keys)
+    /// without printing any value. Variables named by 'env' entries are compared only when
+    /// they are set.
+    Plan {
+        /// The manifest (e.g. 'vault.yaml')
+        #[arg(index = 1)]
+        manifest: std::path::PathBuf,
+        /// Exit with status 1 when the vault differs from the manifest, for CI
+        #[arg(long)]
+        check: bool,
+    },
+    /// Edit a key's value in $EDITOR (useful for multi-line values such as PEM keys or JSON)
+    Edit {
+        /// The key name to edit
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// Import environment variables of a running process or of this shell into a category, or
+    /// migrate the secrets of a password manager
+    Import {
+        /// Read the environment of a running process (Linux, via /proc/<pid>/environ)
+        #[arg(long, conflicts_with_all = ["from_env", "from"])]
+        from_pid: Option<u32>,
+        /// Read the environment of the current shell
+        #[arg(long, conflicts_with = "from")]
+        from_env: bool,
+        /// Read the secrets of a password manager, mapping its folders to categories
+        #[arg(long, value_enum, requires = "source")]
+        from: Option<pmimport::Source>,
+        /// The password store directory or export file to read with --from
+        #[arg(index = 1, requires = "from")]
+        source: Option<std::path::PathBuf>,
+        /// Import variables starting with this prefix, stored without it (e.g. 'APP_')
+        #[arg(long, conflicts_with = "from")]
+        prefix: Option<String>,
+        /// Import this variable under its own name (repeatable)
+        #[arg(long = "var", conflicts_with = "from")]
+        vars: Vec<String>,
+        /// Category to store the variables in (with --from, the category the password
+        /// manager's folders are created under)
+        #[arg(short, long, required_unless_present = "from")]
+        category: Option<String>,
+        /// Overwrite keys that already exist (they are skipped otherwise)
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Keep the local index warm in the foreground, polling GitHub for changes (Ctrl+C to stop)
+    Agent {
+        /// How often to check the repository for changes (e.g. '30s', '5m')
+        #[arg(long, default_value = "5m")]
+        interval: String,
+        /// Also delete keys past their deletion deadline (checked hourly)
+        #[arg(long)]
+        reap: bool,
+    },
+    /// Permanently remove a key from the repository's entire history (rewrites the branch)
+    Purge {
+        /// The key to purge
+        #[arg(index = 1)]
+        key: String,
+        /// Category of the key (e.g. 'api/prod')
+        #[arg(short, long)]
+        category: Option<String>,
+        /// Confirm the history rewrite (required)
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Set how much key history to keep, for the vault or per category
+    Retention {
+        #[command(subcommand)]
+        command: RetentionCommands,
+    },
+    /// Squash the commits older than the retention rules require into one (rewrites the branch)
+    PruneHistory {
+        /// Only show what would be squashed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Delete keys past their deletion deadline ('store --delete-after')
+    Reap {
+        /// Only list the keys that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List, restore or purge deleted keys (deleting a key moves it to the trash)
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+    /// Serve keys over a local HTTP API for tools that cannot run the CLI (Ctrl+C to stop)
+    Serve {
+        /// Address to listen on; keep it on loopback, since traffic is not encrypted
+        #[arg(long, default_value = "127.0.0.1:8200")]
+        listen: String,
+        /// Only allow reading keys (PUT requests are rejected). This is the default unless
+        /// --allow-write is given.
+        #[arg(long, conflicts_with = "allow_write")]
+        read_only: bool,
+        /// Also accept PUT requests that store keys (the server is read-only otherwise)
+        #[arg(long)]
+        allow_write: bool,
+        /// File holding the API token; created with a new token if it does not exist
+        #[arg(long)]
+        token_file: Option<std::path::PathBuf>,
+    },
+    /// List keys that are expired or expire soon (exits non-zero if there are any)
+    Expiring {
+        /// Report keys that expire within this long from now
+        #[arg(long, default_value = "30d")]
+        within: String,
+    },
+    /// Review the security of the vault setup and print prioritized fixes (changes nothing)
+    Posture {
+        /// Keys not changed for longer than this are reported as due for rotation
+        #[arg(long, default_value = "180d")]
+        max_age: String,
+    },
+    /// Push the values of a category's keys to the GitHub Actions secrets of a repository
+    SyncGha {
+        /// Repository whose Actions secrets are written ('owner/name')
+        #[arg(long)]
+        repo: String,
+        /// Category whose keys are pushed; secret names are the key paths relative to it
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: String,
+        /// Only push these keys, by path relative to the category (repeatable)
+        #[arg(long = "key")]
+        keys: Vec<String>,
+        /// Show which secrets would be written without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export a catalog of keys, or the values as Docker secrets
+    Export {
+        /// Only export keys whose path matches this pattern (e.g. 'db/**')
+        #[arg(index = 1)]
+        pattern: Option<String>,
+        /// Export only non-secret metadata (paths, types, last change), never values
+        /// (required for the json and csv formats)
+        #[arg(long)]
+        metadata_only: bool,
+        /// Output format ('docker-secrets' and 'pass' write one file per value into the --out
+        /// directory) [default: the profile's export-format setting, or json]
+        #[arg(short, long, value_enum)]
+        format: Option<catalog::ExportFormat>,
+        /// Only export keys in this category (and its subcategories)
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+        /// Write to a file instead of stdout (a directory for docker-secrets)
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
+        /// Remove the docker-secrets files again after this long (e.g. '10m')
+        #[arg(long)]
+        ttl: Option<String>,
+        /// GPG key to encrypt the entries of a pass export to (repeatable)
+        #[arg(long = "gpg-id")]
+        gpg_ids: Vec<String>,
+    },
+    /// Remove the files of a docker-secrets export (started by 'export --ttl')
+    #[command(hide = true)]
+    CleanSecrets {
+        dir: std::path::PathBuf,
+        /// Wait this many seconds first
+        #[arg(long, default_value_t = 0)]
+        after: u64,
+    },
+    /// Summarize the secret changes since a release, for attaching to release notes
+    Changelog {
+        /// Tag, branch or commit SHA of the previous release, or a date (YYYY-MM-DD)
+        #[arg(long)]
+        since: String,
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "markdown")]
+        format: changelog::ChangelogFormat,
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Claim a key name before its value exists, so teammates do not create it in parallel
+    Reserve {
+        /// The name of the key to reserve
+        #[arg(index = 1)]
+        key: String,
+        /// Optional category path (e.g., 'team/shared')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// Manage files attached to a key (e.g. a CA certificate next to a database password)
+    Attach {
+        #[command(subcommand)]
+        command: AttachCommands,
+    },
+    /// Label versions of a key (e.g. 'v2024-10') to retrieve them with 'get --version <label>'
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+    /// Edit the metadata of many keys at once
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommands,
+    },
+    /// Reorganize categories
+    Category {
+        #[command(subcommand)]
+        command: CategoryCommands,
+    },
+    /// Resolve secrets for dotfile managers such as chezmoi: prints the values of the given
+    /// key paths, or answers one key path per line on stdin with its value as a JSON string
+    TemplateFn {
+        /// Key paths to print ('category/name'); read them from stdin if omitted
+        keys: Vec<String>,
+    },
+    /// Fill a template's `{{ key "category/name" }}` placeholders with decrypted values
+    Render {
+        /// Template file to render
+        #[arg(index = 1)]
+        template: std::path::PathBuf,
+        /// Write the result to this file (readable only by you) instead of stdout
+        #[arg(short, long, conflicts_with = "in_place")]
+        out: Option<std::path::PathBuf>,
+        /// Replace the template file with the rendered result
+        #[arg(long)]
+        in_place: bool,
+    },
+    /// Print the keys of a category as shell export statements
+    /// (e.g. 'eval "$(axkeystore env --category app/dev)"')
+    Env {
+        /// The category to export; variables are named after the key paths inside it
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: String,
+        /// The shell to print statements for
+        #[arg(long, value_enum, default_value_t = shellenv::Shell::Sh)]
+        shell: shellenv::Shell,
+    },
+    /// Run a command with a key's value on its stdin, never in its arguments or environment
+    /// (e.g. 'axkeystore pipe db/password -- psql ...', 'axkeystore pipe api/curlrc -- curl --config -')
+    Pipe {
+        /// Key path ('category/name')
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Write a newline after the value, for commands that read a line
+        #[arg(long)]
+        newline: bool,
+        /// The command to run and its arguments, after '--'
+        #[arg(index = 2, last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Provide the secrets declared in a manifest to a Docker Compose project
+    Compose {
+        #[command(subcommand)]
+        command: ComposeCommands,
+    },
+    /// Print the shell completion script (e.g. 'source <(axkeystore completions bash)')
+    Completions {
+        /// The shell to complete in
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Browse categories and keys interactively: search, view metadata and history, and
+    /// copy, edit or delete keys after a single unlock (also the default without a command)
+    Tui,
+    /// List the plugins found on PATH (executables named 'axkeystore-<name>'), or change
+    /// which of them may store keys
+    Plugins {
+        /// Let this plugin store keys (plugins can only read keys otherwise)
+        #[arg(long, value_name = "NAME", conflicts_with = "deny_write")]
+        allow_write: Option<String>,
+        /// Take back a plugin's permission to store keys
+        #[arg(long, value_name = "NAME")]
+        deny_write: Option<String>,
+    },
+    /// Assign sandboxed WASM value generators and validation policies to categories
+    Wasm {
+        #[command(subcommand)]
+        command: WasmCommands,
+    },
+    // Any other command runs the 'axkeystore-<command>' plugin
+    #[command(external_subcommand)]
+    Plugin(Vec<String>),
 }
 
-/// Profile management subcommands
+/// Key index subcommands
 #[derive(Subcommand)]
-enum ProfileCommands {
-    /// List all profiles
-    List,
-    /// Switch to a specific profile
-    Switch {
-        /// The name of the profile to switch to (omit to switch to default root)
+enum IndexCommands {
+    /// Rebuild the index from the repository
+    Rebuild,
+    /// Complete key names in the shell (keeps a plain-text list of key paths, no metadata)
+    EnableCompletion,
+    /// Stop completing key names and remove the plain-text list of key paths
+    DisableCompletion,
+}
+
+/// Attachment subcommands
+#[derive(Subcommand)]
+enum AttachCommands {
+    /// Attach a file to a key
+    Add {
+        /// The key to attach the file to
         #[arg(index = 1)]
+        key: String,
+        /// The file to attach
+        #[arg(index = 2)]
+        file: std::path::PathBuf,
+        /// Name of the attachment (defaults to the file name)
+        #[arg(short, long)]
         name: Option<String>,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long)]
+        category: Option<String>,
     },
-    /// Delete a profile
-    Delete {
-        /// The name of the profile to delete
+    /// Retrieve an attachment
+    Get {
+        /// The key the file is attached to
         #[arg(index = 1)]
+        key: String,
+        /// Name of the attachment
+        #[arg(index = 2)]
         name: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long)]
+        category: Option<String>,
+        /// Write the attachment to a file instead of printing it (required for binary files)
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
     },
-    /// Show current profile
-    Current,
-    /// Create a new profile
-    Create {
-        /// The name of the profile to create
+    /// List the files attached to a key
+    List {
+        /// The key
+        #[arg(index = 1)]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long)]
+        category: Option<String>,
+    },
+    /// Remove an attachment
+    Remove {
+        /// The key the file is attached to
         #[arg(index = 1)]
+        key: String,
+        /// Name of the attachment
+        #[arg(index = 2)]
         name: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long)]
+        category: Option<String>,
     },
 }
 
-/// Prompts the user for a password via stdin without echo
-fn prompt_password(message: &str) -> Result<String> {
-    print!("{}: ", message);
-    std::io::stdout().flush()?;
-    rpassword::read_password().context("Failed to read password")
+/// Version label subcommands
+#[derive(Subcommand)]
+enum TagCommands {
+    /// Label a version of a key (the latest one unless --version is given)
+    Set {
+        /// The key
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// The label (e.g. 'v2024-10')
+        #[arg(index = 2)]
+        label: String,
+        /// Version (SHA, or another label) to label
+        #[arg(long)]
+        version: Option<String>,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// List the version labels of a key
+    List {
+        /// The key
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+    /// Remove a version label (the version itself is kept)
+    Remove {
+        /// The key
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// The label
+        #[arg(index = 2)]
+        label: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
 }
 
-/// Retrieves the master key from GitHub or initializes it if it doesn't exist
-async fn get_or_init_master_key(storage: &storage::Storage, password: &str) -> Result<String> {
-    match storage.get_master_key_blob().await? {
-        Some(data) => {
-            // Master key exists, try to decrypt it with the provided password
-            let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&data)
-                .context("Failed to parse master key blob from GitHub")?;
+/// Bulk metadata subcommands
+#[derive(Subcommand)]
+enum MetaCommands {
+    /// Change the tags and fields of every key in matching categories, in a single commit
+    /// (e.g. 'meta edit --category "api/**" --add-tag legacy --set owner=platform-team')
+    Edit {
+        /// Categories whose keys are edited; '*' and '**' match like in key patterns, so
+        /// 'api/**' covers 'api' and all of its subcategories
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: String,
+        /// Add a tag (repeatable)
+        #[arg(long, value_name = "TAG")]
+        add_tag: Vec<String>,
+        /// Remove a tag (repeatable)
+        #[arg(long, value_name = "TAG")]
+        remove_tag: Vec<String>,
+        /// Set a field: description, owner or url ('field=' clears it; repeatable)
+        #[arg(long, value_name = "FIELD=VALUE")]
+        set: Vec<String>,
+    },
+}
 
-            match crypto::CryptoHandler::decrypt(&encrypted, password) {
-                Ok(decrypted) => {
-                    return String::from_utf8(decrypted).context("Master key is not valid UTF-8");
-                }
-                Err(_) => {
-                    return Err(anyhow::anyhow!(
-                        "Incorrect master password. Please verify your credentials."
-                    ));
-                }
-            }
-        }
-        None => {
-            // Master key doesn't exist, we use the provided password to initialize it
-            let master_key = crypto::CryptoHandler::generate_master_key();
-            let encrypted = crypto::CryptoHandler::encrypt(master_key.as_bytes(), password)?;
-            let json_blob = serde_json::to_vec(&encrypted)?;
+/// Category management subcommands
+#[derive(Subcommand)]
+enum CategoryCommands {
+    /// List every category with the number of keys it holds
+    List,
+    /// Rename a category, moving every key (and subcategory) under it in a single commit
+    Rename {
+        /// Category to rename (e.g. 'api/stage')
+        #[arg(index = 1)]
+        from: String,
+        /// New name of the category (e.g. 'api/staging'); it must not exist yet
+        #[arg(index = 2)]
+        to: String,
+    },
+    /// Delete a category with every key (and subcategory) under it, in a single commit
+    Delete {
+        /// Category to delete (e.g. 'api/old')
+        #[arg(index = 1)]
+        category: String,
+    },
+    /// Move every key (and subcategory) of one category into another, in a single commit
+    Merge {
+        /// Category to empty (e.g. 'api/production')
+        #[arg(index = 1)]
+        from: String,
+        /// Category to move the keys into (e.g. 'api/prod')
+        #[arg(index = 2)]
+        into: String,
+    },
+}
 
-            storage.save_master_key_blob(&json_blob).await?;
-            println!("Master key initialized and saved to GitHub.");
-            Ok(master_key)
-        }
-    }
+/// Trash subcommands
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List the deleted keys, most recently deleted first
+    List,
+    /// Put deleted keys back in place (the most recently deleted version of each)
+    Restore {
+        /// Key path or wildcard pattern of the keys to restore (e.g. 'api/prod/*')
+        #[arg(index = 1)]
+        pattern: String,
+    },
+    /// Purge every deleted key for good, with its attachments
+    Empty,
+    /// Set how long deleted keys are kept before being purged (e.g. '30d', or 'never')
+    PurgeAfter {
+        /// Age after which deleted keys are purged
+        #[arg(index = 1)]
+        age: String,
+    },
 }
 
-/// Prompts the user for a yes/no confirmation via stdin
-fn prompt_yes_no(message: &str) -> Result<bool> {
-    print!("{} (y/n): ", message);
-    std::io::stdout().flush()?;
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input)?;
-    let input = input.trim().to_lowercase();
-    Ok(input == "y" || input == "yes")
+/// History retention subcommands
+#[derive(Subcommand)]
+enum RetentionCommands {
+    /// Show the retention rules
+    Show,
+    /// Set the retention rule of the vault, or of a category and its subcategories
+    Set {
+        /// Category the rule applies to (the whole vault if omitted)
+        #[arg(short, long)]
+        category: Option<String>,
+        /// Number of latest versions of each key to keep
+        #[arg(long, required_unless_present = "max_age")]
+        versions: Option<usize>,
+        /// Age of the history to keep (e.g. '180d')
+        #[arg(long)]
+        max_age: Option<String>,
+    },
+    /// Remove the retention rule of the vault or of a category
+    Unset {
+        /// Category whose rule to remove (the vault's rule if omitted)
+        #[arg(short, long)]
+        category: Option<String>,
+    },
 }
 
-/// Generate a random alphanumeric string with length between 6 and 36 characters
-fn generate_random_alphanumeric() -> String {
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-    let mut rng = rand::rng();
-    let length = rng.random_range(6..=36);
+/// WASM plugin subcommands
+#[derive(Subcommand)]
+enum WasmCommands {
+    /// List the modules in the plugins directory and the categories they are assigned to
+    List,
+    /// Use modules of the plugins directory for a category and its subcategories
+    Set {
+        /// Category the modules apply to (e.g. 'prod')
+        #[arg(index = 1)]
+        category: String,
+        /// Module that generates values for 'store --generate' and 'get --create-if-missing'
+        #[arg(long, required_unless_present = "policy")]
+        generator: Option<String>,
+        /// Module that validates values before they are stored
+        #[arg(long)]
+        policy: Option<String>,
+    },
+    /// Stop using WASM modules for a category
+    Unset {
+        /// Category to clear
+        #[arg(index = 1)]
+        category: String,
+    },
+}
 
-    (0..length)
-        .map(|_| {
-            let idx = rng.random_range(0..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect()
+/// Device binding subcommands
+#[derive(Subcommand)]
+enum DeviceCommands {
+    /// Bind the token to this machine (re-encrypts a saved token)
+    Bind,
+    /// Release the token from this machine
+    Unbind,
+    /// Show whether the token is bound to a machine
+    Status,
 }
 
-/// Displays the AxKeyStore application banner
-fn display_banner() {
-    // ANSI color codes
-    const CYAN: &str = "\x1b[36m";
-    const GREEN: &str = "\x1b[32m";
-    const MAGENTA: &str = "\x1b[35m";
-    const RESET: &str = "\x1b[0m";
-    const BOLD: &str = "\x1b[1m";
-    const DIM: &str = "\x1b[2m";
+/// Docker Compose subcommands
+#[derive(Subcommand)]
+enum ComposeCommands {
+    /// Run a command with the manifest's secrets as files in $AXKEYSTORE_SECRETS_DIR and its
+    /// variables in the environment, removing the files when it exits
+    /// (e.g. 'axkeystore compose up -- docker compose up')
+    Up {
+        /// Manifest declaring the secrets ([secrets] name = "key/path") and variables
+        /// ([env] NAME = "key/path")
+        #[arg(short, long, default_value = compose::DEFAULT_MANIFEST)]
+        manifest: std::path::PathBuf,
+        /// The command to run and its arguments, after '--'
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+}
 
-    println!();
-    println!("{CYAN}{BOLD}  ╠═══════════════════════════════════════════════════════════════════╣{RESET}");
-    println!(
-        "{CYAN}{BOLD}  {RESET}  {GREEN}★{RESET} {BOLD}AxKeyStore{RESET} is an {MAGENTA}Open Source Project{RESET} built by {BOLD}Appxiom Team{RESET}"
-    );
-    println!(
-        "{CYAN}{BOLD}  {RESET}                                                                   {RESET}"
-    );
-    println!(
-        "{CYAN}{BOLD}  {RESET}  {DIM}Visit{RESET} {CYAN}{BOLD}https://www.appxiom.com{RESET} {DIM}to know more about us.{RESET}"
-    );
+/// Commit signing subcommands
+#[derive(Subcommand)]
+enum SigningCommands {
+    /// Sign the commits AxKeyStore creates with a GPG or SSH key
+    Enable {
+        /// Signature format
+        #[arg(short, long, value_enum)]
+        format: config::SignatureFormat,
+        /// GPG key ID, or path to the SSH private key
+        #[arg(short, long)]
+        key: String,
+        /// Author name recorded on signed commits (defaults to the repository owner)
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Author email recorded on signed commits (must belong to the signing key on GitHub)
+        #[arg(short, long)]
+        email: String,
+    },
+    /// Stop signing commits
+    Disable,
+    /// Trust verified signatures made by a committer email
+    Trust {
+        /// Committer email to trust
+        #[arg(index = 1)]
+        email: String,
+    },
+    /// Stop trusting a committer email
+    Untrust {
+        /// Committer email to remove
+        #[arg(index = 1)]
+        email: String,
+    },
+    /// Turn signature verification on every 'get' on or off
+    Verify {
+        /// 'on' or 'off'
+        #[arg(index = 1, action = clap::ArgAction::Set, value_parser = clap::builder::BoolishValueParser::new())]
+        enabled: bool,
+    },
+    /// Show the signing configuration
+    Status,
+}
+
+/// Recipient management subcommands
+#[derive(Subcommand)]
+enum RecipientCommands {
+    /// Print this profile's public key so a vault owner can add you as a recipient
+    Key,
+    /// Grant a teammate access by wrapping the master key (or a category's data key) for
+    /// their public key
+    Add {
+        /// Name to identify the recipient
+        #[arg(index = 1)]
+        name: String,
+        /// The recipient's public key (from 'axkeystore recipient key')
+        #[arg(index = 2)]
+        public_key: String,
+        /// Share only the keys of this top-level category (e.g. 'team'), instead of the
+        /// whole vault
+        #[arg(short, long)]
+        category: Option<String>,
+    },
+    /// Revoke a teammate's wrapped copy of the master key (or of a category's data key)
+    Remove {
+        /// Name of the recipient to remove
+        #[arg(index = 1)]
+        name: String,
+        /// Remove the recipient from this shared category instead of the whole vault
+        #[arg(short, long)]
+        category: Option<String>,
+    },
+    /// List recipients who can unlock the vault
+    List,
+}
+
+/// Notification routing subcommands
+#[derive(Subcommand)]
+enum NotifyCommands {
+    /// Send changes to keys matching a pattern (e.g. 'prod/**') to a webhook
+    Route {
+        /// Key path pattern; '*' matches one segment and '**' any number of them
+        #[arg(index = 1)]
+        pattern: String,
+        /// Incoming webhook URL
+        #[arg(long)]
+        webhook: String,
+        /// Channel to post to, for webhooks that allow overriding it
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Stop sending changes for a pattern
+    Unroute {
+        /// Pattern of the route to remove
+        #[arg(index = 1)]
+        pattern: String,
+    },
+    /// List routes in the order they are checked (webhook URLs are shortened)
+    List,
+    /// Show which route a key path uses and send it a test message
+    Test {
+        /// Key path to test ('category/name')
+        #[arg(index = 1)]
+        path: String,
+    },
+}
+
+/// Backup subcommands
+#[derive(Subcommand)]
+enum BackupCommands {
+    /// Save every key blob, the vault's metadata and its master key to a backup file
+    /// encrypted with the master password
+    Create {
+        /// File to write the backup to
+        #[arg(index = 1)]
+        file: std::path::PathBuf,
+    },
+    /// Compare a backup with the live vault and report keys that drifted
+    Diff {
+        /// Backup file to compare
+        #[arg(index = 1)]
+        file: std::path::PathBuf,
+    },
+}
+
+/// Deduplication subcommands
+#[derive(Subcommand)]
+enum DedupCommands {
+    /// Store each value held by several keys once, with the keys pointing to it
+    Apply {
+        /// Show the keys that share values without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List the other keys that hold the same value as a key
+    Where {
+        /// The key name
+        #[arg(index = 1, add = ArgValueCandidates::new(completion::key_candidates))]
+        key: String,
+        /// Optional category path (e.g., 'api/production/internal')
+        #[arg(short, long, add = ArgValueCandidates::new(completion::category_candidates))]
+        category: Option<String>,
+    },
+}
+
+/// Service account subcommands
+#[derive(Subcommand)]
+enum ServiceAccountCommands {
+    /// Create a service account and write its password-protected config bundle
+    Create {
+        /// Name of the service account
+        #[arg(index = 1)]
+        name: String,
+        /// Key path patterns the account can read (e.g. 'app/ci/**'), comma-separated
+        #[arg(long, required = true, value_delimiter = ',')]
+        categories: Vec<String>,
+        /// How long the account works (e.g. '90d', '12h')
+        #[arg(long, default_value = "90d")]
+        ttl: String,
+        /// File to write the bundle to (defaults to '<name>.axbundle')
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
+        /// File holding the GitHub token to put in the bundle (defaults to your own token)
+        #[arg(long)]
+        token_file: Option<std::path::PathBuf>,
+    },
+    /// List service accounts
+    List,
+    /// Update the keys service accounts can read with the current values
+    Refresh,
+    /// Revoke a service account, so its bundle can no longer read keys
+    Revoke {
+        /// Name of the service account
+        #[arg(index = 1)]
+        name: String,
+    },
+    /// Print a key's value using a bundle instead of the master password
+    /// (the bundle password is read from AXKEYSTORE_BUNDLE_PASSWORD if set)
+    Get {
+        /// Key path to print ('category/name')
+        #[arg(index = 1)]
+        key: String,
+        /// The service account bundle
+        #[arg(long)]
+        bundle: std::path::PathBuf,
+    },
+}
+
+/// Cache management subcommands
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Show the cache policy and what is currently cached
+    Status,
+    /// Remove cached entries
+    Clear {
+        /// Only clear entries of this kind
+        #[arg(short, long, value_enum)]
+        kind: Option<cache::CacheKind>,
+    },
+    /// Set how long a kind of material may be cached (e.g. '15m', '1h', or '0' to disable)
+    Set {
+        /// The kind of material
+        #[arg(index = 1, value_enum)]
+        kind: cache::CacheKind,
+        /// Maximum age of cached entries
+        #[arg(index = 2)]
+        ttl: String,
+    },
+}
+
+/// Named vault subcommands
+#[derive(Subcommand)]
+enum VaultCommands {
+    /// Add a vault to the profile, creating its repository if missing
+    Add {
+        /// Name used with '--vault' (e.g. 'infra')
+        #[arg(index = 1)]
+        name: String,
+        /// Repository of the vault ('name', or 'owner/name')
+        #[arg(index = 2)]
+        repo: String,
+    },
+    /// List the vaults of the profile
+    List,
+    /// Remove a vault from the profile (its repository and secrets are left untouched)
+    Remove {
+        /// Name of the vault
+        #[arg(index = 1)]
+        name: String,
+    },
+}
+
+/// Master key recovery subcommands
+#[derive(Subcommand)]
+enum RecoveryCommands {
+    /// Split the master key into shares, any THRESHOLD of which rebuild it. The shares are
+    /// shown once and never stored.
+    Setup {
+        /// Number of shares to make
+        #[arg(long, default_value_t = 5)]
+        shares: u8,
+        /// Number of shares needed to rebuild the master key
+        #[arg(long, default_value_t = 3)]
+        threshold: u8,
+        /// Write each share to its own file in this directory instead of printing them
+        #[arg(long, value_name = "DIR")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Rebuild the master key from shares after forgetting the master password, log in to
+    /// GitHub again and set a new master password
+    Restore {
+        /// Repository of the vault ('owner/name', or 'name' for one of your own)
+        #[arg(long)]
+        repo: String,
+    },
+    /// Make a new recovery code for the vault, replacing the one shown at 'init'
+    Code,
+}
+
+/// Profile management subcommands
+#[derive(Subcommand)]
+enum ProfileCommands {
+    /// List all profiles
+    List,
+    /// Switch to a specific profile
+    Switch {
+        /// The name of the profile to switch to (omit to switch to default root)
+        #[arg(index = 1)]
+        name: Option<String>,
+    },
+    /// Delete a profile
+    Delete {
+        /// The name of the profile to delete
+        #[arg(index = 1)]
+        name: String,
+    },
+    /// Show current profile
+    Current,
+    /// Create a new profile
+    Create {
+        /// The name of the profile to create
+        #[arg(index = 1)]
+        name: String,
+    },
+    /// Copy a profile's settings and login to a new profile with the same master password
+    Clone {
+        /// The profile to copy ('default' for the root profile)
+        #[arg(index = 1)]
+        src: String,
+        /// The name of the new profile
+        #[arg(index = 2)]
+        dst: String,
+    },
+    /// Rename a profile
+    Rename {
+        /// The current name of the profile
+        #[arg(index = 1)]
+        old: String,
+        /// The new name of the profile
+        #[arg(index = 2)]
+        new: String,
+    },
+    /// Pack a profile's settings and login into a password-protected bundle, to set it up on
+    /// another machine with 'profile import'
+    Export {
+        /// The profile to export ('default' for the root profile)
+        #[arg(index = 1)]
+        name: String,
+        /// File to write the bundle to (default: '<name>.axp')
+        #[arg(long)]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Set up a profile from a bundle written by 'profile export'
+    Import {
+        /// The bundle file
+        #[arg(index = 1)]
+        file: std::path::PathBuf,
+        /// Name of the new profile (default: the exported profile's name)
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+/// Per-profile settings subcommands
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Change a setting of the profile
+    Set {
+        /// The setting (see 'config list')
+        #[arg(index = 1, value_parser = clap::builder::PossibleValuesParser::new(settings::KEYS.map(|(key, _)| key)))]
+        key: String,
+        /// The new value
+        #[arg(index = 2)]
+        value: String,
+    },
+    /// Print the value of a setting
+    Get {
+        /// The setting (see 'config list')
+        #[arg(index = 1, value_parser = clap::builder::PossibleValuesParser::new(settings::KEYS.map(|(key, _)| key)))]
+        key: String,
+    },
+    /// Clear a setting, so the command's own default applies again
+    Unset {
+        /// The setting (see 'config list')
+        #[arg(index = 1, value_parser = clap::builder::PossibleValuesParser::new(settings::KEYS.map(|(key, _)| key)))]
+        key: String,
+    },
+    /// List the settings with their values
+    List,
+}
+
+/// Prompts the user for a password via stdin without echo
+fn prompt_password(message: &str) -> Result<String> {
+    print!("{}: ", message);
+    std::io::stdout().flush()?;
+    let password = rpassword::read_password().context("Failed to read password")?;
+    redact::register(&password);
+    Ok(password)
+}
+
+/// Opens the storage repository configured for a profile
+async fn open_storage(profile: Option<&str>, password: &str) -> Result<storage::Storage> {
+    let repo_name = config::Config::get_repo_name_with_profile(profile, password)?;
+    storage::Storage::new_with_profile(profile, &repo_name, password).await
+}
+
+/// Retrieves the master key from GitHub or initializes it if it doesn't exist
+async fn get_or_init_master_key(storage: &storage::Storage, password: &str) -> Result<String> {
+    if let Some(master_key) = load_master_key(storage, password).await? {
+        return Ok(master_key);
+    }
+    // Master key doesn't exist, we use the provided password to initialize it
+    let master_key = init_master_key(storage, password).await?;
+    println!("Master key initialized and saved to GitHub.");
+    Ok(master_key)
+}
+
+/// Ensures the storage repository (and the vault branch, if any) exists, creating what is
+/// missing
+async fn init_repo(storage: &storage::Storage) -> Result<()> {
     println!(
-        "{CYAN}{BOLD}  {RESET}  {DIM}You will love our product if you are into software engineering!{RESET}"
+        "Checking if repository {} exists...",
+        storage.repo_full_name()
     );
-    println!("{CYAN}{BOLD}  ╚═══════════════════════════════════════════════════════════════════╝{RESET}");
-    println!();
+    let status = storage.init_repo().await?;
+    if status.created {
+        println!("Created private repository {}.", storage.repo_full_name());
+    } else {
+        println!("Repository exists.");
+    }
+    match status.branch {
+        Some(storage::BranchStatus::Existed(branch)) => println!("Branch '{}' exists.", branch),
+        Some(storage::BranchStatus::Created { branch, from }) => {
+            println!("Created branch '{}' from '{}'.", branch, from)
+        }
+        None => {}
+    }
+    Ok(())
 }
 
-/// Entry point for the AxKeyStore CLI
-#[tokio::main]
-async fn main() -> Result<()> {
-    dotenvy::dotenv().ok(); // Load .env file if it exists
-    let cli = Cli::parse();
+/// The unlocked vault of one profile, for commands that work with two profiles at once
+struct ProfileVault {
+    password: String,
+    storage: storage::Storage,
+    master_key: String,
+}
 
-    // Display the banner
-    display_banner();
+impl ProfileVault {
+    /// Prompts for the master password of a profile and unlocks its vault. With `named`, the
+    /// prompt says which profile the password is for.
+    async fn unlock(profile: Option<&str>, named: bool) -> Result<Self> {
+        let password = if named {
+            prompt_password(&format!(
+                "Enter master password for profile '{}'",
+                profile.unwrap_or("default")
+            ))?
+        } else {
+            prompt_password("Enter master password")?
+        };
+        let storage = open_storage(profile, &password).await?;
+        let master_key = get_or_init_master_key(&storage, &password).await?;
+        Ok(Self {
+            password,
+            storage,
+            master_key,
+        })
+    }
+}
 
-    // Determine the effective profile
-    let effective_profile = match (&cli.profile, config::GlobalConfig::get_active_profile()?) {
-        (Some(p), _) => {
-            config::Config::validate_profile_name(p)?;
-            Some(p.clone())
-        }
-        (None, Some(p)) => Some(p),
-        (None, None) => None,
+/// Unlocks the vaults of a source and a target profile, holding both open. The target is None
+/// when it is the source profile itself.
+async fn unlock_pair(
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<(ProfileVault, Option<ProfileVault>)> {
+    for profile in [from, to].into_iter().flatten() {
+        config::Config::validate_profile_name(profile)?;
+    }
+    let cross_profile = to.is_some_and(|to| Some(to) != from);
+    let source = ProfileVault::unlock(from, cross_profile).await?;
+    let target = match to {
+        Some(to) if cross_profile => Some(ProfileVault::unlock(Some(to), true).await?),
+        _ => None,
     };
+    Ok((source, target))
+}
 
-    let profile_str = effective_profile.as_deref().unwrap_or("default");
+/// Prompts the user for a yes/no confirmation via stdin
+fn prompt_yes_no(message: &str) -> Result<bool> {
+    print!("{} (y/n): ", message);
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    let input = input.trim().to_lowercase();
+    Ok(input == "y" || input == "yes")
+}
 
-    let command = match &cli.command {
-        Some(c) => c,
+/// Writes a decrypted value to a file, or prints it if it is valid UTF-8. A raw value is
+/// printed without a trailing newline, and may be binary when stdout is not a terminal.
+fn output_value(
+    display_path: &str,
+    value: &[u8],
+    out: Option<&std::path::Path>,
+    raw: bool,
+    format: Option<pipe::ValueFormat>,
+) -> Result<()> {
+    let encoded = format.map(|f| pipe::encode(value, f));
+    let value = encoded.as_deref().map_or(value, str::as_bytes);
+    if let Some(path) = out {
+        config::write_private_file(path, value)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+        eprintln!("Wrote {} bytes to '{}'.", value.len(), path.display());
+        return Ok(());
+    }
+
+    if raw && (!std::io::stdout().is_terminal() || std::str::from_utf8(value).is_ok()) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(value)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    match std::str::from_utf8(value) {
+        Ok(text) => {
+            println!("{}", text);
+            Ok(())
+        }
+        Err(_) => Err(anyhow::anyhow!(
+            "Key '{}' holds binary data ({} bytes). Use --out <FILE> to save it, or --format to print it encoded.",
+            display_path,
+            value.len()
+        )),
+    }
+}
+
+/// Prompts the user for a line of input via stdin
+fn prompt_line(message: &str) -> Result<String> {
+    print!("{}: ", message);
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Asks which category a new key belongs in, offering the categories from the cached index
+/// (refreshed from the repository when it has expired) and accepting a new one as free text
+async fn prompt_category(storage: &storage::Storage, password: &str) -> Result<Option<String>> {
+    let profile = storage.profile();
+    let repo = storage.repo_full_name();
+    let lmk = config::Config::get_or_create_lmk_with_profile(profile, password)?;
+
+    let index = match categories::load_index(profile, &repo, &lmk)? {
+        Some(index) => index,
         None => {
-            // Launch TUI
-            let password = prompt_password("Enter master password")?;
+            let index = categories::from_entries(&storage.list_all_keys().await?);
+            categories::store_index(profile, &repo, &lmk, &index)?;
+            index
+        }
+    };
+    if index.is_empty() {
+        return Ok(None);
+    }
+
+    let mut shown: Vec<String> = index.into_iter().collect();
+    loop {
+        println!("\nCategories:");
+        for (i, category) in shown.iter().enumerate() {
+            println!("  {:>3}. {}", i + 1, category);
+        }
+        let input = prompt_line("Category (number, name, prefix* to filter, or blank for none)")?;
+
+        if let Some(prefix) = input.strip_suffix('*') {
+            let matches = categories::filter(prefix, &shown);
+            if matches.is_empty() {
+                println!("No categories start with '{}'.", prefix);
+            } else {
+                shown = matches.into_iter().cloned().collect();
+            }
+            continue;
+        }
+
+        match categories::resolve_choice(&input, &shown) {
+            categories::Choice::Root => return Ok(None),
+            categories::Choice::Existing(category) => return Ok(Some(category)),
+            categories::Choice::New(category) => {
+                let similar = categories::similar(&category, &shown);
+                if !similar.is_empty() {
+                    let names: Vec<&str> = similar.iter().map(|c| c.as_str()).collect();
+                    if !prompt_yes_no(&format!(
+                        "'{}' is a new category, similar to '{}'. Create it anyway?",
+                        category,
+                        names.join("', '")
+                    ))? {
+                        continue;
+                    }
+                }
+                return Ok(Some(category));
+            }
+        }
+    }
+}
+
+/// Logs in to GitHub again for a vault whose master password was lost (the saved token
+/// and repository were encrypted with it), and opens the vault's repository with the new
+/// token
+async fn login_for_recovery(
+    profile: Option<&str>,
+    repo: &str,
+) -> Result<(String, storage::Storage)> {
+    println!("\nLog in to GitHub again:");
+    let token = match auth::authenticate().await {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Authentication failed: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let storage = storage::Storage::with_token(profile, repo, token.clone()).await?;
+    if storage.get_master_key_blob().await?.is_none() {
+        eprintln!(
+            "Repository {} has no master key. Check the repository name.",
+            storage.repo_full_name()
+        );
+        std::process::exit(1);
+    }
+    Ok((token, storage))
+}
+
+/// Protects a recovered master key with a new master password, and starts the profile's
+/// local secrets over under it
+async fn finish_recovery(
+    profile: Option<&str>,
+    storage: &storage::Storage,
+    token: &str,
+    master_key: &str,
+) -> Result<()> {
+    println!("\nSet a new master password:");
+    let new_password = loop {
+        let p1 = prompt_password("New master password")?;
+        if p1.len() < 8 {
+            eprintln!("Password must be at least 8 characters long.");
+            continue;
+        }
+        let p2 = prompt_password("Confirm new master password")?;
+        if p1 == p2 {
+            break p1;
+        }
+        eprintln!("Passwords do not match. Please try again.");
+    };
+
+    let encrypted = crypto::CryptoHandler::encrypt(master_key.as_bytes(), &new_password)?;
+    storage
+        .save_master_key_blob(&serde_json::to_vec(&encrypted)?)
+        .await?;
+    println!("Remote master key updated on GitHub.");
+
+    config::Config::forget_secrets_with_profile(profile)?;
+    cache::clear(profile, None)?;
+    recipients::forget_identity(profile)?;
+    auth::save_token_with_profile(profile, token, &new_password)?;
+    config::Config::set_repo_name_with_profile(profile, &storage.repo_full_name(), &new_password)?;
+    config::Config::set_login_with_profile(profile, storage.login(), &new_password)?;
+    println!(
+        "Access to {} restored for profile '{}' with the new master password.",
+        storage.repo_full_name(),
+        profile.unwrap_or("default")
+    );
+    println!("   Vaults added with 'axkeystore vault add' must be added again.");
+    Ok(())
+}
+
+/// Shows a recovery code once, for the user to keep offline
+fn print_recovery_code(repo: &str, code: &str) {
+    println!("\nRecovery code for {}:\n\n  {}\n", repo, code);
+    println!("It is shown only once. Write it down and keep it offline: with 'axkeystore recover --code <CODE>' it restores access after a forgotten master password, and anyone holding it can read the vault.");
+}
+
+/// Returns true if a key has a stored value or is a derived key
+async fn key_exists(
+    storage: &storage::Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+) -> Result<bool> {
+    if storage
+        .get_file(&storage::Storage::build_key_path(key, category)?)
+        .await?
+        .is_some()
+    {
+        return Ok(true);
+    }
+    let id = derived::key_id(key, category)?;
+    Ok(derived::load(storage, master_key)
+        .await?
+        .keys
+        .contains_key(&id))
+}
+
+/// Decrypts the values of every key, stored or derived (optionally only in one category),
+/// returning them with their paths
+async fn decrypt_values(
+    storage: &storage::Storage,
+    master_key: &str,
+    category: Option<&str>,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut values: Vec<(String, Vec<u8>)> = Vec::new();
+    let entries: Vec<_> = storage
+        .list_all_keys()
+        .await?
+        .into_iter()
+        .filter(|e| category.is_none_or(|c| e.in_category(c)))
+        .collect();
+    let progress = progress::Progress::new(entries.len(), "Decrypting");
+    for entry in entries {
+        let path = entry.display_path();
+        progress.item(&path);
+        let value = serde_json::from_slice::<crypto::EncryptedBlob>(&entry.data)
+            .ok()
+            .and_then(|b| crypto::CryptoHandler::decrypt(&b, master_key).ok())
+            .with_context(|| format!("Key '{}' cannot be decrypted", path))?;
+        progress.succeed();
+        values.push((path, value));
+    }
+    progress.finish();
+    let registry = derived::load(storage, master_key).await?;
+    for (id, d) in &registry.keys {
+        if category.is_some_and(|c| !id.starts_with(&format!("{}/", c.trim_matches('/')))) {
+            continue;
+        }
+        let value = derived::derive_value(master_key, id, d.counter, d.length)?;
+        values.push((id.clone(), value.into_bytes()));
+    }
+    Ok(values)
+}
+
+/// Writes the values of every key (optionally in one category) as Docker secrets files and
+/// prints the matching Compose `secrets:` section
+async fn export_docker_secrets(
+    profile: Option<&str>,
+    category: Option<&str>,
+    pattern: Option<&str>,
+    dir: &std::path::Path,
+    ttl_secs: Option<u64>,
+) -> Result<()> {
+    let password = prompt_password("Enter master password")?;
+    let storage = open_storage(profile, &password).await?;
+    let master_key = get_or_init_master_key(&storage, &password).await?;
+
+    let mut values = decrypt_values(&storage, &master_key, category).await?;
+    if let Some(pattern) = pattern {
+        values.retain(|(path, _)| keyglob::matches(pattern, path));
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        if values.is_empty() {
+            eprintln!("No keys match '{}'.", pattern);
+            std::process::exit(1);
+        }
+        println!("{} key(s) match '{}':", values.len(), pattern);
+        for (path, _) in &values {
+            println!("  {}", path);
+        }
+        if !prompt_yes_no("Write their values as Docker secrets?")? {
+            println!("Export cancelled.");
+            return Ok(());
+        }
+    }
+    let mut secrets = BTreeMap::new();
+    for (path, value) in values {
+        // Reserved keys have no value yet
+        if value.is_empty() {
+            eprintln!("Skipping '{}': it has no value.", path);
+            continue;
+        }
+        let name = dockersecrets::secret_name(&path, category);
+        if secrets.insert(name.clone(), value).is_some() {
+            eprintln!(
+                "Two keys map to the secret name '{}'. Export a narrower category.",
+                name
+            );
+            std::process::exit(1);
+        }
+    }
+    if secrets.is_empty() {
+        eprintln!("No keys to export.");
+        std::process::exit(1);
+    }
+
+    dockersecrets::write_dir(dir, &secrets)?;
+    eprintln!(
+        "Wrote {} secret file(s) to '{}'.",
+        secrets.len(),
+        dir.display()
+    );
+    if let Some(ttl_secs) = ttl_secs {
+        dockersecrets::schedule_clean(dir, ttl_secs)?;
+        eprintln!(
+            "They will be removed in {}.",
+            timeutil::format_duration_secs(ttl_secs)
+        );
+    }
+    let names: Vec<&String> = secrets.keys().collect();
+    print!("{}", dockersecrets::compose_snippet(dir, &names));
+    Ok(())
+}
+
+/// Writes the values of every key (optionally in one category) as a password store that
+/// 'pass' and gopass can read, encrypted to the given GPG keys
+async fn export_pass(
+    profile: Option<&str>,
+    category: Option<&str>,
+    pattern: Option<&str>,
+    dir: &std::path::Path,
+    gpg_ids: &[String],
+) -> Result<()> {
+    if gpg_ids.is_empty() {
+        eprintln!("--format pass requires --gpg-id <ID> for the key(s) to encrypt the entries to.");
+        std::process::exit(1);
+    }
+    let password = prompt_password("Enter master password")?;
+    let storage = open_storage(profile, &password).await?;
+    let master_key = get_or_init_master_key(&storage, &password).await?;
+
+    let mut values = decrypt_values(&storage, &master_key, category).await?;
+    if let Some(pattern) = pattern {
+        values.retain(|(path, _)| keyglob::matches(pattern, path));
+    }
+    values.retain(|(path, value)| {
+        // Reserved keys have no value yet
+        if value.is_empty() {
+            eprintln!("Skipping '{}': it has no value.", path);
+        }
+        !value.is_empty()
+    });
+    values.sort_by(|a, b| a.0.cmp(&b.0));
+    if values.is_empty() {
+        eprintln!("No keys to export.");
+        std::process::exit(1);
+    }
+    println!(
+        "{} key(s) will be written to '{}', encrypted to {}:",
+        values.len(),
+        dir.display(),
+        gpg_ids.join(", ")
+    );
+    for (path, _) in &values {
+        println!("  {}", path);
+    }
+    if !prompt_yes_no("Export them as a password store?")? {
+        println!("Export cancelled.");
+        return Ok(());
+    }
+
+    passstore::write_store(dir, gpg_ids, &values)?;
+    eprintln!(
+        "Wrote {} entr{} to '{}'. Use it with 'PASSWORD_STORE_DIR={} pass'.",
+        values.len(),
+        if values.len() == 1 { "y" } else { "ies" },
+        dir.display(),
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Lists the paths of the keys (stored or derived) that match a pattern, using the repository
+/// tree instead of fetching every key
+async fn matching_paths(
+    storage: &storage::Storage,
+    master_key: &str,
+    pattern: &str,
+) -> Result<Vec<String>> {
+    let tree = storage.list_tree().await?;
+    let registry = derived::load(storage, master_key).await?;
+    Ok(keyglob::expand(
+        pattern,
+        tree.keys()
+            .filter_map(|path| index::key_display_path(path))
+            .chain(registry.keys.keys().map(|id| id.as_str())),
+    )
+    .into_iter()
+    .map(|path| path.to_string())
+    .collect())
+}
+
+/// Prints the values of every key matching a pattern, once the matched keys are confirmed
+async fn get_matching(profile: Option<&str>, pattern: &str) -> Result<()> {
+    let password = prompt_password("Enter master password")?;
+    let storage = open_storage(profile, &password).await?;
+    let master_key = get_or_init_master_key(&storage, &password).await?;
+
+    let base = keyglob::base_category(pattern);
+    let values: Vec<(String, Vec<u8>)> = decrypt_values(&storage, &master_key, base.as_deref())
+        .await?
+        .into_iter()
+        .filter(|(path, _)| keyglob::matches(pattern, path))
+        .collect();
+    if values.is_empty() {
+        eprintln!("No keys match '{}'.", pattern);
+        std::process::exit(1);
+    }
+
+    let mut paths: Vec<&str> = values.iter().map(|(path, _)| path.as_str()).collect();
+    paths.sort();
+    println!("{} key(s) match '{}':", paths.len(), pattern);
+    for path in &paths {
+        println!("  {}", path);
+    }
+    if !prompt_yes_no("Print their values?")? {
+        println!("Cancelled.");
+        return Ok(());
+    }
+
+    let values: BTreeMap<&str, &[u8]> = values
+        .iter()
+        .map(|(path, value)| (path.as_str(), value.as_slice()))
+        .collect();
+    for (path, value) in values {
+        match std::str::from_utf8(value) {
+            Ok(text) => println!("{}: {}", path, text),
+            Err(_) => eprintln!(
+                "Skipping '{}': it holds binary data ({} bytes).",
+                path,
+                value.len()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Deletes every key matching a pattern in a single commit, once the matched keys are
+/// confirmed
+async fn delete_matching(profile: Option<&str>, pattern: &str) -> Result<()> {
+    let password = prompt_password("Enter master password")?;
+    let storage = open_storage(profile, &password).await?;
+    let master_key = get_or_init_master_key(&storage, &password).await?;
+
+    let paths = matching_paths(&storage, &master_key, pattern).await?;
+    if paths.is_empty() {
+        eprintln!("No keys match '{}'.", pattern);
+        std::process::exit(1);
+    }
+
+    println!("{} key(s) match '{}':", paths.len(), pattern);
+    for path in &paths {
+        println!("  {}", path);
+    }
+    if !prompt_yes_no(&format!(
+        "Are you sure you want to delete these {} key(s)?",
+        paths.len()
+    ))? {
+        println!("Deletion cancelled.");
+        return Ok(());
+    }
+
+    delete_keys(
+        &storage,
+        &password,
+        &master_key,
+        &paths,
+        &format!("Delete keys matching: {}", pattern),
+        &audit::AuditEvent::new(
+            "delete",
+            &format!("{}: {} key(s) deleted", pattern, paths.len()),
+        ),
+    )
+    .await?;
+    println!("Deleted {} key(s).", paths.len());
+    notify_change(&storage, &master_key, "deleted", &paths).await;
+    Ok(())
+}
+
+/// Moves keys (stored or derived, by path) with their metadata to the trash in a single commit
+/// that records an audit event and purges deleted keys past the trash's retention, then drops
+/// them from the local key index
+async fn delete_keys(
+    storage: &storage::Storage,
+    password: &str,
+    master_key: &str,
+    paths: &[String],
+    message: &str,
+    event: &audit::AuditEvent,
+) -> Result<()> {
+    let now = timeutil::now_unix();
+    let mut registry = derived::load(storage, master_key).await?;
+    let derived_count = registry.keys.len();
+    let mut changes = trash::trash_changes(storage, master_key, &mut registry, paths, now).await?;
+    if registry.keys.len() != derived_count {
+        changes.extend(derived::save_changes(storage, master_key, &registry).await?);
+    }
+    let (purge, _) = trash::expired_changes(storage, master_key, now).await?;
+    changes.extend(purge);
+    changes.push(audit::append_change(storage, event).await?);
+    storage.commit_changes(&changes, message).await?;
+
+    for path in paths {
+        let (category, name) = templatefn::split_key_path(path);
+        forget_key(storage, password, name, category)?;
+    }
+    Ok(())
+}
+
+/// Copies the keys of a category into another category, possibly in the vault of another
+/// profile, in a single commit after showing what would change
+async fn promote_category(
+    from_profile: Option<&str>,
+    to_profile: Option<&str>,
+    from: &str,
+    to: &str,
+    keys: &[String],
+    dry_run: bool,
+) -> Result<()> {
+    let from = match storage::Storage::validate_category(Some(from))? {
+        Some(from) => from,
+        None => {
+            eprintln!("Both categories must be non-empty.");
+            std::process::exit(1);
+        }
+    };
+    let to = match storage::Storage::validate_category(Some(to))? {
+        Some(to) => to,
+        None => {
+            eprintln!("Both categories must be non-empty.");
+            std::process::exit(1);
+        }
+    };
+    if from == to && to_profile.is_none_or(|p| Some(p) == from_profile) {
+        eprintln!("Cannot promote '{}' into itself.", from);
+        std::process::exit(1);
+    }
+
+    let (source_vault, target_vault) = unlock_pair(from_profile, to_profile).await?;
+    let source =
+        decrypt_values(&source_vault.storage, &source_vault.master_key, Some(&from)).await?;
+    let target_vault = target_vault.as_ref().unwrap_or(&source_vault);
+    let (target_password, target_storage, target_master_key) = (
+        &target_vault.password,
+        &target_vault.storage,
+        &target_vault.master_key,
+    );
+    let target: BTreeMap<String, Vec<u8>> =
+        decrypt_values(target_storage, target_master_key, Some(&to))
+            .await?
+            .into_iter()
+            .collect();
+
+    let steps = promote::plan(&source, &from, &to, keys, &target)?;
+    if steps.is_empty() {
+        println!("No keys to promote in '{}'.", from);
+        return Ok(());
+    }
+    let registry = derived::load(target_storage, target_master_key).await?;
+    let derived_targets: Vec<&str> = steps
+        .iter()
+        .filter(|s| registry.keys.contains_key(&s.to))
+        .map(|s| s.to.as_str())
+        .collect();
+    if !derived_targets.is_empty() {
+        eprintln!(
+            "These keys are derived; their values cannot be replaced: {}",
+            derived_targets.join(", ")
+        );
+        std::process::exit(1);
+    }
+
+    let target_repo = target_storage.repo_full_name();
+    println!("Promoting '{}' to '{}' in '{}':", from, to, target_repo);
+    for step in &steps {
+        let marker = match step.change {
+            promote::Change::Create => "+",
+            promote::Change::Update => "~",
+            promote::Change::Unchanged => "=",
+        };
+        println!("  {} {}", marker, step.to);
+    }
+    let rejected: Vec<(&promote::Step, Vec<String>)> = steps
+        .iter()
+        .filter(|s| s.change != promote::Change::Unchanged && lint::is_protected(&s.to))
+        .map(|s| (s, lint::check(&s.value)))
+        .filter(|(_, problems)| !problems.is_empty())
+        .collect();
+    if !rejected.is_empty() {
+        eprintln!(
+            "Refusing to promote values that fail the validation policy of production categories:"
+        );
+        for (step, problems) in &rejected {
+            eprintln!("  {}: {}", step.from, problems.join("; "));
+        }
+        std::process::exit(1);
+    }
+
+    let pending: Vec<&promote::Step> = steps
+        .iter()
+        .filter(|s| s.change != promote::Change::Unchanged)
+        .collect();
+    let updates = pending
+        .iter()
+        .filter(|s| s.change == promote::Change::Update)
+        .count();
+    println!(
+        "{} to create, {} to update, {} unchanged.",
+        pending.len() - updates,
+        updates,
+        steps.len() - pending.len()
+    );
+    if dry_run || pending.is_empty() {
+        return Ok(());
+    }
+    if updates > 0 && !prompt_yes_no(&format!("Overwrite {} existing key(s)?", updates))? {
+        println!("Promotion cancelled.");
+        return Ok(());
+    }
+
+    let mut changes = Vec::new();
+    for step in &pending {
+        let (category, name) = templatefn::split_key_path(&step.to);
+        let encrypted = encrypt_value(target_master_key, category, &step.value)?;
+        changes.extend(
+            target_storage
+                .key_changes(name, category, Some(&serde_json::to_vec(&encrypted)?))
+                .await?,
+        );
+    }
+    let details = format!("{} key(s) from {} to {}", pending.len(), from, to);
+    changes.push(
+        audit::append_change(target_storage, &audit::AuditEvent::new("promote", &details)).await?,
+    );
+    target_storage
+        .apply_changes(&changes, &format!("Promote {} into {}", from, to))
+        .await?;
+
+    for step in &pending {
+        let (category, name) = templatefn::split_key_path(&step.to);
+        remember_keys(
+            target_storage,
+            target_password,
+            category,
+            &[name],
+            false,
+            None,
+        )?;
+    }
+    println!("Promoted {} key(s) into '{}'.", pending.len(), to);
+    let paths: Vec<String> = pending.iter().map(|s| s.to.clone()).collect();
+    notify_change(target_storage, target_master_key, "stored", &paths).await;
+    Ok(())
+}
+
+/// Loads the vault's keys and their expiry and plans bringing them in line with a manifest.
+/// Returns the plan with the registry of derived keys, which a manifest cannot declare.
+async fn plan_manifest(
+    storage: &storage::Storage,
+    master_key: &str,
+    manifest: &apply::Manifest,
+    require_env: bool,
+    now: u64,
+) -> Result<(Vec<apply::Step>, derived::DerivedRegistry)> {
+    let registry = derived::load(storage, master_key).await?;
+    let derived_keys: Vec<String> = manifest
+        .entries()?
+        .into_keys()
+        .filter(|p| registry.keys.contains_key(p))
+        .collect();
+    if !derived_keys.is_empty() {
+        eprintln!(
+            "These keys are derived; their values cannot be declared in a manifest: {}",
+            derived_keys.join(", ")
+        );
+        std::process::exit(1);
+    }
+    let current: BTreeMap<String, Vec<u8>> = decrypt_values(storage, master_key, None)
+        .await?
+        .into_iter()
+        .filter(|(p, _)| !registry.keys.contains_key(p))
+        .collect();
+    let expiries: BTreeMap<String, u64> = meta::load_all(storage, master_key)
+        .await?
+        .into_iter()
+        .filter_map(|(p, m)| Some((p, m.expires?)))
+        .collect();
+    let steps = apply::plan(
+        manifest,
+        &current,
+        &expiries,
+        |name| std::env::var(name).ok(),
+        require_env,
+        now,
+    )?;
+    Ok((steps, registry))
+}
+
+/// Prints a manifest's plan, key paths only, and returns how many keys it creates, updates
+/// and deletes
+fn print_plan(
+    storage: &storage::Storage,
+    path: &std::path::Path,
+    steps: &[apply::Step],
+) -> (usize, usize, usize) {
+    println!(
+        "Plan for '{}' from '{}':",
+        storage.repo_full_name(),
+        path.display()
+    );
+    for step in steps {
+        let (marker, mut details): (&str, Vec<&str>) = match step.change {
+            apply::Change::Create => ("+", Vec::new()),
+            apply::Change::Update => (
+                "~",
+                [
+                    step.value.is_some().then_some("value"),
+                    step.expires.is_some().then_some("expiry"),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+            ),
+            apply::Change::Delete => ("-", Vec::new()),
+            apply::Change::Unchanged => ("=", Vec::new()),
+        };
+        if step.expired {
+            details.push("expired");
+        }
+        let details = if details.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", details.join(", "))
+        };
+        println!("  {} {}{}", marker, step.path, details);
+    }
+    let count = |change: apply::Change| steps.iter().filter(|s| s.change == change).count();
+    let (created, updated, deleted) = (
+        count(apply::Change::Create),
+        count(apply::Change::Update),
+        count(apply::Change::Delete),
+    );
+    println!(
+        "{} to create, {} to update, {} to delete, {} unchanged.",
+        created,
+        updated,
+        deleted,
+        count(apply::Change::Unchanged)
+    );
+    (created, updated, deleted)
+}
+
+/// Brings the vault in line with a manifest in a single commit after showing the plan.
+/// Deleted keys go to the trash.
+async fn apply_manifest(
+    profile: Option<&str>,
+    path: &std::path::Path,
+    dry_run: bool,
+    auto_approve: bool,
+) -> Result<()> {
+    let manifest = apply::Manifest::load(path)?;
+    let password = prompt_password("Enter master password")?;
+    let storage = open_storage(profile, &password).await?;
+    let master_key = get_or_init_master_key(&storage, &password).await?;
+    let now = timeutil::now_unix();
+    let (steps, mut registry) = plan_manifest(&storage, &master_key, &manifest, true, now).await?;
+
+    let mut rejected = Vec::new();
+    for step in &steps {
+        let value = match &step.value {
+            Some(value) => value,
+            None => continue,
+        };
+        let mut problems = Vec::new();
+        if lint::is_protected(&step.path) {
+            problems.extend(lint::check(value));
+        }
+        let (category, _) = templatefn::split_key_path(&step.path);
+        if let Err(e) = wasmplugin::check_policy(profile, category, &step.path, value) {
+            problems.push(e.to_string());
+        }
+        if !problems.is_empty() {
+            rejected.push((step.path.as_str(), problems));
+        }
+    }
+    if !rejected.is_empty() {
+        eprintln!("Refusing to apply values that fail the validation policy of their category:");
+        for (path, problems) in &rejected {
+            eprintln!("  {}: {}", path, problems.join("; "));
+        }
+        std::process::exit(1);
+    }
+
+    let (created, updated, deleted) = print_plan(&storage, path, &steps);
+    let expired: Vec<&str> = steps
+        .iter()
+        .filter(|s| s.expired)
+        .map(|s| s.path.as_str())
+        .collect();
+    if !expired.is_empty() {
+        eprintln!(
+            "Warning: these values are past their expiry and the manifest does not renew them: {}",
+            expired.join(", ")
+        );
+    }
+    if dry_run || created + updated + deleted == 0 {
+        return Ok(());
+    }
+    if !auto_approve && !prompt_yes_no("Apply these changes?")? {
+        println!("Apply cancelled.");
+        return Ok(());
+    }
+
+    let mut changes = Vec::new();
+    let mut written = Vec::new();
+    for step in &steps {
+        let (category, name) = templatefn::split_key_path(&step.path);
+        if let Some(value) = &step.value {
+            let encrypted = encrypt_value(&master_key, category, value)?;
+            changes.extend(
+                storage
+                    .key_changes(name, category, Some(&serde_json::to_vec(&encrypted)?))
+                    .await?,
+            );
+            written.push(step.path.clone());
+        }
+        if let Some(expires) = step.expires {
+            let update = meta::MetaUpdate {
+                expires: Some(expires),
+                ..Default::default()
+            };
+            changes.extend(
+                meta::update_changes(&storage, &master_key, name, category, &update).await?,
+            );
+        }
+    }
+    let removed: Vec<String> = steps
+        .iter()
+        .filter(|s| s.change == apply::Change::Delete)
+        .map(|s| s.path.clone())
+        .collect();
+    changes
+        .extend(trash::trash_changes(&storage, &master_key, &mut registry, &removed, now).await?);
+    let manifest_name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into_owned(),
+    );
+    let details = format!(
+        "{}: {} created, {} updated, {} deleted",
+        manifest_name, created, updated, deleted
+    );
+    changes.push(audit::append_change(&storage, &audit::AuditEvent::new("apply", &details)).await?);
+    storage
+        .apply_changes(&changes, &format!("Apply manifest: {}", manifest_name))
+        .await?;
+
+    for path in &written {
+        let (category, name) = templatefn::split_key_path(path);
+        remember_keys(&storage, &password, category, &[name], false, None)?;
+    }
+    for path in &removed {
+        let (category, name) = templatefn::split_key_path(path);
+        forget_key(&storage, &password, name, category)?;
+    }
+    println!(
+        "Applied: {} created, {} updated, {} deleted.",
+        created, updated, deleted
+    );
+    if !written.is_empty() {
+        notify_change(&storage, &master_key, "stored", &written).await;
+    }
+    if !removed.is_empty() {
+        notify_change(&storage, &master_key, "deleted", &removed).await;
+    }
+    Ok(())
+}
+
+/// Imports the secrets of a password manager into the vault in a single commit, mapping its
+/// folders to categories below `base`. Usernames and websites go into the keys' metadata.
+async fn import_password_manager(
+    profile: Option<&str>,
+    from: pmimport::Source,
+    source: &std::path::Path,
+    base: Option<&str>,
+    overwrite: bool,
+) -> Result<()> {
+    let base = storage::Storage::validate_category(base)?;
+    let export = from.read(source)?;
+    if export.entries.is_empty() {
+        println!("No secrets found in the {} export.", from.name());
+        return Ok(());
+    }
+    let mut planned = Vec::new();
+    for entry in &export.entries {
+        let category = match (&base, &entry.category) {
+            (Some(base), Some(category)) => Some(format!("{}/{}", base, category)),
+            (Some(base), None) => Some(base.clone()),
+            (None, category) => category.clone(),
+        };
+        let path = derived::key_id(&entry.name, category.as_deref())?;
+        planned.push((path, category, entry));
+    }
+
+    let password = prompt_password("Enter master password")?;
+    let storage = open_storage(profile, &password).await?;
+    let master_key = get_or_init_master_key(&storage, &password).await?;
+    let existing: std::collections::HashSet<String> = storage
+        .list_all_keys()
+        .await?
+        .into_iter()
+        .map(|e| e.display_path())
+        .collect();
+
+    println!("\nSecrets from {}:", from.name());
+    let mut to_import = Vec::new();
+    for (path, category, entry) in &planned {
+        let status = match (existing.contains(path), overwrite) {
+            (false, _) => "new",
+            (true, true) => "overwrite",
+            (true, false) => "exists, skipped",
+        };
+        println!("  {}  ({})", path, status);
+        if status != "exists, skipped" {
+            to_import.push((path, category, *entry));
+        }
+    }
+    if !export.skipped.is_empty() {
+        println!(
+            "   {} item(s) without a password or note left out: {}",
+            export.skipped.len(),
+            export.skipped.join(", ")
+        );
+    }
+    if to_import.is_empty() {
+        println!("Nothing to import. Pass --overwrite to replace existing keys.");
+        return Ok(());
+    }
+    if !prompt_yes_no(&format!("Import {} secret(s)?", to_import.len()))? {
+        println!("Import cancelled.");
+        return Ok(());
+    }
+
+    // Everything is committed at once, so stopping before the commit changes nothing
+    handle_ctrl_c();
+    let mut changes = Vec::new();
+    let progress = progress::Progress::new(to_import.len(), "Encrypting");
+    for (path, category, entry) in &to_import {
+        if cancel::is_requested() {
+            exit_cancelled("Import cancelled; nothing was changed.");
+        }
+        progress.item(path);
+        let encrypted = encrypt_value(&master_key, category.as_deref(), &entry.value)?;
+        changes.extend(
+            storage
+                .key_changes(
+                    &entry.name,
+                    category.as_deref(),
+                    Some(&serde_json::to_vec(&encrypted)?),
+                )
+                .await?,
+        );
+        if entry.username.is_some() || entry.url.is_some() {
+            let update = meta::MetaUpdate {
+                description: entry.username.as_ref().map(|u| format!("Username: {}", u)),
+                url: entry.url.clone(),
+                ..Default::default()
+            };
+            changes.extend(
+                meta::update_changes(
+                    &storage,
+                    &master_key,
+                    &entry.name,
+                    category.as_deref(),
+                    &update,
+                )
+                .await?,
+            );
+        }
+        progress.succeed();
+    }
+    progress.finish();
+    let details = format!(
+        "{} secret(s) from {}{}",
+        to_import.len(),
+        from.name(),
+        base.as_ref()
+            .map_or(String::new(), |b| format!(" into {}", b))
+    );
+    changes
+        .push(audit::append_change(&storage, &audit::AuditEvent::new("import", &details)).await?);
+    if cancel::is_requested() {
+        exit_cancelled("Import cancelled; nothing was changed.");
+    }
+    let message = match &base {
+        Some(base) => format!("Import keys into {}", base),
+        None => format!("Import keys from {}", from.name()),
+    };
+    storage.apply_changes(&changes, &message).await?;
+    for (_, category, entry) in &to_import {
+        remember_keys(
+            &storage,
+            &password,
+            category.as_deref(),
+            &[entry.name.as_str()],
+            false,
+            None,
+        )?;
+    }
+    println!("Imported {}.", details);
+    let skipped = planned.len() - to_import.len();
+    if skipped > 0 {
+        println!("   {} existing key(s) skipped.", skipped);
+    }
+    let paths: Vec<String> = to_import
+        .iter()
+        .map(|(path, _, _)| path.to_string())
+        .collect();
+    notify_change(&storage, &master_key, "stored", &paths).await;
+    Ok(())
+}
+
+/// Records keys that were just written in the cached category index and the local key index
+fn remember_keys(
+    storage: &storage::Storage,
+    password: &str,
+    category: Option<&str>,
+    names: &[&str],
+    is_derived: bool,
+    key_meta: Option<&meta::KeyMeta>,
+) -> Result<()> {
+    let profile = storage.profile();
+    let repo = storage.repo_full_name();
+    let lmk = config::Config::get_or_create_lmk_with_profile(profile, password)?;
+    if let Some(category) = category {
+        categories::record(profile, &repo, &lmk, category)?;
+    }
+    if let Some(mut key_index) = index::load(profile, &repo, &lmk)? {
+        index::record_write(&mut key_index, category, names, is_derived, key_meta);
+        index::save(profile, &lmk, &key_index)?;
+    }
+    Ok(())
+}
+
+/// Writes the keys and vault files of a backup into the profile's repository or another one
+/// (created if missing) in a single commit. The target must be a new vault, or one using the
+/// backup's master key; its keys missing from the backup are kept.
+async fn restore_backup(
+    profile: Option<&str>,
+    password: &str,
+    archive: &backup::Archive,
+    master_key: &str,
+    repo: Option<&str>,
+) -> Result<()> {
+    let storage = match repo {
+        Some(repo) => {
+            let storage = storage::Storage::new_with_profile(profile, repo, password).await?;
+            init_repo(&storage).await?;
+            storage
+        }
+        None => open_storage(profile, password).await?,
+    };
+    let target = storage.repo_full_name();
+    let is_new = storage.get_master_key_blob().await?.is_none();
+    if !is_new && get_or_init_master_key(&storage, password).await? != master_key {
+        eprintln!(
+            "{} is a vault with another master key than the backup. Restore into a new repository with --repo.",
+            target
+        );
+        std::process::exit(1);
+    }
+
+    let keys = backup::key_blobs(archive)?;
+    let files = backup::vault_files(archive)?;
+    let replaced = if is_new {
+        0
+    } else {
+        let existing: std::collections::HashSet<String> = storage
+            .list_all_keys()
+            .await?
+            .into_iter()
+            .map(|entry| entry.display_path())
+            .collect();
+        keys.iter()
+            .filter(|(path, _)| existing.contains(path))
+            .count()
+    };
+    println!(
+        "Backup of '{}' taken {}: {} key(s), {} vault file(s).",
+        archive.repo,
+        archive.created,
+        keys.len(),
+        files.len()
+    );
+    if replaced > 0 {
+        println!(
+            "{} key(s) already in {} will be replaced by their backed-up version.",
+            replaced, target
+        );
+    }
+    if !prompt_yes_no(&format!("Restore the backup into {}?", target))? {
+        println!("Restore cancelled.");
+        return Ok(());
+    }
+
+    handle_ctrl_c();
+    let mut changes = Vec::new();
+    let progress = progress::Progress::new(keys.len() + files.len(), "Preparing");
+    for (path, data) in &keys {
+        if cancel::is_requested() {
+            progress.finish();
+            exit_cancelled("Restore cancelled. Nothing was written.");
+        }
+        progress.item(path);
+        let (category, name) = templatefn::split_key_path(path);
+        changes.extend(storage.key_changes(name, category, Some(data)).await?);
+        progress.succeed();
+    }
+    // A new vault takes over the backed-up audit log; an existing one keeps its own
+    let mut archived_log = None;
+    for (path, data) in &files {
+        if cancel::is_requested() {
+            progress.finish();
+            exit_cancelled("Restore cancelled. Nothing was written.");
+        }
+        progress.item(path);
+        if path == audit::LOG_PATH {
+            if is_new {
+                archived_log = Some(data);
+            }
+        } else {
+            changes.extend(storage.path_changes(path, Some(data)).await?);
+        }
+        progress.succeed();
+    }
+    progress.finish();
+
+    let event = audit::AuditEvent::new(
+        "restore",
+        &format!(
+            "{} key(s) from the backup of {} taken {}",
+            keys.len(),
+            archive.repo,
+            archive.created
+        ),
+    );
+    changes.push(match archived_log {
+        Some(log) => storage::TreeChange::Upsert {
+            path: audit::LOG_PATH.to_string(),
+            content: audit::append(log, &event)?,
+        },
+        None => audit::append_change(&storage, &event).await?,
+    });
+    if is_new {
+        if let Some(blob) = backup::master_key_blob(archive)? {
+            storage.save_master_key_blob(&blob).await?;
+        }
+    }
+    storage
+        .apply_changes(
+            &changes,
+            &format!("Restore backup of {} ({})", archive.repo, archive.created),
+        )
+        .await?;
+
+    let mut names: BTreeMap<Option<&str>, Vec<&str>> = BTreeMap::new();
+    for (path, _) in &keys {
+        let (category, name) = templatefn::split_key_path(path);
+        names.entry(category).or_default().push(name);
+    }
+    for (category, names) in &names {
+        remember_keys(&storage, password, *category, names, false, None)?;
+    }
+    println!(
+        "Restored {} key(s) and {} vault file(s) into {}.",
+        keys.len(),
+        files.len(),
+        target
+    );
+    if let Some(repo) = repo {
+        println!(
+            "   Use it with 'axkeystore vault add <name> {}', or as the profile's vault with 'axkeystore init --repo {}'.",
+            repo, repo
+        );
+    }
+    Ok(())
+}
+
+/// Removes a deleted key from the local key index
+fn forget_key(
+    storage: &storage::Storage,
+    password: &str,
+    key: &str,
+    category: Option<&str>,
+) -> Result<()> {
+    let profile = storage.profile();
+    let repo = storage.repo_full_name();
+    let lmk = config::Config::get_or_create_lmk_with_profile(profile, password)?;
+    if let Some(mut key_index) = index::load(profile, &repo, &lmk)? {
+        index::record_removal(&mut key_index, category, key);
+        index::save(profile, &lmk, &key_index)?;
+    }
+    Ok(())
+}
+
+/// Lets Ctrl-C stop a bulk operation at its next checkpoint instead of killing it mid-write.
+/// A second Ctrl-C exits immediately. Installed only once prompts are done, so Ctrl-C still
+/// aborts a prompt as usual.
+fn handle_ctrl_c() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("\nStopping at the next safe point (press Ctrl-C again to quit now)...");
+        cancel::request();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+}
+
+/// Tells the user what a cancelled operation left behind and exits with the interrupt status
+fn exit_cancelled(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(130);
+}
+
+/// Saves a new value for a key, replacing the version read earlier (`base`: its SHA, None if
+/// the key did not exist then). If someone else changed the key in the meantime, shows who
+/// changed it and lets the user overwrite their change, merge both values in the editor, or
+/// abort. Returns false if the user aborted. Without a terminal, the conflict is an error.
+async fn save_checked(
+    storage: &storage::Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+    value: Vec<u8>,
+    base: Option<String>,
+) -> Result<bool> {
+    let display_path = match category {
+        Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+        None => key.to_string(),
+    };
+    let (mut value, mut expected) = (value, base);
+    loop {
+        let encrypted = encrypt_value(master_key, category, &value)?;
+        let err = match storage
+            .save_blob_expecting(
+                key,
+                &serde_json::to_vec(&encrypted)?,
+                category,
+                expected.as_deref(),
+            )
+            .await
+        {
+            Ok(()) => return Ok(true),
+            Err(e) => e,
+        };
+        if storage::write_conflict(&err).is_none() || !std::io::stdin().is_terminal() {
+            return Err(err);
+        }
+        tracing::debug!("Write conflict on '{}'", display_path);
+
+        println!(
+            "\n'{}' was changed by someone else after it was read.",
+            display_path
+        );
+        if let Some(version) = storage.get_latest_key_version(key, category).await? {
+            println!(
+                "  Last change: {} by {} ({})",
+                version.date,
+                version.author.as_deref().unwrap_or("unknown"),
+                version.message.lines().next().unwrap_or_default()
+            );
+        }
+        let (theirs, current_sha) = match storage.get_blob(key, category).await? {
+            Some((data, sha)) => {
+                let encrypted: crypto::EncryptedBlob =
+                    serde_json::from_slice(&data).context("Failed to parse encrypted blob")?;
+                (
+                    Some(crypto::CryptoHandler::decrypt(&encrypted, master_key)?),
+                    Some(sha),
+                )
+            }
+            None => (None, None),
+        };
+        match &theirs {
+            None => println!("  It has been deleted."),
+            Some(theirs) if *theirs == value => {
+                println!("  It already holds the same value as yours.");
+                return Ok(true);
+            }
+            Some(theirs) => println!(
+                "  Their value has {} bytes; yours has {}.",
+                theirs.len(),
+                value.len()
+            ),
+        }
+
+        // Only text values can be merged in the editor
+        let texts = theirs.as_deref().and_then(|theirs| {
+            Some((
+                std::str::from_utf8(&value).ok()?,
+                std::str::from_utf8(theirs).ok()?,
+            ))
+        });
+        let question = if texts.is_some() {
+            "[o]verwrite their change, [m]erge both in the editor, or [a]bort"
+        } else {
+            "[o]verwrite their change, or [a]bort"
+        };
+        loop {
+            match prompt_line(question)?.to_lowercase().as_str() {
+                "o" | "overwrite" => break,
+                "m" | "merge" if texts.is_some() => {
+                    let (ours, theirs) = texts.unwrap_or_default();
+                    match editor::edit(&editor::conflict_text(ours, theirs))? {
+                        Some(merged) if !editor::has_conflict_markers(&merged) => {
+                            value = merged;
+                            break;
+                        }
+                        _ => println!("The conflict markers are still there; resolve them first."),
+                    }
+                }
+                "" | "a" | "abort" => return Ok(false),
+                other => println!("Unknown action '{}'.", other),
+            }
+        }
+        expected = current_sha;
+    }
+}
+
+/// Announces changed keys on the webhooks of the vault's notification policy, and pushes
+/// the change to the vault's mirror if it is kept in sync after every change. The change is
+/// already committed, so delivery failures only warn.
+async fn notify_change(
+    storage: &storage::Storage,
+    master_key: &str,
+    action: &str,
+    paths: &[String],
+) {
+    let result = async {
+        let policy = notify::load(storage, master_key).await?;
+        notify::dispatch(&policy, &storage.repo_full_name(), action, paths).await
+    }
+    .await;
+    if let Err(e) = result {
+        eprintln!("Warning: failed to send notifications: {}", e);
+    }
+    if let Err(e) = mirror::sync_auto(storage, master_key).await {
+        eprintln!(
+            "Warning: failed to update the mirror: {:#}. Run 'axkeystore mirror' to retry.",
+            e
+        );
+    }
+}
+
+/// Brings the local key index up to date (or builds it) and saves it
+async fn refresh_index(
+    storage: &storage::Storage,
+    password: &str,
+    master_key: &str,
+    full: bool,
+) -> Result<index::KeyIndex> {
+    let profile = storage.profile();
+    let lmk = config::Config::get_or_create_lmk_with_profile(profile, password)?;
+    let previous = if full {
+        None
+    } else {
+        index::load(profile, &storage.repo_full_name(), &lmk)?
+    };
+    let key_index = index::refresh(storage, master_key, previous.as_ref()).await?;
+    index::save(profile, &lmk, &key_index)?;
+    Ok(key_index)
+}
+
+/// Asks how to resolve a key that exists in both categories of a merge. Returns the name to
+/// move the key to, or None to leave it where it is.
+fn resolve_merge_collision(
+    target_category: &str,
+    name: &str,
+    taken: &std::collections::HashSet<String>,
+) -> Result<Option<String>> {
+    loop {
+        let answer = prompt_line(&format!(
+            "Key '{}/{}' already exists. [o]verwrite, [r]ename, or [s]kip",
+            target_category, name
+        ))?;
+        match answer.to_lowercase().as_str() {
+            "o" | "overwrite" => return Ok(Some(name.to_string())),
+            "s" | "skip" => return Ok(None),
+            "r" | "rename" => {
+                let new_name = prompt_line("New key name")?;
+                if let Err(e) = storage::Storage::build_key_path(&new_name, Some(target_category)) {
+                    println!("{}", e);
+                } else if taken.contains(&format!("{}/{}", target_category, new_name)) {
+                    println!("Key '{}/{}' already exists too.", target_category, new_name);
+                } else {
+                    return Ok(Some(new_name));
+                }
+            }
+            _ => println!("Please answer 'o', 'r' or 's'."),
+        }
+    }
+}
+
+/// Lets the user pick a previous version of a key and print, copy, or restore its value
+async fn interactive_history(
+    storage: &storage::Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+    filter: &storage::HistoryFilter,
+) -> Result<()> {
+    let display_path = match category {
+        Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+        None => key.to_string(),
+    };
+
+    let mut versions: Vec<storage::KeyVersion> = Vec::new();
+    let mut page = 1;
+    let mut has_more = true;
+    let mut load_more = true;
+
+    let selected = loop {
+        if load_more && has_more {
+            // Pages with no version made by the --action are skipped
+            let mut batch = Vec::new();
+            while batch.is_empty() && has_more {
+                let fetched = storage
+                    .get_filtered_key_history(key, category, filter, page, 10)
+                    .await?;
+                has_more = fetched.len() == 10;
+                page += 1;
+                batch = fetched.into_iter().filter(|v| filter.matches(v)).collect();
+            }
+
+            if versions.is_empty() {
+                if batch.is_empty() {
+                    println!("No history found for key '{}'.", display_path);
+                    return Ok(());
+                }
+                println!("\nVersion History for '{}':", display_path);
+                println!("{:>3}  {:<10} | {:<25} | {}", "#", "SHA", "Date", "Message");
+                println!("{:-<3}--{:-<10}-+-{:-<25}-+-{:-<20}", "", "", "", "");
+            }
+            for (i, v) in batch.iter().enumerate() {
+                println!(
+                    "{:>3}  {:<10} | {:<25} | {}",
+                    versions.len() + i + 1,
+                    &v.sha[..v.sha.len().min(10)],
+                    v.date,
+                    v.message
+                );
+            }
+            versions.extend(batch);
+        }
+        load_more = false;
+
+        let hint = if has_more {
+            "number, 'm' for more, or 'q' to quit"
+        } else {
+            "number or 'q' to quit"
+        };
+        let input = prompt_line(&format!("\nSelect a version ({})", hint))?;
+        match input.as_str() {
+            "" | "q" => return Ok(()),
+            "m" if has_more => load_more = true,
+            other => match other.parse::<usize>() {
+                Ok(n) if (1..=versions.len()).contains(&n) => break versions[n - 1].clone(),
+                _ => println!("Invalid selection '{}'.", other),
+            },
+        }
+    };
+
+    let short_sha = &selected.sha[..selected.sha.len().min(10)];
+    let data = match storage
+        .get_blob_at_version(key, category, &selected.sha)
+        .await?
+    {
+        Some(data) => data,
+        None => {
+            println!(
+                "Version {} has no value (the key was deleted in this commit).",
+                short_sha
+            );
+            return Ok(());
+        }
+    };
+    let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&data)?;
+    let value = crypto::CryptoHandler::decrypt(&encrypted, master_key)?;
+
+    loop {
+        let action = prompt_line("[p]rint, [c]opy, [r]estore, or [q]uit")?;
+        match action.to_lowercase().as_str() {
+            "p" | "print" => {
+                return output_value(&display_path, &value, None, false, None);
+            }
+            "c" | "copy" => {
+                let text = std::str::from_utf8(&value).map_err(|_| {
+                    anyhow::anyhow!("Binary values cannot be copied to the clipboard")
+                })?;
+                clipboard::copy(text)?;
+                println!(
+                    "Value of '{}' at {} copied to clipboard.",
+                    display_path, short_sha
+                );
+                return Ok(());
+            }
+            "r" | "restore" => {
+                if !prompt_yes_no(&format!(
+                    "Restore '{}' to version {}?",
+                    display_path, short_sha
+                ))? {
+                    println!("Restore cancelled.");
+                    return Ok(());
+                }
+                // Re-encrypt so the restored value gets a fresh salt and nonce
+                let encrypted = encrypt_value(master_key, category, &value)?;
+                storage
+                    .save_blob(key, &serde_json::to_vec(&encrypted)?, category)
+                    .await?;
+                println!("Key '{}' restored to version {}.", display_path, short_sha);
+                return Ok(());
+            }
+            "" | "q" | "quit" => return Ok(()),
+            other => println!("Unknown action '{}'.", other),
+        }
+    }
+}
+
+/// Generate a random alphanumeric string with length between 6 and 36 characters
+fn generate_random_alphanumeric() -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let mut rng = rand::rng();
+    let length = rng.random_range(6..=36);
+
+    (0..length)
+        .map(|_| {
+            let idx = rng.random_range(0..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// Generates a value for a key with the WASM generator configured for its category, or the
+/// built-in generator if there is none
+fn generate_value(profile: Option<&str>, category: Option<&str>, path: &str) -> Result<Vec<u8>> {
+    let config = config::Config::load_with_profile(profile)?;
+    match wasmplugin::generator_for(&config.category_plugins, category) {
+        Some(name) => {
+            wasmplugin::Plugin::load(&wasmplugin::plugins_dir(profile)?, name)?.generate(path)
+        }
+        None => Ok(settings::generate(&config)
+            .unwrap_or_else(|| generate_random_alphanumeric().into_bytes())),
+    }
+}
+
+/// Returns the category of a command: the one given, or for a key given by name alone, the
+/// profile's default category
+fn category_or_default(
+    category: &Option<String>,
+    key: &str,
+    defaults: &config::Defaults,
+) -> Option<String> {
+    match category {
+        Some(category) => Some(category.clone()),
+        None if !key.contains('/') => defaults.category.clone(),
+        None => None,
+    }
+}
+
+/// Unlocks the vault once and opens the interactive browser
+async fn launch_tui(profile: Option<&str>) -> Result<()> {
+    let password = prompt_password("Enter master password")?;
+
+    let mut terminal = match tui::init_terminal() {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Failed to initialize terminal: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let _ = tui::draw_loading(&mut terminal, "Authenticating with GitHub...");
+
+    let repo_name = match config::Config::get_repo_name_with_profile(profile, &password) {
+        Ok(name) => name,
+        Err(e) => {
+            let _ = tui::restore_terminal(terminal);
+            eprintln!("Configuration missing or master password incorrect: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let storage = match storage::Storage::new_with_profile(profile, &repo_name, &password).await {
+        Ok(s) => s,
+        Err(e) => {
+            let _ = tui::restore_terminal(terminal);
+            eprintln!("Failed to initialize storage: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let _ = tui::draw_loading(&mut terminal, "Fetching and verifying master key...");
+    let master_key = match get_or_init_master_key(&storage, &password).await {
+        Ok(k) => k,
+        Err(e) => {
+            let _ = tui::restore_terminal(terminal);
+            eprintln!("Failed to get master key: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let _ = tui::draw_loading(&mut terminal, "Downloading keys from GitHub...");
+    if let Err(e) = tui::run(terminal, storage, master_key, password).await {
+        eprintln!("TUI error: {}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Displays the AxKeyStore application banner
+fn display_banner() {
+    // ANSI color codes
+    const CYAN: &str = "\x1b[36m";
+    const GREEN: &str = "\x1b[32m";
+    const MAGENTA: &str = "\x1b[35m";
+    const RESET: &str = "\x1b[0m";
+    const BOLD: &str = "\x1b[1m";
+    const DIM: &str = "\x1b[2m";
+
+    println!();
+    println!("{CYAN}{BOLD}  ╠═══════════════════════════════════════════════════════════════════╣{RESET}");
+    println!(
+        "{CYAN}{BOLD}  {RESET}  {GREEN}★{RESET} {BOLD}AxKeyStore{RESET} is an {MAGENTA}Open Source Project{RESET} built by {BOLD}Appxiom Team{RESET}"
+    );
+    println!(
+        "{CYAN}{BOLD}  {RESET}                                                                   {RESET}"
+    );
+    println!(
+        "{CYAN}{BOLD}  {RESET}  {DIM}Visit{RESET} {CYAN}{BOLD}https://www.appxiom.com{RESET} {DIM}to know more about us.{RESET}"
+    );
+    println!(
+        "{CYAN}{BOLD}  {RESET}  {DIM}You will love our product if you are into software engineering!{RESET}"
+    );
+    println!("{CYAN}{BOLD}  ╚═══════════════════════════════════════════════════════════════════╝{RESET}");
+    println!();
+}
+
+/// Environment variable that turns the banner off when set to anything but '' or '0'
+const NO_BANNER_VAR: &str = "AXKEYSTORE_NO_BANNER";
+
+/// Returns true if the banner should be printed: only on a terminal, and never for commands
+/// whose output is meant for scripts
+fn show_banner(cli: &Cli) -> bool {
+    let disabled = std::env::var(NO_BANNER_VAR).is_ok_and(|v| !v.is_empty() && v != "0");
+    if cli.quiet || disabled || !std::io::stdout().is_terminal() {
+        return false;
+    }
+    !matches!(
+        cli.command,
+        Some(
+            Commands::Completions { .. }
+                | Commands::TemplateFn { .. }
+                | Commands::Render { .. }
+                | Commands::Env { .. }
+                | Commands::Pipe { .. }
+                | Commands::Compose { .. }
+                | Commands::ServiceAccount {
+                    command: ServiceAccountCommands::Get { .. }
+                }
+                | Commands::Get { raw: true, .. }
+                | Commands::Plugin(_)
+        )
+    )
+}
+
+/// Entry point for the AxKeyStore CLI
+#[tokio::main]
+async fn main() {
+    // Errors may quote GitHub responses or values, so they are printed with secrets masked
+    if let Err(e) = run().await {
+        eprintln!("Error: {}", redact::redact(&format!("{:?}", e)));
+        std::process::exit(1);
+    }
+}
+
+/// Runs the command given on the command line
+async fn run() -> Result<()> {
+    dotenvy::dotenv().ok(); // Load .env file if it exists
+
+    // Answer the shell's completion requests (COMPLETE=<shell>) before anything is printed
+    clap_complete::CompleteEnv::with_factory(Cli::command)
+        .var(completion::COMPLETE_VAR)
+        .complete();
+
+    let cli = Cli::parse();
+
+    if show_banner(&cli) {
+        display_banner();
+    }
+
+    // Determine the effective profile: --profile, then AXKEYSTORE_PROFILE, then the active one
+    let effective_profile = match (&cli.profile, settings::env_profile()?) {
+        (Some(p), _) => {
+            config::Config::validate_profile_name(p)?;
+            Some(p.clone())
+        }
+        (None, Some(p)) => (p != "default").then_some(p),
+        (None, None) => config::GlobalConfig::get_active_profile()?,
+    };
+
+    let profile_str = effective_profile.as_deref().unwrap_or("default");
+
+    let log_file = match config::Config::load_with_profile(effective_profile.as_deref()) {
+        Ok(cfg) if cfg.log_file => Some(logging::log_file_path(effective_profile.as_deref())?),
+        _ => None,
+    };
+    logging::init(cli.verbose, cli.quiet, log_file.as_deref())?;
+    tracing::debug!(
+        "axkeystore {} (profile '{}')",
+        env!("CARGO_PKG_VERSION"),
+        profile_str
+    );
+
+    if let Some(vault) = &cli.vault {
+        if let Err(e) = config::Config::validate_vault_name(vault) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        config::select_vault(vault);
+    } else if let Some(repo) = settings::env_repo()? {
+        // Only the profile this command runs as uses the repository from the environment
+        config::override_repo(effective_profile.as_deref(), &repo);
+    }
+
+    if let Some(padding) = config::Config::load_with_profile(effective_profile.as_deref())
+        .ok()
+        .and_then(|c| c.padding)
+    {
+        crypto::set_padding(padding);
+    }
+
+    // Defaults for options left out on the command line, from the environment or the profile
+    let mut cfg =
+        config::Config::load_with_profile(effective_profile.as_deref()).unwrap_or_default();
+    settings::apply_env(&mut cfg)?;
+    let defaults = cfg.defaults;
+
+    let command = match &cli.command {
+        Some(c) => c,
+        None => {
+            launch_tui(effective_profile.as_deref()).await?;
+            return Ok(());
+        }
+    };
+
+    match command {
+        Commands::Login => {
+            if auth::is_logged_in_with_profile(effective_profile.as_deref()) {
+                let reauth = prompt_yes_no(
+                    "You are already logged in for this profile. Do you want to re-authenticate?",
+                )?;
+                if !reauth {
+                    println!("Login cancelled.");
+                    return Ok(());
+                }
+            }
+
+            let token = match auth::authenticate().await {
+                Ok(t) => t,
+                Err(e) => {
+                    eprintln!("Authentication failed: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            // Check if LMK already exists for this profile
+            let config = config::Config::load_with_profile(effective_profile.as_deref())?;
+            let lmk_exists = config.encrypted_lmk.is_some();
+
+            println!("Setting up master password to secure your token locally...");
+            let password = if lmk_exists {
+                println!("A master password is already set for this profile.");
+                let p = prompt_password("Enter master password")?;
+
+                // Verify the password by trying to decrypt the LMK
+                match config::Config::get_or_create_lmk_with_profile(
+                    effective_profile.as_deref(),
+                    &p,
+                ) {
+                    Ok(_) => p,
+                    Err(_) => {
+                        eprintln!("Incorrect master password.");
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                loop {
+                    let p1 = prompt_password("Set master password")?;
+                    if p1.len() < 8 {
+                        eprintln!("Password must be at least 8 characters long.");
+                        continue;
+                    }
+                    let p2 = prompt_password("Confirm master password")?;
+                    if p1 == p2 {
+                        break p1;
+                    }
+                    eprintln!("Passwords do not match. Please try again.");
+                }
+            };
+
+            auth::save_token_with_profile(effective_profile.as_deref(), &token, &password)?;
+            // Cache the account's login now, so later commands need not look it up
+            if let Ok(login) = storage::fetch_login(&token).await {
+                config::Config::set_login_with_profile(
+                    effective_profile.as_deref(),
+                    &login,
+                    &password,
+                )?;
+            }
+            println!(
+                "Successfully authenticated and secured token for profile '{}'.",
+                effective_profile.as_deref().unwrap_or("default")
+            );
+            println!("\nNext step: If you haven't already, ensure your repository exists on GitHub, then run 'axkeystore init --repo <YOUR_REPO>' to set up your vault.");
+        }
+        Commands::List {
+            names: true,
+            archived,
+        } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let lmk = config::Config::get_or_create_lmk_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            let key_index = match index::load(storage.profile(), &storage.repo_full_name(), &lmk)? {
+                Some(key_index) => key_index,
+                None => {
+                    let master_key = get_or_init_master_key(&storage, &password).await?;
+                    refresh_index(&storage, &password, &master_key, true).await?
+                }
+            };
+
+            if key_index.keys.is_empty() {
+                println!("No keys found in profile '{}'.", profile_str);
+                return Ok(());
+            }
+            let mut grouped: BTreeMap<Option<&str>, Vec<&index::IndexEntry>> = BTreeMap::new();
+            let mut hidden = 0;
+            for entry in key_index.keys.values() {
+                if !*archived && archive::is_archived(entry.category.as_deref()) {
+                    hidden += 1;
+                    continue;
+                }
+                grouped
+                    .entry(entry.category.as_deref())
+                    .or_default()
+                    .push(entry);
+            }
+            for (category, entries) in &grouped {
+                println!("[{}]", category.unwrap_or("(uncategorized)"));
+                for entry in entries {
+                    if entry.derived {
+                        println!("  {} (derived)", entry.name);
+                    } else {
+                        println!("  {}", entry.name);
+                    }
+                }
+            }
+            if hidden > 0 {
+                println!(
+                    "{} archived key(s) hidden. Use --archived to list them.",
+                    hidden
+                );
+            }
+            eprintln!(
+                "(from the local index, refreshed {}; run 'axkeystore index rebuild' after changes made elsewhere)",
+                timeutil::format_rfc3339(key_index.refreshed)
+            );
+        }
+        Commands::List {
+            names: false,
+            archived,
+        } => {
+            let password = prompt_password("Enter master password")?;
+            let repo_name = config::Config::get_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            let storage = storage::Storage::new_with_profile(
+                effective_profile.as_deref(),
+                &repo_name,
+                &password,
+            )
+            .await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let entries = storage.list_all_keys().await?;
+            let registry = derived::load(&storage, &master_key).await?;
+
+            // A full listing is a cheap moment to refresh the category index used by prompts
+            let mut index = categories::from_entries(&entries);
+            index.extend(
+                registry
+                    .keys
+                    .keys()
+                    .filter_map(|id| id.rsplit_once('/').map(|(cat, _)| cat.to_string())),
+            );
+            let lmk = config::Config::get_or_create_lmk_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            categories::store_index(
+                effective_profile.as_deref(),
+                &storage.repo_full_name(),
+                &lmk,
+                &index,
+            )?;
+
+            if entries.is_empty() && registry.keys.is_empty() {
+                println!("No keys found in profile '{}'.", profile_str);
+                return Ok(());
+            }
+
+            // Group entries by category
+            let mut grouped: BTreeMap<Option<String>, Vec<(String, String)>> = BTreeMap::new();
+            let mut hidden = 0;
+
+            for entry in &entries {
+                // Archived values are not even decrypted unless asked for
+                if !*archived && archive::is_archived(entry.category.as_deref()) {
+                    hidden += 1;
+                    continue;
+                }
+                let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&entry.data)
+                    .context("Failed to parse encrypted blob")?;
+                let decrypted = crypto::CryptoHandler::decrypt(&encrypted, &master_key)?;
+                let reserved = if decrypted.is_empty() {
+                    meta::load(
+                        &storage,
+                        &master_key,
+                        &entry.name,
+                        entry.category.as_deref(),
+                    )
+                    .await?
+                    .reserved
+                } else {
+                    None
+                };
+                let value = match reserved {
+                    Some(r) => format!("<reserved by {}>", r.owner),
+                    None => String::from_utf8(decrypted)
+                        .unwrap_or_else(|e| format!("<binary, {} bytes>", e.as_bytes().len())),
+                };
+
+                grouped
+                    .entry(entry.category.clone())
+                    .or_default()
+                    .push((entry.name.clone(), value));
+            }
+
+            for (id, d) in &registry.keys {
+                let value = derived::derive_value(&master_key, id, d.counter, d.length)?;
+                let (category, name) = match id.rsplit_once('/') {
+                    Some((cat, name)) => (Some(cat.to_string()), name.to_string()),
+                    None => (None, id.clone()),
+                };
+                grouped
+                    .entry(category)
+                    .or_default()
+                    .push((format!("{} (derived)", name), value));
+            }
+
+            // ANSI color codes for display
+            const CYAN: &str = "\x1b[36m";
+            const BOLD: &str = "\x1b[1m";
+            const DIM: &str = "\x1b[2m";
+            const RESET: &str = "\x1b[0m";
+
+            println!(
+                "\n{}{}Stored Keys for profile '{}'{}",
+                BOLD, CYAN, profile_str, RESET
+            );
+            println!();
+
+            // Find the max key name length for alignment
+            let max_name_len = grouped
+                .values()
+                .flat_map(|pairs| pairs.iter().map(|(name, _)| name.len()))
+                .max()
+                .unwrap_or(0);
+
+            for (category, pairs) in &grouped {
+                match category {
+                    Some(cat) => println!("{}{}[{}]{}", BOLD, CYAN, cat, RESET),
+                    None => println!("{}{}(uncategorized){}", DIM, CYAN, RESET),
+                }
+                for (name, value) in pairs {
+                    println!("  {:<width$} = {}", name, value, width = max_name_len);
+                }
+                println!();
+            }
+            if hidden > 0 {
+                println!(
+                    "{}{} archived key(s) hidden. Use --archived to list them.{}",
+                    DIM, hidden, RESET
+                );
+            }
+        }
+        Commands::Totp { key, category } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let display_path = match &category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+
+            let data = match storage.get_blob(key, category.as_deref()).await? {
+                Some((data, _)) => data,
+                None => {
+                    eprintln!("Key '{}' not found.", display_path);
+                    std::process::exit(1);
+                }
+            };
+            let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&data)?;
+            let decrypted = crypto::CryptoHandler::decrypt(&encrypted, &master_key)?;
+            let seed = String::from_utf8(decrypted).context("Decrypted data is not valid UTF-8")?;
+            let generator = totp::Totp::parse(&seed)
+                .with_context(|| format!("Key '{}' does not hold a TOTP seed", display_path))?;
+
+            let now = timeutil::now_unix();
+            println!(
+                "{}  (valid for {}s)",
+                generator.code_at(now),
+                generator.remaining_secs(now)
+            );
+        }
+        Commands::Rotate { key, category } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let id = derived::key_id(key, category.as_deref())?;
+            let mut registry = derived::load(&storage, &master_key).await?;
+            let counter = match registry.keys.get_mut(&id) {
+                Some(d) => {
+                    d.counter += 1;
+                    d.counter
+                }
+                None => {
+                    eprintln!(
+                        "Key '{}' is not a derived key. Use 'axkeystore store' to change a stored value.",
+                        id
+                    );
+                    std::process::exit(1);
+                }
+            };
+            derived::save(
+                &storage,
+                &master_key,
+                &registry,
+                &format!("Rotate derived key: {} (counter {})", id, counter),
+            )
+            .await?;
+            println!(
+                "Key '{}' rotated (counter {}). Use 'axkeystore get' to read the new value.",
+                id, counter
+            );
+            notify_change(&storage, &master_key, "rotated", &[id]).await;
+        }
+        Commands::Verify {
+            category,
+            against_backup: Some(file),
+            sample,
+        } => {
+            let password = prompt_password("Enter master password")?;
+            let archive = match backup::read(file, &password) {
+                Ok(archive) => archive,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            // Older backups lack the master key, so the drill can only borrow the live one
+            let live_master_key = if archive.master_key.is_none() {
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                match storage.get_master_key_blob().await? {
+                    Some(_) => Some(get_or_init_master_key(&storage, &password).await?),
+                    None => None,
+                }
+            } else {
+                None
+            };
+
+            let drill = match backup::drill(
+                &archive,
+                &password,
+                live_master_key.as_deref(),
+                category.as_deref(),
+                *sample,
+            ) {
+                Ok(drill) => drill,
+                Err(e) => {
+                    eprintln!("Recovery would fail: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            println!(
+                "Recovery drill for the backup of '{}' taken {}:",
+                archive.repo, archive.created
+            );
+            println!("  Restored {} key(s) into memory.", drill.restored);
+            match drill.master_key {
+                backup::MasterKeySource::Backup => {
+                    println!("  Master key: in the backup, decrypts with the master password.")
+                }
+                backup::MasterKeySource::LiveVault => println!(
+                    "  Master key: NOT in the backup; the live vault's was used for this drill."
+                ),
+                backup::MasterKeySource::Missing => {
+                    println!("  Master key: neither in the backup nor in the live vault.")
+                }
+            }
+            let failed: Vec<_> = drill
+                .sampled
+                .iter()
+                .filter(|(_, r)| *r != backup::Recovery::Ok)
+                .collect();
+            println!(
+                "  Read back {} key(s): {} ok, {} failed.",
+                drill.sampled.len(),
+                drill.sampled.len() - failed.len(),
+                failed.len()
+            );
+            for (path, recovery) in &failed {
+                match recovery {
+                    backup::Recovery::Corrupted(e) => println!("  CORRUPTED   {}: {}", path, e),
+                    _ => println!(
+                        "  UNREADABLE  {}: does not decrypt with the master key",
+                        path
+                    ),
+                }
+            }
+
+            if drill.passed() {
+                println!("\nRecovery from this backup would work.");
+            } else {
+                println!("\nRecovery from this backup would NOT work.");
+                if drill.master_key != backup::MasterKeySource::Backup {
+                    println!("Take a new backup with 'axkeystore backup create'; it includes the master key.");
+                }
+                std::process::exit(1);
+            }
+        }
+        Commands::Verify { category, .. } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            handle_ctrl_c();
+            let entries: Vec<_> = match storage.list_all_keys().await {
+                Ok(entries) => entries,
+                Err(e) if cancel::is_cancelled(&e) => {
+                    exit_cancelled("Verification cancelled before any key was checked.")
+                }
+                Err(e) => return Err(e),
+            }
+            .into_iter()
+            .filter(|e| category.as_deref().is_none_or(|c| e.in_category(c)))
+            .collect();
+
+            let mut checked = 0;
+            let mut corrupted = 0;
+            let mut foreign = 0;
+            let progress = progress::Progress::new(entries.len(), "Verifying");
+            for entry in &entries {
+                if cancel::is_requested() {
+                    break;
+                }
+                checked += 1;
+                let path = entry.display_path();
+                progress.item(&path);
+                let encrypted: crypto::EncryptedBlob = match serde_json::from_slice(&entry.data) {
+                    Ok(b) => b,
+                    Err(_) => {
+                        progress.fail(&format!("CORRUPTED  {}: not a valid encrypted blob", path));
+                        corrupted += 1;
+                        continue;
+                    }
+                };
+                if let Err(e) = crypto::CryptoHandler::check_structure(&encrypted) {
+                    progress.fail(&format!("CORRUPTED  {}: {}", path, e));
+                    corrupted += 1;
+                    continue;
+                }
+                if crypto::CryptoHandler::decrypt(&encrypted, &master_key).is_err() {
+                    progress.fail(&format!(
+                        "FOREIGN    {}: cannot be decrypted with this vault's master key",
+                        path
+                    ));
+                    foreign += 1;
+                    continue;
+                }
+                progress.succeed();
+            }
+            progress.finish();
+
+            println!(
+                "Checked {} keys: {} ok, {} corrupted, {} foreign.",
+                checked,
+                checked - corrupted - foreign,
+                corrupted,
+                foreign
+            );
+            if checked < entries.len() {
+                exit_cancelled(&format!(
+                    "Verification cancelled; {} of {} keys were not checked.",
+                    entries.len() - checked,
+                    entries.len()
+                ));
+            }
+            if corrupted + foreign > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::Init {
+            repo,
+            prefix,
+            branch,
+        } => {
+            if let Some(vault) = config::selected_vault() {
+                eprintln!(
+                    "'init' sets up the profile's own repository. Use 'axkeystore vault add {} {}' to add a vault.",
+                    vault, repo
+                );
+                std::process::exit(1);
+            }
+            let prefix = match prefix.as_deref().map(storage::validate_prefix).transpose() {
+                Ok(prefix) => prefix,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            let branch = match branch.as_deref().map(storage::validate_branch).transpose() {
+                Ok(branch) => branch,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            let password = prompt_password("Enter master password")?;
+            let mut storage =
+                storage::Storage::new_with_profile(effective_profile.as_deref(), repo, &password)
+                    .await?;
+            storage.set_prefix(prefix.clone());
+            storage.set_branch(branch.clone());
+            init_repo(&storage).await?;
+
+            // Never mix the vault into a directory that already holds other content
+            if let Some(prefix) = &prefix {
+                if storage.get_master_key_blob().await?.is_none()
+                    && !storage.list_dir("").await?.is_empty()
+                {
+                    eprintln!(
+                        "'{}' in repository {} already holds other files. Choose a new or empty directory for the vault.",
+                        prefix,
+                        storage.repo_full_name()
+                    );
+                    std::process::exit(1);
+                }
+            }
+
+            // Verify if the password matches the remote master key (if it exists)
+            if let Some(blob) = storage.get_master_key_blob().await? {
+                let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&blob)
+                    .context("Failed to parse master key blob from GitHub")?;
+
+                if crypto::CryptoHandler::decrypt(&encrypted, &password).is_err() {
+                    eprintln!("\nError: The provided password is incorrect for this repository.");
+                    eprintln!("   This repository already has a master key encrypted with a different password.");
+                    eprintln!(
+                        "   Please provide the correct password to sync with this repository.\n"
+                    );
+                    std::process::exit(1);
+                }
+                println!("Master password verified against existing repository.");
+            }
+
+            // A new vault gets its master key now, with a recovery code shown once
+            let recovery_code = if storage.get_master_key_blob().await?.is_none() {
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+                let code = recovery::generate_code();
+                recovery::save_code(&storage, &master_key, &code).await?;
+                Some(code)
+            } else {
+                None
+            };
+
+            // Saved with its owner, so it never depends on the login of the token in use
+            config::Config::set_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &storage.repo_full_name(),
+                &password,
+            )?;
+            let mut cfg = config::Config::load_with_profile(effective_profile.as_deref())?;
+            cfg.storage_prefix = prefix;
+            cfg.branch = branch;
+            cfg.save_with_profile(effective_profile.as_deref())?;
+            println!(
+                "Configuration saved for profile '{}'.",
+                effective_profile.as_deref().unwrap_or("default")
+            );
+            if let Some(code) = recovery_code {
+                print_recovery_code(&storage.repo_full_name(), &code);
+            }
+        }
+        Commands::Store {
+            key,
+            value,
+            prompt: _,
+            generate,
+            category,
+            if_absent,
+            derived,
+            length,
+            totp,
+            file,
+            stdin,
+            keep_newline,
+            expires,
+            delete_after,
+            tags,
+            description,
+            owner,
+            url,
+        } => {
+            let category = &category_or_default(category, key, &defaults);
+            let now = timeutil::now_unix();
+            let expires = expires
+                .as_deref()
+                .map(|e| timeutil::parse_expiry(e, now))
+                .transpose()?;
+            if expires.is_some_and(|e| e <= now) {
+                eprintln!("The expiry must be in the future.");
+                std::process::exit(1);
+            }
+            let delete_after = delete_after
+                .as_deref()
+                .map(|d| timeutil::parse_expiry(d, now))
+                .transpose()?;
+            if delete_after.is_some_and(|d| d <= now) {
+                eprintln!("The deletion deadline must be in the future.");
+                std::process::exit(1);
+            }
+            let update = meta::MetaUpdate {
+                description: description.clone(),
+                tags: (!tags.is_empty()).then(|| tags.clone()),
+                owner: owner.clone(),
+                url: url.clone(),
+                expires,
+                delete_after,
+            };
+
+            let value: Option<Vec<u8>> = match (totp, file) {
+                (Some(seed), _) => {
+                    totp::Totp::parse(seed).context("Invalid TOTP seed")?;
+                    Some(seed.trim().as_bytes().to_vec())
+                }
+                (_, Some(path)) => {
+                    let data = std::fs::read(path)
+                        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+                    if data.len() > storage::MAX_VALUE_SIZE {
+                        eprintln!(
+                            "'{}' is {} bytes; values are limited to {} bytes.",
+                            path.display(),
+                            data.len(),
+                            storage::MAX_VALUE_SIZE
+                        );
+                        std::process::exit(1);
+                    }
+                    Some(data)
+                }
+                _ if *stdin => {
+                    // Read before any prompt; passwords are read from the terminal
+                    let mut data = Vec::new();
+                    std::io::stdin()
+                        .take(storage::MAX_VALUE_SIZE as u64 + 1)
+                        .read_to_end(&mut data)
+                        .context("Failed to read the value from standard input")?;
+                    if data.len() > storage::MAX_VALUE_SIZE {
+                        eprintln!(
+                            "Standard input holds more than {} bytes, the limit for values.",
+                            storage::MAX_VALUE_SIZE
+                        );
+                        std::process::exit(1);
+                    }
+                    if !*keep_newline && data.ends_with(b"\n") {
+                        data.pop();
+                        if data.ends_with(b"\r") {
+                            data.pop();
+                        }
+                    }
+                    if data.is_empty() {
+                        eprintln!("Standard input was empty; nothing to store.");
+                        std::process::exit(1);
+                    }
+                    Some(data)
+                }
+                _ => value.as_ref().map(|v| {
+                    if std::io::stdin().is_terminal() {
+                        eprintln!("Warning: values passed with --value are saved in your shell history and visible to other users through 'ps'. Omit --value to be prompted for it instead.");
+                    }
+                    v.as_bytes().to_vec()
+                }),
+            };
+
+            let password = prompt_password("Enter master password")?;
+            let repo_name = config::Config::get_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            let storage = storage::Storage::new_with_profile(
+                effective_profile.as_deref(),
+                &repo_name,
+                &password,
+            )
+            .await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let category = match category {
+                Some(cat) => Some(cat.clone()),
+                None if !*stdin && std::io::stdin().is_terminal() => {
+                    prompt_category(&storage, &password).await?
+                }
+                None => None,
+            };
+
+            let display_path = match &category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+
+            if *derived {
+                let id = derived::key_id(key, category.as_deref())?;
+                derived::validate_length(*length)?;
+                if storage.get_blob(key, category.as_deref()).await?.is_some() {
+                    eprintln!(
+                        "Key '{}' already has a stored value. Delete it before making it derived.",
+                        display_path
+                    );
+                    std::process::exit(1);
+                }
+
+                let mut registry = derived::load(&storage, &master_key).await?;
+                if registry.keys.contains_key(&id) {
+                    eprintln!(
+                        "Key '{}' is already derived. Use 'axkeystore rotate' to change its value.",
+                        display_path
+                    );
+                    std::process::exit(1);
+                }
+                registry.keys.insert(
+                    id.clone(),
+                    derived::DerivedKey {
+                        counter: 1,
+                        length: *length,
+                    },
+                );
+                derived::save(
+                    &storage,
+                    &master_key,
+                    &registry,
+                    &format!("Add derived key: {}", id),
+                )
+                .await?;
+                if !update.is_empty() {
+                    let changes = meta::update_changes(
+                        &storage,
+                        &master_key,
+                        key,
+                        category.as_deref(),
+                        &update,
+                    )
+                    .await?;
+                    storage
+                        .apply_changes(&changes, &format!("Update metadata of key: {}", id))
+                        .await?;
+                }
+                remember_keys(&storage, &password, category.as_deref(), &[key], true, None)?;
+                println!(
+                    "Key '{}' is now derived from the master key; its value is never stored.",
+                    display_path
+                );
+                return Ok(());
+            }
+
+            if *if_absent {
+                let final_value = match &value {
+                    Some(v) => v.clone(),
+                    None => generate_value(
+                        effective_profile.as_deref(),
+                        category.as_deref(),
+                        &display_path,
+                    )?,
+                };
+                wasmplugin::check_policy(
+                    effective_profile.as_deref(),
+                    category.as_deref(),
+                    &display_path,
+                    &final_value,
+                )?;
+                let encrypted = encrypt_value(&master_key, category.as_deref(), &final_value)?;
+                let json_blob = serde_json::to_vec(&encrypted)?;
+
+                if storage
+                    .create_blob(key, &json_blob, category.as_deref())
+                    .await?
+                {
+                    if !update.is_empty() {
+                        let changes = meta::update_changes(
+                            &storage,
+                            &master_key,
+                            key,
+                            category.as_deref(),
+                            &update,
+                        )
+                        .await?;
+                        storage
+                            .apply_changes(
+                                &changes,
+                                &format!("Update metadata of key: {}", display_path),
+                            )
+                            .await?;
+                    }
+                    remember_keys(
+                        &storage,
+                        &password,
+                        category.as_deref(),
+                        &[key],
+                        false,
+                        None,
+                    )?;
+                    println!("Key '{}' stored successfully.", display_path);
+                    notify_change(&storage, &master_key, "stored", &[display_path]).await;
+                } else {
+                    println!("Key '{}' already exists. Left unchanged.", display_path);
+                }
+                return Ok(());
+            }
+
+            // Check if key already exists
+            let mut key_meta = None;
+            let mut base_sha = None;
+            if let Ok(Some((_, sha))) = storage.get_blob(key, category.as_deref()).await {
+                base_sha = Some(sha);
+                let existing = meta::load(&storage, &master_key, key, category.as_deref()).await?;
+                let question = match &existing.reserved {
+                    // Filling in one's own reservation needs no confirmation
+                    Some(r) if r.owner == storage.login() => None,
+                    Some(r) => Some(format!(
+                        "Key '{}' is reserved by {} (since {}). Store a value anyway?",
+                        display_path, r.owner, r.since
+                    )),
+                    None => Some(format!(
+                        "Key '{}' already exists. Do you want to update it?",
+                        display_path
+                    )),
+                };
+                if let Some(question) = question {
+                    // Standard input held the value, so nothing is left to answer with
+                    if *stdin {
+                        eprintln!("Key '{}' already exists. Values read with --stdin only create keys; delete it first to replace its value.", display_path);
+                        std::process::exit(1);
+                    }
+                    if !prompt_yes_no(&question)? {
+                        println!("Update cancelled.");
+                        return Ok(());
+                    }
+                }
+                key_meta = Some(existing);
+            }
+
+            // Determine the value to store
+            let final_value = match &value {
+                Some(v) => v.clone(),
+                None if !*generate => {
+                    let entered = prompt_password(&format!("Enter value for '{}'", display_path))?;
+                    if entered.is_empty() {
+                        eprintln!("Value cannot be empty.");
+                        std::process::exit(1);
+                    }
+                    if prompt_password("Confirm value")? != entered {
+                        eprintln!("Values do not match.");
+                        std::process::exit(1);
+                    }
+                    entered.into_bytes()
+                }
+                None => {
+                    let generated = generate_value(
+                        effective_profile.as_deref(),
+                        category.as_deref(),
+                        &display_path,
+                    )?;
+                    let generated = String::from_utf8_lossy(&generated).into_owned();
+                    println!("\nGenerated value: {}", generated);
+                    println!("   (Length: {} characters)\n", generated.len());
+
+                    let confirmed = prompt_yes_no("Do you want to use this generated value?")?;
+
+                    if !confirmed {
+                        println!("Operation cancelled.");
+                        return Ok(());
+                    }
+                    generated.into_bytes()
+                }
+            };
+
+            wasmplugin::check_policy(
+                effective_profile.as_deref(),
+                category.as_deref(),
+                &display_path,
+                &final_value,
+            )?;
+
+            // Metadata changes, or a reservation being filled in, land in the same commit as the value
+            let meta_update = match key_meta {
+                Some(mut m) if m.reserved.is_some() || !update.is_empty() => {
+                    m.reserved = None;
+                    update.apply(&mut m);
+                    Some(m)
+                }
+                None if !update.is_empty() => {
+                    let mut m = meta::load(&storage, &master_key, key, category.as_deref()).await?;
+                    update.apply(&mut m);
+                    Some(m)
+                }
+                _ => None,
+            };
+
+            match &meta_update {
+                Some(m) => {
+                    let encrypted = encrypt_value(&master_key, category.as_deref(), &final_value)?;
+                    let json_blob = serde_json::to_vec(&encrypted)?;
+                    let mut changes = storage
+                        .key_changes(key, category.as_deref(), Some(&json_blob))
+                        .await?;
+                    changes.extend(
+                        meta::save_changes(&storage, &master_key, key, category.as_deref(), m)
+                            .await?,
+                    );
+                    storage
+                        .apply_changes(&changes, &format!("Update key: {}", display_path))
+                        .await?;
+                }
+                None => {
+                    if !save_checked(
+                        &storage,
+                        &master_key,
+                        key,
+                        category.as_deref(),
+                        final_value,
+                        base_sha,
+                    )
+                    .await?
+                    {
+                        println!("Nothing was saved.");
+                        return Ok(());
+                    }
+                }
+            }
+            remember_keys(
+                &storage,
+                &password,
+                category.as_deref(),
+                &[key],
+                false,
+                meta_update.as_ref(),
+            )?;
+
+            println!("Key '{}' stored successfully.", display_path);
+            notify_change(&storage, &master_key, "stored", &[display_path]).await;
+        }
+        Commands::Get {
+            key,
+            category,
+            version,
+            verify,
+            create_if_missing,
+            generate: _,
+            out,
+            verify_against,
+            against_profile,
+            archived,
+            raw,
+            format,
+        } => {
+            let category = &category_or_default(category, key, &defaults);
+            // Piped output gets the value alone, as if --raw were given
+            let raw = *raw || !std::io::stdout().is_terminal();
+            let display_path = match category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+            if archive::is_archived(category.as_deref()) && !*archived {
+                if !std::io::stdin().is_terminal() {
+                    eprintln!(
+                        "'{}' is archived. Pass --archived to read it anyway.",
+                        display_path
+                    );
+                    std::process::exit(1);
+                }
+                if !prompt_yes_no(&format!(
+                    "'{}' is archived and should not be in use. Read it anyway?",
+                    display_path
+                ))? {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+            }
+            if keyglob::is_pattern(&display_path) {
+                if version.is_some()
+                    || *verify
+                    || *create_if_missing
+                    || out.is_some()
+                    || verify_against.is_some()
+                    || format.is_some()
+                {
+                    eprintln!("A pattern cannot be combined with --version, --verify, --create-if-missing, --out, --verify-against or --format.");
+                    std::process::exit(1);
+                }
+                return get_matching(effective_profile.as_deref(), &display_path).await;
+            }
+            let format = &format.or(defaults.output);
+
+            // Prompt on the terminal, so stdout carries nothing but the value
+            let password = rpassword::prompt_password("Enter master password: ")
+                .context("Failed to read password")?;
+            let repo_name = config::Config::get_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            let storage = storage::Storage::new_with_profile(
+                effective_profile.as_deref(),
+                &repo_name,
+                &password,
+            )
+            .await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            if let Some(other) = verify_against {
+                let (other_category, other_key) = match other.trim_matches('/').rsplit_once('/') {
+                    Some((cat, name)) => (Some(cat), name),
+                    None => (None, other.trim_matches('/')),
+                };
+                let first = read_value(&storage, &master_key, key, category.as_deref()).await?;
+                let second = match against_profile {
+                    Some(p) if Some(p) != effective_profile.as_ref() => {
+                        config::Config::validate_profile_name(p)?;
+                        let other_vault = ProfileVault::unlock(Some(p), true).await?;
+                        read_value(
+                            &other_vault.storage,
+                            &other_vault.master_key,
+                            other_key,
+                            other_category,
+                        )
+                        .await?
+                    }
+                    _ => read_value(&storage, &master_key, other_key, other_category).await?,
+                };
+                let (first, second) = match (first, second) {
+                    (Some(a), Some(b)) => (a, b),
+                    (None, _) => {
+                        eprintln!("Key '{}' not found.", display_path);
+                        std::process::exit(1);
+                    }
+                    (_, None) => {
+                        eprintln!("Key '{}' not found.", other);
+                        std::process::exit(1);
+                    }
+                };
+
+                let comparison = parity::compare(&first, &second);
+                if comparison.equal {
+                    println!(
+                        "'{}' and '{}' are identical ({} bytes).",
+                        display_path, other, comparison.first_len
+                    );
+                    return Ok(());
+                }
+                println!("'{}' and '{}' differ.", display_path, other);
+                println!(
+                    "  Lengths:         {} and {} bytes",
+                    comparison.first_len, comparison.second_len
+                );
+                println!(
+                    "  Matching prefix: first {} bytes",
+                    comparison.common_prefix
+                );
+                std::process::exit(1);
+            }
+
+            // A version may also be given by a label set with 'tag set'
+            let version = match version {
+                Some(v) => Some(
+                    meta::load(&storage, &master_key, key, category.as_deref())
+                        .await?
+                        .resolve_version(v)
+                        .to_string(),
+                ),
+                None => None,
+            };
+            let data = if let Some(sha) = &version {
+                storage
+                    .get_blob_at_version(key, category.as_deref(), sha)
+                    .await?
+            } else {
+                storage
+                    .get_blob(key, category.as_deref())
+                    .await?
+                    .map(|(d, _)| d)
+            };
+
+            if data.is_none() && version.is_none() {
+                let id = derived::key_id(key, category.as_deref())?;
+                let registry = derived::load(&storage, &master_key).await?;
+                if let Some(d) = registry.keys.get(&id) {
+                    let value = derived::derive_value(&master_key, &id, d.counter, d.length)?;
+                    output_value(
+                        &display_path,
+                        value.as_bytes(),
+                        out.as_deref(),
+                        raw,
+                        *format,
+                    )?;
+                    return Ok(());
+                }
+            }
+
+            let data = match data {
+                None if *create_if_missing => {
+                    let generated = generate_value(
+                        effective_profile.as_deref(),
+                        category.as_deref(),
+                        &display_path,
+                    )?;
+                    wasmplugin::check_policy(
+                        effective_profile.as_deref(),
+                        category.as_deref(),
+                        &display_path,
+                        &generated,
+                    )?;
+                    let encrypted = encrypt_value(&master_key, category.as_deref(), &generated)?;
+                    let json_blob = serde_json::to_vec(&encrypted)?;
+
+                    if storage
+                        .create_blob(key, &json_blob, category.as_deref())
+                        .await?
+                    {
+                        remember_keys(
+                            &storage,
+                            &password,
+                            category.as_deref(),
+                            &[key],
+                            false,
+                            None,
+                        )?;
+                        eprintln!("Key '{}' did not exist and was created.", display_path);
+                        output_value(&display_path, &generated, out.as_deref(), raw, *format)?;
+                        return Ok(());
+                    }
+                    // Another run created the key concurrently, so return its value instead
+                    storage
+                        .get_blob(key, category.as_deref())
+                        .await?
+                        .map(|(d, _)| d)
+                }
+                other => other,
+            };
+
+            if let Some(data) = data {
+                let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&data)?;
+                let decrypted = crypto::CryptoHandler::decrypt(&encrypted, &master_key)?;
+
+                if decrypted.is_empty() && version.is_none() {
+                    let key_meta =
+                        meta::load(&storage, &master_key, key, category.as_deref()).await?;
+                    if let Some(r) = key_meta.reserved {
+                        eprintln!(
+                            "Key '{}' is reserved by {} (since {}) and has no value yet.",
+                            display_path, r.owner, r.since
+                        );
+                        std::process::exit(1);
+                    }
+                }
+
+                let signing =
+                    config::Config::load_with_profile(effective_profile.as_deref())?.signing;
+                if *verify || signing.verify_on_get {
+                    let latest = match &version {
+                        Some(sha) => {
+                            use futures::TryStreamExt;
+                            let history = storage.key_history_stream(
+                                key,
+                                category.as_deref(),
+                                storage::HistoryFilter::default(),
+                            );
+                            futures::pin_mut!(history);
+                            history
+                                .try_filter(|v| std::future::ready(v.sha.starts_with(sha.as_str())))
+                                .try_next()
+                                .await?
+                        }
+                        None => {
+                            storage
+                                .get_latest_key_version(key, category.as_deref())
+                                .await?
+                        }
+                    };
+                    match latest {
+                        Some(v) => {
+                            if let Some(warning) =
+                                signing::verification_warning(&v, &signing.trusted_signers)
+                            {
+                                eprintln!("Warning: '{}': {}.", display_path, warning);
+                            }
+                        }
+                        None => eprintln!(
+                            "Warning: Could not find the commit for '{}' to verify.",
+                            display_path
+                        ),
+                    }
+                }
+
+                output_value(&display_path, &decrypted, out.as_deref(), raw, *format)?;
+            } else {
+                eprintln!("Key '{}' not found.", display_path);
+                std::process::exit(1);
+            }
+        }
+        Commands::History {
+            key,
+            category,
+            interactive,
+            stat,
+            since,
+            until,
+            action,
+            limit,
+            json,
+        } => {
+            let parse_date = |date: &Option<String>| match date {
+                Some(d) => match timeutil::parse_timestamp(d) {
+                    Ok(ts) => Some(ts),
+                    Err(e) => {
+                        eprintln!("Invalid date '{}': {}", d, e);
+                        std::process::exit(1);
+                    }
+                },
+                None => None,
+            };
+            let filter = storage::HistoryFilter {
+                since: parse_date(since),
+                until: parse_date(until),
+                action: *action,
+            };
+            if *limit == Some(0) {
+                eprintln!("--limit must be at least 1.");
+                std::process::exit(1);
+            }
+
+            let password = prompt_password("Enter master password")?;
+            let repo_name = config::Config::get_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            let storage = storage::Storage::new_with_profile(
+                effective_profile.as_deref(),
+                &repo_name,
+                &password,
+            )
+            .await?;
+
+            if *interactive {
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+                interactive_history(&storage, &master_key, key, category.as_deref(), &filter)
+                    .await?;
+                return Ok(());
+            }
+
+            if *json {
+                use futures::{StreamExt, TryStreamExt};
+                let versions: Vec<storage::KeyVersion> = storage
+                    .key_history_stream(key, category.as_deref(), filter)
+                    .take(limit.unwrap_or(usize::MAX))
+                    .try_collect()
+                    .await?;
+                println!("{}", serde_json::to_string_pretty(&versions)?);
+                return Ok(());
+            }
+
+            let mut page = 1;
+            let mut shown = 0;
+            let mut sizes: std::collections::HashMap<String, Option<u64>> =
+                std::collections::HashMap::new();
+            loop {
+                let page_versions = storage
+                    .get_filtered_key_history(key, category.as_deref(), &filter, page, 10)
+                    .await?;
+                // --action is applied here: GitHub only filters by path and period
+                let mut matching: Vec<usize> = (0..page_versions.len())
+                    .filter(|&i| filter.matches(&page_versions[i]))
+                    .collect();
+                if let Some(limit) = limit {
+                    matching.truncate(limit - shown);
+                }
+                if matching.is_empty() {
+                    if page_versions.len() == 10 {
+                        // Nothing on this page was made by that action, but older versions may be
+                        page += 1;
+                        continue;
+                    }
+                    if shown == 0 {
+                        println!("No history found for key '{}'.", key);
+                    } else {
+                        println!("No more versions found.");
+                    }
+                    break;
+                }
+                let versions: Vec<&storage::KeyVersion> =
+                    matching.iter().map(|&i| &page_versions[i]).collect();
+
+                println!("\nVersion History for '{}':", key);
+                if !*stat {
+                    println!(
+                        "{:<40} | {:<25} | {:<20} | {}",
+                        "SHA", "Date", "Author", "Message"
+                    );
+                    println!("{:-<40}-+-{:-<25}-+-{:-<20}-+-{:-<20}", "", "", "", "");
+
+                    for v in &versions {
+                        println!(
+                            "{:<40} | {:<25} | {:<20} | {}",
+                            v.sha,
+                            v.date,
+                            v.author.as_deref().unwrap_or("-"),
+                            v.message
+                        );
+                    }
+                } else {
+                    // The change of each version is relative to the version before it, whatever
+                    // its action: the next one on this page, or the first one of the next page
+                    // (even if it was committed before --since)
+                    let mut rows = Vec::with_capacity(matching.len());
+                    for &i in &matching {
+                        let older = match page_versions.get(i + 1) {
+                            Some(v) => Some(v.clone()),
+                            None => storage
+                                .get_filtered_key_history(
+                                    key,
+                                    category.as_deref(),
+                                    &storage::HistoryFilter {
+                                        since: None,
+                                        ..filter
+                                    },
+                                    page * 10 + 1,
+                                    1,
+                                )
+                                .await?
+                                .into_iter()
+                                .next(),
+                        };
+                        rows.push((&page_versions[i], older));
+                    }
+                    for (v, older) in &rows {
+                        for sha in std::iter::once(&v.sha).chain(older.as_ref().map(|o| &o.sha)) {
+                            if !sizes.contains_key(sha) {
+                                let size = storage
+                                    .blob_size_at_version(key, category.as_deref(), sha)
+                                    .await?;
+                                sizes.insert(sha.clone(), size);
+                            }
+                        }
+                    }
+
+                    println!(
+                        "{:<40} | {:<25} | {:<20} | {:>10} | {:>11} | {}",
+                        "SHA", "Date", "Author", "Size", "Change", "Message"
+                    );
+                    println!(
+                        "{:-<40}-+-{:-<25}-+-{:-<20}-+-{:-<10}-+-{:-<11}-+-{:-<20}",
+                        "", "", "", "", "", ""
+                    );
+                    for (v, older) in &rows {
+                        let size = sizes[&v.sha];
+                        let previous = older.as_ref().and_then(|o| sizes[&o.sha]);
+                        println!(
+                            "{:<40} | {:<25} | {:<20} | {:>10} | {:>11} | {}{}",
+                            v.sha,
+                            v.date,
+                            v.author.as_deref().unwrap_or("-"),
+                            size.map_or("deleted".to_string(), format_size),
+                            size_change(previous, size),
+                            v.message,
+                            if is_unusual_growth(previous, size) {
+                                "  <- unusual size jump"
+                            } else {
+                                ""
+                            }
+                        );
+                    }
+                }
+
+                shown += versions.len();
+                if page_versions.len() < 10 || limit.is_some_and(|limit| shown >= limit) {
+                    break;
+                }
+
+                if !prompt_yes_no("\nShow more versions?")? {
+                    break;
+                }
+                page += 1;
+            }
+        }
+        Commands::Log {
+            action,
+            since,
+            until,
+            json,
+        } => {
+            let parse_date = |date: &Option<String>| match date {
+                Some(d) => match timeutil::parse_timestamp(d) {
+                    Ok(ts) => Some(ts),
+                    Err(e) => {
+                        eprintln!("Invalid date '{}': {}", d, e);
+                        std::process::exit(1);
+                    }
+                },
+                None => None,
+            };
+            let (since, until) = (parse_date(since), parse_date(until));
+
+            let password = prompt_password("Enter master password")?;
+            let repo_name = config::Config::get_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            let storage = storage::Storage::new_with_profile(
+                effective_profile.as_deref(),
+                &repo_name,
+                &password,
+            )
+            .await?;
+
+            let events: Vec<audit::AuditEvent> = audit::load(&storage)
+                .await?
+                .into_iter()
+                .rev()
+                .filter(|e| action.is_empty() || action.iter().any(|a| e.has_action(a)))
+                .filter(|e| {
+                    // Events with a time that cannot be read are only hidden by a period filter
+                    match timeutil::parse_timestamp(&e.time) {
+                        Ok(t) => since.is_none_or(|s| t >= s) && until.is_none_or(|u| t < u),
+                        Err(_) => since.is_none() && until.is_none(),
+                    }
+                })
+                .collect();
+
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&events)?);
+            } else if events.is_empty() {
+                println!("No audit events found.");
+            } else {
+                println!("{:<25} | {:<24} | {}", "Time", "Action", "Details");
+                println!("{:-<25}-+-{:-<24}-+-{:-<20}", "", "", "");
+                for e in &events {
+                    println!("{:<25} | {:<24} | {}", e.time, e.action, e.details);
+                }
+            }
+        }
+        Commands::Archive {
+            key,
+            category,
+            restore,
+        } => {
+            let category = storage::Storage::validate_category(category.as_deref())?;
+            let archived_category = archive::archived_category(category.as_deref());
+            if !*restore && archive::is_archived(category.as_deref()) {
+                eprintln!(
+                    "'{}/{}' is already archived.",
+                    category.as_deref().unwrap_or_default(),
+                    key
+                );
+                std::process::exit(1);
+            }
+            let (from, to) = if *restore {
+                (Some(archived_category.as_str()), category.as_deref())
+            } else {
+                (category.as_deref(), Some(archived_category.as_str()))
+            };
+            let display = |category: Option<&str>| match category {
+                Some(cat) => format!("{}/{}", cat, key),
+                None => key.clone(),
+            };
+
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            if !*restore
+                && derived::load(&storage, &master_key)
+                    .await?
+                    .keys
+                    .contains_key(&derived::key_id(key, category.as_deref())?)
+            {
+                eprintln!(
+                    "'{}' is a derived key: its value depends on its path, so it cannot be archived.",
+                    display(from)
+                );
+                std::process::exit(1);
+            }
+
+            let (verb, action) = if *restore {
+                ("Unarchive", "archive-restore")
+            } else {
+                ("Archive", "archive")
+            };
+            let event =
+                audit::AuditEvent::new(action, &format!("{} -> {}", display(from), display(to)));
+            if let Err(e) = archive::move_key(
+                &storage,
+                &master_key,
+                key,
+                from,
+                to,
+                &format!("{} key: {}", verb, display(category.as_deref())),
+                &event,
+            )
+            .await
+            {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+
+            let lmk = config::Config::get_or_create_lmk_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            if let Some(to) = to {
+                categories::record(
+                    effective_profile.as_deref(),
+                    &storage.repo_full_name(),
+                    &lmk,
+                    to,
+                )?;
+            }
+            if index::load(storage.profile(), &storage.repo_full_name(), &lmk)?.is_some() {
+                refresh_index(&storage, &password, &master_key, false).await?;
+            }
+            if *restore {
+                println!("Key '{}' restored to '{}'.", display(from), display(to));
+            } else {
+                println!(
+                    "Key '{}' archived as '{}'. 'list' hides it; 'get' asks before reading it.",
+                    display(from),
+                    display(to)
+                );
+            }
+            let change = if *restore { "unarchived" } else { "archived" };
+            notify_change(&storage, &master_key, change, &[display(from)]).await;
+        }
+        Commands::Delete { key, category } => {
+            let display_path = match category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+            if keyglob::is_pattern(&display_path) {
+                return delete_matching(effective_profile.as_deref(), &display_path).await;
+            }
+
+            let password = prompt_password("Enter master password")?;
+            let repo_name = config::Config::get_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+            let storage = storage::Storage::new_with_profile(
+                effective_profile.as_deref(),
+                &repo_name,
+                &password,
+            )
+            .await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let id = derived::key_id(key, category.as_deref())?;
+            let is_derived = derived::load(&storage, &master_key)
+                .await?
+                .keys
+                .contains_key(&id);
+            if !is_derived && storage.get_blob(key, category.as_deref()).await?.is_none() {
+                eprintln!("Key '{}' not found.", display_path);
+                std::process::exit(1);
+            }
+
+            // Confirm deletion
+            let should_delete = prompt_yes_no(&format!(
+                "Are you sure you want to delete {}key '{}'?",
+                if is_derived { "derived " } else { "" },
+                display_path
+            ))?;
+
+            if !should_delete {
+                println!("Deletion cancelled.");
+                return Ok(());
+            }
+
+            // The key and its metadata go to the trash in a single commit
+            delete_keys(
+                &storage,
+                &password,
+                &master_key,
+                std::slice::from_ref(&display_path),
+                &format!("Delete key: {}", display_path),
+                &audit::AuditEvent::new("delete", &display_path),
+            )
+            .await?;
+            println!(
+                "Key '{}' deleted (restore it with 'axkeystore trash restore {}').",
+                display_path, display_path
+            );
+            notify_change(&storage, &master_key, "deleted", &[display_path]).await;
+        }
+        Commands::Config { command } => {
+            let profile = effective_profile.as_deref();
+            let mut cfg = config::Config::load_with_profile(profile)?;
+            match command {
+                ConfigCommands::Set { key, value } => {
+                    if let Err(e) = settings::set(&mut cfg, key, value) {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                    cfg.save_with_profile(profile)?;
+                    println!(
+                        "Set {} to '{}' for profile '{}'.",
+                        key,
+                        settings::get(&cfg, key)?.as_deref().unwrap_or("raw"),
+                        profile_str
+                    );
+                }
+                ConfigCommands::Get { key } => match settings::get(&cfg, key)? {
+                    Some(value) => println!("{}", value),
+                    None => {
+                        eprintln!("{} is not set for profile '{}'.", key, profile_str);
+                        std::process::exit(1);
+                    }
+                },
+                ConfigCommands::Unset { key } => {
+                    settings::unset(&mut cfg, key)?;
+                    cfg.save_with_profile(profile)?;
+                    println!("Cleared {} for profile '{}'.", key, profile_str);
+                }
+                ConfigCommands::List => {
+                    println!("\nSettings of profile '{}':", profile_str);
+                    for (key, about) in settings::KEYS {
+                        let value = settings::get(&cfg, key)?;
+                        println!(
+                            "  {:<17} {:<16} {}",
+                            key,
+                            value.as_deref().unwrap_or("-"),
+                            about
+                        );
+                    }
+                }
+            }
+        }
+        Commands::Profile { command } => match command {
+            ProfileCommands::List => {
+                let profiles = config::GlobalConfig::list_profiles()?;
+                let active = config::GlobalConfig::get_active_profile()?;
+                println!("\nProfiles:");
+                if profiles.is_empty() && active.is_none() {
+                    println!("  * default");
+                } else {
+                    // Always show default in the list
+                    let indicator = if active.is_none() { "*" } else { " " };
+                    println!(" {} default", indicator);
+
+                    for p in profiles {
+                        let indicator = if Some(&p) == active.as_ref() {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        println!(" {} {}", indicator, p);
+                    }
+                }
+                println!("\n* Active profile");
+            }
+            ProfileCommands::Switch { name } => {
+                config::GlobalConfig::set_active_profile(name.clone())?;
+                match name {
+                    Some(n) => println!("Switched to profile '{}'.", n),
+                    None => println!("Switched to default root profile."),
+                }
+            }
+            ProfileCommands::Delete { name } => {
+                if prompt_yes_no(&format!(
+                    "Are you sure you want to delete profile '{}'?",
+                    name
+                ))? {
+                    config::GlobalConfig::delete_profile(name)?;
+                    println!("Profile '{}' deleted.", name);
+                }
+            }
+            ProfileCommands::Current => {
+                let active = config::GlobalConfig::get_active_profile()?;
+                println!(
+                    "Current active profile: {}",
+                    active.unwrap_or_else(|| "default".to_string())
+                );
+                if cli.profile.is_none() {
+                    if let Some(name) = settings::env_profile()? {
+                        println!(
+                            "{} selects profile '{}' for commands.",
+                            settings::PROFILE_VAR,
+                            name
+                        );
+                    }
+                }
+            }
+            ProfileCommands::Create { name } => {
+                config::Config::get_config_dir(Some(&name))?;
+                println!("Profile '{}' created.", name);
+            }
+            ProfileCommands::Clone { src, dst } => {
+                let source = (!src.eq_ignore_ascii_case("default")).then_some(src.as_str());
+                if let Some(source) = source {
+                    config::Config::validate_profile_name(source)?;
+                    if !config::GlobalConfig::list_profiles()?
+                        .iter()
+                        .any(|p| p == source)
+                    {
+                        eprintln!("Profile '{}' does not exist.", source);
+                        std::process::exit(1);
+                    }
+                }
+                if !prompt_yes_no(&format!(
+                    "Copy profile '{}' (settings, encrypted GitHub token, repository and vaults) to a new profile '{}'?",
+                    source.unwrap_or("default"),
+                    dst
+                ))? {
+                    println!("Clone cancelled.");
+                    return Ok(());
+                }
+                if let Err(e) = config::GlobalConfig::clone_profile(source, dst) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                println!(
+                    "Profile '{}' created from '{}'. It opens with the same master password.",
+                    dst,
+                    source.unwrap_or("default")
+                );
+            }
+            ProfileCommands::Export { name, out } => {
+                let profile = (!name.eq_ignore_ascii_case("default")).then_some(name.as_str());
+                if let Some(profile) = profile {
+                    config::Config::validate_profile_name(profile)?;
+                    if !config::GlobalConfig::list_profiles()?
+                        .iter()
+                        .any(|p| p == profile)
+                    {
+                        eprintln!("Profile '{}' does not exist.", profile);
+                        std::process::exit(1);
+                    }
+                }
+                let bundle_password = loop {
+                    let p1 = prompt_password("Set bundle password")?;
+                    if p1.len() < 8 {
+                        eprintln!("Password must be at least 8 characters long.");
+                        continue;
+                    }
+                    let p2 = prompt_password("Confirm bundle password")?;
+                    if p1 == p2 {
+                        break p1;
+                    }
+                    eprintln!("Passwords do not match. Please try again.");
+                };
+                let out = out
+                    .clone()
+                    .unwrap_or_else(|| format!("{}.axp", profile.unwrap_or("default")).into());
+                let with_token =
+                    match config::GlobalConfig::export_profile(profile, &out, &bundle_password) {
+                        Ok(with_token) => with_token,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                println!(
+                    "Profile '{}' exported to '{}'.",
+                    profile.unwrap_or("default"),
+                    out.display()
+                );
+                if !with_token {
+                    println!("   Its GitHub token is bound to this machine (or missing), so it was left out; run 'axkeystore login' after importing.");
+                }
+                println!("   Import it on the other machine with 'axkeystore profile import {}', then delete the file.", out.display());
+            }
+            ProfileCommands::Import { file, name } => {
+                let bundle_password = prompt_password("Enter bundle password")?;
+                let (name, with_token) = match config::GlobalConfig::import_profile(
+                    file,
+                    &bundle_password,
+                    name.as_deref(),
+                ) {
+                    Ok(imported) => imported,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+                println!(
+                    "Profile '{}' imported. It opens with the master password it had on the other machine.",
+                    name
+                );
+                let is_default = name.eq_ignore_ascii_case("default");
+                if !with_token {
+                    let login = if is_default {
+                        "axkeystore login".to_string()
+                    } else {
+                        format!("axkeystore --profile {} login", name)
+                    };
+                    println!("   The bundle holds no GitHub token; run '{}'.", login);
+                }
+                if !is_default {
+                    println!("   Switch to it with 'axkeystore profile switch {}'.", name);
+                }
+            }
+            ProfileCommands::Rename { old, new } => {
+                if let Err(e) = config::GlobalConfig::rename_profile(old, new) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                println!("Profile '{}' renamed to '{}'.", old, new);
+            }
+        },
+        Commands::Rekey => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            println!("Planning the rekey...");
+            let plan = rekey::plan(&storage, &password, &master_key).await?;
+            let repo = storage.repo_full_name();
+            println!("\nRekey plan for '{}':", repo);
+            println!(
+                "  Keys:              {} in {} categories ({} encrypted)",
+                plan.keys,
+                plan.categories,
+                format_size(plan.bytes)
+            );
+            println!("  Metadata files:    {}", plan.meta_files);
+            if plan.resumed > 0 {
+                println!(
+                    "  Already migrated:  {} items (resuming an interrupted rekey)",
+                    plan.resumed
+                );
+            }
+            println!(
+                "  Estimated effort:  ~{} GitHub API requests, about {}",
+                plan.api_calls(),
+                timeutil::format_duration_secs(plan.estimated_secs())
+            );
+            if plan.derived_keys > 0 {
+                println!(
+                    "  Derived keys:      {} will get new values, because they are derived from the master key",
+                    plan.derived_keys
+                );
+            }
+            if !plan.recipients.is_empty() {
+                println!(
+                    "  Recipients:        {} (re-wrapped automatically, nothing to do)",
+                    plan.recipients.join(", ")
+                );
+            }
+            match &plan.collaborators {
+                Some(logins) if logins.is_empty() => {
+                    println!("  Collaborators:     none besides you");
+                }
+                Some(logins) => println!(
+                    "  Collaborators:     {} must clear their derived key cache ('axkeystore cache clear') and unlock again with the master password",
+                    logins.join(", ")
+                ),
+                None => println!(
+                    "  Collaborators:     unknown (your token cannot list them); anyone sharing the vault must clear their derived key cache and unlock again"
+                ),
+            }
+            println!("  The master password does not change.");
+
+            let answer = prompt_line(&format!(
+                "\nType the repository name ('{}') to re-encrypt the vault under a new master key",
+                repo
+            ))?;
+            if answer != repo {
+                println!("Rekey cancelled.");
+                return Ok(());
+            }
+
+            handle_ctrl_c();
+            let count = match rekey::run(&storage, &password, &master_key).await {
+                Ok(count) => count,
+                Err(e) if cancel::is_cancelled(&e) => exit_cancelled(
+                    "Rekey cancelled. Progress is saved; run 'axkeystore rekey' again to resume.",
+                ),
+                Err(e) => return Err(e),
+            };
+            println!(
+                "Vault re-encrypted: {} keys now use the new master key.",
+                count
+            );
+            println!(
+                "   Other machines must clear their derived key cache ('axkeystore cache clear')."
+            );
+            if recovery::load_marker(&storage).await?.is_some() {
+                println!("   Recovery shares made before no longer work. Run 'axkeystore recovery setup' again.");
+            }
+            // The old code would still open the old master key, and with it the history
+            if recovery::remove_code(&storage).await? {
+                println!("   The recovery code was removed. Run 'axkeystore recovery code' for a new one.");
+            }
+        }
+        Commands::Recovery { command } => match command {
+            RecoveryCommands::Setup {
+                shares,
+                threshold,
+                out,
+            } => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+                if crypto::CryptoHandler::scoped_categories(&master_key).is_some() {
+                    eprintln!("Only holders of the master key can set up recovery.");
+                    std::process::exit(1);
+                }
+                let split = match recovery::split(&master_key, *shares, *threshold) {
+                    Ok(split) => split,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+                if let Some(previous) = recovery::load_marker(&storage).await? {
+                    if !prompt_yes_no(&format!(
+                        "Shares were already made on {} ({} of {}); they keep working. Make a new set?",
+                        previous.created, previous.threshold, previous.shares
+                    ))? {
+                        println!("Recovery setup cancelled.");
+                        return Ok(());
+                    }
+                }
+
+                match out {
+                    Some(dir) => {
+                        std::fs::create_dir_all(dir)?;
+                        for share in &split {
+                            let path = dir.join(format!("share-{}.txt", share.index));
+                            config::write_private_file(
+                                &path,
+                                format!(
+                                    "AxKeyStore recovery share {} of {} for {} ({} needed)\n{}\n",
+                                    share.index,
+                                    shares,
+                                    storage.repo_full_name(),
+                                    threshold,
+                                    share
+                                )
+                                .as_bytes(),
+                            )?;
+                            println!("Share {} written to '{}'.", share.index, path.display());
+                        }
+                    }
+                    None => {
+                        println!(
+                            "\nRecovery shares for {} ({} of them rebuild the master key):\n",
+                            storage.repo_full_name(),
+                            threshold
+                        );
+                        for share in &split {
+                            println!("  {}", share);
+                        }
+                        println!("\nThey are shown only once.");
+                    }
+                }
+                recovery::save_marker(
+                    &storage,
+                    &recovery::Marker {
+                        created: timeutil::format_rfc3339(timeutil::now_unix()),
+                        shares: *shares,
+                        threshold: *threshold,
+                        check: recovery::check_value(&master_key),
+                    },
+                )
+                .await?;
+                println!("Give each share to a different trusted person or place. Anyone holding {} of them can read the vault.", threshold);
+                println!("   'axkeystore rekey' makes the shares stale; set up recovery again afterwards.");
+            }
+            RecoveryCommands::Restore { repo } => {
+                println!("Enter the recovery shares, one per line.");
+                let mut shares: Vec<recovery::Share> = Vec::new();
+                loop {
+                    let needed = shares.first().map_or(2, |s| s.threshold as usize);
+                    if shares.len() >= needed {
+                        break;
+                    }
+                    let line = prompt_line(&format!("Share {}", shares.len() + 1))?;
+                    if line.is_empty() {
+                        println!("Restore cancelled.");
+                        return Ok(());
+                    }
+                    match line.parse::<recovery::Share>() {
+                        Ok(share) if shares.iter().any(|s| s.index == share.index) => {
+                            eprintln!("Share {} was already entered.", share.index)
+                        }
+                        Ok(share) => shares.push(share),
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+                let master_key = match recovery::combine(&shares) {
+                    Ok(master_key) => master_key,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+                println!("Master key rebuilt.");
+
+                let (token, storage) =
+                    login_for_recovery(effective_profile.as_deref(), repo).await?;
+                if let Some(marker) = recovery::load_marker(&storage).await? {
+                    if marker.check != recovery::check_value(&master_key) {
+                        eprintln!("These shares were made for another master key of {} (was the vault rekeyed since?).", storage.repo_full_name());
+                        std::process::exit(1);
+                    }
+                }
+                finish_recovery(effective_profile.as_deref(), &storage, &token, &master_key)
+                    .await?;
+            }
+            RecoveryCommands::Code => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+                if crypto::CryptoHandler::scoped_categories(&master_key).is_some() {
+                    eprintln!("Only holders of the full master key can make a recovery code.");
+                    std::process::exit(1);
+                }
+                if recovery::has_code(&storage).await?
+                    && !prompt_yes_no("The vault already has a recovery code. Replace it (the old code stops working)?")?
+                {
+                    println!("Recovery code unchanged.");
+                    return Ok(());
+                }
+                let code = recovery::generate_code();
+                recovery::save_code(&storage, &master_key, &code).await?;
+                print_recovery_code(&storage.repo_full_name(), &code);
+            }
+        },
+        Commands::Recover { code, repo } => {
+            let code = match code {
+                Some(code) => code.clone(),
+                None => prompt_password("Enter recovery code")?,
+            };
+            if let Err(e) = recovery::normalize_code(&code) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            let (token, storage) = login_for_recovery(effective_profile.as_deref(), repo).await?;
+            let master_key = match recovery::open_code(&storage, &code).await {
+                Ok(Some(master_key)) => master_key,
+                Ok(None) => {
+                    eprintln!(
+                        "{} has no recovery code. Use 'axkeystore recovery restore' if you made recovery shares.",
+                        storage.repo_full_name()
+                    );
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            println!("Recovery code accepted.");
+            finish_recovery(effective_profile.as_deref(), &storage, &token, &master_key).await?;
+
+            // The code is used up: whoever saw it typed could now open the vault
+            let code = recovery::generate_code();
+            recovery::save_code(&storage, &master_key, &code).await?;
+            println!("\nThe recovery code you used no longer works.");
+            print_recovery_code(&storage.repo_full_name(), &code);
+        }
+        Commands::ResetPassword => {
+            let old_password = prompt_password("Enter current master password")?;
+
+            // 1. Verify old password and retrieve LMK
+            let lmk = match config::Config::get_or_create_lmk_with_profile(
+                effective_profile.as_deref(),
+                &old_password,
+            ) {
+                Ok(k) => k,
+                Err(_) => {
+                    eprintln!("Incorrect old master password.");
+                    std::process::exit(1);
+                }
+            };
+
+            // 2. Try to retrieve RMK if storage is configured
+            let mut rmk_data: Option<(String, storage::Storage)> = None;
+            if let Ok(repo_name) = config::Config::get_repo_name_with_profile(
+                effective_profile.as_deref(),
+                &old_password,
+            ) {
+                if let Ok(storage) = storage::Storage::new_with_profile(
+                    effective_profile.as_deref(),
+                    &repo_name,
+                    &old_password,
+                )
+                .await
+                {
+                    if let Ok(Some(data)) = storage.get_master_key_blob().await {
+                        let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&data)?;
+                        if let Ok(decrypted) =
+                            crypto::CryptoHandler::decrypt(&encrypted, &old_password)
+                        {
+                            let rmk = String::from_utf8(decrypted)?;
+                            rmk_data = Some((rmk, storage));
+                        }
+                    }
+                }
+            }
+
+            // 3. Prompt for new password
+            println!("\nEnter your new master password:");
+            let new_password = loop {
+                let p1 = prompt_password("New master password")?;
+                if p1.len() < 8 {
+                    eprintln!("Password must be at least 8 characters long.");
+                    continue;
+                }
+                let p2 = prompt_password("Confirm new master password")?;
+                if p1 == p2 {
+                    if p1 == old_password {
+                        eprintln!("New password must be different from the old one.");
+                        continue;
+                    }
+                    break p1;
+                }
+                eprintln!("Passwords do not match. Please try again.");
+            };
+
+            // 4. Update RMK remotely if it exists
+            if let Some((rmk, storage)) = rmk_data {
+                let encrypted_rmk = crypto::CryptoHandler::encrypt(rmk.as_bytes(), &new_password)?;
+                let json_blob = serde_json::to_vec(&encrypted_rmk)?;
+                if let Err(e) = storage.save_master_key_blob(&json_blob).await {
+                    eprintln!("Failed to update remote master key on GitHub: {}", e);
+                    eprintln!("   Password reset aborted. Your current password is still active.");
+                    std::process::exit(1);
+                }
+                println!("Remote master key updated on GitHub.");
+            }
+
+            // 5. Update LMK locally
+            let encrypted_lmk = crypto::CryptoHandler::encrypt(lmk.as_bytes(), &new_password)?;
+            let mut cfg = config::Config::load_with_profile(effective_profile.as_deref())?;
+            cfg.encrypted_lmk = Some(encrypted_lmk);
+            cfg.save_with_profile(effective_profile.as_deref())?;
+
+            println!(
+                "Master password successfully reset for profile '{}'.",
+                profile_str
+            );
+        }
+        Commands::Info { key, category } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let display_path = match &category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+
+            let key_meta = meta::load(&storage, &master_key, key, category.as_deref()).await?;
+            let expires = key_meta
+                .expires
+                .map(|e| meta::describe_expiry(e, timeutil::now_unix()));
+
+            println!("Key:      {}", display_path);
+            match storage.get_blob(key, category.as_deref()).await? {
+                Some((data, _)) => {
+                    let encrypted: crypto::EncryptedBlob =
+                        serde_json::from_slice(&data).context("Failed to parse encrypted blob")?;
+                    let kind = match crypto::CryptoHandler::decrypt(&encrypted, &master_key) {
+                        Ok(value) if value.is_empty() && key_meta.reserved.is_some() => {
+                            "placeholder"
+                        }
+                        Ok(value) => catalog::classify(&value),
+                        Err(_) => "unreadable",
+                    };
+                    println!("Type:     {}", kind);
+                    println!(
+                        "Stored:   {} bytes encrypted{}{}",
+                        data.len(),
+                        if encrypted.compression.is_some() {
+                            ", compressed"
+                        } else {
+                            ""
+                        },
+                        if encrypted.padding.is_some() {
+                            ", padded"
+                        } else {
+                            ""
+                        }
+                    );
+                    if let Some(expires) = &expires {
+                        println!("Expires:  {}", expires);
+                    }
+                    if let Some(deadline) = key_meta.delete_after {
+                        println!(
+                            "Deletes:  {}",
+                            meta::describe_deletion(deadline, timeutil::now_unix())
+                        );
+                    }
+                    if let Some(r) = &key_meta.reserved {
+                        println!("Reserved: by {} since {}", r.owner, r.since);
+                    }
+                }
+                None => {
+                    let id = derived::key_id(key, category.as_deref())?;
+                    match derived::load(&storage, &master_key).await?.keys.get(&id) {
+                        Some(d) => {
+                            println!("Type:     derived");
+                            println!("Rotation: counter {}, length {}", d.counter, d.length);
+                            if let Some(expires) = &expires {
+                                println!("Expires:  {}", expires);
+                            }
+                            return Ok(());
+                        }
+                        None => {
+                            eprintln!("Key '{}' not found.", display_path);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+
+            let history = storage
+                .get_full_key_history(key, category.as_deref())
+                .await?;
+            if let Some(summary) = lifecycle::summarize(&history) {
+                println!("Versions: {}", summary.versions);
+                println!("First:    {}", summary.first_change);
+                println!("Last:     {}", summary.last_change);
+                let changed_by: Vec<String> = summary
+                    .changed_by
+                    .iter()
+                    .map(|(who, count)| format!("{} ({})", who, count))
+                    .collect();
+                println!("By:       {}", changed_by.join(", "));
+                if let Some(interval) = summary.average_interval_secs {
+                    println!(
+                        "Rotation: every {} on average",
+                        timeutil::format_duration_secs(interval)
+                    );
+                }
+            }
+        }
+        Commands::Index { command } => match command {
+            IndexCommands::Rebuild => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+                let key_index = refresh_index(&storage, &password, &master_key, true).await?;
+                println!("Index rebuilt ({} keys).", key_index.keys.len());
+            }
+            IndexCommands::EnableCompletion => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+
+                let mut config = config::Config::load_with_profile(effective_profile.as_deref())?;
+                config.complete_key_names = true;
+                config.save_with_profile(effective_profile.as_deref())?;
+                let key_index = refresh_index(&storage, &password, &master_key, false).await?;
+                println!(
+                    "Key name completion enabled ({} keys). Key paths are kept in plain text in the profile's config directory.",
+                    key_index.keys.len()
+                );
+            }
+            IndexCommands::DisableCompletion => {
+                let mut config = config::Config::load_with_profile(effective_profile.as_deref())?;
+                config.complete_key_names = false;
+                config.save_with_profile(effective_profile.as_deref())?;
+                index::remove_names(effective_profile.as_deref())?;
+                println!("Key name completion disabled.");
+            }
+        },
+        Commands::Search {
+            query,
+            regex,
+            category,
+            refresh,
+        } => {
+            let matcher = search::Matcher::new(query, *regex)?;
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let lmk = config::Config::get_or_create_lmk_with_profile(
+                effective_profile.as_deref(),
+                &password,
+            )?;
+
+            // The local index avoids walking the whole repository for every search
+            let cached = if *refresh {
+                None
+            } else {
+                index::load(storage.profile(), &storage.repo_full_name(), &lmk)?
+            };
+            let key_index = match cached {
+                Some(key_index) => key_index,
+                None => {
+                    let master_key = get_or_init_master_key(&storage, &password).await?;
+                    refresh_index(&storage, &password, &master_key, false).await?
+                }
+            };
+            let entries: Vec<index::IndexEntry> = key_index.keys.into_values().collect();
+
+            let found = search::search(&entries, &matcher, category.as_deref());
+            if found.is_empty() {
+                println!("No keys match '{}'.", query);
+                return Ok(());
+            }
+            for (entry, field) in found {
+                let mut line = entry.display_path();
+                if !entry.tags.is_empty() {
+                    line.push_str(&format!("  [{}]", entry.tags.join(", ")));
+                }
+                if let Some(description) = &entry.description {
+                    line.push_str(&format!("  - {}", description));
+                }
+                println!("{}  ({})", line, field);
+            }
+        }
+        Commands::Show { key, category } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let display_path = match &category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+            if !key_exists(&storage, &master_key, key, category.as_deref()).await? {
+                eprintln!("Key '{}' not found.", display_path);
+                std::process::exit(1);
+            }
+
+            let key_meta = meta::load(&storage, &master_key, key, category.as_deref()).await?;
+            println!("Key:         {}", display_path);
+            if let Some(description) = &key_meta.description {
+                println!("Description: {}", description);
+            }
+            if !key_meta.tags.is_empty() {
+                println!("Tags:        {}", key_meta.tags.join(", "));
+            }
+            if let Some(owner) = &key_meta.owner {
+                println!("Owner:       {}", owner);
+            }
+            if let Some(url) = &key_meta.url {
+                println!("URL:         {}", url);
+            }
+            if let Some(expires) = key_meta.expires {
+                println!(
+                    "Expires:     {}",
+                    meta::describe_expiry(expires, timeutil::now_unix())
+                );
+            }
+            if let Some(deadline) = key_meta.delete_after {
+                println!(
+                    "Deletes:     {}",
+                    meta::describe_deletion(deadline, timeutil::now_unix())
+                );
+            }
+            if let Some(r) = &key_meta.reserved {
+                println!("Reserved:    by {} since {}", r.owner, r.since);
+            }
+            if !key_meta.attachments.is_empty() {
+                let names: Vec<&str> = key_meta.attachments.keys().map(|n| n.as_str()).collect();
+                println!("Attachments: {}", names.join(", "));
+            }
+            if !key_meta.labels.is_empty() {
+                let labels: Vec<&str> = key_meta.labels.keys().map(|l| l.as_str()).collect();
+                println!("Labels:      {}", labels.join(", "));
+            }
+            if key_meta.is_empty() {
+                println!("(no metadata; add some with 'store --description/--tag/--owner/--url')");
+            }
+        }
+        Commands::Edit { key, category } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let display_path = match &category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+
+            let (data, base_sha) = match storage.get_blob(key, category.as_deref()).await? {
+                Some(blob) => blob,
+                None => {
+                    let id = derived::key_id(key, category.as_deref())?;
+                    if derived::load(&storage, &master_key)
+                        .await?
+                        .keys
+                        .contains_key(&id)
+                    {
+                        eprintln!(
+                            "Key '{}' is derived and has no stored value to edit. Use 'axkeystore rotate' instead.",
+                            display_path
+                        );
+                    } else {
+                        eprintln!("Key '{}' not found.", display_path);
+                    }
+                    std::process::exit(1);
+                }
+            };
+            let encrypted: crypto::EncryptedBlob =
+                serde_json::from_slice(&data).context("Failed to parse encrypted blob")?;
+            let value = crypto::CryptoHandler::decrypt(&encrypted, &master_key)?;
+            if std::str::from_utf8(&value).is_err() {
+                eprintln!(
+                    "Key '{}' holds binary data. Use 'get --out' and 'store --file' instead.",
+                    display_path
+                );
+                std::process::exit(1);
+            }
+
+            match editor::edit(&value)? {
+                None => println!("No changes to '{}'; nothing saved.", display_path),
+                Some(edited) => {
+                    // Editing can take a while; don't silently replace a change made meanwhile
+                    if !save_checked(
+                        &storage,
+                        &master_key,
+                        key,
+                        category.as_deref(),
+                        edited,
+                        Some(base_sha),
+                    )
+                    .await?
+                    {
+                        println!("Nothing was saved.");
+                        return Ok(());
+                    }
+                    println!("Key '{}' updated.", display_path);
+                    notify_change(&storage, &master_key, "updated", &[display_path]).await;
+                }
+            }
+        }
+        Commands::Import {
+            from: Some(from),
+            source: Some(source),
+            category,
+            overwrite,
+            ..
+        } => {
+            import_password_manager(
+                effective_profile.as_deref(),
+                *from,
+                source,
+                category.as_deref(),
+                *overwrite,
+            )
+            .await?;
+        }
+        Commands::Import {
+            from_pid,
+            from_env,
+            prefix,
+            vars,
+            category,
+            overwrite,
+            ..
+        } => {
+            let category = match category {
+                Some(category) => category,
+                None => {
+                    eprintln!("Choose the category to import into with --category.");
+                    std::process::exit(1);
+                }
+            };
+            let (env, source) = match from_pid {
+                Some(pid) => (
+                    envimport::read_process_env(*pid)?,
+                    format!("process {}", pid),
+                ),
+                None if *from_env => (envimport::current_env(), "the current shell".to_string()),
+                None => {
+                    eprintln!("Choose a source with --from-pid <pid> or --from-env.");
+                    std::process::exit(1);
+                }
+            };
+            if prefix.is_none() && vars.is_empty() {
+                eprintln!("Select variables with --prefix and/or --var.");
+                std::process::exit(1);
+            }
+
+            let selected = envimport::select(&env, prefix.as_deref(), vars);
+            if selected.is_empty() {
+                println!("No matching variables found in {}.", source);
+                return Ok(());
+            }
+            for name in selected.keys() {
+                storage::Storage::build_key_path(name, Some(category))?;
+            }
+
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let category = category.trim_matches('/').to_string();
+            let existing: std::collections::HashSet<String> = storage
+                .list_all_keys()
+                .await?
+                .into_iter()
+                .filter(|e| e.category.as_deref() == Some(category.as_str()))
+                .map(|e| e.name)
+                .collect();
+
+            println!("\nVariables from {}:", source);
+            let mut to_import = Vec::new();
+            for (name, value) in &selected {
+                let status = match (existing.contains(name), *overwrite) {
+                    (false, _) => "new",
+                    (true, true) => "overwrite",
+                    (true, false) => "exists, skipped",
+                };
+                println!("  {}/{}  ({})", category, name, status);
+                if status != "exists, skipped" {
+                    to_import.push((name, value));
+                }
+            }
+            if to_import.is_empty() {
+                println!("Nothing to import. Pass --overwrite to replace existing keys.");
+                return Ok(());
+            }
+            if !prompt_yes_no(&format!("Import {} variable(s)?", to_import.len()))? {
+                println!("Import cancelled.");
+                return Ok(());
+            }
+
+            // Everything is committed at once, so stopping before the commit changes nothing
+            handle_ctrl_c();
+            let mut changes = Vec::new();
+            let progress = progress::Progress::new(to_import.len(), "Encrypting");
+            for (name, value) in &to_import {
+                if cancel::is_requested() {
+                    exit_cancelled("Import cancelled; nothing was changed.");
+                }
+                progress.item(name);
+                let encrypted = encrypt_value(&master_key, Some(&category), value)?;
+                changes.extend(
+                    storage
+                        .key_changes(
+                            name,
+                            Some(&category),
+                            Some(&serde_json::to_vec(&encrypted)?),
+                        )
+                        .await?,
+                );
+                progress.succeed();
+            }
+            progress.finish();
+            let details = format!(
+                "{} variable(s) from {} into {}",
+                to_import.len(),
+                source,
+                category
+            );
+            changes.push(
+                audit::append_change(&storage, &audit::AuditEvent::new("import", &details)).await?,
+            );
+            if cancel::is_requested() {
+                exit_cancelled("Import cancelled; nothing was changed.");
+            }
+            storage
+                .apply_changes(&changes, &format!("Import keys into {}", category))
+                .await?;
+            let names: Vec<&str> = to_import.iter().map(|(name, _)| name.as_str()).collect();
+            remember_keys(
+                &storage,
+                &password,
+                Some(category.as_str()),
+                &names,
+                false,
+                None,
+            )?;
+            println!("Imported {}.", details);
+            let skipped = selected.len() - to_import.len();
+            if skipped > 0 {
+                println!("   {} existing key(s) skipped.", skipped);
+            }
+        }
+        Commands::Agent { interval, reap } => {
+            let interval_secs = timeutil::parse_duration_secs(interval)?;
+            if interval_secs < agent::MIN_INTERVAL_SECS {
+                eprintln!(
+                    "The interval must be at least {}.",
+                    timeutil::format_duration_secs(agent::MIN_INTERVAL_SECS)
+                );
+                std::process::exit(1);
+            }
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            println!(
+                "Agent running for '{}', checking for changes every {} (Ctrl+C to stop).",
+                storage.repo_full_name(),
+                timeutil::format_duration_secs(interval_secs)
+            );
+            handle_ctrl_c();
+            agent::run(&storage, &password, &master_key, interval_secs, *reap).await?;
+            println!("Agent stopped.");
+        }
+        Commands::Purge {
+            key,
+            category,
+            confirm,
+        } => {
+            let mut target = purge::Target::new(key, category.as_deref())?;
+            let display_path = derived::key_id(key, category.as_deref())?;
+
+            eprintln!(
+                "WARNING: purging '{}' rewrites the history of the vault repository.",
+                display_path
+            );
+            eprintln!("  - Every version of the key, its metadata, attachments and trash copies is removed from every commit, and the key is deleted.");
+            eprintln!("  - Every commit since the key was first stored is recreated with a new SHA; their signatures are dropped.");
+            eprintln!("  - Clones of the repository must be re-cloned. Forks, and GitHub until it collects the old commits, may still hold the value.");
+            eprintln!("  - Values shared with other keys through 'dedup' stay in place.");
+            eprintln!("  - A value that was ever exposed should be rotated as well; purging does not revoke it.");
+            if !*confirm {
+                eprintln!("\nRe-run with --confirm to purge the key.");
+                std::process::exit(1);
+            }
+
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            if derived::load(&storage, &master_key)
+                .await?
+                .keys
+                .contains_key(&display_path)
+            {
+                eprintln!(
+                    "Key '{}' is derived; its value was never stored. Use 'delete' instead.",
+                    display_path
+                );
+                std::process::exit(1);
+            }
+
+            // Attachments are stored under opaque ids, so collect them from the metadata
+            let key_meta = meta::load(&storage, &master_key, key, category.as_deref()).await?;
+            let mut attachments: Vec<String> = key_meta
+                .attachments
+                .values()
+                .map(|a| meta::attachment_path(&a.id))
+                .collect();
+            for (_, item) in trash::list(&storage, &master_key).await? {
+                if let Some(m) = item.meta().filter(|_| item.path == display_path) {
+                    attachments
+                        .extend(m.attachments.values().map(|a| meta::attachment_path(&a.id)));
+                }
+            }
+            target.add_files(attachments);
+
+            println!("Rewriting history; this reads every commit and may take a while...");
+            let report = storage.rewrite_history(|path| target.covers(path)).await?;
+            if report.removed.is_empty() {
+                println!("No trace of key '{}' found in the history.", display_path);
+                return Ok(());
+            }
+
+            let event =
+                audit::append_change(&storage, &audit::AuditEvent::new("purge", &display_path))
+                    .await?;
+            storage
+                .commit_changes(&[event], &format!("Purge key: {}", display_path))
+                .await?;
+            forget_key(&storage, &password, key, category.as_deref())?;
+            println!(
+                "Purged '{}': removed {} file(s) from {} rewritten commit(s).",
+                display_path,
+                report.removed.len(),
+                report.commits
+            );
+            println!("Ask everyone with a clone of the repository to clone it again.");
+            notify_change(&storage, &master_key, "purged", &[display_path]).await;
+        }
+        Commands::Retention { command } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            get_or_init_master_key(&storage, &password).await?;
+            let mut settings = retention::load_settings(&storage).await?;
+
+            let describe = |rule: &retention::Rule| {
+                let mut parts = Vec::new();
+                if let Some(versions) = rule.versions {
+                    parts.push(format!("last {} versions", versions));
+                }
+                if let Some(age) = rule.max_age {
+                    parts.push(format!(
+                        "{} of history",
+                        timeutil::format_duration_secs(age)
+                    ));
+                }
+                parts.join(" or ")
+            };
+            let (details, message) = match command {
+                RetentionCommands::Show => {
+                    if settings.is_empty() {
+                        println!("No retention rules are set; the whole history is kept.");
+                        return Ok(());
+                    }
+                    match &settings.default {
+                        Some(rule) => println!("Vault: keep {}", describe(rule)),
+                        None => println!("Vault: keep the whole history"),
+                    }
+                    for (category, rule) in &settings.categories {
+                        println!("  {}: keep {}", category, describe(rule));
+                    }
+                    return Ok(());
+                }
+                RetentionCommands::Set {
+                    category,
+                    versions,
+                    max_age,
+                } => {
+                    if *versions == Some(0) {
+                        eprintln!("At least one version must be kept.");
+                        std::process::exit(1);
+                    }
+                    let max_age = match max_age.as_deref().map(timeutil::parse_duration_secs) {
+                        Some(Ok(0)) => {
+                            eprintln!("The age must be greater than zero.");
+                            std::process::exit(1);
+                        }
+                        Some(Ok(age)) => Some(age),
+                        Some(Err(e)) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                        None => None,
+                    };
+                    let rule = retention::Rule {
+                        versions: *versions,
+                        max_age,
+                    };
+                    let target = match storage::Storage::validate_category(category.as_deref())? {
+                        Some(category) => {
+                            settings.categories.insert(category.clone(), rule);
+                            format!("category '{}'", category)
+                        }
+                        None => {
+                            settings.default = Some(rule);
+                            "the vault".to_string()
+                        }
+                    };
+                    println!("History of {} will keep {}.", target, describe(&rule));
+                    (
+                        format!("{}: keep {}", target, describe(&rule)),
+                        "Update retention rules",
+                    )
+                }
+                RetentionCommands::Unset { category } => {
+                    let removed = match category.as_deref().map(|c| c.trim_matches('/')) {
+                        Some(category) => settings.categories.remove(category).is_some(),
+                        None => settings.default.take().is_some(),
+                    };
+                    let target = match category {
+                        Some(category) => format!("category '{}'", category.trim_matches('/')),
+                        None => "the vault".to_string(),
+                    };
+                    if !removed {
+                        println!("No retention rule is set for {}.", target);
+                        return Ok(());
+                    }
+                    println!("Retention rule removed for {}.", target);
+                    (
+                        format!("{}: rule removed", target),
+                        "Update retention rules",
+                    )
+                }
+            };
+
+            let mut changes = retention::settings_changes(&storage, &settings).await?;
+            changes.push(
+                audit::append_change(&storage, &audit::AuditEvent::new("retention", &details))
+                    .await?,
+            );
+            storage.apply_changes(&changes, message).await?;
+        }
+        Commands::PruneHistory { dry_run } => {
+            use futures::TryStreamExt;
+
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            get_or_init_master_key(&storage, &password).await?;
+
+            let settings = retention::load_settings(&storage).await?;
+            if settings.is_empty() {
+                eprintln!(
+                    "No retention rules are set. Add one with 'axkeystore retention set' first."
+                );
+                std::process::exit(1);
+            }
+
+            let branch = storage.vault_branch().await?;
+            let commits: Vec<storage::KeyVersion> = storage
+                .commits(&branch, None)
+                .try_collect()
+                .await
+                .context("Failed to list commits")?;
+            let positions: BTreeMap<&str, usize> = commits
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (c.sha.as_str(), i))
+                .collect();
+
+            // Every key keeps its retained versions: the oldest one may be squashed (its value
+            // lives on in the squashed tree), but the commits of the newer ones must stay
+            let now = timeutil::now_unix();
+            let mut kept = Vec::new();
+            for path in storage
+                .list_tree()
+                .await?
+                .keys()
+                .filter_map(|path| index::key_display_path(path))
+            {
+                let (category, name) = templatefn::split_key_path(path);
+                let versions = storage.get_full_key_history(name, category).await?;
+                let dates = versions
+                    .iter()
+                    .map(|v| timeutil::parse_timestamp(&v.date))
+                    .collect::<Result<Vec<u64>>>()?;
+                let keep = match settings.rule_for(category) {
+                    Some(rule) => retention::kept_versions(rule, &dates, now),
+                    None => dates.len(),
+                };
+                if keep >= 2 {
+                    kept.extend(positions.get(versions[keep - 2].sha.as_str()));
+                }
+            }
+
+            let point = match retention::squash_point(kept, commits.len()) {
+                Some(point) => point,
+                None => {
+                    println!("Nothing to prune: every commit is within the retention rules.");
+                    return Ok(());
+                }
+            };
+            let until = &commits[point];
+            println!(
+                "{} commit(s) up to {} ({}) will be squashed into one; the {} newer commit(s) are kept.",
+                commits.len() - point,
+                &until.sha[..until.sha.len().min(7)],
+                until.date,
+                point
+            );
+            if *dry_run {
+                println!("Dry run: nothing was changed.");
+                return Ok(());
+            }
+
+            eprintln!("WARNING: pruning rewrites the history of the vault repository.");
+            eprintln!("  - Older versions of keys, and of deleted keys, are gone for good; 'history' and 'get --version' no longer find them.");
+            eprintln!(
+                "  - The kept commits are recreated with new SHAs and lose their signatures."
+            );
+            eprintln!("  - Clones of the repository must be re-cloned. Forks, and GitHub until it collects the old commits, may still hold them.");
+            if !prompt_yes_no("Squash the old history?")? {
+                println!("Prune cancelled.");
+                return Ok(());
+            }
+
+            storage
+                .squash_history(&until.sha, &format!("Squash history up to {}", until.date))
+                .await?;
+            let event = audit::append_change(
+                &storage,
+                &audit::AuditEvent::new(
+                    "prune-history",
+                    &format!(
+                        "{} commit(s) up to {} squashed",
+                        commits.len() - point,
+                        until.date
+                    ),
+                ),
+            )
+            .await?;
+            storage.commit_changes(&[event], "Prune history").await?;
+            println!(
+                "Squashed {} commit(s). Ask everyone with a clone of the repository to clone it again.",
+                commits.len() - point
+            );
+        }
+        Commands::Reap { dry_run } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let now = timeutil::now_unix();
+            let due = reap::find(&storage, &master_key, now).await?;
+            if due.is_empty() {
+                println!("No keys are past their deletion deadline.");
+                return Ok(());
+            }
+            println!("Keys past their deletion deadline:");
+            for key in &due {
+                println!(
+                    "  {}  {}",
+                    key.path,
+                    meta::describe_deletion(key.delete_after, now)
+                );
+            }
+            if *dry_run {
+                println!("Dry run: nothing was deleted.");
+                return Ok(());
+            }
+
+            reap::run(&storage, &master_key, &due).await?;
+            for key in &due {
+                let (category, name) = templatefn::split_key_path(&key.path);
+                forget_key(&storage, &password, name, category)?;
+            }
+            println!("Deleted {} key(s).", due.len());
+            let paths: Vec<String> = due.into_iter().map(|k| k.path).collect();
+            notify_change(&storage, &master_key, "deleted", &paths).await;
+        }
+        Commands::Trash { command } => match command {
+            TrashCommands::List => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+
+                let items = trash::list(&storage, &master_key).await?;
+                if items.is_empty() {
+                    println!("The trash is empty.");
+                    return Ok(());
+                }
+                let settings = trash::load_settings(&storage).await?;
+                let now = timeutil::now_unix();
+                println!("Deleted keys:");
+                for (_, item) in &items {
+                    let purge = match settings.purge_after {
+                        Some(age) => {
+                            let due = item.deleted.saturating_add(age);
+                            if due <= now {
+                                "purged on the next delete".to_string()
+                            } else {
+                                format!("purged in {}", timeutil::format_duration_secs(due - now))
+                            }
+                        }
+                        None => "kept until the trash is emptied".to_string(),
+                    };
+                    println!(
+                        "  {}  deleted {}  ({})",
+                        item.path,
+                        timeutil::format_rfc3339(item.deleted),
+                        purge
+                    );
+                }
+            }
+            TrashCommands::Restore { pattern } => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+
+                let pattern = pattern.trim_matches('/');
+                let mut selected: Vec<(String, trash::Item)> = Vec::new();
+                for (file, item) in trash::list(&storage, &master_key).await? {
+                    let wanted = if keyglob::is_pattern(pattern) {
+                        keyglob::matches(pattern, &item.path)
+                    } else {
+                        item.path == pattern
+                    };
+                    // The list is newest first, so the first version of each path wins
+                    if wanted && !selected.iter().any(|(_, s)| s.path == item.path) {
+                        selected.push((file, item));
+                    }
+                }
+                if selected.is_empty() {
+                    println!("No deleted keys match '{}'.", pattern);
+                    return Ok(());
+                }
+
+                let existing = matching_paths(&storage, &master_key, "**").await?;
+                let replaced: Vec<&str> = selected
+                    .iter()
+                    .map(|(_, item)| item.path.as_str())
+                    .filter(|path| existing.iter().any(|e| e == path))
+                    .collect();
+                if !replaced.is_empty() {
+                    println!("These keys exist again and would be replaced:");
+                    for path in &replaced {
+                        println!("  {}", path);
+                    }
+                    if !prompt_yes_no("Replace them with the deleted versions?")? {
+                        println!("Restore cancelled.");
+                        return Ok(());
+                    }
+                }
+
+                let mut registry = derived::load(&storage, &master_key).await?;
+                let registry_changed = selected
+                    .iter()
+                    .any(|(_, item)| item.is_derived() || registry.keys.contains_key(&item.path));
+                let mut changes = Vec::new();
+                for (file, item) in &selected {
+                    changes.extend(
+                        trash::restore_changes(&storage, &master_key, &mut registry, file, item)
+                            .await?,
+                    );
+                }
+                if registry_changed {
+                    changes.extend(derived::save_changes(&storage, &master_key, &registry).await?);
+                }
+                let paths: Vec<String> = selected.iter().map(|(_, i)| i.path.clone()).collect();
+                changes.push(
+                    audit::append_change(
+                        &storage,
+                        &audit::AuditEvent::new("trash-restore", &paths.join(", ")),
+                    )
+                    .await?,
+                );
+                storage
+                    .commit_changes(
+                        &changes,
+                        &format!("Restore {} key(s) from the trash", paths.len()),
+                    )
+                    .await?;
+
+                for (_, item) in &selected {
+                    let (category, name) = templatefn::split_key_path(&item.path);
+                    remember_keys(
+                        &storage,
+                        &password,
+                        category,
+                        &[name],
+                        item.is_derived(),
+                        item.meta(),
+                    )?;
+                }
+                for path in &paths {
+                    println!("Restored '{}'.", path);
+                }
+                notify_change(&storage, &master_key, "restored", &paths).await;
+            }
+            TrashCommands::Empty => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+
+                let items = trash::list(&storage, &master_key).await?;
+                if items.is_empty() {
+                    println!("The trash is empty.");
+                    return Ok(());
+                }
+                if !prompt_yes_no(&format!(
+                    "Purge {} deleted key(s) for good? They cannot be restored afterwards.",
+                    items.len()
+                ))? {
+                    println!("Purge cancelled.");
+                    return Ok(());
+                }
+
+                let mut changes = Vec::new();
+                for (file, item) in &items {
+                    changes.extend(trash::purge_changes(&storage, file, item).await?);
+                }
+                changes.push(
+                    audit::append_change(
+                        &storage,
+                        &audit::AuditEvent::new(
+                            "trash-empty",
+                            &format!("{} key(s) purged", items.len()),
+                        ),
+                    )
+                    .await?,
+                );
+                storage.commit_changes(&changes, "Empty the trash").await?;
+                println!("Purged {} key(s).", items.len());
+            }
+            TrashCommands::PurgeAfter { age } => {
+                let purge_after = if age.eq_ignore_ascii_case("never") {
+                    None
+                } else {
+                    match timeutil::parse_duration_secs(age) {
+                        Ok(secs) if secs > 0 => Some(secs),
+                        Ok(_) => {
+                            eprintln!("The age must be greater than zero.");
+                            std::process::exit(1);
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                };
+
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                get_or_init_master_key(&storage, &password).await?;
+
+                let settings = trash::Settings { purge_after };
+                let mut changes = trash::settings_changes(&storage, &settings).await?;
+                let details = match purge_after {
+                    Some(secs) => format!("purge after {}", timeutil::format_duration_secs(secs)),
+                    None => "never purge".to_string(),
+                };
+                changes.push(
+                    audit::append_change(
+                        &storage,
+                        &audit::AuditEvent::new("trash-settings", &details),
+                    )
+                    .await?,
+                );
+                storage
+                    .apply_changes(&changes, "Update trash settings")
+                    .await?;
+                match purge_after {
+                    Some(secs) => println!(
+                        "Deleted keys will be purged {} after deletion.",
+                        timeutil::format_duration_secs(secs)
+                    ),
+                    None => println!("Deleted keys will be kept until the trash is emptied."),
+                }
+            }
+        },
+        Commands::Serve {
+            listen,
+            read_only: _,
+            allow_write,
+            token_file,
+        } => {
+            let address: std::net::SocketAddr = match listen.parse() {
+                Ok(address) => address,
+                Err(_) => {
+                    eprintln!(
+                        "Invalid listen address '{}'. Use host:port, e.g. 127.0.0.1:8200.",
+                        listen
+                    );
+                    std::process::exit(1);
+                }
+            };
+            if !address.ip().is_loopback() {
+                eprintln!(
+                    "Warning: {} is reachable from other machines and the API uses plain HTTP.",
+                    address
+                );
+            }
+            let token = match token_file {
+                Some(path) if path.exists() => {
+                    let token = std::fs::read_to_string(path)
+                        .with_context(|| format!("Failed to read {}", path.display()))?;
+                    let token = token.trim().to_string();
+                    if token.is_empty() {
+                        eprintln!("Token file {} is empty.", path.display());
+                        std::process::exit(1);
+                    }
+                    token
+                }
+                Some(path) => {
+                    let token = serve::generate_token();
+                    config::write_private_file(path, token.as_bytes())?;
+                    println!("Wrote a new API token to {}.", path.display());
+                    token
+                }
+                None => serve::generate_token(),
+            };
+
+            let password = prompt_password("Enter master password")?;
+            let vault = axkeystore::Vault::open(effective_profile.as_deref(), &password).await?;
+
+            println!(
+                "Serving '{}' on http://{}{} (Ctrl+C to stop).",
+                vault.storage().repo_full_name(),
+                address,
+                if *allow_write { "" } else { " read-only" }
+            );
+            if token_file.is_none() {
+                println!("API token: {}", token);
+            }
+            handle_ctrl_c();
+            serve::serve(vault, listen, token, !*allow_write).await?;
+            println!("Server stopped.");
+        }
+        Commands::Expiring { within } => {
+            let within_secs = timeutil::parse_duration_secs(within)?;
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let now = timeutil::now_unix();
+            let all = meta::load_all(&storage, &master_key).await?;
+            let found = meta::expiring(&all, now.saturating_add(within_secs));
+            if found.is_empty() {
+                println!("No keys expire within {}.", within);
+                return Ok(());
+            }
+
+            println!("Keys expired or expiring within {}:", within);
+            for (path, expires) in &found {
+                println!("  {}  {}", path, meta::describe_expiry(*expires, now));
+            }
+            std::process::exit(1);
+        }
+        Commands::Posture { max_age } => {
+            let max_age_secs = timeutil::parse_duration_secs(max_age)?;
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+
+            println!("Reviewing '{}'...", storage.repo_full_name());
+            let findings = posture::assess(&storage, max_age_secs).await?;
+
+            println!(
+                "\nSecurity posture for '{}': {}/100\n",
+                storage.repo_full_name(),
+                posture::score(&findings)
+            );
+            for finding in &findings {
+                let status = match finding.status {
+                    posture::Status::Pass => "PASS",
+                    posture::Status::Fail => "FAIL",
+                    posture::Status::Unknown => "????",
+                };
+                println!("  [{}] {}", status, finding.summary);
+            }
+
+            let pending = posture::prioritized(&findings);
+            if pending.is_empty() {
+                println!("\nNo remediation needed.");
+            } else {
+                println!("\nRemediation, most important first:");
+                for (i, finding) in pending.iter().enumerate() {
+                    println!(
+                        "  {}. [{}] {}",
+                        i + 1,
+                        finding.severity.label(),
+                        finding.remediation.as_deref().unwrap_or_default()
+                    );
+                }
+            }
+        }
+        Commands::Export {
+            pattern,
+            metadata_only,
+            format,
+            category,
+            out,
+            ttl,
+            gpg_ids,
+        } => {
+            let format = &format
+                .or(defaults.export_format)
+                .unwrap_or(catalog::ExportFormat::Json);
+            let category = &category.clone().or_else(|| defaults.category.clone());
+            if !gpg_ids.is_empty() && *format != catalog::ExportFormat::Pass {
+                eprintln!("--gpg-id only applies to --format pass.");
+                std::process::exit(1);
+            }
+            if ttl.is_some() && *format != catalog::ExportFormat::DockerSecrets {
+                eprintln!("--ttl only applies to --format docker-secrets.");
+                std::process::exit(1);
+            }
+            let catalog_format = match (format.catalog_format(), *metadata_only) {
+                (Some(f), true) => f,
+                (Some(_), false) => {
+                    eprintln!("The json and csv formats require --metadata-only.");
+                    std::process::exit(1);
+                }
+                (None, true) => {
+                    eprintln!("--metadata-only cannot be used with --format docker-secrets or pass, which export values.");
+                    std::process::exit(1);
+                }
+                (None, false) => {
+                    let dir = match out {
+                        Some(dir) => dir,
+                        None => {
+                            eprintln!(
+                                "--format docker-secrets and --format pass require --out <DIR>."
+                            );
+                            std::process::exit(1);
+                        }
+                    };
+                    if *format == catalog::ExportFormat::Pass {
+                        return export_pass(
+                            effective_profile.as_deref(),
+                            category.as_deref(),
+                            pattern.as_deref(),
+                            dir,
+                            gpg_ids,
+                        )
+                        .await;
+                    }
+                    let ttl_secs = match ttl {
+                        Some(ttl) => Some(timeutil::parse_duration_secs(ttl)?),
+                        None => None,
+                    };
+                    return export_docker_secrets(
+                        effective_profile.as_deref(),
+                        category.as_deref(),
+                        pattern.as_deref(),
+                        dir,
+                        ttl_secs,
+                    )
+                    .await;
+                }
+            };
+
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let mut entries = catalog::build(&storage, &master_key, category.as_deref()).await?;
+            if let Some(pattern) = pattern {
+                entries.retain(|e| keyglob::matches(pattern, &e.path));
+            }
+            let rendered = catalog::render(&entries, catalog_format)?;
+            match out {
+                Some(path) => {
+                    std::fs::write(path, rendered)
+                        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+                    eprintln!(
+                        "Catalog of {} key(s) written to '{}'.",
+                        entries.len(),
+                        path.display()
+                    );
+                }
+                None => print!("{}", rendered),
+            }
+        }
+        Commands::SyncGha {
+            repo,
+            category,
+            keys,
+            dry_run,
+        } => {
+            gha::validate_repo(repo)?;
+            let category = category.trim_matches('/');
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let mut secrets = BTreeMap::new();
+            let mut sources = BTreeMap::new();
+            for (path, value) in decrypt_values(&storage, &master_key, Some(category)).await? {
+                let relative = path
+                    .strip_prefix(category)
+                    .and_then(|rest| rest.strip_prefix('/'))
+                    .unwrap_or(&path);
+                if !keys.is_empty() && !keys.iter().any(|k| k.trim_matches('/') == relative) {
+                    continue;
+                }
+                // Reserved keys have no value yet
+                if value.is_empty() {
+                    eprintln!("Skipping '{}': it has no value.", path);
+                    continue;
+                }
+                let name = gha::secret_name(relative)?;
+                if let Some(other) = sources.insert(name.clone(), path.clone()) {
+                    eprintln!(
+                        "'{}' and '{}' both map to the secret name '{}'.",
+                        other, path, name
+                    );
+                    std::process::exit(1);
+                }
+                secrets.insert(name, value);
+            }
+            for key in keys {
+                let path = format!("{}/{}", category, key.trim_matches('/'));
+                if !sources.values().any(|p| *p == path) {
+                    eprintln!("Key '{}' not found or has no value.", path);
+                    std::process::exit(1);
+                }
+            }
+            if secrets.is_empty() {
+                eprintln!("No keys with values in category '{}'.", category);
+                std::process::exit(1);
+            }
+
+            println!("Actions secrets for '{}':", repo);
+            for (name, path) in &sources {
+                println!("  {:<30} <- {}", name, path);
+            }
+            if *dry_run {
+                println!("Dry run: no secrets were written.");
+                return Ok(());
+            }
+            if !prompt_yes_no(&format!(
+                "Write {} secret(s) to '{}', replacing existing ones with the same names?",
+                secrets.len(),
+                repo
+            ))? {
+                println!("Sync cancelled.");
+                return Ok(());
+            }
+
+            let written = gha::sync(&storage, repo, &secrets).await?;
+            println!("Wrote {} secret(s) to '{}'.", written, repo);
+        }
+        Commands::CleanSecrets { dir, after } => {
+            tokio::time::sleep(std::time::Duration::from_secs(*after)).await;
+            let removed = dockersecrets::clean(dir)?;
+            println!(
+                "Removed {} secret file(s) from '{}'.",
+                removed,
+                dir.display()
+            );
+        }
+        Commands::Changelog { since, format, out } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+
+            let commits = storage.commits_since(since).await?;
+            let sections = changelog::build(&commits);
+            let rendered = changelog::render(&sections, since, *format)?;
+            match out {
+                Some(path) => {
+                    std::fs::write(path, rendered)
+                        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+                    eprintln!(
+                        "Changelog of {} commit(s) written to '{}'.",
+                        commits.len(),
+                        path.display()
+                    );
+                }
+                None => print!("{}", rendered),
+            }
+        }
+        Commands::Reserve { key, category } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let display_path = match &category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+
+            let id = derived::key_id(key, category.as_deref())?;
+            if derived::load(&storage, &master_key)
+                .await?
+                .keys
+                .contains_key(&id)
+            {
+                eprintln!("Key '{}' already exists.", display_path);
+                std::process::exit(1);
+            }
+
+            // The empty placeholder is created atomically, so only one teammate can claim a name
+            let encrypted = crypto::CryptoHandler::encrypt(b"", &master_key)?;
+            if !storage
+                .create_blob(key, &serde_json::to_vec(&encrypted)?, category.as_deref())
+                .await?
+            {
+                match meta::load(&storage, &master_key, key, category.as_deref())
+                    .await?
+                    .reserved
+                {
+                    Some(r) => eprintln!(
+                        "Key '{}' is already reserved by {} (since {}).",
+                        display_path, r.owner, r.since
+                    ),
+                    None => eprintln!("Key '{}' already exists.", display_path),
+                }
+                std::process::exit(1);
+            }
+
+            let mut key_meta = meta::load(&storage, &master_key, key, category.as_deref()).await?;
+            key_meta.reserved = Some(meta::Reservation {
+                owner: storage.login().to_string(),
+                since: timeutil::format_rfc3339(timeutil::now_unix()),
+            });
+            let changes =
+                meta::save_changes(&storage, &master_key, key, category.as_deref(), &key_meta)
+                    .await?;
+            storage
+                .apply_changes(&changes, &format!("Reserve key: {}", display_path))
+                .await?;
+            remember_keys(
+                &storage,
+                &password,
+                category.as_deref(),
+                &[key],
+                false,
+                Some(&key_meta),
+            )?;
+            println!(
+                "Key '{}' is reserved for {}. Store its value with 'axkeystore store' when it is ready.",
+                display_path,
+                storage.login()
+            );
+        }
+        Commands::Attach { command } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let (key, category) = match command {
+                AttachCommands::Add { key, category, .. }
+                | AttachCommands::Get { key, category, .. }
+                | AttachCommands::List { key, category }
+                | AttachCommands::Remove { key, category, .. } => (key, category.as_deref()),
+            };
+            let display_path = match category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+            if !key_exists(&storage, &master_key, key, category).await? {
+                eprintln!("Key '{}' not found.", display_path);
+                std::process::exit(1);
+            }
+            let mut key_meta = meta::load(&storage, &master_key, key, category).await?;
+
+            match command {
+                AttachCommands::Add { file, name, .. } => {
+                    let name = match name {
+                        Some(n) => n.clone(),
+                        None => file
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .context("Cannot derive an attachment name; pass --name")?,
+                    };
+                    let data = std::fs::read(file)
+                        .with_context(|| format!("Failed to read '{}'", file.display()))?;
+                    if data.len() > storage::MAX_VALUE_SIZE {
+                        eprintln!(
+                            "'{}' is {} bytes; attachments are limited to {} bytes.",
+                            file.display(),
+                            data.len(),
+                            storage::MAX_VALUE_SIZE
+                        );
+                        std::process::exit(1);
+                    }
+                    if key_meta.attachments.contains_key(&name)
+                        && !prompt_yes_no(&format!(
+                            "'{}' already has an attachment named '{}'. Replace it?",
+                            display_path, name
+                        ))?
+                    {
+                        println!("Attachment cancelled.");
+                        return Ok(());
+                    }
+
+                    let mut changes = Vec::new();
+                    if let Some(old) = key_meta.attachments.get(&name) {
+                        changes.extend(
+                            storage
+                                .path_changes(&meta::attachment_path(&old.id), None)
+                                .await?,
+                        );
+                    }
+                    let attachment = meta::Attachment {
+                        id: meta::new_attachment_id(),
+                        size: data.len(),
+                        added: timeutil::format_rfc3339(timeutil::now_unix()),
+                    };
+                    let encrypted = encrypt_value(&master_key, category, &data)?;
+                    changes.extend(
+                        storage
+                            .path_changes(
+                                &meta::attachment_path(&attachment.id),
+                                Some(&serde_json::to_vec(&encrypted)?),
+                            )
+                            .await?,
+                    );
+                    key_meta.attachments.insert(name.clone(), attachment);
+                    changes.extend(
+                        meta::save_changes(&storage, &master_key, key, category, &key_meta).await?,
+                    );
+                    storage
+                        .apply_changes(&changes, &format!("Attach file to key: {}", display_path))
+                        .await?;
+                    println!("Attached '{}' to '{}'.", name, display_path);
+                }
+                AttachCommands::Get { name, out, .. } => {
+                    let attachment = match key_meta.attachments.get(name) {
+                        Some(a) => a,
+                        None => {
+                            eprintln!("'{}' has no attachment named '{}'.", display_path, name);
+                            std::process::exit(1);
+                        }
+                    };
+                    let data = meta::read_attachment(&storage, &master_key, attachment).await?;
+                    output_value(
+                        &format!("{} ({})", display_path, name),
+                        &data,
+                        out.as_deref(),
+                        false,
+                        None,
+                    )?;
+                }
+                AttachCommands::List { .. } => {
+                    if key_meta.attachments.is_empty() {
+                        println!("'{}' has no attachments.", display_path);
+                    } else {
+                        println!("Attachments of '{}':", display_path);
+                        for (name, a) in &key_meta.attachments {
+                            println!("  {}  ({} bytes, added {})", name, a.size, a.added);
+                        }
+                    }
+                }
+                AttachCommands::Remove { name, .. } => {
+                    let attachment = match key_meta.attachments.remove(name) {
+                        Some(a) => a,
+                        None => {
+                            eprintln!("'{}' has no attachment named '{}'.", display_path, name);
+                            std::process::exit(1);
+                        }
+                    };
+                    if !prompt_yes_no(&format!(
+                        "Remove attachment '{}' from '{}'?",
+                        name, display_path
+                    ))? {
+                        println!("Removal cancelled.");
+                        return Ok(());
+                    }
+                    let mut changes = storage
+                        .path_changes(&meta::attachment_path(&attachment.id), None)
+                        .await?;
+                    changes.extend(
+                        meta::save_changes(&storage, &master_key, key, category, &key_meta).await?,
+                    );
+                    storage
+                        .apply_changes(
+                            &changes,
+                            &format!("Remove attachment from key: {}", display_path),
+                        )
+                        .await?;
+                    println!("Removed '{}' from '{}'.", name, display_path);
+                }
+            }
+        }
+        Commands::Meta { command } => match command {
+            MetaCommands::Edit {
+                category,
+                add_tag,
+                remove_tag,
+                set,
+            } => {
+                let mut edit = meta::BulkEdit {
+                    add_tags: add_tag.clone(),
+                    remove_tags: remove_tag.clone(),
+                    ..Default::default()
+                };
+                for assignment in set {
+                    if let Err(e) = edit.set(assignment) {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+                if edit.is_empty() {
+                    eprintln!("Nothing to change. Use --add-tag, --remove-tag or --set.");
+                    std::process::exit(1);
+                }
+                let category = category.trim_matches('/');
+                if category.is_empty() {
+                    eprintln!("The category must be non-empty.");
+                    std::process::exit(1);
+                }
+                let pattern = format!("{}/*", category);
+
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+
+                let paths = matching_paths(&storage, &master_key, &pattern).await?;
+                if paths.is_empty() {
+                    eprintln!("No keys found in categories matching '{}'.", category);
+                    std::process::exit(1);
+                }
+
+                let mut changes = Vec::new();
+                let mut edited = Vec::new();
+                for path in &paths {
+                    cancel::check()?;
+                    let (key_category, name) = templatefn::split_key_path(path);
+                    let mut key_meta =
+                        meta::load(&storage, &master_key, name, key_category).await?;
+                    if edit.apply(&mut key_meta) {
+                        changes.extend(
+                            meta::save_changes(
+                                &storage,
+                                &master_key,
+                                name,
+                                key_category,
+                                &key_meta,
+                            )
+                            .await?,
+                        );
+                        edited.push(path.clone());
+                    }
+                }
+                if edited.is_empty() {
+                    println!(
+                        "The {} matching key(s) already have this metadata.",
+                        paths.len()
+                    );
+                    return Ok(());
+                }
+
+                println!("About to update the metadata of {} key(s):", edited.len());
+                for path in &edited {
+                    println!("  {}", path);
+                }
+                if !prompt_yes_no("Proceed?")? {
+                    println!("Metadata edit cancelled.");
+                    return Ok(());
+                }
+
+                changes.push(
+                    audit::append_change(
+                        &storage,
+                        &audit::AuditEvent::new(
+                            "meta-edit",
+                            &format!("{}: {} key(s) updated", category, edited.len()),
+                        ),
+                    )
+                    .await?,
+                );
+                storage
+                    .commit_changes(&changes, &format!("Update metadata of keys: {}", category))
+                    .await?;
+
+                let lmk = config::Config::get_or_create_lmk_with_profile(
+                    effective_profile.as_deref(),
+                    &password,
+                )?;
+                if index::load(storage.profile(), &storage.repo_full_name(), &lmk)?.is_some() {
+                    refresh_index(&storage, &password, &master_key, false).await?;
+                }
+                println!("Updated the metadata of {} key(s).", edited.len());
+            }
+        },
+        Commands::Tag { command } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let (key, category) = match command {
+                TagCommands::Set { key, category, .. }
+                | TagCommands::List { key, category }
+                | TagCommands::Remove { key, category, .. } => (key, category.as_deref()),
+            };
+            let display_path = match category {
+                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                None => key.clone(),
+            };
+            if !key_exists(&storage, &master_key, key, category).await? {
+                eprintln!("Key '{}' not found.", display_path);
+                std::process::exit(1);
+            }
+            let mut key_meta = meta::load(&storage, &master_key, key, category).await?;
+
+            match command {
+                TagCommands::Set { label, version, .. } => {
+                    if let Err(e) = meta::validate_label(label) {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                    let sha = match version {
+                        Some(version) => {
+                            let wanted = key_meta.resolve_version(version);
+                            let matches: Vec<storage::KeyVersion> = storage
+                                .get_full_key_history(key, category)
+                                .await?
+                                .into_iter()
+                                .filter(|v| !wanted.is_empty() && v.sha.starts_with(wanted))
+                                .collect();
+                            match matches.as_slice() {
+                                [v] => v.sha.clone(),
+                                [] => {
+                                    eprintln!(
+                                        "'{}' is not a version of '{}'. Run 'axkeystore history {}' to list them.",
+                                        version, display_path, display_path
+                                    );
+                                    std::process::exit(1);
+                                }
+                                _ => {
+                                    eprintln!(
+                                        "'{}' matches several versions of '{}'. Give more of the SHA.",
+                                        version, display_path
+                                    );
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        None => match storage.get_latest_key_version(key, category).await? {
+                            Some(v) => v.sha,
+                            None => {
+                                eprintln!("No history found for key '{}'.", display_path);
+                                std::process::exit(1);
+                            }
+                        },
+                    };
+                    let short_sha = &sha[..sha.len().min(10)];
+
+                    match key_meta.labels.get(label) {
+                        Some(current) if *current == sha => {
+                            println!(
+                                "'{}' of '{}' already points to version {}.",
+                                label, display_path, short_sha
+                            );
+                            return Ok(());
+                        }
+                        Some(current)
+                            if !prompt_yes_no(&format!(
+                                "'{}' of '{}' points to version {}. Move it to {}?",
+                                label,
+                                display_path,
+                                &current[..current.len().min(10)],
+                                short_sha
+                            ))? =>
+                        {
+                            println!("Labelling cancelled.");
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
+
+                    key_meta.labels.insert(label.clone(), sha.clone());
+                    let changes =
+                        meta::save_changes(&storage, &master_key, key, category, &key_meta).await?;
+                    storage
+                        .apply_changes(
+                            &changes,
+                            &format!("Label version of key: {} ({})", display_path, label),
+                        )
+                        .await?;
+                    println!(
+                        "Labelled version {} of '{}' as '{}'.",
+                        short_sha, display_path, label
+                    );
+                }
+                TagCommands::List { .. } => {
+                    if key_meta.labels.is_empty() {
+                        println!("'{}' has no version labels.", display_path);
+                    } else {
+                        println!("Version labels of '{}':", display_path);
+                        for (label, sha) in &key_meta.labels {
+                            println!("  {:<20} {}", label, sha);
+                        }
+                    }
+                }
+                TagCommands::Remove { label, .. } => {
+                    if key_meta.labels.remove(label).is_none() {
+                        eprintln!("'{}' has no version label '{}'.", display_path, label);
+                        std::process::exit(1);
+                    }
+                    let changes =
+                        meta::save_changes(&storage, &master_key, key, category, &key_meta).await?;
+                    storage
+                        .apply_changes(
+                            &changes,
+                            &format!("Remove version label of key: {} ({})", display_path, label),
+                        )
+                        .await?;
+                    println!("Removed label '{}' from '{}'.", label, display_path);
+                }
+            }
+        }
+        Commands::Promote {
+            from_category: Some(from_category),
+            to_category,
+            keys,
+            from_profile,
+            to_profile,
+            dry_run,
+            ..
+        } => {
+            let to_category = match (to_category, to_profile) {
+                (Some(to), _) => to,
+                (None, Some(_)) => from_category,
+                (None, None) => {
+                    eprintln!("Give the category to copy into with --to (or another profile with --to-profile).");
+                    std::process::exit(1);
+                }
+            };
+            promote_category(
+                from_profile.as_deref().or(effective_profile.as_deref()),
+                to_profile.as_deref(),
+                from_category,
+                to_category,
+                keys,
+                *dry_run,
+            )
+            .await?;
+        }
+        Commands::Promote {
+            from: Some(from),
+            to: Some(to),
+            from_profile,
+            to_profile,
+            ..
+        } => {
+            let from_profile = from_profile.as_deref().or(effective_profile.as_deref());
+            let (from_category, from_key) = templatefn::split_key_path(from);
+            let (to_category, to_key) = templatefn::split_key_path(to);
+            let to_path = derived::key_id(to_key, to_category)?;
+
+            let (source, target) = unlock_pair(from_profile, to_profile.as_deref()).await?;
+            let target = target.as_ref().unwrap_or(&source);
+            if target.storage.repo_full_name() == source.storage.repo_full_name()
+                && derived::key_id(from_key, from_category)? == to_path
+            {
+                eprintln!("Cannot promote '{}' into itself.", to_path);
+                std::process::exit(1);
+            }
+
+            let value =
+                match read_value(&source.storage, &source.master_key, from_key, from_category)
+                    .await?
+                {
+                    Some(value) => value,
+                    None => {
+                        eprintln!("Key '{}' not found.", from);
+                        std::process::exit(1);
+                    }
+                };
+            if lint::is_protected(&to_path) {
+                let problems = lint::check(&value);
+                if !problems.is_empty() {
+                    eprintln!(
+                        "Refusing to promote '{}' into '{}', which is a production category:",
+                        from, to_path
+                    );
+                    for problem in &problems {
+                        eprintln!("  - {}", problem);
+                    }
+                    eprintln!("Store a strong value for '{}' instead.", to_path);
+                    std::process::exit(1);
+                }
+            }
+
+            if derived::load(&target.storage, &target.master_key)
+                .await?
+                .keys
+                .contains_key(&to_path)
+            {
+                eprintln!(
+                    "Key '{}' is derived; its value cannot be replaced.",
+                    to_path
+                );
+                std::process::exit(1);
+            }
+            if target
+                .storage
+                .get_blob(to_key, to_category)
+                .await?
+                .is_some()
+                && !prompt_yes_no(&format!(
+                    "Key '{}' already exists in '{}'. Overwrite it with the value of '{}'?",
+                    to_path,
+                    target.storage.repo_full_name(),
+                    from
+                ))?
+            {
+                println!("Promotion cancelled.");
+                return Ok(());
+            }
+
+            let encrypted = encrypt_value(&target.master_key, to_category, &value)?;
+            target
+                .storage
+                .save_blob(to_key, &serde_json::to_vec(&encrypted)?, to_category)
+                .await?;
+            remember_keys(
+                &target.storage,
+                &target.password,
+                to_category,
+                &[to_key],
+                false,
+                None,
+            )?;
+            println!(
+                "Copied '{}' to '{}' in '{}'.",
+                from,
+                to_path,
+                target.storage.repo_full_name()
+            );
+            notify_change(&target.storage, &target.master_key, "stored", &[to_path]).await;
+        }
+        Commands::Promote { .. } => {
+            eprintln!("Give the key paths to copy, or a category with --from.");
+            std::process::exit(1);
+        }
+        Commands::Plan { manifest, check } => {
+            let manifest_file = apply::Manifest::load(manifest)?;
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+            let (steps, _) = plan_manifest(
+                &storage,
+                &master_key,
+                &manifest_file,
+                false,
+                timeutil::now_unix(),
+            )
+            .await?;
+            print_plan(&storage, manifest, &steps);
+            let drift = steps.iter().filter(|s| s.is_drift()).count();
+            if drift == 0 {
+                println!("The vault matches the manifest.");
+            } else if *check {
+                eprintln!(
+                    "{} key(s) differ from the manifest. Run 'axkeystore apply {}' to bring the vault in line.",
+                    drift,
+                    manifest.display()
+                );
+                std::process::exit(1);
+            }
+        }
+        Commands::Apply {
+            manifest,
+            dry_run,
+            auto_approve,
+        } => {
+            apply_manifest(
+                effective_profile.as_deref(),
+                manifest,
+                *dry_run,
+                *auto_approve,
+            )
+            .await?;
+        }
+        Commands::Render {
+            template,
+            out,
+            in_place,
+        } => {
+            let text = std::fs::read_to_string(template)
+                .with_context(|| format!("Failed to read '{}'", template.display()))?;
+            let paths = templatefn::placeholders(&text);
+
+            let mut values = BTreeMap::new();
+            if !paths.is_empty() {
+                // Prompt on the terminal, so stdout only ever carries the rendered file
+                let password = rpassword::prompt_password("Enter master password: ")
+                    .context("Failed to read password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+                let mut missing = Vec::new();
+                for path in &paths {
+                    let (category, key) = templatefn::split_key_path(path);
+                    match read_value(&storage, &master_key, key, category).await? {
+                        Some(value) => {
+                            values.insert(path.clone(), value);
+                        }
+                        None => missing.push(path.as_str()),
+                    }
+                }
+                if !missing.is_empty() {
+                    eprintln!("Keys not found: {}", missing.join(", "));
+                    std::process::exit(1);
+                }
+            }
+            let rendered = templatefn::render(&text, &values)?;
+
+            let target = if *in_place {
+                Some(template)
+            } else {
+                out.as_ref()
+            };
+            match target {
+                Some(path) => {
+                    config::write_private_file(path, rendered.as_bytes())?;
+                    eprintln!("Rendered {} key(s) into '{}'.", paths.len(), path.display());
+                }
+                None => print!("{}", rendered),
+            }
+        }
+        Commands::Env { category, shell } => {
+            let category = match storage::Storage::validate_category(Some(category))? {
+                Some(category) => category,
+                None => {
+                    eprintln!("The category must be non-empty.");
+                    std::process::exit(1);
+                }
+            };
+            // Prompt on the terminal, so stdout only ever carries the statements
+            let password = rpassword::prompt_password("Enter master password: ")
+                .context("Failed to read password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let mut vars = BTreeMap::new();
+            for (path, value) in decrypt_values(&storage, &master_key, Some(&category)).await? {
+                // Reserved keys have no value yet
+                if value.is_empty() {
+                    eprintln!("Skipping '{}': it has no value.", path);
+                    continue;
+                }
+                let value = match String::from_utf8(value) {
+                    Ok(value) if !value.contains('\0') => value,
+                    _ => {
+                        eprintln!("Skipping '{}': binary values cannot be exported.", path);
+                        continue;
+                    }
+                };
+                let name = shellenv::var_name(&path, &category)?;
+                if vars.insert(name.clone(), value).is_some() {
+                    eprintln!(
+                        "Two keys map to the variable name '{}'. Export a narrower category.",
+                        name
+                    );
+                    std::process::exit(1);
+                }
+            }
+            if vars.is_empty() {
+                eprintln!("No keys to export in '{}'.", category);
+                std::process::exit(1);
+            }
+            for (name, value) in &vars {
+                println!("{}", shellenv::export_line(*shell, name, value));
+            }
+        }
+        Commands::TemplateFn { keys } => {
+            // Prompt on the terminal, so stdout only ever carries values
+            let password = rpassword::prompt_password("Enter master password: ")
+                .context("Failed to read password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+            let mut stdout = std::io::stdout();
+
+            if !keys.is_empty() {
+                for path in keys {
+                    let (category, key) = templatefn::split_key_path(path);
+                    match read_value(&storage, &master_key, key, category).await? {
+                        Some(value) => stdout.write_all(&value)?,
+                        None => {
+                            eprintln!("Key '{}' not found.", path);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                stdout.flush()?;
+                return Ok(());
+            }
+
+            for line in std::io::stdin().lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (category, key) = templatefn::split_key_path(&line);
+                let value = read_value(&storage, &master_key, key, category).await?;
+                let response = match templatefn::response_line(value.as_deref()) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        eprintln!("Key '{}': {}", line.trim(), e);
+                        "null".to_string()
+                    }
+                };
+                writeln!(stdout, "{}", response)?;
+                stdout.flush()?;
+            }
+        }
+        Commands::Tui => {
+            launch_tui(effective_profile.as_deref()).await?;
+        }
+        Commands::Completions { shell } => {
+            print!("{}", completion::registration(*shell)?);
+        }
+        Commands::Plugins {
+            allow_write,
+            deny_write,
+        } => {
+            let profile = effective_profile.as_deref();
+            let mut config = config::Config::load_with_profile(profile)?;
+            if let Some(name) = allow_write {
+                plugin::validate_name(name)?;
+                config.writable_plugins.insert(name.clone());
+                config.save_with_profile(profile)?;
+                println!("Plugin '{}' may now store keys.", name);
+                return Ok(());
+            }
+            if let Some(name) = deny_write {
+                if !config.writable_plugins.remove(name) {
+                    println!("Plugin '{}' could not store keys.", name);
+                    return Ok(());
+                }
+                config.save_with_profile(profile)?;
+                println!("Plugin '{}' can no longer store keys.", name);
+                return Ok(());
+            }
+
+            let plugins = plugin::discover(&std::env::var_os("PATH").unwrap_or_default());
+            if plugins.is_empty() {
+                println!(
+                    "No plugins found. Plugins are executables named '{}<name>' on PATH.",
+                    plugin::PREFIX
+                );
+                return Ok(());
+            }
+            for (name, path) in &plugins {
+                let access = if config.writable_plugins.contains(name) {
+                    "read-write"
+                } else {
+                    "read-only"
+                };
+                println!("  {:<20} {:<10} {}", name, access, path.display());
+            }
+        }
+        Commands::Pipe {
+            key,
+            newline,
+            command,
+        } => {
+            // Prompt on the terminal, so stdin and stdout stay free for the command
+            let password = rpassword::prompt_password("Enter master password: ")
+                .context("Failed to read password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let (category, name) = templatefn::split_key_path(key);
+            let mut value = match read_value(&storage, &master_key, name, category).await? {
+                Some(value) => value,
+                None => {
+                    eprintln!("Key '{}' not found.", key);
+                    std::process::exit(1);
+                }
+            };
+            if *newline {
+                value.push(b'\n');
+            }
+            let code = pipe::run(command, &value)?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Compose {
+            command: ComposeCommands::Up { manifest, command },
+        } => {
+            let manifest = match compose::Manifest::load(manifest) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            let password = rpassword::prompt_password("Enter master password: ")
+                .context("Failed to read password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+
+            let mut values = BTreeMap::new();
+            for key in manifest.keys() {
+                let (category, name) = templatefn::split_key_path(key);
+                match read_value(&storage, &master_key, name, category).await? {
+                    Some(value) => {
+                        values.insert(key, value);
+                    }
+                    None => {
+                        eprintln!("Key '{}' not found.", key);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            let secrets: BTreeMap<String, Vec<u8>> = manifest
+                .secrets
+                .iter()
+                .map(|(name, key)| (name.clone(), values[key.as_str()].clone()))
+                .collect();
+            let mut env = BTreeMap::new();
+            for (var, key) in &manifest.env {
+                match String::from_utf8(values[key.as_str()].clone()) {
+                    Ok(value) => {
+                        env.insert(var.clone(), value);
+                    }
+                    Err(_) => {
+                        eprintln!(
+                            "Key '{}' is binary and cannot be passed in '{}'. Declare it under [secrets] instead.",
+                            key, var
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
 
-            let mut terminal = match tui::init_terminal() {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("Failed to initialize terminal: {}", e);
-                    std::process::exit(1);
+            let dir = compose::secrets_dir();
+            let result = match dockersecrets::write_dir(&dir, &secrets) {
+                Ok(()) => {
+                    // Ctrl-C reaches the command too; wait for it to shut down, so the
+                    // files are removed after the containers that use them
+                    let command = command.clone();
+                    let run_dir = dir.clone();
+                    let child =
+                        tokio::task::spawn_blocking(move || compose::run(&command, &run_dir, &env));
+                    tokio::pin!(child);
+                    loop {
+                        tokio::select! {
+                            result = &mut child => {
+                                break result.context("The command panicked").and_then(|r| r);
+                            }
+                            _ = tokio::signal::ctrl_c() => {
+                                eprintln!(
+                                    "\nWaiting for the command to exit before removing the secrets..."
+                                );
+                            }
+                        }
+                    }
                 }
+                Err(e) => Err(e),
             };
-
-            let _ = tui::draw_loading(&mut terminal, "Authenticating with GitHub...");
-
-            let repo_name = match config::Config::get_repo_name_with_profile(
-                effective_profile.as_deref(),
-                &password,
-            ) {
-                Ok(name) => name,
-                Err(e) => {
-                    let _ = tui::restore_terminal(terminal);
-                    eprintln!("Configuration missing or master password incorrect: {}", e);
+            if let Err(e) = dockersecrets::clean(&dir) {
+                eprintln!(
+                    "Warning: failed to remove the secrets in '{}': {}",
+                    dir.display(),
+                    e
+                );
+            }
+            let code = result?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Plugin(args) => {
+            let (name, args) = match args.split_first() {
+                Some(split) => split,
+                None => return Ok(()),
+            };
+            plugin::validate_name(name)?;
+            let executable = match plugin::find(name, &std::env::var_os("PATH").unwrap_or_default())
+            {
+                Some(executable) => executable,
+                None => {
+                    eprintln!(
+                        "Unknown command '{}'. No '{}{}' plugin was found on PATH (see 'axkeystore --help').",
+                        name,
+                        plugin::PREFIX,
+                        name
+                    );
                     std::process::exit(1);
                 }
             };
 
-            let storage = match storage::Storage::new_with_profile(
+            // Prompt on the terminal, so stdout only carries the plugin's output
+            let password = rpassword::prompt_password("Enter master password: ")
+                .context("Failed to read password")?;
+            let writable = config::Config::load_with_profile(effective_profile.as_deref())?
+                .writable_plugins
+                .contains(name);
+            let vault = axkeystore::Vault::open(effective_profile.as_deref(), &password).await?;
+            let code = plugin::run(
+                &executable,
+                name,
+                args,
                 effective_profile.as_deref(),
-                &repo_name,
-                &password,
+                vault,
+                writable,
             )
-            .await
-            {
-                Ok(s) => s,
-                Err(e) => {
-                    let _ = tui::restore_terminal(terminal);
-                    eprintln!("Failed to initialize storage: {}", e);
-                    std::process::exit(1);
+            .await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        Commands::Wasm { command } => {
+            let profile = effective_profile.as_deref();
+            let dir = wasmplugin::plugins_dir(profile)?;
+            let mut config = config::Config::load_with_profile(profile)?;
+            match command {
+                WasmCommands::List => {
+                    println!("Plugins directory: {}", dir.display());
+                    let modules = wasmplugin::list_modules(&dir)?;
+                    if modules.is_empty() {
+                        println!("No modules found. Copy '<name>.wasm' files there to use them.");
+                    } else {
+                        println!("Modules:");
+                        for name in &modules {
+                            println!("  {}", name);
+                        }
+                    }
+                    if !config.category_plugins.is_empty() {
+                        println!("Categories:");
+                        for (category, plugins) in &config.category_plugins {
+                            println!(
+                                "  {}  generator: {}  policy: {}",
+                                category,
+                                plugins.generator.as_deref().unwrap_or("-"),
+                                plugins.policy.as_deref().unwrap_or("-")
+                            );
+                        }
+                    }
                 }
-            };
-
-            let _ = tui::draw_loading(&mut terminal, "Fetching and verifying master key...");
-            let master_key = match get_or_init_master_key(&storage, &password).await {
-                Ok(k) => k,
-                Err(e) => {
-                    let _ = tui::restore_terminal(terminal);
-                    eprintln!("Failed to get master key: {}", e);
-                    std::process::exit(1);
+                WasmCommands::Set {
+                    category,
+                    generator,
+                    policy,
+                } => {
+                    let category = match storage::Storage::validate_category(Some(category))? {
+                        Some(category) => category,
+                        None => {
+                            eprintln!("The category must be non-empty.");
+                            std::process::exit(1);
+                        }
+                    };
+                    // Compile the modules now, so a broken one is not found on the next store
+                    for name in generator.iter().chain(policy.iter()) {
+                        if let Err(e) = wasmplugin::Plugin::load(&dir, name) {
+                            eprintln!("{:#}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                    let plugins = config.category_plugins.entry(category.clone()).or_default();
+                    if generator.is_some() {
+                        plugins.generator = generator.clone();
+                    }
+                    if policy.is_some() {
+                        plugins.policy = policy.clone();
+                    }
+                    config.save_with_profile(profile)?;
+                    println!("WASM plugins updated for category '{}'.", category);
+                }
+                WasmCommands::Unset { category } => {
+                    let category = category.trim_matches('/');
+                    if config.category_plugins.remove(category).is_none() {
+                        println!("No WASM plugins are assigned to category '{}'.", category);
+                        return Ok(());
+                    }
+                    config.save_with_profile(profile)?;
+                    println!("WASM plugins removed from category '{}'.", category);
                 }
-            };
-
-            let _ = tui::draw_loading(&mut terminal, "Downloading keys from GitHub...");
-            if let Err(e) = tui::run(terminal, storage, master_key).await {
-                eprintln!("TUI error: {}", e);
-                std::process::exit(1);
             }
-            return Ok(());
         }
-    };
+        Commands::Category { command } => match command {
+            CategoryCommands::List => {
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
 
-    match command {
-        Commands::Login => {
-            if auth::is_logged_in_with_profile(effective_profile.as_deref()) {
-                let reauth = prompt_yes_no(
-                    "You are already logged in for this profile. Do you want to re-authenticate?",
+                let tree = storage.list_tree().await?;
+                let registry = derived::load(&storage, &master_key).await?;
+                let counts = categories::counts(
+                    tree.keys()
+                        .filter_map(|path| index::key_display_path(path))
+                        .chain(registry.keys.keys().map(|id| id.as_str())),
+                );
+                if counts.is_empty() {
+                    println!("No categories found.");
+                    return Ok(());
+                }
+
+                let width = counts.keys().map(|c| c.len()).max().unwrap_or(0);
+                for (category, (direct, total)) in &counts {
+                    if total > direct {
+                        println!(
+                            "  {:<width$}  {} key(s), {} in total",
+                            category,
+                            direct,
+                            total,
+                            width = width
+                        );
+                    } else {
+                        println!("  {:<width$}  {} key(s)", category, direct, width = width);
+                    }
+                }
+
+                // Listing saw every category, so the cached index can be replaced
+                let lmk = config::Config::get_or_create_lmk_with_profile(
+                    effective_profile.as_deref(),
+                    &password,
                 )?;
-                if !reauth {
-                    println!("Login cancelled.");
+                categories::store_index(
+                    effective_profile.as_deref(),
+                    &storage.repo_full_name(),
+                    &lmk,
+                    &counts
+                        .iter()
+                        .filter(|(_, (direct, _))| *direct > 0)
+                        .map(|(category, _)| category.clone())
+                        .collect(),
+                )?;
+            }
+            CategoryCommands::Delete { category } => {
+                let category = match storage::Storage::validate_category(Some(category))? {
+                    Some(category) => category,
+                    None => {
+                        eprintln!("The category must be non-empty.");
+                        std::process::exit(1);
+                    }
+                };
+
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+
+                let paths =
+                    matching_paths(&storage, &master_key, &format!("{}/**", category)).await?;
+                if paths.is_empty() {
+                    println!("No keys found in category '{}'.", category);
+                    return Ok(());
+                }
+
+                println!("\nAbout to delete {} key(s):", paths.len());
+                for path in &paths {
+                    println!("  {}", path);
+                }
+                if !prompt_yes_no(&format!(
+                    "Are you sure you want to delete category '{}' and every key in it?",
+                    category
+                ))? {
+                    println!("Deletion cancelled.");
                     return Ok(());
                 }
+
+                delete_keys(
+                    &storage,
+                    &password,
+                    &master_key,
+                    &paths,
+                    &format!("Delete category: {}", category),
+                    &audit::AuditEvent::new(
+                        "category-delete",
+                        &format!("{}: {} key(s) deleted", category, paths.len()),
+                    ),
+                )
+                .await?;
+
+                let lmk = config::Config::get_or_create_lmk_with_profile(
+                    effective_profile.as_deref(),
+                    &password,
+                )?;
+                if let Some(mut category_index) = categories::load_index(
+                    effective_profile.as_deref(),
+                    &storage.repo_full_name(),
+                    &lmk,
+                )? {
+                    category_index
+                        .retain(|c| c != &category && !c.starts_with(&format!("{}/", category)));
+                    categories::store_index(
+                        effective_profile.as_deref(),
+                        &storage.repo_full_name(),
+                        &lmk,
+                        &category_index,
+                    )?;
+                }
+                println!("Category '{}' deleted ({} key(s)).", category, paths.len());
+                notify_change(&storage, &master_key, "deleted", &paths).await;
             }
+            CategoryCommands::Merge { from, into }
+            | CategoryCommands::Rename { from, to: into } => {
+                let rename = matches!(command, CategoryCommands::Rename { .. });
+                let verb = if rename { "rename" } else { "merge" };
+                let (from, into) = match (
+                    storage::Storage::validate_category(Some(from))?,
+                    storage::Storage::validate_category(Some(into))?,
+                ) {
+                    (Some(from), Some(into)) => (from, into),
+                    _ => {
+                        eprintln!("Both categories must be non-empty.");
+                        std::process::exit(1);
+                    }
+                };
+                let into_is_inside_from = into == from || into.starts_with(&format!("{}/", from));
+                if into_is_inside_from {
+                    eprintln!(
+                        "Cannot {} '{}' to '{}': the target must be outside the source category.",
+                        verb, from, into
+                    );
+                    std::process::exit(1);
+                }
 
-            let token = match auth::authenticate().await {
-                Ok(t) => t,
-                Err(e) => {
-                    eprintln!("Authentication failed: {:#}", e);
+                let password = prompt_password("Enter master password")?;
+                let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                let master_key = get_or_init_master_key(&storage, &password).await?;
+
+                let entries = storage.list_all_keys().await?;
+                let mut taken: std::collections::HashSet<String> =
+                    entries.iter().map(|e| e.display_path()).collect();
+                let sources: Vec<&storage::KeyEntry> =
+                    entries.iter().filter(|e| e.in_category(&from)).collect();
+
+                let registry = derived::load(&storage, &master_key).await?;
+                let derived_in_source: Vec<&String> = registry
+                    .keys
+                    .keys()
+                    .filter(|id| id.starts_with(&format!("{}/", from)))
+                    .collect();
+
+                if sources.is_empty() {
+                    println!("No stored keys found in category '{}'.", from);
+                    return Ok(());
+                }
+                let target_exists = entries.iter().any(|e| e.in_category(&into))
+                    || registry
+                        .keys
+                        .keys()
+                        .any(|id| id.starts_with(&format!("{}/", into)));
+                if rename && target_exists {
+                    eprintln!(
+                        "Category '{}' already exists. Use 'axkeystore category merge {} {}' to combine them.",
+                        into, from, into
+                    );
                     std::process::exit(1);
                 }
-            };
 
-            // Check if LMK already exists for this profile
-            let config = config::Config::load_with_profile(effective_profile.as_deref())?;
-            let lmk_exists = config.encrypted_lmk.is_some();
+                // Plan every move before touching the repository
+                let mut moves = Vec::new();
+                let mut skipped = 0;
+                let mut renamed = 0;
+                for entry in &sources {
+                    let category = entry.category.as_deref().unwrap_or_default();
+                    let target_category = format!("{}{}", into, &category[from.len()..]);
+                    let target_path = format!("{}/{}", target_category, entry.name);
 
-            println!("Setting up master password to secure your token locally...");
-            let password = if lmk_exists {
-                println!("A master password is already set for this profile.");
-                let p = prompt_password("Enter master password")?;
+                    let name = if taken.contains(&target_path) {
+                        match resolve_merge_collision(&target_category, &entry.name, &taken)? {
+                            Some(name) => {
+                                if name != entry.name {
+                                    renamed += 1;
+                                }
+                                name
+                            }
+                            None => {
+                                skipped += 1;
+                                continue;
+                            }
+                        }
+                    } else {
+                        entry.name.clone()
+                    };
+                    taken.insert(format!("{}/{}", target_category, name));
+                    moves.push((*entry, target_category, name));
+                }
 
-                // Verify the password by trying to decrypt the LMK
-                match config::Config::get_or_create_lmk_with_profile(
+                if moves.is_empty() {
+                    println!("Nothing to {}.", verb);
+                    return Ok(());
+                }
+
+                println!("\nAbout to move {} key(s):", moves.len());
+                for (entry, target_category, name) in &moves {
+                    println!("  {} -> {}/{}", entry.display_path(), target_category, name);
+                }
+                if !prompt_yes_no(&format!("Proceed with the {}?", verb))? {
+                    println!("Category {} cancelled.", verb);
+                    return Ok(());
+                }
+
+                let details = format!(
+                    "{} -> {}: {} key(s) moved, {} renamed, {} skipped",
+                    from,
+                    into,
+                    moves.len(),
+                    renamed,
+                    skipped
+                );
+                let mut changes = Vec::new();
+                for (entry, target_category, name) in &moves {
+                    let overwrite = entries.iter().any(|e| {
+                        e.category.as_deref() == Some(target_category.as_str()) && e.name == *name
+                    });
+                    let data = reencrypt_for(&master_key, Some(target_category), &entry.data)?;
+                    changes.extend(
+                        storage
+                            .key_changes(name, Some(target_category), Some(&data))
+                            .await?,
+                    );
+                    changes.extend(
+                        storage
+                            .key_changes(&entry.name, entry.category.as_deref(), None)
+                            .await?,
+                    );
+                    changes.extend(
+                        meta::move_changes(
+                            &storage,
+                            &master_key,
+                            (&entry.name, entry.category.as_deref()),
+                            (name, Some(target_category)),
+                            overwrite,
+                        )
+                        .await?,
+                    );
+                }
+                changes.push(
+                    audit::append_change(
+                        &storage,
+                        &audit::AuditEvent::new(&format!("category-{}", verb), &details),
+                    )
+                    .await?,
+                );
+                let message = if rename {
+                    format!("Rename category: {} to {}", from, into)
+                } else {
+                    format!("Merge category: {} into {}", from, into)
+                };
+                storage.commit_changes(&changes, &message).await?;
+
+                // Refresh the category index with the new layout
+                let moved: std::collections::HashSet<String> =
+                    moves.iter().map(|(e, _, _)| e.display_path()).collect();
+                let mut category_index: std::collections::BTreeSet<String> = entries
+                    .iter()
+                    .filter(|e| !moved.contains(&e.display_path()))
+                    .filter_map(|e| e.category.clone())
+                    .collect();
+                category_index.extend(moves.iter().map(|(_, cat, _)| cat.clone()));
+                category_index.extend(
+                    registry
+                        .keys
+                        .keys()
+                        .filter_map(|id| id.rsplit_once('/').map(|(cat, _)| cat.to_string())),
+                );
+                let lmk = config::Config::get_or_create_lmk_with_profile(
                     effective_profile.as_deref(),
-                    &p,
-                ) {
-                    Ok(_) => p,
-                    Err(_) => {
-                        eprintln!("Incorrect master password.");
+                    &password,
+                )?;
+                categories::store_index(
+                    effective_profile.as_deref(),
+                    &storage.repo_full_name(),
+                    &lmk,
+                    &category_index,
+                )?;
+                if index::load(storage.profile(), &storage.repo_full_name(), &lmk)?.is_some() {
+                    refresh_index(&storage, &password, &master_key, false).await?;
+                }
+
+                if rename {
+                    println!("Renamed '{}' to '{}' ({}).", from, into, details);
+                } else {
+                    println!("Merged '{}' into '{}' ({}).", from, into, details);
+                }
+                if !derived_in_source.is_empty() {
+                    println!(
+                        "Note: derived keys were left in place because their values depend on their path: {}",
+                        derived_in_source
+                            .iter()
+                            .map(|s| s.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+            }
+        },
+        Commands::Recipient { command } => {
+            let password = prompt_password("Enter master password")?;
+
+            match command {
+                RecipientCommands::Key => {
+                    let identity = recipients::get_or_create_identity(
+                        effective_profile.as_deref(),
+                        &password,
+                    )?;
+                    println!("Your public key for profile '{}':", profile_str);
+                    println!("{}", crypto::CryptoHandler::identity_public_key(&identity));
+                    println!("\nShare it with the vault owner, who can run:");
+                    println!("  axkeystore recipient add <your-name> <public-key>");
+                    println!("(with '--category <name>' to share one top-level category only)");
+                }
+                RecipientCommands::Add {
+                    name,
+                    public_key,
+                    category: None,
+                } => {
+                    let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                    let master_key = get_or_init_master_key(&storage, &password).await?;
+                    recipients::add(&storage, name, public_key, &master_key).await?;
+                    println!("Recipient '{}' can now unlock this vault.", name);
+                }
+                RecipientCommands::Add {
+                    name,
+                    public_key,
+                    category: Some(category),
+                } => {
+                    let category = match recipients::validate_shared_category(category) {
+                        Ok(category) => category,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                    let master_key = get_or_init_master_key(&storage, &password).await?;
+                    // Keys written before category keys existed are still under the master key
+                    let migrated =
+                        rekey::reencrypt_category(&storage, &master_key, &category).await?;
+                    if !migrated.is_empty() {
+                        println!(
+                            "Re-encrypted {} key(s) of '{}' with the category's key.",
+                            migrated.len(),
+                            category
+                        );
+                    }
+                    recipients::add_for_category(
+                        &storage,
+                        name,
+                        public_key,
+                        &master_key,
+                        &category,
+                    )
+                    .await?;
+                    println!(
+                        "Recipient '{}' can now read the keys of category '{}' (and its subcategories) only.",
+                        name, category
+                    );
+                }
+                RecipientCommands::Remove {
+                    name,
+                    category: Some(category),
+                } => {
+                    let category = match recipients::validate_shared_category(category) {
+                        Ok(category) => category,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                    if !prompt_yes_no(&format!(
+                        "Are you sure you want to remove recipient '{}' from category '{}'?",
+                        name, category
+                    ))? {
+                        println!("Removal cancelled.");
+                        return Ok(());
+                    }
+                    if recipients::remove_for_category(&storage, name, &category).await? {
+                        println!("Recipient '{}' removed from category '{}'.", name, category);
+                        println!("   Note: they may have already seen the category's key. Rotate the master key ('axkeystore rekey') to fully revoke access.");
+                    } else {
+                        eprintln!("Recipient '{}' not found in category '{}'.", name, category);
                         std::process::exit(1);
                     }
                 }
-            } else {
-                loop {
-                    let p1 = prompt_password("Set master password")?;
-                    if p1.len() < 8 {
-                        eprintln!("Password must be at least 8 characters long.");
-                        continue;
+                RecipientCommands::Remove {
+                    name,
+                    category: None,
+                } => {
+                    let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                    if !prompt_yes_no(&format!(
+                        "Are you sure you want to remove recipient '{}'?",
+                        name
+                    ))? {
+                        println!("Removal cancelled.");
+                        return Ok(());
                     }
-                    let p2 = prompt_password("Confirm master password")?;
-                    if p1 == p2 {
-                        break p1;
+                    if recipients::remove(&storage, name).await? {
+                        println!("Recipient '{}' removed.", name);
+                        println!("   Note: they may have already seen the master key. Rotate the master key to fully revoke access.");
+                    } else {
+                        eprintln!("Recipient '{}' not found.", name);
+                        std::process::exit(1);
                     }
-                    eprintln!("Passwords do not match. Please try again.");
                 }
-            };
+                RecipientCommands::List => {
+                    let storage = open_storage(effective_profile.as_deref(), &password).await?;
+                    let list = recipients::list(&storage).await?;
+                    let categories = recipients::shared_categories(&storage).await?;
+                    if list.is_empty() && categories.is_empty() {
+                        println!("No recipients. Only the master password can unlock this vault.");
+                    }
+                    if !list.is_empty() {
+                        println!("\nRecipients:");
+                        for r in list {
+                            println!("  {:<20} {}", r.name, r.public_key);
+                        }
+                    }
+                    for category in categories {
+                        println!("\nRecipients of category '{}':", category);
+                        for r in recipients::list_for_category(&storage, &category).await? {
+                            println!("  {:<20} {}", r.name, r.public_key);
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Backup { command } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            match command {
+                BackupCommands::Create { file } => {
+                    let archive = backup::create(&storage).await?;
+                    backup::write(file, &archive, &password)?;
+                    println!(
+                        "Backed up {} key(s) and {} vault file(s) from '{}' to '{}'.",
+                        archive.keys.len(),
+                        archive.files.len(),
+                        archive.repo,
+                        file.display()
+                    );
+                }
+                BackupCommands::Diff { file } => {
+                    let archive = backup::read(file, &password)?;
+                    let master_key = get_or_init_master_key(&storage, &password).await?;
+                    let repo = storage.repo_full_name();
+                    if archive.repo != repo {
+                        eprintln!(
+                            "Warning: the backup was taken from '{}', not '{}'.",
+                            archive.repo, repo
+                        );
+                    }
 
-            auth::save_token_with_profile(effective_profile.as_deref(), &token, &password)?;
-            println!(
-                "Successfully authenticated and secured token for profile '{}'.",
-                effective_profile.as_deref().unwrap_or("default")
-            );
-            println!("\nNext step: If you haven't already, ensure your repository exists on GitHub, then run 'axkeystore init --repo <YOUR_REPO>' to set up your vault.");
+                    let live: BTreeMap<String, Vec<u8>> = storage
+                        .list_all_keys()
+                        .await?
+                        .into_iter()
+                        .map(|entry| (entry.display_path(), entry.data))
+                        .collect();
+                    let drift = backup::diff(&archive, &live, &master_key)?;
+                    println!(
+                        "Backup of {} ({} keys) against the live vault ({} keys):",
+                        archive.created,
+                        archive.keys.len(),
+                        live.len()
+                    );
+                    if drift.is_empty() {
+                        println!("  No drift: every key matches the backup.");
+                        return Ok(());
+                    }
+
+                    let mut drifted = 0;
+                    for (path, kind) in &drift {
+                        let description = match kind {
+                            backup::Drift::NotBackedUp => "not in the backup",
+                            backup::Drift::Deleted => "deleted since the backup",
+                            backup::Drift::Changed => "value changed",
+                            backup::Drift::Reencrypted => "re-encrypted, same value",
+                            backup::Drift::Unreadable => {
+                                "cannot be decrypted with the current master key"
+                            }
+                        };
+                        if *kind != backup::Drift::Reencrypted {
+                            drifted += 1;
+                        }
+                        println!("  {:<40} {}", path, description);
+                    }
+                    if drifted > 0 {
+                        println!(
+                            "{} key(s) drifted. Take a new backup with 'axkeystore backup create'.",
+                            drifted
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
         }
-        Commands::List => {
+        Commands::Restore { file, repo } => {
             let password = prompt_password("Enter master password")?;
-            let repo_name = config::Config::get_repo_name_with_profile(
-                effective_profile.as_deref(),
-                &password,
-            )?;
-            let storage = storage::Storage::new_with_profile(
+            let archive = match backup::read(file, &password) {
+                Ok(archive) => archive,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            let master_key = match backup::master_key(&archive, &password) {
+                Ok(Some(master_key)) => master_key,
+                Ok(None) => {
+                    eprintln!("The backup has no master key (it was taken by an older version), so its keys cannot be restored.");
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            restore_backup(
                 effective_profile.as_deref(),
-                &repo_name,
                 &password,
+                &archive,
+                &master_key,
+                repo.as_deref(),
             )
             .await?;
+        }
+        Commands::Mirror {
+            to,
+            provider,
+            host,
+            auto,
+            off,
+        } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
             let master_key = get_or_init_master_key(&storage, &password).await?;
+            if *off {
+                match mirror::load(&storage, &master_key).await? {
+                    Some(settings) => {
+                        mirror::remove(&storage, "Stop mirroring").await?;
+                        println!(
+                            "Mirroring stopped. {} was left as it is.",
+                            settings.describe()
+                        );
+                    }
+                    None => println!("The vault is not mirrored."),
+                }
+                return Ok(());
+            }
 
-            let entries = storage.list_all_keys().await?;
+            let settings = match to {
+                Some(to) => {
+                    let provider = provider.unwrap_or_default();
+                    let checked =
+                        mirror::validate_repo(to, provider, storage.login()).and_then(|repo| {
+                            Ok((
+                                repo,
+                                host.as_deref().map(mirror::validate_host).transpose()?,
+                            ))
+                        });
+                    let (repo, host) = match checked {
+                        Ok(checked) => checked,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    // The profile's token only reaches github.com; other hosts need their own
+                    let token = if provider == mirror::Provider::Github && host.is_none() {
+                        if repo.eq_ignore_ascii_case(&storage.repo_full_name()) {
+                            eprintln!("A vault cannot be mirrored to its own repository.");
+                            std::process::exit(1);
+                        }
+                        init_repo(
+                            &storage::Storage::new_with_profile(
+                                effective_profile.as_deref(),
+                                &repo,
+                                &password,
+                            )
+                            .await?,
+                        )
+                        .await?;
+                        None
+                    } else {
+                        let token = prompt_password(&format!(
+                            "Enter a {} access token that can push to {}",
+                            provider.name(),
+                            repo
+                        ))?;
+                        if token.trim().is_empty() {
+                            eprintln!("An access token is needed to push to the mirror.");
+                            std::process::exit(1);
+                        }
+                        redact::register(&token);
+                        Some(token.trim().to_string())
+                    };
+                    let settings = mirror::Mirror {
+                        provider,
+                        repo,
+                        host,
+                        token,
+                        auto: *auto,
+                    };
+                    mirror::save(
+                        &storage,
+                        &master_key,
+                        &settings,
+                        &format!("Mirror to {}", settings.describe()),
+                    )
+                    .await?;
+                    settings
+                }
+                None => match mirror::load(&storage, &master_key).await? {
+                    Some(settings) => settings,
+                    None => {
+                        eprintln!("The vault is not mirrored. Set up a mirror with 'axkeystore mirror --to <repo>'.");
+                        std::process::exit(1);
+                    }
+                },
+            };
+
+            println!(
+                "Pushing {} to {}...",
+                storage.repo_full_name(),
+                settings.describe()
+            );
+            if let Err(e) = mirror::push(&storage, &settings) {
+                eprintln!("{:#}", e);
+                eprintln!("Run 'axkeystore mirror' again to retry.");
+                std::process::exit(1);
+            }
+            println!("Mirrored to {}.", settings.describe());
+            if settings.auto {
+                println!("   Every change to keys is pushed to the mirror as well.");
+            }
+        }
+        Commands::Migrate {
+            to_repo,
+            archive_old,
+        } => {
+            let profile = effective_profile.as_deref();
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(profile, &password).await?;
+            // Only the vault's owner can move it
+            get_or_init_master_key(&storage, &password).await?;
+            let target = storage::Storage::new_with_profile(profile, to_repo, &password).await?;
+            let (source_repo, target_repo) = (storage.repo_full_name(), target.repo_full_name());
+            if target_repo.eq_ignore_ascii_case(&source_repo) {
+                eprintln!("The vault is already in {}.", source_repo);
+                std::process::exit(1);
+            }
+            if target.repo_exists().await?
+                && target
+                    .branch_head(&target.default_branch().await?)
+                    .await?
+                    .is_some()
+            {
+                eprintln!(
+                    "{} already has commits. Move the vault to a new or empty repository.",
+                    target_repo
+                );
+                std::process::exit(1);
+            }
 
-            if entries.is_empty() {
-                println!("No keys found in profile '{}'.", profile_str);
+            let user = match config::selected_vault() {
+                Some(vault) => format!("Vault '{}'", vault),
+                None => format!("Profile '{}'", profile.unwrap_or("default")),
+            };
+            println!(
+                "{} will be copied to {} with its whole history, and {} will use the copy.",
+                source_repo,
+                target_repo,
+                user.to_lowercase()
+            );
+            if *archive_old {
+                println!("{} will then be archived (read-only).", source_repo);
+            }
+            if !prompt_yes_no("Migrate the vault?")? {
+                println!("Migration cancelled.");
                 return Ok(());
             }
 
-            // Group entries by category
-            let mut grouped: BTreeMap<Option<String>, Vec<(String, String)>> = BTreeMap::new();
-
-            for entry in &entries {
-                let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&entry.data)
-                    .context("Failed to parse encrypted blob")?;
-                let decrypted = crypto::CryptoHandler::decrypt(&encrypted, &master_key)?;
-                let value =
-                    String::from_utf8(decrypted).context("Decrypted data is not valid UTF-8")?;
-
-                grouped
-                    .entry(entry.category.clone())
-                    .or_default()
-                    .push((entry.name.clone(), value));
+            init_repo(&target).await?;
+            println!("Copying {} to {}...", source_repo, target_repo);
+            if let Err(e) = mirror::copy_to(&storage, &target_repo) {
+                eprintln!("{:#}", e);
+                eprintln!("{} is still in use.", source_repo);
+                std::process::exit(1);
+            }
+            target
+                .set_default_branch(&storage.default_branch().await?)
+                .await?;
+            // The copy must open with the same master key before the vault switches to it
+            if target.get_master_key_blob().await? != storage.get_master_key_blob().await? {
+                eprintln!(
+                    "The copy in {} does not hold the vault's master key. {} is still in use.",
+                    target_repo, source_repo
+                );
+                std::process::exit(1);
             }
+            let event = audit::AuditEvent::new("migrate", &format!("from {}", source_repo));
+            target
+                .apply_changes(
+                    &[audit::append_change(&target, &event).await?],
+                    &format!("Migrate vault from {}", source_repo),
+                )
+                .await?;
 
-            // ANSI color codes for display
-            const CYAN: &str = "\x1b[36m";
-            const BOLD: &str = "\x1b[1m";
-            const DIM: &str = "\x1b[2m";
-            const RESET: &str = "\x1b[0m";
+            match config::selected_vault() {
+                Some(vault) => config::Config::set_vault_repo_with_profile(
+                    profile,
+                    vault,
+                    &target_repo,
+                    &password,
+                )?,
+                None => {
+                    config::Config::set_repo_name_with_profile(profile, &target_repo, &password)?
+                }
+            }
+            println!("Vault migrated. {} now uses {}.", user, target_repo);
 
+            if *archive_old {
+                match storage.archive_repo().await {
+                    Ok(()) => println!("{} archived.", source_repo),
+                    Err(e) => eprintln!(
+                        "Warning: {:#}. Archive it in its settings on GitHub instead.",
+                        e
+                    ),
+                }
+            }
             println!(
-                "\n{}{}Stored Keys for profile '{}'{}",
-                BOLD, CYAN, profile_str, RESET
+                "   Other machines and profiles still use {}; point them to the new repository ('axkeystore init --repo {}', or 'vault add' for vaults).",
+                source_repo, target_repo
             );
-            println!();
-
-            // Find the max key name length for alignment
-            let max_name_len = grouped
-                .values()
-                .flat_map(|pairs| pairs.iter().map(|(name, _)| name.len()))
-                .max()
-                .unwrap_or(0);
-
-            for (category, pairs) in &grouped {
-                match category {
-                    Some(cat) => println!("{}{}[{}]{}", BOLD, CYAN, cat, RESET),
-                    None => println!("{}{}(uncategorized){}", DIM, CYAN, RESET),
+        }
+        Commands::Dedup { command } => {
+            let password = prompt_password("Enter master password")?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let master_key = get_or_init_master_key(&storage, &password).await?;
+            match command {
+                DedupCommands::Apply { dry_run } => {
+                    let entries = storage.list_all_keys().await?;
+                    let plan = dedup::plan(&master_key, &entries)?;
+                    if plan.groups.is_empty() {
+                        println!("No values are shared by several keys.");
+                    }
+                    for group in &plan.groups {
+                        println!("Shared by {} keys:", group.paths.len());
+                        for path in &group.paths {
+                            println!("  {}", path);
+                        }
+                    }
+                    if *dry_run {
+                        return Ok(());
+                    }
+                    let removed = dedup::apply(&storage, &plan).await?;
+                    if !plan.groups.is_empty() {
+                        println!(
+                            "Stored {} shared value(s) once, with {} key(s) pointing to them.",
+                            plan.groups.len(),
+                            plan.groups.iter().map(|g| g.paths.len()).sum::<usize>()
+                        );
+                    }
+                    if removed > 0 {
+                        println!("Removed {} shared value(s) no key uses anymore.", removed);
+                    }
                 }
-                for (name, value) in pairs {
-                    println!("  {:<width$} = {}", name, value, width = max_name_len);
+                DedupCommands::Where { key, category } => {
+                    let display_path = match category {
+                        Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
+                        None => key.clone(),
+                    };
+                    let others = match dedup::sharing(&storage, key, category.as_deref()).await? {
+                        Some(others) => others,
+                        None => {
+                            // Not deduplicated: compare the value with every other key
+                            let value =
+                                match read_value(&storage, &master_key, key, category.as_deref())
+                                    .await?
+                                {
+                                    Some(value) => value,
+                                    None => {
+                                        eprintln!("Key '{}' not found.", display_path);
+                                        std::process::exit(1);
+                                    }
+                                };
+                            let id = dedup::value_id(&master_key, &value);
+                            decrypt_values(&storage, &master_key, None)
+                                .await?
+                                .into_iter()
+                                .filter(|(path, other)| {
+                                    *path != display_path
+                                        && dedup::value_id(&master_key, other) == id
+                                })
+                                .map(|(path, _)| path)
+                                .collect()
+                        }
+                    };
+                    if others.is_empty() {
+                        println!("No other key holds the value of '{}'.", display_path);
+                    } else {
+                        println!("Keys holding the same value as '{}':", display_path);
+                        for path in &others {
+                            println!("  {}", path);
+                        }
+                    }
                 }
-                println!();
             }
         }
-        Commands::Init { repo } => {
-            let password = prompt_password("Enter master password")?;
-            let storage =
-                storage::Storage::new_with_profile(effective_profile.as_deref(), repo, &password)
-                    .await?;
-            storage.init_repo().await?;
-
-            // Verify if the password matches the remote master key (if it exists)
-            if let Some(blob) = storage.get_master_key_blob().await? {
-                let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&blob)
-                    .context("Failed to parse master key blob from GitHub")?;
-
-                if crypto::CryptoHandler::decrypt(&encrypted, &password).is_err() {
-                    eprintln!("\nError: The provided password is incorrect for this repository.");
-                    eprintln!("   This repository already has a master key encrypted with a different password.");
-                    eprintln!(
-                        "   Please provide the correct password to sync with this repository.\n"
-                    );
-                    std::process::exit(1);
+        Commands::ServiceAccount { command } => {
+            // Consumers read with the bundle alone, without a profile or master password
+            if let ServiceAccountCommands::Get { key, bundle } = command {
+                let password = match std::env::var("AXKEYSTORE_BUNDLE_PASSWORD") {
+                    Ok(password) => password,
+                    Err(_) => rpassword::prompt_password("Enter bundle password: ")
+                        .context("Failed to read password")?,
+                };
+                let bundle = serviceaccount::read_bundle(bundle, &password)?;
+                let snapshot = serviceaccount::fetch(&bundle).await?;
+                match snapshot.value(key)? {
+                    Some(value) => {
+                        let mut stdout = std::io::stdout();
+                        stdout.write_all(&value)?;
+                        stdout.flush()?;
+                    }
+                    None => {
+                        eprintln!(
+                            "Key '{}' not found or not readable by service account '{}'.",
+                            key, bundle.name
+                        );
+                        std::process::exit(1);
+                    }
                 }
-                println!("Master password verified against existing repository.");
+                return Ok(());
             }
 
-            config::Config::set_repo_name_with_profile(
-                effective_profile.as_deref(),
-                repo,
-                &password,
-            )?;
-            println!(
-                "Configuration saved for profile '{}'.",
-                effective_profile.as_deref().unwrap_or("default")
-            );
-        }
-        Commands::Store {
-            key,
-            value,
-            category,
-        } => {
             let password = prompt_password("Enter master password")?;
-            let repo_name = config::Config::get_repo_name_with_profile(
-                effective_profile.as_deref(),
-                &password,
-            )?;
-            let storage = storage::Storage::new_with_profile(
-                effective_profile.as_deref(),
-                &repo_name,
-                &password,
-            )
-            .await?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
             let master_key = get_or_init_master_key(&storage, &password).await?;
+            let accounts = serviceaccount::list(&storage, &master_key).await?;
+            let now = timeutil::now_unix();
+            match command {
+                ServiceAccountCommands::Create {
+                    name,
+                    categories,
+                    ttl,
+                    out,
+                    token_file,
+                } => {
+                    if accounts.iter().any(|a| &a.name == name) {
+                        eprintln!("Service account '{}' already exists.", name);
+                        std::process::exit(1);
+                    }
+                    let ttl_secs = timeutil::parse_duration_secs(ttl)?;
+                    if ttl_secs == 0 {
+                        eprintln!("The TTL must be longer than zero.");
+                        std::process::exit(1);
+                    }
+                    let account = serviceaccount::ServiceAccount::new(
+                        name,
+                        categories.clone(),
+                        now,
+                        ttl_secs,
+                    )?;
+                    let token = match token_file {
+                        Some(path) => std::fs::read_to_string(path)
+                            .with_context(|| format!("Failed to read '{}'", path.display()))?
+                            .trim()
+                            .to_string(),
+                        None => {
+                            println!("Warning: the bundle will carry your own GitHub token. Use --token-file with a fine-grained token that can only read this repository to limit what the bundle can do on GitHub.");
+                            auth::get_saved_token_with_profile(
+                                effective_profile.as_deref(),
+                                &password,
+                            )?
+                        }
+                    };
 
-            let display_path = match &category {
-                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
-                None => key.clone(),
-            };
+                    let values = decrypt_values(&storage, &master_key, None).await?;
+                    let (mut changes, count) =
+                        account.save_changes(&storage, &master_key, &values).await?;
+                    if count == 0 {
+                        println!(
+                            "Warning: no keys match {}. Store them and run 'axkeystore service-account refresh'.",
+                            categories.join(", ")
+                        );
+                    }
 
-            // Check if key already exists
-            if let Ok(Some((_, _))) = storage.get_blob(key, category.as_deref()).await {
-                let should_update = prompt_yes_no(&format!(
-                    "Key '{}' already exists. Do you want to update it?",
-                    display_path
-                ))?;
+                    let bundle_password = loop {
+                        let p1 = prompt_password("Set bundle password")?;
+                        if p1.len() < 8 {
+                            eprintln!("Password must be at least 8 characters long.");
+                            continue;
+                        }
+                        let p2 = prompt_password("Confirm bundle password")?;
+                        if p1 == p2 {
+                            break p1;
+                        }
+                        eprintln!("Passwords do not match. Please try again.");
+                    };
+                    let out = out
+                        .clone()
+                        .unwrap_or_else(|| format!("{}.axbundle", name).into());
 
-                if !should_update {
-                    println!("Update cancelled.");
-                    return Ok(());
+                    changes.push(
+                        audit::append_change(
+                            &storage,
+                            &audit::AuditEvent::new(
+                                "service-account-create",
+                                &format!("{} ({})", name, categories.join(", ")),
+                            ),
+                        )
+                        .await?,
+                    );
+                    storage
+                        .apply_changes(&changes, &format!("Create service account: {}", name))
+                        .await?;
+                    serviceaccount::write_bundle(
+                        &out,
+                        &account.bundle(&storage.repo_full_name(), &token),
+                        &bundle_password,
+                    )?;
+                    println!(
+                        "Created service account '{}' reading {} key(s) until {}.",
+                        name,
+                        count,
+                        timeutil::format_rfc3339(account.expires)
+                    );
+                    println!(
+                        "Wrote its bundle to '{}'. Consumers read keys with 'axkeystore service-account get <category/name> --bundle {}'.",
+                        out.display(),
+                        out.display()
+                    );
                 }
-            }
-
-            // Determine the value to store
-            let final_value = match value {
-                Some(v) => v.clone(),
-                None => {
-                    // Generate a random alphabetic value
-                    let generated = generate_random_alphanumeric();
-                    println!("\nGenerated value: {}", generated);
-                    println!("   (Length: {} characters)\n", generated.len());
-
-                    let confirmed = prompt_yes_no("Do you want to use this generated value?")?;
-
-                    if !confirmed {
-                        println!("Operation cancelled.");
+                ServiceAccountCommands::List => {
+                    if accounts.is_empty() {
+                        println!("No service accounts.");
+                    }
+                    for account in &accounts {
+                        let status = if account.is_expired(now) {
+                            "expired"
+                        } else {
+                            "active"
+                        };
+                        println!(
+                            "  {:<20} {:<8} until {}  {}",
+                            account.name,
+                            status,
+                            timeutil::format_rfc3339(account.expires),
+                            account.categories.join(", ")
+                        );
+                    }
+                }
+                ServiceAccountCommands::Refresh => {
+                    let active: Vec<&serviceaccount::ServiceAccount> =
+                        accounts.iter().filter(|a| !a.is_expired(now)).collect();
+                    if active.is_empty() {
+                        println!("No active service accounts.");
                         return Ok(());
                     }
-                    generated
+                    let values = decrypt_values(&storage, &master_key, None).await?;
+                    let mut changes = Vec::new();
+                    for account in active {
+                        let (account_changes, count) =
+                            account.save_changes(&storage, &master_key, &values).await?;
+                        changes.extend(account_changes);
+                        println!("  {:<20} {} key(s)", account.name, count);
+                    }
+                    storage
+                        .apply_changes(&changes, "Refresh service account keys")
+                        .await?;
+                    println!("Service accounts refreshed.");
                 }
-            };
-
-            let encrypted = crypto::CryptoHandler::encrypt(final_value.as_bytes(), &master_key)?;
-            let json_blob = serde_json::to_vec(&encrypted)?;
-
-            storage
-                .save_blob(key, &json_blob, category.as_deref())
-                .await?;
-
-            println!("Key '{}' stored successfully.", display_path);
+                ServiceAccountCommands::Revoke { name } => {
+                    if !accounts.iter().any(|a| &a.name == name) {
+                        eprintln!("Service account '{}' not found.", name);
+                        std::process::exit(1);
+                    }
+                    let mut changes = serviceaccount::removal_changes(&storage, name).await?;
+                    changes.push(
+                        audit::append_change(
+                            &storage,
+                            &audit::AuditEvent::new("service-account-revoke", name),
+                        )
+                        .await?,
+                    );
+                    storage
+                        .apply_changes(&changes, &format!("Revoke service account: {}", name))
+                        .await?;
+                    println!("Revoked service account '{}'.", name);
+                    println!("Its bundle can no longer read keys. Rotate the values it could read (they remain in the repository history) and revoke the token it carried if it was a dedicated one.");
+                }
+                // Handled above, without the master password
+                ServiceAccountCommands::Get { .. } => {}
+            }
         }
-        Commands::Get {
-            key,
-            category,
-            version,
-        } => {
+        Commands::Notify { command } => {
             let password = prompt_password("Enter master password")?;
-            let repo_name = config::Config::get_repo_name_with_profile(
-                effective_profile.as_deref(),
-                &password,
-            )?;
-            let storage = storage::Storage::new_with_profile(
-                effective_profile.as_deref(),
-                &repo_name,
-                &password,
-            )
-            .await?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
             let master_key = get_or_init_master_key(&storage, &password).await?;
-
-            let display_path = match &category {
-                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
-                None => key.clone(),
-            };
-
-            let data = if let Some(sha) = version {
-                storage
-                    .get_blob_at_version(key, category.as_deref(), sha)
-                    .await?
-            } else {
-                storage
-                    .get_blob(key, category.as_deref())
-                    .await?
-                    .map(|(d, _)| d)
-            };
-
-            if let Some(data) = data {
-                let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&data)?;
-                let decrypted = crypto::CryptoHandler::decrypt(&encrypted, &master_key)?;
-                let value =
-                    String::from_utf8(decrypted).context("Decrypted data is not valid UTF-8")?;
-                println!("{}", value);
+            let mut policy = notify::load(&storage, &master_key).await?;
+            match command {
+                NotifyCommands::Route {
+                    pattern,
+                    webhook,
+                    channel,
+                } => {
+                    notify::validate_pattern(pattern)?;
+                    notify::validate_webhook(webhook)?;
+                    policy.set_route(notify::Route {
+                        pattern: pattern.clone(),
+                        webhook: webhook.clone(),
+                        channel: channel.clone(),
+                    });
+                    notify::save(
+                        &storage,
+                        &master_key,
+                        &policy,
+                        &format!("Route notifications: {}", pattern),
+                    )
+                    .await?;
+                    println!(
+                        "Changes to keys matching '{}' will be sent to {}.",
+                        pattern,
+                        notify::redact_webhook(webhook)
+                    );
+                }
+                NotifyCommands::Unroute { pattern } => {
+                    if !policy.remove_route(pattern) {
+                        eprintln!("No route for '{}'.", pattern);
+                        std::process::exit(1);
+                    }
+                    notify::save(
+                        &storage,
+                        &master_key,
+                        &policy,
+                        &format!("Remove notification route: {}", pattern),
+                    )
+                    .await?;
+                    println!("Route for '{}' removed.", pattern);
+                }
+                NotifyCommands::List => {
+                    if policy.routes.is_empty() {
+                        println!("No notification routes. Add one with 'axkeystore notify route'.");
+                    } else {
+                        println!("\nRoutes (first match wins):");
+                        for route in &policy.routes {
+                            println!(
+                                "  {:<24} {}{}",
+                                route.pattern,
+                                notify::redact_webhook(&route.webhook),
+                                route
+                                    .channel
+                                    .as_ref()
+                                    .map(|c| format!(" ({})", c))
+                                    .unwrap_or_default()
+                            );
+                        }
+                    }
+                }
+                NotifyCommands::Test { path } => {
+                    let path = path.trim_matches('/').to_string();
+                    let pattern = match policy.route_for(&path) {
+                        Some(route) => route.pattern.clone(),
+                        None => {
+                            println!(
+                                "No route matches '{}'; its changes are not announced.",
+                                path
+                            );
+                            return Ok(());
+                        }
+                    };
+                    notify::dispatch(
+                        &policy,
+                        &storage.repo_full_name(),
+                        "tested",
+                        std::slice::from_ref(&path),
+                    )
+                    .await?;
+                    println!("'{}' uses route '{}'. Test message sent.", path, pattern);
+                }
+            }
+        }
+        Commands::Vault { command } => match command {
+            VaultCommands::Add { name, repo } => {
+                if let Err(e) = config::Config::validate_vault_name(name) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                let password = prompt_password("Enter master password")?;
+                let storage = storage::Storage::new_with_profile(
+                    effective_profile.as_deref(),
+                    repo,
+                    &password,
+                )
+                .await?;
+                init_repo(&storage).await?;
+                // An existing vault must open with this password (or the user's identity)
+                if storage.get_master_key_blob().await?.is_some() {
+                    get_or_init_master_key(&storage, &password).await?;
+                }
+                config::Config::set_vault_repo_with_profile(
+                    effective_profile.as_deref(),
+                    name,
+                    &storage.repo_full_name(),
+                    &password,
+                )?;
+                println!(
+                    "Vault '{}' added ({}). Use it with '--vault {}'.",
+                    name,
+                    storage.repo_full_name(),
+                    name
+                );
+            }
+            VaultCommands::List => {
+                let password = prompt_password("Enter master password")?;
+                let vaults = config::Config::get_vault_repos_with_profile(
+                    effective_profile.as_deref(),
+                    &password,
+                )?;
+                if vaults.is_empty() {
+                    println!(
+                        "Profile '{}' has no vaults besides its repository. Add one with 'axkeystore vault add NAME REPO'.",
+                        profile_str
+                    );
+                    return Ok(());
+                }
+                for (name, repo) in &vaults {
+                    println!("{:<20} {}", name, repo);
+                }
+            }
+            VaultCommands::Remove { name } => {
+                if !config::Config::remove_vault_with_profile(effective_profile.as_deref(), name)? {
+                    eprintln!("Profile '{}' has no vault named '{}'.", profile_str, name);
+                    std::process::exit(1);
+                }
+                println!(
+                    "Vault '{}' removed from profile '{}'. Its repository was left untouched.",
+                    name, profile_str
+                );
+            }
+        },
+        Commands::Padding { scheme, off } => {
+            let mut cfg = config::Config::load_with_profile(effective_profile.as_deref())?;
+            if scheme.is_none() && !*off {
+                match cfg.padding {
+                    Some(padding) => println!(
+                        "Profile '{}' pads values with the '{}' scheme.",
+                        profile_str,
+                        padding.name()
+                    ),
+                    None => println!(
+                        "Profile '{}' stores values unpadded. Enable padding with 'axkeystore padding padme' or 'axkeystore padding bucket'.",
+                        profile_str
+                    ),
+                }
+                return Ok(());
+            }
+            cfg.padding = *scheme;
+            cfg.save_with_profile(effective_profile.as_deref())?;
+            match scheme {
+                Some(padding) => {
+                    println!(
+                        "Profile '{}' now pads values with the '{}' scheme.",
+                        profile_str,
+                        padding.name()
+                    );
+                    println!("   Keys stored before keep their size until they are next written ('axkeystore rekey' rewrites them all).");
+                    println!("   Everyone sharing the vault needs a version of axkeystore that reads padded values.");
+                }
+                None => println!("Profile '{}' no longer pads values.", profile_str),
+            }
+        }
+        Commands::LogFile { on, off } => {
+            let mut cfg = config::Config::load_with_profile(effective_profile.as_deref())?;
+            let path = logging::log_file_path(effective_profile.as_deref())?;
+            if !*on && !*off {
+                if cfg.log_file {
+                    println!("Profile '{}' logs to '{}'.", profile_str, path.display());
+                } else {
+                    println!(
+                        "Profile '{}' does not log to a file. Turn it on with 'axkeystore log-file --on'.",
+                        profile_str
+                    );
+                }
+                return Ok(());
+            }
+            cfg.log_file = *on;
+            cfg.save_with_profile(effective_profile.as_deref())?;
+            if *on {
+                println!(
+                    "Profile '{}' now logs every command's GitHub requests to '{}'.",
+                    profile_str,
+                    path.display()
+                );
+                println!("   The log holds key names and repository paths, never tokens or values. Delete it when done.");
             } else {
-                eprintln!("Key '{}' not found.", display_path);
-                std::process::exit(1);
+                println!("Profile '{}' no longer logs to a file.", profile_str);
+            }
+        }
+        Commands::Parallelism { limit, reset } => {
+            let mut cfg = config::Config::load_with_profile(effective_profile.as_deref())?;
+            if limit.is_none() && !*reset {
+                match cfg.parallelism {
+                    Some(limit) => println!(
+                        "Profile '{}' makes up to {} requests at once in bulk reads.",
+                        profile_str, limit
+                    ),
+                    None => println!(
+                        "Profile '{}' makes up to {} requests at once in bulk reads (the default).",
+                        profile_str,
+                        storage::DEFAULT_PARALLELISM
+                    ),
+                }
+                return Ok(());
             }
+            cfg.parallelism = limit.map(|l| l as usize);
+            cfg.save_with_profile(effective_profile.as_deref())?;
+            println!(
+                "Profile '{}' now makes up to {} requests at once in bulk reads.",
+                profile_str,
+                cfg.parallelism.unwrap_or(storage::DEFAULT_PARALLELISM)
+            );
         }
-        Commands::History { key, category } => {
+        Commands::Whoami => {
             let password = prompt_password("Enter master password")?;
-            let repo_name = config::Config::get_repo_name_with_profile(
-                effective_profile.as_deref(),
-                &password,
-            )?;
-            let storage = storage::Storage::new_with_profile(
-                effective_profile.as_deref(),
-                &repo_name,
-                &password,
-            )
-            .await?;
+            let storage = open_storage(effective_profile.as_deref(), &password).await?;
+            let has_master_key = storage.get_master_key_blob().await?.is_some();
 
-            let mut page = 1;
-            loop {
-                let versions = storage
-                    .get_key_history(key, category.as_deref(), page, 10)
-                    .await?;
-                if versions.is_empty() {
-                    if page == 1 {
-                        println!("No history found for key '{}'.", key);
-                    } else {
-                        println!("No more versions found.");
-                    }
-                    break;
-                }
-
-                println!("\nVersion History for '{}':", key);
-                println!("{:<40} | {:<25} | {}", "SHA", "Date", "Message");
-                println!("{:-<40}-+-{:-<25}-+-{:-<20}", "", "", "");
-
-                for v in &versions {
-                    println!("{:<40} | {:<25} | {}", v.sha, v.date, v.message);
+            println!(
+                "Profile:     {}",
+                effective_profile.as_deref().unwrap_or("default")
+            );
+            println!("GitHub user: {}", storage.login());
+            if let Some(vault) = config::selected_vault() {
+                println!("Vault:       {}", vault);
+            }
+            match storage.prefix() {
+                Some(prefix) => println!(
+                    "Repository:  {} (vault in '{}')",
+                    storage.repo_full_name(),
+                    prefix
+                ),
+                None => println!("Repository:  {}", storage.repo_full_name()),
+            }
+            println!(
+                "Branch:      {}",
+                storage
+                    .branch()
+                    .unwrap_or("default branch of the repository")
+            );
+            println!("API host:    {}", storage.api_base());
+            println!(
+                "Master key:  {}",
+                if has_master_key {
+                    "present in the repository"
+                } else {
+                    "not created yet (created by the first 'store')"
                 }
-
-                if versions.len() < 10 {
-                    break;
+            );
+        }
+        Commands::Doctor => {
+            let password = prompt_password("Enter master password")?;
+            println!(
+                "Checking profile '{}'...\n",
+                effective_profile.as_deref().unwrap_or("default")
+            );
+            let checks = doctor::run(effective_profile.as_deref(), &password).await;
+            for check in &checks {
+                println!(
+                    "  [{}] {}: {}",
+                    check.status.label(),
+                    check.name,
+                    check.detail
+                );
+                if let Some(fix) = &check.fix {
+                    println!("         Fix: {}", fix);
                 }
+            }
 
-                if !prompt_yes_no("\nShow more versions?")? {
-                    break;
-                }
-                page += 1;
+            let failed = checks
+                .iter()
+                .filter(|c| c.status == doctor::Status::Fail)
+                .count();
+            let warned = checks
+                .iter()
+                .filter(|c| c.status == doctor::Status::Warn)
+                .count();
+            if failed > 0 {
+                eprintln!("\n{} check(s) failed.", failed);
+                std::process::exit(1);
+            } else if warned > 0 {
+                println!("\nEverything works, with {} warning(s).", warned);
+            } else {
+                println!("\nEverything works.");
             }
         }
-        Commands::Delete { key, category } => {
+        #[cfg(feature = "e2e")]
+        Commands::E2e { repo, keep } => {
+            if config::selected_vault().is_some() {
+                eprintln!("'e2e' uses its own repository and cannot be combined with --vault.");
+                std::process::exit(1);
+            }
+            let repo = repo.clone().unwrap_or_else(e2e::random_repo_name);
+            if let Err(e) = e2e::validate_repo_name(&repo) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
             let password = prompt_password("Enter master password")?;
-            let repo_name = config::Config::get_repo_name_with_profile(
-                effective_profile.as_deref(),
-                &password,
-            )?;
-            let storage = storage::Storage::new_with_profile(
-                effective_profile.as_deref(),
-                &repo_name,
-                &password,
-            )
-            .await?;
-            let _master_key = get_or_init_master_key(&storage, &password).await?;
-
-            let display_path = match &category {
-                Some(cat) => format!("{}/{}", cat.trim_matches('/'), key),
-                None => key.clone(),
-            };
-
-            // Check if key exists first
-            if storage.get_blob(key, category.as_deref()).await?.is_none() {
-                eprintln!("Key '{}' not found.", display_path);
+            let mut storage =
+                storage::Storage::new_with_profile(effective_profile.as_deref(), &repo, &password)
+                    .await?;
+            // The throwaway repository holds nothing but the test vault
+            storage.set_prefix(None);
+            storage.set_branch(None);
+            if storage.repo_exists().await? {
+                eprintln!(
+                    "Repository {} already exists. The end-to-end test only runs against a repository it creates.",
+                    storage.repo_full_name()
+                );
                 std::process::exit(1);
             }
+            init_repo(&storage).await?;
 
-            // Confirm deletion
-            let should_delete = prompt_yes_no(&format!(
-                "Are you sure you want to delete key '{}'?",
-                display_path
-            ))?;
+            println!(
+                "\nRunning the end-to-end test in {}...",
+                storage.repo_full_name()
+            );
+            let result = e2e::run(&storage, &password).await;
 
-            if !should_delete {
-                println!("Deletion cancelled.");
-                return Ok(());
+            if *keep {
+                println!("Repository {} kept.", storage.repo_full_name());
+            } else if let Err(e) = storage.delete_repo().await {
+                eprintln!("Warning: {}", e);
+                eprintln!(
+                    "   Delete it by hand, or grant the token the 'delete_repo' scope for the next run."
+                );
+            } else {
+                println!("Repository {} deleted.", storage.repo_full_name());
             }
 
-            if storage.delete_blob(key, category.as_deref()).await? {
-                println!("Key '{}' deleted successfully.", display_path);
-            } else {
-                eprintln!("Failed to delete key '{}'.", display_path);
+            if let Err(e) = result {
+                eprintln!("\nEnd-to-end test failed: {:#}", e);
                 std::process::exit(1);
             }
+            println!("\nEnd-to-end test passed.");
         }
-        Commands::Profile { command } => match command {
-            ProfileCommands::List => {
-                let profiles = config::GlobalConfig::list_profiles()?;
-                let active = config::GlobalConfig::get_active_profile()?;
-                println!("\nProfiles:");
-                if profiles.is_empty() && active.is_none() {
-                    println!("  * default");
-                } else {
-                    // Always show default in the list
-                    let indicator = if active.is_none() { "*" } else { " " };
-                    println!(" {} default", indicator);
-
-                    for p in profiles {
-                        let indicator = if Some(&p) == active.as_ref() {
-                            "*"
-                        } else {
-                            " "
-                        };
-                        println!(" {} {}", indicator, p);
+        Commands::Device { command } => {
+            let profile = effective_profile.as_deref();
+            let profile_name = profile.unwrap_or("default");
+            let bind = match command {
+                DeviceCommands::Status => {
+                    if config::Config::load_with_profile(profile)?.bind_token_to_device {
+                        println!(
+                            "The GitHub token of profile '{}' is bound to the machine it was saved on.",
+                            profile_name
+                        );
+                    } else {
+                        println!(
+                            "The GitHub token of profile '{}' is not bound to a machine. Bind it with 'axkeystore device bind'.",
+                            profile_name
+                        );
                     }
+                    return Ok(());
                 }
-                println!("\n* Active profile");
-            }
-            ProfileCommands::Switch { name } => {
-                config::GlobalConfig::set_active_profile(name.clone())?;
-                match name {
-                    Some(n) => println!("Switched to profile '{}'.", n),
-                    None => println!("Switched to default root profile."),
+                DeviceCommands::Bind => true,
+                DeviceCommands::Unbind => false,
+            };
+            if bind {
+                if let Err(e) = device::fingerprint() {
+                    eprintln!("Cannot bind the token to this machine: {}", e);
+                    std::process::exit(1);
                 }
             }
-            ProfileCommands::Delete { name } => {
-                if prompt_yes_no(&format!(
-                    "Are you sure you want to delete profile '{}'?",
-                    name
-                ))? {
-                    config::GlobalConfig::delete_profile(name)?;
-                    println!("Profile '{}' deleted.", name);
-                }
+
+            let password = prompt_password("Enter master password")?;
+            if let Err(e) = auth::set_device_binding_with_profile(profile, &password, bind) {
+                eprintln!("{}", e);
+                std::process::exit(1);
             }
-            ProfileCommands::Current => {
-                let active = config::GlobalConfig::get_active_profile()?;
+            if bind {
                 println!(
-                    "Current active profile: {}",
-                    active.unwrap_or_else(|| "default".to_string())
+                    "The GitHub token of profile '{}' is now bound to this machine.",
+                    profile_name
+                );
+                println!("If the machine's identifier changes (e.g. after reinstalling the OS), run 'axkeystore login' again.");
+            } else {
+                println!(
+                    "The GitHub token of profile '{}' is no longer bound to this machine.",
+                    profile_name
                 );
             }
-            ProfileCommands::Create { name } => {
-                config::Config::get_config_dir(Some(&name))?;
-                println!("Profile '{}' created.", name);
-            }
-        },
-        Commands::ResetPassword => {
-            let old_password = prompt_password("Enter current master password")?;
-
-            // 1. Verify old password and retrieve LMK
-            let lmk = match config::Config::get_or_create_lmk_with_profile(
-                effective_profile.as_deref(),
-                &old_password,
-            ) {
-                Ok(k) => k,
-                Err(_) => {
-                    eprintln!("Incorrect old master password.");
-                    std::process::exit(1);
+        }
+        Commands::Signing { command } => {
+            let profile = effective_profile.as_deref();
+            let mut cfg = config::Config::load_with_profile(profile)?;
+            match command {
+                SigningCommands::Enable {
+                    format,
+                    key,
+                    name,
+                    email,
+                } => {
+                    cfg.signing.format = Some(*format);
+                    cfg.signing.key = Some(key.clone());
+                    cfg.signing.name = name.clone();
+                    cfg.signing.email = Some(email.clone());
+                    cfg.save_with_profile(profile)?;
+                    println!(
+                        "Commits created by AxKeyStore will now be signed with {} key '{}'.",
+                        format.label(),
+                        key
+                    );
                 }
-            };
-
-            // 2. Try to retrieve RMK if storage is configured
-            let mut rmk_data: Option<(String, storage::Storage)> = None;
-            if let Ok(repo_name) = config::Config::get_repo_name_with_profile(
-                effective_profile.as_deref(),
-                &old_password,
-            ) {
-                if let Ok(storage) = storage::Storage::new_with_profile(
-                    effective_profile.as_deref(),
-                    &repo_name,
-                    &old_password,
-                )
-                .await
-                {
-                    if let Ok(Some(data)) = storage.get_master_key_blob().await {
-                        let encrypted: crypto::EncryptedBlob = serde_json::from_slice(&data)?;
-                        if let Ok(decrypted) =
-                            crypto::CryptoHandler::decrypt(&encrypted, &old_password)
-                        {
-                            let rmk = String::from_utf8(decrypted)?;
-                            rmk_data = Some((rmk, storage));
-                        }
+                SigningCommands::Disable => {
+                    cfg.signing.format = None;
+                    cfg.signing.key = None;
+                    cfg.save_with_profile(profile)?;
+                    println!("Commit signing disabled.");
+                }
+                SigningCommands::Trust { email } => {
+                    if !cfg
+                        .signing
+                        .trusted_signers
+                        .iter()
+                        .any(|t| t.eq_ignore_ascii_case(email))
+                    {
+                        cfg.signing.trusted_signers.push(email.clone());
+                        cfg.save_with_profile(profile)?;
                     }
+                    println!("Signatures by '{}' are trusted.", email);
                 }
-            }
-
-            // 3. Prompt for new password
-            println!("\nEnter your new master password:");
-            let new_password = loop {
-                let p1 = prompt_password("New master password")?;
-                if p1.len() < 8 {
-                    eprintln!("Password must be at least 8 characters long.");
-                    continue;
+                SigningCommands::Untrust { email } => {
+                    let before = cfg.signing.trusted_signers.len();
+                    cfg.signing
+                        .trusted_signers
+                        .retain(|t| !t.eq_ignore_ascii_case(email));
+                    if cfg.signing.trusted_signers.len() == before {
+                        eprintln!("'{}' is not a trusted signer.", email);
+                        std::process::exit(1);
+                    }
+                    cfg.save_with_profile(profile)?;
+                    println!("Signatures by '{}' are no longer trusted.", email);
                 }
-                let p2 = prompt_password("Confirm new master password")?;
-                if p1 == p2 {
-                    if p1 == old_password {
-                        eprintln!("New password must be different from the old one.");
-                        continue;
+                SigningCommands::Verify { enabled } => {
+                    cfg.signing.verify_on_get = *enabled;
+                    cfg.save_with_profile(profile)?;
+                    println!(
+                        "Signature verification on 'get' {}.",
+                        if *enabled { "enabled" } else { "disabled" }
+                    );
+                }
+                SigningCommands::Status => {
+                    let s = &cfg.signing;
+                    println!("\nSigning settings for profile '{}':", profile_str);
+                    match (s.format, &s.key) {
+                        (Some(format), Some(key)) => println!(
+                            "  Signing:   {} key '{}' as {} <{}>",
+                            format.label(),
+                            key,
+                            s.name.as_deref().unwrap_or("(repository owner)"),
+                            s.email.as_deref().unwrap_or("")
+                        ),
+                        _ => println!("  Signing:   disabled"),
+                    }
+                    println!(
+                        "  Verify on get: {}",
+                        if s.verify_on_get { "on" } else { "off" }
+                    );
+                    if s.trusted_signers.is_empty() {
+                        println!("  Trusted signers: any verified signature");
+                    } else {
+                        println!("  Trusted signers: {}", s.trusted_signers.join(", "));
                     }
-                    break p1;
                 }
-                eprintln!("Passwords do not match. Please try again.");
-            };
-
-            // 4. Update RMK remotely if it exists
-            if let Some((rmk, storage)) = rmk_data {
-                let encrypted_rmk = crypto::CryptoHandler::encrypt(rmk.as_bytes(), &new_password)?;
-                let json_blob = serde_json::to_vec(&encrypted_rmk)?;
-                if let Err(e) = storage.save_master_key_blob(&json_blob).await {
-                    eprintln!("Failed to update remote master key on GitHub: {}", e);
-                    eprintln!("   Password reset aborted. Your current password is still active.");
-                    std::process::exit(1);
+            }
+        }
+        Commands::Cache { command } => match command {
+            CacheCommands::Status => {
+                let statuses = cache::status(effective_profile.as_deref())?;
+                println!("\nCache policy for profile '{}':", profile_str);
+                println!(
+                    "{:<14} | {:<10} | {:<8} | {}",
+                    "Kind", "Max age", "Entries", "Oldest"
+                );
+                println!("{:-<14}-+-{:-<10}-+-{:-<8}-+-{:-<10}", "", "", "", "");
+                for s in statuses {
+                    let max_age = if s.ttl == 0 {
+                        "disabled".to_string()
+                    } else {
+                        timeutil::format_duration_secs(s.ttl)
+                    };
+                    let oldest = s
+                        .oldest_age
+                        .map(timeutil::format_duration_secs)
+                        .unwrap_or_else(|| "-".to_string());
+                    println!(
+                        "{:<14} | {:<10} | {:<8} | {}",
+                        s.kind.dir_name(),
+                        max_age,
+                        s.entries,
+                        oldest
+                    );
+                }
+                println!("{:<14} | {:<10} | {:<8} | -", "plaintext", "never", 0);
+            }
+            CacheCommands::Clear { kind } => {
+                let removed = cache::clear(effective_profile.as_deref(), *kind)?;
+                println!("Removed {} cached entries.", removed);
+            }
+            CacheCommands::Set { kind, ttl } => {
+                let secs = timeutil::parse_duration_secs(ttl)?;
+                cache::set_ttl(effective_profile.as_deref(), *kind, secs)?;
+                if secs == 0 {
+                    println!("Caching of {} disabled.", kind.dir_name());
+                } else {
+                    println!(
+                        "{} may now be cached for up to {}.",
+                        kind.dir_name(),
+                        timeutil::format_duration_secs(secs)
+                    );
                 }
-                println!("Remote master key updated on GitHub.");
             }
+        },
+    }
 
-            // 5. Update LMK locally
-            let encrypted_lmk = crypto::CryptoHandler::encrypt(lmk.as_bytes(), &new_password)?;
-            let mut cfg = config::Config::load_with_profile(effective_profile.as_deref())?;
-            cfg.encrypted_lmk = Some(encrypted_lmk);
-            cfg.save_with_profile(effective_profile.as_deref())?;
+    Ok(())
+}
 
-            println!(
-                "Master password successfully reset for profile '{}'.",
-                profile_str
-            );
-        }
+/// Formats a byte count for display (e.g. '512 B', '3.4 KiB')
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
     }
+    format!("{:.1} {}", value, UNITS[unit])
+}
 
-    Ok(())
+/// Describes how the stored size changed from the previous version ('new' for the first
+/// version or a re-created key, 'deleted' when the key was removed)
+fn size_change(previous: Option<u64>, current: Option<u64>) -> String {
+    match (previous, current) {
+        (_, None) => "deleted".to_string(),
+        (None, Some(_)) => "new".to_string(),
+        (Some(prev), Some(cur)) if cur >= prev => format!("+{}", format_size(cur - prev)),
+        (Some(prev), Some(cur)) => format!("-{}", format_size(prev - cur)),
+    }
+}
+
+/// A version is flagged when its stored size more than quadruples and grows by over 4 KiB,
+/// which usually means a whole file was stored where a short secret belongs
+fn is_unusual_growth(previous: Option<u64>, current: Option<u64>) -> bool {
+    match (previous, current) {
+        (Some(prev), Some(cur)) => cur > prev.saturating_mul(4) && cur - prev > 4096,
+        _ => false,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_cli() {
+        // Catches clashing short flags and positional indexes, which clap only reports when
+        // the affected subcommand is parsed
+        Cli::command().debug_assert();
+    }
+
     #[test]
     fn test_generate_random_alphanumeric() {
         for _ in 0..100 {
@@ -798,4 +9275,20 @@ mod tests {
             assert!(s.chars().all(|c| c.is_alphanumeric()));
         }
     }
+
+    #[test]
+    fn test_size_change() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3482), "3.4 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+
+        assert_eq!(size_change(None, Some(180)), "new");
+        assert_eq!(size_change(Some(180), Some(200)), "+20 B");
+        assert_eq!(size_change(Some(200), Some(180)), "-20 B");
+        assert_eq!(size_change(Some(180), None), "deleted");
+
+        assert!(is_unusual_growth(Some(180), Some(48_000)));
+        assert!(!is_unusual_growth(Some(180), Some(900)));
+        assert!(!is_unusual_growth(None, Some(48_000)));
+    }
 }
@@ -0,0 +1,650 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::{Storage, TreeChange};
+use crate::{timeutil, vault};
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Repository directory of the encrypted per-key metadata documents
+/// ('.axkeystore/meta/<category>/<key>.json', mirroring 'keys/')
+pub const META_DIR: &str = ".axkeystore/meta";
+
+/// Repository directory of encrypted attachments, stored under opaque ids so file names
+/// are not revealed and keys can be moved without touching them
+pub const ATTACHMENTS_DIR: &str = ".axkeystore/attachments";
+
+/// A file attached to a key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Attachment {
+    /// Opaque id of the encrypted blob in ATTACHMENTS_DIR
+    pub id: String,
+    /// Size of the attached file in bytes
+    pub size: usize,
+    /// When the file was attached (RFC 3339)
+    pub added: String,
+}
+
+/// A claim on a key name made before its value exists
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Reservation {
+    /// GitHub login of the teammate who reserved the key
+    pub owner: String,
+    /// When the key was reserved (RFC 3339)
+    pub since: String,
+}
+
+/// Everything AxKeyStore knows about a key besides its value
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct KeyMeta {
+    /// Free-form description of what the key is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Tags, sorted and without duplicates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Person or team responsible for the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Related URL (e.g. the provider's console)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Attached files, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attachments: BTreeMap<String, Attachment>,
+    /// When the value expires (Unix seconds)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    /// Set while the key is only a placeholder, cleared when a value is stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserved: Option<Reservation>,
+    /// When 'reap' deletes the key (Unix seconds), for temporary credentials
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_after: Option<u64>,
+    /// Version labels (e.g. 'v2024-10'), with the commit SHA each one points to
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl KeyMeta {
+    /// Returns true if there is nothing worth storing
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none()
+            && self.tags.is_empty()
+            && self.owner.is_none()
+            && self.url.is_none()
+            && self.attachments.is_empty()
+            && self.expires.is_none()
+            && self.reserved.is_none()
+            && self.delete_after.is_none()
+            && self.labels.is_empty()
+    }
+
+    /// Resolves a version given as a label or as a commit SHA to a commit SHA
+    pub fn resolve_version<'a>(&'a self, version: &'a str) -> &'a str {
+        self.labels.get(version).map_or(version, |sha| sha.as_str())
+    }
+}
+
+/// Validates a version label. Labels that could be mistaken for a commit SHA are rejected, so
+/// '--version' always means the same thing.
+pub fn validate_label(label: &str) -> Result<()> {
+    if label.is_empty()
+        || !label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid label '{}'. Use alphabets, numbers, '.', '-' and '_'",
+            label
+        ));
+    }
+    if label.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow::anyhow!(
+            "Label '{}' looks like a commit SHA. Use a label with other characters (e.g. 'v{}')",
+            label,
+            label
+        ));
+    }
+    Ok(())
+}
+
+/// Metadata changes requested on the command line. Fields left as None are kept; an empty
+/// string (or an empty tag list) clears the field.
+#[derive(Default)]
+pub struct MetaUpdate {
+    pub description: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub owner: Option<String>,
+    pub url: Option<String>,
+    pub expires: Option<u64>,
+    pub delete_after: Option<u64>,
+}
+
+impl MetaUpdate {
+    /// Returns true if nothing would change
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none()
+            && self.tags.is_none()
+            && self.owner.is_none()
+            && self.url.is_none()
+            && self.expires.is_none()
+            && self.delete_after.is_none()
+    }
+
+    /// Applies the changes to a key's metadata
+    pub fn apply(&self, meta: &mut KeyMeta) {
+        let text = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        if let Some(description) = &self.description {
+            meta.description = text(description);
+        }
+        if let Some(owner) = &self.owner {
+            meta.owner = text(owner);
+        }
+        if let Some(url) = &self.url {
+            meta.url = text(url);
+        }
+        if let Some(tags) = &self.tags {
+            let tags: std::collections::BTreeSet<String> =
+                tags.iter().filter_map(|t| text(t)).collect();
+            meta.tags = tags.into_iter().collect();
+        }
+        if let Some(expires) = self.expires {
+            meta.expires = Some(expires);
+        }
+        if let Some(delete_after) = self.delete_after {
+            meta.delete_after = Some(delete_after);
+        }
+    }
+}
+
+/// Metadata changes applied to many keys at once by 'meta edit'
+#[derive(Default)]
+pub struct BulkEdit {
+    /// Tags added to every key
+    pub add_tags: Vec<String>,
+    /// Tags removed from every key
+    pub remove_tags: Vec<String>,
+    /// Fields set on every key ('' clears the field)
+    pub fields: MetaUpdate,
+}
+
+impl BulkEdit {
+    /// Returns true if nothing would change
+    pub fn is_empty(&self) -> bool {
+        self.add_tags.is_empty() && self.remove_tags.is_empty() && self.fields.is_empty()
+    }
+
+    /// Records a 'field=value' assignment (description, owner or url)
+    pub fn set(&mut self, assignment: &str) -> Result<()> {
+        let (field, value) = assignment.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!("Invalid assignment '{}'. Use 'field=value'", assignment)
+        })?;
+        let slot = match field.trim() {
+            "description" => &mut self.fields.description,
+            "owner" => &mut self.fields.owner,
+            "url" => &mut self.fields.url,
+            other => {
+                return Err(anyhow::anyhow!(
+                    "Unknown field '{}'. Fields that can be set: description, owner, url",
+                    other
+                ))
+            }
+        };
+        *slot = Some(value.to_string());
+        Ok(())
+    }
+
+    /// Applies the changes to a key's metadata, returning true if anything changed
+    pub fn apply(&self, meta: &mut KeyMeta) -> bool {
+        let before = meta.clone();
+        self.fields.apply(meta);
+        let mut tags: std::collections::BTreeSet<String> = meta.tags.drain(..).collect();
+        tags.extend(
+            self.add_tags
+                .iter()
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty()),
+        );
+        for tag in &self.remove_tags {
+            tags.remove(tag.trim());
+        }
+        meta.tags = tags.into_iter().collect();
+        *meta != before
+    }
+}
+
+/// Returns the repository path of a key's metadata document
+pub fn meta_path(key: &str, category: Option<&str>) -> Result<String> {
+    let path = Storage::build_key_path(key, category)?;
+    Ok(format!("{}/{}", META_DIR, path.trim_start_matches("keys/")))
+}
+
+/// Returns the display path of the key a metadata document belongs to
+pub fn key_display_path(meta_path: &str) -> Option<&str> {
+    meta_path
+        .strip_prefix(META_DIR)?
+        .strip_prefix('/')?
+        .strip_suffix(".json")
+}
+
+/// Returns the repository path of an attachment blob
+pub fn attachment_path(id: &str) -> String {
+    format!("{}/{}.json", ATTACHMENTS_DIR, id)
+}
+
+/// Generates a new random attachment id
+pub fn new_attachment_id() -> String {
+    let bytes: [u8; 16] = rand::rng().random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decrypts a document encrypted with the master key
+fn decrypt_document(data: &[u8], master_key: &str, what: &str) -> Result<Vec<u8>> {
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(data).with_context(|| format!("Failed to parse {}", what))?;
+    CryptoHandler::decrypt(&encrypted, master_key)
+        .with_context(|| format!("Failed to decrypt {}", what))
+}
+
+/// Loads a key's metadata (empty if it has none)
+pub async fn load(
+    storage: &Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+) -> Result<KeyMeta> {
+    let path = meta_path(key, category)?;
+    match storage.read_file(&path).await? {
+        Some(data) => {
+            let decrypted = decrypt_document(&data, master_key, "key metadata")?;
+            serde_json::from_slice(&decrypted).context("Key metadata is corrupted")
+        }
+        None => Ok(KeyMeta::default()),
+    }
+}
+
+/// Loads the metadata of every key that has any, by key display path
+pub async fn load_all(storage: &Storage, master_key: &str) -> Result<Vec<(String, KeyMeta)>> {
+    let mut all = Vec::new();
+    for path in storage.list_files_recursive(META_DIR).await? {
+        let display_path = match key_display_path(&path) {
+            Some(p) => p,
+            None => continue,
+        };
+        let data = match storage.read_file(&path).await? {
+            Some(d) => d,
+            None => continue,
+        };
+        let decrypted = decrypt_document(&data, master_key, "key metadata")?;
+        let meta = serde_json::from_slice(&decrypted)
+            .with_context(|| format!("Metadata of '{}' is corrupted", display_path))?;
+        all.push((display_path.to_string(), meta));
+    }
+    Ok(all)
+}
+
+/// Returns the keys that expire before the deadline, soonest first
+pub fn expiring(all: &[(String, KeyMeta)], deadline: u64) -> Vec<(&str, u64)> {
+    let mut found: Vec<(&str, u64)> = all
+        .iter()
+        .filter_map(|(path, meta)| Some((path.as_str(), meta.expires?)))
+        .filter(|(_, expires)| *expires <= deadline)
+        .collect();
+    found.sort_by_key(|(path, expires)| (*expires, *path));
+    found
+}
+
+/// Returns the keys whose deletion deadline has passed, earliest deadline first
+pub fn overdue(all: &[(String, KeyMeta)], now: u64) -> Vec<(&str, u64)> {
+    let mut found: Vec<(&str, u64)> = all
+        .iter()
+        .filter_map(|(path, meta)| Some((path.as_str(), meta.delete_after?)))
+        .filter(|(_, deadline)| *deadline <= now)
+        .collect();
+    found.sort_by_key(|(path, deadline)| (*deadline, *path));
+    found
+}
+
+/// Describes a deletion deadline relative to now (e.g. '2025-06-30T00:00:00Z (in 12d)')
+pub fn describe_deletion(deadline: u64, now: u64) -> String {
+    let date = timeutil::format_rfc3339(deadline);
+    if deadline > now {
+        format!(
+            "{} (in {})",
+            date,
+            timeutil::format_duration_secs(deadline - now)
+        )
+    } else {
+        format!(
+            "{} (overdue by {}; deleted by the next 'reap')",
+            date,
+            timeutil::format_duration_secs(now - deadline)
+        )
+    }
+}
+
+/// Describes an expiry relative to now (e.g. '2025-06-30T00:00:00Z (in 12d)')
+pub fn describe_expiry(expires: u64, now: u64) -> String {
+    let date = timeutil::format_rfc3339(expires);
+    if expires > now {
+        format!(
+            "{} (in {})",
+            date,
+            timeutil::format_duration_secs(expires - now)
+        )
+    } else {
+        format!(
+            "{} (expired {} ago)",
+            date,
+            timeutil::format_duration_secs(now - expires)
+        )
+    }
+}
+
+/// Builds the changes that apply a metadata update to a key
+pub async fn update_changes(
+    storage: &Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+    update: &MetaUpdate,
+) -> Result<Vec<TreeChange>> {
+    let mut meta = load(storage, master_key, key, category).await?;
+    update.apply(&mut meta);
+    save_changes(storage, master_key, key, category, &meta).await
+}
+
+/// Builds the changes that save a key's metadata, removing the document once it is empty
+pub async fn save_changes(
+    storage: &Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+    meta: &KeyMeta,
+) -> Result<Vec<TreeChange>> {
+    let path = meta_path(key, category)?;
+    if meta.is_empty() {
+        return storage.path_changes(&path, None).await;
+    }
+    let encrypted = vault::encrypt_value(master_key, category, &serde_json::to_vec(meta)?)?;
+    storage
+        .path_changes(&path, Some(&serde_json::to_vec(&encrypted)?))
+        .await
+}
+
+/// Builds the changes that remove a key's metadata and every file attached to it
+pub async fn removal_changes(
+    storage: &Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+) -> Result<Vec<TreeChange>> {
+    let meta = load(storage, master_key, key, category).await?;
+    let mut changes = Vec::new();
+    for attachment in meta.attachments.values() {
+        changes.extend(
+            storage
+                .path_changes(&attachment_path(&attachment.id), None)
+                .await?,
+        );
+    }
+    changes.extend(
+        storage
+            .path_changes(&meta_path(key, category)?, None)
+            .await?,
+    );
+    Ok(changes)
+}
+
+/// Builds the changes that move a key's metadata to a new key path, re-encrypting it and the
+/// key's attachments when the top-level category changes. When the target key is being
+/// overwritten, its own metadata and attachments are removed first.
+pub async fn move_changes(
+    storage: &Storage,
+    master_key: &str,
+    from: (&str, Option<&str>),
+    to: (&str, Option<&str>),
+    overwrite: bool,
+) -> Result<Vec<TreeChange>> {
+    let from_path = meta_path(from.0, from.1)?;
+    let to_path = meta_path(to.0, to.1)?;
+    let mut changes = Vec::new();
+
+    let source = storage.read_file(&from_path).await?;
+    if overwrite {
+        let target = load(storage, master_key, to.0, to.1).await?;
+        for attachment in target.attachments.values() {
+            changes.extend(
+                storage
+                    .path_changes(&attachment_path(&attachment.id), None)
+                    .await?,
+            );
+        }
+        if source.is_none() {
+            changes.extend(storage.path_changes(&to_path, None).await?);
+        }
+    }
+
+    // Metadata and attachments use the data key of the key's top-level category, so moving
+    // to another one re-encrypts them; attachments keep their paths
+    if let Some(data) = source {
+        if vault::top_category(from.1) != vault::top_category(to.1) {
+            let meta: KeyMeta =
+                serde_json::from_slice(&decrypt_document(&data, master_key, "key metadata")?)
+                    .context("Key metadata is corrupted")?;
+            for attachment in meta.attachments.values() {
+                let path = attachment_path(&attachment.id);
+                if let Some(file) = storage.read_file(&path).await? {
+                    let file = vault::reencrypt_for(master_key, to.1, &file)?;
+                    changes.extend(storage.path_changes(&path, Some(&file)).await?);
+                }
+            }
+        }
+        let data = vault::reencrypt_for(master_key, to.1, &data)?;
+        changes.extend(storage.path_changes(&to_path, Some(&data)).await?);
+        changes.extend(storage.path_changes(&from_path, None).await?);
+    }
+    Ok(changes)
+}
+
+/// Reads and decrypts an attachment
+pub async fn read_attachment(
+    storage: &Storage,
+    master_key: &str,
+    attachment: &Attachment,
+) -> Result<Vec<u8>> {
+    let data = storage
+        .read_file(&attachment_path(&attachment.id))
+        .await?
+        .context("Attachment blob is missing from the repository")?;
+    decrypt_document(&data, master_key, "attachment")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(
+            meta_path("db-prod", Some("/cloud/aws/")).unwrap(),
+            ".axkeystore/meta/cloud/aws/db-prod.json"
+        );
+        assert_eq!(meta_path("api", None).unwrap(), ".axkeystore/meta/api.json");
+        assert!(meta_path("bad/key", None).is_err());
+        assert_eq!(
+            key_display_path(".axkeystore/meta/cloud/aws/db-prod.json"),
+            Some("cloud/aws/db-prod")
+        );
+        assert_eq!(key_display_path("keys/api.json"), None);
+
+        let id = new_attachment_id();
+        assert_eq!(id.len(), 32);
+        assert_ne!(id, new_attachment_id());
+        assert_eq!(
+            attachment_path(&id),
+            format!(".axkeystore/attachments/{}.json", id)
+        );
+    }
+
+    #[test]
+    fn test_empty_meta_serialization() {
+        let meta = KeyMeta::default();
+        assert!(meta.is_empty());
+        assert_eq!(serde_json::to_string(&meta).unwrap(), "{}");
+        let parsed: KeyMeta = serde_json::from_str("{}").unwrap();
+        assert_eq!(parsed, meta);
+
+        let reserved = KeyMeta {
+            reserved: Some(Reservation {
+                owner: "octocat".to_string(),
+                since: "2024-01-01T00:00:00Z".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert!(!reserved.is_empty());
+        let json = serde_json::to_string(&reserved).unwrap();
+        assert_eq!(
+            json,
+            r#"{"reserved":{"owner":"octocat","since":"2024-01-01T00:00:00Z"}}"#
+        );
+        assert_eq!(serde_json::from_str::<KeyMeta>(&json).unwrap(), reserved);
+    }
+
+    #[test]
+    fn test_labels() {
+        assert!(validate_label("v2024-10").is_ok());
+        assert!(validate_label("release_1.2").is_ok());
+        assert!(validate_label("").is_err());
+        assert!(validate_label("my label").is_err());
+        assert!(validate_label("v1/2").is_err());
+        assert!(validate_label("deadbeef").is_err());
+        assert!(validate_label("2024").is_err());
+
+        let mut meta = KeyMeta::default();
+        meta.labels
+            .insert("v2024-10".to_string(), "abc123def".to_string());
+        assert!(!meta.is_empty());
+        assert_eq!(meta.resolve_version("v2024-10"), "abc123def");
+        assert_eq!(meta.resolve_version("abc123"), "abc123");
+    }
+
+    #[test]
+    fn test_meta_update() {
+        let mut meta = KeyMeta {
+            description: Some("old".to_string()),
+            owner: Some("payments".to_string()),
+            expires: Some(100),
+            ..Default::default()
+        };
+        let update = MetaUpdate {
+            description: Some(" Stripe live key ".to_string()),
+            tags: Some(vec![
+                "prod".to_string(),
+                "billing".to_string(),
+                "prod".to_string(),
+                " ".to_string(),
+            ]),
+            url: Some("".to_string()),
+            ..Default::default()
+        };
+        assert!(!update.is_empty());
+        update.apply(&mut meta);
+        assert_eq!(meta.description.as_deref(), Some("Stripe live key"));
+        assert_eq!(meta.tags, vec!["billing", "prod"]);
+        assert_eq!(meta.owner.as_deref(), Some("payments"));
+        assert!(meta.url.is_none());
+        assert_eq!(meta.expires, Some(100));
+
+        MetaUpdate {
+            description: Some(String::new()),
+            tags: Some(Vec::new()),
+            owner: Some(String::new()),
+            ..Default::default()
+        }
+        .apply(&mut meta);
+        meta.expires = None;
+        assert!(meta.is_empty());
+        assert!(MetaUpdate::default().is_empty());
+    }
+
+    #[test]
+    fn test_bulk_edit() {
+        let mut edit = BulkEdit {
+            add_tags: vec!["legacy".to_string(), " ".to_string()],
+            remove_tags: vec!["prod".to_string()],
+            ..Default::default()
+        };
+        edit.set("owner=platform-team").unwrap();
+        edit.set("url=").unwrap();
+        assert!(edit.set("owner").is_err());
+        assert!(edit.set("expires=90d").is_err());
+        assert!(!edit.is_empty());
+
+        let mut meta = KeyMeta {
+            tags: vec!["billing".to_string(), "prod".to_string()],
+            url: Some("https://example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(edit.apply(&mut meta));
+        assert_eq!(meta.tags, vec!["billing", "legacy"]);
+        assert_eq!(meta.owner.as_deref(), Some("platform-team"));
+        assert!(meta.url.is_none());
+
+        // Applying it again changes nothing
+        assert!(!edit.apply(&mut meta));
+        assert!(BulkEdit::default().is_empty());
+    }
+
+    #[test]
+    fn test_expiring() {
+        let with_expiry = |expires| KeyMeta {
+            expires,
+            ..Default::default()
+        };
+        let all = vec![
+            ("later".to_string(), with_expiry(Some(500))),
+            ("none".to_string(), with_expiry(None)),
+            ("expired".to_string(), with_expiry(Some(50))),
+            ("soon".to_string(), with_expiry(Some(150))),
+        ];
+        assert_eq!(expiring(&all, 200), vec![("expired", 50), ("soon", 150)]);
+        assert!(expiring(&all, 10).is_empty());
+        assert!(!with_expiry(Some(1)).is_empty());
+
+        assert_eq!(
+            describe_expiry(86400 * 3, 86400),
+            "1970-01-04T00:00:00Z (in 2d)"
+        );
+        assert_eq!(
+            describe_expiry(0, 3600),
+            "1970-01-01T00:00:00Z (expired 1h ago)"
+        );
+    }
+
+    #[test]
+    fn test_overdue() {
+        let with_deadline = |delete_after| KeyMeta {
+            delete_after,
+            ..Default::default()
+        };
+        let all = vec![
+            ("ci/temp-token".to_string(), with_deadline(Some(100))),
+            ("kept".to_string(), with_deadline(None)),
+            ("ci/later".to_string(), with_deadline(Some(900))),
+            ("ci/older".to_string(), with_deadline(Some(40))),
+        ];
+        assert_eq!(
+            overdue(&all, 100),
+            vec![("ci/older", 40), ("ci/temp-token", 100)]
+        );
+        assert!(overdue(&all, 10).is_empty());
+        assert!(!with_deadline(Some(1)).is_empty());
+
+        assert_eq!(
+            describe_deletion(0, 7200),
+            "1970-01-01T00:00:00Z (overdue by 2h; deleted by the next 'reap')"
+        );
+    }
+}
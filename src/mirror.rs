@@ -0,0 +1,355 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::Storage;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Repository path of the encrypted mirror settings. They may hold an access token for the
+/// mirror, so they are encrypted with the master key.
+pub const SETTINGS_PATH: &str = ".axkeystore/mirror.json";
+
+/// Refs copied to the mirror: branches and tags, replacing and pruning what the mirror has
+const REFSPECS: [&str; 2] = ["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"];
+
+/// Hosting service of a mirror repository
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Github,
+    Gitlab,
+}
+
+impl Provider {
+    /// Returns the name shown to the user
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::Github => "GitHub",
+            Provider::Gitlab => "GitLab",
+        }
+    }
+
+    /// Returns the host of the public service
+    fn default_host(&self) -> &'static str {
+        match self {
+            Provider::Github => "github.com",
+            Provider::Gitlab => "gitlab.com",
+        }
+    }
+
+    /// Returns the user name git sends with an access token over HTTPS
+    fn token_user(&self) -> &'static str {
+        match self {
+            Provider::Github => "x-access-token",
+            Provider::Gitlab => "oauth2",
+        }
+    }
+}
+
+/// A second repository that receives a copy of the vault's repository (still encrypted)
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Mirror {
+    pub provider: Provider,
+    /// Repository path on the mirror ('owner/name', or 'group/subgroup/name' on GitLab)
+    pub repo: String,
+    /// Host of a self-managed instance (None for github.com or gitlab.com)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Access token for the mirror (None to use the profile's GitHub token)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Push to the mirror after every change to keys, not only on demand
+    #[serde(default)]
+    pub auto: bool,
+}
+
+impl Mirror {
+    /// Returns the host the mirror lives on
+    pub fn host(&self) -> &str {
+        self.host
+            .as_deref()
+            .unwrap_or_else(|| self.provider.default_host())
+    }
+
+    /// Returns the HTTPS URL of the mirror repository
+    pub fn url(&self) -> String {
+        format!("https://{}/{}.git", self.host(), self.repo)
+    }
+
+    /// Returns where the mirror is, for messages ('gitlab.com/me/vault')
+    pub fn describe(&self) -> String {
+        format!("{}/{}", self.host(), self.repo)
+    }
+}
+
+/// Validates a mirror repository path. A bare name on GitHub means a repository of the
+/// authenticated user; GitLab projects need their namespace.
+pub fn validate_repo(repo: &str, provider: Provider, login: &str) -> Result<String> {
+    let repo = repo.trim().trim_matches('/');
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+    let valid = !repo.is_empty()
+        && repo.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+    if !valid {
+        return Err(anyhow::anyhow!("Invalid mirror repository '{}'", repo));
+    }
+    match (provider, repo.matches('/').count()) {
+        (Provider::Github, 0) => Ok(format!("{}/{}", login, repo)),
+        (Provider::Github, 1) | (Provider::Gitlab, 1..) => Ok(repo.to_string()),
+        (Provider::Github, _) => Err(anyhow::anyhow!(
+            "GitHub repositories are 'owner/name', not '{}'",
+            repo
+        )),
+        (Provider::Gitlab, _) => Err(anyhow::anyhow!(
+            "GitLab projects need their namespace ('group/name'), not '{}'",
+            repo
+        )),
+    }
+}
+
+/// Validates the host of a self-managed instance ('gitlab.example.com')
+pub fn validate_host(host: &str) -> Result<String> {
+    let host = host.trim().trim_end_matches('/');
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | ':'));
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid host '{}'. Give the host name only (e.g. 'gitlab.example.com')",
+            host
+        ));
+    }
+    Ok(host.to_ascii_lowercase())
+}
+
+/// Returns the HTTPS URL git clones a vault repository from, given the GitHub API URL the
+/// vault is reached through (GitHub Enterprise serves its API under '/api/v3')
+pub fn source_url(api_base: &str, repo: &str) -> String {
+    let api_base = api_base.trim_end_matches('/');
+    let web = match api_base {
+        "https://api.github.com" => "https://github.com",
+        _ => api_base.strip_suffix("/api/v3").unwrap_or(api_base),
+    };
+    format!("{}/{}.git", web, repo)
+}
+
+/// Reads the mirror settings, if the vault is mirrored
+pub async fn load(storage: &Storage, master_key: &str) -> Result<Option<Mirror>> {
+    let data = match storage.get_file(SETTINGS_PATH).await? {
+        Some((data, _)) => data,
+        None => return Ok(None),
+    };
+
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&data).context("Failed to parse mirror settings")?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, master_key)
+        .context("Failed to decrypt mirror settings")?;
+    serde_json::from_slice(&decrypted)
+        .map(Some)
+        .context("Mirror settings are corrupted")
+}
+
+/// Encrypts and saves the mirror settings
+pub async fn save(
+    storage: &Storage,
+    master_key: &str,
+    mirror: &Mirror,
+    message: &str,
+) -> Result<()> {
+    let encrypted = CryptoHandler::encrypt(&serde_json::to_vec(mirror)?, master_key)?;
+    storage
+        .put_file(SETTINGS_PATH, &serde_json::to_vec(&encrypted)?, message)
+        .await
+}
+
+/// Removes the mirror settings. Returns false if the vault was not mirrored.
+pub async fn remove(storage: &Storage, message: &str) -> Result<bool> {
+    storage.delete_file(SETTINGS_PATH, message).await
+}
+
+/// Returns the header git authenticates with to a repository, through its configuration
+/// for that URL
+fn credential(url: &str, user: &str, token: &str) -> (String, String) {
+    let encoded = BASE64.encode(format!("{}:{}", user, token));
+    crate::redact::register(&encoded);
+    (
+        format!("http.{}.extraHeader", url),
+        format!("Authorization: Basic {}", encoded),
+    )
+}
+
+/// Runs git with credentials passed through its environment, never on the command line
+/// where other users of the machine could see them
+fn git(args: &[&str], dir: Option<&Path>, credentials: &[(String, String)]) -> Result<()> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.arg("-C").arg(dir);
+    }
+    command
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_CONFIG_COUNT", credentials.len().to_string());
+    for (index, (key, value)) in credentials.iter().enumerate() {
+        command
+            .env(format!("GIT_CONFIG_KEY_{}", index), key)
+            .env(format!("GIT_CONFIG_VALUE_{}", index), value);
+    }
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to run 'git'. Is it installed?")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git {} failed: {}",
+            args[0],
+            crate::redact::redact(String::from_utf8_lossy(&output.stderr).trim())
+        ));
+    }
+    Ok(())
+}
+
+/// Removes a working copy of the vault's repository when dropped
+struct Scratch(PathBuf);
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Copies the vault's repository to the mirror: every branch and tag, with its whole
+/// history, replacing what the mirror had (rewritten history, e.g. after 'purge', is pushed
+/// as is). Values stay encrypted, as in the vault's repository.
+pub fn push(storage: &Storage, mirror: &Mirror) -> Result<()> {
+    let token = mirror.token.as_deref().unwrap_or(storage.token());
+    copy(
+        storage,
+        &mirror.url(),
+        credential(&mirror.url(), mirror.provider.token_user(), token),
+    )
+    .with_context(|| format!("Failed to push to {}", mirror.describe()))
+}
+
+/// Copies the vault's repository, with its whole history, to another repository on the same
+/// GitHub server, with the same token
+pub fn copy_to(storage: &Storage, repo: &str) -> Result<()> {
+    let target = source_url(storage.api_base(), repo);
+    copy(
+        storage,
+        &target,
+        credential(&target, Provider::Github.token_user(), storage.token()),
+    )
+    .with_context(|| format!("Failed to push to {}", repo))
+}
+
+/// Pushes every branch and tag of the vault's repository to a git URL, with the credential
+/// for that URL
+fn copy(storage: &Storage, target: &str, target_credential: (String, String)) -> Result<()> {
+    let source = source_url(storage.api_base(), &storage.repo_full_name());
+    let credentials = [
+        credential(&source, Provider::Github.token_user(), storage.token()),
+        target_credential,
+    ];
+
+    let scratch = Scratch(std::env::temp_dir().join(format!(
+        "axkeystore-mirror-{}-{:08x}",
+        std::process::id(),
+        rand::random::<u32>()
+    )));
+    let dir = scratch.0.to_string_lossy().into_owned();
+    git(
+        &["clone", "--bare", "--quiet", &source, &dir],
+        None,
+        &credentials,
+    )
+    .with_context(|| format!("Failed to read {}", storage.repo_full_name()))?;
+    let mut args = vec!["push", "--prune", "--quiet", target];
+    args.extend(REFSPECS);
+    git(&args, Some(&scratch.0), &credentials)
+}
+
+/// Pushes to the mirror if the vault is mirrored after every change. Returns the mirror
+/// pushed to, if any.
+pub async fn sync_auto(storage: &Storage, master_key: &str) -> Result<Option<Mirror>> {
+    match load(storage, master_key).await? {
+        Some(mirror) if mirror.auto => {
+            push(storage, &mirror)?;
+            Ok(Some(mirror))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_repo() {
+        assert_eq!(
+            validate_repo("vault-mirror", Provider::Github, "octocat").unwrap(),
+            "octocat/vault-mirror"
+        );
+        assert_eq!(
+            validate_repo(" myorg/vault.git ", Provider::Github, "octocat").unwrap(),
+            "myorg/vault"
+        );
+        assert_eq!(
+            validate_repo("team/infra/vault", Provider::Gitlab, "octocat").unwrap(),
+            "team/infra/vault"
+        );
+        assert!(validate_repo("vault", Provider::Gitlab, "octocat").is_err());
+        assert!(validate_repo("a/b/c", Provider::Github, "octocat").is_err());
+        assert!(validate_repo("me/../vault", Provider::Gitlab, "octocat").is_err());
+        assert!(validate_repo("me/va ult", Provider::Github, "octocat").is_err());
+
+        assert_eq!(
+            validate_host("GitLab.Example.com/").unwrap(),
+            "gitlab.example.com"
+        );
+        assert!(validate_host("https://gitlab.example.com").is_err());
+    }
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            source_url("https://api.github.com", "octocat/vault"),
+            "https://github.com/octocat/vault.git"
+        );
+        assert_eq!(
+            source_url("https://ghe.example.com/api/v3/", "team/vault"),
+            "https://ghe.example.com/team/vault.git"
+        );
+
+        let mirror = Mirror {
+            provider: Provider::Gitlab,
+            repo: "me/vault".to_string(),
+            host: None,
+            token: Some("glpat-secret".to_string()),
+            auto: true,
+        };
+        assert_eq!(mirror.url(), "https://gitlab.com/me/vault.git");
+        assert_eq!(mirror.describe(), "gitlab.com/me/vault");
+
+        let (key, value) = credential(&mirror.url(), "oauth2", "glpat-secret");
+        assert_eq!(key, "http.https://gitlab.com/me/vault.git.extraHeader");
+        assert_eq!(
+            value,
+            format!(
+                "Authorization: Basic {}",
+                BASE64.encode("oauth2:glpat-secret")
+            )
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::Storage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Repository path of the encrypted notification policy. Webhook URLs embed their own
+/// credentials, so the policy is encrypted with the master key.
+pub const POLICY_PATH: &str = ".axkeystore/notify.json";
+
+/// Sends notifications about keys matching a pattern to a webhook
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Route {
+    /// Key path pattern; '*' matches one path segment and '**' any number of them
+    pub pattern: String,
+    /// Incoming webhook URL (Slack, Mattermost, or any endpoint that accepts JSON)
+    pub webhook: String,
+    /// Channel to post to, for webhooks that allow overriding it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+}
+
+/// Where notifications about key changes go. Routes are checked in order; the first route
+/// whose pattern matches a key receives the notification about it.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct NotifyPolicy {
+    pub routes: Vec<Route>,
+}
+
+impl NotifyPolicy {
+    /// Returns the route that receives notifications about a key path, if any
+    pub fn route_for(&self, path: &str) -> Option<&Route> {
+        self.routes
+            .iter()
+            .find(|route| pattern_matches(&route.pattern, path))
+    }
+
+    /// Adds a route at the end, or replaces the route with the same pattern in place
+    pub fn set_route(&mut self, route: Route) {
+        match self.routes.iter_mut().find(|r| r.pattern == route.pattern) {
+            Some(existing) => *existing = route,
+            None => self.routes.push(route),
+        }
+    }
+
+    /// Removes the route with a pattern. Returns false if there was none.
+    pub fn remove_route(&mut self, pattern: &str) -> bool {
+        let before = self.routes.len();
+        self.routes.retain(|r| r.pattern != pattern);
+        self.routes.len() != before
+    }
+}
+
+/// Validates a route pattern: category-style segments, '*' or '**'
+pub fn validate_pattern(pattern: &str) -> Result<()> {
+    if pattern.is_empty() {
+        return Err(anyhow::anyhow!("Pattern cannot be empty"));
+    }
+    for segment in pattern.split('/') {
+        let valid = segment == "*"
+            || segment == "**"
+            || (!segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        if !valid {
+            return Err(anyhow::anyhow!(
+                "Invalid pattern segment '{}'. Use names, '*' or '**' separated by '/'",
+                segment
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validates a webhook URL
+pub fn validate_webhook(webhook: &str) -> Result<()> {
+    let url = reqwest::Url::parse(webhook).context("Invalid webhook URL")?;
+    if !matches!(url.scheme(), "https" | "http") || url.host_str().is_none() {
+        return Err(anyhow::anyhow!("Webhook URL must be an http(s) URL"));
+    }
+    Ok(())
+}
+
+/// Shortens a webhook URL to its host, since the path usually holds the secret token
+pub fn redact_webhook(webhook: &str) -> String {
+    match reqwest::Url::parse(webhook) {
+        Ok(url) => format!(
+            "{}://{}/\u{2026}",
+            url.scheme(),
+            url.host_str().unwrap_or_default()
+        ),
+        Err(_) => "<invalid URL>".to_string(),
+    }
+}
+
+/// Checks whether a key path ('category/name') matches a route pattern (see keyglob::matches)
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    crate::keyglob::matches(pattern, path)
+}
+
+/// Loads the notification policy (empty if none exists yet)
+pub async fn load(storage: &Storage, master_key: &str) -> Result<NotifyPolicy> {
+    let data = match storage.get_file(POLICY_PATH).await? {
+        Some((data, _)) => data,
+        None => return Ok(NotifyPolicy::default()),
+    };
+
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&data).context("Failed to parse notification policy")?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, master_key)
+        .context("Failed to decrypt notification policy")?;
+    serde_json::from_slice(&decrypted).context("Notification policy is corrupted")
+}
+
+/// Encrypts and saves the notification policy
+pub async fn save(
+    storage: &Storage,
+    master_key: &str,
+    policy: &NotifyPolicy,
+    message: &str,
+) -> Result<()> {
+    if CryptoHandler::scoped_categories(master_key).is_some() {
+        return Err(anyhow::anyhow!(
+            "Only holders of the master key can change notification routes."
+        ));
+    }
+    let encrypted = CryptoHandler::encrypt_compressed(&serde_json::to_vec(policy)?, master_key)?;
+    storage
+        .put_file(POLICY_PATH, &serde_json::to_vec(&encrypted)?, message)
+        .await
+}
+
+/// Posts one message per route about the keys it covers (never their values). Keys that
+/// match no route are not announced. Returns the number of messages sent.
+pub async fn dispatch(
+    policy: &NotifyPolicy,
+    repo: &str,
+    action: &str,
+    paths: &[String],
+) -> Result<usize> {
+    let mut by_route: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for path in paths {
+        if let Some(index) = policy
+            .routes
+            .iter()
+            .position(|route| pattern_matches(&route.pattern, path))
+        {
+            by_route.entry(index).or_default().push(path);
+        }
+    }
+    if by_route.is_empty() {
+        return Ok(0);
+    }
+
+    let client = reqwest::Client::builder()
+        .user_agent("axkeystore-cli")
+        .build()?;
+    let mut sent = 0;
+    let mut failures = Vec::new();
+    for (index, keys) in by_route {
+        let route = &policy.routes[index];
+        let text = format!(
+            "[{}] {} {}: {}",
+            repo,
+            if keys.len() == 1 { "Key" } else { "Keys" },
+            action,
+            keys.join(", ")
+        );
+        let mut payload = serde_json::json!({ "text": text });
+        if let Some(channel) = &route.channel {
+            payload["channel"] = serde_json::Value::String(channel.clone());
+        }
+
+        match client.post(&route.webhook).json(&payload).send().await {
+            Ok(res) if res.status().is_success() => sent += 1,
+            Ok(res) => failures.push(format!("'{}' returned {}", route.pattern, res.status())),
+            Err(e) => failures.push(format!("'{}': {}", route.pattern, e)),
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Webhook delivery failed for {}",
+            failures.join("; ")
+        ));
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn route(pattern: &str, webhook: &str) -> Route {
+        Route {
+            pattern: pattern.to_string(),
+            webhook: webhook.to_string(),
+            channel: None,
+        }
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern_matches("prod/**", "prod/db/password"));
+        assert!(pattern_matches("prod/**", "prod/api"));
+        assert!(!pattern_matches("prod/**", "production/api"));
+        assert!(pattern_matches("*/db/*", "prod/db/password"));
+        assert!(!pattern_matches("*/db/*", "prod/db/replica/password"));
+        assert!(pattern_matches("**/password", "prod/db/password"));
+        assert!(pattern_matches("**", "github-token"));
+        assert!(!pattern_matches("prod/*", "github-token"));
+
+        assert!(validate_pattern("prod/**").is_ok());
+        assert!(validate_pattern("prod/db*").is_err());
+        assert!(validate_pattern("prod//db").is_err());
+    }
+
+    #[test]
+    fn test_routes() {
+        let mut policy = NotifyPolicy::default();
+        policy.set_route(route("prod/**", "https://hooks.example.com/security"));
+        policy.set_route(route("**", "https://hooks.example.com/team"));
+        assert_eq!(
+            policy.route_for("prod/db").unwrap().webhook,
+            "https://hooks.example.com/security"
+        );
+        assert_eq!(
+            policy.route_for("dev/db").unwrap().webhook,
+            "https://hooks.example.com/team"
+        );
+
+        // Replacing a route keeps its position
+        policy.set_route(route("prod/**", "https://hooks.example.com/oncall"));
+        assert_eq!(policy.routes.len(), 2);
+        assert_eq!(policy.routes[0].webhook, "https://hooks.example.com/oncall");
+
+        assert!(policy.remove_route("**"));
+        assert!(!policy.remove_route("**"));
+        assert!(policy.route_for("dev/db").is_none());
+
+        assert_eq!(
+            redact_webhook("https://hooks.slack.com/services/T0/B0/secret"),
+            "https://hooks.slack.com/\u{2026}"
+        );
+        assert!(validate_webhook("ftp://example.com/hook").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/security"))
+            .and(body_json(serde_json::json!({
+                "text": "[org/vault] Keys updated: prod/db, prod/api",
+                "channel": "#security"
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/team"))
+            .and(body_json(serde_json::json!({
+                "text": "[org/vault] Key updated: dev/db"
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mut security = route("prod/**", &format!("{}/security", mock_server.uri()));
+        security.channel = Some("#security".to_string());
+        let policy = NotifyPolicy {
+            routes: vec![
+                security,
+                route("dev/**", &format!("{}/team", mock_server.uri())),
+            ],
+        };
+        let paths: Vec<String> = ["prod/db", "dev/db", "prod/api", "github-token"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            dispatch(&policy, "org/vault", "updated", &paths)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            dispatch(&policy, "org/vault", "updated", &["other".to_string()])
+                .await
+                .unwrap(),
+            0
+        );
+    }
+}
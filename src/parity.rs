@@ -0,0 +1,51 @@
+/// Result of comparing two secret values without revealing either of them
+#[derive(Debug, PartialEq)]
+pub struct Comparison {
+    pub equal: bool,
+    /// Number of leading bytes the values have in common
+    pub common_prefix: usize,
+    pub first_len: usize,
+    pub second_len: usize,
+}
+
+/// Compares two values. Every byte is visited, so the time taken does not depend on where
+/// the values first differ.
+pub fn compare(first: &[u8], second: &[u8]) -> Comparison {
+    let mut common_prefix = 0;
+    let mut diverged = false;
+    for i in 0..first.len().max(second.len()) {
+        let same = first.get(i).is_some() && first.get(i) == second.get(i);
+        diverged |= !same;
+        common_prefix += usize::from(!diverged);
+    }
+    Comparison {
+        equal: first.len() == second.len() && !diverged,
+        common_prefix,
+        first_len: first.len(),
+        second_len: second.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let same = compare(b"s3cret-value", b"s3cret-value");
+        assert!(same.equal);
+        assert_eq!(same.common_prefix, 12);
+
+        let different = compare(b"s3cret-value", b"s3cret-other!");
+        assert!(!different.equal);
+        assert_eq!(different.common_prefix, 7);
+        assert_eq!((different.first_len, different.second_len), (12, 13));
+
+        let prefix = compare(b"abc", b"abcdef");
+        assert!(!prefix.equal);
+        assert_eq!(prefix.common_prefix, 3);
+
+        assert!(compare(b"", b"").equal);
+        assert_eq!(compare(b"x", b"y").common_prefix, 0);
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// File at the root of a password store naming the GPG keys its entries are encrypted to
+const GPG_ID_FILE: &str = ".gpg-id";
+
+/// Returns the file holding a key in a password store ('cloud/api' -> '<dir>/cloud/api.gpg')
+pub fn entry_file(dir: &Path, path: &str) -> PathBuf {
+    let mut file = dir.to_path_buf();
+    for segment in path.split('/') {
+        file.push(segment);
+    }
+    let mut file = file.into_os_string();
+    file.push(".gpg");
+    PathBuf::from(file)
+}
+
+/// Returns the contents of a pass entry for a value: 'pass' expects every entry to end
+/// with a line break
+pub fn entry_contents(value: &[u8]) -> Vec<u8> {
+    let mut contents = value.to_vec();
+    if !contents.ends_with(b"\n") {
+        contents.push(b'\n');
+    }
+    contents
+}
+
+/// Encrypts contents to the recipients with gpg, writing them to a file
+fn encrypt_to(file: &Path, contents: &[u8], gpg_ids: &[String]) -> Result<()> {
+    let mut command = Command::new("gpg");
+    command.args(["--quiet", "--batch", "--yes", "--encrypt"]);
+    for id in gpg_ids {
+        command.args(["--recipient", id]);
+    }
+    let mut child = command
+        .arg("--output")
+        .arg(file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run 'gpg'. Is it installed?")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open gpg's input")?
+        .write_all(contents)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "gpg failed to encrypt '{}': {}",
+            file.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Writes values (by key path) as a password store that 'pass' and gopass can read: one
+/// gpg-encrypted file per key, in directories following the categories, with a '.gpg-id'
+/// naming the recipients. The directory must be empty or not exist yet.
+pub fn write_store(dir: &Path, gpg_ids: &[String], values: &[(String, Vec<u8>)]) -> Result<()> {
+    let gpg_ids: Vec<String> = gpg_ids
+        .iter()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+    if gpg_ids.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Name the GPG key(s) to encrypt the entries to with --gpg-id"
+        ));
+    }
+    let occupied = std::fs::read_dir(dir).is_ok_and(|mut listing| listing.next().is_some());
+    if occupied {
+        return Err(anyhow::anyhow!(
+            "'{}' is not empty. Export into a new directory",
+            dir.display()
+        ));
+    }
+
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    let mut ids = gpg_ids.join("\n");
+    ids.push('\n');
+    crate::config::write_private_file(&dir.join(GPG_ID_FILE), ids.as_bytes())?;
+
+    for (path, value) in values {
+        let file = entry_file(dir, path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create '{}'", parent.display()))?;
+        }
+        encrypt_to(&file, &entry_contents(value), &gpg_ids)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_file() {
+        let dir = Path::new("/tmp/store");
+        assert_eq!(
+            entry_file(dir, "cloud/aws/api.key"),
+            PathBuf::from("/tmp/store/cloud/aws/api.key.gpg")
+        );
+        assert_eq!(
+            entry_file(dir, "email"),
+            PathBuf::from("/tmp/store/email.gpg")
+        );
+        assert_eq!(entry_contents(b"hunter22"), b"hunter22\n");
+        assert_eq!(entry_contents(b"line 1\nline 2\n"), b"line 1\nline 2\n");
+    }
+
+    #[test]
+    fn test_write_store_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = write_store(dir.path(), &[" ".into()], &[]).unwrap_err();
+        assert!(err.to_string().contains("--gpg-id"));
+
+        std::fs::write(dir.path().join("existing"), "x").unwrap();
+        let err = write_store(dir.path(), &["me@example.com".into()], &[]).unwrap_err();
+        assert!(err.to_string().contains("not empty"));
+        assert!(!dir.path().join(GPG_ID_FILE).exists());
+    }
+}
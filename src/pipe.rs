@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs a command with a value on its stdin, and nowhere else: neither its arguments nor its
+/// environment carry it, so it does not show up in 'ps' or '/proc/<pid>/environ'. Stdout and
+/// stderr are inherited. Returns the command's exit code.
+pub fn run(command: &[String], value: &[u8]) -> Result<i32> {
+    let (program, args) = command.split_first().context("No command given")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run '{}'", program))?;
+
+    // Write from another thread, so a command that exits without reading all of its input
+    // cannot leave us blocked on a full pipe
+    let mut stdin = child
+        .stdin
+        .take()
+        .context("Failed to open the command's input")?;
+    let value = value.to_vec();
+    let writer = std::thread::spawn(move || {
+        // Dropping stdin closes it, so the command sees the end of the value
+        match stdin.write_all(&value) {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+            _ => Ok(()),
+        }
+    });
+
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to wait for '{}'", program))?;
+    writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("Failed to pass the value to '{}'", program))?
+        .with_context(|| format!("Failed to pass the value to '{}'", program))?;
+    // A command killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Text encodings for printing values that may be binary
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueFormat {
+    Base64,
+    Hex,
+}
+
+/// Encodes a value for printing
+pub fn encode(value: &[u8], format: ValueFormat) -> String {
+    match format {
+        ValueFormat::Base64 => BASE64.encode(value),
+        ValueFormat::Hex => value.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(b"\x00\xffab", ValueFormat::Hex), "00ff6162");
+        assert_eq!(encode(b"\x00\xffab", ValueFormat::Base64), "AP9hYg==");
+        assert_eq!(encode(b"", ValueFormat::Hex), "");
+    }
+
+    fn command(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        assert_eq!(
+            run(&command(r#"read v; test "$v" = s3cret"#), b"s3cret\n").unwrap(),
+            0
+        );
+        assert_eq!(run(&command("exit 3"), b"ignored").unwrap(), 3);
+        // A command that never reads a large value still completes
+        assert_eq!(run(&command("true"), &vec![b'x'; 1 << 20]).unwrap(), 0);
+        assert!(run(&[], b"value").is_err());
+        assert!(run(&["axkeystore-missing-command".to_string()], b"value").is_err());
+    }
+}
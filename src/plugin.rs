@@ -0,0 +1,223 @@
+use crate::serve;
+use crate::vault::Vault;
+use anyhow::{Context as _, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tokio::net::TcpListener;
+
+/// Prefix of plugin executables: 'axkeystore rotate' runs 'axkeystore-rotate'
+pub const PREFIX: &str = "axkeystore-";
+
+/// Version of the context passed to plugins
+const CONTEXT_FORMAT: u32 = 1;
+
+/// What a plugin receives as JSON on stdin
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Context {
+    /// Context format version
+    pub format: u32,
+    /// The plugin name (the subcommand it was invoked as)
+    pub name: String,
+    /// The arguments after the subcommand
+    pub args: Vec<String>,
+    /// The profile in use (None for the default profile)
+    pub profile: Option<String>,
+    /// The repository of the vault ('owner/repo')
+    pub repo: String,
+    /// The local HTTP API through which the plugin reads (and, if allowed, writes) keys
+    pub api: Api,
+}
+
+/// Access to the vault, brokered by a local HTTP API that only lives as long as the plugin.
+/// The API is served by the invoking process rather than by 'axkeystore agent', which has
+/// no API of its own and may not be running; it is read-only unless the plugin was allowed
+/// to write.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Api {
+    /// Base URL (e.g. 'http://127.0.0.1:49152'); keys are at '<url>/v1/keys/<path>'
+    pub url: String,
+    /// Bearer token for the API, valid for this invocation only
+    pub token: String,
+    /// Whether PUT requests are accepted ('axkeystore plugins --allow-write <name>')
+    pub writable: bool,
+}
+
+/// Validates that a plugin name contains only alphabets, numbers, underscores, and dashes
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.starts_with('-')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(anyhow::anyhow!(
+            "'{}' is not a valid command or plugin name. Only alphabets, numbers, '_' and '-' are allowed.",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the plugin name of an executable file name, or None if it is not a plugin
+fn plugin_name(file_name: &str) -> Option<&str> {
+    let name = file_name.strip_prefix(PREFIX)?;
+    let name = if cfg!(windows) {
+        name.strip_suffix(".exe")?
+    } else {
+        name
+    };
+    validate_name(name).ok().map(|_| name)
+}
+
+/// Returns true if a path is a file the current user can execute
+fn is_executable(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        #[cfg(unix)]
+        Ok(meta) => {
+            use std::os::unix::fs::PermissionsExt;
+            meta.is_file() && meta.permissions().mode() & 0o111 != 0
+        }
+        #[cfg(not(unix))]
+        Ok(meta) => meta.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// Lists the plugins found in a search path (the value of PATH), by name. When a name is found
+/// in several directories, the first one wins, as it would for the shell.
+pub fn discover(search_path: &OsStr) -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    for dir in std::env::split_paths(search_path) {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let name = match file_name.to_str().and_then(plugin_name) {
+                Some(name) => name,
+                None => continue,
+            };
+            let path = entry.path();
+            if !plugins.contains_key(name) && is_executable(&path) {
+                plugins.insert(name.to_string(), path);
+            }
+        }
+    }
+    plugins
+}
+
+/// Finds the executable of a plugin in a search path (the value of PATH)
+pub fn find(name: &str, search_path: &OsStr) -> Option<PathBuf> {
+    let file_name = if cfg!(windows) {
+        format!("{}{}.exe", PREFIX, name)
+    } else {
+        format!("{}{}", PREFIX, name)
+    };
+    std::env::split_paths(search_path)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| is_executable(path))
+}
+
+/// Runs a plugin: serves the vault on an ephemeral loopback port with a fresh token (read-only
+/// unless `writable`), passes the context on stdin, and stops serving once the plugin exits.
+/// Returns its exit code.
+pub async fn run(
+    executable: &Path,
+    name: &str,
+    args: &[String],
+    profile: Option<&str>,
+    vault: Vault,
+    writable: bool,
+) -> Result<i32> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .context("Failed to start the plugin API")?;
+    let context = Context {
+        format: CONTEXT_FORMAT,
+        name: name.to_string(),
+        args: args.to_vec(),
+        profile: profile.map(|p| p.to_string()),
+        repo: vault.storage().repo_full_name(),
+        api: Api {
+            url: format!("http://{}", listener.local_addr()?),
+            token: serve::generate_token(),
+            writable,
+        },
+    };
+
+    let mut child = Command::new(executable)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run '{}'", executable.display()))?;
+    {
+        // Dropping stdin closes it, so the plugin sees the end of the context
+        let mut stdin = child.stdin.take().context("Failed to open plugin input")?;
+        stdin.write_all(&serde_json::to_vec(&context)?)?;
+    }
+
+    let server = serve::serve_listener(listener, vault, context.api.token, !writable);
+    let waiter = tokio::task::spawn_blocking(move || child.wait());
+    let status = tokio::select! {
+        status = waiter => status.context("Failed to wait for the plugin")??,
+        result = server => {
+            result?;
+            return Err(anyhow::anyhow!("The plugin API stopped before the plugin exited"));
+        }
+    };
+    // A plugin killed by a signal has no exit code
+    Ok(status.code().unwrap_or(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("rotate").is_ok());
+        assert!(validate_name("rotate-aws_keys2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("-rotate").is_err());
+        assert!(validate_name("../rotate").is_err());
+        assert!(validate_name("rotate keys").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discover_and_find() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let write = |dir: &Path, file: &str, mode: u32| {
+            let path = dir.join(file);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            path
+        };
+        let rotate = write(first.path(), "axkeystore-rotate", 0o755);
+        write(second.path(), "axkeystore-rotate", 0o755);
+        let audit = write(second.path(), "axkeystore-audit", 0o755);
+        write(second.path(), "axkeystore-disabled", 0o644);
+        write(second.path(), "other-tool", 0o755);
+
+        let search_path = std::env::join_paths([first.path(), second.path()]).unwrap();
+        let plugins = discover(&search_path);
+        assert_eq!(
+            plugins.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("audit".to_string(), audit),
+                ("rotate".to_string(), rotate.clone())
+            ]
+        );
+        assert_eq!(find("rotate", &search_path), Some(rotate));
+        assert_eq!(find("disabled", &search_path), None);
+        assert_eq!(find("missing", &search_path), None);
+    }
+}
@@ -0,0 +1,594 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Password managers whose exports can be imported
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    /// A password-store directory (entries are decrypted with gpg)
+    Pass,
+    /// An unencrypted Bitwarden JSON export
+    Bitwarden,
+    /// A 1Password export (.1pux)
+    #[value(name = "1password")]
+    OnePassword,
+}
+
+impl Source {
+    /// Returns the name shown to users
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Pass => "pass",
+            Source::Bitwarden => "Bitwarden",
+            Source::OnePassword => "1Password",
+        }
+    }
+
+    /// Reads the secrets of an export (or of a password-store directory)
+    pub fn read(self, path: &Path) -> Result<Export> {
+        match self {
+            Source::Pass => read_pass(path),
+            Source::Bitwarden => {
+                let data = std::fs::read(path)
+                    .with_context(|| format!("Failed to read '{}'", path.display()))?;
+                parse_bitwarden(&data)
+                    .with_context(|| format!("'{}' is not a Bitwarden export", path.display()))
+            }
+            Source::OnePassword => read_1pux(path),
+        }
+    }
+}
+
+/// One secret read from a password manager
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// Category the folder (or 1Password vault) maps to
+    pub category: Option<String>,
+    pub name: String,
+    pub value: Vec<u8>,
+    /// Username of a login, recorded in the key's metadata
+    pub username: Option<String>,
+    /// Website of a login, recorded in the key's metadata
+    pub url: Option<String>,
+}
+
+impl Entry {
+    /// Returns the key path below the category the export is imported into
+    pub fn path(&self) -> String {
+        match &self.category {
+            Some(category) => format!("{}/{}", category, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// The secrets of an export, with the names of items that hold nothing to import (cards,
+/// identities, logins without a password)
+#[derive(Debug, Default)]
+pub struct Export {
+    pub entries: Vec<Entry>,
+    pub skipped: Vec<String>,
+    /// Paths of the entries so far
+    taken: BTreeSet<String>,
+}
+
+impl Export {
+    /// Adds an entry, renaming it ('name-2') if its path is taken by an earlier one
+    fn push(&mut self, mut entry: Entry) {
+        let base = entry.name.clone();
+        let mut n = 1;
+        while self.taken.contains(&entry.path()) {
+            n += 1;
+            entry.name = format!("{}-{}", base, n);
+        }
+        self.taken.insert(entry.path());
+        self.entries.push(entry);
+    }
+
+    /// Adds a login: its password under the item's name, and its one-time password seed
+    /// under '<name>-totp'
+    fn push_login(
+        &mut self,
+        category: Option<String>,
+        title: &str,
+        password: Option<&str>,
+        totp: Option<&str>,
+        username: Option<&str>,
+        url: Option<&str>,
+    ) {
+        let text = |v: Option<&str>| v.filter(|v| !v.trim().is_empty()).map(String::from);
+        let (password, totp) = (text(password), text(totp));
+        if password.is_none() && totp.is_none() {
+            self.skipped.push(title.to_string());
+            return;
+        }
+        let name = key_name(title);
+        if let Some(password) = password {
+            self.push(Entry {
+                category: category.clone(),
+                name: name.clone(),
+                value: password.into_bytes(),
+                username: text(username),
+                url: text(url),
+            });
+        }
+        if let Some(totp) = totp {
+            self.push(Entry {
+                category,
+                name: format!("{}-totp", name),
+                value: totp.into_bytes(),
+                username: None,
+                url: text(url),
+            });
+        }
+    }
+
+    /// Adds a secure note under the item's name
+    fn push_note(&mut self, category: Option<String>, title: &str, notes: Option<&str>) {
+        match notes.filter(|n| !n.trim().is_empty()) {
+            Some(notes) => self.push(Entry {
+                category,
+                name: key_name(title),
+                value: notes.as_bytes().to_vec(),
+                username: None,
+                url: None,
+            }),
+            None => self.skipped.push(title.to_string()),
+        }
+    }
+}
+
+/// Turns a folder or vault name into a category segment: characters other than letters,
+/// digits, '-' and '_' become dashes ('Work Stuff' -> 'Work-Stuff')
+pub fn category_segment(name: &str) -> String {
+    slug(name, |c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Turns an item title into a key name, also keeping dots ('api.example.com')
+pub fn key_name(title: &str) -> String {
+    let name = slug(title, |c| {
+        c.is_alphanumeric() || matches!(c, '-' | '_' | '.')
+    });
+    let name = name.trim_matches(|c| c == '.' || c == '-');
+    if name.is_empty() {
+        "unnamed".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+fn slug(text: &str, allowed: impl Fn(char) -> bool) -> String {
+    let mut out = String::new();
+    for c in text.trim().chars() {
+        if allowed(c) {
+            out.push(c);
+        } else if !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_matches('-').to_string()
+}
+
+/// Maps a folder path ('Work/Cloud') to a category, or None for the top level
+fn folder_category(folder: &str) -> Option<String> {
+    let segments: Vec<String> = folder
+        .split('/')
+        .map(category_segment)
+        .filter(|s| !s.is_empty())
+        .collect();
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+/// Lists the entries of a password store: the '.gpg' files below it, as paths relative to it
+/// without the extension. Hidden files and directories ('.git', '.gpg-id') are left out.
+pub fn pass_entries(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let listing = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read '{}'", dir.display()))?;
+        for item in listing {
+            let path = item?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "gpg") {
+                if let Ok(relative) = path.with_extension("").strip_prefix(root) {
+                    found.push(relative.to_path_buf());
+                }
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Reads a password store, decrypting each entry with gpg (which may ask for the key's
+/// passphrase). The whole entry is imported, as 'pass show' prints it.
+pub fn read_pass(root: &Path) -> Result<Export> {
+    let mut export = Export::default();
+    for relative in pass_entries(root)? {
+        let mut file = root.join(&relative).into_os_string();
+        file.push(".gpg");
+        let file = PathBuf::from(file);
+        let output = Command::new("gpg")
+            .args(["--quiet", "--batch", "--use-agent", "--decrypt"])
+            .arg(&file)
+            .output()
+            .context("Failed to run 'gpg'. Is it installed?")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "gpg failed to decrypt '{}': {}",
+                file.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let mut value = output.stdout;
+        if value.ends_with(b"\n") {
+            value.pop();
+        }
+        if value.is_empty() {
+            export.skipped.push(relative.display().to_string());
+            continue;
+        }
+        let folder = relative
+            .parent()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        let title = relative
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        export.push(Entry {
+            category: folder_category(&folder),
+            name: key_name(&title),
+            value,
+            username: None,
+            url: None,
+        });
+    }
+    Ok(export)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    /// 1 login, 2 secure note, 3 card, 4 identity
+    #[serde(rename = "type")]
+    kind: u8,
+    name: String,
+    folder_id: Option<String>,
+    notes: Option<String>,
+    login: Option<BitwardenLogin>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenLogin {
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Option<Vec<BitwardenUri>>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+/// Parses an unencrypted Bitwarden JSON export. Folders ('Work/Cloud') map to categories.
+pub fn parse_bitwarden(data: &[u8]) -> Result<Export> {
+    let bitwarden: BitwardenExport = serde_json::from_slice(data)?;
+    if bitwarden.encrypted {
+        return Err(anyhow::anyhow!(
+            "The export is encrypted. Export the vault again as 'JSON' rather than 'JSON (Encrypted)', and delete the file once imported"
+        ));
+    }
+    let mut export = Export::default();
+    for item in &bitwarden.items {
+        let category = item
+            .folder_id
+            .as_ref()
+            .and_then(|id| bitwarden.folders.iter().find(|f| &f.id == id))
+            .and_then(|f| folder_category(&f.name));
+        match (item.kind, &item.login) {
+            (1, Some(login)) => {
+                let url = login.uris.iter().flatten().find_map(|u| u.uri.as_deref());
+                export.push_login(
+                    category,
+                    &item.name,
+                    login.password.as_deref(),
+                    login.totp.as_deref(),
+                    login.username.as_deref(),
+                    url,
+                );
+            }
+            (2, _) => export.push_note(category, &item.name, item.notes.as_deref()),
+            _ => export.skipped.push(item.name.clone()),
+        }
+    }
+    Ok(export)
+}
+
+#[derive(Deserialize)]
+struct OnePasswordExport {
+    #[serde(default)]
+    accounts: Vec<OnePasswordAccount>,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordAccount {
+    #[serde(default)]
+    vaults: Vec<OnePasswordVault>,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordVault {
+    attrs: OnePasswordVaultAttrs,
+    #[serde(default)]
+    items: Vec<OnePasswordItem>,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordVaultAttrs {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePasswordItem {
+    #[serde(default)]
+    state: String,
+    /// '001' login, '003' secure note, '005' password
+    #[serde(default)]
+    category_uuid: String,
+    overview: OnePasswordOverview,
+    #[serde(default)]
+    details: OnePasswordDetails,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordOverview {
+    #[serde(default)]
+    title: String,
+    url: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePasswordDetails {
+    #[serde(default)]
+    login_fields: Vec<OnePasswordLoginField>,
+    notes_plain: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    sections: Vec<OnePasswordSection>,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordLoginField {
+    #[serde(default)]
+    value: String,
+    designation: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordSection {
+    #[serde(default)]
+    fields: Vec<OnePasswordField>,
+}
+
+#[derive(Deserialize)]
+struct OnePasswordField {
+    #[serde(default)]
+    value: serde_json::Value,
+}
+
+/// Parses the 'export.data' document of a 1Password export. Each vault maps to a category;
+/// archived items are left out.
+pub fn parse_1password(data: &[u8]) -> Result<Export> {
+    let onepassword: OnePasswordExport = serde_json::from_slice(data)?;
+    let mut export = Export::default();
+    let vaults = onepassword.accounts.iter().flat_map(|a| &a.vaults);
+    for vault in vaults {
+        let category = folder_category(&vault.attrs.name);
+        for item in vault.items.iter().filter(|i| i.state != "archived") {
+            let details = &item.details;
+            let title = &item.overview.title;
+            let field = |designation: &str| {
+                details
+                    .login_fields
+                    .iter()
+                    .find(|f| f.designation.as_deref() == Some(designation))
+                    .map(|f| f.value.as_str())
+            };
+            let totp = details
+                .sections
+                .iter()
+                .flat_map(|s| &s.fields)
+                .find_map(|f| f.value.get("totp").and_then(|t| t.as_str()));
+            match item.category_uuid.as_str() {
+                "001" => export.push_login(
+                    category.clone(),
+                    title,
+                    field("password"),
+                    totp,
+                    field("username"),
+                    item.overview.url.as_deref(),
+                ),
+                "005" => export.push_login(
+                    category.clone(),
+                    title,
+                    details.password.as_deref(),
+                    totp,
+                    None,
+                    item.overview.url.as_deref(),
+                ),
+                "003" => export.push_note(category.clone(), title, details.notes_plain.as_deref()),
+                _ => export.skipped.push(title.clone()),
+            }
+        }
+    }
+    Ok(export)
+}
+
+/// Reads a 1Password export (.1pux), a ZIP archive holding 'export.data'
+pub fn read_1pux(path: &Path) -> Result<Export> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open '{}'", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("'{}' is not a 1Password export", path.display()))?;
+    let mut data = Vec::new();
+    archive
+        .by_name("export.data")
+        .with_context(|| format!("'{}' holds no 'export.data'", path.display()))?
+        .read_to_end(&mut data)?;
+    parse_1password(&data)
+        .with_context(|| format!("'{}' is not a 1Password export", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(export: &Export) -> Vec<(String, &str)> {
+        export
+            .entries
+            .iter()
+            .map(|e| (e.path(), std::str::from_utf8(&e.value).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(category_segment(" Work Stuff! "), "Work-Stuff");
+        assert_eq!(key_name("api.example.com (old)"), "api.example.com-old");
+        assert_eq!(key_name("../.."), "unnamed");
+        assert_eq!(
+            folder_category("Work/Cloud Accounts"),
+            Some("Work/Cloud-Accounts".into())
+        );
+        assert_eq!(folder_category(" / "), None);
+    }
+
+    #[test]
+    fn test_parse_bitwarden() {
+        let data = br#"{
+            "encrypted": false,
+            "folders": [{"id": "f1", "name": "Work/Cloud"}],
+            "items": [
+                {"type": 1, "name": "AWS Console", "folderId": "f1",
+                 "login": {"username": "ops", "password": "pw1", "totp": "JBSWY3DPEHPK3PXP",
+                           "uris": [{"uri": "https://aws.amazon.com"}]}},
+                {"type": 1, "name": "AWS Console", "folderId": "f1",
+                 "login": {"password": "pw2"}},
+                {"type": 2, "name": "Recovery codes", "folderId": null, "notes": "a b c"},
+                {"type": 3, "name": "Visa", "card": {}},
+                {"type": 1, "name": "No password", "login": {"username": "x"}}
+            ]
+        }"#;
+        let export = parse_bitwarden(data).unwrap();
+        assert_eq!(
+            paths(&export),
+            vec![
+                ("Work/Cloud/AWS-Console".to_string(), "pw1"),
+                (
+                    "Work/Cloud/AWS-Console-totp".to_string(),
+                    "JBSWY3DPEHPK3PXP"
+                ),
+                ("Work/Cloud/AWS-Console-2".to_string(), "pw2"),
+                ("Recovery-codes".to_string(), "a b c"),
+            ]
+        );
+        assert_eq!(export.entries[0].username.as_deref(), Some("ops"));
+        assert_eq!(
+            export.entries[0].url.as_deref(),
+            Some("https://aws.amazon.com")
+        );
+        assert_eq!(export.skipped, vec!["Visa", "No password"]);
+
+        let err = parse_bitwarden(br#"{"encrypted": true, "items": []}"#).unwrap_err();
+        assert!(err.to_string().contains("encrypted"));
+    }
+
+    #[test]
+    fn test_parse_1password() {
+        let data = br#"{"accounts": [{"vaults": [{
+            "attrs": {"name": "Private"},
+            "items": [
+                {"state": "active", "categoryUuid": "001",
+                 "overview": {"title": "GitHub", "url": "https://github.com"},
+                 "details": {
+                    "loginFields": [
+                        {"value": "octocat", "designation": "username"},
+                        {"value": "hunter22", "designation": "password"}
+                    ],
+                    "sections": [{"fields": [{"value": {"totp": "otpauth://totp/GitHub?secret=ABC"}}]}]
+                 }},
+                {"state": "active", "categoryUuid": "005",
+                 "overview": {"title": "Wi-Fi"}, "details": {"password": "wifi-pass"}},
+                {"state": "archived", "categoryUuid": "005",
+                 "overview": {"title": "Old"}, "details": {"password": "old"}},
+                {"state": "active", "categoryUuid": "003",
+                 "overview": {"title": "Notes"}, "details": {"notesPlain": "text"}}
+            ]
+        }]}]}"#;
+        let export = parse_1password(data).unwrap();
+        assert_eq!(
+            paths(&export),
+            vec![
+                ("Private/GitHub".to_string(), "hunter22"),
+                (
+                    "Private/GitHub-totp".to_string(),
+                    "otpauth://totp/GitHub?secret=ABC"
+                ),
+                ("Private/Wi-Fi".to_string(), "wifi-pass"),
+                ("Private/Notes".to_string(), "text"),
+            ]
+        );
+        assert_eq!(export.entries[0].username.as_deref(), Some("octocat"));
+        assert!(export.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_pass_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("work/cloud")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".gpg-id"), "me@example.com").unwrap();
+        std::fs::write(root.join(".git/config.gpg"), "").unwrap();
+        std::fs::write(root.join("email.gpg"), "").unwrap();
+        std::fs::write(root.join("work/cloud/aws.gpg"), "").unwrap();
+        std::fs::write(root.join("work/readme.txt"), "").unwrap();
+        assert_eq!(
+            pass_entries(root).unwrap(),
+            vec![PathBuf::from("email"), PathBuf::from("work/cloud/aws")]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::cache::CacheKind;
+use crate::config::Config;
+use crate::crypto::CryptoHandler;
+use crate::storage::Storage;
+use crate::timeutil;
+use anyhow::Result;
+
+/// Minimum Argon2id memory cost (KiB) and iterations recommended by OWASP
+const MIN_KDF_MEMORY_KIB: u32 = 19 * 1024;
+const MIN_KDF_ITERATIONS: u32 = 2;
+
+/// How much a failed check matters, in priority order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    High,
+    Medium,
+    Low,
+}
+
+impl Severity {
+    /// Points deducted from the score when a check of this severity fails
+    fn weight(&self) -> u32 {
+        match self {
+            Severity::High => 25,
+            Severity::Medium => 10,
+            Severity::Low => 5,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::High => "HIGH",
+            Severity::Medium => "MEDIUM",
+            Severity::Low => "LOW",
+        }
+    }
+}
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Fail,
+    /// The check could not be performed (e.g. missing token scope)
+    Unknown,
+}
+
+/// The result of one posture check
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub status: Status,
+    /// What was checked and what was found
+    pub summary: String,
+    /// How to fix a failed or unknown check
+    pub remediation: Option<String>,
+}
+
+impl Finding {
+    fn pass(severity: Severity, summary: String) -> Self {
+        Self {
+            severity,
+            status: Status::Pass,
+            summary,
+            remediation: None,
+        }
+    }
+
+    fn fail(severity: Severity, summary: String, remediation: &str) -> Self {
+        Self {
+            severity,
+            status: Status::Fail,
+            summary,
+            remediation: Some(remediation.to_string()),
+        }
+    }
+
+    fn unknown(severity: Severity, summary: String, remediation: &str) -> Self {
+        Self {
+            severity,
+            status: Status::Unknown,
+            summary,
+            remediation: Some(remediation.to_string()),
+        }
+    }
+}
+
+/// Scores a set of findings out of 100. Failed checks deduct points by severity; checks that
+/// could not be performed deduct nothing.
+pub fn score(findings: &[Finding]) -> u32 {
+    let penalty: u32 = findings
+        .iter()
+        .filter(|f| f.status == Status::Fail)
+        .map(|f| f.severity.weight())
+        .sum();
+    100u32.saturating_sub(penalty)
+}
+
+/// Returns the findings that need attention, most severe first (failures before unknowns)
+pub fn prioritized(findings: &[Finding]) -> Vec<&Finding> {
+    let mut pending: Vec<&Finding> = findings
+        .iter()
+        .filter(|f| f.status != Status::Pass)
+        .collect();
+    pending.sort_by_key(|f| (f.severity, f.status == Status::Unknown));
+    pending
+}
+
+/// Runs every posture check against the vault without changing anything. Keys whose last
+/// change is older than max_age_secs are reported as due for rotation.
+pub async fn assess(storage: &Storage, max_age_secs: u64) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let config = Config::load_with_profile(storage.profile())?;
+
+    // Repository visibility
+    findings.push(match storage.is_private().await {
+        Ok(true) => Finding::pass(Severity::High, "Repository is private".to_string()),
+        Ok(false) => Finding::fail(
+            Severity::High,
+            "Repository is PUBLIC: anyone can download the encrypted vault".to_string(),
+            "Make the repository private in its GitHub settings (Settings > General > Danger Zone).",
+        ),
+        Err(e) => Finding::unknown(
+            Severity::High,
+            format!("Could not determine repository visibility ({})", e),
+            "Check that the token can read the repository.",
+        ),
+    });
+
+    // Two-factor authentication on the GitHub account
+    findings.push(match storage.two_factor_enabled().await {
+        Ok(Some(true)) => Finding::pass(
+            Severity::High,
+            "Two-factor authentication is enabled on the GitHub account".to_string(),
+        ),
+        Ok(Some(false)) => Finding::fail(
+            Severity::High,
+            "Two-factor authentication is DISABLED on the GitHub account".to_string(),
+            "Enable 2FA at https://github.com/settings/security.",
+        ),
+        Ok(None) | Err(_) => Finding::unknown(
+            Severity::High,
+            "Could not read the account's two-factor status".to_string(),
+            "Verify 2FA manually at https://github.com/settings/security.",
+        ),
+    });
+
+    // Collaborators outside the owning account or organization
+    findings.push(match storage.outside_collaborators().await {
+        Ok(outside) if outside.is_empty() => Finding::pass(
+            Severity::Medium,
+            "No outside collaborators have access to the repository".to_string(),
+        ),
+        Ok(outside) => Finding::fail(
+            Severity::Medium,
+            format!(
+                "{} outside collaborator(s) have access: {}",
+                outside.len(),
+                outside.join(", ")
+            ),
+            "Review repository access (Settings > Collaborators) and remove anyone who no longer needs it.",
+        ),
+        Err(_) => Finding::unknown(
+            Severity::Medium,
+            "Could not list repository collaborators".to_string(),
+            "Review repository access manually (Settings > Collaborators); listing requires admin access.",
+        ),
+    });
+
+    // Commit signing
+    findings.push(if !config.signing.is_enabled() {
+        Finding::fail(
+            Severity::Medium,
+            "Commits created by AxKeyStore are not signed".to_string(),
+            "Run 'axkeystore signing enable' with a GPG or SSH key registered on GitHub.",
+        )
+    } else if !config.signing.verify_on_get {
+        Finding::fail(
+            Severity::Low,
+            "Commits are signed, but signatures are not checked on 'get'".to_string(),
+            "Run 'axkeystore signing verify on'.",
+        )
+    } else {
+        Finding::pass(
+            Severity::Medium,
+            "Commits are signed and verified on 'get'".to_string(),
+        )
+    });
+
+    // Key derivation parameters
+    let (memory, iterations, parallelism) = CryptoHandler::kdf_params();
+    let kdf = format!(
+        "Argon2id uses {} KiB memory, {} iteration(s), {} lane(s)",
+        memory, iterations, parallelism
+    );
+    findings.push(
+        if memory >= MIN_KDF_MEMORY_KIB && iterations >= MIN_KDF_ITERATIONS {
+            Finding::pass(Severity::Medium, kdf)
+        } else {
+            Finding::fail(
+                Severity::Medium,
+                kdf,
+                "Upgrade AxKeyStore; key derivation is weaker than the OWASP recommendation.",
+            )
+        },
+    );
+
+    // Caching of derived keys
+    let cached: Vec<&str> = [CacheKind::DerivedKeys]
+        .iter()
+        .filter(|k| k.ttl(&config.cache_policy) > 0)
+        .map(|k| k.dir_name())
+        .collect();
+    findings.push(if cached.is_empty() {
+        Finding::pass(
+            Severity::Low,
+            "Derived keys are not cached on disk".to_string(),
+        )
+    } else {
+        Finding::fail(
+            Severity::Low,
+            format!(
+                "Sensitive material is cached on disk: {}",
+                cached.join(", ")
+            ),
+            "Run 'axkeystore cache set <kind> 0' unless the convenience is worth the exposure.",
+        )
+    });
+
+    // Key rotation ages
+    let now = timeutil::now_unix();
+    let mut stale = Vec::new();
+    for entry in storage.list_all_keys().await? {
+        let changed = storage
+            .get_latest_key_version(&entry.name, entry.category.as_deref())
+            .await?
+            .and_then(|v| timeutil::parse_timestamp(&v.date).ok());
+        if let Some(changed) = changed {
+            let age = now.saturating_sub(changed);
+            if age > max_age_secs {
+                stale.push(format!(
+                    "{} ({})",
+                    entry.display_path(),
+                    timeutil::format_duration_secs(age)
+                ));
+            }
+        }
+    }
+    let limit = timeutil::format_duration_secs(max_age_secs);
+    findings.push(if stale.is_empty() {
+        Finding::pass(
+            Severity::Low,
+            format!("Every key was changed within the last {}", limit),
+        )
+    } else {
+        Finding::fail(
+            Severity::Low,
+            format!(
+                "{} key(s) not rotated in over {}: {}",
+                stale.len(),
+                limit,
+                stale.join(", ")
+            ),
+            "Rotate these secrets at their source and update them with 'axkeystore store'.",
+        )
+    });
+
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_and_priorities() {
+        let findings = vec![
+            Finding::pass(Severity::High, "private".into()),
+            Finding::fail(Severity::Low, "stale keys".into(), "rotate"),
+            Finding::unknown(Severity::High, "2fa".into(), "check"),
+            Finding::fail(Severity::Medium, "unsigned".into(), "sign"),
+            Finding::fail(Severity::High, "public".into(), "make private"),
+        ];
+
+        assert_eq!(score(&findings), 100 - 25 - 10 - 5);
+        let order: Vec<&str> = prioritized(&findings)
+            .iter()
+            .map(|f| f.summary.as_str())
+            .collect();
+        assert_eq!(order, vec!["public", "2fa", "unsigned", "stale keys"]);
+
+        let all_failed: Vec<Finding> = (0..5)
+            .map(|_| Finding::fail(Severity::High, "x".into(), "y"))
+            .collect();
+        assert_eq!(score(&all_failed), 0);
+    }
+}
@@ -0,0 +1,130 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How the items of a bulk operation ended
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub succeeded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl Summary {
+    /// Returns the number of items that were handled, whatever the outcome
+    pub fn total(&self) -> usize {
+        self.succeeded + self.skipped + self.failed
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} succeeded, {} skipped, {} failed",
+            self.succeeded, self.skipped, self.failed
+        )
+    }
+}
+
+/// A progress bar on stderr for an operation over many keys, showing the key at hand and
+/// counting how each one ended. Without a terminal the bar is hidden, but lines printed
+/// through it still reach stderr.
+pub struct Progress {
+    bar: ProgressBar,
+    succeeded: AtomicUsize,
+    skipped: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl Progress {
+    /// Starts a bar for `total` items, labelled with what is being done ('Re-encrypting')
+    pub fn new(total: usize, action: &str) -> Self {
+        let bar = ProgressBar::new(total as u64);
+        if let Ok(style) = ProgressStyle::with_template(&format!(
+            "{} [{{bar:30}}] {{pos}}/{{len}} {{wide_msg}}",
+            action
+        )) {
+            bar.set_style(style.progress_chars("=> "));
+        }
+        Self {
+            bar,
+            succeeded: AtomicUsize::new(0),
+            skipped: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Shows the item being worked on
+    pub fn item(&self, name: &str) {
+        self.bar.set_message(name.to_string());
+    }
+
+    /// Prints a line above the bar
+    pub fn println(&self, line: &str) {
+        if self.bar.is_hidden() {
+            eprintln!("{}", line);
+        } else {
+            self.bar.println(line);
+        }
+    }
+
+    /// Counts an item that was handled
+    pub fn succeed(&self) {
+        self.succeeded.fetch_add(1, Ordering::Relaxed);
+        self.bar.inc(1);
+    }
+
+    /// Counts an item that needed nothing done
+    pub fn skip(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        self.bar.inc(1);
+    }
+
+    /// Counts an item that failed, printing why
+    pub fn fail(&self, line: &str) {
+        self.println(line);
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.bar.inc(1);
+    }
+
+    /// Returns the counts so far
+    pub fn summary(&self) -> Summary {
+        Summary {
+            succeeded: self.succeeded.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes the bar and returns the counts
+    pub fn finish(self) -> Summary {
+        self.bar.finish_and_clear();
+        self.summary()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let progress = Progress::new(4, "Checking");
+        progress.item("cloud/api");
+        progress.succeed();
+        progress.succeed();
+        progress.skip();
+        progress.fail("FAILED  db/pass");
+        let summary = progress.finish();
+        assert_eq!(
+            summary,
+            Summary {
+                succeeded: 2,
+                skipped: 1,
+                failed: 1
+            }
+        );
+        assert_eq!(summary.total(), 4);
+        assert_eq!(summary.to_string(), "2 succeeded, 1 skipped, 1 failed");
+    }
+}
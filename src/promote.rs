@@ -0,0 +1,147 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// What promoting a key does to its target
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The target key does not exist yet
+    Create,
+    /// The target key exists with a different value
+    Update,
+    /// The target key already has the same value
+    Unchanged,
+}
+
+/// One key of a category promotion
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    /// Source key path ('category/name')
+    pub from: String,
+    /// Target key path ('category/name')
+    pub to: String,
+    pub change: Change,
+    /// The value to copy
+    pub value: Vec<u8>,
+}
+
+/// Returns a key path relative to a category, or None if it is not inside it
+fn relative<'a>(path: &'a str, category: &str) -> Option<&'a str> {
+    path.strip_prefix(category.trim_matches('/'))
+        .and_then(|rest| rest.strip_prefix('/'))
+}
+
+/// Plans copying the keys of one category into another. `source` holds the decrypted values
+/// of the source category and `target` those of the target category, by key path. `keys`
+/// limits the promotion to these paths relative to the source category (all keys if empty).
+pub fn plan(
+    source: &[(String, Vec<u8>)],
+    from: &str,
+    to: &str,
+    keys: &[String],
+    target: &BTreeMap<String, Vec<u8>>,
+) -> Result<Vec<Step>> {
+    let missing: Vec<&str> = keys
+        .iter()
+        .map(|k| k.trim_matches('/'))
+        .filter(|k| {
+            !source
+                .iter()
+                .any(|(path, _)| relative(path, from) == Some(*k))
+        })
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Keys not found in '{}': {}",
+            from,
+            missing.join(", ")
+        ));
+    }
+
+    let mut steps = Vec::new();
+    for (path, value) in source {
+        let rel = match relative(path, from) {
+            Some(rel) => rel,
+            None => continue,
+        };
+        if !keys.is_empty() && !keys.iter().any(|k| k.trim_matches('/') == rel) {
+            continue;
+        }
+        let to_path = format!("{}/{}", to.trim_matches('/'), rel);
+        let change = match target.get(&to_path) {
+            None => Change::Create,
+            Some(existing) if existing == value => Change::Unchanged,
+            Some(_) => Change::Update,
+        };
+        steps.push(Step {
+            from: path.clone(),
+            to: to_path,
+            change,
+            value: value.clone(),
+        });
+    }
+    steps.sort_by(|a, b| a.to.cmp(&b.to));
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let source: Vec<(String, Vec<u8>)> = [
+            ("app/staging/api", "new-api"),
+            ("app/staging/db/password", "same"),
+            ("app/staging/token", "token"),
+        ]
+        .iter()
+        .map(|(path, value)| (path.to_string(), value.as_bytes().to_vec()))
+        .collect();
+        let target: BTreeMap<String, Vec<u8>> = [
+            ("app/prod/api", "old-api"),
+            ("app/prod/db/password", "same"),
+        ]
+        .iter()
+        .map(|(path, value)| (path.to_string(), value.as_bytes().to_vec()))
+        .collect();
+
+        let steps = plan(&source, "app/staging", "app/prod/", &[], &target).unwrap();
+        let summary: Vec<(&str, &str, Change)> = steps
+            .iter()
+            .map(|s| (s.from.as_str(), s.to.as_str(), s.change))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("app/staging/api", "app/prod/api", Change::Update),
+                (
+                    "app/staging/db/password",
+                    "app/prod/db/password",
+                    Change::Unchanged
+                ),
+                ("app/staging/token", "app/prod/token", Change::Create),
+            ]
+        );
+
+        let only = plan(
+            &source,
+            "app/staging",
+            "app/prod",
+            &["token".into()],
+            &target,
+        )
+        .unwrap();
+        assert_eq!(only.len(), 1);
+        assert_eq!(only[0].to, "app/prod/token");
+
+        let err = plan(
+            &source,
+            "app/staging",
+            "app/prod",
+            &["nope".into()],
+            &target,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("nope"));
+    }
+}
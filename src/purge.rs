@@ -0,0 +1,91 @@
+use crate::meta;
+use crate::storage::Storage;
+use crate::trash::TRASH_DIR;
+use anyhow::Result;
+use std::collections::BTreeSet;
+
+/// The repository files that hold a key's data, to be removed from every commit
+#[derive(Debug)]
+pub struct Target {
+    /// Display path of the key ('category/name' or just 'name')
+    id: String,
+    /// Path of the key blob
+    blob: String,
+    /// Path of the key's metadata
+    meta: String,
+    /// Further files, such as attachments
+    extra: BTreeSet<String>,
+}
+
+/// Returns true if a repository path is a file or one of its chunks ('<file>.part000', ...)
+fn is_file(path: &str, file: &str) -> bool {
+    path.strip_prefix(file)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(".part"))
+}
+
+impl Target {
+    /// Targets a key's blob, its metadata, and its copies in the trash
+    pub fn new(key: &str, category: Option<&str>) -> Result<Self> {
+        let blob = Storage::build_key_path(key, category)?;
+        Ok(Self {
+            id: blob
+                .trim_start_matches("keys/")
+                .trim_end_matches(".json")
+                .to_string(),
+            blob,
+            meta: meta::meta_path(key, category)?,
+            extra: BTreeSet::new(),
+        })
+    }
+
+    /// Also targets the given files (e.g. the key's attachments)
+    pub fn add_files(&mut self, files: impl IntoIterator<Item = String>) {
+        self.extra.extend(files);
+    }
+
+    /// Returns true if a repository path holds data of the key
+    pub fn covers(&self, path: &str) -> bool {
+        if is_file(path, &self.blob)
+            || is_file(path, &self.meta)
+            || self.extra.iter().any(|file| is_file(path, file))
+        {
+            return true;
+        }
+        // Trash copies are at '<trash>/<deletion time>/<id>.json'
+        path.strip_prefix(TRASH_DIR)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .and_then(|rest| rest.split_once('/'))
+            .is_some_and(|(deleted, rest)| {
+                deleted.chars().all(|c| c.is_ascii_digit())
+                    && is_file(rest, &format!("{}.json", self.id))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_covers() {
+        let mut target = Target::new("token", Some("api/prod")).unwrap();
+        target.add_files([meta::attachment_path("abc")]);
+
+        assert!(target.covers("keys/api/prod/token.json"));
+        assert!(target.covers("keys/api/prod/token.json.part002"));
+        assert!(target.covers(".axkeystore/meta/api/prod/token.json"));
+        assert!(target.covers(".axkeystore/trash/1700000000/api/prod/token.json"));
+        assert!(target.covers(".axkeystore/attachments/abc.json"));
+
+        assert!(!target.covers("keys/api/prod/token2.json"));
+        assert!(!target.covers("keys/api/prod/token.json.bak"));
+        assert!(!target.covers("keys/api/token.json"));
+        assert!(!target.covers(".axkeystore/trash/1700000000/api/prod/other.json"));
+        assert!(!target.covers(".axkeystore/trash/x/api/prod/token.json"));
+
+        let root = Target::new("token", None).unwrap();
+        assert!(root.covers("keys/token.json"));
+        assert!(root.covers(".axkeystore/trash/5/token.json"));
+        assert!(!root.covers("keys/api/prod/token.json"));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::audit::{self, AuditEvent};
+use crate::storage::Storage;
+use crate::templatefn::split_key_path;
+use crate::{derived, meta, timeutil};
+use anyhow::Result;
+
+/// A key whose deletion deadline has passed
+#[derive(Debug, PartialEq)]
+pub struct Overdue {
+    /// Display path of the key ('category/name' or just 'name')
+    pub path: String,
+    /// The deadline that passed (Unix seconds)
+    pub delete_after: u64,
+}
+
+/// Lists the keys whose deletion deadline ('store --delete-after') has passed
+pub async fn find(storage: &Storage, master_key: &str, now: u64) -> Result<Vec<Overdue>> {
+    let all = meta::load_all(storage, master_key).await?;
+    Ok(meta::overdue(&all, now)
+        .into_iter()
+        .map(|(path, delete_after)| Overdue {
+            path: path.to_string(),
+            delete_after,
+        })
+        .collect())
+}
+
+/// Deletes every overdue key with its metadata and attachments (derived keys are removed from
+/// the registry) in a single commit that also records one audit event per key
+pub async fn run(storage: &Storage, master_key: &str, due: &[Overdue]) -> Result<()> {
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let mut registry = derived::load(storage, master_key).await?;
+    let mut registry_changed = false;
+    let mut changes = Vec::new();
+    let mut events = Vec::new();
+    for key in due {
+        let (category, name) = split_key_path(&key.path);
+        if registry
+            .keys
+            .remove(&derived::key_id(name, category)?)
+            .is_some()
+        {
+            registry_changed = true;
+        } else {
+            changes.extend(storage.key_changes(name, category, None).await?);
+        }
+        changes.extend(meta::removal_changes(storage, master_key, name, category).await?);
+        events.push(AuditEvent::new(
+            "reap",
+            &format!(
+                "{} (delete after {})",
+                key.path,
+                timeutil::format_rfc3339(key.delete_after)
+            ),
+        ));
+    }
+    if registry_changed {
+        changes.extend(derived::save_changes(storage, master_key, &registry).await?);
+    }
+    changes.push(audit::append_all_change(storage, &events).await?);
+
+    storage
+        .apply_changes(
+            &changes,
+            &format!("Delete {} key(s) past their deletion deadline", due.len()),
+        )
+        .await
+}
@@ -0,0 +1,317 @@
+use crate::config::Config;
+use crate::crypto::{CryptoHandler, EncryptedBlob, WrappedKey};
+use crate::storage::Storage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Repository directory holding one wrapped copy of the master key per recipient
+const RECIPIENTS_DIR: &str = ".axkeystore/recipients";
+
+/// Repository directory holding the data keys of shared categories: one directory per
+/// top-level category, with one wrapped copy of the category's key per recipient
+const CATEGORY_KEYS_DIR: &str = ".axkeystore/keys";
+
+/// A collaborator who can unlock the vault with their own identity instead of the master password
+#[derive(Serialize, Deserialize)]
+pub struct Recipient {
+    /// Display name of the recipient
+    pub name: String,
+    /// Base64 encoded X25519 public key of the recipient
+    pub public_key: String,
+    /// The remote master key (or a category's data key) wrapped for this recipient's
+    /// public key
+    pub wrapped_key: WrappedKey,
+}
+
+/// Returns the path of the encrypted local identity for a profile
+fn identity_path(profile: Option<&str>) -> Result<PathBuf> {
+    Ok(Config::get_config_dir(profile)?.join("identity.json"))
+}
+
+/// Returns the repository path of a recipient's wrapped master key
+fn recipient_path(name: &str) -> String {
+    format!("{}/{}.json", RECIPIENTS_DIR, name)
+}
+
+/// Returns the repository path of a recipient's wrapped copy of a category's data key
+fn category_recipient_path(category: &str, name: &str) -> String {
+    format!("{}/{}/{}.json", CATEGORY_KEYS_DIR, category, name)
+}
+
+/// Validates that a category can be shared on its own: only top-level categories have a
+/// data key. Returns the category without surrounding slashes.
+pub fn validate_shared_category(category: &str) -> Result<String> {
+    match Storage::validate_category(Some(category))? {
+        Some(category) if !category.contains('/') => Ok(category),
+        _ => Err(anyhow::anyhow!(
+            "Only top-level categories can be shared on their own ('{}' is not one).",
+            category
+        )),
+    }
+}
+
+/// Validates that a recipient name contains only alphabets, numbers, underscores, and dashes
+pub fn validate_recipient_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(anyhow::anyhow!(
+            "Recipient name '{}' is invalid. Only alphabets, numbers, '_' and '-' are allowed.",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Loads and decrypts this profile's X25519 identity, if one has been generated
+pub fn load_identity(profile: Option<&str>, password: &str) -> Result<Option<[u8; 32]>> {
+    let path = identity_path(profile)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let lmk = Config::get_or_create_lmk_with_profile(profile, password)?;
+    let content = std::fs::read(&path)?;
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&content).context("Failed to parse local identity")?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, &lmk)
+        .map_err(|_| anyhow::anyhow!("Incorrect master password or corrupted local identity."))?;
+
+    let secret: [u8; 32] = decrypted
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Local identity is corrupted"))?;
+    Ok(Some(secret))
+}
+
+/// Deletes this profile's identity (e.g. once the password that encrypts it is lost),
+/// returning false if it had none
+pub fn forget_identity(profile: Option<&str>) -> Result<bool> {
+    let path = identity_path(profile)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    std::fs::remove_file(path)?;
+    Ok(true)
+}
+
+/// Loads this profile's identity, generating and saving a new one (encrypted with the LMK) if needed
+pub fn get_or_create_identity(profile: Option<&str>, password: &str) -> Result<[u8; 32]> {
+    if let Some(secret) = load_identity(profile, password)? {
+        return Ok(secret);
+    }
+
+    let lmk = Config::get_or_create_lmk_with_profile(profile, password)?;
+    let secret = CryptoHandler::generate_identity();
+    let encrypted = CryptoHandler::encrypt(&secret, &lmk)?;
+    crate::config::write_private_file(
+        &identity_path(profile)?,
+        &serde_json::to_vec_pretty(&encrypted)?,
+    )?;
+    Ok(secret)
+}
+
+/// Lists all recipients that have a wrapped copy of the master key
+pub async fn list(storage: &Storage) -> Result<Vec<Recipient>> {
+    list_in(storage, RECIPIENTS_DIR).await
+}
+
+/// Lists the recipients of a category, who have a wrapped copy of its data key
+pub async fn list_for_category(storage: &Storage, category: &str) -> Result<Vec<Recipient>> {
+    list_in(storage, &format!("{}/{}", CATEGORY_KEYS_DIR, category)).await
+}
+
+/// Lists the categories shared with at least one recipient, sorted
+pub async fn shared_categories(storage: &Storage) -> Result<Vec<String>> {
+    let mut categories: Vec<String> = storage
+        .list_dir(CATEGORY_KEYS_DIR)
+        .await?
+        .into_iter()
+        .filter(|entry| entry.is_dir)
+        .map(|entry| entry.name)
+        .collect();
+    categories.sort();
+    Ok(categories)
+}
+
+/// Lists the recipient files of a directory, sorted by name
+async fn list_in(storage: &Storage, dir: &str) -> Result<Vec<Recipient>> {
+    let mut recipients = Vec::new();
+    for entry in storage.list_dir(dir).await? {
+        if entry.is_dir || !entry.name.ends_with(".json") {
+            continue;
+        }
+        if let Some((data, _)) = storage.get_file(&entry.path).await? {
+            let recipient: Recipient = serde_json::from_slice(&data)
+                .with_context(|| format!("Failed to parse recipient file '{}'", entry.path))?;
+            recipients.push(recipient);
+        }
+    }
+    recipients.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(recipients)
+}
+
+/// Wraps the master key for a recipient's public key and stores it in the repository
+pub async fn add(storage: &Storage, name: &str, public_key: &str, master_key: &str) -> Result<()> {
+    validate_recipient_name(name)?;
+    if CryptoHandler::scoped_categories(master_key).is_some() {
+        return Err(anyhow::anyhow!(
+            "Only holders of the master key can share the whole vault. Share a category with '--category' instead."
+        ));
+    }
+
+    let recipient = Recipient {
+        name: name.to_string(),
+        public_key: public_key.trim().to_string(),
+        wrapped_key: CryptoHandler::wrap_for_recipient(master_key.as_bytes(), public_key)?,
+    };
+
+    storage
+        .put_file(
+            &recipient_path(name),
+            &serde_json::to_vec_pretty(&recipient)?,
+            &format!("Add recipient: {}", name),
+        )
+        .await
+}
+
+/// Wraps the data key of a top-level category for a recipient's public key and stores it in
+/// the repository. The recipient can then read the values of that category only.
+pub async fn add_for_category(
+    storage: &Storage,
+    name: &str,
+    public_key: &str,
+    master_key: &str,
+    category: &str,
+) -> Result<()> {
+    validate_recipient_name(name)?;
+    let category_key = CryptoHandler::category_key(master_key, category)?;
+
+    let recipient = Recipient {
+        name: name.to_string(),
+        public_key: public_key.trim().to_string(),
+        wrapped_key: CryptoHandler::wrap_for_recipient(category_key.as_bytes(), public_key)?,
+    };
+
+    storage
+        .put_file(
+            &category_recipient_path(category, name),
+            &serde_json::to_vec_pretty(&recipient)?,
+            &format!("Add recipient: {} to category {}", name, category),
+        )
+        .await
+}
+
+/// Removes a recipient's wrapped copy of a category's data key, returning false if the
+/// recipient did not have one
+pub async fn remove_for_category(storage: &Storage, name: &str, category: &str) -> Result<bool> {
+    validate_recipient_name(name)?;
+    storage
+        .delete_file(
+            &category_recipient_path(category, name),
+            &format!("Remove recipient: {} from category {}", name, category),
+        )
+        .await
+}
+
+/// Removes a recipient's wrapped master key, returning false if the recipient did not exist
+pub async fn remove(storage: &Storage, name: &str) -> Result<bool> {
+    validate_recipient_name(name)?;
+    storage
+        .delete_file(
+            &recipient_path(name),
+            &format!("Remove recipient: {}", name),
+        )
+        .await
+}
+
+/// Attempts to unlock the master key with this profile's identity instead of the master password
+pub async fn unwrap_master_key(storage: &Storage, password: &str) -> Result<Option<String>> {
+    let secret = match load_identity(storage.profile(), password)? {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let public_key = CryptoHandler::identity_public_key(&secret);
+
+    for recipient in list(storage).await? {
+        if recipient.public_key == public_key {
+            let master_key = CryptoHandler::unwrap_with_identity(&recipient.wrapped_key, &secret)?;
+            return Ok(Some(
+                String::from_utf8(master_key).context("Master key is not valid UTF-8")?,
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Attempts to unlock the categories shared with this profile's identity. Returns a vault
+/// key scoped to those categories, or None if none is shared with it.
+pub async fn unwrap_category_keys(storage: &Storage, password: &str) -> Result<Option<String>> {
+    let secret = match load_identity(storage.profile(), password)? {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let public_key = CryptoHandler::identity_public_key(&secret);
+
+    let mut keys = BTreeMap::new();
+    for category in shared_categories(storage).await? {
+        for recipient in list_for_category(storage, &category).await? {
+            if recipient.public_key == public_key {
+                let key = CryptoHandler::unwrap_with_identity(&recipient.wrapped_key, &secret)?;
+                keys.insert(
+                    category.clone(),
+                    String::from_utf8(key).context("Category key is not valid UTF-8")?,
+                );
+            }
+        }
+    }
+
+    if keys.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(CryptoHandler::scoped_key(&keys)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_recipient_name() {
+        assert!(validate_recipient_name("alice").is_ok());
+        assert!(validate_recipient_name("ci_bot-2").is_ok());
+        assert!(validate_recipient_name("").is_err());
+        assert!(validate_recipient_name("../evil").is_err());
+        assert!(validate_recipient_name("bob smith").is_err());
+    }
+
+    #[test]
+    fn test_validate_shared_category() {
+        assert_eq!(validate_shared_category("team").unwrap(), "team");
+        assert_eq!(validate_shared_category("/team/").unwrap(), "team");
+        assert!(validate_shared_category("team/shared").is_err());
+        assert!(validate_shared_category("").is_err());
+        assert!(validate_shared_category("../team").is_err());
+    }
+
+    #[test]
+    fn test_identity_is_persisted() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        assert!(load_identity(None, "pass").unwrap().is_none());
+        let first = get_or_create_identity(None, "pass").unwrap();
+        let second = get_or_create_identity(None, "pass").unwrap();
+        assert_eq!(first, second);
+
+        // Wrong password cannot unlock the identity
+        assert!(load_identity(None, "wrong").is_err());
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+}
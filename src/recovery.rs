@@ -0,0 +1,404 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::Storage;
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Repository file recording that recovery shares exist (never the shares themselves)
+const MARKER_PATH: &str = ".axkeystore/recovery.json";
+
+/// Prefix of an encoded share
+const SHARE_PREFIX: &str = "AXKS";
+
+/// Repository file holding the master key encrypted with the recovery code
+const CODE_PATH: &str = ".axkeystore/recovery-code.json";
+
+/// Characters of a recovery code (Crockford's base32, without I, L, O and U)
+const CODE_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Characters in a recovery code, 5 bits each (160 bits)
+const CODE_LENGTH: usize = 32;
+
+/// One share of the remote master key (Shamir's secret sharing over GF(256)). Any
+/// `threshold` shares of the same split rebuild the key; fewer reveal nothing about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    /// Number of shares needed to rebuild the key
+    pub threshold: u8,
+    /// Position of the share (1-based), the x coordinate of its points
+    pub index: u8,
+    /// One point per byte of the key
+    pub data: Vec<u8>,
+    /// Check value of the key the share belongs to, so shares of different splits (or a
+    /// mistyped share) are detected
+    pub check: String,
+}
+
+/// What 'recovery setup' leaves in the repository, so a rekey can tell the shares are stale
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Marker {
+    /// When the shares were made (RFC 3339)
+    pub created: String,
+    pub shares: u8,
+    pub threshold: u8,
+    /// Check value of the master key the shares rebuild
+    pub check: String,
+}
+
+/// Returns the check value of a master key: the first 4 bytes of its SHA-256, in hex
+pub fn check_value(master_key: &str) -> String {
+    Sha256::digest(master_key.as_bytes())[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Multiplies two elements of GF(256) (AES polynomial)
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Returns the multiplicative inverse of a non-zero element of GF(256) (a^254)
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+/// Splits a master key into `shares` shares, any `threshold` of which rebuild it
+pub fn split(master_key: &str, shares: u8, threshold: u8) -> Result<Vec<Share>> {
+    if threshold < 2 || threshold > shares {
+        return Err(anyhow::anyhow!(
+            "The threshold must be at least 2 and at most the number of shares ({}).",
+            shares
+        ));
+    }
+    let check = check_value(master_key);
+    let mut rng = rand::rng();
+    let mut result: Vec<Share> = (1..=shares)
+        .map(|index| Share {
+            threshold,
+            index,
+            data: Vec::with_capacity(master_key.len()),
+            check: check.clone(),
+        })
+        .collect();
+
+    for &byte in master_key.as_bytes() {
+        // A random polynomial of degree threshold - 1 whose value at 0 is the byte
+        let mut coefficients = vec![byte];
+        coefficients.extend((1..threshold).map(|_| rng.random::<u8>()));
+        for share in &mut result {
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0, |acc, &c| gf_mul(acc, share.index) ^ c);
+            share.data.push(y);
+        }
+    }
+    Ok(result)
+}
+
+/// Rebuilds a master key from shares of one split. Fails if there are too few shares, if
+/// they come from different splits, or if the result does not match their check value.
+pub fn combine(shares: &[Share]) -> Result<String> {
+    let first = shares
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No shares given."))?;
+    for share in shares {
+        if share.threshold != first.threshold
+            || share.check != first.check
+            || share.data.len() != first.data.len()
+        {
+            return Err(anyhow::anyhow!(
+                "Share {} belongs to another set of shares.",
+                share.index
+            ));
+        }
+    }
+    let mut indices: Vec<u8> = shares.iter().map(|s| s.index).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    if indices.len() != shares.len() {
+        return Err(anyhow::anyhow!("The same share was given twice."));
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(anyhow::anyhow!(
+            "{} shares are needed, {} given.",
+            first.threshold,
+            shares.len()
+        ));
+    }
+
+    // Lagrange interpolation at 0 over the first `threshold` shares
+    let shares = &shares[..first.threshold as usize];
+    let mut secret = vec![0u8; first.data.len()];
+    for (j, share) in shares.iter().enumerate() {
+        let mut numerator = 1;
+        let mut denominator = 1;
+        for (m, other) in shares.iter().enumerate() {
+            if m != j {
+                numerator = gf_mul(numerator, other.index);
+                denominator = gf_mul(denominator, other.index ^ share.index);
+            }
+        }
+        let basis = gf_mul(numerator, gf_inv(denominator));
+        for (byte, &y) in secret.iter_mut().zip(&share.data) {
+            *byte ^= gf_mul(y, basis);
+        }
+    }
+
+    let master_key = String::from_utf8(secret)
+        .ok()
+        .filter(|key| check_value(key) == first.check)
+        .ok_or_else(|| {
+            anyhow::anyhow!("The shares do not rebuild the master key. Check them for typos.")
+        })?;
+    Ok(master_key)
+}
+
+impl std::fmt::Display for Share {
+    /// Formats the share for printing ('AXKS-3-1-<points>-<check>')
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let data: String = self.data.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            SHARE_PREFIX, self.threshold, self.index, data, self.check
+        )
+    }
+}
+
+impl std::str::FromStr for Share {
+    type Err = anyhow::Error;
+
+    /// Parses a share printed by 'recovery setup', ignoring surrounding whitespace and case
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Not a recovery share: '{}'", s.trim());
+        let s = s.trim().to_ascii_lowercase();
+        let parts: Vec<&str> = s.split('-').collect();
+        let [prefix, threshold, index, data, check] = parts[..] else {
+            return Err(invalid());
+        };
+        if !prefix.eq_ignore_ascii_case(SHARE_PREFIX)
+            || data.len() % 2 != 0
+            || !data.chars().all(|c| c.is_ascii_hexdigit())
+            || check.len() != 8
+            || !check.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(invalid());
+        }
+        let data = (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+            .collect::<std::result::Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        let share = Share {
+            threshold: threshold.parse().map_err(|_| invalid())?,
+            index: index.parse().map_err(|_| invalid())?,
+            data,
+            check: check.to_string(),
+        };
+        if share.index == 0 || share.threshold < 2 {
+            return Err(invalid());
+        }
+        Ok(share)
+    }
+}
+
+/// Loads the recovery marker of the vault, if shares were made
+pub async fn load_marker(storage: &Storage) -> Result<Option<Marker>> {
+    match storage.get_file(MARKER_PATH).await? {
+        Some((data, _)) => Ok(Some(
+            serde_json::from_slice(&data).context("Failed to parse the recovery marker")?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Records that shares were made for the current master key
+pub async fn save_marker(storage: &Storage, marker: &Marker) -> Result<()> {
+    storage
+        .put_file(
+            MARKER_PATH,
+            &serde_json::to_vec_pretty(marker)?,
+            &format!(
+                "Set up recovery shares ({} of {})",
+                marker.threshold, marker.shares
+            ),
+        )
+        .await
+}
+
+/// Returns a new random recovery code, in dash-separated groups of four characters
+pub fn generate_code() -> String {
+    let mut rng = rand::rng();
+    let chars: Vec<char> = (0..CODE_LENGTH)
+        .map(|_| CODE_ALPHABET[rng.random_range(0..CODE_ALPHABET.len())] as char)
+        .collect();
+    chars
+        .chunks(4)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Brings a typed recovery code to its canonical form: dashes and spaces dropped, upper
+/// case, and the letters easily mistaken for digits (O, I, L) read as those digits
+pub fn normalize_code(code: &str) -> Result<String> {
+    let normalized: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect();
+    if normalized.len() != CODE_LENGTH || !normalized.bytes().all(|b| CODE_ALPHABET.contains(&b)) {
+        return Err(anyhow::anyhow!(
+            "Not a recovery code. It has {} characters from 0-9 and A-Z, in groups of four.",
+            CODE_LENGTH
+        ));
+    }
+    Ok(normalized)
+}
+
+/// Stores the master key encrypted with a recovery code, replacing any earlier code
+pub async fn save_code(storage: &Storage, master_key: &str, code: &str) -> Result<()> {
+    let encrypted = CryptoHandler::encrypt(master_key.as_bytes(), &normalize_code(code)?)?;
+    storage
+        .put_file(
+            CODE_PATH,
+            &serde_json::to_vec(&encrypted)?,
+            "Set up recovery code",
+        )
+        .await
+}
+
+/// Returns true if the vault has a recovery code
+pub async fn has_code(storage: &Storage) -> Result<bool> {
+    Ok(storage.get_file(CODE_PATH).await?.is_some())
+}
+
+/// Decrypts the master key with a recovery code. Returns None if the vault has no code.
+pub async fn open_code(storage: &Storage, code: &str) -> Result<Option<String>> {
+    let code = normalize_code(code)?;
+    let Some((data, _)) = storage.get_file(CODE_PATH).await? else {
+        return Ok(None);
+    };
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&data).context("Failed to parse the recovery code file")?;
+    let master_key = CryptoHandler::decrypt(&encrypted, &code)
+        .map_err(|_| anyhow::anyhow!("Incorrect recovery code."))?;
+    Ok(Some(String::from_utf8(master_key)?))
+}
+
+/// Removes the recovery code (a rekey leaves it opening the old master key). Returns false
+/// if the vault had none.
+pub async fn remove_code(storage: &Storage) -> Result<bool> {
+    storage
+        .delete_file(CODE_PATH, "Remove recovery code of the old master key")
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_combine() {
+        let master_key = "abcdefghijklmnopqrstuvwxyz0123456789";
+        let shares = split(master_key, 5, 3).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        // Any three shares rebuild the key
+        for picked in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let subset: Vec<Share> = picked.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine(&subset).unwrap(), master_key);
+        }
+        // More than enough works too
+        assert_eq!(combine(&shares).unwrap(), master_key);
+
+        // Two are not enough, and a share cannot be counted twice
+        assert!(combine(&shares[..2]).is_err());
+        let repeated = vec![shares[0].clone(), shares[0].clone(), shares[1].clone()];
+        assert!(combine(&repeated).is_err());
+
+        // A damaged share fails the check instead of yielding a wrong key
+        let mut damaged = shares[..3].to_vec();
+        damaged[1].data[0] ^= 1;
+        assert!(combine(&damaged).is_err());
+
+        // Shares of another split are refused
+        let other = split("another-master-key-000000000000000000", 5, 3).unwrap();
+        let mixed = vec![shares[0].clone(), shares[1].clone(), other[2].clone()];
+        assert!(combine(&mixed).is_err());
+
+        assert!(split(master_key, 5, 1).is_err());
+        assert!(split(master_key, 3, 4).is_err());
+    }
+
+    #[test]
+    fn test_share_encoding() {
+        let share = Share {
+            threshold: 3,
+            index: 2,
+            data: vec![0x00, 0xab, 0x10],
+            check: "0a1b2c3d".to_string(),
+        };
+        let encoded = share.to_string();
+        assert_eq!(encoded, "AXKS-3-2-00ab10-0a1b2c3d");
+        assert_eq!(encoded.parse::<Share>().unwrap(), share);
+        assert_eq!(
+            format!("  {}\n", encoded.to_lowercase())
+                .parse::<Share>()
+                .unwrap(),
+            share
+        );
+
+        assert!("AXKS-3-2-00ab1-0a1b2c3d".parse::<Share>().is_err());
+        assert!("AXKS-3-0-00ab10-0a1b2c3d".parse::<Share>().is_err());
+        assert!("XYZ-3-2-00ab10-0a1b2c3d".parse::<Share>().is_err());
+        assert!("AXKS-3-2-00ab10".parse::<Share>().is_err());
+        assert!("AXKS-2-1-aé0-0a1b2c3d".parse::<Share>().is_err());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let code = generate_code();
+        assert_eq!(code.len(), CODE_LENGTH + CODE_LENGTH / 4 - 1);
+        assert_eq!(code.split('-').count(), CODE_LENGTH / 4);
+        assert_eq!(normalize_code(&code).unwrap(), code.replace('-', ""));
+        assert_ne!(generate_code(), code);
+
+        // Case, spacing and look-alike letters do not matter
+        assert_eq!(
+            normalize_code(" 0123-4567-89ab-cdef ghjk-mnpq-rstv-wxyz\n").unwrap(),
+            "0123456789ABCDEFGHJKMNPQRSTVWXYZ"
+        );
+        assert_eq!(
+            normalize_code("OIL3-4567-89AB-CDEF-GHJK-MNPQ-RSTV-WXYZ").unwrap(),
+            "0113456789ABCDEFGHJKMNPQRSTVWXYZ"
+        );
+
+        assert!(normalize_code("0123-4567").is_err());
+        assert!(normalize_code("0123-4567-89AB-CDEF-GHJK-MNPQ-RSTV-WXYU").is_err());
+        assert!(normalize_code("AXKS-3-2-00ab10-0a1b2c3d").is_err());
+    }
+}
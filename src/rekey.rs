@@ -0,0 +1,575 @@
+use crate::config::Config;
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::progress::Progress;
+use crate::storage::Storage;
+use crate::{cache, cancel, derived, meta, recipients, serviceaccount, trash, vault};
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Journal marker for the recipients' wrapped keys, which are migrated along with the keys
+const RECIPIENTS_MARKER: &str = ".axkeystore/recipients";
+
+/// Repository path of the new master key of an unfinished rekey, encrypted with the master
+/// password like the master key itself
+pub const PENDING_KEY_PATH: &str = ".axkeystore/rekey_pending.json";
+
+/// Estimated GitHub requests to re-encrypt one key (look up its SHA, write it)
+const CALLS_PER_KEY: usize = 2;
+
+/// Estimated GitHub requests to re-encrypt one metadata file or attachment (read it, then
+/// commit it through the Git Data API)
+const CALLS_PER_META_FILE: usize = 7;
+
+/// Rough average duration of a GitHub request, including write throttling
+const MILLIS_PER_CALL: u64 = 400;
+
+/// Progress of an in-flight re-encryption, so an interrupted run can resume without
+/// stranding keys that were already re-encrypted under the new master key
+#[derive(Serialize, Deserialize)]
+pub struct Journal {
+    /// The repository being re-encrypted ('owner/repo')
+    pub repo: String,
+    /// The new master key that migrated items are encrypted with
+    pub new_master_key: String,
+    /// Paths that have already been re-encrypted
+    pub processed: BTreeSet<String>,
+}
+
+/// Returns the path of the encrypted rekey journal for a profile
+fn journal_path(profile: Option<&str>) -> Result<PathBuf> {
+    Ok(Config::get_config_dir(profile)?.join("rekey_journal.json"))
+}
+
+/// Loads the journal of an interrupted rekey, if any
+pub fn load_journal(profile: Option<&str>, lmk: &str) -> Result<Option<Journal>> {
+    let path = journal_path(profile)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&std::fs::read(&path)?).context("Failed to parse rekey journal")?;
+    let decrypted =
+        CryptoHandler::decrypt(&encrypted, lmk).context("Failed to decrypt rekey journal")?;
+    Ok(Some(
+        serde_json::from_slice(&decrypted).context("Rekey journal is corrupted")?,
+    ))
+}
+
+/// Encrypts the journal with the Local Master Key and writes it to disk
+fn save_journal(profile: Option<&str>, lmk: &str, journal: &Journal) -> Result<()> {
+    let encrypted = CryptoHandler::encrypt(&serde_json::to_vec(journal)?, lmk)?;
+    crate::config::write_private_file(&journal_path(profile)?, &serde_json::to_vec(&encrypted)?)
+}
+
+/// Removes the journal once a rekey has completed
+fn remove_journal(profile: Option<&str>) -> Result<()> {
+    let path = journal_path(profile)?;
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Fetches the new master key of an unfinished rekey from the repository, if any
+async fn load_pending_key(storage: &Storage, password: &str) -> Result<Option<String>> {
+    let Some(data) = storage.read_file(PENDING_KEY_PATH).await? else {
+        return Ok(None);
+    };
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&data).context("Failed to parse the pending rekey key")?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, password)
+        .context("Failed to decrypt the pending rekey key")?;
+    Ok(Some(
+        String::from_utf8(decrypted).context("Pending rekey key is not valid UTF-8")?,
+    ))
+}
+
+/// What a rekey will re-encrypt and who is affected, shown for confirmation before
+/// anything is changed
+pub struct Plan {
+    /// Number of stored keys
+    pub keys: usize,
+    /// Number of categories holding them
+    pub categories: usize,
+    /// Total size of the encrypted key blobs
+    pub bytes: u64,
+    /// Number of metadata documents and attachments
+    pub meta_files: usize,
+    /// Number of derived keys, whose values change with the master key
+    pub derived_keys: usize,
+    /// Recipients whose wrapped copy of the master key is replaced
+    pub recipients: Vec<String>,
+    /// Other GitHub collaborators on the vault repository, or None if the token cannot list them
+    pub collaborators: Option<Vec<String>>,
+    /// Items already migrated by an interrupted run that this run resumes
+    pub resumed: usize,
+}
+
+impl Plan {
+    /// Estimated number of GitHub API requests, including the verification pass
+    pub fn api_calls(&self) -> usize {
+        let remaining_keys = self.keys.saturating_sub(self.resumed);
+        // Key directories are listed twice and metadata files read again by the verification
+        // pass; the new master key is stored as pending first, and replaces the master key at
+        // the end
+        let verification = 2 * (self.categories + 1) + self.meta_files;
+        remaining_keys * CALLS_PER_KEY
+            + self.meta_files * CALLS_PER_META_FILE
+            + self.recipients.len() * CALLS_PER_KEY
+            + usize::from(self.derived_keys > 0) * CALLS_PER_KEY
+            + verification
+            + 3
+    }
+
+    /// Estimated duration in seconds
+    pub fn estimated_secs(&self) -> u64 {
+        (self.api_calls() as u64 * MILLIS_PER_CALL).div_ceil(1000)
+    }
+}
+
+/// Surveys the vault for a rekey without changing anything
+pub async fn plan(storage: &Storage, password: &str, master_key: &str) -> Result<Plan> {
+    let profile = storage.profile();
+    let lmk = Config::get_or_create_lmk_with_profile(profile, password)?;
+    let repo = storage.repo_full_name();
+    let resumed = match load_journal(profile, &lmk)? {
+        Some(j) if j.repo == repo => j.processed.len(),
+        _ => 0,
+    };
+
+    let entries = storage.list_all_keys().await?;
+    let categories: BTreeSet<&str> = entries
+        .iter()
+        .filter_map(|e| e.category.as_deref())
+        .collect();
+    let collaborators = match storage
+        .collaborators("all")
+        .try_collect::<Vec<String>>()
+        .await
+    {
+        Ok(logins) => Some(
+            logins
+                .into_iter()
+                .filter(|login| !login.eq_ignore_ascii_case(storage.login()))
+                .collect(),
+        ),
+        Err(_) => None,
+    };
+
+    Ok(Plan {
+        keys: entries.len(),
+        categories: categories.len(),
+        bytes: entries.iter().map(|e| e.data.len() as u64).sum(),
+        meta_files: meta_files(storage).await?.len(),
+        derived_keys: derived::load(storage, master_key).await?.keys.len(),
+        recipients: {
+            let mut names: Vec<String> = recipients::list(storage)
+                .await?
+                .into_iter()
+                .map(|r| r.name)
+                .collect();
+            for category in recipients::shared_categories(storage).await? {
+                for recipient in recipients::list_for_category(storage, &category).await? {
+                    names.push(format!("{} ({})", recipient.name, category));
+                }
+            }
+            names
+        },
+        collaborators,
+        resumed,
+    })
+}
+
+/// Re-encrypts the values, metadata and attachments of a top-level category that are not encrypted with
+/// its data key yet (written before category keys existed, or moved in from another
+/// category), in a single commit. Returns the paths of the re-encrypted keys.
+pub async fn reencrypt_category(
+    storage: &Storage,
+    master_key: &str,
+    category: &str,
+) -> Result<Vec<String>> {
+    let reencrypt = |data: &[u8], path: &str| -> Result<Option<Vec<u8>>> {
+        let blob: EncryptedBlob = serde_json::from_slice(data)
+            .with_context(|| format!("'{}' is not a valid encrypted blob", path))?;
+        if blob.category.as_deref() == Some(category) {
+            return Ok(None);
+        }
+        let plaintext = CryptoHandler::decrypt(&blob, master_key)?;
+        let encrypted = CryptoHandler::encrypt_for_category(&plaintext, master_key, category)?;
+        Ok(Some(serde_json::to_vec(&encrypted)?))
+    };
+
+    let mut changes = Vec::new();
+    let mut paths = Vec::new();
+    for entry in storage.list_all_keys().await? {
+        let top = entry.category.as_deref().and_then(|c| c.split('/').next());
+        if top != Some(category) {
+            continue;
+        }
+        let path = entry.display_path();
+        if let Some(data) = reencrypt(&entry.data, &path)? {
+            changes.extend(
+                storage
+                    .key_changes(&entry.name, entry.category.as_deref(), Some(&data))
+                    .await?,
+            );
+            paths.push(path.clone());
+        }
+        let key_meta =
+            meta::load(storage, master_key, &entry.name, entry.category.as_deref()).await?;
+        let mut files = vec![meta::meta_path(&entry.name, entry.category.as_deref())?];
+        files.extend(
+            key_meta
+                .attachments
+                .values()
+                .map(|attachment| meta::attachment_path(&attachment.id)),
+        );
+        for file in files {
+            if let Some(data) = storage.read_file(&file).await? {
+                if let Some(data) = reencrypt(&data, &file)? {
+                    changes.extend(storage.path_changes(&file, Some(&data)).await?);
+                }
+            }
+        }
+    }
+
+    if !changes.is_empty() {
+        storage
+            .commit_changes(&changes, &format!("Re-encrypt category: {}", category))
+            .await?;
+    }
+    Ok(paths)
+}
+
+/// Returns the repository paths of every key metadata document, attachment, service account
+/// and deleted key
+async fn meta_files(storage: &Storage) -> Result<Vec<String>> {
+    let mut paths = storage.list_files_recursive(meta::META_DIR).await?;
+    paths.extend(storage.list_files_recursive(meta::ATTACHMENTS_DIR).await?);
+    paths.extend(serviceaccount::account_files(storage).await?);
+    paths.extend(trash::item_files(storage).await?);
+    Ok(paths)
+}
+
+/// Re-encrypts every key, its metadata and attachments, the derived key registry and the
+/// recipients' wrapped keys under a
+/// new master key. Every blob is test-decrypted with the new key before the master key in the
+/// repository is replaced, and progress is journaled so an interrupted run can be resumed.
+/// The new key is stored in the repository (encrypted with the password) before anything is
+/// encrypted with it, so an interrupted run can also be finished from another machine.
+/// A cancellation request stops the run between items with the journal up to date.
+pub async fn run(storage: &Storage, password: &str, old_master_key: &str) -> Result<usize> {
+    if CryptoHandler::scoped_categories(old_master_key).is_some() {
+        return Err(anyhow::anyhow!(
+            "Rekeying needs the master key, which you have no access to."
+        ));
+    }
+    let profile = storage.profile();
+    let lmk = Config::get_or_create_lmk_with_profile(profile, password)?;
+    let repo = storage.repo_full_name();
+
+    // Nothing is encrypted with a new key before the repository holds it, so the repository's
+    // copy wins over a local journal made for another key
+    let pending = load_pending_key(storage, password).await?;
+    let mut journal = match load_journal(profile, &lmk)? {
+        Some(j) if j.repo == repo && pending.as_ref().is_none_or(|k| *k == j.new_master_key) => {
+            tracing::info!(
+                "Resuming an interrupted rekey ({} items already migrated).",
+                j.processed.len()
+            );
+            j
+        }
+        _ => Journal {
+            repo,
+            new_master_key: match &pending {
+                Some(key) => {
+                    tracing::info!(
+                        "Resuming an interrupted rekey started on this or another machine."
+                    );
+                    key.clone()
+                }
+                None => CryptoHandler::generate_master_key(),
+            },
+            processed: BTreeSet::new(),
+        },
+    };
+    // Persist the new key, locally and in the repository, before anything is encrypted with it
+    save_journal(profile, &lmk, &journal)?;
+    let new_master_key = journal.new_master_key.clone();
+    if pending.is_none() {
+        let encrypted = CryptoHandler::encrypt(new_master_key.as_bytes(), password)?;
+        storage
+            .put_file(
+                PENDING_KEY_PATH,
+                &serde_json::to_vec(&encrypted)?,
+                "Start rekey",
+            )
+            .await
+            .context("Failed to store the new master key")?;
+    }
+
+    // 1. Keys
+    let entries = storage.list_all_keys().await?;
+    let progress = Progress::new(entries.len(), "Re-encrypting keys");
+    for entry in &entries {
+        let path = entry.display_path();
+        progress.item(&path);
+        if journal.processed.contains(&path) {
+            progress.skip();
+            continue;
+        }
+        cancel::check()?;
+
+        let blob: EncryptedBlob = serde_json::from_slice(&entry.data)
+            .with_context(|| format!("Key '{}' is not a valid encrypted blob", path))?;
+        let plaintext = match CryptoHandler::decrypt(&blob, old_master_key) {
+            Ok(p) => Some(p),
+            // Re-encrypted by an earlier run that stopped before journaling it
+            Err(_) if CryptoHandler::decrypt(&blob, &new_master_key).is_ok() => None,
+            Err(_) => {
+                return Err(anyhow::anyhow!(
+                    "Key '{}' cannot be decrypted with the current master key. Run 'axkeystore verify' and fix it before rekeying.",
+                    path
+                ))
+            }
+        };
+
+        if let Some(plaintext) = plaintext {
+            // Values in categories move to their category's data key on the way
+            let encrypted =
+                vault::encrypt_value(&new_master_key, entry.category.as_deref(), &plaintext)?;
+            storage
+                .save_blob(
+                    &entry.name,
+                    &serde_json::to_vec(&encrypted)?,
+                    entry.category.as_deref(),
+                )
+                .await?;
+        }
+
+        journal.processed.insert(path.clone());
+        save_journal(profile, &lmk, &journal)?;
+        progress.succeed();
+    }
+    let summary = progress.finish();
+    tracing::info!(
+        "Keys: {} re-encrypted, {} already done by an earlier run.",
+        summary.succeeded,
+        summary.skipped
+    );
+
+    // 2. Derived key registry
+    if !journal.processed.contains(derived::REGISTRY_PATH)
+        && storage.get_file(derived::REGISTRY_PATH).await?.is_some()
+    {
+        let registry = match derived::load(storage, old_master_key).await {
+            Ok(r) => r,
+            Err(_) => derived::load(storage, &new_master_key).await?,
+        };
+        derived::save(
+            storage,
+            &new_master_key,
+            &registry,
+            "Re-encrypt derived key registry",
+        )
+        .await?;
+        journal.processed.insert(derived::REGISTRY_PATH.to_string());
+        save_journal(profile, &lmk, &journal)?;
+    }
+
+    // 3. Recipients
+    if !journal.processed.contains(RECIPIENTS_MARKER) {
+        for recipient in recipients::list(storage).await? {
+            recipients::add(
+                storage,
+                &recipient.name,
+                &recipient.public_key,
+                &new_master_key,
+            )
+            .await?;
+        }
+        // Category keys derive from the master key, so their recipients get the new ones
+        for category in recipients::shared_categories(storage).await? {
+            for recipient in recipients::list_for_category(storage, &category).await? {
+                recipients::add_for_category(
+                    storage,
+                    &recipient.name,
+                    &recipient.public_key,
+                    &new_master_key,
+                    &category,
+                )
+                .await?;
+            }
+        }
+        journal.processed.insert(RECIPIENTS_MARKER.to_string());
+        save_journal(profile, &lmk, &journal)?;
+    }
+
+    // 4. Key metadata and attachments
+    let paths = meta_files(storage).await?;
+    let progress = Progress::new(paths.len(), "Re-encrypting metadata");
+    for path in paths {
+        progress.item(&path);
+        if journal.processed.contains(&path) {
+            progress.skip();
+            continue;
+        }
+        cancel::check()?;
+        let data = storage
+            .read_file(&path)
+            .await?
+            .with_context(|| format!("'{}' disappeared during rekey", path))?;
+        let blob: EncryptedBlob = serde_json::from_slice(&data)
+            .with_context(|| format!("'{}' is not a valid encrypted blob", path))?;
+        match CryptoHandler::decrypt(&blob, old_master_key) {
+            Ok(plaintext) => {
+                let encrypted = match &blob.category {
+                    Some(category) => {
+                        CryptoHandler::encrypt_for_category(&plaintext, &new_master_key, category)?
+                    }
+                    None => CryptoHandler::encrypt_compressed(&plaintext, &new_master_key)?,
+                };
+                let changes = storage
+                    .path_changes(&path, Some(&serde_json::to_vec(&encrypted)?))
+                    .await?;
+                storage
+                    .apply_changes(&changes, &format!("Re-encrypt {}", path))
+                    .await?;
+            }
+            Err(_) if CryptoHandler::decrypt(&blob, &new_master_key).is_ok() => {}
+            Err(_) => {
+                return Err(anyhow::anyhow!(
+                    "'{}' cannot be decrypted with the current master key. Fix or remove it before rekeying.",
+                    path
+                ))
+            }
+        }
+        journal.processed.insert(path);
+        save_journal(profile, &lmk, &journal)?;
+        progress.succeed();
+    }
+    progress.finish();
+
+    // 5. Verification pass: nothing may be left that the new key cannot open
+    tracing::info!("Verifying that every key decrypts with the new master key...");
+    let mut failures = Vec::new();
+    let entries = storage.list_all_keys().await?;
+    for entry in &entries {
+        let ok = serde_json::from_slice::<EncryptedBlob>(&entry.data)
+            .ok()
+            .is_some_and(|b| CryptoHandler::decrypt(&b, &new_master_key).is_ok());
+        if !ok {
+            failures.push(entry.display_path());
+        }
+    }
+    if derived::load(storage, &new_master_key).await.is_err() {
+        failures.push(derived::REGISTRY_PATH.to_string());
+    }
+    for path in meta_files(storage).await? {
+        let ok = storage
+            .read_file(&path)
+            .await?
+            .and_then(|data| serde_json::from_slice::<EncryptedBlob>(&data).ok())
+            .is_some_and(|b| CryptoHandler::decrypt(&b, &new_master_key).is_ok());
+        if !ok {
+            failures.push(path);
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Verification failed for: {}. The master key was not changed; run 'axkeystore rekey' again to resume.",
+            failures.join(", ")
+        ));
+    }
+
+    // Every key now holds its own blob, so shared blobs (encrypted with the old key) are unused
+    let mut changes = Vec::new();
+    for path in storage
+        .list_files_recursive(crate::storage::DEDUP_DIR)
+        .await?
+    {
+        changes.extend(storage.path_changes(&path, None).await?);
+    }
+    if !changes.is_empty() {
+        storage
+            .apply_changes(&changes, "Remove shared blobs of the old master key")
+            .await?;
+    }
+
+    // 6. Flip the master key only after everything verified
+    cancel::check()?;
+    let encrypted = CryptoHandler::encrypt(new_master_key.as_bytes(), password)?;
+    storage
+        .save_master_key_blob(&serde_json::to_vec(&encrypted)?)
+        .await?;
+    storage
+        .delete_file(PENDING_KEY_PATH, "Finish rekey")
+        .await?;
+    cache::clear(profile, Some(cache::CacheKind::DerivedKeys))?;
+    remove_journal(profile)?;
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        assert!(load_journal(None, "lmk").unwrap().is_none());
+
+        let mut journal = Journal {
+            repo: "octocat/vault".to_string(),
+            new_master_key: "new-key".to_string(),
+            processed: BTreeSet::new(),
+        };
+        journal.processed.insert("cloud/api".to_string());
+        save_journal(None, "lmk", &journal).unwrap();
+
+        // The journal holds the new master key, so it must not be readable in plain text
+        let raw = std::fs::read_to_string(journal_path(None).unwrap()).unwrap();
+        assert!(!raw.contains("new-key"));
+
+        let loaded = load_journal(None, "lmk").unwrap().unwrap();
+        assert_eq!(loaded.repo, "octocat/vault");
+        assert_eq!(loaded.new_master_key, "new-key");
+        assert!(loaded.processed.contains("cloud/api"));
+        assert!(load_journal(None, "wrong").is_err());
+
+        remove_journal(None).unwrap();
+        assert!(load_journal(None, "lmk").unwrap().is_none());
+
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[test]
+    fn test_plan_estimate() {
+        let mut plan = Plan {
+            keys: 10,
+            categories: 3,
+            bytes: 4096,
+            meta_files: 2,
+            derived_keys: 1,
+            recipients: vec!["alice".to_string()],
+            collaborators: None,
+            resumed: 0,
+        };
+        // 10 keys, 2 metadata files, 1 recipient, the registry, verification, and storing,
+        // flipping and removing the pending key
+        assert_eq!(plan.api_calls(), 20 + 14 + 2 + 2 + 10 + 3);
+        assert_eq!(plan.estimated_secs(), 21);
+
+        // Keys migrated by an interrupted run are not written again
+        plan.resumed = 10;
+        assert_eq!(plan.api_calls(), 31);
+    }
+}
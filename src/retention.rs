@@ -0,0 +1,156 @@
+use crate::storage::{Storage, TreeChange};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Repository path of the retention settings. They are not secret, so they are not encrypted.
+const SETTINGS_PATH: &str = ".axkeystore/retention.json";
+
+/// How much history of a key to keep. A version is kept while it is one of the latest
+/// `versions`, or while it was current within the last `max_age` seconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rule {
+    /// Number of latest versions to keep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub versions: Option<usize>,
+    /// Age (in seconds) of the history to keep
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+/// Retention rules for the vault and for individual categories (with their subcategories)
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Settings {
+    /// Rule for keys not covered by a category rule (None keeps their whole history)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Rule>,
+    /// Rules by category
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub categories: BTreeMap<String, Rule>,
+}
+
+impl Settings {
+    /// Returns true if no rule is set, so the whole history is kept
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.categories.is_empty()
+    }
+
+    /// Returns the rule for a category: its own, its closest parent's, or the default
+    pub fn rule_for(&self, category: Option<&str>) -> Option<&Rule> {
+        let mut category = category.map(|c| c.trim_matches('/'));
+        while let Some(current) = category {
+            if let Some(rule) = self.categories.get(current) {
+                return Some(rule);
+            }
+            category = current.rfind('/').map(|i| &current[..i]);
+        }
+        self.default.as_ref()
+    }
+}
+
+/// Returns how many of a key's versions a rule keeps, given when each version was committed
+/// (Unix seconds, newest first). The current version is always kept.
+pub fn kept_versions(rule: &Rule, dates: &[u64], now: u64) -> usize {
+    if dates.is_empty() {
+        return 0;
+    }
+    if rule.versions.is_none() && rule.max_age.is_none() {
+        return dates.len();
+    }
+    let mut keep = rule.versions.unwrap_or(0);
+    if let Some(age) = rule.max_age {
+        // Versions committed within the window, plus the one that was current when it began
+        let recent = dates
+            .iter()
+            .take_while(|date| now.saturating_sub(**date) < age)
+            .count();
+        keep = keep.max(recent + 1);
+    }
+    keep.clamp(1, dates.len())
+}
+
+/// Returns the position (in a branch's commits, newest first) of the newest commit that can be
+/// squashed, given the positions of the commits that must stay distinct. The latest commit
+/// always stays. None if nothing older than those commits is left to squash.
+pub fn squash_point(kept: impl IntoIterator<Item = usize>, total: usize) -> Option<usize> {
+    let point = kept.into_iter().max().unwrap_or(0) + 1;
+    // Squashing the root commit alone changes nothing
+    (point + 1 < total).then_some(point)
+}
+
+/// Loads the retention settings (empty if none are saved)
+pub async fn load_settings(storage: &Storage) -> Result<Settings> {
+    match storage.read_file(SETTINGS_PATH).await? {
+        Some(data) => serde_json::from_slice(&data).context("Retention settings are corrupted"),
+        None => Ok(Settings::default()),
+    }
+}
+
+/// Builds the changes that save the retention settings
+pub async fn settings_changes(storage: &Storage, settings: &Settings) -> Result<Vec<TreeChange>> {
+    storage
+        .path_changes(SETTINGS_PATH, Some(&serde_json::to_vec_pretty(settings)?))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_for() {
+        let mut settings = Settings::default();
+        assert!(settings.is_empty());
+        assert_eq!(settings.rule_for(Some("prod")), None);
+
+        let default = Rule {
+            versions: Some(20),
+            max_age: None,
+        };
+        let prod = Rule {
+            versions: Some(5),
+            max_age: Some(86400),
+        };
+        settings.default = Some(default);
+        settings.categories.insert("prod".to_string(), prod);
+        assert_eq!(settings.rule_for(Some("prod/db")), Some(&prod));
+        assert_eq!(settings.rule_for(Some("prod")), Some(&prod));
+        assert_eq!(settings.rule_for(Some("production")), Some(&default));
+        assert_eq!(settings.rule_for(None), Some(&default));
+    }
+
+    #[test]
+    fn test_kept_versions() {
+        let dates = [1000, 900, 500, 100];
+        let by_count = Rule {
+            versions: Some(2),
+            max_age: None,
+        };
+        assert_eq!(kept_versions(&by_count, &dates, 1000), 2);
+        assert_eq!(kept_versions(&by_count, &dates[..1], 1000), 1);
+
+        // Two versions are younger than 200s, plus the one current when the window began
+        let by_age = Rule {
+            versions: None,
+            max_age: Some(200),
+        };
+        assert_eq!(kept_versions(&by_age, &dates, 1050), 3);
+        assert_eq!(kept_versions(&by_age, &dates, 5000), 1);
+
+        let both = Rule {
+            versions: Some(2),
+            max_age: Some(200),
+        };
+        assert_eq!(kept_versions(&both, &dates, 1050), 3);
+        assert_eq!(kept_versions(&Rule::default(), &dates, 1050), 4);
+        assert_eq!(kept_versions(&by_count, &[], 1050), 0);
+    }
+
+    #[test]
+    fn test_squash_point() {
+        assert_eq!(squash_point([0, 3, 1], 10), Some(4));
+        assert_eq!(squash_point([], 10), Some(1));
+        assert_eq!(squash_point([8], 10), None);
+        assert_eq!(squash_point([], 2), None);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::index::IndexEntry;
+use anyhow::{Context, Result};
+
+/// How the query is matched against names, categories, tags and descriptions
+pub enum Matcher {
+    /// Case-insensitive substring
+    Text(String),
+    /// Case-insensitive regular expression
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    /// Creates a matcher for a query
+    pub fn new(query: &str, is_regex: bool) -> Result<Self> {
+        if is_regex {
+            let regex = regex::RegexBuilder::new(query)
+                .case_insensitive(true)
+                .build()
+                .with_context(|| format!("Invalid regular expression '{}'", query))?;
+            Ok(Self::Regex(regex))
+        } else {
+            Ok(Self::Text(query.to_lowercase()))
+        }
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Self::Text(query) => text.to_lowercase().contains(query.as_str()),
+            Self::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    /// Returns which part of the entry matched ('name', 'category', 'tag' or 'description')
+    pub fn match_entry(&self, entry: &IndexEntry) -> Option<&'static str> {
+        if self.is_match(&entry.name) {
+            Some("name")
+        } else if entry.category.as_deref().is_some_and(|c| self.is_match(c)) {
+            Some("category")
+        } else if entry.tags.iter().any(|t| self.is_match(t)) {
+            Some("tag")
+        } else if entry
+            .description
+            .as_deref()
+            .is_some_and(|d| self.is_match(d))
+        {
+            Some("description")
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the entries that match, optionally restricted to a category, sorted by path
+pub fn search<'a>(
+    entries: &'a [IndexEntry],
+    matcher: &Matcher,
+    category: Option<&str>,
+) -> Vec<(&'a IndexEntry, &'static str)> {
+    let mut found: Vec<(&IndexEntry, &'static str)> = entries
+        .iter()
+        .filter(|e| category.is_none_or(|c| e.in_category(c)))
+        .filter_map(|e| matcher.match_entry(e).map(|field| (e, field)))
+        .collect();
+    found.sort_by_key(|(e, _)| e.display_path());
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<IndexEntry> {
+        vec![
+            IndexEntry {
+                category: Some("payments/stripe".to_string()),
+                name: "live-key".to_string(),
+                tags: vec!["prod".to_string()],
+                ..Default::default()
+            },
+            IndexEntry {
+                category: Some("cloud/aws".to_string()),
+                name: "api".to_string(),
+                description: Some("Stripe webhook relay".to_string()),
+                ..Default::default()
+            },
+            IndexEntry {
+                category: None,
+                name: "github-token".to_string(),
+                tags: vec!["ci".to_string()],
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn paths(found: &[(&IndexEntry, &str)]) -> Vec<String> {
+        found.iter().map(|(e, _)| e.display_path()).collect()
+    }
+
+    #[test]
+    fn test_text_search() {
+        let entries = entries();
+        let matcher = Matcher::new("STRIPE", false).unwrap();
+        let found = search(&entries, &matcher, None);
+        assert_eq!(
+            paths(&found),
+            vec!["cloud/aws/api", "payments/stripe/live-key"]
+        );
+        assert_eq!(found[0].1, "description");
+        assert_eq!(found[1].1, "category");
+
+        let found = search(&entries, &Matcher::new("ci", false).unwrap(), None);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1, "tag");
+
+        let scoped = search(&entries, &matcher, Some("payments"));
+        assert_eq!(paths(&scoped), vec!["payments/stripe/live-key"]);
+    }
+
+    #[test]
+    fn test_regex_search() {
+        let entries = entries();
+        let found = search(
+            &entries,
+            &Matcher::new("^(api|.*-token)$", true).unwrap(),
+            None,
+        );
+        assert_eq!(paths(&found), vec!["cloud/aws/api", "github-token"]);
+        assert!(Matcher::new("(", true).is_err());
+    }
+}
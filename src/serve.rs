@@ -0,0 +1,350 @@
+use crate::audit::AuditEvent;
+use crate::storage::MAX_VALUE_SIZE;
+use crate::vault::Vault;
+use crate::{cancel, redact};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Prefix of key URLs; the rest of the path is the key path ('category/name')
+const KEYS_PREFIX: &str = "/v1/keys/";
+
+/// Largest request line or header accepted, to bound the memory a connection can use
+const MAX_LINE_SIZE: usize = 8 * 1024;
+
+/// Most headers accepted in a request
+const MAX_HEADERS: usize = 64;
+
+/// How long a client may take to send its whole request, so slow or idle connections do
+/// not pile up
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The parts of an HTTP request the API looks at
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub authorization: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// An HTTP response
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, serde_json::json!({ "error": message }))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// Serializes the response; every connection serves a single request
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len()
+        );
+        if self.status == 401 {
+            out.push_str("WWW-Authenticate: Bearer\r\n");
+        }
+        out.push_str("\r\n");
+        let mut bytes = out.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+/// What the API does with a request, decided before the vault is touched
+#[derive(Debug, PartialEq)]
+enum Action<'a> {
+    Health,
+    Get(&'a str),
+    Put(&'a str),
+    Reject(Response),
+}
+
+/// Generates a random API token
+pub fn generate_token() -> String {
+    use rand::Rng;
+    let bytes: [u8; 32] = rand::rng().random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks an Authorization header against the API token in constant time
+fn authorized(header: Option<&str>, token: &str) -> bool {
+    let presented = match header.and_then(|h| h.strip_prefix("Bearer ")) {
+        Some(presented) => presented.trim(),
+        None => return false,
+    };
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn plan<'a>(request: &'a Request, token: &str, read_only: bool) -> Action<'a> {
+    // Health checks carry no secrets, so containers can probe without the token
+    if request.path == "/v1/health" {
+        return Action::Health;
+    }
+    if !authorized(request.authorization.as_deref(), token) {
+        return Action::Reject(Response::error(401, "Missing or invalid bearer token"));
+    }
+    let path = match request.path.strip_prefix(KEYS_PREFIX) {
+        Some(path) if !path.trim_matches('/').is_empty() => path.trim_matches('/'),
+        _ => return Action::Reject(Response::error(404, "Unknown endpoint")),
+    };
+    match request.method.as_str() {
+        "GET" => Action::Get(path),
+        "PUT" if !read_only => Action::Put(path),
+        "PUT" => Action::Reject(Response::error(
+            405,
+            "The server is read-only; restart it with --allow-write to store keys",
+        )),
+        _ => Action::Reject(Response::error(405, "Method not allowed")),
+    }
+}
+
+/// Reads one request: the request line, the headers, and a body of Content-Length bytes
+pub async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Request> {
+    let request_line = read_line(reader).await?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(anyhow::anyhow!("Malformed request line")),
+    };
+    // The query string is not used by any endpoint
+    let path = target.split('?').next().unwrap_or_default().to_string();
+
+    let mut authorization = None;
+    let mut content_length = 0;
+    for _ in 0..=MAX_HEADERS {
+        let line = read_line(reader).await?;
+        if line.is_empty() {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await?;
+            return Ok(Request {
+                method,
+                path,
+                authorization,
+                body,
+            });
+        }
+        let (name, value) = match line.split_once(':') {
+            Some(header) => header,
+            None => return Err(anyhow::anyhow!("Malformed header")),
+        };
+        if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.trim().to_string());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.trim().parse().context("Invalid Content-Length")?;
+            if content_length > MAX_VALUE_SIZE {
+                return Err(anyhow::anyhow!("Request body too large"));
+            }
+        }
+    }
+    Err(anyhow::anyhow!("Too many headers"))
+}
+
+/// Reads a CRLF-terminated line without its terminator
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<String> {
+    let mut line = Vec::new();
+    let read = reader
+        .take(MAX_LINE_SIZE as u64)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if read == 0 {
+        return Err(anyhow::anyhow!("Connection closed"));
+    }
+    if !line.ends_with(b"\n") {
+        return Err(anyhow::anyhow!("Line too long"));
+    }
+    let line = String::from_utf8(line).context("Request is not valid UTF-8")?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+async fn respond(vault: &Vault, token: &str, read_only: bool, request: &Request) -> Response {
+    match plan(request, token, read_only) {
+        Action::Health => Response::json(200, serde_json::json!({ "status": "ok" })),
+        Action::Get(path) => match vault.get(path).await {
+            Ok(Some(value)) => Response {
+                status: 200,
+                content_type: "application/octet-stream",
+                body: value,
+            },
+            Ok(None) => Response::error(404, "Key not found"),
+            Err(e) => Response::error(500, &redact::redact(&e.to_string())),
+        },
+        Action::Put(path) => match vault
+            .put_audited(path, &request.body, &AuditEvent::new("serve", path))
+            .await
+        {
+            Ok(()) => Response {
+                status: 204,
+                content_type: "application/json",
+                body: Vec::new(),
+            },
+            Err(e) => Response::error(400, &redact::redact(&e.to_string())),
+        },
+        Action::Reject(response) => response,
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    vault: &Vault,
+    token: &str,
+    read_only: bool,
+) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut reader)).await {
+        Ok(Ok(request)) => respond(vault, token, read_only, &request).await,
+        Ok(Err(e)) => Response::error(400, &redact::redact(&e.to_string())),
+        Err(_) => Response::error(408, "Request not received in time"),
+    };
+    write.write_all(&response.to_bytes()).await?;
+    write.shutdown().await?;
+    Ok(())
+}
+
+/// Serves the vault over HTTP until cancellation is requested. Key values are returned
+/// as-is by `GET /v1/keys/<path>`; unless the server is read-only, `PUT /v1/keys/<path>`
+/// stores the request body, subject to the category's policy, and records it in the audit
+/// log. Every key request needs `Authorization: Bearer <token>`.
+pub async fn serve(vault: Vault, listen: &str, token: String, read_only: bool) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+    serve_listener(listener, vault, token, read_only).await
+}
+
+/// Serves the vault like `serve`, on a listener that is already bound (e.g. to an ephemeral
+/// port)
+pub async fn serve_listener(
+    listener: TcpListener,
+    vault: Vault,
+    token: String,
+    read_only: bool,
+) -> Result<()> {
+    let state = Arc::new((vault, token));
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = cancel::requested() => return Ok(()),
+        };
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            let (vault, token) = &*state;
+            let _ = handle_connection(stream, vault, token, read_only).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, authorization: Option<&str>) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            authorization: authorization.map(|a| a.to_string()),
+            body: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let raw = b"PUT /v1/keys/prod/db?x=1 HTTP/1.1\r\nHost: localhost\r\nauthorization: Bearer abc\r\nContent-Length: 7\r\n\r\nhunter2";
+        let mut reader = BufReader::new(&raw[..]);
+        let parsed = read_request(&mut reader).await.unwrap();
+        assert_eq!(parsed.method, "PUT");
+        assert_eq!(parsed.path, "/v1/keys/prod/db");
+        assert_eq!(parsed.authorization.as_deref(), Some("Bearer abc"));
+        assert_eq!(parsed.body, b"hunter2");
+
+        let mut reader = BufReader::new(&b"garbage\r\n\r\n"[..]);
+        assert!(read_request(&mut reader).await.is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let token = "secret-token";
+        let auth = Some("Bearer secret-token");
+
+        assert_eq!(
+            plan(&request("GET", "/v1/health", None), token, true),
+            Action::Health
+        );
+        assert_eq!(
+            plan(&request("GET", "/v1/keys/prod/db", auth), token, true),
+            Action::Get("prod/db")
+        );
+        assert!(matches!(
+            plan(
+                &request("GET", "/v1/keys/prod/db", Some("Bearer wrong")),
+                token,
+                true
+            ),
+            Action::Reject(Response { status: 401, .. })
+        ));
+        assert!(matches!(
+            plan(&request("GET", "/v1/keys/prod/db", None), token, true),
+            Action::Reject(Response { status: 401, .. })
+        ));
+        assert!(matches!(
+            plan(&request("PUT", "/v1/keys/prod/db", auth), token, true),
+            Action::Reject(Response { status: 405, .. })
+        ));
+        assert_eq!(
+            plan(&request("PUT", "/v1/keys/prod/db", auth), token, false),
+            Action::Put("prod/db")
+        );
+        assert!(matches!(
+            plan(&request("GET", "/v1/keys/", auth), token, true),
+            Action::Reject(Response { status: 404, .. })
+        ));
+    }
+
+    #[test]
+    fn test_token() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+        assert!(authorized(Some(&format!("Bearer {}", token)), &token));
+        assert!(!authorized(Some(&token), &token));
+        assert!(!authorized(Some("Bearer "), &token));
+    }
+}
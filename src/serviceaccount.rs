@@ -0,0 +1,313 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::notify::pattern_matches;
+use crate::storage::{Storage, TreeChange};
+use crate::timeutil;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Repository directory holding the service accounts and their key snapshots
+const ACCOUNTS_DIR: &str = ".axkeystore/service-accounts";
+
+/// Version of the config bundle format
+const BUNDLE_FORMAT: u32 = 1;
+
+/// A headless consumer that can read the keys of some categories without the master
+/// password. It holds its own key, which only decrypts a snapshot of those keys.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServiceAccount {
+    pub name: String,
+    /// Key path patterns the account can read (e.g. 'app/ci/**')
+    pub categories: Vec<String>,
+    /// When the account was created (Unix seconds)
+    pub created: u64,
+    /// When the account stops working (Unix seconds)
+    pub expires: u64,
+    /// The account's key, which encrypts its snapshot
+    key: String,
+}
+
+/// The values a service account can read, encrypted with the account's key
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Snapshot {
+    /// When the account stops working (Unix seconds)
+    pub expires: u64,
+    /// Base64 encoded values, by key path
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    /// Returns the value of a key path ('category/name'), or None if the account cannot read it
+    pub fn value(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        match self.keys.get(path.trim_matches('/')) {
+            Some(encoded) => Ok(Some(
+                BASE64
+                    .decode(encoded)
+                    .context("Service account keys are corrupted")?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Everything a headless consumer needs to read a service account's keys. It is written
+/// encrypted with a password of its own.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Bundle {
+    /// Bundle format version
+    pub format: u32,
+    /// Name of the service account
+    pub name: String,
+    /// The repository of the vault ('owner/repo')
+    pub repo: String,
+    /// GitHub token used to read the repository
+    pub token: String,
+    /// The account's key
+    pub key: String,
+    /// When the account stops working (Unix seconds)
+    pub expires: u64,
+}
+
+/// Returns the repository path of a service account
+fn account_path(name: &str) -> String {
+    format!("{}/{}.json", ACCOUNTS_DIR, name)
+}
+
+/// Returns the repository path of a service account's key snapshot
+fn snapshot_path(name: &str) -> String {
+    format!("{}/{}.keys.json", ACCOUNTS_DIR, name)
+}
+
+/// Validates that a service account name contains only alphabets, numbers, underscores, and
+/// dashes
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(anyhow::anyhow!(
+            "Service account name '{}' is invalid. Only alphabets, numbers, '_' and '-' are allowed.",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the values (by key path) that match any of the given patterns
+pub fn select<'a>(
+    patterns: &[String],
+    values: &'a [(String, Vec<u8>)],
+) -> BTreeMap<&'a str, &'a [u8]> {
+    values
+        .iter()
+        .filter(|(path, _)| patterns.iter().any(|p| pattern_matches(p, path)))
+        .map(|(path, value)| (path.as_str(), value.as_slice()))
+        .collect()
+}
+
+impl ServiceAccount {
+    /// Creates a service account with a new key
+    pub fn new(name: &str, categories: Vec<String>, now: u64, ttl_secs: u64) -> Result<Self> {
+        validate_name(name)?;
+        if categories.is_empty() {
+            return Err(anyhow::anyhow!(
+                "A service account needs at least one category"
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            categories,
+            created: now,
+            expires: now.saturating_add(ttl_secs),
+            key: CryptoHandler::generate_master_key(),
+        })
+    }
+
+    /// Returns true if the account has expired
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.expires
+    }
+
+    /// Builds the bundle that gives a headless consumer access to this account
+    pub fn bundle(&self, repo: &str, token: &str) -> Bundle {
+        Bundle {
+            format: BUNDLE_FORMAT,
+            name: self.name.clone(),
+            repo: repo.to_string(),
+            token: token.to_string(),
+            key: self.key.clone(),
+            expires: self.expires,
+        }
+    }
+
+    /// Builds the changes that save the account and a fresh snapshot of the values it can read
+    /// (from every decrypted value of the vault). Returns them with the number of keys in the
+    /// snapshot.
+    pub async fn save_changes(
+        &self,
+        storage: &Storage,
+        master_key: &str,
+        values: &[(String, Vec<u8>)],
+    ) -> Result<(Vec<TreeChange>, usize)> {
+        let snapshot = Snapshot {
+            expires: self.expires,
+            keys: select(&self.categories, values)
+                .into_iter()
+                .map(|(path, value)| (path.to_string(), BASE64.encode(value)))
+                .collect(),
+        };
+        let count = snapshot.keys.len();
+
+        let account = CryptoHandler::encrypt(&serde_json::to_vec(self)?, master_key)?;
+        let snapshot =
+            CryptoHandler::encrypt_compressed(&serde_json::to_vec(&snapshot)?, &self.key)?;
+        let mut changes = storage
+            .path_changes(
+                &account_path(&self.name),
+                Some(&serde_json::to_vec(&account)?),
+            )
+            .await?;
+        changes.extend(
+            storage
+                .path_changes(
+                    &snapshot_path(&self.name),
+                    Some(&serde_json::to_vec(&snapshot)?),
+                )
+                .await?,
+        );
+        Ok((changes, count))
+    }
+}
+
+/// Lists the repository paths of the service accounts (encrypted with the master key), leaving
+/// out their snapshots
+pub async fn account_files(storage: &Storage) -> Result<Vec<String>> {
+    Ok(storage
+        .list_files_recursive(ACCOUNTS_DIR)
+        .await?
+        .into_iter()
+        .filter(|path| path.ends_with(".json") && !path.ends_with(".keys.json"))
+        .collect())
+}
+
+/// Lists the service accounts of a vault
+pub async fn list(storage: &Storage, master_key: &str) -> Result<Vec<ServiceAccount>> {
+    let mut accounts = Vec::new();
+    for path in account_files(storage).await? {
+        let data = match storage.read_file(&path).await? {
+            Some(data) => data,
+            None => continue,
+        };
+        let encrypted: EncryptedBlob = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse service account '{}'", path))?;
+        let decrypted = CryptoHandler::decrypt(&encrypted, master_key)
+            .with_context(|| format!("Failed to decrypt service account '{}'", path))?;
+        accounts.push(
+            serde_json::from_slice(&decrypted)
+                .with_context(|| format!("Service account '{}' is corrupted", path))?,
+        );
+    }
+    Ok(accounts)
+}
+
+/// Builds the changes that remove a service account and its snapshot (empty if it does not
+/// exist)
+pub async fn removal_changes(storage: &Storage, name: &str) -> Result<Vec<TreeChange>> {
+    let mut changes = storage.path_changes(&account_path(name), None).await?;
+    changes.extend(storage.path_changes(&snapshot_path(name), None).await?);
+    Ok(changes)
+}
+
+/// Writes a bundle, encrypted with its password, to a file readable only by the current user
+pub fn write_bundle(path: &Path, bundle: &Bundle, password: &str) -> Result<()> {
+    let encrypted = CryptoHandler::encrypt(&serde_json::to_vec(bundle)?, password)?;
+    crate::config::write_private_file(path, &serde_json::to_vec_pretty(&encrypted)?)
+}
+
+/// Reads and decrypts a bundle
+pub fn read_bundle(path: &Path, password: &str) -> Result<Bundle> {
+    let data =
+        std::fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let encrypted: EncryptedBlob = serde_json::from_slice(&data)
+        .with_context(|| format!("'{}' is not a service account bundle", path.display()))?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, password)
+        .map_err(|_| anyhow::anyhow!("Incorrect bundle password or corrupted bundle."))?;
+    let bundle: Bundle =
+        serde_json::from_slice(&decrypted).context("Service account bundle is corrupted")?;
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(anyhow::anyhow!(
+            "Unsupported bundle format {} (expected {})",
+            bundle.format,
+            BUNDLE_FORMAT
+        ));
+    }
+    Ok(bundle)
+}
+
+/// Fetches the snapshot of the keys a bundle's service account can read. Fails if the
+/// account was revoked or has expired.
+pub async fn fetch(bundle: &Bundle) -> Result<Snapshot> {
+    let storage = Storage::with_token(None, &bundle.repo, bundle.token.clone()).await?;
+    let data = storage
+        .read_file(&snapshot_path(&bundle.name))
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Service account '{}' has been revoked", bundle.name))?;
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(&data).context("Failed to parse service account keys")?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, &bundle.key)
+        .context("Failed to decrypt service account keys")?;
+    let snapshot: Snapshot =
+        serde_json::from_slice(&decrypted).context("Service account keys are corrupted")?;
+    if timeutil::now_unix() >= snapshot.expires {
+        return Err(anyhow::anyhow!(
+            "Service account '{}' expired on {}",
+            bundle.name,
+            timeutil::format_rfc3339(snapshot.expires)
+        ));
+    }
+    Ok(snapshot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let values: Vec<(String, Vec<u8>)> = ["app/ci/npm", "app/ci/deploy/ssh", "app/prod/db"]
+            .iter()
+            .map(|path| (path.to_string(), path.as_bytes().to_vec()))
+            .collect();
+        let selected = select(&["app/ci/**".to_string()], &values);
+        assert_eq!(
+            selected.keys().copied().collect::<Vec<_>>(),
+            vec!["app/ci/deploy/ssh", "app/ci/npm"]
+        );
+        assert!(select(&["other/**".to_string()], &values).is_empty());
+    }
+
+    #[test]
+    fn test_new() {
+        let account = ServiceAccount::new("ci-reader", vec!["app/ci/**".into()], 1000, 60).unwrap();
+        assert_eq!(account.expires, 1060);
+        assert!(!account.is_expired(1059));
+        assert!(account.is_expired(1060));
+        assert!(ServiceAccount::new("ci reader", vec!["app/**".into()], 0, 60).is_err());
+        assert!(ServiceAccount::new("ci-reader", Vec::new(), 0, 60).is_err());
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ci-reader.axbundle");
+        let account = ServiceAccount::new("ci-reader", vec!["app/ci/**".into()], 0, 60).unwrap();
+        let bundle = account.bundle("octocat/vault", "ghp_token");
+
+        write_bundle(&path, &bundle, "bundle password").unwrap();
+        assert_eq!(read_bundle(&path, "bundle password").unwrap(), bundle);
+        assert!(read_bundle(&path, "wrong password").is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use crate::apply::{self, Charset};
+use crate::config::Config;
+use crate::storage::{self, Storage};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+/// Profile to use when --profile is left out ('default' for the root profile)
+pub const PROFILE_VAR: &str = "AXKEYSTORE_PROFILE";
+
+/// Repository of the vault ('owner/name', or 'name' for one of your own), instead of the
+/// profile's
+pub const REPO_VAR: &str = "AXKEYSTORE_REPO";
+
+/// Category to use when --category is left out, instead of the profile's category setting
+pub const CATEGORY_VAR: &str = "AXKEYSTORE_CATEGORY";
+
+/// How 'get' prints values when --format is left out, instead of the profile's output
+/// setting
+pub const OUTPUT_VAR: &str = "AXKEYSTORE_OUTPUT";
+
+/// Settings of a profile that 'config set' writes, with what each one controls
+pub const KEYS: [(&str, &str); 6] = [
+    (
+        "category",
+        "Category of 'store', 'get' and 'export' when --category is left out",
+    ),
+    (
+        "output",
+        "How 'get' prints values when --format is left out: raw, base64 or hex",
+    ),
+    (
+        "export-format",
+        "Format of 'export' when --format is left out: json, csv, docker-secrets or pass",
+    ),
+    (
+        "generate-length",
+        "Length of values generated by 'store --generate' (6-1024)",
+    ),
+    (
+        "generate-charset",
+        "Characters of generated values: alphanumeric, hex or symbols",
+    ),
+    ("branch", "Branch of the repository that holds the vault"),
+];
+
+/// Returns an error unless the key is one of [`KEYS`]
+fn check_key(key: &str) -> Result<()> {
+    if KEYS.iter().any(|(name, _)| *name == key) {
+        return Ok(());
+    }
+    let names: Vec<&str> = KEYS.iter().map(|(name, _)| *name).collect();
+    Err(anyhow::anyhow!(
+        "Unknown setting '{}'. Settings are: {}",
+        key,
+        names.join(", ")
+    ))
+}
+
+/// Parses a value of a clap value enum, naming the accepted values if it is none of them
+fn parse_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T> {
+    T::from_str(value, true).map_err(|_| {
+        let names: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect();
+        anyhow::anyhow!(
+            "Invalid value '{}' for {}. Expected one of: {}",
+            value,
+            key,
+            names.join(", ")
+        )
+    })
+}
+
+/// Name of a value enum's variant, as given on the command line
+fn enum_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the value of a setting, or None if it is not set
+pub fn get(config: &Config, key: &str) -> Result<Option<String>> {
+    check_key(key)?;
+    let defaults = &config.defaults;
+    Ok(match key {
+        "category" => defaults.category.clone(),
+        "output" => defaults.output.as_ref().map(enum_name),
+        "export-format" => defaults.export_format.as_ref().map(enum_name),
+        "generate-length" => defaults.generate_length.map(|l| l.to_string()),
+        "generate-charset" => defaults.generate_charset.as_ref().map(enum_name),
+        _ => config.branch.clone(),
+    })
+}
+
+/// Sets a setting after checking its value. An output of 'raw' is the same as none.
+pub fn set(config: &mut Config, key: &str, value: &str) -> Result<()> {
+    check_key(key)?;
+    let value = value.trim();
+    let defaults = &mut config.defaults;
+    match key {
+        "category" => {
+            defaults.category = match Storage::validate_category(Some(value))? {
+                Some(category) => Some(category),
+                None => return Err(anyhow::anyhow!("The category cannot be empty")),
+            }
+        }
+        "output" if value.eq_ignore_ascii_case("raw") => defaults.output = None,
+        "output" => defaults.output = Some(parse_enum(key, value)?),
+        "export-format" => defaults.export_format = Some(parse_enum(key, value)?),
+        "generate-length" => {
+            let length: usize = value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid length '{}'", value))?;
+            if !(apply::MIN_GENERATED_LENGTH..=apply::MAX_GENERATED_LENGTH).contains(&length) {
+                return Err(anyhow::anyhow!(
+                    "The length must be between {} and {}",
+                    apply::MIN_GENERATED_LENGTH,
+                    apply::MAX_GENERATED_LENGTH
+                ));
+            }
+            defaults.generate_length = Some(length);
+        }
+        "generate-charset" => defaults.generate_charset = Some(parse_enum::<Charset>(key, value)?),
+        _ => config.branch = Some(storage::validate_branch(value)?),
+    }
+    Ok(())
+}
+
+/// Clears a setting, so the command's own default applies again
+pub fn unset(config: &mut Config, key: &str) -> Result<()> {
+    check_key(key)?;
+    let defaults = &mut config.defaults;
+    match key {
+        "category" => defaults.category = None,
+        "output" => defaults.output = None,
+        "export-format" => defaults.export_format = None,
+        "generate-length" => defaults.generate_length = None,
+        "generate-charset" => defaults.generate_charset = None,
+        _ => config.branch = None,
+    }
+    Ok(())
+}
+
+/// Returns an environment variable, treating an empty one as unset
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Returns the profile named by AXKEYSTORE_PROFILE, if set. 'default' names the root profile.
+pub fn env_profile() -> Result<Option<String>> {
+    match env_var(PROFILE_VAR) {
+        Some(name) if name.eq_ignore_ascii_case("default") => Ok(Some("default".to_string())),
+        Some(name) => {
+            Config::validate_profile_name(&name)
+                .with_context(|| format!("Invalid {}", PROFILE_VAR))?;
+            Ok(Some(name))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Returns the repository named by AXKEYSTORE_REPO, if set
+pub fn env_repo() -> Result<Option<String>> {
+    let repo = match env_var(REPO_VAR) {
+        Some(repo) => repo.trim_matches('/').to_string(),
+        None => return Ok(None),
+    };
+    let segments: Vec<&str> = repo.split('/').collect();
+    let valid = segments.len() <= 2
+        && segments.iter().all(|segment| {
+            !segment.is_empty()
+                && *segment != "."
+                && *segment != ".."
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid {} '{}'. Use 'owner/name', or 'name' for one of your own repositories.",
+            REPO_VAR,
+            repo
+        ));
+    }
+    Ok(Some(repo))
+}
+
+/// Overrides the category and output settings with AXKEYSTORE_CATEGORY and
+/// AXKEYSTORE_OUTPUT, so the order is: command line option, environment, profile setting
+pub fn apply_env(config: &mut Config) -> Result<()> {
+    for (var, key) in [(CATEGORY_VAR, "category"), (OUTPUT_VAR, "output")] {
+        if let Some(value) = env_var(var) {
+            set(config, key, &value).with_context(|| format!("Invalid {}", var))?;
+        }
+    }
+    Ok(())
+}
+
+/// Generates a value with the profile's generator policy, or None if it has none
+pub fn generate(config: &Config) -> Option<Vec<u8>> {
+    let defaults = &config.defaults;
+    if defaults.generate_length.is_none() && defaults.generate_charset.is_none() {
+        return None;
+    }
+    let policy = apply::Generate {
+        length: defaults
+            .generate_length
+            .unwrap_or(apply::DEFAULT_GENERATED_LENGTH),
+        charset: defaults.generate_charset.unwrap_or_default(),
+    };
+    Some(policy.value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::ExportFormat;
+    use crate::pipe::ValueFormat;
+
+    #[test]
+    fn test_set_get_unset() {
+        let mut config = Config::default();
+        for (key, _) in KEYS {
+            assert_eq!(get(&config, key).unwrap(), None);
+        }
+
+        set(&mut config, "category", "/team/prod/").unwrap();
+        assert_eq!(
+            get(&config, "category").unwrap().as_deref(),
+            Some("team/prod")
+        );
+        set(&mut config, "output", "HEX").unwrap();
+        assert_eq!(config.defaults.output, Some(ValueFormat::Hex));
+        set(&mut config, "output", "raw").unwrap();
+        assert_eq!(get(&config, "output").unwrap(), None);
+        set(&mut config, "export-format", "docker-secrets").unwrap();
+        assert_eq!(
+            get(&config, "export-format").unwrap().as_deref(),
+            Some("docker-secrets")
+        );
+        set(&mut config, "generate-length", "40").unwrap();
+        set(&mut config, "generate-charset", "symbols").unwrap();
+        assert_eq!(
+            get(&config, "generate-charset").unwrap().as_deref(),
+            Some("symbols")
+        );
+        set(&mut config, "branch", "secrets").unwrap();
+        assert_eq!(config.branch.as_deref(), Some("secrets"));
+
+        // Invalid values leave the setting as it was
+        assert!(set(&mut config, "category", "team/../prod").is_err());
+        assert!(set(&mut config, "export-format", "yaml").is_err());
+        assert!(set(&mut config, "generate-length", "5").is_err());
+        assert!(set(&mut config, "generate-length", "many").is_err());
+        assert!(set(&mut config, "color", "blue").is_err());
+        assert!(get(&config, "color").is_err());
+        assert_eq!(
+            get(&config, "generate-length").unwrap().as_deref(),
+            Some("40")
+        );
+
+        unset(&mut config, "category").unwrap();
+        unset(&mut config, "branch").unwrap();
+        assert_eq!(get(&config, "category").unwrap(), None);
+        assert_eq!(config.branch, None);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        std::env::set_var(CATEGORY_VAR, "ci/deploy");
+        std::env::set_var(OUTPUT_VAR, "raw");
+        std::env::set_var(REPO_VAR, "acme/vault");
+        std::env::set_var(PROFILE_VAR, "Default");
+
+        let mut config = Config::default();
+        set(&mut config, "category", "team/prod").unwrap();
+        set(&mut config, "output", "hex").unwrap();
+        set(&mut config, "export-format", "csv").unwrap();
+        apply_env(&mut config).unwrap();
+        assert_eq!(config.defaults.category.as_deref(), Some("ci/deploy"));
+        assert_eq!(config.defaults.output, None);
+        assert_eq!(config.defaults.export_format, Some(ExportFormat::Csv));
+        assert_eq!(env_repo().unwrap().as_deref(), Some("acme/vault"));
+        assert_eq!(env_profile().unwrap().as_deref(), Some("default"));
+
+        std::env::set_var(OUTPUT_VAR, "yaml");
+        std::env::set_var(REPO_VAR, "acme/team/vault");
+        std::env::set_var(PROFILE_VAR, "");
+        assert!(apply_env(&mut config).is_err());
+        assert!(env_repo().is_err());
+        assert_eq!(env_profile().unwrap(), None);
+
+        for var in [CATEGORY_VAR, OUTPUT_VAR, REPO_VAR, PROFILE_VAR] {
+            std::env::remove_var(var);
+        }
+        assert_eq!(env_repo().unwrap(), None);
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut config = Config::default();
+        assert!(generate(&config).is_none());
+
+        set(&mut config, "generate-charset", "hex").unwrap();
+        let value = generate(&config).unwrap();
+        assert_eq!(value.len(), apply::DEFAULT_GENERATED_LENGTH);
+        assert!(value.iter().all(|b| b"0123456789abcdef".contains(b)));
+
+        set(&mut config, "generate-length", "64").unwrap();
+        assert_eq!(generate(&config).unwrap().len(), 64);
+    }
+}
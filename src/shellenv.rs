@@ -0,0 +1,98 @@
+use anyhow::Result;
+
+/// Shells that 'env' can print variable assignments for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    /// POSIX shells (sh, bash, zsh), also understood by direnv
+    Sh,
+    Fish,
+    Powershell,
+}
+
+/// Returns the environment variable name of a key from its path relative to the exported
+/// category: upper-cased, with '/', '-' and '.' replaced by '_' (e.g. 'app/dev/db/api-key'
+/// exported from 'app/dev' becomes 'DB_API_KEY'). Fails for names that are not valid
+/// variable names.
+pub fn var_name(path: &str, category: &str) -> Result<String> {
+    let relative = path
+        .strip_prefix(category.trim_matches('/'))
+        .and_then(|rest| rest.strip_prefix('/'))
+        .unwrap_or(path);
+    let name: String = relative
+        .chars()
+        .map(|c| match c {
+            '/' | '-' | '.' => '_',
+            c => c.to_ascii_uppercase(),
+        })
+        .collect();
+    if name.is_empty()
+        || name.starts_with(|c: char| c.is_ascii_digit())
+        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(anyhow::anyhow!(
+            "'{}' cannot be used as an environment variable name ('{}')",
+            relative,
+            name
+        ));
+    }
+    Ok(name)
+}
+
+/// Quotes a value so the shell reads it back verbatim, including newlines
+pub fn quote(shell: Shell, value: &str) -> String {
+    match shell {
+        // Nothing is special inside single quotes except the closing quote itself
+        Shell::Sh => format!("'{}'", value.replace('\'', "'\\''")),
+        Shell::Fish => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+        Shell::Powershell => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+/// Returns the statement that exports a variable in a shell
+pub fn export_line(shell: Shell, name: &str, value: &str) -> String {
+    let value = quote(shell, value);
+    match shell {
+        Shell::Sh => format!("export {}={}", name, value),
+        Shell::Fish => format!("set -gx {} {}", name, value),
+        Shell::Powershell => format!("$env:{} = {}", name, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_var_name() {
+        assert_eq!(
+            var_name("app/dev/db/api-key", "/app/dev/").unwrap(),
+            "DB_API_KEY"
+        );
+        assert_eq!(
+            var_name("app/dev/sentry.dsn", "app/dev").unwrap(),
+            "SENTRY_DSN"
+        );
+        assert_eq!(
+            var_name("app/development", "app/dev").unwrap(),
+            "APP_DEVELOPMENT"
+        );
+        assert!(var_name("app/dev/2fa", "app/dev").is_err());
+    }
+
+    #[test]
+    fn test_export_line() {
+        let value = "it's a \\ \"test\"\nline 2 $HOME";
+        assert_eq!(
+            export_line(Shell::Sh, "FOO", value),
+            "export FOO='it'\\''s a \\ \"test\"\nline 2 $HOME'"
+        );
+        assert_eq!(
+            export_line(Shell::Fish, "FOO", value),
+            "set -gx FOO 'it\\'s a \\\\ \"test\"\nline 2 $HOME'"
+        );
+        assert_eq!(
+            export_line(Shell::Powershell, "FOO", value),
+            "$env:FOO = 'it''s a \\ \"test\"\nline 2 $HOME'"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use crate::config::{SignatureFormat, SigningConfig};
+use crate::storage::KeyVersion;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Builds the raw git commit object that GitHub will create, which is exactly what must be signed
+pub fn build_commit_payload(
+    tree: &str,
+    parent: &str,
+    name: &str,
+    email: &str,
+    timestamp: u64,
+    message: &str,
+) -> String {
+    format!(
+        "tree {tree}\nparent {parent}\nauthor {name} <{email}> {timestamp} +0000\ncommitter {name} <{email}> {timestamp} +0000\n\n{message}"
+    )
+}
+
+/// Signs a commit payload with the configured GPG key or SSH key, returning the armored signature
+pub fn sign_payload(config: &SigningConfig, payload: &str) -> Result<String> {
+    let key = config
+        .key
+        .as_deref()
+        .context("No signing key configured. Run 'axkeystore signing enable'.")?;
+
+    let mut command = match config.format {
+        Some(SignatureFormat::Gpg) => {
+            let mut c = Command::new("gpg");
+            c.args(["--batch", "--armor", "--detach-sign", "--local-user", key]);
+            c
+        }
+        Some(SignatureFormat::Ssh) => {
+            let mut c = Command::new("ssh-keygen");
+            c.args(["-Y", "sign", "-n", "git", "-f", key]);
+            c
+        }
+        None => return Err(anyhow::anyhow!("Commit signing is not enabled")),
+    };
+
+    let program = command.get_program().to_string_lossy().to_string();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run '{}'. Is it installed?", program))?;
+
+    child
+        .stdin
+        .take()
+        .context("Failed to open signer input")?
+        .write_all(payload.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "'{}' failed to sign the commit: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    String::from_utf8(output.stdout).context("Signature is not valid UTF-8")
+}
+
+/// Describes why the commit behind a key version should not be trusted, or None if it is trusted
+pub fn verification_warning(version: &KeyVersion, trusted_signers: &[String]) -> Option<String> {
+    let short_sha = &version.sha[..version.sha.len().min(7)];
+
+    if !version.verified {
+        return Some(match version.verification_reason.as_deref() {
+            None | Some("unsigned") => format!("commit {} is unsigned", short_sha),
+            Some("unknown_key") => format!("commit {} is signed by an unknown key", short_sha),
+            Some(reason) => format!(
+                "commit {} has a signature that could not be verified ({})",
+                short_sha, reason
+            ),
+        });
+    }
+
+    if trusted_signers.is_empty() {
+        return None;
+    }
+
+    let committer = version.committer_email.as_deref().unwrap_or_default();
+    if trusted_signers
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(committer))
+    {
+        None
+    } else {
+        Some(format!(
+            "commit {} is signed by '{}', who is not a trusted signer",
+            short_sha, committer
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(verified: bool, reason: Option<&str>, email: Option<&str>) -> KeyVersion {
+        KeyVersion {
+            sha: "0123456789abcdef".to_string(),
+            date: "2024-01-01T10:00:00Z".to_string(),
+            message: "Update key: api".to_string(),
+            verified,
+            verification_reason: reason.map(|r| r.to_string()),
+            committer_email: email.map(|e| e.to_string()),
+            author: None,
+        }
+    }
+
+    #[test]
+    fn test_build_commit_payload() {
+        let payload = build_commit_payload(
+            "tree_sha",
+            "parent_sha",
+            "Jane",
+            "jane@example.com",
+            1_704_103_200,
+            "Update key: api",
+        );
+        assert_eq!(
+            payload,
+            "tree tree_sha\nparent parent_sha\nauthor Jane <jane@example.com> 1704103200 +0000\ncommitter Jane <jane@example.com> 1704103200 +0000\n\nUpdate key: api"
+        );
+    }
+
+    #[test]
+    fn test_verification_warning() {
+        assert!(
+            verification_warning(&version(false, Some("unsigned"), None), &[])
+                .unwrap()
+                .contains("unsigned")
+        );
+        assert!(
+            verification_warning(&version(false, Some("unknown_key"), None), &[])
+                .unwrap()
+                .contains("unknown key")
+        );
+
+        let trusted = vec!["Jane@Example.com".to_string()];
+        let valid = version(true, Some("valid"), Some("jane@example.com"));
+        assert!(verification_warning(&valid, &[]).is_none());
+        assert!(verification_warning(&valid, &trusted).is_none());
+
+        let other = version(true, Some("valid"), Some("mallory@example.com"));
+        assert!(verification_warning(&other, &trusted)
+            .unwrap()
+            .contains("not a trusted signer"));
+    }
+}
@@ -1,8 +1,77 @@
 use crate::auth::get_saved_token_with_profile;
+use crate::config::{Config, SigningConfig};
+use crate::logging::SendLogged;
+use crate::redact;
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+
+/// Maximum size of a single file accepted by the GitHub Contents API.
+/// Larger blobs are split into chunks described by a manifest.
+pub const MAX_FILE_SIZE: usize = 1024 * 1024;
+
+/// Size of each chunk of a blob larger than MAX_FILE_SIZE
+const CHUNK_SIZE: usize = 768 * 1024;
+
+/// Largest plaintext value accepted for a single key
+pub const MAX_VALUE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Requests bulk reads make at once unless the profile sets another limit
+pub const DEFAULT_PARALLELISM: usize = 8;
+
+/// Most requests bulk reads may make at once (GitHub penalizes more as abuse)
+pub const MAX_PARALLELISM: usize = 32;
+
+/// Repository path of the encrypted remote master key
+pub const MASTER_KEY_PATH: &str = ".axkeystore/master_key.json";
+
+/// The rate limit counts as low once fewer than 1/LOW_RATE_LIMIT_DIVISOR of the hourly
+/// requests are left
+const LOW_RATE_LIMIT_DIVISOR: u64 = 10;
+
+/// Set once the low rate limit warning was printed, so a command opening several vaults
+/// warns only once
+static RATE_LIMIT_WARNED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Repository directory holding blobs shared by keys with identical values
+pub const DEDUP_DIR: &str = ".axkeystore/dedup";
+
+/// Page size used when following every page of a GitHub listing (the API's maximum)
+pub const PAGE_SIZE: u32 = 100;
+
+/// Streams the items of a paginated listing. `fetch_page` is called with page numbers
+/// starting at 1 until it returns fewer than `per_page` items; pages are only fetched as
+/// the stream is consumed, so stopping early saves requests.
+pub fn paginate<T, F, Fut>(per_page: u32, fetch_page: F) -> impl Stream<Item = Result<T>>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    stream::try_unfold(
+        (fetch_page, Some(1)),
+        move |(mut fetch_page, page)| async move {
+            let page = match page {
+                Some(page) => page,
+                None => return Ok(None),
+            };
+            let items = fetch_page(page).await?;
+            let next = if items.len() < per_page as usize {
+                None
+            } else {
+                Some(page + 1)
+            };
+            Ok::<_, anyhow::Error>(Some((
+                stream::iter(items.into_iter().map(Ok)),
+                (fetch_page, next),
+            )))
+        },
+    )
+    .try_flatten()
+}
 
 /// Internal response from GitHub user endpoint
 #[derive(Debug, Deserialize)]
@@ -23,6 +92,34 @@ struct UpdateFileRequest {
     message: String,
     content: String,
     sha: Option<String>,
+    /// Branch to commit to (the repository's default branch when absent)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+}
+
+/// Stored in place of a key blob that was split into chunks ('<key>.json.part000', ...)
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct ChunkManifest {
+    /// Number of chunks
+    chunks: usize,
+    /// Total size of the reassembled blob in bytes
+    size: usize,
+    /// Hex encoded SHA-256 of the reassembled blob
+    sha256: String,
+}
+
+/// Wrapper that distinguishes a manifest file from a regular encrypted blob
+#[derive(Debug, Deserialize, Serialize)]
+struct ManifestFile {
+    chunked: ChunkManifest,
+}
+
+/// Stored in place of a key blob whose value is shared with other keys; the blob itself is
+/// stored once under the dedup directory
+#[derive(Debug, Deserialize, Serialize)]
+struct PointerFile {
+    /// Id of the shared blob (see dedup_path)
+    dedup: String,
 }
 
 /// Represents a specific version (commit) of a key
@@ -34,6 +131,52 @@ pub struct KeyVersion {
     pub date: String,
     /// Commit message
     pub message: String,
+    /// Whether GitHub verified the commit signature
+    #[serde(default)]
+    pub verified: bool,
+    /// GitHub's verification reason (e.g. "valid", "unsigned", "unknown_key")
+    #[serde(default)]
+    pub verification_reason: Option<String>,
+    /// Email address of the committer
+    #[serde(default)]
+    pub committer_email: Option<String>,
+    /// GitHub login of the author, or the author's name if it has no GitHub account
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
+/// Narrows a key's history to a period and a kind of operation
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HistoryFilter {
+    /// Only versions committed at or after this time (Unix seconds)
+    pub since: Option<u64>,
+    /// Only versions committed at or before this time (Unix seconds)
+    pub until: Option<u64>,
+    /// Only versions whose commit records this action. GitHub cannot filter by it, so it is
+    /// applied to the versions fetched.
+    pub action: Option<crate::changelog::Action>,
+}
+
+impl HistoryFilter {
+    /// Returns true if a version passes the action filter (the period is applied by GitHub)
+    pub fn matches(&self, version: &KeyVersion) -> bool {
+        self.action
+            .is_none_or(|action| crate::changelog::action_of(&version.message) == Some(action))
+    }
+}
+
+impl From<GitHubCommit> for KeyVersion {
+    fn from(c: GitHubCommit) -> Self {
+        KeyVersion {
+            sha: c.sha,
+            date: c.commit.author.date,
+            message: c.commit.message,
+            verified: c.commit.verification.as_ref().is_some_and(|v| v.verified),
+            verification_reason: c.commit.verification.map(|v| v.reason),
+            committer_email: c.commit.committer.and_then(|a| a.email),
+            author: c.author.map(|u| u.login).or(c.commit.author.name),
+        }
+    }
 }
 
 /// Represents a stored key entry with its category and encrypted data
@@ -47,24 +190,270 @@ pub struct KeyEntry {
     pub data: Vec<u8>,
 }
 
+impl KeyEntry {
+    /// Returns the display path ('category/name' or just 'name')
+    pub fn display_path(&self) -> String {
+        match &self.category {
+            Some(cat) => format!("{}/{}", cat, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Returns true if the entry is in the given category or one of its subcategories
+    pub fn in_category(&self, category: &str) -> bool {
+        let category = category.trim_matches('/');
+        match &self.category {
+            Some(cat) => {
+                cat == category
+                    || (cat.starts_with(category) && cat[category.len()..].starts_with('/'))
+            }
+            None => category.is_empty(),
+        }
+    }
+}
+
 /// Internal struct to map GitHub commit list response
 #[derive(Debug, Deserialize)]
 struct GitHubCommit {
     sha: String,
     commit: GitHubCommitDetails,
+    /// GitHub account of the author (None if the author's email is not linked to one)
+    #[serde(default)]
+    author: Option<GitHubUser>,
+}
+
+/// Internal struct for a GitHub account
+#[derive(Debug, Deserialize)]
+struct GitHubUser {
+    login: String,
 }
 
 /// Internal struct for GitHub commit details
 #[derive(Debug, Deserialize)]
 struct GitHubCommitDetails {
     author: GitHubAuthor,
+    #[serde(default)]
+    committer: Option<GitHubAuthor>,
     message: String,
+    #[serde(default)]
+    verification: Option<GitHubVerification>,
 }
 
 /// Internal struct for GitHub commit author data
 #[derive(Debug, Deserialize)]
 struct GitHubAuthor {
     date: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+/// Internal struct for GitHub's commit signature verification result
+#[derive(Debug, Deserialize)]
+struct GitHubVerification {
+    verified: bool,
+    reason: String,
+}
+
+/// Public key that GitHub Actions secrets of a repository are encrypted with
+#[derive(Debug, Deserialize)]
+pub struct ActionsPublicKey {
+    /// Identifier GitHub uses to tell which key a secret was encrypted with
+    pub key_id: String,
+    /// Base64 encoded X25519 public key
+    pub key: String,
+}
+
+/// Internal response from the GitHub repository endpoint
+#[derive(Debug, Deserialize)]
+struct RepoResponse {
+    default_branch: String,
+    #[serde(default)]
+    private: bool,
+}
+
+/// Internal response from the GitHub rate limit endpoint
+#[derive(Debug, Deserialize)]
+struct RateLimitResponse {
+    resources: RateLimitResources,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimitResources {
+    core: RateLimitStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimitStatus {
+    limit: u64,
+    remaining: u64,
+    reset: u64,
+}
+
+/// Internal response from the GitHub endpoint listing the app installations of the user
+#[derive(Debug, Deserialize)]
+struct InstallationsResponse {
+    total_count: usize,
+}
+
+/// The token's GitHub API rate limit, and GitHub's clock when it was read
+#[derive(Debug)]
+pub struct RateLimit {
+    /// Requests allowed per hour
+    pub limit: u64,
+    /// Requests left in the current window
+    pub remaining: u64,
+    /// When the window resets (Unix seconds)
+    pub reset: u64,
+    /// GitHub's time from the response's 'Date' header (Unix seconds)
+    pub server_time: Option<u64>,
+}
+
+impl RateLimit {
+    /// Reads the rate limit GitHub reports in the headers of every API response
+    fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let number = |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+        Some(Self {
+            limit: number("x-ratelimit-limit")?,
+            remaining: number("x-ratelimit-remaining")?,
+            reset: number("x-ratelimit-reset")?,
+            server_time: headers
+                .get(reqwest::header::DATE)
+                .and_then(|date| date.to_str().ok())
+                .and_then(|date| crate::timeutil::parse_http_date(date).ok()),
+        })
+    }
+
+    /// Returns true if so few requests are left that a bulk operation may fail part-way
+    pub fn is_low(&self) -> bool {
+        self.remaining < self.limit / LOW_RATE_LIMIT_DIVISOR
+    }
+
+    /// Describes how many requests are left and when the limit resets
+    pub fn describe(&self, now: u64) -> String {
+        format!(
+            "{} of {} GitHub API requests left until {} (in {})",
+            self.remaining,
+            self.limit,
+            crate::timeutil::format_rfc3339(self.reset),
+            crate::timeutil::format_duration_secs(self.reset.saturating_sub(now))
+        )
+    }
+}
+
+/// Internal struct for a repository collaborator
+#[derive(Debug, Deserialize)]
+struct Collaborator {
+    login: String,
+}
+
+/// Internal struct for the authenticated user (two-factor status is only visible to the user)
+#[derive(Debug, Deserialize)]
+struct AuthenticatedUser {
+    two_factor_authentication: Option<bool>,
+}
+
+/// Internal struct for a Git object reference (ref target, blob, tree or commit)
+#[derive(Debug, Deserialize)]
+struct GitObject {
+    sha: String,
+}
+
+/// Internal response from the Git Data API ref endpoint
+#[derive(Debug, Deserialize)]
+struct GitRef {
+    object: GitObject,
+}
+
+/// Internal response from the Git Data API commit endpoint
+#[derive(Debug, Deserialize)]
+struct GitCommit {
+    tree: GitObject,
+}
+
+/// Internal response from the Git Data API commit endpoint, with everything needed to
+/// recreate the commit
+#[derive(Debug, Deserialize)]
+struct GitCommitObject {
+    tree: GitObject,
+    parents: Vec<GitObject>,
+    message: String,
+    author: serde_json::Value,
+    committer: serde_json::Value,
+}
+
+/// Internal response from the compare endpoint (commits are listed oldest first)
+#[derive(Debug, Deserialize)]
+struct CompareResponse {
+    commits: Vec<GitHubCommit>,
+}
+
+/// Internal response from the Git Data API tree endpoint (listed recursively)
+#[derive(Debug, Deserialize)]
+struct GitTreeListing {
+    tree: Vec<GitTreeItem>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+/// Internal struct for one entry of a recursive tree listing
+#[derive(Debug, Deserialize)]
+struct GitTreeItem {
+    path: String,
+    #[serde(rename = "type")]
+    item_type: String,
+    sha: String,
+    /// Blob size in bytes (absent for subtrees)
+    #[serde(default)]
+    size: Option<u64>,
+}
+
+/// A single file change applied as part of a commit created through the Git Data API
+#[derive(Debug, Clone)]
+pub enum TreeChange {
+    /// Creates or replaces a file with the given content
+    Upsert { path: String, content: Vec<u8> },
+    /// Removes a file
+    Delete { path: String },
+}
+
+/// Outcome of removing files from the whole history of the vault branch
+#[derive(Debug, Default, PartialEq)]
+pub struct HistoryRewrite {
+    /// Number of commits that were recreated
+    pub commits: usize,
+    /// Paths that were removed from at least one commit
+    pub removed: BTreeSet<String>,
+}
+
+/// What 'init_repo' found or created
+#[derive(Debug, PartialEq, Eq)]
+pub struct RepoStatus {
+    /// True if the repository did not exist and was created
+    pub created: bool,
+    /// The vault branch, if the vault is kept on one
+    pub branch: Option<BranchStatus>,
+}
+
+/// Whether the vault branch existed or was created by 'init_repo'
+#[derive(Debug, PartialEq, Eq)]
+pub enum BranchStatus {
+    /// The branch existed already
+    Existed(String),
+    /// The branch was created from the head of the default branch
+    Created { branch: String, from: String },
+}
+
+/// An entry in a repository directory listing
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// The file or directory name
+    pub name: String,
+    /// The full path from the repository root
+    pub path: String,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
 }
 
 /// Internal struct for an item returned by the GitHub Contents API (when listing a directory)
@@ -76,83 +465,484 @@ struct ContentsItem {
     item_type: String,
 }
 
+/// Returns the hex encoded SHA-256 digest of data
+fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Returns the stored size of a file in a directory listing: the sum of its chunks if it is
+/// chunked, the size of the file itself otherwise, or None if it is not listed
+fn stored_size(items: &[GitTreeItem], file_name: &str) -> Option<u64> {
+    let chunk_prefix = format!("{}.part", file_name);
+    let chunks: Vec<u64> = items
+        .iter()
+        .filter(|item| item.path.starts_with(&chunk_prefix))
+        .map(|item| item.size.unwrap_or(0))
+        .collect();
+    let file = items.iter().find(|item| item.path == file_name)?;
+    if chunks.is_empty() {
+        file.size
+    } else {
+        Some(chunks.iter().sum())
+    }
+}
+
+/// Validates a storage prefix: a relative directory of the repository that holds the vault,
+/// so the repository can host other content too. Returns it without surrounding slashes.
+pub fn validate_prefix(prefix: &str) -> Result<String> {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        return Err(anyhow::anyhow!("The storage prefix cannot be empty"));
+    }
+    for segment in prefix.split('/') {
+        if segment.is_empty()
+            || segment == "."
+            || segment == ".."
+            || !segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid storage prefix segment '{}'. Use names with alphabets, numbers, '.', '-' and '_', separated by '/'",
+                segment
+            ));
+        }
+    }
+    // A vault at the repository root keeps its files in these directories
+    let first = prefix.split('/').next().unwrap_or_default();
+    if first == "keys" || first == ".axkeystore" {
+        return Err(anyhow::anyhow!(
+            "Storage prefix '{}' collides with the layout of a vault at the repository root",
+            prefix
+        ));
+    }
+    Ok(prefix.to_string())
+}
+
+/// Validates the name of the branch holding the vault (e.g. 'vault' or 'secrets/prod')
+pub fn validate_branch(branch: &str) -> Result<String> {
+    let valid = !branch.is_empty()
+        && branch
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+        && !branch.starts_with(['-', '.', '/'])
+        && !branch.ends_with(['.', '/'])
+        && !branch.ends_with(".lock")
+        && !branch.contains("..")
+        && !branch.contains("//")
+        && !branch.contains("/.");
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Invalid branch name '{}'. Use alphabets, numbers, '.', '-' and '_', separated by '/'",
+            branch
+        ));
+    }
+    Ok(branch.to_string())
+}
+
+/// Returns the repository path of a path in the vault, placing it under the prefix
+fn to_repo_path(prefix: Option<&str>, path: &str) -> String {
+    match prefix {
+        Some(prefix) if path.is_empty() => prefix.to_string(),
+        Some(prefix) => format!("{}/{}", prefix, path),
+        None => path.to_string(),
+    }
+}
+
+/// Returns the path in the vault of a repository path, or None if it is outside the prefix
+fn from_repo_path<'a>(prefix: Option<&str>, path: &'a str) -> Option<&'a str> {
+    match prefix {
+        Some(prefix) => path.strip_prefix(prefix)?.strip_prefix('/'),
+        None => Some(path),
+    }
+}
+
+/// Returns the base URL of the GitHub API
+fn api_base() -> String {
+    std::env::var("AXKEYSTORE_API_URL").unwrap_or_else(|_| "https://api.github.com".to_string())
+}
+
+/// Looks up the GitHub login a token belongs to
+pub async fn fetch_login(token: &str) -> Result<String> {
+    let client = Client::builder().user_agent("axkeystore-cli").build()?;
+    let res = client
+        .get(format!("{}/user", api_base()))
+        .bearer_auth(token)
+        .send_logged()
+        .await?;
+    warn_if_rate_limit_low(res.headers());
+    let user_res: UserResponse = res
+        .json()
+        .await
+        .context("Failed to get user info. Check if token is valid.")?;
+    Ok(user_res.login)
+}
+
+/// Warns before a command starts using up what is left of the rate limit, so bulk operations
+/// can be deferred instead of failing part-way. Printed once per process.
+fn warn_if_rate_limit_low(headers: &reqwest::header::HeaderMap) {
+    if let Some(rate_limit) = RateLimit::from_headers(headers).filter(RateLimit::is_low) {
+        if !RATE_LIMIT_WARNED.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            tracing::warn!(
+                "Warning: Only {}. Consider deferring bulk operations.",
+                rate_limit.describe(crate::timeutil::now_unix())
+            );
+        }
+    }
+}
+
+/// The error a write returns when the file changed since its SHA was read, e.g. because
+/// another machine updated the same key at the same time
+#[derive(Debug, Clone)]
+pub struct WriteConflict {
+    /// Repository path of the file (relative to the vault)
+    pub path: String,
+    /// The file's SHA now, or None if it was deleted
+    pub current_sha: Option<String>,
+}
+
+impl std::fmt::Display for WriteConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.current_sha {
+            Some(_) => write!(f, "'{}' was changed by another write", self.path),
+            None => write!(f, "'{}' was deleted by another write", self.path),
+        }
+    }
+}
+
+impl std::error::Error for WriteConflict {}
+
+/// Returns the write conflict that caused an error, if any
+pub fn write_conflict(err: &anyhow::Error) -> Option<&WriteConflict> {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<WriteConflict>())
+}
+
 /// Handles all interactions with the GitHub repository backend
 pub struct Storage {
     client: Client,
     token: String,
     owner: String,
     repo: String,
+    login: String,
+    /// Whether the login came from the profile's configuration instead of GitHub
+    login_cached: bool,
     api_base: String,
+    profile: Option<String>,
+    signing: SigningConfig,
+    /// Directory of the repository that holds the vault (None for the repository root)
+    prefix: Option<String>,
+    /// Branch of the repository that holds the vault (None for the default branch)
+    branch: Option<String>,
+    /// Requests bulk reads make at once
+    parallelism: usize,
 }
 
 impl Storage {
-    /// Creates a new Storage instance for a specific profile
+    /// Creates a new Storage instance for a specific profile. The GitHub login of the saved
+    /// token is cached (encrypted) in the profile's configuration, so it is only looked up
+    /// when missing or after GitHub rejected the token.
     pub async fn new_with_profile(
         profile: Option<&str>,
         repo: &str,
         password: &str,
     ) -> Result<Self> {
-        let token = if let Ok(t) = std::env::var("AXKEYSTORE_TEST_TOKEN") {
-            t
-        } else {
-            get_saved_token_with_profile(profile, password)?
-        };
+        if let Ok(t) = std::env::var("AXKEYSTORE_TEST_TOKEN") {
+            return Self::with_token(profile, repo, t).await;
+        }
+        let token = get_saved_token_with_profile(profile, password)?;
 
-        let api_base = std::env::var("AXKEYSTORE_API_URL")
-            .unwrap_or_else(|_| "https://api.github.com".to_string());
+        // A login that no longer decrypts is looked up again
+        if let Ok(Some(login)) = Config::get_login_with_profile(profile, password) {
+            let mut storage = Self::from_parts(profile, repo, token, login)?;
+            storage.login_cached = true;
+            return Ok(storage);
+        }
+        let storage = Self::with_token(profile, repo, token).await?;
+        // Only an optimization: without it, the next command looks the login up again
+        let _ = Config::set_login_with_profile(profile, storage.login(), password);
+        Ok(storage)
+    }
+
+    /// Creates a new Storage instance authenticated with a given token instead of the token
+    /// saved for the profile (e.g. the token of a service account bundle)
+    pub async fn with_token(profile: Option<&str>, repo: &str, token: String) -> Result<Self> {
+        let login = fetch_login(&token).await?;
+        Self::from_parts(profile, repo, token, login)
+    }
 
+    /// Creates a Storage instance for a token whose GitHub login is already known
+    fn from_parts(profile: Option<&str>, repo: &str, token: String, login: String) -> Result<Self> {
         let client = Client::builder().user_agent("axkeystore-cli").build()?;
+        redact::register(&token);
 
-        // Get current user to determine owner
-        let user_res: UserResponse = client
-            .get(format!("{}/user", api_base))
-            .bearer_auth(&token)
-            .send()
-            .await?
-            .json()
-            .await
-            .context("Failed to get user info. Check if token is valid.")?;
+        // Repositories given as 'owner/repo' (e.g. a teammate's or an organization's vault)
+        // are used as-is; otherwise the repository belongs to the authenticated user
+        let (owner, repo) = match repo.split_once('/') {
+            Some((owner, name)) => (owner.to_string(), name.to_string()),
+            None => (login.clone(), repo.to_string()),
+        };
 
+        let mut config = Config::load_with_profile(profile)?;
+        // The prefix and branch describe the profile's repository, not its other vaults
+        if crate::config::selected_vault().is_some() {
+            config.storage_prefix = None;
+            config.branch = None;
+        }
         Ok(Self {
             client,
             token,
-            owner: user_res.login,
-            repo: repo.to_string(),
-            api_base,
+            owner,
+            repo,
+            login,
+            login_cached: false,
+            api_base: api_base(),
+            profile: profile.map(|p| p.to_string()),
+            signing: config.signing,
+            prefix: config.storage_prefix,
+            branch: config.branch,
+            parallelism: config
+                .parallelism
+                .unwrap_or(DEFAULT_PARALLELISM)
+                .clamp(1, MAX_PARALLELISM),
         })
     }
 
-    /// Ensures the storage repository exists on GitHub
-    pub async fn init_repo(&self) -> Result<()> {
-        println!(
-            "Checking if repository {}/{} exists...",
-            self.owner, self.repo
-        );
+    /// Looks at every GitHub response: warns once when the rate limit runs low, and forgets
+    /// a cached login when GitHub rejects the token, so the next command looks it up again
+    fn check_response(&self, res: &reqwest::Response) {
+        warn_if_rate_limit_low(res.headers());
+        if res.status() == reqwest::StatusCode::UNAUTHORIZED && self.login_cached {
+            let _ = Config::forget_login_with_profile(self.profile.as_deref());
+        }
+    }
+
+    /// Places the vault in a directory of the repository (None for the repository root)
+    pub fn set_prefix(&mut self, prefix: Option<String>) {
+        self.prefix = prefix;
+    }
+
+    /// Returns the directory of the repository that holds the vault, if not its root
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Keeps the vault on a branch of the repository (None for the default branch)
+    pub fn set_branch(&mut self, branch: Option<String>) {
+        self.branch = branch;
+    }
+
+    /// Returns the branch that holds the vault, if not the repository's default branch
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_deref()
+    }
+
+    /// Returns the repository path of a path in the vault
+    fn repo_path(&self, path: &str) -> String {
+        to_repo_path(self.prefix.as_deref(), path)
+    }
+
+    /// Returns the path in the vault of a repository path, or None if it is outside the vault
+    fn vault_path<'a>(&self, path: &'a str) -> Option<&'a str> {
+        from_repo_path(self.prefix.as_deref(), path)
+    }
+
+    /// Returns the profile this storage was opened with (None for the default profile)
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Returns how many requests bulk reads make at once
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+
+    /// Returns the GitHub login of the authenticated user
+    pub fn login(&self) -> &str {
+        &self.login
+    }
+
+    /// Returns the base URL of the GitHub API in use
+    pub fn api_base(&self) -> &str {
+        &self.api_base
+    }
+
+    /// Returns the GitHub token the storage authenticates with (e.g. for git over HTTPS)
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Returns the repository in 'owner/repo' form
+    pub fn repo_full_name(&self) -> String {
+        format!("{}/{}", self.owner, self.repo)
+    }
 
+    /// Ensures the storage repository (and the vault branch, if any) exists on GitHub,
+    /// creating what is missing, and reports what it found
+    pub async fn init_repo(&self) -> Result<RepoStatus> {
         let url = format!("{}/repos/{}/{}", self.api_base, self.owner, self.repo);
         let res = self
             .client
             .get(&url)
             .bearer_auth(&self.token)
-            .send()
+            .send_logged()
             .await?;
-
-        if res.status() == reqwest::StatusCode::NOT_FOUND {
-            return Err(anyhow::anyhow!(
-                "Repository '{}/{}' not found. Please create a private repository manually on GitHub before initializing.",
-                self.owner, self.repo
-            ));
+        self.check_response(&res);
+
+        let created = if res.status() == reqwest::StatusCode::NOT_FOUND {
+            if let Err(e) = self.create_repo().await {
+                let hint = if self.is_org_repo() {
+                    ". Ask an owner of the organization to create it (private), or to allow members to create repositories"
+                } else {
+                    ""
+                };
+                return Err(e.context(format!(
+                    "Repository '{}/{}' not found and could not be created{}",
+                    self.owner, self.repo, hint
+                )));
+            }
+            true
         } else if res.status().is_success() {
-            println!("Repository exists.");
+            false
         } else {
             return Err(anyhow::anyhow!("Error checking repo: {}", res.status()));
+        };
+
+        let branch = match &self.branch {
+            Some(branch) => Some(self.create_branch(branch).await?),
+            None => None,
+        };
+
+        Ok(RepoStatus { created, branch })
+    }
+
+    /// Returns true if the repository belongs to someone other than the authenticated user
+    /// (an organization)
+    fn is_org_repo(&self) -> bool {
+        !self.owner.eq_ignore_ascii_case(&self.login)
+    }
+
+    /// Creates the repository as a private one, in the organization that owns it or among
+    /// the authenticated user's repositories
+    async fn create_repo(&self) -> Result<()> {
+        let url = if self.is_org_repo() {
+            format!("{}/orgs/{}/repos", self.api_base, self.owner)
+        } else {
+            format!("{}/user/repos", self.api_base)
+        };
+        let _: serde_json::Value = self
+            .send_json(
+                reqwest::Method::POST,
+                &url,
+                Some(&serde_json::json!({
+                    "name": self.repo,
+                    "private": true,
+                    "description": "AxKeyStore vault"
+                })),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns true if the repository exists and is visible to the token
+    pub async fn repo_exists(&self) -> Result<bool> {
+        let url = format!("{}/repos/{}/{}", self.api_base, self.owner, self.repo);
+        let res = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.token)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+        match res.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(anyhow::anyhow!("Error checking repo: {}", status)),
         }
+    }
+
+    /// Changes settings of the repository (PATCH /repos/{owner}/{repo})
+    async fn update_repo(&self, settings: serde_json::Value) -> Result<()> {
+        let url = format!("{}/repos/{}/{}", self.api_base, self.owner, self.repo);
+        let _: serde_json::Value = self
+            .send_json(reqwest::Method::PATCH, &url, Some(&settings))
+            .await?;
+        Ok(())
+    }
+
+    /// Makes a branch the repository's default branch
+    pub async fn set_default_branch(&self, branch: &str) -> Result<()> {
+        self.update_repo(serde_json::json!({ "default_branch": branch }))
+            .await
+            .with_context(|| format!("Failed to make '{}' the default branch", branch))
+    }
+
+    /// Archives the repository, making it read-only. Only its owner or an admin can.
+    pub async fn archive_repo(&self) -> Result<()> {
+        self.update_repo(serde_json::json!({ "archived": true }))
+            .await
+            .with_context(|| format!("Failed to archive {}/{}", self.owner, self.repo))
+    }
 
+    /// Deletes the repository. Needs a token with the 'delete_repo' scope.
+    #[cfg(feature = "e2e")]
+    pub async fn delete_repo(&self) -> Result<()> {
+        let url = format!("{}/repos/{}/{}", self.api_base, self.owner, self.repo);
+        let res = self
+            .client
+            .delete(&url)
+            .bearer_auth(&self.token)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Failed to delete repository {}/{}: {} - {}",
+                self.owner,
+                self.repo,
+                status,
+                redact::redact(&text)
+            ));
+        }
         Ok(())
     }
 
+    /// Creates a branch from the head of the default branch, unless it exists already. The
+    /// branch then holds whatever the default branch held, including a vault.
+    async fn create_branch(&self, branch: &str) -> Result<BranchStatus> {
+        if self.branch_head(branch).await?.is_some() {
+            return Ok(BranchStatus::Existed(branch.to_string()));
+        }
+
+        let default_branch = self.default_branch().await?;
+        let head = self.branch_head(&default_branch).await?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Repository '{}/{}' has no commits yet, so branch '{}' cannot be created. Add a first commit (e.g. a README) to '{}' and try again.",
+                self.owner,
+                self.repo,
+                branch,
+                default_branch
+            )
+        })?;
+        let _: GitRef = self
+            .send_json(
+                reqwest::Method::POST,
+                &self.git_url("refs"),
+                Some(&serde_json::json!({ "ref": format!("refs/heads/{}", branch), "sha": head })),
+            )
+            .await
+            .with_context(|| format!("Failed to create branch '{}'", branch))?;
+        Ok(BranchStatus::Created {
+            branch: branch.to_string(),
+            from: default_branch,
+        })
+    }
+
     /// Validates and sanitizes a category path string
-    fn validate_category(category: Option<&str>) -> Result<Option<String>> {
+    pub fn validate_category(category: Option<&str>) -> Result<Option<String>> {
         match category {
             None => Ok(None),
             Some(cat) => {
@@ -196,7 +986,7 @@ impl Storage {
     }
 
     /// Generates the GitHub file path for a specific key and category
-    fn build_key_path(key: &str, category: Option<&str>) -> Result<String> {
+    pub fn build_key_path(key: &str, category: Option<&str>) -> Result<String> {
         let validated_category = Self::validate_category(category)?;
 
         // Validate the key name
@@ -214,19 +1004,34 @@ impl Storage {
         Ok(path)
     }
 
-    /// Fetches the encrypted master key blob from the hidden application directory
-    pub async fn get_master_key_blob(&self) -> Result<Option<Vec<u8>>> {
-        let url = format!(
-            "{}/repos/{}/{}/contents/.axkeystore/master_key.json",
-            self.api_base, self.owner, self.repo
-        );
+    /// Returns the Contents API URL for a path in the vault
+    fn contents_url(&self, file_path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}/contents/{}",
+            self.api_base,
+            self.owner,
+            self.repo,
+            self.repo_path(file_path)
+        )
+    }
+
+    /// Returns the Contents API URL reading a path from the vault branch
+    fn contents_read_url(&self, file_path: &str) -> String {
+        match &self.branch {
+            Some(branch) => format!("{}?ref={}", self.contents_url(file_path), branch),
+            None => self.contents_url(file_path),
+        }
+    }
 
+    /// Fetches the decoded content and SHA of a file at the given repository path
+    pub async fn get_file(&self, file_path: &str) -> Result<Option<(Vec<u8>, String)>> {
         let res = self
             .client
-            .get(&url)
+            .get(self.contents_read_url(file_path))
             .bearer_auth(&self.token)
-            .send()
+            .send_logged()
             .await?;
+        self.check_response(&res);
 
         if res.status() == reqwest::StatusCode::NOT_FOUND {
             return Ok(None);
@@ -234,398 +1039,2613 @@ impl Storage {
 
         if !res.status().is_success() {
             return Err(anyhow::anyhow!(
-                "Failed to fetch master key: {}",
+                "Failed to fetch file '{}': {}",
+                file_path,
                 res.status()
             ));
         }
 
         let file_res: FileResponse = res.json().await?;
+        // Github returns content as base64 with newlines
         let content_clean = file_res.content.replace('\n', "");
         let decoded = BASE64
             .decode(content_clean)
-            .context("Failed to decode base64 master key from GitHub")?;
+            .context("Failed to decode base64 content from GitHub")?;
 
-        Ok(Some(decoded))
+        Ok(Some((decoded, file_res.sha)))
     }
 
-    /// Saves the encrypted master key blob to the repository
-    pub async fn save_master_key_blob(&self, data: &[u8]) -> Result<()> {
-        let url = format!(
-            "{}/repos/{}/{}/contents/.axkeystore/master_key.json",
-            self.api_base, self.owner, self.repo
-        );
-
-        // Check if file exists to get SHA
-        let res = self
-            .client
-            .get(&url)
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
-
-        let sha = if res.status().is_success() {
-            let file_res: FileResponse = res.json().await?;
-            Some(file_res.sha)
-        } else {
-            None
-        };
-
-        let encoded_content = BASE64.encode(data);
-
-        let body = UpdateFileRequest {
-            message: "Initialize master key".to_string(),
-            content: encoded_content,
-            sha,
-        };
+    /// Returns the Git Data API URL for a path below the repository's git database
+    fn git_url(&self, path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}/git/{}",
+            self.api_base, self.owner, self.repo, path
+        )
+    }
 
-        let res = self
+    /// Sends a JSON request and deserializes the JSON response, failing on non-success statuses
+    async fn send_json<T: serde::de::DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<T> {
+        let mut req = self
             .client
-            .put(&url)
-            .bearer_auth(&self.token)
-            .json(&body)
-            .send()
-            .await?;
+            .request(method.clone(), url)
+            .bearer_auth(&self.token);
+        if let Some(body) = body {
+            req = req.json(body);
+        }
+        let res = req.send_logged().await?;
+        self.check_response(&res);
 
         if !res.status().is_success() {
             let status = res.status();
             let text = res.text().await.unwrap_or_default();
             return Err(anyhow::anyhow!(
-                "Failed to save master key: {} - {}",
+                "GitHub request {} {} failed: {} - {}",
+                method,
+                url,
                 status,
-                text
+                redact::redact(&text)
             ));
         }
 
-        Ok(())
+        Ok(res.json().await?)
     }
 
-    /// Fetches the current encrypted data and SHA for a specific key
-    pub async fn get_blob(
-        &self,
-        key: &str,
-        category: Option<&str>,
-    ) -> Result<Option<(Vec<u8>, String)>> {
-        let path = Self::build_key_path(key, category)?;
-        let url = format!(
-            "{}/repos/{}/{}/contents/{}",
-            self.api_base, self.owner, self.repo, path
-        );
-
-        let res = self
-            .client
-            .get(&url)
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
-
-        if res.status() == reqwest::StatusCode::NOT_FOUND {
-            return Ok(None);
-        }
-
-        if !res.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to fetch key: {}", res.status()));
+    /// Returns the branch AxKeyStore reads from and writes to: the profile's branch, or the
+    /// repository's default branch
+    pub async fn vault_branch(&self) -> Result<String> {
+        match &self.branch {
+            Some(branch) => Ok(branch.clone()),
+            None => self.default_branch().await,
         }
+    }
 
-        let file_res: FileResponse = res.json().await?;
-        // Github returns content as base64 with newlines
-        let content_clean = file_res.content.replace('\n', "");
-        let decoded = BASE64
-            .decode(content_clean)
-            .context("Failed to decode base64 content from GitHub")?;
-
-        Ok(Some((decoded, file_res.sha)))
+    /// Returns the repository's default branch
+    pub async fn default_branch(&self) -> Result<String> {
+        let url = format!("{}/repos/{}/{}", self.api_base, self.owner, self.repo);
+        let repo: RepoResponse = self
+            .send_json(reqwest::Method::GET, &url, None)
+            .await
+            .context("Failed to fetch repository info")?;
+        Ok(repo.default_branch)
     }
 
-    /// Fetches the encrypted data for a key at a specific commit version
-    pub async fn get_blob_at_version(
-        &self,
-        key: &str,
-        category: Option<&str>,
-        sha: &str,
-    ) -> Result<Option<Vec<u8>>> {
-        let path = Self::build_key_path(key, category)?;
-        let url = format!(
-            "{}/repos/{}/{}/contents/{}?ref={}",
-            self.api_base, self.owner, self.repo, path, sha
-        );
+    /// Returns true if the repository is private
+    pub async fn is_private(&self) -> Result<bool> {
+        let url = format!("{}/repos/{}/{}", self.api_base, self.owner, self.repo);
+        let repo: RepoResponse = self
+            .send_json(reqwest::Method::GET, &url, None)
+            .await
+            .context("Failed to fetch repository info")?;
+        Ok(repo.private)
+    }
 
+    /// Returns the token's API rate limit (reading it does not count against the limit)
+    pub async fn rate_limit(&self) -> Result<RateLimit> {
         let res = self
             .client
-            .get(&url)
+            .get(format!("{}/rate_limit", self.api_base))
             .bearer_auth(&self.token)
-            .send()
+            .send_logged()
             .await?;
-
-        if res.status() == reqwest::StatusCode::NOT_FOUND {
-            return Ok(None);
-        }
-
+        self.check_response(&res);
         if !res.status().is_success() {
             return Err(anyhow::anyhow!(
-                "Failed to fetch key at version {}: {}",
-                sha,
+                "Failed to fetch the rate limit: {}",
                 res.status()
             ));
         }
-
-        let file_res: FileResponse = res.json().await?;
-        let content_clean = file_res.content.replace('\n', "");
-        let decoded = BASE64
-            .decode(content_clean)
-            .context("Failed to decode base64 content from GitHub")?;
-
-        Ok(Some(decoded))
+        let server_time = res
+            .headers()
+            .get(reqwest::header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| crate::timeutil::parse_http_date(date).ok());
+        let status: RateLimitResponse = res.json().await?;
+        Ok(RateLimit {
+            limit: status.resources.core.limit,
+            remaining: status.resources.core.remaining,
+            reset: status.resources.core.reset,
+            server_time,
+        })
     }
 
-    /// Retrieves the list of versions (commits) for a specific key
-    pub async fn get_key_history(
-        &self,
-        key: &str,
-        category: Option<&str>,
-        page: u32,
-        per_page: u32,
-    ) -> Result<Vec<KeyVersion>> {
-        let path = Self::build_key_path(key, category)?;
-        let url = format!(
-            "{}/repos/{}/{}/commits",
-            self.api_base, self.owner, self.repo
-        );
-
+    /// Returns how many installations of the GitHub App the user can access, or None if the
+    /// token is not a GitHub App user token (e.g. a personal access token)
+    pub async fn installation_count(&self) -> Result<Option<usize>> {
         let res = self
             .client
-            .get(&url)
+            .get(format!("{}/user/installations", self.api_base))
             .bearer_auth(&self.token)
-            .query(&[
-                ("path", path.as_str()),
-                ("page", &page.to_string()),
-                ("per_page", &per_page.to_string()),
-            ])
-            .send()
+            .send_logged()
             .await?;
-
-        if !res.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "Failed to fetch key history: {}",
-                res.status()
-            ));
+        self.check_response(&res);
+        match res.status() {
+            status if status.is_success() => {
+                let installations: InstallationsResponse = res.json().await?;
+                Ok(Some(installations.total_count))
+            }
+            reqwest::StatusCode::FORBIDDEN => Ok(None),
+            status => Err(anyhow::anyhow!(
+                "Failed to list app installations: {}",
+                status
+            )),
         }
+    }
 
-        let commits: Vec<GitHubCommit> = res.json().await?;
-        let versions = commits
-            .into_iter()
-            .map(|c| KeyVersion {
-                sha: c.sha,
-                date: c.commit.author.date,
-                message: c.commit.message,
-            })
-            .collect();
+    /// Streams every item of a paginated GitHub listing. `url` is the listing's full URL,
+    /// with or without a query; the page parameters are appended to it.
+    pub fn paged<'a, T>(&'a self, url: String) -> impl Stream<Item = Result<T>> + 'a
+    where
+        T: serde::de::DeserializeOwned + 'a,
+    {
+        let separator = if url.contains('?') { '&' } else { '?' };
+        paginate(PAGE_SIZE, move |page| {
+            let url = format!("{}{}page={}&per_page={}", url, separator, page, PAGE_SIZE);
+            async move { self.send_json(reqwest::Method::GET, &url, None).await }
+        })
+    }
 
-        Ok(versions)
+    /// Streams the commits of a branch, newest first, optionally only those after a time
+    /// (Unix seconds)
+    pub fn commits<'a>(
+        &'a self,
+        branch: &str,
+        since: Option<u64>,
+    ) -> impl Stream<Item = Result<KeyVersion>> + 'a {
+        let mut url = format!(
+            "{}/repos/{}/{}/commits?sha={}",
+            self.api_base, self.owner, self.repo, branch
+        );
+        if let Some(since) = since {
+            url.push_str(&format!(
+                "&since={}",
+                crate::timeutil::format_rfc3339(since)
+            ));
+        }
+        self.paged::<GitHubCommit>(url).map_ok(KeyVersion::from)
     }
 
-    /// Uploads or updates an encrypted key blob to the repository
-    pub async fn save_blob(&self, key: &str, data: &[u8], category: Option<&str>) -> Result<()> {
-        let path = Self::build_key_path(key, category)?;
+    /// Streams the logins of the repository's collaborators with an affiliation ('outside',
+    /// 'direct' or 'all')
+    pub fn collaborators<'a>(
+        &'a self,
+        affiliation: &str,
+    ) -> impl Stream<Item = Result<String>> + 'a {
         let url = format!(
-            "{}/repos/{}/{}/contents/{}",
-            self.api_base, self.owner, self.repo, path
+            "{}/repos/{}/{}/collaborators?affiliation={}",
+            self.api_base, self.owner, self.repo, affiliation
         );
+        self.paged::<Collaborator>(url).map_ok(|c| c.login)
+    }
 
-        // Check if file exists to get SHA (for update)
-        let sha = if let Ok(Some((_, sha))) = self.get_blob(key, category).await {
-            Some(sha)
-        } else {
-            None
-        };
-
-        let encoded_content = BASE64.encode(data);
-
-        let commit_message = match category {
-            Some(cat) => format!("Update key: {}/{}", cat.trim_matches('/'), key),
-            None => format!("Update key: {}", key),
-        };
+    /// Returns the logins of collaborators who are not members of the owning organization
+    /// (or, for personal repositories, everyone except the owner)
+    pub async fn outside_collaborators(&self) -> Result<Vec<String>> {
+        let collaborators: Vec<String> = self
+            .collaborators("outside")
+            .try_collect()
+            .await
+            .context("Failed to list repository collaborators")?;
+        Ok(collaborators
+            .into_iter()
+            .filter(|login| !login.eq_ignore_ascii_case(&self.owner))
+            .collect())
+    }
 
-        let body = UpdateFileRequest {
-            message: commit_message,
-            content: encoded_content,
-            sha,
-        };
+    /// Returns the GitHub Actions public key of a repository ('owner/name'), which need not be
+    /// the vault repository
+    pub async fn actions_public_key(&self, repo: &str) -> Result<ActionsPublicKey> {
+        let url = format!(
+            "{}/repos/{}/actions/secrets/public-key",
+            self.api_base, repo
+        );
+        self.send_json(reqwest::Method::GET, &url, None)
+            .await
+            .with_context(|| format!("Failed to fetch the Actions public key of '{}'", repo))
+    }
 
+    /// Creates or updates a GitHub Actions secret of a repository ('owner/name') with a value
+    /// already encrypted for the repository's Actions public key
+    pub async fn put_actions_secret(
+        &self,
+        repo: &str,
+        name: &str,
+        encrypted_value: &str,
+        key_id: &str,
+    ) -> Result<()> {
+        let url = format!("{}/repos/{}/actions/secrets/{}", self.api_base, repo, name);
         let res = self
             .client
             .put(&url)
             .bearer_auth(&self.token)
-            .json(&body)
-            .send()
+            .json(&serde_json::json!({
+                "encrypted_value": encrypted_value,
+                "key_id": key_id,
+            }))
+            .send_logged()
             .await?;
+        self.check_response(&res);
 
         if !res.status().is_success() {
-            let status = res.status();
-            let text = res.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("Failed to save key: {} - {}", status, text));
+            return Err(anyhow::anyhow!(
+                "Failed to set Actions secret '{}' in '{}': {}",
+                name,
+                repo,
+                res.status()
+            ));
         }
-
         Ok(())
     }
 
-    /// Deletes a key from the repository
-    pub async fn delete_blob(&self, key: &str, category: Option<&str>) -> Result<bool> {
-        let path = Self::build_key_path(key, category)?;
-
-        // First, get the file to retrieve its SHA (required for deletion)
-        let sha = match self.get_blob(key, category).await? {
-            Some((_, sha)) => sha,
-            None => return Ok(false), // Key doesn't exist
-        };
-
-        let url = format!(
-            "{}/repos/{}/{}/contents/{}",
-            self.api_base, self.owner, self.repo, path
-        );
-
-        let commit_message = match category {
-            Some(cat) => format!("Delete key: {}/{}", cat.trim_matches('/'), key),
-            None => format!("Delete key: {}", key),
-        };
-
-        let body = serde_json::json!({
-            "message": commit_message,
-            "sha": sha
-        });
+    /// Returns whether the authenticated account has two-factor authentication enabled, or None
+    /// if the token is not allowed to see it
+    pub async fn two_factor_enabled(&self) -> Result<Option<bool>> {
+        let url = format!("{}/user", self.api_base);
+        let user: AuthenticatedUser = self
+            .send_json(reqwest::Method::GET, &url, None)
+            .await
+            .context("Failed to fetch the authenticated user")?;
+        Ok(user.two_factor_authentication)
+    }
 
+    /// Returns the commit SHA at the head of a branch, or None if the branch has no commits yet
+    pub async fn branch_head(&self, branch: &str) -> Result<Option<String>> {
         let res = self
             .client
-            .delete(&url)
+            .get(self.git_url(&format!("ref/heads/{}", branch)))
             .bearer_auth(&self.token)
-            .json(&body)
-            .send()
+            .send_logged()
             .await?;
+        self.check_response(&res);
+
+        // GitHub answers 409 for refs of an empty repository
+        if res.status() == reqwest::StatusCode::NOT_FOUND
+            || res.status() == reqwest::StatusCode::CONFLICT
+        {
+            return Ok(None);
+        }
 
         if !res.status().is_success() {
-            let status = res.status();
-            let text = res.text().await.unwrap_or_default();
             return Err(anyhow::anyhow!(
-                "Failed to delete key: {} - {}",
-                status,
-                text
+                "Failed to fetch branch '{}': {}",
+                branch,
+                res.status()
             ));
         }
 
-        Ok(true)
+        let git_ref: GitRef = res.json().await?;
+        Ok(Some(git_ref.object.sha))
     }
 
-    /// Fetches the raw content of a file at the given repository path
-    async fn get_file_content_by_path(&self, file_path: &str) -> Result<Vec<u8>> {
-        let url = format!(
-            "{}/repos/{}/{}/contents/{}",
-            self.api_base, self.owner, self.repo, file_path
-        );
-
-        let res = self
-            .client
-            .get(&url)
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+    /// Lists every file on the vault branch with its blob SHA in a single request, so
+    /// changes can be detected without walking directories
+    pub async fn list_tree(&self) -> Result<BTreeMap<String, String>> {
+        let branch = self.vault_branch().await?;
+        let head = match self.branch_head(&branch).await? {
+            Some(head) => head,
+            None => return Ok(BTreeMap::new()),
+        };
 
-        if !res.status().is_success() {
+        let listing: GitTreeListing = self
+            .send_json(
+                reqwest::Method::GET,
+                &self.git_url(&format!("trees/{}?recursive=1", head)),
+                None,
+            )
+            .await
+            .context("Failed to list the repository tree")?;
+        if listing.truncated {
             return Err(anyhow::anyhow!(
-                "Failed to fetch file '{}': {}",
-                file_path,
-                res.status()
+                "The repository is too large to be listed in a single request"
             ));
         }
 
-        let file_res: FileResponse = res.json().await?;
-        let content_clean = file_res.content.replace('\n', "");
-        let decoded = BASE64
-            .decode(content_clean)
-            .context("Failed to decode base64 content from GitHub")?;
-
-        Ok(decoded)
+        Ok(listing
+            .tree
+            .into_iter()
+            .filter(|item| item.item_type == "blob")
+            .filter_map(|item| Some((self.vault_path(&item.path)?.to_string(), item.sha)))
+            .collect())
     }
 
-    /// Lists all stored keys across all categories by listing the keys/ directory recursively
-    pub async fn list_all_keys(&self) -> Result<Vec<KeyEntry>> {
+    /// Applies a set of file changes as a single commit on the vault branch using the
+    /// Git Data API, signing the commit when signing is enabled. Returns the new commit SHA.
+    pub async fn commit_changes(&self, changes: &[TreeChange], message: &str) -> Result<String> {
+        let branch = self.vault_branch().await?;
+        let parent = self.branch_head(&branch).await?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Branch '{}' has no commits yet. Store a key before creating signed or batched commits.",
+                branch
+            )
+        })?;
+
+        let base: GitCommit = self
+            .send_json(
+                reqwest::Method::GET,
+                &self.git_url(&format!("commits/{}", parent)),
+                None,
+            )
+            .await?;
+
         let mut entries = Vec::new();
-        let mut dirs_to_visit = vec!["keys".to_string()];
+        for change in changes {
+            match change {
+                TreeChange::Upsert { path, content } => {
+                    let blob: GitObject = self
+                        .send_json(
+                            reqwest::Method::POST,
+                            &self.git_url("blobs"),
+                            Some(&serde_json::json!({
+                                "content": BASE64.encode(content),
+                                "encoding": "base64"
+                            })),
+                        )
+                        .await?;
+                    entries.push(serde_json::json!({
+                        "path": self.repo_path(path), "mode": "100644", "type": "blob", "sha": blob.sha
+                    }));
+                }
+                TreeChange::Delete { path } => {
+                    entries.push(serde_json::json!({
+                        "path": self.repo_path(path), "mode": "100644", "type": "blob", "sha": null
+                    }));
+                }
+            }
+        }
 
-        while let Some(current_dir) = dirs_to_visit.pop() {
-            let url = format!(
-                "{}/repos/{}/{}/contents/{}",
-                self.api_base, self.owner, self.repo, current_dir
+        let tree: GitObject = self
+            .send_json(
+                reqwest::Method::POST,
+                &self.git_url("trees"),
+                Some(&serde_json::json!({ "base_tree": base.tree.sha, "tree": entries })),
+            )
+            .await?;
+
+        let mut commit = serde_json::json!({
+            "message": message,
+            "tree": tree.sha,
+            "parents": [parent],
+        });
+
+        if self.signing.is_enabled() {
+            let name = self
+                .signing
+                .name
+                .clone()
+                .unwrap_or_else(|| self.owner.clone());
+            let email = self.signing.email.clone().context(
+                "Signed commits need an email. Run 'axkeystore signing enable --email ...'.",
+            )?;
+            let timestamp = crate::timeutil::now_unix();
+            let payload = crate::signing::build_commit_payload(
+                &tree.sha, &parent, &name, &email, timestamp, message,
             );
+            let identity = serde_json::json!({
+                "name": name,
+                "email": email,
+                "date": crate::timeutil::format_rfc3339(timestamp),
+            });
+            commit["author"] = identity.clone();
+            commit["committer"] = identity;
+            commit["signature"] =
+                serde_json::Value::String(crate::signing::sign_payload(&self.signing, &payload)?);
+        }
 
-            let res = self
-                .client
-                .get(&url)
-                .bearer_auth(&self.token)
-                .send()
-                .await?;
+        let created: GitObject = self
+            .send_json(
+                reqwest::Method::POST,
+                &self.git_url("commits"),
+                Some(&commit),
+            )
+            .await?;
 
-            if res.status() == reqwest::StatusCode::NOT_FOUND {
-                // If the root keys/ directory doesn't exist, we just continue (it means repo is empty)
-                // If a subdir disappears mid-flight, we also just skip it.
-                continue;
-            }
+        let _: serde_json::Value = self
+            .send_json(
+                reqwest::Method::PATCH,
+                &self.git_url(&format!("refs/heads/{}", branch)),
+                Some(&serde_json::json!({ "sha": created.sha, "force": false })),
+            )
+            .await
+            .context("Failed to update branch; it may have been changed concurrently")?;
 
-            if !res.status().is_success() {
+        Ok(created.sha)
+    }
+
+    /// Removes every file matching a predicate from all commits of the vault branch, using
+    /// the Git Data API, and force-updates the branch to the rewritten history. Commits that
+    /// are recreated keep their author, committer, dates and message, but lose any signature.
+    pub async fn rewrite_history(&self, remove: impl Fn(&str) -> bool) -> Result<HistoryRewrite> {
+        let branch = self.vault_branch().await?;
+        let head = self
+            .branch_head(&branch)
+            .await?
+            .with_context(|| format!("Branch '{}' has no commits yet", branch))?;
+
+        // Load every commit reachable from the head
+        let mut commits: HashMap<String, GitCommitObject> = HashMap::new();
+        let mut pending = vec![head.clone()];
+        while let Some(sha) = pending.pop() {
+            if commits.contains_key(&sha) {
+                continue;
+            }
+            let commit: GitCommitObject = self
+                .send_json(
+                    reqwest::Method::GET,
+                    &self.git_url(&format!("commits/{}", sha)),
+                    None,
+                )
+                .await?;
+            pending.extend(commit.parents.iter().map(|p| p.sha.clone()));
+            commits.insert(sha, commit);
+        }
+
+        // Order them so that parents come before their children
+        let mut order = Vec::new();
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![(head.clone(), false)];
+        while let Some((sha, parents_done)) = stack.pop() {
+            if parents_done {
+                order.push(sha);
+                continue;
+            }
+            if !visited.insert(sha.clone()) {
+                continue;
+            }
+            stack.push((sha.clone(), true));
+            for parent in &commits[&sha].parents {
+                if !visited.contains(&parent.sha) {
+                    stack.push((parent.sha.clone(), false));
+                }
+            }
+        }
+
+        let mut report = HistoryRewrite::default();
+        let mut rewritten: HashMap<String, String> = HashMap::new();
+        for sha in order {
+            let commit = &commits[&sha];
+            let parents: Vec<String> = commit
+                .parents
+                .iter()
+                .map(|p| rewritten[&p.sha].clone())
+                .collect();
+
+            let listing: GitTreeListing = self
+                .send_json(
+                    reqwest::Method::GET,
+                    &self.git_url(&format!("trees/{}?recursive=1", commit.tree.sha)),
+                    None,
+                )
+                .await
+                .with_context(|| format!("Failed to list the tree of commit {}", sha))?;
+            if listing.truncated {
                 return Err(anyhow::anyhow!(
-                    "Failed to list directory '{}': {}",
-                    current_dir,
-                    res.status()
+                    "The tree of commit {} is too large to be listed in a single request",
+                    sha
                 ));
             }
+            let removed: Vec<String> = listing
+                .tree
+                .into_iter()
+                .filter(|item| {
+                    item.item_type == "blob" && self.vault_path(&item.path).is_some_and(&remove)
+                })
+                .map(|item| item.path)
+                .collect();
+
+            let tree = if removed.is_empty() {
+                commit.tree.sha.clone()
+            } else {
+                let entries: Vec<serde_json::Value> = removed
+                    .iter()
+                    .map(|path| {
+                        serde_json::json!({
+                            "path": path, "mode": "100644", "type": "blob", "sha": null
+                        })
+                    })
+                    .collect();
+                report.removed.extend(removed);
+                let tree: GitObject = self
+                    .send_json(
+                        reqwest::Method::POST,
+                        &self.git_url("trees"),
+                        Some(&serde_json::json!({
+                            "base_tree": commit.tree.sha, "tree": entries
+                        })),
+                    )
+                    .await?;
+                tree.sha
+            };
+
+            let unchanged = tree == commit.tree.sha
+                && parents
+                    .iter()
+                    .zip(&commit.parents)
+                    .all(|(new, old)| new == &old.sha);
+            if unchanged {
+                rewritten.insert(sha.clone(), sha);
+                continue;
+            }
+            let created: GitObject = self
+                .send_json(
+                    reqwest::Method::POST,
+                    &self.git_url("commits"),
+                    Some(&serde_json::json!({
+                        "message": commit.message,
+                        "tree": tree,
+                        "parents": parents,
+                        "author": commit.author,
+                        "committer": commit.committer,
+                    })),
+                )
+                .await?;
+            report.commits += 1;
+            rewritten.insert(sha, created.sha);
+        }
 
-            // GitHub Contents API can return an object for a file, or an array for a directory.
-            // Since we know current_dir is a directory, it should be an array.
-            let items: Vec<ContentsItem> = res.json().await?;
+        let new_head = &rewritten[&head];
+        if new_head == &head {
+            return Ok(report);
+        }
+        if self.branch_head(&branch).await?.as_deref() != Some(head.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Branch '{}' changed while its history was being rewritten. Nothing was changed; run the command again.",
+                branch
+            ));
+        }
+        let _: serde_json::Value = self
+            .send_json(
+                reqwest::Method::PATCH,
+                &self.git_url(&format!("refs/heads/{}", branch)),
+                Some(&serde_json::json!({ "sha": new_head, "force": true })),
+            )
+            .await
+            .context("Failed to update the branch to the rewritten history")?;
+        Ok(report)
+    }
 
-            for item in items {
-                if item.item_type == "dir" {
-                    // Queue subdirectory for visiting
-                    dirs_to_visit.push(item.path);
-                } else if item.item_type == "file" && item.name.ends_with(".json") {
-                    // Parse category and key name from the path
-                    // Path format: keys/name.json or keys/cat/sub/name.json
-                    let relative = if item.path.starts_with("keys/") {
-                        &item.path[5..] // Strip "keys/" prefix
-                    } else {
-                        &item.path // Fallback, shouldn't happen unless GitHub acts weirdly
-                    };
+    /// Replaces every commit of the vault branch up to and including `until` with a single
+    /// commit of its tree, and replays the newer commits on top of it (keeping their author,
+    /// committer, dates and message, but not their signatures). The history must be linear.
+    /// Returns the new head of the branch.
+    pub async fn squash_history(&self, until: &str, message: &str) -> Result<String> {
+        if let Some(prefix) = &self.prefix {
+            return Err(anyhow::anyhow!(
+                "The vault shares its repository (under '{}'), and squashing would rewrite the history of the other content too",
+                prefix
+            ));
+        }
+        let branch = self.vault_branch().await?;
+        let head = self
+            .branch_head(&branch)
+            .await?
+            .with_context(|| format!("Branch '{}' has no commits yet", branch))?;
+
+        // Collect the commits after `until`, newest first
+        let mut newer: Vec<GitCommitObject> = Vec::new();
+        let mut sha = head.clone();
+        while sha != until {
+            let commit: GitCommitObject = self
+                .send_json(
+                    reqwest::Method::GET,
+                    &self.git_url(&format!("commits/{}", sha)),
+                    None,
+                )
+                .await?;
+            sha = match commit.parents.as_slice() {
+                [parent] => parent.sha.clone(),
+                [] => {
+                    return Err(anyhow::anyhow!(
+                        "Commit {} is not on branch '{}'",
+                        until,
+                        branch
+                    ))
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Branch '{}' has merge commits; only a linear history can be squashed",
+                        branch
+                    ))
+                }
+            };
+            newer.push(commit);
+        }
 
-                    let key_name = item.name.trim_end_matches(".json").to_string();
+        let base: GitCommitObject = self
+            .send_json(
+                reqwest::Method::GET,
+                &self.git_url(&format!("commits/{}", until)),
+                None,
+            )
+            .await?;
+        let mut parent: GitObject = self
+            .send_json(
+                reqwest::Method::POST,
+                &self.git_url("commits"),
+                Some(&serde_json::json!({
+                    "message": message,
+                    "tree": base.tree.sha,
+                    "parents": [],
+                })),
+            )
+            .await?;
+        for commit in newer.iter().rev() {
+            parent = self
+                .send_json(
+                    reqwest::Method::POST,
+                    &self.git_url("commits"),
+                    Some(&serde_json::json!({
+                        "message": commit.message,
+                        "tree": commit.tree.sha,
+                        "parents": [parent.sha],
+                        "author": commit.author,
+                        "committer": commit.committer,
+                    })),
+                )
+                .await?;
+        }
 
-                    let category = if let Some(slash_pos) = relative.rfind('/') {
-                        Some(relative[..slash_pos].to_string())
+        if self.branch_head(&branch).await?.as_deref() != Some(head.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Branch '{}' changed while its history was being rewritten. Nothing was changed; run the command again.",
+                branch
+            ));
+        }
+        let _: serde_json::Value = self
+            .send_json(
+                reqwest::Method::PATCH,
+                &self.git_url(&format!("refs/heads/{}", branch)),
+                Some(&serde_json::json!({ "sha": parent.sha, "force": true })),
+            )
+            .await
+            .context("Failed to update the branch to the rewritten history")?;
+        Ok(parent.sha)
+    }
+
+    /// Returns true if writes should go through a signed Git Data API commit.
+    /// The very first commit of an empty repository cannot be signed, so it falls back to the Contents API.
+    async fn use_signed_commit(&self) -> Result<bool> {
+        if !self.signing.is_enabled() {
+            return Ok(false);
+        }
+        let branch = self.vault_branch().await?;
+        if self.branch_head(&branch).await?.is_some() {
+            return Ok(true);
+        }
+        tracing::warn!("Warning: The repository is empty, so this first commit cannot be signed.");
+        Ok(false)
+    }
+
+    /// Creates or updates a file at the given repository path
+    pub async fn put_file(&self, file_path: &str, data: &[u8], message: &str) -> Result<()> {
+        if self.use_signed_commit().await? {
+            let change = TreeChange::Upsert {
+                path: file_path.to_string(),
+                content: data.to_vec(),
+            };
+            self.commit_changes(&[change], message)
+                .await
+                .with_context(|| format!("Failed to save '{}'", file_path))?;
+            return Ok(());
+        }
+
+        // Check if file exists to get SHA (for update)
+        let sha = self.get_file(file_path).await?.map(|(_, sha)| sha);
+        self.put_file_at(file_path, data, message, sha).await
+    }
+
+    /// Writes a file through the Contents API, replacing the version with the given SHA (None
+    /// if the file must not exist yet). Fails with a [`WriteConflict`] if the file changed.
+    async fn put_file_at(
+        &self,
+        file_path: &str,
+        data: &[u8],
+        message: &str,
+        sha: Option<String>,
+    ) -> Result<()> {
+        let body = UpdateFileRequest {
+            message: message.to_string(),
+            content: BASE64.encode(data),
+            sha,
+            branch: self.branch.clone(),
+        };
+
+        let res = self
+            .client
+            .put(self.contents_url(file_path))
+            .bearer_auth(&self.token)
+            .json(&body)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            // GitHub answers 409 for a stale SHA, and 422 when a SHA is missing because
+            // the file was created in the meantime
+            if status == reqwest::StatusCode::CONFLICT
+                || (status == reqwest::StatusCode::UNPROCESSABLE_ENTITY && text.contains("sha"))
+            {
+                return Err(self.conflict(file_path).await?.into());
+            }
+            return Err(anyhow::anyhow!(
+                "Failed to save '{}': {} - {}",
+                file_path,
+                status,
+                redact::redact(&text)
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Describes a write that lost the race against another one, with the file's SHA now
+    async fn conflict(&self, file_path: &str) -> Result<WriteConflict> {
+        Ok(WriteConflict {
+            path: file_path.to_string(),
+            current_sha: self.get_file(file_path).await?.map(|(_, sha)| sha),
+        })
+    }
+
+    /// Creates a file only if it does not exist yet, returning false if it already existed.
+    /// Safe against concurrent writers: GitHub rejects a create without a SHA when the file exists.
+    pub async fn create_file(&self, file_path: &str, data: &[u8], message: &str) -> Result<bool> {
+        if self.use_signed_commit().await? {
+            if self.get_file(file_path).await?.is_some() {
+                return Ok(false);
+            }
+            let change = TreeChange::Upsert {
+                path: file_path.to_string(),
+                content: data.to_vec(),
+            };
+            // The branch update is fast-forward only, so a concurrent writer makes it fail
+            return match self.commit_changes(&[change], message).await {
+                Ok(_) => Ok(true),
+                Err(e) => {
+                    if self.get_file(file_path).await?.is_some() {
+                        Ok(false)
                     } else {
-                        None
-                    };
+                        Err(e.context(format!("Failed to create '{}'", file_path)))
+                    }
+                }
+            };
+        }
 
-                    // Fetch the file content
-                    let data = self.get_file_content_by_path(&item.path).await?;
+        let body = UpdateFileRequest {
+            message: message.to_string(),
+            content: BASE64.encode(data),
+            sha: None,
+            branch: self.branch.clone(),
+        };
 
-                    entries.push(KeyEntry {
-                        name: key_name,
-                        category,
-                        data,
-                    });
+        let res = self
+            .client
+            .put(self.contents_url(file_path))
+            .bearer_auth(&self.token)
+            .json(&body)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+
+        let status = res.status();
+        if !status.is_success() {
+            let text = res.text().await.unwrap_or_default();
+            // GitHub answers 422 for a missing SHA when the file already exists; other 422s
+            // are validation errors
+            if status == reqwest::StatusCode::CONFLICT
+                || (status == reqwest::StatusCode::UNPROCESSABLE_ENTITY && text.contains("sha"))
+            {
+                return Ok(false);
+            }
+            return Err(anyhow::anyhow!(
+                "Failed to create '{}': {} - {}",
+                file_path,
+                status,
+                redact::redact(&text)
+            ));
+        }
+
+        Ok(true)
+    }
+
+    /// Deletes a file at the given repository path, returning false if it did not exist
+    pub async fn delete_file(&self, file_path: &str, message: &str) -> Result<bool> {
+        // First, get the file to retrieve its SHA (required for deletion)
+        let sha = match self.get_file(file_path).await? {
+            Some((_, sha)) => sha,
+            None => return Ok(false),
+        };
+
+        if self.use_signed_commit().await? {
+            let change = TreeChange::Delete {
+                path: file_path.to_string(),
+            };
+            self.commit_changes(&[change], message)
+                .await
+                .with_context(|| format!("Failed to delete '{}'", file_path))?;
+            return Ok(true);
+        }
+
+        let mut body = serde_json::json!({
+            "message": message,
+            "sha": sha
+        });
+        if let Some(branch) = &self.branch {
+            body["branch"] = serde_json::json!(branch);
+        }
+
+        let res = self
+            .client
+            .delete(self.contents_url(file_path))
+            .bearer_auth(&self.token)
+            .json(&body)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Failed to delete '{}': {} - {}",
+                file_path,
+                status,
+                redact::redact(&text)
+            ));
+        }
+
+        Ok(true)
+    }
+
+    /// Lists the immediate children of a repository directory (empty if it does not exist)
+    pub async fn list_dir(&self, dir_path: &str) -> Result<Vec<DirEntry>> {
+        let res = self
+            .client
+            .get(self.contents_read_url(dir_path))
+            .bearer_auth(&self.token)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to list directory '{}': {}",
+                dir_path,
+                res.status()
+            ));
+        }
+
+        let items: Vec<ContentsItem> = res.json().await?;
+        Ok(items
+            .into_iter()
+            .map(|item| DirEntry {
+                is_dir: item.item_type == "dir",
+                path: self
+                    .vault_path(&item.path)
+                    .unwrap_or(&item.path)
+                    .to_string(),
+                name: item.name,
+            })
+            .collect())
+    }
+
+    /// Fetches the encrypted master key blob from the hidden application directory
+    pub async fn get_master_key_blob(&self) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .get_file(MASTER_KEY_PATH)
+            .await
+            .context("Failed to fetch master key")?
+            .map(|(data, _)| data))
+    }
+
+    /// Saves the encrypted master key blob to the repository
+    pub async fn save_master_key_blob(&self, data: &[u8]) -> Result<()> {
+        self.put_file(MASTER_KEY_PATH, data, "Initialize master key")
+            .await
+            .context("Failed to save master key")
+    }
+
+    /// Fetches the current encrypted data and SHA for a specific key
+    pub async fn get_blob(
+        &self,
+        key: &str,
+        category: Option<&str>,
+    ) -> Result<Option<(Vec<u8>, String)>> {
+        let path = Self::build_key_path(key, category)?;
+        match self.get_file(&path).await? {
+            Some((data, sha)) => Ok(Some((self.resolve_chunks(&path, data, None).await?, sha))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the path of a blob's chunk
+    fn chunk_path(path: &str, index: usize) -> String {
+        format!("{}.part{:03}", path, index)
+    }
+
+    /// Parses a chunk manifest, returning None for a regular blob
+    fn parse_manifest(data: &[u8]) -> Option<ChunkManifest> {
+        serde_json::from_slice::<ManifestFile>(data)
+            .ok()
+            .map(|m| m.chunked)
+    }
+
+    /// Returns the repository path of a shared blob
+    pub fn dedup_path(id: &str) -> String {
+        format!("{}/{}.json", DEDUP_DIR, id)
+    }
+
+    /// Returns the content of a key file that points to a shared blob
+    pub fn dedup_pointer(id: &str) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&PointerFile {
+            dedup: id.to_string(),
+        })?)
+    }
+
+    /// Parses a pointer to a shared blob, returning its id, or None for any other blob
+    pub fn parse_pointer(data: &[u8]) -> Option<String> {
+        serde_json::from_slice::<PointerFile>(data)
+            .ok()
+            .map(|p| p.dedup)
+    }
+
+    /// Follows a pointer to a shared blob and reassembles a chunked blob from its manifest
+    /// (optionally as of a commit), verifying its size and checksum. Regular blobs are returned
+    /// unchanged.
+    async fn resolve_chunks(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        sha: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let (path, data) = match Self::parse_pointer(&data) {
+            Some(id) => {
+                let shared = Self::dedup_path(&id);
+                let data = match sha {
+                    Some(sha) => self.get_file_at_ref(&shared, sha).await?,
+                    None => self.get_file(&shared).await?.map(|(d, _)| d),
                 }
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Shared blob '{}' of '{}' is missing", shared, path)
+                })?;
+                (shared, data)
             }
+            None => (path.to_string(), data),
+        };
+        let path = path.as_str();
+
+        let manifest = match Self::parse_manifest(&data) {
+            Some(m) => m,
+            None => return Ok(data),
+        };
+
+        let mut blob = Vec::with_capacity(manifest.size);
+        for index in 0..manifest.chunks {
+            let chunk_path = Self::chunk_path(path, index);
+            let chunk = match sha {
+                Some(sha) => self.get_file_at_ref(&chunk_path, sha).await?,
+                None => self.get_file(&chunk_path).await?.map(|(d, _)| d),
+            };
+            blob.extend(chunk.ok_or_else(|| {
+                anyhow::anyhow!("Chunk '{}' of '{}' is missing", chunk_path, path)
+            })?);
         }
 
-        Ok(entries)
+        if blob.len() != manifest.size || sha256_hex(&blob) != manifest.sha256 {
+            return Err(anyhow::anyhow!(
+                "Chunked blob '{}' is corrupted (size or checksum mismatch)",
+                path
+            ));
+        }
+        Ok(blob)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use wiremock::matchers::{method, path};
-    use wiremock::{Mock, MockServer, ResponseTemplate};
+    /// Returns the number of chunks the blob currently stored at a path is split into
+    async fn existing_chunk_count(&self, path: &str) -> Result<usize> {
+        Ok(self
+            .get_file(path)
+            .await?
+            .and_then(|(data, _)| Self::parse_manifest(&data))
+            .map_or(0, |m| m.chunks))
+    }
+
+    /// Builds the changes that store a blob at a path, splitting it into chunks when it is too
+    /// large for the Contents API and removing chunks left over from a previous, larger version
+    fn blob_changes(path: &str, data: &[u8], old_chunks: usize) -> Result<Vec<TreeChange>> {
+        let mut changes = Vec::new();
+        let mut new_chunks = 0;
+
+        if data.len() <= MAX_FILE_SIZE {
+            changes.push(TreeChange::Upsert {
+                path: path.to_string(),
+                content: data.to_vec(),
+            });
+        } else {
+            for (index, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+                changes.push(TreeChange::Upsert {
+                    path: Self::chunk_path(path, index),
+                    content: chunk.to_vec(),
+                });
+                new_chunks += 1;
+            }
+            let manifest = ManifestFile {
+                chunked: ChunkManifest {
+                    chunks: new_chunks,
+                    size: data.len(),
+                    sha256: sha256_hex(data),
+                },
+            };
+            changes.push(TreeChange::Upsert {
+                path: path.to_string(),
+                content: serde_json::to_vec_pretty(&manifest)?,
+            });
+        }
+
+        for index in new_chunks..old_chunks {
+            changes.push(TreeChange::Delete {
+                path: Self::chunk_path(path, index),
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Applies several changes in a single commit, or one commit per file if the repository
+    /// has no commits yet (the Git Data API cannot create the first commit)
+    pub async fn apply_changes(&self, changes: &[TreeChange], message: &str) -> Result<()> {
+        let branch = self.vault_branch().await?;
+        if self.branch_head(&branch).await?.is_some() {
+            self.commit_changes(changes, message).await?;
+            return Ok(());
+        }
+
+        for change in changes {
+            match change {
+                TreeChange::Upsert { path, content } => {
+                    self.put_file(path, content, message).await?
+                }
+                TreeChange::Delete { path } => {
+                    self.delete_file(path, message).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches the encrypted data for a key at a specific commit version
+    pub async fn get_blob_at_version(
+        &self,
+        key: &str,
+        category: Option<&str>,
+        sha: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let path = Self::build_key_path(key, category)?;
+        match self.get_file_at_ref(&path, sha).await? {
+            Some(data) => Ok(Some(self.resolve_chunks(&path, data, Some(sha)).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the size in bytes of a key's stored blob as of a commit, read from the tree of
+    /// its directory without downloading it. Chunked blobs count the size of their chunks.
+    /// Returns None if the key did not exist at that commit.
+    pub async fn blob_size_at_version(
+        &self,
+        key: &str,
+        category: Option<&str>,
+        sha: &str,
+    ) -> Result<Option<u64>> {
+        let path = Self::build_key_path(key, category)?;
+        let (dir, file_name) = path.rsplit_once('/').unwrap_or(("", &path));
+
+        let res = self
+            .client
+            .get(self.git_url(&format!("trees/{}:{}", sha, self.repo_path(dir))))
+            .bearer_auth(&self.token)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+
+        // The directory itself may not have existed at that commit
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to fetch the tree at version {}: {}",
+                sha,
+                res.status()
+            ));
+        }
+
+        let listing: GitTreeListing = res.json().await?;
+        Ok(stored_size(&listing.tree, file_name))
+    }
+
+    /// Fetches the decoded content of a file as of a specific commit
+    async fn get_file_at_ref(&self, path: &str, sha: &str) -> Result<Option<Vec<u8>>> {
+        let url = format!("{}?ref={}", self.contents_url(path), sha);
+
+        let res = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.token)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to fetch key at version {}: {}",
+                sha,
+                res.status()
+            ));
+        }
+
+        let file_res: FileResponse = res.json().await?;
+        let content_clean = file_res.content.replace('\n', "");
+        let decoded = BASE64
+            .decode(content_clean)
+            .context("Failed to decode base64 content from GitHub")?;
+
+        Ok(Some(decoded))
+    }
+
+    /// Retrieves the list of versions (commits) for a specific key
+    pub async fn get_key_history(
+        &self,
+        key: &str,
+        category: Option<&str>,
+        page: u32,
+        per_page: u32,
+    ) -> Result<Vec<KeyVersion>> {
+        self.get_filtered_key_history(key, category, &HistoryFilter::default(), page, per_page)
+            .await
+    }
+
+    /// Fetches a page of the commits that touched a key within a period (newest first)
+    pub async fn get_filtered_key_history(
+        &self,
+        key: &str,
+        category: Option<&str>,
+        filter: &HistoryFilter,
+        page: u32,
+        per_page: u32,
+    ) -> Result<Vec<KeyVersion>> {
+        let path = self.repo_path(&Self::build_key_path(key, category)?);
+        let url = format!(
+            "{}/repos/{}/{}/commits",
+            self.api_base, self.owner, self.repo
+        );
+
+        let mut query = vec![
+            ("path", path),
+            ("page", page.to_string()),
+            ("per_page", per_page.to_string()),
+        ];
+        if let Some(since) = filter.since {
+            query.push(("since", crate::timeutil::format_rfc3339(since)));
+        }
+        if let Some(until) = filter.until {
+            query.push(("until", crate::timeutil::format_rfc3339(until)));
+        }
+        if let Some(branch) = &self.branch {
+            query.push(("sha", branch.clone()));
+        }
+        let res = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.token)
+            .query(&query)
+            .send_logged()
+            .await?;
+        self.check_response(&res);
+
+        if !res.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Failed to fetch key history: {}",
+                res.status()
+            ));
+        }
+
+        let commits: Vec<GitHubCommit> = res.json().await?;
+        Ok(commits.into_iter().map(KeyVersion::from).collect())
+    }
+
+    /// Returns every commit on the vault branch after a point (newest first). `since` is
+    /// either a date ('2024-01-01' or an RFC 3339 timestamp) or a git ref such as a tag or
+    /// commit SHA, in which case the commits reachable from the branch but not from the ref
+    /// are returned.
+    pub async fn commits_since(&self, since: &str) -> Result<Vec<KeyVersion>> {
+        let branch = self.vault_branch().await?;
+        if let Ok(ts) = crate::timeutil::parse_timestamp(since) {
+            return self
+                .commits(&branch, Some(ts))
+                .try_collect()
+                .await
+                .context("Failed to list commits");
+        }
+
+        let commits: Vec<GitHubCommit> = paginate(PAGE_SIZE, |page| {
+            let url = format!(
+                "{}/repos/{}/{}/compare/{}...{}?page={}&per_page={}",
+                self.api_base, self.owner, self.repo, since, branch, page, PAGE_SIZE
+            );
+            async move {
+                let compare: CompareResponse =
+                    self.send_json(reqwest::Method::GET, &url, None).await?;
+                Ok(compare.commits)
+            }
+        })
+        .try_collect()
+        .await
+        .with_context(|| format!("Failed to compare '{}' with '{}'", since, branch))?;
+
+        // The compare endpoint lists oldest first
+        Ok(commits.into_iter().rev().map(KeyVersion::from).collect())
+    }
+
+    /// Streams every commit that touched a key within a period, newest first
+    pub fn key_history_stream<'a>(
+        &'a self,
+        key: &'a str,
+        category: Option<&'a str>,
+        filter: HistoryFilter,
+    ) -> impl Stream<Item = Result<KeyVersion>> + 'a {
+        paginate(PAGE_SIZE, move |page| async move {
+            self.get_filtered_key_history(key, category, &filter, page, PAGE_SIZE)
+                .await
+        })
+        .try_filter(move |version| std::future::ready(filter.matches(version)))
+    }
+
+    /// Returns every commit that touched a key (newest first), following all pages
+    pub async fn get_full_key_history(
+        &self,
+        key: &str,
+        category: Option<&str>,
+    ) -> Result<Vec<KeyVersion>> {
+        self.key_history_stream(key, category, HistoryFilter::default())
+            .try_collect()
+            .await
+    }
+
+    /// Returns the most recent commit that touched a key, if any
+    pub async fn get_latest_key_version(
+        &self,
+        key: &str,
+        category: Option<&str>,
+    ) -> Result<Option<KeyVersion>> {
+        Ok(self
+            .get_key_history(key, category, 1, 1)
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Uploads or updates an encrypted key blob to the repository. Fails with a
+    /// [`WriteConflict`] if another write to the key lands between reading its SHA and
+    /// saving.
+    pub async fn save_blob(&self, key: &str, data: &[u8], category: Option<&str>) -> Result<()> {
+        let path = Self::build_key_path(key, category)?;
+        let old_chunks = self.existing_chunk_count(&path).await?;
+        if data.len() <= MAX_FILE_SIZE && old_chunks == 0 {
+            return self
+                .put_file(&path, data, &Self::update_message(key, category))
+                .await;
+        }
+
+        let changes = Self::blob_changes(&path, data, old_chunks)?;
+        self.apply_changes(&changes, &Self::update_message(key, category))
+            .await
+    }
+
+    /// Like [`Storage::save_blob`], but only replaces the version of the key with the given
+    /// SHA, as returned by [`Storage::get_blob`] (None if the key must not exist yet). Fails
+    /// with a [`WriteConflict`] if the key changed since that version was read.
+    pub async fn save_blob_expecting(
+        &self,
+        key: &str,
+        data: &[u8],
+        category: Option<&str>,
+        expected_sha: Option<&str>,
+    ) -> Result<()> {
+        let path = Self::build_key_path(key, category)?;
+        let message = Self::update_message(key, category);
+        let old_chunks = self.existing_chunk_count(&path).await?;
+        if data.len() <= MAX_FILE_SIZE && old_chunks == 0 && !self.use_signed_commit().await? {
+            return self
+                .put_file_at(&path, data, &message, expected_sha.map(|s| s.to_string()))
+                .await;
+        }
+
+        // Commits created through the Git Data API do not check the file's SHA
+        let conflict = self.conflict(&path).await?;
+        if conflict.current_sha.as_deref() != expected_sha {
+            return Err(conflict.into());
+        }
+        let changes = Self::blob_changes(&path, data, old_chunks)?;
+        self.apply_changes(&changes, &message).await
+    }
+
+    /// Returns the commit message of a key update
+    pub fn update_message(key: &str, category: Option<&str>) -> String {
+        match category {
+            Some(cat) => format!("Update key: {}/{}", cat.trim_matches('/'), key),
+            None => format!("Update key: {}", key),
+        }
+    }
+
+    /// Uploads an encrypted key blob only if the key does not exist yet, returning false if it did
+    pub async fn create_blob(
+        &self,
+        key: &str,
+        data: &[u8],
+        category: Option<&str>,
+    ) -> Result<bool> {
+        let path = Self::build_key_path(key, category)?;
+
+        let commit_message = match category {
+            Some(cat) => format!("Create key: {}/{}", cat.trim_matches('/'), key),
+            None => format!("Create key: {}", key),
+        };
+
+        if data.len() <= MAX_FILE_SIZE {
+            return self.create_file(&path, data, &commit_message).await;
+        }
+
+        // Chunked blobs are written in one fast-forward-only commit, so a concurrent
+        // writer makes it fail instead of being overwritten
+        if self.get_file(&path).await?.is_some() {
+            return Ok(false);
+        }
+        let changes = Self::blob_changes(&path, data, 0)?;
+        match self.apply_changes(&changes, &commit_message).await {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                if self.get_file(&path).await?.is_some() {
+                    Ok(false)
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Deletes a key from the repository
+    pub async fn delete_blob(&self, key: &str, category: Option<&str>) -> Result<bool> {
+        let path = Self::build_key_path(key, category)?;
+
+        let commit_message = match category {
+            Some(cat) => format!("Delete key: {}/{}", cat.trim_matches('/'), key),
+            None => format!("Delete key: {}", key),
+        };
+
+        if self.existing_chunk_count(&path).await? == 0 {
+            return self.delete_file(&path, &commit_message).await;
+        }
+
+        let changes = self.key_changes(key, category, None).await?;
+        self.apply_changes(&changes, &commit_message).await?;
+        Ok(true)
+    }
+
+    /// Builds the changes that write (Some) or remove (None) a key blob, including its chunks,
+    /// so several keys can be changed in a single commit with commit_changes
+    pub async fn key_changes(
+        &self,
+        key: &str,
+        category: Option<&str>,
+        data: Option<&[u8]>,
+    ) -> Result<Vec<TreeChange>> {
+        let path = Self::build_key_path(key, category)?;
+        self.path_changes(&path, data).await
+    }
+
+    /// Builds the changes that write (Some) or remove (None) a file at any repository path,
+    /// chunking it like a key blob. Removing a file that does not exist yields no changes.
+    pub async fn path_changes(&self, path: &str, data: Option<&[u8]>) -> Result<Vec<TreeChange>> {
+        let existing = self.get_file(path).await?;
+        let old_chunks = existing
+            .as_ref()
+            .and_then(|(data, _)| Self::parse_manifest(data))
+            .map_or(0, |m| m.chunks);
+
+        match data {
+            Some(data) => Self::blob_changes(path, data, old_chunks),
+            None if existing.is_none() => Ok(Vec::new()),
+            None => {
+                let mut changes: Vec<TreeChange> = (0..old_chunks)
+                    .map(|index| TreeChange::Delete {
+                        path: Self::chunk_path(path, index),
+                    })
+                    .collect();
+                changes.push(TreeChange::Delete {
+                    path: path.to_string(),
+                });
+                Ok(changes)
+            }
+        }
+    }
+
+    /// Reads a file written with path_changes, reassembling it if it was chunked
+    pub async fn read_file(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        match self.get_file(path).await? {
+            Some((data, _)) => Ok(Some(self.resolve_chunks(path, data, None).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Lists the paths of all files below a repository directory, leaving out chunk files
+    pub async fn list_files_recursive(&self, dir_path: &str) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        let mut dirs_to_visit = vec![dir_path.to_string()];
+        while let Some(dir) = dirs_to_visit.pop() {
+            for entry in self.list_dir(&dir).await? {
+                if entry.is_dir {
+                    dirs_to_visit.push(entry.path);
+                } else if !Self::is_chunk_path(&entry.path) {
+                    files.push(entry.path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Returns true for the chunk files of a chunked blob ('<path>.partNNN')
+    fn is_chunk_path(path: &str) -> bool {
+        path.rsplit_once(".part")
+            .is_some_and(|(_, n)| n.len() == 3 && n.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    /// Lists all stored keys across all categories by listing the keys/ directory recursively
+    pub async fn list_all_keys(&self) -> Result<Vec<KeyEntry>> {
+        let mut entries = Vec::new();
+        let mut dirs_to_visit = vec!["keys".to_string()];
+
+        while let Some(current_dir) = dirs_to_visit.pop() {
+            crate::cancel::check()?;
+            let url = self.contents_read_url(&current_dir);
+
+            let res = self
+                .client
+                .get(&url)
+                .bearer_auth(&self.token)
+                .send_logged()
+                .await?;
+            self.check_response(&res);
+
+            if res.status() == reqwest::StatusCode::NOT_FOUND {
+                // If the root keys/ directory doesn't exist, we just continue (it means repo is empty)
+                // If a subdir disappears mid-flight, we also just skip it.
+                continue;
+            }
+
+            if !res.status().is_success() {
+                return Err(anyhow::anyhow!(
+                    "Failed to list directory '{}': {}",
+                    current_dir,
+                    res.status()
+                ));
+            }
+
+            // GitHub Contents API can return an object for a file, or an array for a directory.
+            // Since we know current_dir is a directory, it should be an array.
+            let mut items: Vec<ContentsItem> = res.json().await?;
+
+            for item in &mut items {
+                if let Some(path) = self.vault_path(&item.path) {
+                    item.path = path.to_string();
+                }
+            }
+            let mut files = Vec::new();
+            for item in items {
+                if item.item_type == "dir" {
+                    // Queue subdirectory for visiting
+                    dirs_to_visit.push(item.path);
+                } else if item.item_type == "file" && item.name.ends_with(".json") {
+                    files.push(item);
+                }
+            }
+
+            // The files of a directory are fetched a few at a time
+            let fetched: Vec<KeyEntry> = stream::iter(files)
+                .map(|item| async move {
+                    // Parse category and key name from the path
+                    // Path format: keys/name.json or keys/cat/sub/name.json
+                    let relative = if item.path.starts_with("keys/") {
+                        &item.path[5..] // Strip "keys/" prefix
+                    } else {
+                        &item.path // Fallback, shouldn't happen unless GitHub acts weirdly
+                    };
+
+                    let key_name = item.name.trim_end_matches(".json").to_string();
+
+                    let category = if let Some(slash_pos) = relative.rfind('/') {
+                        Some(relative[..slash_pos].to_string())
+                    } else {
+                        None
+                    };
+
+                    // Fetch the file content
+                    let data = self
+                        .get_file(&item.path)
+                        .await?
+                        .map(|(data, _)| data)
+                        .ok_or_else(|| anyhow::anyhow!("File '{}' disappeared", item.path))?;
+                    let data = self.resolve_chunks(&item.path, data, None).await?;
+
+                    Ok::<_, anyhow::Error>(KeyEntry {
+                        name: key_name,
+                        category,
+                        data,
+                    })
+                })
+                .buffer_unordered(self.parallelism)
+                .try_collect()
+                .await?;
+            entries.extend(fetched);
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_storage_caches_login() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+        crate::auth::save_token_with_profile(None, "saved_token", "test-pass").unwrap();
+
+        // Only the first of two commands looks the login up
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "login": "testuser"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        assert!(!storage.login_cached);
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        assert!(storage.login_cached);
+        assert_eq!(storage.repo_full_name(), "testuser/test-repo");
+        assert!(Config::get_login_with_profile(None, "test-pass")
+            .unwrap()
+            .is_some());
+
+        // A rejected token makes the next command look the login up again
+        assert!(storage.is_private().await.is_err());
+        assert!(Config::get_login_with_profile(None, "test-pass")
+            .unwrap()
+            .is_none());
+
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_init_repo_exists() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        // 1. Mock User endpoint
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "login": "testuser"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        // 2. Mock Repo Check (Existing)
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo"))
+            .respond_with(ResponseTemplate::new(200)) // 200 OK means exists
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.init_repo().await.unwrap(),
+            RepoStatus {
+                created: false,
+                branch: None
+            }
+        );
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_init_repo_not_found() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        // User
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Check (Not Found)
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/missing-repo"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "missing-repo", "test-pass")
+            .await
+            .unwrap();
+
+        let result = storage.init_repo().await;
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("not found"));
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_init_repo_creates_in_org() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/myorg/axkeystore-storage"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/orgs/myorg/repos"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "name": "axkeystore-storage",
+                "private": true
+            })))
+            .respond_with(
+                ResponseTemplate::new(201)
+                    .set_body_json(serde_json::json!({ "full_name": "myorg/axkeystore-storage" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "myorg/axkeystore-storage", "test-pass")
+            .await
+            .unwrap();
+        assert_eq!(storage.repo_full_name(), "myorg/axkeystore-storage");
+        assert!(storage.init_repo().await.unwrap().created);
+
+        // An organization that does not let the user create repositories
+        let denied = Storage::new_with_profile(None, "otherorg/vault", "test-pass")
+            .await
+            .unwrap();
+        let err = denied.init_repo().await.unwrap_err().to_string();
+        assert!(err.contains("could not be created"));
+        assert!(err.contains("owner of the organization"));
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[test]
+    fn test_storage_validate_category() {
+        assert_eq!(
+            Storage::validate_category(Some("prod/api")).unwrap(),
+            Some("prod/api".to_string())
+        );
+        assert_eq!(
+            Storage::validate_category(Some("  stage/backend  ")).unwrap(),
+            Some("stage/backend".to_string())
+        );
+        assert_eq!(
+            Storage::validate_category(Some("/leading/slash/")).unwrap(),
+            Some("leading/slash".to_string())
+        );
+        assert_eq!(Storage::validate_category(None).unwrap(), None);
+        assert_eq!(Storage::validate_category(Some("")).unwrap(), None);
+
+        // Errors
+        assert!(Storage::validate_category(Some("invalid@char")).is_err());
+        assert!(Storage::validate_category(Some("path/../traversal")).is_err());
+        assert!(Storage::validate_category(Some("path//empty-segment")).is_err());
+    }
+
+    #[test]
+    fn test_storage_build_key_path() {
+        assert_eq!(
+            Storage::build_key_path("my-key", None).unwrap(),
+            "keys/my-key.json"
+        );
+        assert_eq!(
+            Storage::build_key_path("my-key", Some("db/prod")).unwrap(),
+            "keys/db/prod/my-key.json"
+        );
+
+        // Errors
+        assert!(Storage::build_key_path("invalid/key", None).is_err());
+    }
+
+    #[test]
+    fn test_blob_changes_chunking() {
+        // Small blobs are stored as a single file
+        let changes = Storage::blob_changes("keys/a.json", b"small", 0).unwrap();
+        assert_eq!(changes.len(), 1);
+
+        // Large blobs are split into chunks plus a manifest
+        let data = vec![7u8; MAX_FILE_SIZE + 10];
+        let changes = Storage::blob_changes("keys/a.json", &data, 0).unwrap();
+        assert_eq!(changes.len(), 3);
+        let manifest = match &changes[2] {
+            TreeChange::Upsert { path, content } => {
+                assert_eq!(path, "keys/a.json");
+                Storage::parse_manifest(content).unwrap()
+            }
+            _ => panic!("expected manifest upsert"),
+        };
+        assert_eq!(manifest.chunks, 2);
+        assert_eq!(manifest.size, data.len());
+        assert_eq!(manifest.sha256, sha256_hex(&data));
+        assert!(
+            matches!(&changes[1], TreeChange::Upsert { path, .. } if path == "keys/a.json.part001")
+        );
+
+        // Shrinking a chunked blob removes its leftover chunks
+        let changes = Storage::blob_changes("keys/a.json", b"small", 2).unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(
+            matches!(&changes[2], TreeChange::Delete { path } if path == "keys/a.json.part001")
+        );
+
+        // Regular encrypted blobs are never mistaken for manifests
+        let blob = crate::crypto::CryptoHandler::encrypt(b"value", "pw").unwrap();
+        assert!(Storage::parse_manifest(&serde_json::to_vec(&blob).unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_parse_pointer() {
+        let pointer = Storage::dedup_pointer("abc123").unwrap();
+        assert_eq!(Storage::parse_pointer(&pointer).as_deref(), Some("abc123"));
+        assert!(Storage::parse_manifest(&pointer).is_none());
+        assert_eq!(
+            Storage::dedup_path("abc123"),
+            ".axkeystore/dedup/abc123.json"
+        );
+
+        let blob = crate::crypto::CryptoHandler::encrypt(b"value", "pw").unwrap();
+        assert!(Storage::parse_pointer(&serde_json::to_vec(&blob).unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_key_entry_in_category() {
+        let entry = KeyEntry {
+            name: "db".to_string(),
+            category: Some("cloud/aws/prod".to_string()),
+            data: Vec::new(),
+        };
+        assert_eq!(entry.display_path(), "cloud/aws/prod/db");
+        assert!(entry.in_category("cloud"));
+        assert!(entry.in_category("cloud/aws/"));
+        assert!(entry.in_category("cloud/aws/prod"));
+        assert!(!entry.in_category("cloud/aw"));
+        assert!(!entry.in_category("cloud/aws/prod/db"));
+    }
+
+    #[tokio::test]
+    async fn test_storage_get_key_history() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        // Mock User
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Mock Commits
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/commits"))
+            .and(wiremock::matchers::query_param("path", "keys/my-key.json"))
+            .and(wiremock::matchers::query_param("page", "1"))
+            .and(wiremock::matchers::query_param("per_page", "10"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {
+                    "sha": "sha1",
+                    "commit": {
+                        "author": { "date": "2024-01-01T10:00:00Z" },
+                        "message": "msg1"
+                    }
+                },
+                {
+                    "sha": "sha2",
+                    "commit": {
+                        "author": { "date": "2024-01-01T11:00:00Z" },
+                        "message": "msg2"
+                    }
+                }
+            ])))
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        let history = storage
+            .get_key_history("my-key", None, 1, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].sha, "sha1");
+        assert_eq!(history[1].sha, "sha2");
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_get_filtered_key_history() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // The period is passed on to GitHub as RFC 3339 timestamps
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/commits"))
+            .and(wiremock::matchers::query_param("path", "keys/my-key.json"))
+            .and(wiremock::matchers::query_param(
+                "since",
+                "2024-01-01T00:00:00Z",
+            ))
+            .and(wiremock::matchers::query_param(
+                "until",
+                "2024-02-01T00:00:00Z",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {
+                    "sha": "sha1",
+                    "author": { "login": "octocat" },
+                    "commit": {
+                        "author": { "date": "2024-01-02T10:00:00Z", "name": "The Octocat" },
+                        "message": "msg1"
+                    }
+                },
+                {
+                    "sha": "sha2",
+                    "author": null,
+                    "commit": {
+                        "author": { "date": "2024-01-01T11:00:00Z", "name": "CI Bot" },
+                        "message": "msg2"
+                    }
+                }
+            ])))
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        let filter = HistoryFilter {
+            since: Some(1_704_067_200),
+            until: Some(1_706_745_600),
+            action: None,
+        };
+        let history = storage
+            .get_filtered_key_history("my-key", None, &filter, 1, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].author.as_deref(), Some("octocat"));
+        assert_eq!(history[1].author.as_deref(), Some("CI Bot"));
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+    #[tokio::test]
+    async fn test_storage_get_master_key_blob() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        // Mock User
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Mock Master Key File
+        let dummy_data = b"secret_master_key";
+        let encoded = BASE64.encode(dummy_data);
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/repos/testuser/test-repo/contents/.axkeystore/master_key.json",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "content": encoded,
+                "encoding": "base64",
+                "sha": "sha123",
+                "size": encoded.len(),
+                "node_id": "ignored",
+                "name": "master_key.json",
+                "path": ".axkeystore/master_key.json",
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+
+        let retrieved = storage.get_master_key_blob().await.unwrap();
+        assert!(retrieved.is_some());
+        assert_eq!(retrieved.unwrap(), dummy_data);
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_list_all_keys() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        // Mock User
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Mock Contents API for root keys directory
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/contents/keys"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "name": "api-token.json", "path": "keys/api-token.json", "type": "file" },
+                { "name": "cloud", "path": "keys/cloud", "type": "dir" }
+            ])))
+            .mount(&mock_server)
+            .await;
+
+        // Mock Contents API for keys/cloud directory
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/contents/keys/cloud"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "name": "aws", "path": "keys/cloud/aws", "type": "dir" }
+            ])))
+            .mount(&mock_server)
+            .await;
+
+        // Mock Contents API for keys/cloud/aws directory
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/contents/keys/cloud/aws"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "name": "secret-key.json", "path": "keys/cloud/aws/secret-key.json", "type": "file" }
+            ])))
+            .mount(&mock_server)
+            .await;
+
+        // Mock file content for uncategorized key
+        let data1 = b"encrypted_api_token";
+        let encoded1 = BASE64.encode(data1);
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/contents/keys/api-token.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "content": encoded1,
+                "sha": "sha-1"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        // Mock file content for categorized key
+        let data2 = b"encrypted_aws_secret";
+        let encoded2 = BASE64.encode(data2);
+        Mock::given(method("GET"))
+            .and(path(
+                "/repos/testuser/test-repo/contents/keys/cloud/aws/secret-key.json",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "content": encoded2,
+                "sha": "sha-2"
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+
+        let mut entries = storage.list_all_keys().await.unwrap();
+        // Since we iterate using pop(), the order is non-deterministic or reverse to insertion
+        // Sort by name to make assertions stable
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(entries.len(), 2);
+
+        // First entry: api-token (uncategorized)
+        assert_eq!(entries[0].name, "api-token");
+        assert_eq!(entries[0].category, None);
+        assert_eq!(entries[0].data, data1);
+
+        // Second entry: secret-key (categorized)
+        assert_eq!(entries[1].name, "secret-key");
+        assert_eq!(entries[1].category, Some("cloud/aws".to_string()));
+        assert_eq!(entries[1].data, data2);
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_commit_changes() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "default_branch": "main" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/git/ref/heads/main"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "object": { "sha": "parent-sha" } })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/git/commits/parent-sha"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "tree": { "sha": "base-tree" } })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/blobs"))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": "blob-sha" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/trees"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "base_tree": "base-tree"
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": "new-tree" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/commits"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "tree": "new-tree",
+                "parents": ["parent-sha"]
+            })))
+            .respond_with(
+                ResponseTemplate::new(201)
+                    .set_body_json(serde_json::json!({ "sha": "new-commit" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("PATCH"))
+            .and(path("/repos/testuser/test-repo/git/refs/heads/main"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "sha": "new-commit"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        let sha = storage
+            .commit_changes(
+                &[
+                    TreeChange::Upsert {
+                        path: "keys/a.json".to_string(),
+                        content: b"data".to_vec(),
+                    },
+                    TreeChange::Delete {
+                        path: "keys/b.json".to_string(),
+                    },
+                ],
+                "Batch update",
+            )
+            .await
+            .unwrap();
+        assert_eq!(sha, "new-commit");
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_rewrite_history() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "default_branch": "main" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/git/ref/heads/main"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "object": { "sha": "second" } })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // The secret was stored in the first commit and deleted in the second
+        let author = serde_json::json!({
+            "name": "testuser", "email": "test@example.com", "date": "2024-01-01T00:00:00Z"
+        });
+        for (sha, tree, parents) in [
+            ("first", "tree-1", vec![]),
+            ("second", "tree-2", vec!["first"]),
+        ] {
+            let parents: Vec<serde_json::Value> = parents
+                .into_iter()
+                .map(|p| serde_json::json!({ "sha": p }))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/testuser/test-repo/git/commits/{}",
+                    sha
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "tree": { "sha": tree },
+                    "parents": parents,
+                    "message": format!("Commit {}", sha),
+                    "author": author,
+                    "committer": author,
+                })))
+                .mount(&mock_server)
+                .await;
+        }
+        for (tree, files) in [
+            ("tree-1", vec!["keys/api/token.json", "keys/other.json"]),
+            ("tree-2", vec!["keys/other.json"]),
+        ] {
+            let items: Vec<serde_json::Value> = files
+                .into_iter()
+                .map(|f| serde_json::json!({ "path": f, "type": "blob", "sha": "s" }))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/testuser/test-repo/git/trees/{}",
+                    tree
+                )))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(serde_json::json!({ "tree": items, "truncated": false })),
+                )
+                .mount(&mock_server)
+                .await;
+        }
+
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/trees"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "base_tree": "tree-1",
+                "tree": [{ "path": "keys/api/token.json", "sha": null }]
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": "tree-1b" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/commits"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "tree": "tree-1b",
+                "parents": [],
+                "author": author
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": "first-b" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // The second commit keeps its tree but gets the rewritten parent
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/commits"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "tree": "tree-2",
+                "parents": ["first-b"]
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": "second-b" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("PATCH"))
+            .and(path("/repos/testuser/test-repo/git/refs/heads/main"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "sha": "second-b",
+                "force": true
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        let report = storage
+            .rewrite_history(|path| path.starts_with("keys/api/token.json"))
+            .await
+            .unwrap();
+        assert_eq!(report.commits, 2);
+        assert_eq!(
+            report.removed.into_iter().collect::<Vec<_>>(),
+            vec!["keys/api/token.json"]
+        );
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[tokio::test]
+    async fn test_storage_squash_history() {
+        let _lock = crate::config::TEST_MUTEX.lock().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
+
+        let mock_server = MockServer::start().await;
+        std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
+        std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
+
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "default_branch": "main" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/git/ref/heads/main"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "object": { "sha": "third" } })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let author = serde_json::json!({
+            "name": "testuser", "email": "test@example.com", "date": "2024-01-01T00:00:00Z"
+        });
+        for (sha, tree, parent) in [("third", "tree-3", "second"), ("second", "tree-2", "first")] {
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/repos/testuser/test-repo/git/commits/{}",
+                    sha
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "tree": { "sha": tree },
+                    "parents": [{ "sha": parent }],
+                    "message": format!("Commit {}", sha),
+                    "author": author,
+                    "committer": author,
+                })))
+                .mount(&mock_server)
+                .await;
+        }
+
+        // The first two commits become one, with the tree of the second
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/commits"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "message": "Squash history",
+                "tree": "tree-2"
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": "base" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/commits"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "message": "Commit third",
+                "tree": "tree-3",
+                "parents": ["base"],
+                "author": author
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "sha": "third-b" })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("PATCH"))
+            .and(path("/repos/testuser/test-repo/git/refs/heads/main"))
+            .and(wiremock::matchers::body_partial_json(serde_json::json!({
+                "sha": "third-b",
+                "force": true
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+            .await
+            .unwrap();
+        let head = storage
+            .squash_history("second", "Squash history")
+            .await
+            .unwrap();
+        assert_eq!(head, "third-b");
+
+        std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
+        std::env::remove_var("AXKEYSTORE_API_URL");
+        std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
+    }
+
+    #[test]
+    fn test_rate_limit_from_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert!(RateLimit::from_headers(&headers).is_none());
+
+        headers.insert("x-ratelimit-limit", "5000".parse().unwrap());
+        headers.insert("x-ratelimit-remaining", "499".parse().unwrap());
+        headers.insert("x-ratelimit-reset", "1704068100".parse().unwrap());
+        headers.insert(
+            reqwest::header::DATE,
+            "Mon, 01 Jan 2024 00:00:00 GMT".parse().unwrap(),
+        );
+        let rate_limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(rate_limit.remaining, 499);
+        assert_eq!(rate_limit.server_time, Some(1_704_067_200));
+        assert!(rate_limit.is_low());
+        assert_eq!(
+            rate_limit.describe(1_704_067_200),
+            "499 of 5000 GitHub API requests left until 2024-01-01T00:15:00Z (in 15m)"
+        );
+
+        headers.insert("x-ratelimit-remaining", "500".parse().unwrap());
+        assert!(!RateLimit::from_headers(&headers).unwrap().is_low());
+    }
+
+    #[test]
+    fn test_validate_prefix() {
+        assert_eq!(validate_prefix("/secrets/vault/").unwrap(), "secrets/vault");
+        assert_eq!(validate_prefix("ops.vault").unwrap(), "ops.vault");
+        assert!(validate_prefix("").is_err());
+        assert!(validate_prefix("secrets//vault").is_err());
+        assert!(validate_prefix("secrets/../vault").is_err());
+        assert!(validate_prefix("my vault").is_err());
+        assert!(validate_prefix("keys").is_err());
+        assert!(validate_prefix(".axkeystore/vault").is_err());
+        assert!(validate_prefix("keystore").is_ok());
+    }
+
+    #[test]
+    fn test_validate_branch() {
+        assert_eq!(validate_branch("vault").unwrap(), "vault");
+        assert_eq!(
+            validate_branch("secrets/prod-1.x").unwrap(),
+            "secrets/prod-1.x"
+        );
+        assert!(validate_branch("").is_err());
+        assert!(validate_branch("-vault").is_err());
+        assert!(validate_branch("vault/").is_err());
+        assert!(validate_branch("secrets//prod").is_err());
+        assert!(validate_branch("secrets/.prod").is_err());
+        assert!(validate_branch("vault..old").is_err());
+        assert!(validate_branch("vault.lock").is_err());
+        assert!(validate_branch("my vault").is_err());
+        assert!(validate_branch("vault~1").is_err());
+    }
+
+    #[test]
+    fn test_prefixed_paths() {
+        assert_eq!(to_repo_path(None, "keys/a.json"), "keys/a.json");
+        assert_eq!(
+            to_repo_path(Some("ops/vault"), "keys/a.json"),
+            "ops/vault/keys/a.json"
+        );
+        assert_eq!(to_repo_path(Some("ops/vault"), ""), "ops/vault");
+        assert_eq!(
+            from_repo_path(Some("ops/vault"), "ops/vault/keys/a.json"),
+            Some("keys/a.json")
+        );
+        assert_eq!(
+            from_repo_path(Some("ops/vault"), "ops/vaults/keys/a.json"),
+            None
+        );
+        assert_eq!(from_repo_path(Some("ops/vault"), "README.md"), None);
+        assert_eq!(from_repo_path(None, "README.md"), Some("README.md"));
+    }
 
     #[tokio::test]
-    async fn test_storage_init_repo_exists() {
+    async fn test_storage_list_tree() {
         let _lock = crate::config::TEST_MUTEX.lock().unwrap();
         let temp_dir = tempfile::tempdir().unwrap();
         std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
@@ -634,26 +3654,54 @@ mod tests {
         std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
         std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
 
-        // 1. Mock User endpoint
         Mock::given(method("GET"))
             .and(path("/user"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
-                "login": "testuser"
-            })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "login": "testuser" })),
+            )
             .mount(&mock_server)
             .await;
 
-        // 2. Mock Repo Check (Existing)
         Mock::given(method("GET"))
             .and(path("/repos/testuser/test-repo"))
-            .respond_with(ResponseTemplate::new(200)) // 200 OK means exists
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "default_branch": "main" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/git/ref/heads/main"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "object": { "sha": "head-sha" } })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/git/trees/head-sha"))
+            .and(wiremock::matchers::query_param("recursive", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "tree": [
+                    { "path": "keys", "type": "tree", "sha": "t1" },
+                    { "path": "keys/api.json", "type": "blob", "sha": "b1" },
+                    { "path": "keys/cloud/db.json", "type": "blob", "sha": "b2" }
+                ],
+                "truncated": false
+            })))
             .mount(&mock_server)
             .await;
 
         let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
             .await
             .unwrap();
-        storage.init_repo().await.unwrap();
+        let tree = storage.list_tree().await.unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree["keys/api.json"], "b1");
+        assert_eq!(tree["keys/cloud/db.json"], "b2");
 
         std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
         std::env::remove_var("AXKEYSTORE_API_URL");
@@ -661,7 +3709,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_storage_init_repo_not_found() {
+    async fn test_storage_create_blob_if_absent() {
         let _lock = crate::config::TEST_MUTEX.lock().unwrap();
         let temp_dir = tempfile::tempdir().unwrap();
         std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
@@ -670,7 +3718,6 @@ mod tests {
         std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
         std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
 
-        // User
         Mock::given(method("GET"))
             .and(path("/user"))
             .respond_with(
@@ -680,66 +3727,45 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        // Check (Not Found)
-        Mock::given(method("GET"))
-            .and(path("/repos/testuser/missing-repo"))
-            .respond_with(ResponseTemplate::new(404))
+        // New key is created
+        Mock::given(method("PUT"))
+            .and(path("/repos/testuser/test-repo/contents/keys/new-key.json"))
+            .respond_with(ResponseTemplate::new(201))
             .mount(&mock_server)
             .await;
 
-        let storage = Storage::new_with_profile(None, "missing-repo", "test-pass")
+        // Existing key: GitHub rejects a create without a SHA
+        Mock::given(method("PUT"))
+            .and(path("/repos/testuser/test-repo/contents/keys/old-key.json"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "message": "Invalid request.\n\n\"sha\" wasn't supplied."
+            })))
+            .mount(&mock_server)
+            .await;
+
+        // Any other validation error is reported
+        Mock::given(method("PUT"))
+            .and(path("/repos/testuser/test-repo/contents/keys/bad-key.json"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "message": "Invalid request.\n\nFor 'content', \"???\" is not valid base64."
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
             .await
             .unwrap();
-
-        let result = storage.init_repo().await;
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("not found"));
+        assert!(storage.create_blob("new-key", b"data", None).await.unwrap());
+        assert!(!storage.create_blob("old-key", b"data", None).await.unwrap());
+        assert!(storage.create_blob("bad-key", b"data", None).await.is_err());
 
         std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
         std::env::remove_var("AXKEYSTORE_API_URL");
         std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
     }
 
-    #[test]
-    fn test_storage_validate_category() {
-        assert_eq!(
-            Storage::validate_category(Some("prod/api")).unwrap(),
-            Some("prod/api".to_string())
-        );
-        assert_eq!(
-            Storage::validate_category(Some("  stage/backend  ")).unwrap(),
-            Some("stage/backend".to_string())
-        );
-        assert_eq!(
-            Storage::validate_category(Some("/leading/slash/")).unwrap(),
-            Some("leading/slash".to_string())
-        );
-        assert_eq!(Storage::validate_category(None).unwrap(), None);
-        assert_eq!(Storage::validate_category(Some("")).unwrap(), None);
-
-        // Errors
-        assert!(Storage::validate_category(Some("invalid@char")).is_err());
-        assert!(Storage::validate_category(Some("path/../traversal")).is_err());
-        assert!(Storage::validate_category(Some("path//empty-segment")).is_err());
-    }
-
-    #[test]
-    fn test_storage_build_key_path() {
-        assert_eq!(
-            Storage::build_key_path("my-key", None).unwrap(),
-            "keys/my-key.json"
-        );
-        assert_eq!(
-            Storage::build_key_path("my-key", Some("db/prod")).unwrap(),
-            "keys/db/prod/my-key.json"
-        );
-
-        // Errors
-        assert!(Storage::build_key_path("invalid/key", None).is_err());
-    }
-
     #[tokio::test]
-    async fn test_storage_get_key_history() {
+    async fn test_storage_save_blob_conflict() {
         let _lock = crate::config::TEST_MUTEX.lock().unwrap();
         let temp_dir = tempfile::tempdir().unwrap();
         std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
@@ -748,7 +3774,6 @@ mod tests {
         std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
         std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
 
-        // Mock User
         Mock::given(method("GET"))
             .and(path("/user"))
             .respond_with(
@@ -757,50 +3782,58 @@ mod tests {
             )
             .mount(&mock_server)
             .await;
-
-        // Mock Commits
+        // Another machine already replaced the version this one read
         Mock::given(method("GET"))
-            .and(path("/repos/testuser/test-repo/commits"))
-            .and(wiremock::matchers::query_param("path", "keys/my-key.json"))
-            .and(wiremock::matchers::query_param("page", "1"))
-            .and(wiremock::matchers::query_param("per_page", "10"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
-                {
-                    "sha": "sha1",
-                    "commit": {
-                        "author": { "date": "2024-01-01T10:00:00Z" },
-                        "message": "msg1"
-                    }
-                },
-                {
-                    "sha": "sha2",
-                    "commit": {
-                        "author": { "date": "2024-01-01T11:00:00Z" },
-                        "message": "msg2"
-                    }
-                }
-            ])))
+            .and(path("/repos/testuser/test-repo/contents/keys/api.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "content": BASE64.encode(b"theirs"),
+                "sha": "sha-theirs"
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/testuser/test-repo/contents/keys/api.json"))
+            .and(wiremock::matchers::body_partial_json(
+                serde_json::json!({ "sha": "sha-mine" }),
+            ))
+            .respond_with(ResponseTemplate::new(409).set_body_json(serde_json::json!({
+                "message": "keys/api.json does not match sha-mine"
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/testuser/test-repo/contents/keys/api.json"))
+            .and(wiremock::matchers::body_partial_json(
+                serde_json::json!({ "sha": "sha-theirs" }),
+            ))
+            .respond_with(ResponseTemplate::new(200))
             .mount(&mock_server)
             .await;
 
         let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
             .await
             .unwrap();
-        let history = storage
-            .get_key_history("my-key", None, 1, 10)
+        let err = storage
+            .save_blob_expecting("api", b"mine", None, Some("sha-mine"))
+            .await
+            .unwrap_err();
+        let conflict = write_conflict(&err).expect("a write conflict");
+        assert_eq!(conflict.path, "keys/api.json");
+        assert_eq!(conflict.current_sha.as_deref(), Some("sha-theirs"));
+
+        // Overwriting the version that is there now succeeds
+        storage
+            .save_blob_expecting("api", b"mine", None, Some("sha-theirs"))
             .await
             .unwrap();
 
-        assert_eq!(history.len(), 2);
-        assert_eq!(history[0].sha, "sha1");
-        assert_eq!(history[1].sha, "sha2");
-
         std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
         std::env::remove_var("AXKEYSTORE_API_URL");
         std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
     }
+
     #[tokio::test]
-    async fn test_storage_get_master_key_blob() {
+    async fn test_storage_branch() {
         let _lock = crate::config::TEST_MUTEX.lock().unwrap();
         let temp_dir = tempfile::tempdir().unwrap();
         std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
@@ -809,7 +3842,6 @@ mod tests {
         std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
         std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
 
-        // Mock User
         Mock::given(method("GET"))
             .and(path("/user"))
             .respond_with(
@@ -818,34 +3850,88 @@ mod tests {
             )
             .mount(&mock_server)
             .await;
-
-        // Mock Master Key File
-        let dummy_data = b"secret_master_key";
-        let encoded = BASE64.encode(dummy_data);
-
         Mock::given(method("GET"))
-            .and(path(
-                "/repos/testuser/test-repo/contents/.axkeystore/master_key.json",
-            ))
+            .and(path("/repos/testuser/test-repo"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "default_branch": "main" })),
+            )
+            .mount(&mock_server)
+            .await;
+        // The branch does not exist yet and is created from the head of 'main'
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/git/ref/heads/main"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "object": { "sha": "main-head" } })),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/repos/testuser/test-repo/git/refs"))
+            .and(wiremock::matchers::body_json(serde_json::json!({
+                "ref": "refs/heads/vault",
+                "sha": "main-head"
+            })))
+            .respond_with(
+                ResponseTemplate::new(201)
+                    .set_body_json(serde_json::json!({ "object": { "sha": "main-head" } })),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Files are only found on the vault branch, and written to it
+        Mock::given(method("GET"))
+            .and(path("/repos/testuser/test-repo/contents/keys/api.json"))
+            .and(wiremock::matchers::query_param("ref", "vault"))
             .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
-                "content": encoded,
-                "encoding": "base64",
-                "sha": "sha123",
-                "size": encoded.len(),
-                "node_id": "ignored",
-                "name": "master_key.json",
-                "path": ".axkeystore/master_key.json",
+                "content": BASE64.encode(b"old"),
+                "sha": "sha-1"
             })))
             .mount(&mock_server)
             .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/testuser/test-repo/contents/keys/api.json"))
+            .and(wiremock::matchers::body_partial_json(
+                serde_json::json!({ "sha": "sha-1", "branch": "vault" }),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/repos/testuser/test-repo/contents/keys/api.json"))
+            .and(wiremock::matchers::body_partial_json(
+                serde_json::json!({ "sha": "sha-1", "branch": "vault" }),
+            ))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
 
-        let storage = Storage::new_with_profile(None, "test-repo", "test-pass")
+        let mut storage = Storage::new_with_profile(None, "test-repo", "test-pass")
             .await
             .unwrap();
+        storage.set_branch(Some("vault".to_string()));
+        assert_eq!(
+            storage.init_repo().await.unwrap().branch,
+            Some(BranchStatus::Created {
+                branch: "vault".to_string(),
+                from: "main".to_string()
+            })
+        );
+        assert_eq!(storage.vault_branch().await.unwrap(), "vault");
 
-        let retrieved = storage.get_master_key_blob().await.unwrap();
-        assert!(retrieved.is_some());
-        assert_eq!(retrieved.unwrap(), dummy_data);
+        let (content, sha) = storage.get_file("keys/api.json").await.unwrap().unwrap();
+        assert_eq!((content.as_slice(), sha.as_str()), (&b"old"[..], "sha-1"));
+        storage
+            .put_file("keys/api.json", b"new", "Update key: api")
+            .await
+            .unwrap();
+        assert!(storage
+            .delete_file("keys/api.json", "Delete key: api")
+            .await
+            .unwrap());
 
         std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
         std::env::remove_var("AXKEYSTORE_API_URL");
@@ -853,7 +3939,7 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_storage_list_all_keys() {
+    async fn test_storage_commits_since() {
         let _lock = crate::config::TEST_MUTEX.lock().unwrap();
         let temp_dir = tempfile::tempdir().unwrap();
         std::env::set_var("AXKEYSTORE_TEST_CONFIG_DIR", temp_dir.path());
@@ -862,7 +3948,6 @@ mod tests {
         std::env::set_var("AXKEYSTORE_TEST_TOKEN", "mock_token");
         std::env::set_var("AXKEYSTORE_API_URL", mock_server.uri());
 
-        // Mock User
         Mock::given(method("GET"))
             .and(path("/user"))
             .respond_with(
@@ -872,57 +3957,49 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        // Mock Contents API for root keys directory
-        Mock::given(method("GET"))
-            .and(path("/repos/testuser/test-repo/contents/keys"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
-                { "name": "api-token.json", "path": "keys/api-token.json", "type": "file" },
-                { "name": "cloud", "path": "keys/cloud", "type": "dir" }
-            ])))
-            .mount(&mock_server)
-            .await;
-
-        // Mock Contents API for keys/cloud directory
         Mock::given(method("GET"))
-            .and(path("/repos/testuser/test-repo/contents/keys/cloud"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
-                { "name": "aws", "path": "keys/cloud/aws", "type": "dir" }
-            ])))
+            .and(path("/repos/testuser/test-repo"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "default_branch": "main" })),
+            )
             .mount(&mock_server)
             .await;
 
-        // Mock Contents API for keys/cloud/aws directory
-        Mock::given(method("GET"))
-            .and(path("/repos/testuser/test-repo/contents/keys/cloud/aws"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
-                { "name": "secret-key.json", "path": "keys/cloud/aws/secret-key.json", "type": "file" }
-            ])))
-            .mount(&mock_server)
-            .await;
+        let commit = |sha: &str, date: &str, message: &str| {
+            serde_json::json!({
+                "sha": sha,
+                "commit": { "author": { "date": date }, "message": message }
+            })
+        };
 
-        // Mock file content for uncategorized key
-        let data1 = b"encrypted_api_token";
-        let encoded1 = BASE64.encode(data1);
+        // A ref is compared with the branch; GitHub lists those commits oldest first
         Mock::given(method("GET"))
-            .and(path("/repos/testuser/test-repo/contents/keys/api-token.json"))
+            .and(path("/repos/testuser/test-repo/compare/v1...main"))
             .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
-                "content": encoded1,
-                "sha": "sha-1"
+                "commits": [
+                    commit("c1", "2024-01-01T10:00:00Z", "Update key: api"),
+                    commit("c2", "2024-01-02T10:00:00Z", "Delete key: old")
+                ]
             })))
             .mount(&mock_server)
             .await;
 
-        // Mock file content for categorized key
-        let data2 = b"encrypted_aws_secret";
-        let encoded2 = BASE64.encode(data2);
+        // A date lists the branch's commits since then
         Mock::given(method("GET"))
-            .and(path(
-                "/repos/testuser/test-repo/contents/keys/cloud/aws/secret-key.json",
+            .and(path("/repos/testuser/test-repo/commits"))
+            .and(wiremock::matchers::query_param("sha", "main"))
+            .and(wiremock::matchers::query_param(
+                "since",
+                "2024-01-02T00:00:00Z",
             ))
-            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
-                "content": encoded2,
-                "sha": "sha-2"
-            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!([commit(
+                    "c2",
+                    "2024-01-02T10:00:00Z",
+                    "Delete key: old"
+                )])),
+            )
             .mount(&mock_server)
             .await;
 
@@ -930,25 +4007,69 @@ mod tests {
             .await
             .unwrap();
 
-        let mut entries = storage.list_all_keys().await.unwrap();
-        // Since we iterate using pop(), the order is non-deterministic or reverse to insertion
-        // Sort by name to make assertions stable
-        entries.sort_by(|a, b| a.name.cmp(&b.name));
-
-        assert_eq!(entries.len(), 2);
-
-        // First entry: api-token (uncategorized)
-        assert_eq!(entries[0].name, "api-token");
-        assert_eq!(entries[0].category, None);
-        assert_eq!(entries[0].data, data1);
+        let commits = storage.commits_since("v1").await.unwrap();
+        let shas: Vec<&str> = commits.iter().map(|c| c.sha.as_str()).collect();
+        assert_eq!(shas, vec!["c2", "c1"]);
 
-        // Second entry: secret-key (categorized)
-        assert_eq!(entries[1].name, "secret-key");
-        assert_eq!(entries[1].category, Some("cloud/aws".to_string()));
-        assert_eq!(entries[1].data, data2);
+        let commits = storage.commits_since("2024-01-02").await.unwrap();
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].message, "Delete key: old");
 
         std::env::remove_var("AXKEYSTORE_TEST_TOKEN");
         std::env::remove_var("AXKEYSTORE_API_URL");
         std::env::remove_var("AXKEYSTORE_TEST_CONFIG_DIR");
     }
+
+    #[tokio::test]
+    async fn test_paginate() {
+        use futures::StreamExt;
+
+        let requested = std::cell::RefCell::new(Vec::new());
+        let items: Vec<u32> = paginate(2, |page| {
+            requested.borrow_mut().push(page);
+            let items = match page {
+                1 => vec![1, 2],
+                2 => vec![3, 4],
+                _ => vec![5],
+            };
+            async move { Ok(items) }
+        })
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(items, vec![1, 2, 3, 4, 5]);
+        assert_eq!(*requested.borrow(), vec![1, 2, 3]);
+
+        // Pages are only fetched as the stream is consumed
+        requested.borrow_mut().clear();
+        let items: Vec<u32> = paginate(2, |page| {
+            requested.borrow_mut().push(page);
+            async move { Ok(vec![page; 2]) }
+        })
+        .take(3)
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(items, vec![1, 1, 2]);
+        assert_eq!(*requested.borrow(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_stored_size() {
+        let item = |path: &str, size: u64| GitTreeItem {
+            path: path.to_string(),
+            item_type: "blob".to_string(),
+            sha: format!("sha-{}", path),
+            size: Some(size),
+        };
+        let items = vec![
+            item("api.json", 180),
+            item("cert.json", 90),
+            item("cert.json.part000", 700_000),
+            item("cert.json.part001", 250_000),
+        ];
+        assert_eq!(stored_size(&items, "api.json"), Some(180));
+        assert_eq!(stored_size(&items, "cert.json"), Some(950_000));
+        assert_eq!(stored_size(&items, "db.json"), None);
+    }
 }
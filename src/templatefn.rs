@@ -0,0 +1,112 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// Pattern of a template placeholder: `{{ key "category/name" }}`
+const PLACEHOLDER: &str = r#"\{\{\s*key\s+"([^"]+)"\s*\}\}"#;
+
+/// Splits a key path ('category/name' or just 'name') into category and key name
+pub fn split_key_path(path: &str) -> (Option<&str>, &str) {
+    let path = path.trim().trim_matches('/');
+    match path.rsplit_once('/') {
+        Some((cat, name)) => (Some(cat), name),
+        None => (None, path),
+    }
+}
+
+/// Formats the answer to one request of the line protocol: the value as a JSON string, or
+/// `null` if the key does not exist. Each answer is a single line, so multi-line values
+/// (PEM keys, JSON documents) cannot be confused with the next answer.
+pub fn response_line(value: Option<&[u8]>) -> Result<String> {
+    match value {
+        Some(value) => {
+            let text = std::str::from_utf8(value)
+                .map_err(|_| anyhow::anyhow!("the value is binary and cannot be templated"))?;
+            Ok(serde_json::to_string(text)?)
+        }
+        None => Ok("null".to_string()),
+    }
+}
+
+fn placeholder_regex() -> regex::Regex {
+    regex::Regex::new(PLACEHOLDER).expect("placeholder pattern is valid")
+}
+
+/// Returns the key paths a template refers to, without duplicates, in order of first use
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for captures in placeholder_regex().captures_iter(template) {
+        let path = captures[1].trim().trim_matches('/').to_string();
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// Replaces every placeholder with the value of the key it names. Every key must have a
+/// text value in `values`.
+pub fn render(template: &str, values: &BTreeMap<String, Vec<u8>>) -> Result<String> {
+    let regex = placeholder_regex();
+    let mut out = String::with_capacity(template.len());
+    let mut last = 0;
+    for captures in regex.captures_iter(template) {
+        let whole = captures.get(0).expect("a match has a whole group");
+        let path = captures[1].trim().trim_matches('/');
+        let value = values
+            .get(path)
+            .ok_or_else(|| anyhow::anyhow!("Key '{}' has no value", path))?;
+        let text = std::str::from_utf8(value)
+            .map_err(|_| anyhow::anyhow!("Key '{}' is binary and cannot be templated", path))?;
+        out.push_str(&template[last..whole.start()]);
+        out.push_str(text);
+        last = whole.end();
+    }
+    out.push_str(&template[last..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_key_path() {
+        assert_eq!(split_key_path("cloud/aws/api"), (Some("cloud/aws"), "api"));
+        assert_eq!(split_key_path(" /github-token \n"), (None, "github-token"));
+    }
+
+    #[test]
+    fn test_response_line() {
+        assert_eq!(response_line(Some(b"hunter2")).unwrap(), "\"hunter2\"");
+        assert_eq!(
+            response_line(Some(b"-----BEGIN KEY-----\nabc\n")).unwrap(),
+            "\"-----BEGIN KEY-----\\nabc\\n\""
+        );
+        assert_eq!(response_line(None).unwrap(), "null");
+        assert!(response_line(Some(&[0xff, 0xfe])).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let template = "user = app\npassword = {{ key \"api/prod/db-password\" }}\n\
+                        replica = {{key \"api/prod/db-password\"}}\ntoken = {{ key \"/ci/token\" }}\n";
+        assert_eq!(
+            placeholders(template),
+            vec!["api/prod/db-password", "ci/token"]
+        );
+
+        let mut values = BTreeMap::new();
+        values.insert("api/prod/db-password".to_string(), b"s3cr3t".to_vec());
+        values.insert("ci/token".to_string(), b"abc".to_vec());
+        assert_eq!(
+            render(template, &values).unwrap(),
+            "user = app\npassword = s3cr3t\nreplica = s3cr3t\ntoken = abc\n"
+        );
+
+        values.insert("ci/token".to_string(), vec![0xff]);
+        assert!(render(template, &values).is_err());
+        values.remove("ci/token");
+        assert!(render(template, &values).is_err());
+        assert_eq!(render("{{ other }}", &values).unwrap(), "{{ other }}");
+    }
+}
@@ -0,0 +1,295 @@
+use anyhow::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the current time as seconds since the Unix epoch
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parses a human-friendly duration such as '90d', '12h', '30m', '45s' or a plain number of seconds
+pub fn parse_duration_secs(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let (number, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => input.split_at(pos),
+        None => (input, "s"),
+    };
+
+    let value: u64 = number.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid duration '{}'. Use a number followed by s, m, h, d or w (e.g. '90d').",
+            input
+        )
+    })?;
+
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid duration unit '{}' in '{}'. Use s, m, h, d or w.",
+                unit,
+                input
+            ))
+        }
+    };
+
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("Duration '{}' is too large", input))
+}
+
+/// Formats a number of seconds as a compact duration (e.g. '3d', '5h', '12m', '40s')
+pub fn format_duration_secs(secs: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+
+    if secs >= DAY {
+        format!("{}d", secs / DAY)
+    } else if secs >= HOUR {
+        format!("{}h", secs / HOUR)
+    } else if secs >= MINUTE {
+        format!("{}m", secs / MINUTE)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Converts days since the Unix epoch into a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = (z - era * 146_097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe as i64 + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Converts a civil date into days since the Unix epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = (year - era * 400) as u64;
+    let month = month as u64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as u64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe as i64 - 719_468
+}
+
+/// Formats a Unix timestamp as an RFC 3339 UTC string (e.g. '2024-01-01T10:00:00Z')
+pub fn format_rfc3339(ts: u64) -> String {
+    let (year, month, day) = civil_from_days((ts / 86_400) as i64);
+    let secs = ts % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+/// Parses an RFC 3339 timestamp (e.g. '2024-01-01T10:00:00Z') or a plain date ('2024-01-01')
+/// into seconds since the Unix epoch
+pub fn parse_timestamp(input: &str) -> Result<u64> {
+    let input = input.trim();
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid date '{}'. Use YYYY-MM-DD or YYYY-MM-DDTHH:MM:SSZ.",
+            input
+        )
+    };
+
+    let num = |range: std::ops::Range<usize>| -> Result<u32> {
+        input
+            .get(range)
+            .filter(|s| s.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(invalid)
+    };
+
+    // Every valid form is ASCII, which also makes the byte offsets below safe to slice at
+    if !input.is_ascii() || input.len() < 10 || &input[4..5] != "-" || &input[7..8] != "-" {
+        return Err(invalid());
+    }
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    let mut secs = days_from_civil(year as i64, month, day) * 86_400;
+
+    if input.len() > 10 {
+        if !matches!(&input[10..11], "T" | "t" | " ") || input.len() < 19 {
+            return Err(invalid());
+        }
+        let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+        if hour > 23 || minute > 59 || second > 60 {
+            return Err(invalid());
+        }
+        secs += (hour * 3600 + minute * 60 + second) as i64;
+
+        // Skip fractional seconds, then apply the UTC offset
+        let mut rest = &input[19..];
+        if let Some(stripped) = rest.strip_prefix('.') {
+            rest = stripped.trim_start_matches(|c: char| c.is_ascii_digit());
+        }
+        match rest {
+            "" | "Z" | "z" => {}
+            offset if offset.len() == 6 && &offset[3..4] == ":" => {
+                let sign = match &offset[0..1] {
+                    "+" => 1,
+                    "-" => -1,
+                    _ => return Err(invalid()),
+                };
+                let hours: i64 = offset[1..3].parse().map_err(|_| invalid())?;
+                let minutes: i64 = offset[4..6].parse().map_err(|_| invalid())?;
+                secs -= sign * (hours * 3600 + minutes * 60);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    u64::try_from(secs).map_err(|_| invalid())
+}
+
+/// Parses an HTTP date (e.g. 'Tue, 15 Nov 1994 08:12:31 GMT', as in a 'Date' header) into
+/// seconds since the Unix epoch
+pub fn parse_http_date(input: &str) -> Result<u64> {
+    let invalid = || anyhow::anyhow!("Invalid HTTP date '{}'", input);
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|m| m == month).ok_or_else(invalid)? as u32 + 1;
+    let day: u32 = day.parse().map_err(|_| invalid())?;
+    let year: i64 = year.parse().map_err(|_| invalid())?;
+    let hms: Vec<u32> = time
+        .split(':')
+        .map(|n| n.parse().map_err(|_| invalid()))
+        .collect::<Result<_>>()?;
+    let [hour, minute, second] = hms.as_slice() else {
+        return Err(invalid());
+    };
+    if !(1..=31).contains(&day) || *hour > 23 || *minute > 59 || *second > 60 {
+        return Err(invalid());
+    }
+    let secs =
+        days_from_civil(year, month, day) * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+    u64::try_from(secs).map_err(|_| invalid())
+}
+
+/// Parses an expiry given either relative to `now` ('90d') or as an absolute date
+/// ('2025-06-30' or an RFC 3339 timestamp)
+pub fn parse_expiry(input: &str, now: u64) -> Result<u64> {
+    if input.trim().contains('-') {
+        return parse_timestamp(input);
+    }
+    let secs = parse_duration_secs(input)?;
+    now.checked_add(secs)
+        .ok_or_else(|| anyhow::anyhow!("Expiry '{}' is too far in the future", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration_secs() {
+        assert_eq!(parse_duration_secs("45").unwrap(), 45);
+        assert_eq!(parse_duration_secs("45s").unwrap(), 45);
+        assert_eq!(parse_duration_secs("30m").unwrap(), 1800);
+        assert_eq!(parse_duration_secs("12h").unwrap(), 43200);
+        assert_eq!(parse_duration_secs("90d").unwrap(), 90 * 86400);
+        assert_eq!(parse_duration_secs("2w").unwrap(), 14 * 86400);
+        assert_eq!(parse_duration_secs(" 0 ").unwrap(), 0);
+
+        assert!(parse_duration_secs("").is_err());
+        assert!(parse_duration_secs("d").is_err());
+        assert!(parse_duration_secs("10y").is_err());
+        assert!(parse_duration_secs("-5m").is_err());
+    }
+
+    #[test]
+    fn test_parse_expiry() {
+        let now = 1_704_103_200;
+        assert_eq!(parse_expiry("90d", now).unwrap(), now + 90 * 86400);
+        assert_eq!(parse_expiry("2025-06-30", now).unwrap(), 1_751_241_600);
+        assert_eq!(
+            parse_expiry("2025-06-30T12:00:00Z", now).unwrap(),
+            1_751_284_800
+        );
+        assert!(parse_expiry("soon", now).is_err());
+        assert!(parse_expiry("2025-13-01", now).is_err());
+    }
+
+    #[test]
+    fn test_format_duration_secs() {
+        assert_eq!(format_duration_secs(40), "40s");
+        assert_eq!(format_duration_secs(720), "12m");
+        assert_eq!(format_duration_secs(5 * 3600), "5h");
+        assert_eq!(format_duration_secs(3 * 86400 + 10), "3d");
+    }
+
+    #[test]
+    fn test_rfc3339_round_trip() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(1_704_103_200), "2024-01-01T10:00:00Z");
+        assert_eq!(format_rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+
+        for ts in [0u64, 951_782_400, 1_704_103_200, 4_102_444_799] {
+            assert_eq!(parse_timestamp(&format_rfc3339(ts)).unwrap(), ts);
+        }
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(
+            parse_http_date("Mon, 01 Jan 2024 00:00:00 GMT").unwrap(),
+            1_704_067_200
+        );
+        assert_eq!(
+            parse_http_date("Tue, 15 Nov 1994 08:12:31 GMT").unwrap(),
+            784_887_151
+        );
+        assert!(parse_http_date("Mon, 01 Foo 2024 00:00:00 GMT").is_err());
+        assert!(parse_http_date("Mon, 01 Jan 2024 00:00 GMT").is_err());
+        assert!(parse_http_date("2024-01-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp_formats() {
+        assert_eq!(parse_timestamp("2024-01-01").unwrap(), 1_704_067_200);
+        assert_eq!(
+            parse_timestamp("2024-01-01T10:00:00.123Z").unwrap(),
+            1_704_103_200
+        );
+        assert_eq!(
+            parse_timestamp("2024-01-01T12:00:00+02:00").unwrap(),
+            1_704_103_200
+        );
+
+        assert!(parse_timestamp("2024-13-01").is_err());
+        assert!(parse_timestamp("01/02/2024").is_err());
+        assert!(parse_timestamp("2024-01-01T10:00").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
+        assert!(parse_timestamp("202é-01-01").is_err());
+        assert!(parse_timestamp("2024-01-01Té0:00:00Z").is_err());
+        assert!(parse_timestamp("2024-01-01T10:00:00+0é:00").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::Result;
+use hmac::digest::KeyInit;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+/// HMAC algorithm used to compute TOTP codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// A TOTP generator parsed from an otpauth:// URI or a bare base32 secret
+#[derive(Debug, Clone, PartialEq)]
+pub struct Totp {
+    /// Decoded shared secret
+    pub secret: Vec<u8>,
+    /// HMAC algorithm (SHA1 unless the URI says otherwise)
+    pub algorithm: Algorithm,
+    /// Number of digits in a code (6-8)
+    pub digits: u32,
+    /// Validity period of a code in seconds
+    pub period: u64,
+    /// Issuer of the account, if known
+    pub issuer: Option<String>,
+    /// Account name from the URI label, if known
+    pub account: Option<String>,
+}
+
+/// Decodes RFC 4648 base32 (case-insensitive, padding and spaces ignored)
+fn decode_base32(input: &str) -> Result<Vec<u8>> {
+    let mut bits: u64 = 0;
+    let mut bit_count = 0;
+    let mut out = Vec::new();
+
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Invalid base32 character '{}' in secret",
+                    c
+                ))
+            }
+        };
+        bits = (bits << 5) | value;
+        bit_count += 5;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            out.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+
+    if out.is_empty() {
+        return Err(anyhow::anyhow!("TOTP secret is empty"));
+    }
+    Ok(out)
+}
+
+/// Decodes %XX escapes (and '+' as space) in a URI component
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        out.push(b);
+                        i += 3;
+                        continue;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Computes an HMAC with any supported digest
+fn hmac<M: Mac + KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as KeyInit>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    Mac::update(&mut mac, message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+impl Totp {
+    /// Parses an otpauth://totp/ URI or a bare base32 secret (SHA1, 6 digits, 30 seconds)
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        let rest = match input.strip_prefix("otpauth://") {
+            Some(rest) => rest,
+            None => {
+                return Ok(Self {
+                    secret: decode_base32(input)?,
+                    algorithm: Algorithm::Sha1,
+                    digits: 6,
+                    period: 30,
+                    issuer: None,
+                    account: None,
+                })
+            }
+        };
+
+        let (kind, rest) = rest
+            .split_once('/')
+            .ok_or_else(|| anyhow::anyhow!("Invalid otpauth URI: missing label"))?;
+        if !kind.eq_ignore_ascii_case("totp") {
+            return Err(anyhow::anyhow!(
+                "Unsupported OTP type '{}'. Only TOTP is supported.",
+                kind
+            ));
+        }
+
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let label = percent_decode(label);
+        let (mut issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (
+                Some(issuer.trim().to_string()),
+                Some(account.trim().to_string()),
+            ),
+            None if label.is_empty() => (None, None),
+            None => (None, Some(label.trim().to_string())),
+        };
+
+        let mut secret = None;
+        let mut algorithm = Algorithm::Sha1;
+        let mut digits = 6;
+        let mut period = 30;
+
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value);
+            match name.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(decode_base32(&value)?),
+                "issuer" => issuer = Some(value),
+                "algorithm" => {
+                    algorithm = match value.to_ascii_uppercase().as_str() {
+                        "SHA1" => Algorithm::Sha1,
+                        "SHA256" => Algorithm::Sha256,
+                        "SHA512" => Algorithm::Sha512,
+                        other => {
+                            return Err(anyhow::anyhow!("Unsupported TOTP algorithm '{}'", other))
+                        }
+                    }
+                }
+                "digits" => {
+                    digits = value
+                        .parse()
+                        .ok()
+                        .filter(|d| (6..=8).contains(d))
+                        .ok_or_else(|| anyhow::anyhow!("TOTP digits must be 6, 7 or 8"))?
+                }
+                "period" => {
+                    period =
+                        value.parse().ok().filter(|p| *p > 0).ok_or_else(|| {
+                            anyhow::anyhow!("TOTP period must be a positive number")
+                        })?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            secret: secret.ok_or_else(|| anyhow::anyhow!("Invalid otpauth URI: missing secret"))?,
+            algorithm,
+            digits,
+            period,
+            issuer,
+            account,
+        })
+    }
+
+    /// Computes the code valid at the given Unix timestamp (RFC 6238)
+    pub fn code_at(&self, timestamp: u64) -> String {
+        let counter = (timestamp / self.period).to_be_bytes();
+        let digest = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.secret, &counter),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.secret, &counter),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.secret, &counter),
+        };
+
+        // Dynamic truncation (RFC 4226 section 5.3)
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        let code = binary % 10u32.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// Returns how many seconds the code at the given timestamp remains valid
+    pub fn remaining_secs(&self, timestamp: u64) -> u64 {
+        self.period - timestamp % self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totp(secret: &[u8], algorithm: Algorithm) -> Totp {
+        Totp {
+            secret: secret.to_vec(),
+            algorithm,
+            digits: 8,
+            period: 30,
+            issuer: None,
+            account: None,
+        }
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        let sha1 = totp(b"12345678901234567890", Algorithm::Sha1);
+        let sha256 = totp(b"12345678901234567890123456789012", Algorithm::Sha256);
+        let sha512 = totp(
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+            Algorithm::Sha512,
+        );
+
+        assert_eq!(sha1.code_at(59), "94287082");
+        assert_eq!(sha1.code_at(1_111_111_109), "07081804");
+        assert_eq!(sha1.code_at(20_000_000_000), "65353130");
+        assert_eq!(sha256.code_at(59), "46119246");
+        assert_eq!(sha256.code_at(1_234_567_890), "91819424");
+        assert_eq!(sha512.code_at(59), "90693936");
+        assert_eq!(sha512.code_at(2_000_000_000), "38618901");
+    }
+
+    #[test]
+    fn test_parse_otpauth_uri() {
+        let t = Totp::parse(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30",
+        )
+        .unwrap();
+        assert_eq!(t.secret, b"12345678901234567890");
+        assert_eq!(t.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(t.account.as_deref(), Some("john@example.com"));
+        assert_eq!(t.digits, 6);
+        assert_eq!(t.code_at(59), "287082");
+        assert_eq!(t.remaining_secs(59), 1);
+
+        // Bare base32 secrets use the defaults
+        let bare = Totp::parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+        assert_eq!(bare.secret, t.secret);
+
+        assert!(Totp::parse("otpauth://hotp/x?secret=GEZDGNBV").is_err());
+        assert!(Totp::parse("otpauth://totp/x?issuer=y").is_err());
+        assert!(Totp::parse("otpauth://totp/x?secret=GEZDGNBV&digits=4").is_err());
+        assert!(Totp::parse("not-base32!").is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::derived::{self, DerivedKey, DerivedRegistry};
+use crate::meta::{self, KeyMeta};
+use crate::storage::{Storage, TreeChange};
+use crate::templatefn::split_key_path;
+use crate::vault;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+
+/// Repository directory of deleted keys ('.axkeystore/trash/<deleted>/<category>/<key>.json',
+/// with the deletion time in Unix seconds)
+pub const TRASH_DIR: &str = ".axkeystore/trash";
+
+/// Repository path of the trash settings. They are not secret, so they are not encrypted.
+const SETTINGS_PATH: &str = ".axkeystore/trash.json";
+
+/// How long deleted keys are kept when nothing else is configured
+pub const DEFAULT_PURGE_AFTER_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// How long deleted keys stay in the trash
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    /// Seconds after which deleted keys are purged for good (None to keep them until the
+    /// trash is emptied)
+    pub purge_after: Option<u64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            purge_after: Some(DEFAULT_PURGE_AFTER_SECS),
+        }
+    }
+}
+
+/// A deleted key with everything needed to restore it, encrypted like the key's value
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Item {
+    /// Display path of the key ('category/name' or just 'name')
+    pub path: String,
+    /// When the key was deleted (Unix seconds)
+    pub deleted: u64,
+    /// Base64 encoded value of a stored key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    /// Settings of a derived key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    derived: Option<DerivedKey>,
+    /// The key's metadata; its attachments stay in place until the item is purged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<KeyMeta>,
+}
+
+impl Item {
+    /// Returns true if the deleted key was derived rather than stored
+    pub fn is_derived(&self) -> bool {
+        self.derived.is_some()
+    }
+
+    /// Returns the metadata the key had when it was deleted
+    pub fn meta(&self) -> Option<&KeyMeta> {
+        self.meta.as_ref()
+    }
+}
+
+/// Returns the repository path of a deleted key
+pub fn item_path(deleted: u64, path: &str) -> String {
+    format!("{}/{}/{}.json", TRASH_DIR, deleted, path.trim_matches('/'))
+}
+
+/// Returns when the key in a trash file was deleted, from its repository path
+fn deleted_at(item_file: &str) -> Option<u64> {
+    item_file
+        .strip_prefix(TRASH_DIR)?
+        .strip_prefix('/')?
+        .split('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Returns true if a key deleted at a time (Unix seconds) is due to be purged
+pub fn is_expired(deleted: u64, settings: &Settings, now: u64) -> bool {
+    settings
+        .purge_after
+        .is_some_and(|age| now >= deleted.saturating_add(age))
+}
+
+/// Loads the trash settings (the defaults if none are saved)
+pub async fn load_settings(storage: &Storage) -> Result<Settings> {
+    match storage.read_file(SETTINGS_PATH).await? {
+        Some(data) => serde_json::from_slice(&data).context("Trash settings are corrupted"),
+        None => Ok(Settings::default()),
+    }
+}
+
+/// Builds the changes that save the trash settings
+pub async fn settings_changes(storage: &Storage, settings: &Settings) -> Result<Vec<TreeChange>> {
+    storage
+        .path_changes(SETTINGS_PATH, Some(&serde_json::to_vec_pretty(settings)?))
+        .await
+}
+
+/// Lists the repository paths of the deleted keys
+pub async fn item_files(storage: &Storage) -> Result<Vec<String>> {
+    Ok(storage
+        .list_files_recursive(TRASH_DIR)
+        .await?
+        .into_iter()
+        .filter(|path| path.ends_with(".json"))
+        .collect())
+}
+
+/// Reads and decrypts a deleted key
+async fn load_item(storage: &Storage, master_key: &str, item_file: &str) -> Result<Option<Item>> {
+    let data = match storage.read_file(item_file).await? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let encrypted: EncryptedBlob = serde_json::from_slice(&data)
+        .with_context(|| format!("Failed to parse deleted key '{}'", item_file))?;
+    let decrypted = CryptoHandler::decrypt(&encrypted, master_key)
+        .with_context(|| format!("Failed to decrypt deleted key '{}'", item_file))?;
+    Ok(Some(serde_json::from_slice(&decrypted).with_context(
+        || format!("Deleted key '{}' is corrupted", item_file),
+    )?))
+}
+
+/// Lists the deleted keys with their repository paths, most recently deleted first
+pub async fn list(storage: &Storage, master_key: &str) -> Result<Vec<(String, Item)>> {
+    let mut items = Vec::new();
+    for path in item_files(storage).await? {
+        if let Some(item) = load_item(storage, master_key, &path).await? {
+            items.push((path, item));
+        }
+    }
+    items.sort_by(|(_, a), (_, b)| b.deleted.cmp(&a.deleted).then(a.path.cmp(&b.path)));
+    Ok(items)
+}
+
+/// Builds the changes that move keys (stored or derived, by display path) to the trash.
+/// Derived keys are removed from the registry, which the caller saves.
+pub async fn trash_changes(
+    storage: &Storage,
+    master_key: &str,
+    registry: &mut DerivedRegistry,
+    paths: &[String],
+    now: u64,
+) -> Result<Vec<TreeChange>> {
+    let mut changes = Vec::new();
+    for path in paths {
+        let (category, name) = split_key_path(path);
+        let id = derived::key_id(name, category)?;
+        let meta = meta::load(storage, master_key, name, category).await?;
+
+        let (value, derived) = match registry.keys.remove(&id) {
+            Some(derived) => (None, Some(derived)),
+            None => {
+                let (data, _) = storage
+                    .get_blob(name, category)
+                    .await?
+                    .with_context(|| format!("Key '{}' not found", path))?;
+                let encrypted: EncryptedBlob = serde_json::from_slice(&data)
+                    .with_context(|| format!("Failed to parse key '{}'", path))?;
+                let value = CryptoHandler::decrypt(&encrypted, master_key)
+                    .with_context(|| format!("Key '{}' cannot be decrypted", path))?;
+                changes.extend(storage.key_changes(name, category, None).await?);
+                (Some(BASE64.encode(value)), None)
+            }
+        };
+        if !meta.is_empty() {
+            changes.extend(
+                storage
+                    .path_changes(&meta::meta_path(name, category)?, None)
+                    .await?,
+            );
+        }
+
+        let item = Item {
+            path: id,
+            deleted: now,
+            value,
+            derived,
+            meta: (!meta.is_empty()).then_some(meta),
+        };
+        // A key scoped to some categories can only encrypt for those categories
+        let encrypted = vault::encrypt_value(master_key, category, &serde_json::to_vec(&item)?)?;
+        changes.extend(
+            storage
+                .path_changes(
+                    &item_path(now, &item.path),
+                    Some(&serde_json::to_vec(&encrypted)?),
+                )
+                .await?,
+        );
+    }
+    Ok(changes)
+}
+
+/// Builds the changes that put a deleted key back in place (replacing a key that now exists
+/// at its path) and remove it from the trash. Derived keys are added back to the registry,
+/// which the caller saves.
+pub async fn restore_changes(
+    storage: &Storage,
+    master_key: &str,
+    registry: &mut DerivedRegistry,
+    item_file: &str,
+    item: &Item,
+) -> Result<Vec<TreeChange>> {
+    let (category, name) = split_key_path(&item.path);
+    let mut changes = Vec::new();
+    if let Some(value) = &item.value {
+        let value = BASE64
+            .decode(value)
+            .with_context(|| format!("Deleted key '{}' is corrupted", item.path))?;
+        let encrypted = vault::encrypt_value(master_key, category, &value)?;
+        changes.extend(
+            storage
+                .key_changes(name, category, Some(&serde_json::to_vec(&encrypted)?))
+                .await?,
+        );
+        registry.keys.remove(&item.path);
+    }
+    if let Some(derived) = &item.derived {
+        registry.keys.insert(item.path.clone(), derived.clone());
+        changes.extend(storage.key_changes(name, category, None).await?);
+    }
+    changes.extend(
+        meta::save_changes(
+            storage,
+            master_key,
+            name,
+            category,
+            item.meta.as_ref().unwrap_or(&KeyMeta::default()),
+        )
+        .await?,
+    );
+    changes.extend(storage.path_changes(item_file, None).await?);
+    Ok(changes)
+}
+
+/// Builds the changes that remove a deleted key for good, with its attachments
+pub async fn purge_changes(
+    storage: &Storage,
+    item_file: &str,
+    item: &Item,
+) -> Result<Vec<TreeChange>> {
+    let mut changes = Vec::new();
+    if let Some(meta) = &item.meta {
+        for attachment in meta.attachments.values() {
+            changes.extend(
+                storage
+                    .path_changes(&meta::attachment_path(&attachment.id), None)
+                    .await?,
+            );
+        }
+    }
+    changes.extend(storage.path_changes(item_file, None).await?);
+    Ok(changes)
+}
+
+/// Builds the changes that purge the deleted keys past the retention of the trash. Only
+/// those are decrypted, since the deletion time is part of their path. Returns the changes
+/// with the number of purged keys.
+pub async fn expired_changes(
+    storage: &Storage,
+    master_key: &str,
+    now: u64,
+) -> Result<(Vec<TreeChange>, usize)> {
+    let settings = load_settings(storage).await?;
+    let mut changes = Vec::new();
+    let mut purged = 0;
+    for path in item_files(storage).await? {
+        if !deleted_at(&path).is_some_and(|deleted| is_expired(deleted, &settings, now)) {
+            continue;
+        }
+        if let Some(item) = load_item(storage, master_key, &path).await? {
+            changes.extend(purge_changes(storage, &path, &item).await?);
+            purged += 1;
+        }
+    }
+    Ok((changes, purged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(deleted: u64) -> Item {
+        Item {
+            path: "api/prod/token".to_string(),
+            deleted,
+            value: Some(BASE64.encode("sk_live")),
+            derived: None,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_item_path() {
+        assert_eq!(
+            item_path(1700000000, "/api/prod/token"),
+            ".axkeystore/trash/1700000000/api/prod/token.json"
+        );
+        assert_eq!(item_path(5, "token"), ".axkeystore/trash/5/token.json");
+    }
+
+    #[test]
+    fn test_deleted_at() {
+        assert_eq!(
+            deleted_at(&item_path(1700000000, "api/token")),
+            Some(1700000000)
+        );
+        assert_eq!(deleted_at(".axkeystore/trash/later/api/token.json"), None);
+        assert_eq!(deleted_at(".axkeystore/meta/api/token.json"), None);
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let settings = Settings {
+            purge_after: Some(60),
+        };
+        assert!(!is_expired(1000, &settings, 1059));
+        assert!(is_expired(1000, &settings, 1060));
+        let keep = Settings { purge_after: None };
+        assert!(!is_expired(0, &keep, u64::MAX));
+        assert_eq!(
+            Settings::default().purge_after,
+            Some(DEFAULT_PURGE_AFTER_SECS)
+        );
+    }
+
+    #[test]
+    fn test_item_roundtrip() {
+        let json = serde_json::to_string(&item(7)).unwrap();
+        assert!(!json.contains("derived"));
+        assert_eq!(serde_json::from_str::<Item>(&json).unwrap(), item(7));
+    }
+}
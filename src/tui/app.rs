@@ -1,7 +1,10 @@
-use anyhow::{Context, Result};
-use std::collections::BTreeMap;
-use crate::storage::Storage;
 use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::meta::KeyMeta;
+use crate::storage::{KeyVersion, Storage};
+use crate::tui::history::History;
+use crate::tui::tree::{self, Row};
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
 
 pub enum InputMode {
     Normal,
@@ -16,13 +19,51 @@ pub enum InputMode {
     AddingProfileRepo,
     AddingProfilePassword,
     ConfirmingDeleteProfile,
+    Searching,
+    ConfirmingDeleteKey,
+}
+
+/// A decrypted key of the vault
+pub struct KeyItem {
+    pub category: Option<String>,
+    pub name: String,
+    pub value: Vec<u8>,
 }
 
+impl KeyItem {
+    pub fn display_path(&self) -> String {
+        match &self.category {
+            Some(cat) => format!("{}/{}", cat, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// The value as text, or None if it is binary
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.value).ok()
+    }
+}
+
+/// Metadata and recent history of a key, loaded when the key is opened
+pub struct KeyDetails {
+    pub meta: KeyMeta,
+    pub history: Vec<KeyVersion>,
+}
+
+/// Number of versions shown in the details pane
+const HISTORY_LIMIT: u32 = 10;
+
 pub struct App {
     pub storage: Storage,
     pub master_key: String,
-    pub entries: BTreeMap<Option<String>, Vec<(String, String)>>,
-    pub flat_entries: Vec<(Option<String>, String, String)>, // Category, Key, Decrypted Value
+    pub password: String,
+    pub keys: Vec<KeyItem>,
+    pub rows: Vec<Row>,
+    pub collapsed: BTreeSet<String>,
+    pub search_query: String,
+    pub details: HashMap<String, KeyDetails>,
+    pub reveal: bool,
+    pub status: Option<String>,
     pub selected_index: usize,
     pub input_mode: InputMode,
     pub category_input: String,
@@ -35,15 +76,24 @@ pub struct App {
     pub new_profile_name: String,
     pub new_profile_repo: String,
     pub new_profile_password: String,
+    pub history: History,
+    /// Query and position of the current reverse search through the history
+    pub history_match: Option<(String, usize)>,
 }
 
 impl App {
-    pub async fn new(storage: Storage, master_key: String) -> Result<App> {
+    pub async fn new(storage: Storage, master_key: String, password: String) -> Result<App> {
         let mut app = App {
             storage,
             master_key,
-            entries: BTreeMap::new(),
-            flat_entries: Vec::new(),
+            password,
+            keys: Vec::new(),
+            rows: Vec::new(),
+            collapsed: BTreeSet::new(),
+            search_query: String::new(),
+            details: HashMap::new(),
+            reveal: false,
+            status: None,
             selected_index: 0,
             input_mode: InputMode::Normal,
             category_input: String::new(),
@@ -56,55 +106,279 @@ impl App {
             new_profile_name: String::new(),
             new_profile_repo: String::new(),
             new_profile_password: String::new(),
+            history: History::default(),
+            history_match: None,
         };
+        app.load_history();
         app.load_keys().await?;
         Ok(app)
     }
 
     pub async fn load_keys(&mut self) -> Result<()> {
         let entries = self.storage.list_all_keys().await?;
-        
-        self.entries.clear();
-        for entry in &entries {
+
+        self.keys.clear();
+        for entry in entries {
             let encrypted: EncryptedBlob = serde_json::from_slice(&entry.data)
                 .context("Failed to parse encrypted blob")?;
-            if let Ok(decrypted) = CryptoHandler::decrypt(&encrypted, &self.master_key) {
-                if let Ok(value) = String::from_utf8(decrypted) {
-                    self.entries
-                        .entry(entry.category.clone())
-                        .or_default()
-                        .push((entry.name.clone(), value));
-                }
-            }
-        }
-
-        self.flat_entries.clear();
-        for (category, pairs) in &self.entries {
-            for (name, value) in pairs {
-                self.flat_entries.push((category.clone(), name.clone(), value.clone()));
+            if let Ok(value) = CryptoHandler::decrypt(&encrypted, &self.master_key) {
+                self.keys.push(KeyItem {
+                    category: entry.category,
+                    name: entry.name,
+                    value,
+                });
             }
         }
 
+        self.details.clear();
         self.selected_index = 0;
+        self.refresh_rows();
         Ok(())
     }
 
+    /// Rebuilds the visible rows: the category tree, or the matches of the search query
+    pub fn refresh_rows(&mut self) {
+        self.rows = if self.search_query.trim().is_empty() {
+            let keys: Vec<(Option<&str>, &str)> = self
+                .keys
+                .iter()
+                .map(|k| (k.category.as_deref(), k.name.as_str()))
+                .collect();
+            tree::build_rows(&keys, &self.collapsed)
+        } else {
+            let paths: Vec<String> = self.keys.iter().map(|k| k.display_path()).collect();
+            tree::search_rows(&paths, &self.search_query)
+        };
+        if self.selected_index >= self.rows.len() {
+            self.selected_index = self.rows.len().saturating_sub(1);
+        }
+    }
+
+    /// Returns the index of the selected key, if a key (not a category) is selected
+    pub fn selected_key_index(&self) -> Option<usize> {
+        match self.rows.get(self.selected_index) {
+            Some(Row::Key { index, .. }) => Some(*index),
+            _ => None,
+        }
+    }
+
+    pub fn selected_key(&self) -> Option<&KeyItem> {
+        self.selected_key_index().map(|i| &self.keys[i])
+    }
+
     pub fn next(&mut self) {
-        if !self.flat_entries.is_empty() {
-            self.selected_index = (self.selected_index + 1) % self.flat_entries.len();
+        if !self.rows.is_empty() {
+            self.selected_index = (self.selected_index + 1) % self.rows.len();
+            self.reveal = false;
         }
     }
 
     pub fn previous(&mut self) {
-        if !self.flat_entries.is_empty() {
+        if !self.rows.is_empty() {
             if self.selected_index > 0 {
                 self.selected_index -= 1;
             } else {
-                self.selected_index = self.flat_entries.len() - 1;
+                self.selected_index = self.rows.len() - 1;
             }
+            self.reveal = false;
         }
     }
 
+    /// Collapses the selected category, or moves from a key to its category
+    pub fn collapse(&mut self) {
+        match self.rows.get(self.selected_index) {
+            Some(Row::Category {
+                path,
+                expanded: true,
+                ..
+            }) => {
+                self.collapsed.insert(path.clone());
+                self.refresh_rows();
+            }
+            Some(Row::Key { index, depth }) if *depth > 0 => {
+                let category = self.keys[*index].category.clone();
+                if let Some(pos) = self.rows.iter().position(
+                    |r| matches!(r, Row::Category { path, .. } if Some(path) == category.as_ref()),
+                ) {
+                    self.selected_index = pos;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn expand(&mut self) {
+        if let Some(Row::Category {
+            path,
+            expanded: false,
+            ..
+        }) = self.rows.get(self.selected_index)
+        {
+            self.collapsed.remove(path);
+            self.refresh_rows();
+        }
+    }
+
+    /// Expands or collapses the selected category. Returns true if a key is selected instead.
+    pub fn toggle(&mut self) -> bool {
+        match self.rows.get(self.selected_index) {
+            Some(Row::Category { expanded: true, .. }) => {
+                self.collapse();
+                false
+            }
+            Some(Row::Category { .. }) => {
+                self.expand();
+                false
+            }
+            Some(Row::Key { .. }) => true,
+            None => false,
+        }
+    }
+
+    /// Loads the metadata and recent history of the selected key, unless already loaded
+    pub async fn load_details(&mut self) -> Result<()> {
+        let item = match self.selected_key() {
+            Some(item) => item,
+            None => return Ok(()),
+        };
+        let path = item.display_path();
+        if self.details.contains_key(&path) {
+            return Ok(());
+        }
+        let (name, category) = (item.name.clone(), item.category.clone());
+        let meta =
+            crate::meta::load(&self.storage, &self.master_key, &name, category.as_deref()).await?;
+        let history = self
+            .storage
+            .get_key_history(&name, category.as_deref(), 1, HISTORY_LIMIT)
+            .await?;
+        self.details.insert(path, KeyDetails { meta, history });
+        Ok(())
+    }
+
+    pub fn toggle_reveal(&mut self) {
+        if self.selected_key().is_some() {
+            self.reveal = !self.reveal;
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.input_mode = InputMode::Searching;
+    }
+
+    pub fn handle_search_char(&mut self, c: char) {
+        self.history_match = None;
+        self.search_query.push(c);
+        self.selected_index = 0;
+        self.refresh_rows();
+    }
+
+    pub fn handle_search_backspace(&mut self) {
+        self.history_match = None;
+        self.search_query.pop();
+        self.selected_index = 0;
+        self.refresh_rows();
+    }
+
+    /// Keeps the current matches and returns to browsing them
+    pub fn finish_search(&mut self) {
+        self.record_history(&self.search_query.clone());
+        self.history_match = None;
+        self.input_mode = InputMode::Normal;
+    }
+
+    /// Drops the search and returns to the category tree
+    pub fn clear_search(&mut self) {
+        self.history_match = None;
+        self.search_query.clear();
+        self.selected_index = 0;
+        self.refresh_rows();
+        self.input_mode = InputMode::Normal;
+    }
+
+    pub fn copy_selected(&mut self) {
+        let item = match self.selected_key() {
+            Some(item) => item,
+            None => return,
+        };
+        let status = match item.text() {
+            Some(text) => match crate::clipboard::copy(text) {
+                Ok(()) => format!("Copied '{}' to the clipboard.", item.display_path()),
+                Err(e) => format!("Failed to copy: {}", e),
+            },
+            None => format!(
+                "'{}' holds binary data and cannot be copied.",
+                item.display_path()
+            ),
+        };
+        self.status = Some(status);
+    }
+
+    /// Saves an edited value of a key
+    pub async fn save_edit(&mut self, index: usize, value: Vec<u8>) -> Result<()> {
+        let item = &self.keys[index];
+        let path = item.display_path();
+        let encrypted =
+            axkeystore::vault::encrypt_value(&self.master_key, item.category.as_deref(), &value)?;
+        match self
+            .storage
+            .save_blob(
+                &item.name,
+                &serde_json::to_vec(&encrypted)?,
+                item.category.as_deref(),
+            )
+            .await
+        {
+            Ok(_) => {
+                self.keys[index].value = value;
+                // The history gained a version
+                self.details.remove(&path);
+                self.status = Some(format!("Key '{}' updated.", path));
+                self.input_mode = InputMode::Normal;
+            }
+            Err(e) => {
+                self.input_mode = InputMode::Error(format!("Failed to save: {}", e));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start_delete_key(&mut self) {
+        if self.selected_key().is_some() {
+            self.input_mode = InputMode::ConfirmingDeleteKey;
+        }
+    }
+
+    /// Moves the selected key, together with its metadata, to the trash
+    pub async fn delete_selected(&mut self) -> Result<()> {
+        let index = match self.selected_key_index() {
+            Some(index) => index,
+            None => {
+                self.input_mode = InputMode::Normal;
+                return Ok(());
+            }
+        };
+        self.input_mode = InputMode::Processing;
+        let path = self.keys[index].display_path();
+
+        crate::delete_keys(
+            &self.storage,
+            &self.password,
+            &self.master_key,
+            std::slice::from_ref(&path),
+            &format!("Delete key: {}", path),
+            &crate::audit::AuditEvent::new("delete", &path),
+        )
+        .await?;
+
+        self.keys.remove(index);
+        self.details.remove(&path);
+        self.refresh_rows();
+        self.status = Some(format!("Key '{}' moved to the trash.", path));
+        self.input_mode = InputMode::Normal;
+        Ok(())
+    }
+
     pub fn start_add_key(&mut self) {
         self.category_input.clear();
         self.name_input.clear();
@@ -113,9 +387,69 @@ impl App {
     }
 
     pub fn cancel_input(&mut self) {
+        self.history_match = None;
         self.input_mode = InputMode::Normal;
     }
 
+    /// Loads the history saved by earlier sessions of the current profile
+    fn load_history(&mut self) {
+        let profile = self.storage.profile();
+        self.history =
+            crate::config::Config::get_or_create_lmk_with_profile(profile, &self.password)
+                .and_then(|lmk| History::load(profile, &self.storage.repo_full_name(), &lmk))
+                .unwrap_or_default();
+        self.history_match = None;
+    }
+
+    /// Saves the history for later sessions, if the cache policy keeps metadata
+    pub fn save_history(&self) -> Result<()> {
+        let profile = self.storage.profile();
+        let lmk = crate::config::Config::get_or_create_lmk_with_profile(profile, &self.password)?;
+        self.history
+            .save(profile, &self.storage.repo_full_name(), &lmk)
+    }
+
+    /// Records typed text in the history, unless it contains the value of a key
+    fn record_history(&mut self, entry: &str) {
+        let secrets: Vec<&str> = self.keys.iter().filter_map(|k| k.text()).collect();
+        self.history.record(entry, &secrets);
+    }
+
+    /// Returns the text being typed in the current mode, if it can be recalled from the history
+    fn history_input(&mut self) -> Option<&mut String> {
+        match self.input_mode {
+            InputMode::Searching => Some(&mut self.search_query),
+            InputMode::AddingCategory => Some(&mut self.category_input),
+            InputMode::AddingName => Some(&mut self.name_input),
+            _ => None,
+        }
+    }
+
+    /// Replaces the current input with the next older history entry containing the text typed
+    /// before the reverse search started (Ctrl-R)
+    pub fn reverse_search_history(&mut self) {
+        let typed = match self.history_input() {
+            Some(input) => input.clone(),
+            None => return,
+        };
+        let (query, before) = match &self.history_match {
+            Some((query, position)) => (query.clone(), Some(*position)),
+            None => (typed, None),
+        };
+        let (position, entry) = match self.history.reverse_search(&query, before) {
+            Some((position, entry)) => (position, entry.to_string()),
+            None => return,
+        };
+        if let Some(input) = self.history_input() {
+            *input = entry;
+        }
+        self.history_match = Some((query, position));
+        if matches!(self.input_mode, InputMode::Searching) {
+            self.selected_index = 0;
+            self.refresh_rows();
+        }
+    }
+
     pub fn start_switch_profile(&mut self) {
         let mut profiles = vec!["default".to_string()];
         if let Ok(loaded) = crate::config::GlobalConfig::list_profiles() {
@@ -225,6 +559,7 @@ impl App {
     }
 
     pub fn handle_char(&mut self, c: char) {
+        self.history_match = None;
         match self.input_mode {
             InputMode::AddingCategory => self.category_input.push(c),
             InputMode::AddingName => self.name_input.push(c),
@@ -234,6 +569,7 @@ impl App {
     }
 
     pub fn handle_backspace(&mut self) {
+        self.history_match = None;
         match self.input_mode {
             InputMode::AddingCategory => { self.category_input.pop(); },
             InputMode::AddingName => { self.name_input.pop(); },
@@ -245,11 +581,15 @@ impl App {
     pub fn handle_enter(&mut self) -> bool {
         match self.input_mode {
             InputMode::AddingCategory => {
+                self.record_history(&self.category_input.clone());
+                self.history_match = None;
                 self.input_mode = InputMode::AddingName;
                 false
             }
             InputMode::AddingName => {
                 if !self.name_input.trim().is_empty() {
+                    self.record_history(&self.name_input.clone());
+                    self.history_match = None;
                     self.input_mode = InputMode::AddingValue;
                 }
                 false
@@ -370,8 +710,11 @@ impl App {
             }
         };
 
+        let _ = self.save_history();
         self.storage = storage;
         self.master_key = master_key;
+        self.password = password;
+        self.load_history();
         if let Err(e) = self.load_keys().await {
             self.input_mode = InputMode::Error(format!("Failed to load keys: {}", e));
             return Ok(());
@@ -462,13 +805,20 @@ impl App {
             }
         };
 
-        if let Err(e) = crate::config::Config::set_repo_name_with_profile(Some(&name), &repo, &password) {
+        if let Err(e) = crate::config::Config::set_repo_name_with_profile(
+            Some(&name),
+            &storage.repo_full_name(),
+            &password,
+        ) {
             self.input_mode = InputMode::Error(format!("Failed to save config: {}", e));
             return Ok(());
         }
 
+        let _ = self.save_history();
         self.storage = storage;
         self.master_key = master_key;
+        self.password = password;
+        self.load_history();
         if let Err(e) = self.load_keys().await {
             self.input_mode = InputMode::Error(format!("Failed to load keys: {}", e));
             return Ok(());
@@ -0,0 +1,116 @@
+use crate::cache::{self, CacheKind};
+use anyhow::{Context, Result};
+
+/// Most entries kept; the oldest are dropped first
+const MAX_ENTRIES: usize = 200;
+
+/// Shortest secret value that is looked for inside an entry before recording it
+const MIN_SECRET_LEN: usize = 6;
+
+/// Text typed in the TUI (searches, categories and key names), oldest first, so long key
+/// paths can be recalled with reverse search. Values and passwords are never recorded.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+/// Returns the cache entry name of the history of a repository
+fn cache_name(repo: &str) -> String {
+    format!("tui_history_{}", repo)
+}
+
+impl History {
+    /// Loads the history saved by earlier sessions (empty if there is none or it expired)
+    pub fn load(profile: Option<&str>, repo: &str, lmk: &str) -> Result<Self> {
+        match cache::load(profile, CacheKind::Metadata, &cache_name(repo), lmk)? {
+            Some(data) => Ok(Self {
+                entries: serde_json::from_slice(&data).context("Saved TUI history is corrupted")?,
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Saves the history encrypted in the metadata cache, so it is kept only as long as the
+    /// cache policy allows (and not at all if metadata caching is disabled)
+    pub fn save(&self, profile: Option<&str>, repo: &str, lmk: &str) -> Result<()> {
+        cache::store(
+            profile,
+            CacheKind::Metadata,
+            &cache_name(repo),
+            &serde_json::to_vec(&self.entries)?,
+            lmk,
+        )
+    }
+
+    /// Records an entry as the newest one. Blank entries are ignored, and so are entries that
+    /// contain one of the given secret values (e.g. a value pasted into the search box).
+    pub fn record(&mut self, entry: &str, secrets: &[&str]) {
+        let entry = entry.trim();
+        if entry.is_empty()
+            || secrets
+                .iter()
+                .any(|s| s.trim() == entry || (s.len() >= MIN_SECRET_LEN && entry.contains(s)))
+        {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+    }
+
+    /// Finds the newest entry containing the query that is older than the entry at `before`
+    /// (or the newest overall), returning its position and text
+    pub fn reverse_search(&self, query: &str, before: Option<usize>) -> Option<(usize, &str)> {
+        let end = before.unwrap_or(self.entries.len()).min(self.entries.len());
+        let query = query.to_lowercase();
+        self.entries[..end]
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, e)| e.to_lowercase().contains(&query))
+            .map(|(i, e)| (i, e.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let mut history = History::default();
+        history.record("  prod/payments ", &[]);
+        history.record("", &[]);
+        history.record("hunter2-secret", &["hunter2-secret"]);
+        history.record("pasted sk_live_123456 here", &["sk_live_123456"]);
+        history.record("dev", &[]);
+        history.record("prod/payments", &[]);
+        assert_eq!(history.entries, vec!["dev", "prod/payments"]);
+
+        for i in 0..MAX_ENTRIES {
+            history.record(&format!("entry-{}", i), &[]);
+        }
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert_eq!(history.entries[0], "entry-0");
+    }
+
+    #[test]
+    fn test_reverse_search() {
+        let mut history = History::default();
+        for entry in ["prod/db", "dev/api", "prod/payments/stripe", "staging"] {
+            history.record(entry, &[]);
+        }
+        assert_eq!(
+            history.reverse_search("PROD", None),
+            Some((2, "prod/payments/stripe"))
+        );
+        assert_eq!(
+            history.reverse_search("prod", Some(2)),
+            Some((0, "prod/db"))
+        );
+        assert_eq!(history.reverse_search("prod", Some(0)), None);
+        assert_eq!(history.reverse_search("", None), Some((3, "staging")));
+    }
+}
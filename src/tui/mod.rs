@@ -1,6 +1,6 @@
 use anyhow::Result;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -11,6 +11,8 @@ use ratatui::{
 use std::{io, time::Duration};
 
 pub mod app;
+pub mod history;
+pub mod tree;
 pub mod ui;
 
 use app::App;
@@ -52,10 +54,16 @@ pub fn draw_loading(terminal: &mut TuiTerminal, msg: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn run(mut terminal: TuiTerminal, storage: Storage, master_key: String) -> Result<()> {
+pub async fn run(
+    mut terminal: TuiTerminal,
+    storage: Storage,
+    master_key: String,
+    password: String,
+) -> Result<()> {
     // create app and run it
-    let mut app = App::new(storage, master_key).await?;
+    let mut app = App::new(storage, master_key, password).await?;
     let res = run_app(&mut terminal, &mut app).await;
+    let _ = app.save_history();
 
     // restore terminal
     restore_terminal(terminal)?;
@@ -67,6 +75,48 @@ pub async fn run(mut terminal: TuiTerminal, storage: Storage, master_key: String
     Ok(())
 }
 
+/// Hands the terminal to the user's editor to change the selected key, then saves the result
+async fn edit_selected(terminal: &mut TuiTerminal, app: &mut App) -> Result<()> {
+    let index = match app.selected_key_index() {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+    if app.keys[index].text().is_none() {
+        app.status = Some(format!(
+            "'{}' holds binary data and cannot be edited here.",
+            app.keys[index].display_path()
+        ));
+        return Ok(());
+    }
+
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    let edited = crate::editor::edit(&app.keys[index].value);
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableMouseCapture
+    )?;
+    terminal.clear()?;
+
+    match edited? {
+        Some(value) => {
+            app.input_mode = app::InputMode::Processing;
+            terminal.draw(|f| ui::draw(f, app))?;
+            app.save_edit(index, value).await
+        }
+        None => {
+            app.status = Some("No changes; nothing saved.".to_string());
+            Ok(())
+        }
+    }
+}
+
 async fn run_app(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>, app: &mut App) -> Result<()> {
     loop {
         terminal.draw(|f| ui::draw(f, app))?;
@@ -76,19 +126,73 @@ async fn run_app(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>, app
             if let Event::Key(key) = event::read()? {
                 match app.input_mode {
                     app::InputMode::Normal => {
+                        app.status = None;
                         match key.code {
-                            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                            KeyCode::Char('q') => return Ok(()),
+                            KeyCode::Esc if !app.search_query.is_empty() => app.clear_search(),
+                            KeyCode::Esc => return Ok(()),
                             KeyCode::Up => app.previous(),
                             KeyCode::Down => app.next(),
+                            KeyCode::Left => app.collapse(),
+                            KeyCode::Right => app.expand(),
+                            KeyCode::Enter => {
+                                if app.toggle() {
+                                    if let Err(e) = app.load_details().await {
+                                        app.input_mode = app::InputMode::Error(format!(
+                                            "Failed to load details: {}",
+                                            e
+                                        ));
+                                    }
+                                }
+                            }
+                            KeyCode::Char('/') => app.start_search(),
+                            KeyCode::Char('v') => app.toggle_reveal(),
+                            KeyCode::Char('c') => app.copy_selected(),
+                            KeyCode::Char('e') => {
+                                if let Err(e) = edit_selected(terminal, app).await {
+                                    app.input_mode =
+                                        app::InputMode::Error(format!("Failed to edit: {}", e));
+                                }
+                            }
+                            KeyCode::Char('d') => app.start_delete_key(),
                             KeyCode::Char('a') => app.start_add_key(),
                             KeyCode::Char('p') => app.start_switch_profile(),
                             _ => {}
                         }
                     }
-                    app::InputMode::AddingCategory 
-                    | app::InputMode::AddingName 
+                    app::InputMode::Searching => match key.code {
+                        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app.reverse_search_history()
+                        }
+                        KeyCode::Char(c) => app.handle_search_char(c),
+                        KeyCode::Backspace => app.handle_search_backspace(),
+                        KeyCode::Up => app.previous(),
+                        KeyCode::Down => app.next(),
+                        KeyCode::Enter => app.finish_search(),
+                        KeyCode::Esc => app.clear_search(),
+                        _ => {}
+                    },
+                    app::InputMode::ConfirmingDeleteKey => match key.code {
+                        KeyCode::Char('y') | KeyCode::Char('Y') => {
+                            app.input_mode = app::InputMode::Processing;
+                            terminal.draw(|f| ui::draw(f, app))?;
+                            if let Err(e) = app.delete_selected().await {
+                                app.input_mode =
+                                    app::InputMode::Error(format!("Failed to delete: {}", e));
+                            }
+                        }
+                        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                            app.cancel_input()
+                        }
+                        _ => {}
+                    },
+                    app::InputMode::AddingCategory
+                    | app::InputMode::AddingName
                     | app::InputMode::AddingValue => {
                         match key.code {
+                            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                app.reverse_search_history()
+                            }
                             KeyCode::Char(c) => app.handle_char(c),
                             KeyCode::Backspace => app.handle_backspace(),
                             KeyCode::Enter => {
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// One line of the key browser
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+    /// A category; `path` is the full category path and `name` its last segment
+    Category {
+        path: String,
+        name: String,
+        depth: usize,
+        expanded: bool,
+    },
+    /// A key, by index into the list of keys
+    Key { index: usize, depth: usize },
+}
+
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    keys: Vec<usize>,
+}
+
+/// Builds the rows of the category tree. Subcategories are listed before the keys of a
+/// category; the contents of collapsed categories are left out.
+pub fn build_rows(keys: &[(Option<&str>, &str)], collapsed: &BTreeSet<String>) -> Vec<Row> {
+    let mut root = Node::default();
+    for (index, (category, _)) in keys.iter().enumerate() {
+        let mut node = &mut root;
+        for segment in category
+            .unwrap_or_default()
+            .split('/')
+            .filter(|s| !s.is_empty())
+        {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+        node.keys.push(index);
+    }
+
+    let mut rows = Vec::new();
+    walk(&root, "", 0, keys, collapsed, &mut rows);
+    rows
+}
+
+fn walk(
+    node: &Node,
+    path: &str,
+    depth: usize,
+    keys: &[(Option<&str>, &str)],
+    collapsed: &BTreeSet<String>,
+    rows: &mut Vec<Row>,
+) {
+    for (name, child) in &node.children {
+        let child_path = if path.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", path, name)
+        };
+        let expanded = !collapsed.contains(&child_path);
+        rows.push(Row::Category {
+            path: child_path.clone(),
+            name: name.clone(),
+            depth,
+            expanded,
+        });
+        if expanded {
+            walk(child, &child_path, depth + 1, keys, collapsed, rows);
+        }
+    }
+    let mut indexes = node.keys.clone();
+    indexes.sort_by_key(|i| keys[*i].1);
+    rows.extend(indexes.into_iter().map(|index| Row::Key { index, depth }));
+}
+
+/// Scores how well a query matches a key path, or None if it does not match. The query's
+/// characters must appear in order; consecutive characters and characters at the start of
+/// a segment ('/', '-', '_', '.') score higher, so 'prdb' ranks 'prod/db' above 'prod/api-db'.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    let chars: Vec<char> = candidate.to_lowercase().chars().collect();
+
+    let mut score = 0i64;
+    let mut matched = 0;
+    let mut previous: Option<usize> = None;
+    for (i, c) in chars.iter().enumerate() {
+        if matched == query.len() {
+            break;
+        }
+        if *c != query[matched] {
+            continue;
+        }
+        score += 1;
+        if i > 0 && previous == Some(i - 1) {
+            score += 5;
+        }
+        if i == 0 || matches!(chars[i - 1], '/' | '-' | '_' | '.') {
+            score += 10;
+        }
+        previous = Some(i);
+        matched += 1;
+    }
+    if matched < query.len() {
+        return None;
+    }
+    // Prefer shorter paths among equally good matches
+    Some(score * 100 - chars.len() as i64)
+}
+
+/// Returns the keys matching a query as a flat list, best match first
+pub fn search_rows(paths: &[String], query: &str) -> Vec<Row> {
+    let mut found: Vec<(i64, usize)> = paths
+        .iter()
+        .enumerate()
+        .filter_map(|(index, path)| fuzzy_score(query, path).map(|score| (score, index)))
+        .collect();
+    found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| paths[a.1].cmp(&paths[b.1])));
+    found
+        .into_iter()
+        .map(|(_, index)| Row::Key { index, depth: 0 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> Vec<(Option<&'static str>, &'static str)> {
+        vec![
+            (Some("prod/db"), "password"),
+            (None, "github-token"),
+            (Some("prod"), "api-key"),
+            (Some("dev"), "api-key"),
+            (Some("prod/db"), "host"),
+        ]
+    }
+
+    #[test]
+    fn test_build_rows() {
+        let rows = build_rows(&keys(), &BTreeSet::new());
+        let category = |path: &str, depth| Row::Category {
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap().to_string(),
+            depth,
+            expanded: true,
+        };
+        assert_eq!(
+            rows,
+            vec![
+                category("dev", 0),
+                Row::Key { index: 3, depth: 1 },
+                category("prod", 0),
+                category("prod/db", 1),
+                Row::Key { index: 4, depth: 2 },
+                Row::Key { index: 0, depth: 2 },
+                Row::Key { index: 2, depth: 1 },
+                Row::Key { index: 1, depth: 0 },
+            ]
+        );
+
+        let collapsed: BTreeSet<String> = ["prod".to_string()].into();
+        let rows = build_rows(&keys(), &collapsed);
+        assert_eq!(rows.len(), 4);
+        assert!(matches!(
+            &rows[2],
+            Row::Category {
+                expanded: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("prdb", "prod/db").is_some());
+        assert!(fuzzy_score("dbpr", "prod/db").is_none());
+        assert!(fuzzy_score("PROD", "prod/db").is_some());
+        assert!(fuzzy_score("prdb", "prod/db") > fuzzy_score("prdb", "prod/api-db"));
+        assert_eq!(fuzzy_score("", "anything"), Some(-8));
+    }
+
+    #[test]
+    fn test_search_rows() {
+        let paths: Vec<String> = ["prod/api-key", "dev/api-key", "github-token"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            search_rows(&paths, "api"),
+            vec![
+                Row::Key { index: 1, depth: 0 },
+                Row::Key { index: 0, depth: 0 }
+            ]
+        );
+        assert!(search_rows(&paths, "zzz").is_empty());
+    }
+}
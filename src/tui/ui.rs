@@ -2,11 +2,12 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Clear},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
     Frame,
 };
 
-use crate::tui::app::{App, InputMode};
+use crate::tui::app::{App, InputMode, KeyItem};
+use crate::tui::tree::Row;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let size = f.area();
@@ -36,79 +37,83 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
         .split(chunks[1]);
 
-    // Construct the list of items
+    // Construct the list of rows
     let mut items = Vec::new();
-    let mut current_category: Option<String> = None;
-
-    let mut item_index = 0;
-    for (category, pairs) in &app.entries {
-        if *category != current_category {
-            let cat_name = match category {
-                Some(c) => format!("[{}]", c),
-                None => "(uncategorized)".to_string(),
-            };
-            items.push(ListItem::new(Line::from(Span::styled(
-                cat_name,
-                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
-            ))));
-            current_category = category.clone();
-        }
-
-        for (name, _) in pairs {
-            let mut style = Style::default().fg(Color::White);
-            if item_index == app.selected_index {
-                // Highlight the selected item
-                style = style.fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD);
+    for (i, row) in app.rows.iter().enumerate() {
+        let (text, mut style) = match row {
+            Row::Category {
+                name,
+                depth,
+                expanded,
+                ..
+            } => (
+                format!(
+                    "{}{} {}",
+                    "  ".repeat(*depth),
+                    if *expanded { "\u{25be}" } else { "\u{25b8}" },
+                    name
+                ),
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Row::Key { index, depth } => {
+                let item = &app.keys[*index];
+                // Search results are flat, so show the full path
+                let label = if app.search_query.is_empty() {
+                    item.name.clone()
+                } else {
+                    item.display_path()
+                };
+                (
+                    format!("{}  {}", "  ".repeat(*depth), label),
+                    Style::default().fg(Color::White),
+                )
             }
-
-            items.push(ListItem::new(Line::from(Span::styled(
-                format!("  {}", name),
-                style,
-            ))));
-
-            item_index += 1;
+        };
+        if i == app.selected_index {
+            // Highlight the selected item
+            style = style
+                .fg(Color::Black)
+                .bg(Color::Cyan)
+                .add_modifier(Modifier::BOLD);
         }
+        items.push(ListItem::new(Line::from(Span::styled(text, style))));
     }
 
-    let keys_list = List::new(items)
-        .block(Block::default().title("Keys").borders(Borders::ALL));
+    let keys_title =
+        if app.search_query.is_empty() && !matches!(app.input_mode, InputMode::Searching) {
+            "Keys".to_string()
+        } else {
+            format!("Keys - search: {}", app.search_query)
+        };
+    let keys_list =
+        List::new(items).block(Block::default().title(keys_title).borders(Borders::ALL));
 
     f.render_widget(keys_list, body_chunks[0]);
 
     // Detail view
-    let detail_text = if !app.flat_entries.is_empty() {
-        let selected = &app.flat_entries[app.selected_index];
-        let cat_display = match &selected.0 {
-            Some(c) => c.clone(),
-            None => "(uncategorized)".to_string(),
-        };
-
-        vec![
-            Line::from(vec![
-                Span::styled("Category: ", Style::default().fg(Color::Gray)),
-                Span::raw(cat_display),
-            ]),
-            Line::from(vec![
-                Span::styled("Key:      ", Style::default().fg(Color::Gray)),
-                Span::raw(&selected.1),
-            ]),
-            Line::from(""),
-            Line::from(Span::styled("Value:", Style::default().fg(Color::Gray))),
-            Line::from(Span::styled(&selected.2, Style::default().fg(Color::Green))),
-        ]
-    } else {
-        vec![Line::from(Span::raw("No keys found in this profile."))]
+    let detail_text = match app.selected_key() {
+        Some(selected) => detail_lines(app, selected),
+        None if app.keys.is_empty() => {
+            vec![Line::from(Span::raw("No keys found in this profile."))]
+        }
+        None => vec![Line::from(Span::raw("Select a key to see its details."))],
     };
 
     let detail_view = Paragraph::new(detail_text)
-        .block(Block::default().title("Details").borders(Borders::ALL));
+        .block(Block::default().title("Details").borders(Borders::ALL))
+        .wrap(Wrap { trim: false });
 
     f.render_widget(detail_view, body_chunks[1]);
 
     // Footer
-    let footer_text = match app.input_mode {
-        InputMode::Normal => " Navigate: \u{2191}/\u{2193} | Add: a | Profile: p | Quit: q / Esc ",
-        _ => " Type your input | Enter to submit | Esc to cancel "
+    let footer_text = match (&app.status, &app.input_mode) {
+        (Some(status), InputMode::Normal) => format!(" {} ", status),
+        (None, InputMode::Normal) => " \u{2191}/\u{2193} \u{2190}/\u{2192} | Open: Enter | Search: / | Show: v | Copy: c | Edit: e | Delete: d | Add: a | Profile: p | Quit: q ".to_string(),
+        (_, InputMode::Searching) => " Type to filter | \u{2191}/\u{2193} to move | History: Ctrl-R | Enter to keep results | Esc to clear ".to_string(),
+        (_, InputMode::AddingCategory | InputMode::AddingName) => " Type your input | History: Ctrl-R | Enter to submit | Esc to cancel ".to_string(),
+        _ => " Type your input | Enter to submit | Esc to cancel ".to_string()
     };
     let footer = Paragraph::new(Span::styled(
         footer_text,
@@ -119,7 +124,7 @@ pub fn draw(f: &mut Frame, app: &mut App) {
 
     // Handle Input Popups
     match app.input_mode {
-        InputMode::Normal => {}
+        InputMode::Normal | InputMode::Searching => {}
         InputMode::AddingCategory => {
             draw_input_popup(f, "Enter Category (Optional)", &app.category_input, false);
         }
@@ -130,7 +135,7 @@ pub fn draw(f: &mut Frame, app: &mut App) {
             draw_input_popup(f, "Enter Value", &app.value_input, true);
         }
         InputMode::Processing => {
-            draw_msg_popup(f, "Processing...", "Saving your key securely.");
+            draw_msg_popup(f, "Processing...", "Syncing with GitHub.");
         }
         InputMode::Error(ref msg) => {
             draw_msg_popup(f, "Error", msg);
@@ -150,6 +155,18 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         InputMode::AddingProfilePassword => {
             draw_input_popup(f, "Enter Master Password for the Profile", &app.new_profile_password, true);
         }
+        InputMode::ConfirmingDeleteKey => {
+            if let Some(item) = app.selected_key() {
+                draw_msg_popup(
+                    f,
+                    "Confirm Deletion",
+                    &format!(
+                        "Are you sure you want to delete key '{}'? (y/n)",
+                        item.display_path()
+                    ),
+                );
+            }
+        }
         InputMode::ConfirmingDeleteProfile => {
             if let Some(profile) = app.profiles.get(app.selected_profile_index) {
                 draw_msg_popup(f, "Confirm Deletion", &format!("Are you sure you want to delete profile '{}'? (y/n)", profile));
@@ -158,6 +175,106 @@ pub fn draw(f: &mut Frame, app: &mut App) {
     }
 }
 
+fn detail_lines<'a>(app: &'a App, selected: &'a KeyItem) -> Vec<Line<'a>> {
+    let label = |text: &'a str| Span::styled(text, Style::default().fg(Color::Gray));
+    let cat_display = match &selected.category {
+        Some(c) => c.clone(),
+        None => "(uncategorized)".to_string(),
+    };
+    let mut lines = vec![
+        Line::from(vec![label("Category: "), Span::raw(cat_display)]),
+        Line::from(vec![label("Key:      "), Span::raw(&selected.name)]),
+        Line::from(""),
+        Line::from(label("Value:")),
+    ];
+    let dim = Style::default().fg(Color::DarkGray);
+    match selected.text() {
+        Some(_) if !app.reveal => {
+            lines.push(Line::from(Span::styled("******** (press v to show)", dim)))
+        }
+        // Multi-line values keep their line breaks
+        Some(text) => lines.extend(
+            text.lines()
+                .map(|l| Line::from(Span::styled(l, Style::default().fg(Color::Green)))),
+        ),
+        None => lines.push(Line::from(Span::styled(
+            format!("<binary, {} bytes>", selected.value.len()),
+            dim,
+        ))),
+    }
+    lines.push(Line::from(""));
+
+    let details = match app.details.get(&selected.display_path()) {
+        Some(details) => details,
+        None => {
+            lines.push(Line::from(Span::styled(
+                "Press Enter to load metadata and history.",
+                dim,
+            )));
+            return lines;
+        }
+    };
+
+    let meta = &details.meta;
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    if let Some(description) = &meta.description {
+        fields.push(("Description: ", description.clone()));
+    }
+    if !meta.tags.is_empty() {
+        fields.push(("Tags:        ", meta.tags.join(", ")));
+    }
+    if let Some(owner) = &meta.owner {
+        fields.push(("Owner:       ", owner.clone()));
+    }
+    if let Some(url) = &meta.url {
+        fields.push(("URL:         ", url.clone()));
+    }
+    if let Some(expires) = meta.expires {
+        fields.push((
+            "Expires:     ",
+            crate::meta::describe_expiry(expires, crate::timeutil::now_unix()),
+        ));
+    }
+    if let Some(reservation) = &meta.reserved {
+        fields.push((
+            "Reserved by: ",
+            format!("{} since {}", reservation.owner, reservation.since),
+        ));
+    }
+    if !meta.attachments.is_empty() {
+        fields.push((
+            "Attachments: ",
+            meta.attachments
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", "),
+        ));
+    }
+    if fields.is_empty() {
+        lines.push(Line::from(Span::styled("No metadata.", dim)));
+    }
+    for (name, value) in fields {
+        lines.push(Line::from(vec![label(name), Span::raw(value)]));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(label("History:")));
+    if details.history.is_empty() {
+        lines.push(Line::from(Span::styled("No history.", dim)));
+    }
+    for version in &details.history {
+        lines.push(Line::from(vec![
+            Span::styled(
+                version.sha.chars().take(7).collect::<String>(),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::raw(format!(" {} {}", version.date, version.message)),
+        ]));
+    }
+    lines
+}
+
 pub fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -0,0 +1,328 @@
+use crate::audit::AuditEvent;
+use crate::config::Config;
+use crate::crypto::{CryptoHandler, EncryptedBlob};
+use crate::storage::{KeyVersion, Storage, MAX_VALUE_SIZE};
+use crate::templatefn::split_key_path;
+use crate::{audit, cache, derived, index, mirror, notify, recipients, wasmplugin};
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+
+/// Retrieves the master key from GitHub, or returns None if the vault has none yet.
+/// When the profile's cache policy allows derived keys to be cached, the decrypted master key
+/// is kept locally (encrypted with the LMK) to skip the remote round-trip.
+pub async fn load_master_key(storage: &Storage, password: &str) -> Result<Option<String>> {
+    let profile = storage.profile();
+    let cache_name = format!("master_key_{}", storage.repo_full_name());
+    let policy = Config::load_with_profile(profile)?.cache_policy;
+    let lmk = if cache::CacheKind::DerivedKeys.ttl(&policy) > 0 {
+        Some(Config::get_or_create_lmk_with_profile(profile, password)?)
+    } else {
+        None
+    };
+
+    if let Some(lmk) = &lmk {
+        if let Some(cached) = cache::load(profile, cache::CacheKind::DerivedKeys, &cache_name, lmk)?
+        {
+            return String::from_utf8(cached)
+                .map(Some)
+                .context("Cached master key is not valid UTF-8");
+        }
+    }
+
+    let master_key = match storage.get_master_key_blob().await? {
+        None => return Ok(None),
+        Some(data) => {
+            // Master key exists, try to decrypt it with the provided password
+            let encrypted: EncryptedBlob = serde_json::from_slice(&data)
+                .context("Failed to parse master key blob from GitHub")?;
+
+            match CryptoHandler::decrypt(&encrypted, password) {
+                Ok(decrypted) => {
+                    String::from_utf8(decrypted).context("Master key is not valid UTF-8")?
+                }
+                // Collaborators unlock the vault with their own identity instead, either
+                // entirely or for the categories shared with them
+                Err(_) => match recipients::unwrap_master_key(storage, password).await? {
+                    Some(master_key) => master_key,
+                    None => match recipients::unwrap_category_keys(storage, password).await? {
+                        Some(scoped_key) => scoped_key,
+                        None => {
+                            return Err(anyhow::anyhow!(
+                                "Incorrect master password. Please verify your credentials."
+                            ));
+                        }
+                    },
+                },
+            }
+        }
+    };
+
+    if let Some(lmk) = &lmk {
+        cache::store(
+            profile,
+            cache::CacheKind::DerivedKeys,
+            &cache_name,
+            master_key.as_bytes(),
+            lmk,
+        )?;
+    }
+
+    Ok(Some(master_key))
+}
+
+/// Generates a new master key for a vault that has none yet and saves it to GitHub,
+/// encrypted with the master password
+pub async fn init_master_key(storage: &Storage, password: &str) -> Result<String> {
+    let master_key = CryptoHandler::generate_master_key();
+    let encrypted = CryptoHandler::encrypt(master_key.as_bytes(), password)?;
+    storage
+        .save_master_key_blob(&serde_json::to_vec(&encrypted)?)
+        .await?;
+    Ok(master_key)
+}
+
+/// Encrypts a key's value (or metadata) for storage. Values in a category are encrypted
+/// with the data key of its top-level category, so that category can be shared on its own.
+/// Keys without a category need the master key, so a key scoped to some categories cannot
+/// write them.
+pub fn encrypt_value(
+    master_key: &str,
+    category: Option<&str>,
+    data: &[u8],
+) -> Result<EncryptedBlob> {
+    match top_category(category) {
+        Some(top) => CryptoHandler::encrypt_for_category(data, master_key, top),
+        None if CryptoHandler::scoped_categories(master_key).is_some() => Err(anyhow::anyhow!(
+            "Keys without a category need the master key, which you have no access to."
+        )),
+        None => CryptoHandler::encrypt_compressed(data, master_key),
+    }
+}
+
+/// Returns the top-level category whose data key encrypts the values of a category (None
+/// for keys without a category, which use the master key itself)
+pub fn top_category(category: Option<&str>) -> Option<&str> {
+    category
+        .and_then(|c| c.trim_matches('/').split('/').next())
+        .filter(|top| !top.is_empty())
+}
+
+/// Prepares an encrypted document (a key value, its metadata or an attachment) for being
+/// moved to another category: it is re-encrypted with the data key of the category's
+/// top-level category, unless it already uses that key.
+pub fn reencrypt_for(master_key: &str, category: Option<&str>, data: &[u8]) -> Result<Vec<u8>> {
+    let encrypted: EncryptedBlob =
+        serde_json::from_slice(data).context("Failed to parse encrypted blob")?;
+    if encrypted.category.as_deref() == top_category(category) {
+        return Ok(data.to_vec());
+    }
+    let value = CryptoHandler::decrypt(&encrypted, master_key)?;
+    let reencrypted = encrypt_value(master_key, category, &value)?;
+    Ok(serde_json::to_vec(&reencrypted)?)
+}
+
+/// Returns the decrypted value of a key, whether it is stored or derived, or None if it
+/// does not exist
+pub async fn read_value(
+    storage: &Storage,
+    master_key: &str,
+    key: &str,
+    category: Option<&str>,
+) -> Result<Option<Vec<u8>>> {
+    if let Some((data, _)) = storage.get_blob(key, category).await? {
+        let encrypted: EncryptedBlob =
+            serde_json::from_slice(&data).context("Failed to parse encrypted blob")?;
+        return Ok(Some(CryptoHandler::decrypt(&encrypted, master_key)?));
+    }
+    let id = derived::key_id(key, category)?;
+    match derived::load(storage, master_key).await?.keys.get(&id) {
+        Some(d) => Ok(Some(
+            derived::derive_value(master_key, &id, d.counter, d.length)?.into_bytes(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// An unlocked vault, for programs that embed AxKeyStore (e.g. to fetch secrets at startup).
+/// Keys are addressed by path ('category/name' or just 'name').
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// let vault = axkeystore::Vault::open(None, "master password").await?;
+/// let token = vault.get("ci/npm-token").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Vault {
+    storage: Storage,
+    master_key: String,
+}
+
+impl Vault {
+    /// Opens the vault of a profile (None for the default profile) with its master password.
+    /// The profile must have been set up with 'axkeystore login' and 'axkeystore init'; a
+    /// vault without a master key is an error rather than being initialized.
+    pub async fn open(profile: Option<&str>, password: &str) -> Result<Self> {
+        let repo_name = Config::get_repo_name_with_profile(profile, password)?;
+        let storage = Storage::new_with_profile(profile, &repo_name, password).await?;
+        let master_key = load_master_key(&storage, password).await?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "The vault in {} has no master key yet. Run 'axkeystore init' first.",
+                storage.repo_full_name()
+            )
+        })?;
+        Ok(Self {
+            storage,
+            master_key,
+        })
+    }
+
+    /// Returns the underlying storage, for operations the facade does not cover
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Returns the value of a key, or None if it does not exist
+    pub async fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let (category, key) = split_key_path(path);
+        read_value(&self.storage, &self.master_key, key, category).await
+    }
+
+    /// Stores a value, creating the key or replacing its current value. The value must pass
+    /// the category's WASM policy, and change notifications are sent as for 'axkeystore store'.
+    pub async fn put(&self, path: &str, value: &[u8]) -> Result<()> {
+        self.write(path, value, None).await
+    }
+
+    /// Stores a value like [`Vault::put`], recording an event in the audit log in the same
+    /// commit
+    pub async fn put_audited(&self, path: &str, value: &[u8], event: &AuditEvent) -> Result<()> {
+        self.write(path, value, Some(event)).await
+    }
+
+    async fn write(&self, path: &str, value: &[u8], event: Option<&AuditEvent>) -> Result<()> {
+        if value.len() > MAX_VALUE_SIZE {
+            return Err(anyhow::anyhow!(
+                "Values are limited to {} bytes",
+                MAX_VALUE_SIZE
+            ));
+        }
+        let (category, key) = split_key_path(path);
+        let id = derived::key_id(key, category)?;
+        if derived::load(&self.storage, &self.master_key)
+            .await?
+            .keys
+            .contains_key(&id)
+        {
+            return Err(anyhow::anyhow!(
+                "Key '{}' is derived from the master key and cannot be stored",
+                path
+            ));
+        }
+        wasmplugin::check_policy(self.storage.profile(), category, path, value)?;
+        let encrypted = serde_json::to_vec(&encrypt_value(&self.master_key, category, value)?)?;
+        match event {
+            Some(event) => {
+                let mut changes = self
+                    .storage
+                    .key_changes(key, category, Some(&encrypted))
+                    .await?;
+                changes.push(audit::append_change(&self.storage, event).await?);
+                self.storage
+                    .apply_changes(&changes, &Storage::update_message(key, category))
+                    .await?;
+            }
+            None => self.storage.save_blob(key, &encrypted, category).await?,
+        }
+
+        // The value is stored; failing to tell others about it does not undo that
+        let notified = async {
+            let policy = notify::load(&self.storage, &self.master_key).await?;
+            let repo = self.storage.repo_full_name();
+            notify::dispatch(&policy, &repo, "stored", &[path.to_string()]).await
+        }
+        .await;
+        if let Err(e) = notified {
+            tracing::warn!("Warning: failed to send notifications: {}", e);
+        }
+        if let Err(e) = mirror::sync_auto(&self.storage, &self.master_key).await {
+            tracing::warn!("Warning: failed to update the mirror: {:#}", e);
+        }
+        Ok(())
+    }
+
+    /// Returns the paths of all keys (stored and derived), sorted
+    pub async fn list(&self) -> Result<Vec<String>> {
+        let mut paths: BTreeSet<String> = self
+            .storage
+            .list_tree()
+            .await?
+            .keys()
+            .filter_map(|path| index::key_display_path(path))
+            .map(|path| path.to_string())
+            .collect();
+        paths.extend(
+            derived::load(&self.storage, &self.master_key)
+                .await?
+                .keys
+                .into_keys(),
+        );
+        Ok(paths.into_iter().collect())
+    }
+
+    /// Returns every version of a key, newest first
+    pub async fn history(&self, path: &str) -> Result<Vec<KeyVersion>> {
+        let (category, key) = split_key_path(path);
+        self.storage.get_full_key_history(key, category).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoped_key_needs_category() {
+        let master_key = CryptoHandler::generate_master_key();
+        let team = CryptoHandler::derive_category_key(&master_key, "team").unwrap();
+        let keys = std::collections::BTreeMap::from([("team".to_string(), team)]);
+        let scoped = CryptoHandler::scoped_key(&keys).unwrap();
+
+        let blob = encrypt_value(&scoped, Some("team/dev"), b"team secret").unwrap();
+        assert_eq!(
+            CryptoHandler::decrypt(&blob, &master_key).unwrap(),
+            b"team secret"
+        );
+        assert!(encrypt_value(&scoped, None, b"lost secret").is_err());
+        assert!(encrypt_value(&scoped, Some("/"), b"lost secret").is_err());
+    }
+
+    #[test]
+    fn test_reencrypt_for() {
+        assert_eq!(top_category(Some("/team/shared/")), Some("team"));
+        assert_eq!(top_category(Some("")), None);
+        assert_eq!(top_category(None), None);
+
+        let master_key = CryptoHandler::generate_master_key();
+        let data = serde_json::to_vec(
+            &encrypt_value(&master_key, Some("team/shared"), b"team secret").unwrap(),
+        )
+        .unwrap();
+
+        // Within the same top-level category the stored document is kept as it is
+        assert_eq!(
+            reencrypt_for(&master_key, Some("team/other"), &data).unwrap(),
+            data
+        );
+
+        for target in [Some("personal"), None] {
+            let moved = reencrypt_for(&master_key, target, &data).unwrap();
+            let blob: EncryptedBlob = serde_json::from_slice(&moved).unwrap();
+            assert_eq!(blob.category.as_deref(), target);
+            assert_eq!(
+                CryptoHandler::decrypt(&blob, &master_key).unwrap(),
+                b"team secret"
+            );
+        }
+    }
+}
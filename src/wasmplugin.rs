@@ -0,0 +1,382 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use wasmtime::{Caller, Engine, Instance, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Directory (inside the profile's configuration directory) holding the trusted WASM modules
+pub const PLUGINS_DIR: &str = "wasm-plugins";
+
+/// Module name of the functions the host offers to plugins
+const HOST_MODULE: &str = "axkeystore";
+
+/// Most linear memory a plugin may grow to
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+/// Instructions budget of a single call; a plugin that runs out is stopped
+const FUEL: u64 = 1_000_000_000;
+
+/// Largest value or message a plugin may return
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// The WASM plugins that apply to a category and its subcategories
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CategoryPlugins {
+    /// Module that generates values for '--generate' (instead of the built-in generator)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generator: Option<String>,
+    /// Module that validates values before they are stored in the category
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+}
+
+/// Returns the directory of trusted WASM modules for a profile
+pub fn plugins_dir(profile: Option<&str>) -> Result<PathBuf> {
+    Ok(Config::get_config_dir(profile)?.join(PLUGINS_DIR))
+}
+
+/// Returns the path of a module in the plugins directory
+pub fn module_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.wasm", name))
+}
+
+/// Lists the names of the modules in the plugins directory
+pub fn list_modules(dir: &Path) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let name = file_name.strip_suffix(".wasm")?;
+            crate::plugin::validate_name(name).ok()?;
+            Some(name.to_string())
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the module that applies to a category for one kind of plugin: the one configured
+/// for the category itself or else its closest parent. Keys without a category have none.
+fn lookup<'a>(
+    rules: &'a BTreeMap<String, CategoryPlugins>,
+    category: Option<&str>,
+    pick: impl Fn(&'a CategoryPlugins) -> Option<&'a String>,
+) -> Option<&'a str> {
+    let mut category = category?.trim_matches('/');
+    loop {
+        if let Some(name) = rules.get(category).and_then(&pick) {
+            return Some(name.as_str());
+        }
+        category = &category[..category.rfind('/')?];
+    }
+}
+
+/// Returns the generator module that applies to a category, if any
+pub fn generator_for<'a>(
+    rules: &'a BTreeMap<String, CategoryPlugins>,
+    category: Option<&str>,
+) -> Option<&'a str> {
+    lookup(rules, category, |p| p.generator.as_ref())
+}
+
+/// Returns the policy module that applies to a category, if any
+pub fn policy_for<'a>(
+    rules: &'a BTreeMap<String, CategoryPlugins>,
+    category: Option<&str>,
+) -> Option<&'a str> {
+    lookup(rules, category, |p| p.policy.as_ref())
+}
+
+/// Checks a value against the WASM policy configured for its category, if any
+pub fn check_policy(
+    profile: Option<&str>,
+    category: Option<&str>,
+    path: &str,
+    value: &[u8],
+) -> Result<()> {
+    let config = Config::load_with_profile(profile)?;
+    let name = match policy_for(&config.category_plugins, category) {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let problems = Plugin::load(&plugins_dir(profile)?, name)?.validate(path, value)?;
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Policy '{}' rejected the value of '{}': {}",
+            name,
+            path,
+            problems.join("; ")
+        ));
+    }
+    Ok(())
+}
+
+/// State of a running plugin
+struct HostState {
+    limits: StoreLimits,
+}
+
+/// A compiled WASM module. Modules get no WASI imports, so they cannot touch files, the
+/// network or the environment; the host only lends them randomness.
+///
+/// A module exports 'memory' and 'alloc(len: i32) -> i32', plus one or both of:
+/// - 'generate(path_ptr, path_len) -> i64': returns the new value
+/// - 'validate(value_ptr, value_len, path_ptr, path_len) -> i64': returns the problems found,
+///   one per line (nothing if the value passes)
+///
+/// Results are packed as '(ptr << 32) | len'. Modules may import
+/// 'axkeystore.random(ptr: i32, len: i32)', which fills memory with secure random bytes.
+pub struct Plugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+}
+
+impl Plugin {
+    /// Compiles a module of the plugins directory
+    pub fn load(dir: &Path, name: &str) -> Result<Self> {
+        crate::plugin::validate_name(name)?;
+        let path = module_path(dir, name);
+        if !path.is_file() {
+            return Err(anyhow::anyhow!(
+                "WASM plugin '{}' not found. Copy it to {}.",
+                name,
+                path.display()
+            ));
+        }
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, &path)
+            .with_context(|| format!("Failed to compile WASM plugin '{}'", name))?;
+        Ok(Self {
+            name: name.to_string(),
+            engine,
+            module,
+        })
+    }
+
+    /// Starts a fresh instance, so no state carries over between calls
+    fn instantiate(&self) -> Result<(Store<HostState>, Instance)> {
+        let mut store = Store::new(
+            &self.engine,
+            HostState {
+                limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(FUEL)?;
+
+        let mut linker = Linker::new(&self.engine);
+        linker.func_wrap(
+            HOST_MODULE,
+            "random",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<()> {
+                let memory = caller
+                    .get_export("memory")
+                    .and_then(|export| export.into_memory())
+                    .context("Plugin does not export its memory")?;
+                let mut bytes = vec![0u8; len.max(0) as usize];
+                rand::rng().fill(&mut bytes[..]);
+                memory.write(&mut caller, ptr as u32 as usize, &bytes)?;
+                Ok(())
+            },
+        )?;
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .with_context(|| format!("Failed to start WASM plugin '{}'", self.name))?;
+        Ok((store, instance))
+    }
+
+    /// Copies bytes into the plugin's memory and returns where they are
+    fn write_input(store: &mut Store<HostState>, instance: &Instance, data: &[u8]) -> Result<i32> {
+        let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "alloc")?;
+        let ptr = alloc.call(&mut *store, i32::try_from(data.len())?)?;
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .context("Plugin does not export its memory")?;
+        memory.write(&mut *store, ptr as u32 as usize, data)?;
+        Ok(ptr)
+    }
+
+    /// Reads a packed '(ptr << 32) | len' result out of the plugin's memory
+    fn read_output(
+        store: &mut Store<HostState>,
+        instance: &Instance,
+        packed: i64,
+    ) -> Result<Vec<u8>> {
+        let (ptr, len) = ((packed as u64 >> 32) as usize, packed as u32 as usize);
+        if len > MAX_OUTPUT {
+            return Err(anyhow::anyhow!(
+                "Plugin returned more than {} bytes",
+                MAX_OUTPUT
+            ));
+        }
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .context("Plugin does not export its memory")?;
+        let mut data = vec![0u8; len];
+        memory.read(&*store, ptr, &mut data)?;
+        Ok(data)
+    }
+
+    /// Generates a value for a key path ('category/name')
+    pub fn generate(&self, path: &str) -> Result<Vec<u8>> {
+        let (mut store, instance) = self.instantiate()?;
+        let generate = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, "generate")
+            .with_context(|| format!("WASM plugin '{}' is not a generator", self.name))?;
+        let path_ptr = Self::write_input(&mut store, &instance, path.as_bytes())?;
+        let packed = generate
+            .call(&mut store, (path_ptr, path.len() as i32))
+            .with_context(|| format!("WASM plugin '{}' failed", self.name))?;
+        let value = Self::read_output(&mut store, &instance, packed)?;
+        if value.is_empty() {
+            return Err(anyhow::anyhow!(
+                "WASM plugin '{}' generated an empty value",
+                self.name
+            ));
+        }
+        Ok(value)
+    }
+
+    /// Checks a value for a key path against the policy and returns the problems found
+    /// (empty if it passes)
+    pub fn validate(&self, path: &str, value: &[u8]) -> Result<Vec<String>> {
+        let (mut store, instance) = self.instantiate()?;
+        let validate = instance
+            .get_typed_func::<(i32, i32, i32, i32), i64>(&mut store, "validate")
+            .with_context(|| format!("WASM plugin '{}' is not a policy", self.name))?;
+        let value_ptr = Self::write_input(&mut store, &instance, value)?;
+        let path_ptr = Self::write_input(&mut store, &instance, path.as_bytes())?;
+        let packed = validate
+            .call(
+                &mut store,
+                (
+                    value_ptr,
+                    i32::try_from(value.len())?,
+                    path_ptr,
+                    path.len() as i32,
+                ),
+            )
+            .with_context(|| format!("WASM plugin '{}' failed", self.name))?;
+        let output = Self::read_output(&mut store, &instance, packed)?;
+        Ok(String::from_utf8_lossy(&output)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_string())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A generator that returns 8 random lowercase letters, and a policy that rejects values
+    /// shorter than 8 bytes
+    const MODULE: &str = r#"
+        (module
+          (import "axkeystore" "random" (func $random (param i32 i32)))
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "too short")
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func (export "generate") (param i32 i32) (result i64)
+            (local $i i32)
+            (call $random (i32.const 512) (i32.const 8))
+            (block $done
+              (loop $next_char
+                (br_if $done (i32.eq (local.get $i) (i32.const 8)))
+                (i32.store8
+                  (i32.add (i32.const 512) (local.get $i))
+                  (i32.add (i32.const 97)
+                    (i32.rem_u (i32.load8_u (i32.add (i32.const 512) (local.get $i))) (i32.const 26))))
+                (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                (br $next_char)))
+            (i64.or (i64.shl (i64.const 512) (i64.const 32)) (i64.const 8)))
+          (func (export "validate") (param i32 i32 i32 i32) (result i64)
+            (if (result i64) (i32.lt_u (local.get 1) (i32.const 8))
+              (then (i64.const 9))
+              (else (i64.const 0)))))
+    "#;
+
+    fn write_module(dir: &Path, name: &str, source: &str) {
+        std::fs::write(module_path(dir, name), source).unwrap();
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut rules = BTreeMap::new();
+        rules.insert(
+            "prod".to_string(),
+            CategoryPlugins {
+                generator: Some("long".to_string()),
+                policy: Some("strict".to_string()),
+            },
+        );
+        rules.insert(
+            "prod/db".to_string(),
+            CategoryPlugins {
+                generator: Some("db".to_string()),
+                policy: None,
+            },
+        );
+        assert_eq!(generator_for(&rules, Some("prod/db/eu")), Some("db"));
+        assert_eq!(policy_for(&rules, Some("prod/db")), Some("strict"));
+        assert_eq!(generator_for(&rules, Some("prod")), Some("long"));
+        assert_eq!(generator_for(&rules, Some("production")), None);
+        assert_eq!(policy_for(&rules, None), None);
+    }
+
+    #[test]
+    fn test_generate_and_validate() {
+        let dir = tempfile::tempdir().unwrap();
+        write_module(dir.path(), "letters", MODULE);
+        assert_eq!(list_modules(dir.path()).unwrap(), vec!["letters"]);
+
+        let plugin = Plugin::load(dir.path(), "letters").unwrap();
+        let value = plugin.generate("api/token").unwrap();
+        assert_eq!(value.len(), 8);
+        assert!(value.iter().all(|b| b.is_ascii_lowercase()));
+
+        assert!(plugin
+            .validate("api/token", b"long enough")
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            plugin.validate("api/token", b"short").unwrap(),
+            vec!["too short"]
+        );
+        assert!(Plugin::load(dir.path(), "missing").is_err());
+    }
+
+    #[test]
+    fn test_runaway_plugin_is_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        write_module(
+            dir.path(),
+            "spin",
+            r#"(module
+                 (memory (export "memory") 1)
+                 (func (export "alloc") (param i32) (result i32) (i32.const 0))
+                 (func (export "generate") (param i32 i32) (result i64)
+                   (loop $forever (br $forever))
+                   (i64.const 0)))"#,
+        );
+        let plugin = Plugin::load(dir.path(), "spin").unwrap();
+        assert!(plugin.generate("api/token").is_err());
+    }
+}